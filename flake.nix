@@ -34,6 +34,9 @@
               openssl
               sqlite
             ];
+
+            # Query macros are checked against the cached metadata in .sqlx
+            SQLX_OFFLINE = "true";
           };
         in
         {
@@ -140,7 +143,7 @@
                 Type = "simple";
                 User = cfg.user;
                 Group = cfg.group;
-                ExecStart = "${lnurlw-server}/bin/lnurlw-server";
+                ExecStart = "${lnurlw-server}/bin/lnurlw-server serve";
                 Restart = "always";
                 RestartSec = 10;
                 
@@ -226,6 +229,8 @@
             openssl
             sqlite
           ];
+
+          SQLX_OFFLINE = "true";
         };
         
         devShells.default = pkgs.mkShell {
@@ -233,6 +238,7 @@
             rustToolchain
             cargo-watch
             cargo-edit
+            sqlx-cli
             pkg-config
             openssl
             sqlite
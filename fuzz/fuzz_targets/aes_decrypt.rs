@@ -0,0 +1,18 @@
+//! Decrypting any `p` with any key, as the server does for every card a tap without card ID may belong to
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lnurlw_core::crypto::{AesKey, aes_decrypt, aes_encrypt, parse_decrypted_data};
+
+fuzz_target!(|data: &[u8]| {
+    let (Some(key), Some(ciphertext)) = (data.get(..16), data.get(16..32)) else {
+        return;
+    };
+    let key = AesKey::from_hex(&hex::encode(key)).expect("16 bytes make a key");
+    let ciphertext: &[u8; 16] = ciphertext.try_into().unwrap();
+
+    let plaintext = aes_decrypt(&key, ciphertext);
+    assert_eq!(&aes_encrypt(&key, &plaintext), ciphertext);
+    let _ = parse_decrypted_data(&plaintext);
+});
@@ -0,0 +1,18 @@
+//! Counters from bytes of any length
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lnurlw_core::crypto::Counter;
+
+fuzz_target!(|data: &[u8]| {
+    match Counter::from_bytes(data) {
+        Ok(counter) => {
+            assert_eq!(data.len(), 3);
+            assert_eq!(counter.to_bytes(), data);
+            assert!(counter.value() <= Counter::MAX);
+            assert_eq!(counter.value() + counter.remaining(), Counter::MAX);
+        }
+        Err(_) => assert_ne!(data.len(), 3),
+    }
+});
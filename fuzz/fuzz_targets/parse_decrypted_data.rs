@@ -0,0 +1,18 @@
+//! Parsing a decrypted `p`, which is whatever the tap's sender chose if the key is wrong
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lnurlw_core::crypto::{Counter, parse_decrypted_data};
+
+fuzz_target!(|data: [u8; 16]| {
+    match parse_decrypted_data(&data) {
+        Ok((uid, counter)) => {
+            assert_eq!(data[0], 0xC7);
+            assert_eq!(uid.as_bytes(), &data[1..8]);
+            assert_eq!(counter.to_bytes(), data[8..11]);
+            assert!(counter.value() <= Counter::MAX);
+        }
+        Err(_) => assert_ne!(data[0], 0xC7),
+    }
+});
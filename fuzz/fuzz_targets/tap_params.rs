@@ -0,0 +1,17 @@
+//! The `p` and `c` query parameters of a tap and the keys they're checked with
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lnurlw_core::validation::{decode_params, validate_card_pure};
+
+fuzz_target!(|input: (&str, &str, &str, &str)| {
+    let (p, c, k1, k2) = input;
+
+    if let Ok((p_bytes, c_bytes)) = decode_params(p, c) {
+        assert_eq!(hex::encode(p_bytes), p.to_ascii_lowercase());
+        assert_eq!(hex::encode(c_bytes), c.to_ascii_lowercase());
+    }
+    // Errors are reasons for the wallet, the point is that nothing panics
+    let _ = validate_card_pure(k1, k2, p, c);
+});
@@ -0,0 +1,396 @@
+//! Typed client for lnurlw-server
+//!
+//! Covers card registration, the operator API and the LNURLw endpoints a
+//! wallet calls, with the request and response types in [`models`]:
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), lnurlw_client::Error> {
+//! use lnurlw_client::{Client, models::CreateCardRequest};
+//!
+//! let client = Client::new("https://card.example.com");
+//! let request = CreateCardRequest {
+//!     card_name: "Alice".to_string(),
+//!     tx_limit_sats: Some(10_000),
+//!     day_limit_sats: None,
+//!     enabled: None,
+//!     balance_mode: None,
+//!     tags: None,
+//!     owner_id: None,
+//!     org_id: None,
+//! };
+//! let card = client.create_card(&request, Some("alice-1")).await?;
+//! let keys = client.card_registration(card.one_time_code().unwrap()).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! LNURL errors come back as [`Error::Lnurl`] with the server's error code, so
+//! callers can branch on it instead of on the translated reason.
+
+pub mod models;
+
+use reqwest::{RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+
+use crate::models::{
+    BulkAction, BulkRequest, BulkResponse, CardDiagnostics, CardFee, CardMetadata, CardRegistrationResponse, CardSelector, CardToken,
+    CreateCardRequest, CreatedCard, DescriptionTemplate, FrozenCard, LnurlError, LostReport, Metadata, OnchainFallback, Payment, ReceiptKey, ReceiptVerification, ReplacedCard, SignedReceipt, Stats, TokenScope, Topup, TopupStatus, WebhookEvent, WithdrawRequest,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The server couldn't be reached
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    /// An LNURL endpoint refused, `code` is one of the server's error codes
+    #[error("{code}: {reason}")]
+    Lnurl { code: String, reason: String },
+    /// An operator or registration endpoint answered with an error status
+    #[error("server answered {0}")]
+    Status(StatusCode),
+    /// The answer wasn't the JSON expected from the endpoint
+    #[error("unexpected response: {0}")]
+    Decode(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base: String,
+}
+
+impl Client {
+    /// Client for the server at `base`, e.g. `https://card.example.com`
+    pub fn new(base: &str) -> Self {
+        Self::with_http(reqwest::Client::new(), base)
+    }
+
+    /// Client sending its requests with `http`, for timeouts, proxies or headers of its own
+    ///
+    /// The operator endpoints, like [`Client::create_card`], need an operator API
+    /// key as default `Authorization: Bearer` header.
+    pub fn with_http(http: reqwest::Client, base: &str) -> Self {
+        Self { http, base: base.trim_end_matches('/').to_string() }
+    }
+
+    pub fn base(&self) -> &str {
+        &self.base
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base, path)
+    }
+
+    /// GET /ln, the withdraw request for the `p` and `c` of a tap
+    pub async fn tap(&self, card_id: Option<i64>, p: &str, c: &str) -> Result<WithdrawRequest> {
+        let mut query = vec![("p", p.to_string()), ("c", c.to_string())];
+        if let Some(card_id) = card_id {
+            query.push(("card_id", card_id.to_string()));
+        }
+        lnurl(self.http.get(self.url("/ln")).query(&query)).await
+    }
+
+    /// The withdraw request behind a URL a card sent, `lnurlw://` taken as `https://` (LUD-17)
+    pub async fn withdraw_request(&self, url: &str) -> Result<WithdrawRequest> {
+        let url = match url.strip_prefix("lnurlw://") {
+            Some(rest) => format!("https://{}", rest),
+            None => url.to_string(),
+        };
+        lnurl(self.http.get(url)).await
+    }
+
+    /// GET /ln/callback, have the server pay `invoice` for the withdrawal `k1` was issued for
+    pub async fn withdraw(&self, k1: &str, invoice: &str) -> Result<()> {
+        let request = self.http.get(self.url("/ln/callback")).query(&[("k1", k1), ("pr", invoice)]);
+        lnurl::<serde_json::Value>(request).await.map(|_| ())
+    }
+
+    /// POST /v1/createboltcard, retried safely with the same `idempotency_key`
+    pub async fn create_card(&self, card: &CreateCardRequest, idempotency_key: Option<&str>) -> Result<CreatedCard> {
+        let mut request = self.http.post(self.url("/v1/createboltcard")).json(card);
+        if let Some(key) = idempotency_key {
+            request = request.header("Idempotency-Key", key);
+        }
+        api(request).await
+    }
+
+    /// GET /v1/new, the keys to program a card with; the one-time code is used up by this
+    pub async fn card_registration(&self, one_time_code: &str) -> Result<CardRegistrationResponse> {
+        api(self.http.get(self.url("/v1/new")).query(&[("a", one_time_code)])).await
+    }
+
+    /// POST /v1/cards/{card_id}/replace, a new card with the old one's limits, balance, owner and tags
+    pub async fn replace_card(&self, card_id: i64) -> Result<ReplacedCard> {
+        api(self.http.post(self.url(&format!("/v1/cards/{}/replace", card_id)))).await
+    }
+
+    /// GET /v1/payments/{k1}
+    pub async fn payment(&self, k1: &str) -> Result<Payment> {
+        api(self.http.get(self.url(&format!("/v1/payments/{}", k1)))).await
+    }
+
+    /// PUT /v1/payments/{k1}/memo, `None` or an empty memo removing it
+    pub async fn set_payment_memo(&self, k1: &str, memo: Option<&str>) -> Result<Payment> {
+        let request = self.http.put(self.url(&format!("/v1/payments/{}/memo", k1))).json(&json!({"memo": memo}));
+        api(request).await
+    }
+
+    /// GET /v1/cards/{card_id}/fee
+    pub async fn card_fee(&self, card_id: i64) -> Result<CardFee> {
+        api(self.http.get(self.url(&format!("/v1/cards/{}/fee", card_id)))).await
+    }
+
+    /// PUT /v1/cards/{card_id}/fee, a flat fee plus a percentage of each withdrawal
+    pub async fn set_card_fee(&self, card_id: i64, flat_msats: i64, percent: f64) -> Result<CardFee> {
+        let request = self
+            .http
+            .put(self.url(&format!("/v1/cards/{}/fee", card_id)))
+            .json(&json!({"flat_msats": flat_msats, "percent": percent}));
+        api(request).await
+    }
+
+    /// DELETE /v1/cards/{card_id}/fee, charging the card the server's fee again
+    pub async fn clear_card_fee(&self, card_id: i64) -> Result<()> {
+        api_empty(self.http.delete(self.url(&format!("/v1/cards/{}/fee", card_id)))).await
+    }
+
+    /// GET /receipt/{k1}/signed of a settled withdrawal
+    pub async fn signed_receipt(&self, k1: &str) -> Result<SignedReceipt> {
+        api(self.http.get(self.url(&format!("/receipt/{}/signed", k1)))).await
+    }
+
+    /// GET /v1/receipts/keys
+    pub async fn receipt_keys(&self) -> Result<Vec<ReceiptKey>> {
+        api(self.http.get(self.url("/v1/receipts/keys"))).await
+    }
+
+    /// POST /v1/receipts/verify, checking the receipt against the server's keys
+    pub async fn verify_receipt(&self, receipt: &SignedReceipt) -> Result<ReceiptVerification> {
+        api(self.http.post(self.url("/v1/receipts/verify")).json(receipt)).await
+    }
+
+    /// GET /v1/stats over the last `days` days, of one card or all of them
+    pub async fn stats(&self, days: Option<i64>, card_id: Option<i64>) -> Result<Stats> {
+        let mut query = Vec::new();
+        if let Some(days) = days {
+            query.push(("days", days));
+        }
+        if let Some(card_id) = card_id {
+            query.push(("card_id", card_id));
+        }
+        api(self.http.get(self.url("/v1/stats")).query(&query)).await
+    }
+
+    /// POST /v1/cards/bulk, returns the ids of the cards the action was applied to
+    pub async fn bulk_update(&self, selector: &CardSelector, action: &BulkAction) -> Result<Vec<i64>> {
+        let request = self.http.post(self.url("/v1/cards/bulk")).json(&BulkRequest { selector, action });
+        api::<BulkResponse>(request).await.map(|response| response.card_ids)
+    }
+
+    /// PUT /v1/cards/{card_id}/tags, replacing the card's tags
+    pub async fn set_tags(&self, card_id: i64, tags: &[String]) -> Result<()> {
+        let request = self.http.put(self.url(&format!("/v1/cards/{}/tags", card_id))).json(&json!({"tags": tags}));
+        api_empty(request).await
+    }
+
+    /// GET /v1/cards/{card_id}/metadata
+    pub async fn metadata(&self, card_id: i64) -> Result<Metadata> {
+        api(self.http.get(self.url(&format!("/v1/cards/{}/metadata", card_id)))).await
+    }
+
+    /// PUT /v1/cards/{card_id}/metadata, replacing the card's metadata
+    pub async fn set_metadata(&self, card_id: i64, metadata: &Metadata) -> Result<Metadata> {
+        api(self.http.put(self.url(&format!("/v1/cards/{}/metadata", card_id))).json(metadata)).await
+    }
+
+    /// PATCH /v1/cards/{card_id}/metadata, setting the keys given and removing those set to `null`
+    pub async fn update_metadata(&self, card_id: i64, patch: &Metadata) -> Result<Metadata> {
+        api(self.http.patch(self.url(&format!("/v1/cards/{}/metadata", card_id))).json(patch)).await
+    }
+
+    /// GET /v1/cards/metadata, the cards that have `key`, with the value `value` if given
+    pub async fn find_cards_by_metadata(&self, key: &str, value: Option<&str>) -> Result<Vec<CardMetadata>> {
+        let mut query = vec![("key", key)];
+        if let Some(value) = value {
+            query.push(("value", value));
+        }
+        api(self.http.get(self.url("/v1/cards/metadata")).query(&query)).await
+    }
+
+    /// GET /v1/cards/{card_id}/description
+    pub async fn description(&self, card_id: i64) -> Result<DescriptionTemplate> {
+        api(self.http.get(self.url(&format!("/v1/cards/{}/description", card_id)))).await
+    }
+
+    /// PUT /v1/cards/{card_id}/description, `None` going back to the server's template
+    pub async fn set_description(&self, card_id: i64, template: Option<&str>) -> Result<DescriptionTemplate> {
+        let request = self
+            .http
+            .put(self.url(&format!("/v1/cards/{}/description", card_id)))
+            .json(&json!({"template": template}));
+        api(request).await
+    }
+
+    /// GET /v1/cards/{card_id}/diagnostics
+    pub async fn card_diagnostics(&self, card_id: i64) -> Result<CardDiagnostics> {
+        api(self.http.get(self.url(&format!("/v1/cards/{}/diagnostics", card_id)))).await
+    }
+
+    /// GET /v1/cards/{card_id}/onchain-fallback
+    pub async fn onchain_fallback(&self, card_id: i64) -> Result<OnchainFallback> {
+        api(self.http.get(self.url(&format!("/v1/cards/{}/onchain-fallback", card_id)))).await
+    }
+
+    /// PUT /v1/cards/{card_id}/onchain-fallback, `None` removing the address
+    pub async fn set_onchain_fallback(&self, card_id: i64, address: Option<&str>) -> Result<OnchainFallback> {
+        let request = self
+            .http
+            .put(self.url(&format!("/v1/cards/{}/onchain-fallback", card_id)))
+            .json(&json!({"address": address}));
+        api(request).await
+    }
+
+    /// POST /v1/cards/{card_id}/tokens, a cardholder link for one self-service page
+    pub async fn create_token(&self, card_id: i64, scope: TokenScope) -> Result<CardToken> {
+        let request = self.http.post(self.url(&format!("/v1/cards/{}/tokens", card_id))).json(&json!({"scope": scope}));
+        api(request).await
+    }
+
+    /// POST /v1/cards/{card_id}/report-lost, freezing the card until it is enabled again
+    pub async fn report_lost(&self, card_id: i64, note: Option<&str>) -> Result<LostReport> {
+        let request = self
+            .http
+            .post(self.url(&format!("/v1/cards/{}/report-lost", card_id)))
+            .json(&json!({"note": note}));
+        api(request).await
+    }
+
+    /// POST /v1/cards/{card_id}/freeze, refusing taps until `until` (RFC 3339) or until unfrozen
+    pub async fn freeze(&self, card_id: i64, until: Option<&str>, reason: Option<&str>) -> Result<FrozenCard> {
+        let request = self
+            .http
+            .post(self.url(&format!("/v1/cards/{}/freeze", card_id)))
+            .json(&json!({"until": until, "reason": reason}));
+        api(request).await
+    }
+
+    /// DELETE /v1/cards/{card_id}/freeze
+    pub async fn unfreeze(&self, card_id: i64) -> Result<()> {
+        api_empty(self.http.delete(self.url(&format!("/v1/cards/{}/freeze", card_id)))).await
+    }
+
+    /// DELETE /v1/cards/{card_id}, restorable until purged
+    pub async fn delete_card(&self, card_id: i64) -> Result<()> {
+        api_empty(self.http.delete(self.url(&format!("/v1/cards/{}", card_id)))).await
+    }
+
+    /// POST /v1/cards/{card_id}/restore
+    pub async fn restore_card(&self, card_id: i64) -> Result<()> {
+        api_empty(self.http.post(self.url(&format!("/v1/cards/{}/restore", card_id)))).await
+    }
+
+    /// DELETE /v1/cards/{card_id}/clone-flag, after reviewing a card flagged as a suspected clone
+    pub async fn clear_clone_flag(&self, card_id: i64) -> Result<()> {
+        api_empty(self.http.delete(self.url(&format!("/v1/cards/{}/clone-flag", card_id)))).await
+    }
+
+    /// POST /v1/cards/{card_id}/topups
+    pub async fn create_topup(&self, card_id: i64, amount_sats: i64) -> Result<Topup> {
+        let request = self
+            .http
+            .post(self.url(&format!("/v1/cards/{}/topups", card_id)))
+            .json(&json!({"amount_sats": amount_sats}));
+        api(request).await
+    }
+
+    /// GET /v1/topups/{payment_hash}, crediting the card if the invoice was paid since
+    pub async fn topup(&self, payment_hash: &str) -> Result<TopupStatus> {
+        api(self.http.get(self.url(&format!("/v1/topups/{}", payment_hash)))).await
+    }
+
+    /// GET /v1/webhooks/events, newest first; `status` is `pending`, `delivered` or `dead`
+    pub async fn webhook_events(&self, status: Option<&str>, limit: Option<i64>) -> Result<Vec<WebhookEvent>> {
+        let mut query = Vec::new();
+        if let Some(status) = status {
+            query.push(("status", status.to_string()));
+        }
+        if let Some(limit) = limit {
+            query.push(("limit", limit.to_string()));
+        }
+        api(self.http.get(self.url("/v1/webhooks/events")).query(&query)).await
+    }
+
+    /// POST /v1/webhooks/events/{event_id}/redeliver
+    pub async fn redeliver_webhook_event(&self, event_id: i64) -> Result<()> {
+        api_empty(self.http.post(self.url(&format!("/v1/webhooks/events/{}/redeliver", event_id)))).await
+    }
+}
+
+/// Answer of an LNURL endpoint, which sends its errors as JSON
+async fn lnurl<T: DeserializeOwned>(request: RequestBuilder) -> Result<T> {
+    let response = request.send().await?;
+    let status = response.status();
+    parse_lnurl(status, &response.bytes().await?)
+}
+
+fn parse_lnurl<T: DeserializeOwned>(status: StatusCode, body: &[u8]) -> Result<T> {
+    if let Ok(error) = serde_json::from_slice::<LnurlError>(body)
+        && error.status == "ERROR"
+    {
+        return Err(Error::Lnurl { code: error.code, reason: error.reason });
+    }
+    if !status.is_success() {
+        return Err(Error::Status(status));
+    }
+    Ok(serde_json::from_slice(body)?)
+}
+
+/// Answer of an operator or registration endpoint, which only has a status for errors
+async fn api<T: DeserializeOwned>(request: RequestBuilder) -> Result<T> {
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        return Err(Error::Status(response.status()));
+    }
+    Ok(serde_json::from_slice(&response.bytes().await?)?)
+}
+
+async fn api_empty(request: RequestBuilder) -> Result<()> {
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        return Err(Error::Status(response.status()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lnurl_error() {
+        let body = br#"{"status":"ERROR","code":"REPLAY_DETECTED","reason":"Karte wurde bereits verwendet"}"#;
+        match parse_lnurl::<WithdrawRequest>(StatusCode::BAD_REQUEST, body) {
+            Err(Error::Lnurl { code, .. }) => assert_eq!(code, "REPLAY_DETECTED"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(parse_lnurl::<WithdrawRequest>(StatusCode::BAD_GATEWAY, b"<html>"), Err(Error::Status(_))));
+
+        let request: serde_json::Value = parse_lnurl(StatusCode::OK, br#"{"status":"OK"}"#).unwrap();
+        assert_eq!(request["status"], "OK");
+    }
+
+    #[test]
+    fn test_one_time_code() {
+        let card = CreatedCard { status: "OK".to_string(), url: "https://card.example.com/new?a=9f86d081".to_string() };
+        assert_eq!(card.one_time_code(), Some("9f86d081"));
+    }
+
+    #[test]
+    fn test_bulk_request() {
+        let request = BulkRequest { selector: &CardSelector::Tag("event".to_string()), action: &BulkAction::Disable };
+        assert_eq!(serde_json::to_value(request).unwrap(), json!({"tag": "event", "action": "disable"}));
+    }
+}
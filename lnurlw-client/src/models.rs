@@ -0,0 +1,310 @@
+//! Requests and responses of the endpoints, as the server sends them
+
+use serde::{Deserialize, Serialize};
+
+pub use lnurlw_core::models::{CardRegistrationResponse, CreateCardRequest, PaymentStatus, WebhookEvent};
+
+/// LUD-03 withdraw request a tap is answered with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WithdrawRequest {
+    pub tag: String,
+    pub callback: String,
+    pub k1: String,
+    pub default_description: String,
+    pub min_withdrawable: u64,
+    pub max_withdrawable: u64,
+}
+
+/// A card waiting to be programmed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CreatedCard {
+    pub status: String,
+    /// Registration URL for the programming app, `https://<domain>/v1/new?a=<code>`
+    pub url: String,
+}
+
+impl CreatedCard {
+    /// The one-time code of the registration URL
+    pub fn one_time_code(&self) -> Option<&str> {
+        let (_, query) = self.url.split_once('?')?;
+        query.split('&').find_map(|pair| pair.strip_prefix("a="))
+    }
+}
+
+/// A card issued in place of another, which was disabled
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplacedCard {
+    pub status: String,
+    /// ID of the replacement
+    pub card_id: i64,
+    pub replaced_card_id: i64,
+    /// Balance moved to the replacement
+    pub balance_msats: i64,
+    /// Registration URL to program the replacement with
+    pub url: String,
+}
+
+/// State of the withdrawal of a tap
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Payment {
+    pub k1: String,
+    pub card_id: i64,
+    pub status: PaymentStatus,
+    pub amount_msats: Option<i64>,
+    pub payment_hash: Option<String>,
+    pub failure_reason: Option<String>,
+    /// Invoices tried so far
+    pub attempts: i64,
+    pub payment_time: Option<String>,
+    /// Note for expense annotation, the invoice's description unless the operator set one
+    pub memo: Option<String>,
+    /// Address the withdrawal was paid to on-chain instead of an invoice
+    pub onchain_address: Option<String>,
+    pub onchain_txid: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailyStats {
+    pub day: String,
+    pub volume_msats: i64,
+    pub payments: i64,
+    pub failures: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CardVolume {
+    pub card_id: i64,
+    pub card_name: String,
+    pub volume_msats: i64,
+    pub payments: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub days: i64,
+    pub card_id: Option<i64>,
+    pub total_volume_msats: i64,
+    pub total_payments: i64,
+    pub total_failures: i64,
+    /// Share of attempted payments that did not settle
+    pub failure_rate: f64,
+    pub daily: Vec<DailyStats>,
+    pub top_cards: Vec<CardVolume>,
+}
+
+/// Which cards a bulk action applies to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CardSelector {
+    CardIds(Vec<i64>),
+    Tag(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum BulkAction {
+    Enable,
+    Disable,
+    /// Update the limits that are set, leaving the others unchanged
+    SetLimits {
+        tx_limit_sats: Option<i64>,
+        day_limit_sats: Option<i64>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct BulkRequest<'a> {
+    #[serde(flatten)]
+    pub selector: &'a CardSelector,
+    #[serde(flatten)]
+    pub action: &'a BulkAction,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BulkResponse {
+    pub status: String,
+    pub card_ids: Vec<i64>,
+}
+
+/// Key-value data of a card; values are strings, numbers or booleans
+pub type Metadata = serde_json::Map<String, serde_json::Value>;
+
+/// Template of the description wallets are offered for a card's withdrawals
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DescriptionTemplate {
+    /// `None` for the server's `--withdraw-description`
+    pub template: Option<String>,
+}
+
+/// Address a card's large or repeatedly failing withdrawals are paid to on-chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OnchainFallback {
+    /// `None` if the card has none
+    pub address: Option<String>,
+}
+
+/// What the server knows about a card, for triaging one that stopped working
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CardDiagnostics {
+    pub card_id: i64,
+    pub card_name: String,
+    pub enabled: bool,
+    pub frozen: bool,
+    pub frozen_until: Option<String>,
+    pub reported_lost_at: Option<String>,
+    /// Set while the card is blocked as a suspected clone
+    pub clone_flagged_at: Option<String>,
+    /// Set while the card is deleted and can still be restored
+    pub deleted_at: Option<String>,
+    pub last_counter: i64,
+    /// Taps left before the counter runs out
+    pub counter_remaining: i64,
+    pub last_tap_at: Option<String>,
+    pub last_successful_tap_at: Option<String>,
+    pub counter_gaps: CounterGaps,
+    pub recent_failures: Vec<TapFailure>,
+    pub key_checks: Vec<KeyCheck>,
+    /// What keeps the card from working, in plain words
+    pub issues: Vec<String>,
+}
+
+/// How far the counter moved between the card's recent successful taps
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CounterGaps {
+    pub taps: usize,
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+    pub mean: Option<f64>,
+    /// Counter values the server never saw
+    pub skipped: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TapFailure {
+    pub created_at: Option<String>,
+    pub counter: Option<i64>,
+    pub reason: Option<String>,
+}
+
+/// Format check of the UID or one of the keys
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyCheck {
+    pub key: String,
+    pub ok: bool,
+    pub problem: Option<String>,
+}
+
+/// A card with its metadata
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CardMetadata {
+    pub card_id: i64,
+    pub card_name: String,
+    pub metadata: Metadata,
+}
+
+/// What a card token grants access to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenScope {
+    /// Cardholder self-service lost report
+    ReportLost,
+    /// Read-only status widget for customer-facing displays
+    Widget,
+    /// Top-up page for balance-mode cards
+    TopUp,
+}
+
+/// A cardholder link
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CardToken {
+    pub status: String,
+    pub token: String,
+    pub url: String,
+}
+
+/// A lost report the card was frozen for
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LostReport {
+    pub status: String,
+    pub report_id: i64,
+    /// When the card was first reported lost, earlier reports included
+    pub reported_lost_at: Option<String>,
+}
+
+/// Service fee charged on a card's withdrawals
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CardFee {
+    pub card_id: i64,
+    /// Whether the card has a fee of its own, otherwise it's charged the server's
+    pub own: bool,
+    pub flat_msats: i64,
+    pub percent: f64,
+}
+
+/// What a signed receipt states about a settled withdrawal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Receipt {
+    pub issuer: String,
+    pub k1: String,
+    pub amount_msats: i64,
+    pub payment_hash: String,
+    pub preimage: String,
+    pub settled_at: String,
+}
+
+/// A receipt with the server's Ed25519 signature
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedReceipt {
+    pub receipt: Receipt,
+    pub public_key: String,
+    pub signature: String,
+}
+
+/// A public key the server signed receipts with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReceiptKey {
+    pub public_key: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReceiptVerification {
+    pub valid: bool,
+    pub reason: Option<String>,
+}
+
+/// A card refusing taps until `frozen_until`, or until it is unfrozen
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrozenCard {
+    pub status: String,
+    pub card_id: i64,
+    pub frozen_at: String,
+    pub frozen_until: Option<String>,
+    pub reason: Option<String>,
+}
+
+/// Invoice that tops up a balance-mode card once paid
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Topup {
+    pub status: String,
+    pub payment_hash: String,
+    pub invoice: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopupStatus {
+    pub payment_hash: String,
+    pub amount_sats: i64,
+    pub paid: bool,
+    pub balance_sats: i64,
+}
+
+/// Error body of the LNURL endpoints
+#[derive(Debug, Deserialize)]
+pub(crate) struct LnurlError {
+    pub status: String,
+    #[serde(default)]
+    pub code: String,
+    pub reason: String,
+}
@@ -0,0 +1,79 @@
+//! Benchmarks for the tap validation path
+//!
+//! Run with `cargo bench -p lnurlw-core`.
+
+use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
+
+use lnurlw_core::{
+    crypto::{AesKey, CardUid, Counter, aes_decrypt, generate_sun, verify_cmac},
+    validation::{validate_card_pure, validate_card_with_keys},
+};
+
+struct Tap {
+    k1: AesKey,
+    k2: AesKey,
+    uid: CardUid,
+    counter: Counter,
+    p: [u8; 16],
+    c: [u8; 8],
+}
+
+fn tap() -> Tap {
+    let k1 = AesKey::generate();
+    let k2 = AesKey::generate();
+    let uid = CardUid::from_hex("04a39493cc8680").unwrap();
+    let counter = Counter::new(42);
+    let (p, c) = generate_sun(&k1, &k2, &uid, &counter);
+    Tap { k1, k2, uid, counter, p, c }
+}
+
+fn bench_crypto(c: &mut Criterion) {
+    let tap = tap();
+
+    c.bench_function("aes_decrypt", |b| {
+        b.iter(|| aes_decrypt(black_box(&tap.k1), black_box(&tap.p)))
+    });
+
+    c.bench_function("verify_cmac", |b| {
+        b.iter(|| verify_cmac(black_box(&tap.k2), &tap.uid, &tap.counter, black_box(&tap.c)))
+    });
+}
+
+fn bench_validation(c: &mut Criterion) {
+    let tap = tap();
+    let (k1_hex, k2_hex) = (tap.k1.to_string(), tap.k2.to_string());
+    let (p_hex, c_hex) = (hex::encode(tap.p), hex::encode(tap.c));
+
+    c.bench_function("validate_card_pure", |b| {
+        b.iter(|| validate_card_pure(black_box(&k1_hex), &k2_hex, black_box(&p_hex), &c_hex).unwrap())
+    });
+
+    c.bench_function("validate_card_with_keys", |b| {
+        b.iter(|| validate_card_with_keys(black_box(&tap.k1), &tap.k2, black_box(&tap.p), &tap.c).unwrap())
+    });
+}
+
+/// Taps without card ID: try every card's keys until one validates, the
+/// matching card last as the worst case
+fn bench_trial_decryption(c: &mut Criterion) {
+    let mut group = c.benchmark_group("trial_decryption");
+
+    for cards in [10, 100, 1000] {
+        let tap = tap();
+        let mut keys: Vec<(AesKey, AesKey)> = (1..cards).map(|_| (AesKey::generate(), AesKey::generate())).collect();
+        keys.push((tap.k1.clone(), tap.k2.clone()));
+
+        group.bench_with_input(BenchmarkId::from_parameter(cards), &keys, |b, keys| {
+            b.iter(|| {
+                keys.iter()
+                    .find_map(|(k1, k2)| validate_card_with_keys(k1, k2, black_box(&tap.p), &tap.c).ok())
+                    .unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_crypto, bench_validation, bench_trial_decryption);
+criterion_main!(benches);
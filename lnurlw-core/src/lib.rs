@@ -0,0 +1,17 @@
+//! Bolt Card validation without the server
+//!
+//! SUN message decryption and CMAC verification, tap validation, the
+//! Lightning backend trait and the card models, for wallets, POS software and
+//! other projects that handle Bolt Card taps themselves.
+//!
+//! With `default-features = false` only `crypto` is built, without std.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub mod crypto;
+#[cfg(feature = "std")]
+pub mod lightning;
+#[cfg(feature = "std")]
+pub mod models;
+#[cfg(feature = "std")]
+pub mod validation;
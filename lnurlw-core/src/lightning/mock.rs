@@ -0,0 +1,425 @@
+//! Scriptable mock backend for tests and demos
+//!
+//! By default every payment succeeds instantly. Tests queue [`Fault`]s for
+//! the next payments, add a delay to every call or take the node offline to
+//! exercise retries, timeouts and failover deterministically, and read back
+//! the calls that were made. Invoices the mock creates count as paid as soon
+//! as anyone asks, unless held until a test pays them with
+//! [`MockLightning::receive`]. The same can be set up from a backend spec, see
+//! [`MockLightning::from_spec`].
+
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use lightning_invoice::{Currency, InvoiceBuilder, PaymentSecret};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{Invoice, LightningBackend, Network, NodeInfo, OnchainPayment, OutgoingPayment, PaymentResult};
+
+/// What a scripted payment does instead of succeeding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// The node reports the payment failed with this reason
+    Fail(String),
+    /// The call errors before anything is paid, e.g. the node can't be reached
+    Error(String),
+    /// The call errors but the payment went out, as when the connection drops mid-payment
+    Lost,
+    /// Only `arrived_msats` of a multi-path payment arrived: the call errors and
+    /// the node reports the payment pending until [`MockLightning::resolve`]
+    Partial { arrived_msats: u64 },
+}
+
+/// A scripted payment: wait `delay`, then succeed or run into `fault`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Step {
+    pub delay: Duration,
+    pub fault: Option<Fault>,
+}
+
+impl From<Fault> for Step {
+    fn from(fault: Fault) -> Self {
+        Step { delay: Duration::ZERO, fault: Some(fault) }
+    }
+}
+
+/// A call made to the mock
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call {
+    /// Name of the trait method
+    pub method: &'static str,
+    /// Payment hash, offer or description the call was about
+    pub target: Option<String>,
+    pub amount_msats: Option<u64>,
+}
+
+/// State of a payment on the mock node
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Payment {
+    Succeeded(String),
+    Pending,
+    Failed,
+}
+
+pub struct MockLightning {
+    payments: Mutex<HashMap<String, Payment>>,
+    script: Mutex<VecDeque<Step>>,
+    /// Run the script again from the start once it's used up
+    cycle: bool,
+    used: Mutex<Vec<Step>>,
+    /// Added to every call
+    delay: Duration,
+    offline: AtomicBool,
+    /// Leave created invoices unpaid until they are received
+    hold_invoices: AtomicBool,
+    /// Payment hashes of the invoices paid to the mock
+    received: Mutex<HashSet<String>>,
+    calls: Mutex<Vec<Call>>,
+    balance_msats: u64,
+    /// Network of the invoices the mock creates
+    network: Network,
+}
+
+impl Default for MockLightning {
+    fn default() -> Self {
+        Self {
+            payments: Mutex::default(),
+            script: Mutex::default(),
+            cycle: false,
+            used: Mutex::default(),
+            delay: Duration::ZERO,
+            offline: AtomicBool::new(false),
+            hold_invoices: AtomicBool::new(false),
+            received: Mutex::default(),
+            calls: Mutex::default(),
+            balance_msats: 1_000_000_000,
+            network: Network::Regtest,
+        }
+    }
+}
+
+impl MockLightning {
+    /// Parse the options of a `mock:<options>` backend spec
+    ///
+    /// Options are separated by commas: `delay_ms=<ms>` for every call,
+    /// `balance_sats=<sats>`, `network=<network>` of the invoices it creates
+    /// (default regtest), `hold_invoices` to leave the invoices it creates
+    /// unpaid, `cycle` to repeat the script, and the script
+    /// itself as `script=<step>;<step>;...` where a step is `ok`, `fail`,
+    /// `error`, `lost` or `partial`, optionally followed by `@<ms>` to delay
+    /// it, e.g. `mock:delay_ms=50,script=ok;fail@2000;lost,cycle`.
+    pub fn from_spec(options: &str) -> Result<Self> {
+        let mut mock = Self::default();
+        let mut script = Vec::new();
+        for option in options.split(',').map(str::trim).filter(|option| !option.is_empty()) {
+            match option.split_once('=') {
+                Some(("delay_ms", ms)) => mock.delay = Duration::from_millis(ms.parse()?),
+                Some(("balance_sats", sats)) => mock.balance_msats = sats.parse::<u64>()? * 1000,
+                Some(("network", network)) => mock.network = network.parse().map_err(|e: String| anyhow!(e))?,
+                Some(("script", steps)) => {
+                    script = steps.split(';').map(parse_step).collect::<Result<_>>()?;
+                }
+                None if option == "cycle" => mock.cycle = true,
+                None if option == "hold_invoices" => mock.hold_invoices(true),
+                _ => bail!("Unknown mock option {:?}", option),
+            }
+        }
+        mock.script = Mutex::new(script.into());
+
+        Ok(mock)
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn with_balance_msats(mut self, balance_msats: u64) -> Self {
+        self.balance_msats = balance_msats;
+        self
+    }
+
+    /// Create invoices for `network` instead of regtest
+    pub fn with_network(mut self, network: Network) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Queue what the next payments do, after those queued before
+    pub fn script(&self, steps: impl IntoIterator<Item = impl Into<Step>>) {
+        self.script.lock().unwrap().extend(steps.into_iter().map(Into::into));
+    }
+
+    /// Make every call fail as if the node was down, until set back
+    pub fn set_offline(&self, offline: bool) {
+        self.offline.store(offline, Ordering::SeqCst);
+    }
+
+    /// Leave the invoices the mock creates unpaid until [`MockLightning::receive`] pays them
+    pub fn hold_invoices(&self, hold: bool) {
+        self.hold_invoices.store(hold, Ordering::SeqCst);
+    }
+
+    /// Pay an invoice the mock created, as a payer elsewhere would
+    pub fn receive(&self, payment_hash: &str) {
+        self.received.lock().unwrap().insert(payment_hash.to_string());
+    }
+
+    /// Settle or fail a payment left pending by [`Fault::Partial`]
+    pub fn resolve(&self, payment_hash: &str, succeeded: bool) {
+        let mut payments = self.payments.lock().unwrap();
+        if let Some(payment @ Payment::Pending) = payments.get_mut(payment_hash) {
+            *payment = if succeeded { Payment::Succeeded("0".repeat(64)) } else { Payment::Failed };
+        }
+    }
+
+    /// Calls made so far, oldest first
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Record the call, wait the delay and fail if offline
+    async fn enter(&self, method: &'static str, target: Option<String>, amount_msats: Option<u64>) -> Result<()> {
+        self.calls.lock().unwrap().push(Call { method, target, amount_msats });
+        sleep(self.delay).await;
+        if self.offline.load(Ordering::SeqCst) {
+            bail!("Mock node is offline");
+        }
+        Ok(())
+    }
+
+    fn next_step(&self) -> Step {
+        let mut script = self.script.lock().unwrap();
+        let mut used = self.used.lock().unwrap();
+        if script.is_empty() && self.cycle {
+            script.extend(used.drain(..));
+        }
+        let step = script.pop_front().unwrap_or_default();
+        if self.cycle {
+            used.push(step.clone());
+        }
+        step
+    }
+
+    /// Run the next step of the script for a payment
+    async fn pay(&self, payment_hash: String) -> Result<PaymentResult> {
+        let step = self.next_step();
+        sleep(step.delay).await;
+
+        let preimage = "0".repeat(64);
+        let mut payments = self.payments.lock().unwrap();
+        match step.fault {
+            None => {
+                payments.insert(payment_hash, Payment::Succeeded(preimage.clone()));
+                Ok(PaymentResult { success: true, preimage: Some(preimage), error: None, fee_msats: Some(0) })
+            }
+            Some(Fault::Fail(reason)) => {
+                payments.insert(payment_hash, Payment::Failed);
+                Ok(PaymentResult { success: false, preimage: None, error: Some(reason), fee_msats: None })
+            }
+            Some(Fault::Error(error)) => Err(anyhow!(error)),
+            Some(Fault::Lost) => {
+                payments.insert(payment_hash, Payment::Succeeded(preimage));
+                Err(anyhow!("Connection to mock node lost"))
+            }
+            Some(Fault::Partial { arrived_msats }) => {
+                payments.insert(payment_hash, Payment::Pending);
+                Err(anyhow!("Only {} msats of the payment arrived so far", arrived_msats))
+            }
+        }
+    }
+}
+
+fn parse_step(step: &str) -> Result<Step> {
+    let (fault, delay) = match step.split_once('@') {
+        Some((fault, ms)) => (fault, Duration::from_millis(ms.parse()?)),
+        None => (step, Duration::ZERO),
+    };
+    let fault = match fault {
+        "ok" => None,
+        "fail" => Some(Fault::Fail("Mock payment failed".to_string())),
+        "error" => Some(Fault::Error("Mock node error".to_string())),
+        "lost" => Some(Fault::Lost),
+        "partial" => Some(Fault::Partial { arrived_msats: 0 }),
+        _ => bail!("Unknown mock step {:?}, expected ok, fail, error, lost or partial", fault),
+    };
+    Ok(Step { delay, fault })
+}
+
+async fn sleep(duration: Duration) {
+    if !duration.is_zero() {
+        tokio::time::sleep(duration).await;
+    }
+}
+
+#[async_trait]
+impl LightningBackend for MockLightning {
+    async fn pay_invoice(&self, invoice: &Invoice, expected_amount_msats: u64) -> Result<PaymentResult> {
+        let amount_msats = invoice.amount_msats()?;
+        self.enter("pay_invoice", Some(invoice.payment_hash()), Some(amount_msats)).await?;
+
+        if amount_msats != expected_amount_msats {
+            return Ok(PaymentResult {
+                success: false,
+                preimage: None,
+                error: Some(format!(
+                    "Invoice amount {} msats doesn't match expected {} msats",
+                    amount_msats, expected_amount_msats
+                )),
+                fee_msats: None,
+            });
+        }
+
+        if invoice.is_expired() {
+            return Ok(PaymentResult {
+                success: false,
+                preimage: None,
+                error: Some("Invoice is expired".to_string()),
+                fee_msats: None,
+            });
+        }
+
+        self.pay(invoice.payment_hash()).await
+    }
+
+    async fn get_info(&self) -> Result<NodeInfo> {
+        self.enter("get_info", None, None).await?;
+
+        Ok(NodeInfo {
+            alias: "Mock Node".to_string(),
+            balance_msats: self.balance_msats,
+        })
+    }
+
+    async fn create_invoice(&self, amount_msats: u64, description: &str) -> Result<Invoice> {
+        self.enter("create_invoice", Some(description.to_string()), Some(amount_msats)).await?;
+
+        let secp = Secp256k1::new();
+        let node_key = SecretKey::from_slice(&rand::random::<[u8; 32]>())?;
+        let preimage = rand::random::<[u8; 32]>();
+
+        let currency = match self.network {
+            Network::Bitcoin => Currency::Bitcoin,
+            Network::Testnet => Currency::BitcoinTestnet,
+            Network::Signet => Currency::Signet,
+            Network::Regtest => Currency::Regtest,
+        };
+        let invoice = InvoiceBuilder::new(currency)
+            .amount_milli_satoshis(amount_msats)
+            .description(description.to_string())
+            .payment_hash(sha256::Hash::hash(&preimage))
+            .payment_secret(PaymentSecret(rand::random()))
+            .duration_since_epoch(SystemTime::now().duration_since(UNIX_EPOCH)?)
+            .min_final_cltv_expiry_delta(144)
+            .build_signed(|msg| secp.sign_ecdsa_recoverable(msg, &node_key))
+            .map_err(|e| anyhow!("Failed to create invoice: {}", e))?;
+
+        Ok(Invoice(invoice))
+    }
+
+    async fn is_invoice_paid(&self, payment_hash: &str) -> Result<bool> {
+        self.enter("is_invoice_paid", Some(payment_hash.to_string()), None).await?;
+
+        // Unless held, mock invoices count as paid as soon as anyone asks
+        Ok(!self.hold_invoices.load(Ordering::SeqCst) || self.received.lock().unwrap().contains(payment_hash))
+    }
+
+    async fn outgoing_payment(&self, payment_hash: &str) -> Result<OutgoingPayment> {
+        self.enter("outgoing_payment", Some(payment_hash.to_string()), None).await?;
+
+        Ok(match self.payments.lock().unwrap().get(payment_hash) {
+            Some(Payment::Succeeded(preimage)) => OutgoingPayment::Succeeded { preimage: Some(preimage.clone()) },
+            Some(Payment::Pending) => OutgoingPayment::Pending,
+            Some(Payment::Failed) => OutgoingPayment::Failed,
+            None => OutgoingPayment::Unknown,
+        })
+    }
+
+    async fn pay_offer(&self, offer: &str, amount_msats: u64) -> Result<PaymentResult> {
+        self.enter("pay_offer", Some(offer.to_string()), Some(amount_msats)).await?;
+
+        if !offer.starts_with("lno1") {
+            return Err(anyhow!("Invalid offer"));
+        }
+        // Offers have no payment hash up front, the mock keys them by offer and amount
+        self.pay(format!("{}:{}", offer, amount_msats)).await
+    }
+
+    /// Runs the next step of the script like a payment, with a random txid
+    async fn send_onchain(&self, address: &str, amount_sats: u64) -> Result<OnchainPayment> {
+        self.enter("send_onchain", Some(address.to_string()), Some(amount_sats * 1000)).await?;
+
+        let step = self.next_step();
+        sleep(step.delay).await;
+        match step.fault {
+            None => Ok(OnchainPayment { success: true, txid: Some(hex::encode(rand::random::<[u8; 32]>())), error: None }),
+            Some(Fault::Fail(reason)) => Ok(OnchainPayment { success: false, txid: None, error: Some(reason) }),
+            Some(Fault::Error(error)) => Err(anyhow!(error)),
+            Some(Fault::Lost | Fault::Partial { .. }) => Err(anyhow!("Connection to mock node lost")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_script() {
+        let mock = MockLightning::default();
+        mock.script([Fault::Fail("No route".to_string()), Fault::Lost, Fault::Partial { arrived_msats: 400 }]);
+        let mut invoices = Vec::new();
+        for _ in 0..4 {
+            invoices.push(mock.create_invoice(1000, "test").await.unwrap());
+        }
+
+        let failed = mock.pay_invoice(&invoices[0], 1000).await.unwrap();
+        assert_eq!((failed.success, failed.error.as_deref()), (false, Some("No route")));
+        assert!(mock.pay_invoice(&invoices[1], 1000).await.is_err());
+        assert!(mock.pay_invoice(&invoices[2], 1000).await.is_err());
+        assert!(mock.pay_invoice(&invoices[3], 1000).await.unwrap().success);
+
+        let hashes: Vec<String> = invoices.iter().map(Invoice::payment_hash).collect();
+        let status = |i: usize| mock.outgoing_payment(&hashes[i]);
+        assert_eq!(status(0).await.unwrap(), OutgoingPayment::Failed);
+        assert!(matches!(status(1).await.unwrap(), OutgoingPayment::Succeeded { .. }));
+        assert_eq!(status(2).await.unwrap(), OutgoingPayment::Pending);
+        mock.resolve(&hashes[2], false);
+        assert_eq!(status(2).await.unwrap(), OutgoingPayment::Failed);
+
+        let calls = mock.calls();
+        assert_eq!(calls.iter().filter(|call| call.method == "pay_invoice").count(), 4);
+        assert_eq!(calls[4].target.as_ref(), Some(&hashes[0]));
+    }
+
+    #[tokio::test]
+    async fn test_from_spec() {
+        let mock = MockLightning::from_spec("balance_sats=5,network=signet,script=fail;ok@1,cycle").unwrap();
+        assert_eq!(mock.get_info().await.unwrap().balance_msats, 5000);
+        assert_eq!(mock.create_invoice(1000, "test").await.unwrap().network(), Some(Network::Signet));
+        let mut outcomes = Vec::new();
+        for _ in 0..4 {
+            let invoice = mock.create_invoice(1000, "test").await.unwrap();
+            outcomes.push(mock.pay_invoice(&invoice, 1000).await.unwrap().success);
+        }
+        assert_eq!(outcomes, [false, true, false, true]);
+
+        mock.set_offline(true);
+        assert!(mock.get_info().await.is_err());
+
+        let mock = MockLightning::from_spec("hold_invoices").unwrap();
+        let invoice = mock.create_invoice(1000, "test").await.unwrap();
+        assert!(!mock.is_invoice_paid(&invoice.payment_hash()).await.unwrap());
+        mock.receive(&invoice.payment_hash());
+        assert!(mock.is_invoice_paid(&invoice.payment_hash()).await.unwrap());
+        mock.hold_invoices(false);
+        assert!(mock.is_invoice_paid("00").await.unwrap());
+
+        assert!(MockLightning::from_spec("script=ok;crash").is_err());
+        assert!(MockLightning::from_spec("speed=fast").is_err());
+    }
+}
@@ -0,0 +1,217 @@
+mod mock;
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use bitcoin::hashes::{sha256, Hash};
+use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescriptionRef, Currency};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::fmt;
+
+pub use mock::{Call as MockCall, Fault, MockLightning, Step as MockStep};
+
+/// Newtype wrapper around Bolt11Invoice for convenience methods
+#[derive(Debug, Clone)]
+pub struct Invoice(Bolt11Invoice);
+
+impl FromStr for Invoice {
+    type Err = anyhow::Error;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Bolt11Invoice::from_str(s)
+            .map(Self)
+            .map_err(|e| anyhow!("Invalid invoice: {}", e))
+    }
+}
+
+#[allow(dead_code)]
+impl Invoice {
+    pub fn amount_msats(&self) -> Result<u64> {
+        self.0
+            .amount_milli_satoshis()
+            .ok_or_else(|| anyhow!("Invoice must have an amount"))
+    }
+    
+    pub fn description(&self) -> Option<String> {
+        match self.0.description() {
+            Bolt11InvoiceDescriptionRef::Direct(desc) => Some(desc.to_string()),
+            Bolt11InvoiceDescriptionRef::Hash(_) => None,
+        }
+    }
+    
+    /// Whether the invoice carries `expected` as its description or the hash of it
+    pub fn description_matches(&self, expected: &str) -> bool {
+        match self.0.description() {
+            Bolt11InvoiceDescriptionRef::Direct(desc) => desc.as_inner().0 == expected,
+            Bolt11InvoiceDescriptionRef::Hash(hash) => hash.0 == sha256::Hash::hash(expected.as_bytes()),
+        }
+    }
+
+    pub fn payment_hash(&self) -> String {
+        hex::encode(self.0.payment_hash().as_ref() as &[u8])
+    }
+    
+    pub fn is_expired(&self) -> bool {
+        // For now, assume invoices don't expire quickly during our mock testing
+        // In a real implementation, you'd check against current time
+        false
+    }
+    
+    /// The network the invoice is for, `None` for simnet
+    pub fn network(&self) -> Option<Network> {
+        match self.0.currency() {
+            Currency::Bitcoin => Some(Network::Bitcoin),
+            Currency::BitcoinTestnet => Some(Network::Testnet),
+            Currency::Signet => Some(Network::Signet),
+            Currency::Regtest => Some(Network::Regtest),
+            Currency::Simnet => None,
+        }
+    }
+
+    pub fn bolt11(&self) -> String {
+        self.0.to_string()
+    }
+    
+    pub fn inner(&self) -> &Bolt11Invoice {
+        &self.0
+    }
+}
+
+impl fmt::Display for Invoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Bitcoin network a node runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    Bitcoin,
+    Testnet,
+    Signet,
+    Regtest,
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bitcoin" | "mainnet" => Ok(Network::Bitcoin),
+            "testnet" => Ok(Network::Testnet),
+            "signet" => Ok(Network::Signet),
+            "regtest" => Ok(Network::Regtest),
+            _ => Err(format!("unknown network {:?}, expected bitcoin, testnet, signet or regtest", s)),
+        }
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Network::Bitcoin => "bitcoin",
+            Network::Testnet => "testnet",
+            Network::Signet => "signet",
+            Network::Regtest => "regtest",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentResult {
+    pub success: bool,
+    pub preimage: Option<String>,
+    pub error: Option<String>,
+    /// Routing fee paid on top of the amount, if the backend knows it
+    #[serde(default)]
+    pub fee_msats: Option<u64>,
+}
+
+/// Outcome of sending to an on-chain address
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OnchainPayment {
+    pub success: bool,
+    /// The transaction paying the address, if the backend knows it yet
+    pub txid: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Check an on-chain address, and that it's for `network` if given
+pub fn parse_address(address: &str, network: Option<Network>) -> Result<String> {
+    let address = bitcoin::Address::from_str(address.trim()).map_err(|e| anyhow!("Invalid address: {}", e))?;
+    let address = match network {
+        Some(network) => {
+            let network = match network {
+                Network::Bitcoin => bitcoin::Network::Bitcoin,
+                Network::Testnet => bitcoin::Network::Testnet,
+                Network::Signet => bitcoin::Network::Signet,
+                Network::Regtest => bitcoin::Network::Regtest,
+            };
+            address.require_network(network).map_err(|_| anyhow!("Address is for another network"))?
+        }
+        None => address.assume_checked(),
+    };
+    Ok(address.to_string())
+}
+
+/// What the node knows about a payment it was asked to make
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum OutgoingPayment {
+    Succeeded { preimage: Option<String> },
+    Failed,
+    /// Still being routed
+    Pending,
+    /// The node has no record of the payment
+    Unknown,
+}
+
+impl OutgoingPayment {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutgoingPayment::Succeeded { .. } => "succeeded",
+            OutgoingPayment::Failed => "failed",
+            OutgoingPayment::Pending => "pending",
+            OutgoingPayment::Unknown => "unknown",
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[async_trait]
+pub trait LightningBackend: Send + Sync {
+    /// Pay a Lightning invoice after validation
+    async fn pay_invoice(&self, invoice: &Invoice, expected_amount_msats: u64) -> Result<PaymentResult>;
+    
+    /// Get node info (balance, etc.)
+    async fn get_info(&self) -> Result<NodeInfo>;
+
+    /// Create an invoice for receiving `amount_msats`
+    async fn create_invoice(&self, amount_msats: u64, description: &str) -> Result<Invoice>;
+
+    /// Check whether an invoice we created has been paid
+    async fn is_invoice_paid(&self, payment_hash: &str) -> Result<bool>;
+
+    /// Look up a payment we made by its payment hash
+    async fn outgoing_payment(&self, payment_hash: &str) -> Result<OutgoingPayment>;
+
+    /// Pay `amount_msats` to a BOLT12 offer, for backends whose node supports offers
+    async fn pay_offer(&self, offer: &str, amount_msats: u64) -> Result<PaymentResult> {
+        let _ = (offer, amount_msats);
+        Err(anyhow!("This Lightning backend can't pay BOLT12 offers"))
+    }
+
+    /// Send `amount_sats` to an on-chain address, for backends with an on-chain wallet
+    async fn send_onchain(&self, address: &str, amount_sats: u64) -> Result<OnchainPayment> {
+        let _ = (address, amount_sats);
+        Err(anyhow!("This Lightning backend can't send on-chain"))
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeInfo {
+    pub alias: String,
+    pub balance_msats: u64,
+}
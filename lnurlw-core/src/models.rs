@@ -0,0 +1,350 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Card {
+    pub card_id: i64,
+    pub uid: String,
+    pub k0_auth_key: String,
+    pub k1_decrypt_key: String,
+    pub k2_cmac_key: String,
+    pub k3: String,
+    pub k4: String,
+    pub last_counter: i64,
+    pub enabled: bool,
+    pub tx_limit_sats: i64,
+    pub day_limit_sats: i64,
+    pub card_name: String,
+    pub one_time_code: Option<String>,
+    pub one_time_code_expiry: Option<String>,
+    pub one_time_code_used: Option<bool>,
+    pub created_at: Option<String>,
+    pub balance_mode: bool,
+    pub balance_msats: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct CardPayment {
+    pub payment_id: i64,
+    pub card_id: i64,
+    pub k1: String,
+    pub invoice: Option<String>,
+    pub amount_msats: Option<i64>,
+    pub payment_time: Option<String>,
+    pub created_at: Option<String>,
+    pub payment_hash: Option<String>,
+    pub preimage: Option<String>,
+    pub status: PaymentStatus,
+    pub failure_reason: Option<String>,
+    /// Withdrawable range advertised for this k1, unset for older sessions
+    pub min_withdrawable_msats: Option<i64>,
+    pub max_withdrawable_msats: Option<i64>,
+    /// Invoices tried for this session, including a pending one
+    pub attempts: i64,
+    /// Note for expense annotation, the invoice's description unless the operator set one
+    pub memo: Option<String>,
+    /// Address the session was paid to instead of an invoice
+    pub onchain_address: Option<String>,
+    pub onchain_txid: Option<String>,
+}
+
+/// Where a withdrawal session is in its lifecycle
+///
+/// ```text
+/// created -> invoice_attached -> in_flight -> settled
+///    |             |                |
+///    |             +-------------> failed -> invoice_attached (retry)
+///    +--------------------------------+----> expired
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+pub enum PaymentStatus {
+    /// The k1 was handed out, no invoice yet
+    Created,
+    /// The wallet's invoice was accepted and its amount reserved
+    InvoiceAttached,
+    /// The invoice was handed to the Lightning node
+    InFlight,
+    Settled,
+    /// The payment failed; the session can be retried with a new invoice
+    Failed,
+    /// The session ran out of time without settling
+    Expired,
+}
+
+impl PaymentStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PaymentStatus::Created => "created",
+            PaymentStatus::InvoiceAttached => "invoice_attached",
+            PaymentStatus::InFlight => "in_flight",
+            PaymentStatus::Settled => "settled",
+            PaymentStatus::Failed => "failed",
+            PaymentStatus::Expired => "expired",
+        }
+    }
+
+    /// Whether the payment's amount counts against the card's limits
+    pub fn is_reserved(self) -> bool {
+        matches!(self, PaymentStatus::InvoiceAttached | PaymentStatus::InFlight)
+    }
+}
+
+impl fmt::Display for PaymentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct CardTap {
+    pub tap_id: i64,
+    pub card_id: i64,
+    pub uid: Option<String>,
+    pub counter: Option<i64>,
+    pub success: bool,
+    pub reason: Option<String>,
+    pub created_at: Option<String>,
+}
+
+/// A card reported lost, by its holder or the operator
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LostReport {
+    pub report_id: i64,
+    pub card_id: i64,
+    pub source: String,
+    pub note: Option<String>,
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AuditEntry {
+    pub audit_id: i64,
+    pub actor: String,
+    pub action: String,
+    /// JSON object describing the change
+    pub details: String,
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct DashboardSession {
+    pub session_hash: String,
+    pub username: String,
+    pub expires_at: String,
+    pub created_at: Option<String>,
+    pub role: String,
+}
+
+/// Cardholder account owning cards
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct User {
+    pub user_id: i64,
+    pub username: String,
+    #[serde(skip_serializing)]
+    pub password_hash: String,
+    pub enabled: bool,
+    pub created_at: Option<String>,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub policy: UserPolicy,
+    /// Invite the user registered with
+    pub invite_id: Option<i64>,
+    /// Set once the user's personal data was erased
+    pub erased_at: Option<String>,
+}
+
+/// Quotas and limits of a user's cards, unset fields don't restrict anything
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct UserPolicy {
+    /// Most cards the user may own, disabled ones included
+    pub max_cards: Option<i64>,
+    /// Limits of new cards that don't ask for any, instead of the server's defaults
+    pub default_tx_limit_sats: Option<i64>,
+    pub default_day_limit_sats: Option<i64>,
+    /// Highest limits the user's cards may be given
+    pub max_tx_limit_sats: Option<i64>,
+    pub max_day_limit_sats: Option<i64>,
+}
+
+/// Code that lets someone register a cardholder account
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct UserInvite {
+    pub invite_id: i64,
+    pub code: String,
+    pub max_uses: i64,
+    pub uses: i64,
+    pub expires_at: Option<String>,
+    pub note: Option<String>,
+    pub created_at: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+/// API key a user issued to a third-party app, without its secret
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct UserApiKey {
+    pub key_id: i64,
+    pub user_id: i64,
+    pub name: String,
+    /// Comma separated scopes
+    pub scopes: String,
+    pub created_at: Option<String>,
+    pub last_used_at: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+/// API key for the operator API, without its secret
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct OperatorApiKey {
+    pub key_id: i64,
+    pub name: String,
+    /// `admin` or `viewer`
+    pub role: String,
+    pub created_at: Option<String>,
+    pub last_used_at: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+/// Channel a user is notified on about events of their cards
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct UserNotificationChannel {
+    pub channel_id: i64,
+    pub user_id: i64,
+    /// `email`, `telegram` or `nostr`
+    pub kind: String,
+    pub target: String,
+    /// Comma separated event names, `None` for all events
+    pub events: Option<String>,
+    pub created_at: Option<String>,
+}
+
+/// Operator notification with the state of its delivery
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct WebhookEvent {
+    pub event_id: i64,
+    /// Name of the event, e.g. `payment_settled`
+    pub event: String,
+    /// The notification as JSON
+    pub payload: String,
+    /// `pending`, `delivered` or `dead`
+    pub status: String,
+    pub attempts: i64,
+    pub last_error: Option<String>,
+    pub created_at: Option<String>,
+    pub delivered_at: Option<String>,
+}
+
+/// Tenant with its own cards and Lightning backend
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Organization {
+    pub org_id: i64,
+    pub slug: String,
+    pub name: String,
+    /// Backend spec, see `--lightning-backend`
+    pub lightning_backend: String,
+    pub created_at: Option<String>,
+    /// Domain the organization's cards are served on, the server's when unset
+    pub domain: Option<String>,
+}
+
+/// A card's sub-account of the capital on its organization's node
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct CardAccount {
+    pub card_id: i64,
+    pub card_name: String,
+    pub org_id: Option<i64>,
+    /// Top-ups and operator allocations
+    pub funded_msats: i64,
+    /// Settled withdrawals
+    pub spent_msats: i64,
+    /// Withdrawals being paid that haven't settled yet
+    pub reserved_msats: i64,
+}
+
+impl CardAccount {
+    /// What the card may still spend of its funding, negative if it overspent
+    pub fn available_msats(&self) -> i64 {
+        self.funded_msats - self.spent_msats - self.reserved_msats
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateCardRequest {
+    pub card_name: String,
+    pub tx_limit_sats: Option<i64>,
+    pub day_limit_sats: Option<i64>,
+    pub enabled: Option<bool>,
+    /// Spend only from a prepaid balance that is topped up over Lightning
+    pub balance_mode: Option<bool>,
+    pub tags: Option<Vec<String>>,
+    /// User owning the card, the operator when unset
+    pub owner_id: Option<i64>,
+    /// Organization whose Lightning backend serves the card, the server's when unset
+    pub org_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct CardTopup {
+    pub topup_id: i64,
+    pub card_id: i64,
+    pub payment_hash: String,
+    pub invoice: String,
+    pub amount_msats: i64,
+    pub paid: bool,
+    pub paid_at: Option<String>,
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct JobRecord {
+    pub job_id: i64,
+    pub kind: String,
+    pub payload: String,
+    /// `queued`, `running` or `dead`
+    pub status: String,
+    pub attempts: i64,
+    pub run_after: String,
+    pub last_error: Option<String>,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+/// Payment the Lightning node and the database disagree about
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PaymentDiscrepancy {
+    pub discrepancy_id: i64,
+    pub payment_id: i64,
+    pub card_id: i64,
+    /// `in_flight_unknown`, `settled_not_on_node` or `paid_on_node_not_recorded`
+    pub kind: String,
+    /// `failed`, `pending`, `unknown` or `succeeded`
+    pub node_status: String,
+    pub created_at: Option<String>,
+    pub resolved_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct CardPreviousKeys {
+    pub card_id: i64,
+    pub k0_auth_key: String,
+    pub k1_decrypt_key: String,
+    pub k2_cmac_key: String,
+    pub k3: String,
+    pub k4: String,
+    pub valid_until: String,
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CardRegistrationResponse {
+    pub protocol_name: String,
+    pub protocol_version: i32,
+    pub card_name: String,
+    pub lnurlw_base: String,
+    pub k0: String,
+    pub k1: String,
+    pub k2: String,
+    pub k3: String,
+    pub k4: String,
+}
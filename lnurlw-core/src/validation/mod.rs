@@ -0,0 +1,336 @@
+use anyhow::Result;
+use std::borrow::Borrow;
+use crate::{
+    crypto::{self, AesKey, aes_decrypt, verify_cmac, parse_decrypted_data, CardUid, Counter},
+    models::Card,
+};
+
+/// Result of card validation
+#[derive(Debug, PartialEq)]
+pub enum ValidationResult {
+    Success {
+        uid: CardUid,
+        counter: Counter,
+    },
+    Error(String),
+}
+
+/// Why an authenticated tap doesn't fit the card it was made with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CardCheckError {
+    /// The tap carries another UID than the one the card was first seen with
+    UidMismatch,
+    /// The card's counter reached its maximum, so no further tap can be fresh
+    CounterExhausted,
+    /// The counter isn't above the last one seen
+    Replay,
+}
+
+impl CardCheckError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CardCheckError::UidMismatch => "UID mismatch",
+            CardCheckError::CounterExhausted => "Card counter exhausted - card needs replacement",
+            CardCheckError::Replay => "Invalid counter - possible replay attack",
+        }
+    }
+}
+
+impl std::fmt::Display for CardCheckError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::error::Error for CardCheckError {}
+
+/// Why a tap doesn't authenticate with a card's keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthError {
+    /// The card's stored K1 isn't a valid key
+    InvalidK1Key,
+    /// The card's stored K2 isn't a valid key
+    InvalidK2Key,
+    /// `p` doesn't decrypt to UID and counter, i.e. another card's K1
+    InvalidData,
+    /// `c` doesn't match, i.e. another card's K2 or a forged tap
+    InvalidCmac,
+}
+
+impl AuthError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuthError::InvalidK1Key => "Invalid k1 key",
+            AuthError::InvalidK2Key => "Invalid k2 key",
+            AuthError::InvalidData => "Invalid decrypted data",
+            AuthError::InvalidCmac => "Invalid CMAC - card authentication failed",
+        }
+    }
+}
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// Trait for database operations needed for validation
+#[async_trait::async_trait]
+pub trait CardRepository {
+    async fn get_card_by_id(&self, card_id: i64) -> Result<Option<Card>>;
+    async fn update_card_uid(&self, card_id: i64, uid: &str) -> Result<()>;
+    async fn update_card_counter(&self, card_id: i64, counter: i64) -> Result<bool>;
+}
+
+/// Trait for crypto operations
+pub trait CryptoService {
+    fn decrypt(&self, key: &AesKey, ciphertext: &[u8; 16]) -> [u8; 16];
+    fn verify_cmac(&self, key: &AesKey, uid: &CardUid, counter: &Counter, expected_cmac: &[u8; 8]) -> bool;
+    fn parse_decrypted_data(&self, decrypted: &[u8; 16]) -> crypto::Result<(CardUid, Counter)>;
+}
+
+/// Default implementation of crypto operations
+pub struct DefaultCryptoService;
+
+impl CryptoService for DefaultCryptoService {
+    fn decrypt(&self, key: &AesKey, ciphertext: &[u8; 16]) -> [u8; 16] {
+        aes_decrypt(key, ciphertext)
+    }
+
+    fn verify_cmac(&self, key: &AesKey, uid: &CardUid, counter: &Counter, expected_cmac: &[u8; 8]) -> bool {
+        verify_cmac(key, uid, counter, expected_cmac)
+    }
+
+    fn parse_decrypted_data(&self, decrypted: &[u8; 16]) -> crypto::Result<(CardUid, Counter)> {
+        parse_decrypted_data(decrypted)
+    }
+}
+
+/// Parsed K1/K2 of a card
+#[derive(Debug, Clone)]
+pub struct CardKeys {
+    pub k1: AesKey,
+    pub k2: AesKey,
+}
+
+impl CardKeys {
+    pub fn from_card(card: &Card) -> std::result::Result<Self, AuthError> {
+        Self::from_hex(&card.k1_decrypt_key, &card.k2_cmac_key)
+    }
+
+    pub fn from_hex(k1_hex: &str, k2_hex: &str) -> std::result::Result<Self, AuthError> {
+        Ok(Self {
+            k1: AesKey::from_hex(k1_hex).map_err(|_| AuthError::InvalidK1Key)?,
+            k2: AesKey::from_hex(k2_hex).map_err(|_| AuthError::InvalidK2Key)?,
+        })
+    }
+}
+
+/// Card validation service
+///
+/// The server and [`validate_card_pure`] both go through `authenticate` and
+/// `check_card`, so there is a single implementation of the tap checks.
+pub struct CardValidator<C: CryptoService> {
+    crypto: C,
+}
+
+impl<C: CryptoService> CardValidator<C> {
+    pub fn new(crypto: C) -> Self {
+        Self { crypto }
+    }
+
+    /// Decrypt `p` with K1 and check `c` with K2
+    pub fn authenticate(
+        &self,
+        k1: &AesKey,
+        k2: &AesKey,
+        p_bytes: &[u8; 16],
+        c_bytes: &[u8; 8],
+    ) -> std::result::Result<pure::ValidationResult, AuthError> {
+        // Decrypt the data
+        let decrypted = self.crypto.decrypt(k1, p_bytes);
+
+        // Parse UID and counter
+        let (uid, counter) = self.crypto.parse_decrypted_data(&decrypted)
+            .map_err(|_| AuthError::InvalidData)?;
+
+        // Verify CMAC
+        if !self.crypto.verify_cmac(k2, &uid, &counter, c_bytes) {
+            return Err(AuthError::InvalidCmac);
+        }
+
+        Ok(pure::ValidationResult { uid, counter })
+    }
+
+    /// Check an authenticated tap against the card's UID and counter
+    ///
+    /// A card without UID accepts any; it takes the UID of its first tap.
+    pub fn check_card(&self, card: &Card, tap: &pure::ValidationResult) -> std::result::Result<(), CardCheckError> {
+        if !card.uid.is_empty() && card.uid != tap.uid.to_string() {
+            return Err(CardCheckError::UidMismatch);
+        }
+
+        // The counter doesn't wrap, so no tap after the last one can be fresh
+        if card.last_counter >= Counter::MAX as i64 {
+            return Err(CardCheckError::CounterExhausted);
+        }
+
+        // Replay protection
+        if tap.counter.value() as i64 <= card.last_counter {
+            return Err(CardCheckError::Replay);
+        }
+
+        Ok(())
+    }
+
+    /// The first of `cards` whose keys authenticate the tap and whose UID it carries
+    ///
+    /// For taps without card ID; the caller orders the cards so that likely
+    /// matches come first.
+    pub fn find_card<K: Borrow<CardKeys>>(
+        &self,
+        cards: impl IntoIterator<Item = (Card, K)>,
+        p_bytes: &[u8; 16],
+        c_bytes: &[u8; 8],
+    ) -> Option<(Card, pure::ValidationResult)> {
+        cards.into_iter().find_map(|(card, keys)| {
+            let keys = keys.borrow();
+            match self.authenticate(&keys.k1, &keys.k2, p_bytes, c_bytes) {
+                Ok(tap) if card.uid.is_empty() || card.uid == tap.uid.to_string() => Some((card, tap)),
+                _ => None,
+            }
+        })
+    }
+
+    /// Validate card parameters, update the card's UID and counter and return them if valid
+    pub async fn validate_card<R: CardRepository>(
+        &self,
+        repo: &R,
+        card_id: i64,
+        p_hex: &str,
+        c_hex: &str,
+    ) -> ValidationResult {
+        // Decode hex parameters
+        let (p_bytes, c_bytes) = match decode_params(p_hex, c_hex) {
+            Ok(params) => params,
+            Err(reason) => return ValidationResult::Error(reason.to_string()),
+        };
+
+        // Look up the card
+        let card = match repo.get_card_by_id(card_id).await {
+            Ok(Some(card)) => card,
+            Ok(None) => return ValidationResult::Error("Card not found".to_string()),
+            Err(_) => return ValidationResult::Error("Database error".to_string()),
+        };
+
+        if !card.enabled {
+            return ValidationResult::Error("Card disabled".to_string());
+        }
+
+        let tap = CardKeys::from_card(&card)
+            .and_then(|keys| self.authenticate(&keys.k1, &keys.k2, &p_bytes, &c_bytes))
+            .map_err(|e| e.as_str())
+            .and_then(|tap| self.check_card(&card, &tap).map(|()| tap).map_err(|e| e.as_str()));
+        let tap = match tap {
+            Ok(tap) => tap,
+            Err(reason) => return ValidationResult::Error(reason.to_string()),
+        };
+
+        // Update UID if not set
+        if card.uid.is_empty() && repo.update_card_uid(card_id, &tap.uid.to_string()).await.is_err() {
+            return ValidationResult::Error("Database error".to_string());
+        }
+
+        match repo.update_card_counter(card_id, tap.counter.value() as i64).await {
+            Ok(true) => {},
+            Ok(false) => return ValidationResult::Error("Counter update failed".to_string()),
+            Err(_) => return ValidationResult::Error("Database error".to_string()),
+        }
+
+        ValidationResult::Success { uid: tap.uid, counter: tap.counter }
+    }
+}
+
+impl CardValidator<DefaultCryptoService> {
+    /// Create a validator with default crypto service
+    pub fn new_default() -> Self {
+        Self::new(DefaultCryptoService)
+    }
+}
+
+pub mod pure;
+
+pub use pure::{ParamError, TapParam, decode_params, validate_card_pure, validate_card_with_keys};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_sun;
+
+    fn card(card_id: i64, uid: &str, last_counter: i64) -> (Card, CardKeys) {
+        let keys = CardKeys { k1: AesKey::generate(), k2: AesKey::generate() };
+        let card = Card {
+            card_id,
+            uid: uid.to_string(),
+            k0_auth_key: AesKey::generate().to_string(),
+            k1_decrypt_key: keys.k1.to_string(),
+            k2_cmac_key: keys.k2.to_string(),
+            k3: AesKey::generate().to_string(),
+            k4: AesKey::generate().to_string(),
+            last_counter,
+            enabled: true,
+            tx_limit_sats: 1000,
+            day_limit_sats: 10000,
+            card_name: format!("Card {}", card_id),
+            one_time_code: None,
+            one_time_code_expiry: None,
+            one_time_code_used: None,
+            created_at: None,
+            balance_mode: false,
+            balance_msats: 0,
+        };
+        (card, keys)
+    }
+
+    #[test]
+    fn test_check_card() {
+        let validator = CardValidator::new_default();
+        let (card, keys) = card(1, "04996c6a926980", 5);
+        let tap = |uid: &str, counter| {
+            let (p, c) = generate_sun(&keys.k1, &keys.k2, &CardUid::from_hex(uid).unwrap(), &Counter::new(counter));
+            validator.authenticate(&keys.k1, &keys.k2, &p, &c).unwrap()
+        };
+
+        assert_eq!(validator.check_card(&card, &tap("04996c6a926980", 6)), Ok(()));
+        assert_eq!(validator.check_card(&card, &tap("04996c6a926980", 5)), Err(CardCheckError::Replay));
+        assert_eq!(validator.check_card(&card, &tap("04a39493cc8680", 6)), Err(CardCheckError::UidMismatch));
+
+        let (unused, _) = self::card(2, "", 0);
+        assert_eq!(validator.check_card(&unused, &tap("04a39493cc8680", 1)), Ok(()));
+
+        let (exhausted, _) = self::card(3, "04996c6a926980", Counter::MAX as i64);
+        assert_eq!(
+            validator.check_card(&exhausted, &tap("04996c6a926980", Counter::MAX)),
+            Err(CardCheckError::CounterExhausted)
+        );
+    }
+
+    #[test]
+    fn test_find_card() {
+        let validator = CardValidator::new_default();
+        let cards: Vec<_> = (1..=5).map(|card_id| card(card_id, "", 0)).collect();
+        let uid = CardUid::from_hex("04996c6a926980").unwrap();
+        let (p, c) = generate_sun(&cards[3].1.k1, &cards[3].1.k2, &uid, &Counter::new(1));
+
+        let (found, tap) = validator.find_card(cards.clone(), &p, &c).unwrap();
+        assert_eq!(found.card_id, 4);
+        assert_eq!(tap.uid, uid);
+
+        // A card already bound to another UID doesn't match even with the right keys
+        let mut bound = cards;
+        bound[3].0.uid = "04a39493cc8680".to_string();
+        assert!(validator.find_card(bound, &p, &c).is_none());
+    }
+}
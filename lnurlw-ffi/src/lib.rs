@@ -0,0 +1,165 @@
+//! Kotlin and Swift bindings for the tap validation in `lnurlw-core`
+//!
+//! Keys, UIDs and SUN parameters cross the boundary as hex strings, the same
+//! form the server stores and the card puts into its URL.
+
+use lnurlw_core::{
+    crypto::{self, AesKey, CardUid, Counter},
+    validation::{self, AuthError},
+};
+
+uniffi::setup_scaffolding!();
+
+#[derive(Debug, PartialEq, thiserror::Error, uniffi::Error)]
+pub enum CardError {
+    /// A key, UID, counter or tap parameter is malformed
+    #[error("{reason}")]
+    Invalid { reason: String },
+    /// `p` doesn't decrypt to a UID and counter, i.e. another card's K1
+    #[error("Invalid decrypted data")]
+    InvalidData,
+    /// `c` doesn't match, i.e. another card's K2 or a forged tap
+    #[error("Invalid CMAC")]
+    InvalidCmac,
+}
+
+impl CardError {
+    fn invalid(reason: impl ToString) -> Self {
+        CardError::Invalid { reason: reason.to_string() }
+    }
+}
+
+/// UID and counter of a tap whose CMAC checked out
+#[derive(Debug, PartialEq, uniffi::Record)]
+pub struct Tap {
+    pub uid: String,
+    pub counter: u32,
+}
+
+/// The `p` and `c` parameters of a tap
+#[derive(Debug, uniffi::Record)]
+pub struct SunParams {
+    pub p: String,
+    pub c: String,
+}
+
+/// Application keys to program a card with
+#[derive(Debug, uniffi::Record)]
+pub struct CardKeys {
+    pub k0: String,
+    pub k1: String,
+    pub k2: String,
+    pub k3: String,
+    pub k4: String,
+}
+
+/// Decrypt `p` with K1 and check `c` with K2, as the server does for every tap
+#[uniffi::export]
+pub fn validate_card(k1: String, k2: String, p: String, c: String) -> Result<Tap, CardError> {
+    let (p, c) = validation::decode_params(&p, &c).map_err(CardError::invalid)?;
+    let k1 = AesKey::from_hex(&k1).map_err(CardError::invalid)?;
+    let k2 = AesKey::from_hex(&k2).map_err(CardError::invalid)?;
+    let result = validation::validate_card_with_keys(&k1, &k2, &p, &c).map_err(|e| match e {
+        AuthError::InvalidData => CardError::InvalidData,
+        AuthError::InvalidCmac => CardError::InvalidCmac,
+        AuthError::InvalidK1Key | AuthError::InvalidK2Key => CardError::invalid(e),
+    })?;
+
+    Ok(Tap {
+        uid: result.uid.to_string(),
+        counter: result.counter.value(),
+    })
+}
+
+/// Produce the `p` and `c` a card with these keys sends for a tap
+#[uniffi::export]
+pub fn generate_sun(k1: String, k2: String, uid: String, counter: u32) -> Result<SunParams, CardError> {
+    if counter > 0xFF_FFFF {
+        return Err(CardError::invalid("Counter must fit in 3 bytes"));
+    }
+
+    let k1 = AesKey::from_hex(&k1).map_err(CardError::invalid)?;
+    let k2 = AesKey::from_hex(&k2).map_err(CardError::invalid)?;
+    let uid = CardUid::from_hex(&uid).map_err(CardError::invalid)?;
+    let (p, c) = crypto::generate_sun(&k1, &k2, &uid, &Counter::new(counter));
+
+    Ok(SunParams {
+        p: hex::encode(p),
+        c: hex::encode(c),
+    })
+}
+
+/// Generate a fresh set of random keys
+#[uniffi::export]
+pub fn generate_keys() -> CardKeys {
+    CardKeys {
+        k0: AesKey::generate().to_string(),
+        k1: AesKey::generate().to_string(),
+        k2: AesKey::generate().to_string(),
+        k3: AesKey::generate().to_string(),
+        k4: AesKey::generate().to_string(),
+    }
+}
+
+/// Derive a card's keys from the issuer key, its UID and a key version
+///
+/// The Bolt Card deterministic scheme, so an app can program a card with the
+/// keys the server derives for it.
+#[uniffi::export]
+pub fn derive_keys(issuer_key: String, uid: String, version: u32) -> Result<CardKeys, CardError> {
+    let issuer_key = AesKey::from_hex(&issuer_key).map_err(CardError::invalid)?;
+    let uid = CardUid::from_hex(&uid).map_err(CardError::invalid)?;
+    let keys = crypto::derive_keys(&issuer_key, &uid, version);
+
+    Ok(CardKeys {
+        k0: keys.k0.to_string(),
+        k1: keys.k1.to_string(),
+        k2: keys.k2.to_string(),
+        k3: keys.k3.to_string(),
+        k4: keys.k4.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_and_validate() {
+        let keys = generate_keys();
+        let sun = generate_sun(keys.k1.clone(), keys.k2.clone(), "04996c6a926980".to_string(), 7).unwrap();
+
+        let tap = validate_card(keys.k1.clone(), keys.k2.clone(), sun.p.clone(), sun.c.clone()).unwrap();
+        assert_eq!(tap, Tap { uid: "04996c6a926980".to_string(), counter: 7 });
+
+        let wrong_c = "0000000000000000".to_string();
+        assert_eq!(
+            validate_card(keys.k1.clone(), keys.k2.clone(), sun.p.clone(), wrong_c),
+            Err(CardError::InvalidCmac)
+        );
+        assert_eq!(
+            validate_card(keys.k2.clone(), keys.k2.clone(), sun.p.clone(), sun.c.clone()),
+            Err(CardError::InvalidData)
+        );
+        assert!(matches!(
+            validate_card("00".to_string(), keys.k2, sun.p, sun.c),
+            Err(CardError::Invalid { .. })
+        ));
+    }
+
+    #[test]
+    fn test_derive_keys() {
+        let keys = derive_keys("00000000000000000000000000000001".to_string(), "04a39493cc8680".to_string(), 1).unwrap();
+        assert_eq!(keys.k1, "55da174c9608993dc27bb3f30a4a7314");
+        assert_eq!(keys.k2, "f4b404be700ab285e333e32348fa3d3b");
+
+        // Derived keys work like any others
+        let sun = generate_sun(keys.k1.clone(), keys.k2.clone(), "04a39493cc8680".to_string(), 1).unwrap();
+        assert_eq!(validate_card(keys.k1, keys.k2, sun.p, sun.c).unwrap().counter, 1);
+
+        assert!(matches!(
+            derive_keys("01".to_string(), "04a39493cc8680".to_string(), 1),
+            Err(CardError::Invalid { .. })
+        ));
+    }
+}
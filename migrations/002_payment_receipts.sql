@@ -0,0 +1,4 @@
+-- Store settlement proof for payment receipts
+
+ALTER TABLE card_payments ADD COLUMN payment_hash TEXT;
+ALTER TABLE card_payments ADD COLUMN preimage TEXT;
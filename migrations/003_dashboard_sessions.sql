@@ -0,0 +1,10 @@
+-- Login sessions for the web dashboard
+
+CREATE TABLE IF NOT EXISTS dashboard_sessions (
+    session_hash TEXT PRIMARY KEY,
+    username TEXT NOT NULL,
+    expires_at DATETIME NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_dashboard_sessions_expires_at ON dashboard_sessions(expires_at);
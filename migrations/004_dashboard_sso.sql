@@ -0,0 +1,9 @@
+-- Dashboard roles and OpenID Connect login state
+
+ALTER TABLE dashboard_sessions ADD COLUMN role TEXT NOT NULL DEFAULT 'admin';
+
+CREATE TABLE IF NOT EXISTS oidc_login_states (
+    state TEXT PRIMARY KEY,
+    code_verifier TEXT NOT NULL,
+    expires_at DATETIME NOT NULL
+);
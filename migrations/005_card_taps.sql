@@ -0,0 +1,14 @@
+-- History of card taps on the LNURLw endpoint
+
+CREATE TABLE IF NOT EXISTS card_taps (
+    tap_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    card_id INTEGER NOT NULL,
+    uid TEXT,
+    counter INTEGER,
+    success BOOLEAN NOT NULL,
+    reason TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (card_id) REFERENCES cards(card_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_card_taps_card_id ON card_taps(card_id);
@@ -0,0 +1,22 @@
+-- Scoped per-card tokens for cardholder-facing pages, and lost card reports
+
+CREATE TABLE IF NOT EXISTS card_tokens (
+    token_hash TEXT PRIMARY KEY,
+    card_id INTEGER NOT NULL,
+    scope TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (card_id) REFERENCES cards(card_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_card_tokens_card_id ON card_tokens(card_id);
+
+CREATE TABLE IF NOT EXISTS lost_reports (
+    report_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    card_id INTEGER NOT NULL,
+    source TEXT NOT NULL,
+    note TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (card_id) REFERENCES cards(card_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_lost_reports_card_id ON lost_reports(card_id);
@@ -0,0 +1,18 @@
+-- Prepaid balance mode: cards can only spend what was topped up
+
+ALTER TABLE cards ADD COLUMN balance_mode BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE cards ADD COLUMN balance_msats INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS card_topups (
+    topup_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    card_id INTEGER NOT NULL,
+    payment_hash TEXT NOT NULL UNIQUE,
+    invoice TEXT NOT NULL,
+    amount_msats INTEGER NOT NULL,
+    paid BOOLEAN NOT NULL DEFAULT 0,
+    paid_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (card_id) REFERENCES cards(card_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_card_topups_card_id ON card_topups(card_id);
@@ -0,0 +1,18 @@
+-- Free-form card tags for grouping, and an audit trail of operator actions
+
+CREATE TABLE IF NOT EXISTS card_tags (
+    card_id INTEGER NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (card_id, tag),
+    FOREIGN KEY (card_id) REFERENCES cards(card_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_card_tags_tag ON card_tags(tag);
+
+CREATE TABLE IF NOT EXISTS audit_log (
+    audit_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    actor TEXT NOT NULL,
+    action TEXT NOT NULL,
+    details TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
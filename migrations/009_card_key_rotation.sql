@@ -0,0 +1,14 @@
+-- Keys a card had before its last rotation, still accepted until valid_until
+-- so the card keeps working until it has been reprogrammed
+
+CREATE TABLE IF NOT EXISTS card_previous_keys (
+    card_id INTEGER PRIMARY KEY,
+    k0_auth_key TEXT NOT NULL,
+    k1_decrypt_key TEXT NOT NULL,
+    k2_cmac_key TEXT NOT NULL,
+    k3 TEXT NOT NULL,
+    k4 TEXT NOT NULL,
+    valid_until DATETIME NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (card_id) REFERENCES cards(card_id)
+);
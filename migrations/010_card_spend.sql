@@ -0,0 +1,18 @@
+-- Settled spend per card and hour, maintained when a payment settles so limit
+-- checks don't have to sum all of a card's payments. Daily limits are a rolling
+-- 24 hour window, hourly rows let the window move without rescanning payments.
+
+CREATE TABLE IF NOT EXISTS card_spend (
+    card_id INTEGER NOT NULL,
+    hour TEXT NOT NULL,
+    amount_msats INTEGER NOT NULL DEFAULT 0,
+    payments INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (card_id, hour),
+    FOREIGN KEY (card_id) REFERENCES cards(card_id)
+);
+
+INSERT INTO card_spend (card_id, hour, amount_msats, payments)
+SELECT card_id, strftime('%Y-%m-%d %H:00:00', payment_time), SUM(COALESCE(amount_msats, 0)), COUNT(*)
+FROM card_payments
+WHERE paid = 1 AND payment_time IS NOT NULL AND card_id IN (SELECT card_id FROM cards)
+GROUP BY card_id, strftime('%Y-%m-%d %H:00:00', payment_time);
@@ -0,0 +1,12 @@
+-- Track where a withdrawal is in its lifecycle, so payments executed in the
+-- background can be followed and reserve their amount while in flight:
+-- open (k1 handed out), pending (invoice accepted, being paid), paid, failed
+
+ALTER TABLE card_payments ADD COLUMN status TEXT NOT NULL DEFAULT 'open';
+ALTER TABLE card_payments ADD COLUMN failure_reason TEXT;
+
+UPDATE card_payments SET status = CASE
+    WHEN paid = 1 THEN 'paid'
+    WHEN invoice IS NOT NULL THEN 'failed'
+    ELSE 'open'
+END;
@@ -0,0 +1,6 @@
+-- Remember the withdrawable range handed out with each k1, so the callback
+-- only accepts invoices the wallet was actually offered. Sessions opened
+-- before this migration have no bounds and are checked against the limits only.
+
+ALTER TABLE card_payments ADD COLUMN min_withdrawable_msats INTEGER;
+ALTER TABLE card_payments ADD COLUMN max_withdrawable_msats INTEGER;
@@ -0,0 +1,6 @@
+-- Count the invoices tried for each withdrawal session, so a failed payment
+-- can be retried with a new invoice a bounded number of times
+
+ALTER TABLE card_payments ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+
+UPDATE card_payments SET attempts = 1 WHERE invoice IS NOT NULL;
@@ -0,0 +1,15 @@
+-- Replace the `paid` flag and the coarse statuses with an explicit state machine:
+-- created (k1 handed out) -> invoice_attached (invoice accepted, amount reserved)
+-- -> in_flight (handed to the node) -> settled | failed, and created/failed -> expired.
+-- Failed sessions can go back to invoice_attached with a new invoice.
+-- The column keeps its old default 'open', so new sessions set 'created' explicitly.
+
+UPDATE card_payments SET status = CASE
+    WHEN paid = 1 THEN 'settled'
+    WHEN status = 'open' THEN 'created'
+    -- The payment may have reached the node before the last shutdown
+    WHEN status = 'pending' THEN 'in_flight'
+    ELSE 'failed'
+END;
+
+ALTER TABLE card_payments DROP COLUMN paid;
@@ -0,0 +1,18 @@
+-- Reconciliation of payments against the Lightning node: when a payment was
+-- handed to the node, and disagreements between node and database that need
+-- an operator to look at them
+
+ALTER TABLE card_payments ADD COLUMN in_flight_since DATETIME;
+
+CREATE TABLE IF NOT EXISTS payment_discrepancies (
+    discrepancy_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    payment_id INTEGER NOT NULL,
+    -- settled_not_on_node or paid_on_node_not_recorded
+    kind TEXT NOT NULL,
+    -- What the node reported: failed, pending, unknown or succeeded
+    node_status TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    resolved_at DATETIME,
+    UNIQUE (payment_id, kind),
+    FOREIGN KEY (payment_id) REFERENCES card_payments(payment_id)
+);
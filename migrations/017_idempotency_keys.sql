@@ -0,0 +1,12 @@
+-- Responses of requests made with an Idempotency-Key header, so a retried
+-- request gets the original response instead of creating a second card.
+-- A key without response belongs to a request that is still running.
+
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    endpoint TEXT NOT NULL,
+    idempotency_key TEXT NOT NULL,
+    request_hash TEXT NOT NULL,
+    response TEXT,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (endpoint, idempotency_key)
+);
@@ -0,0 +1,24 @@
+-- Cardholder accounts. A card with an owner is only visible to that user
+-- through the /api/me endpoints; cards without one belong to the operator.
+
+CREATE TABLE IF NOT EXISTS users (
+    user_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    username TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT 1,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE cards ADD COLUMN owner_id INTEGER REFERENCES users(user_id);
+
+CREATE INDEX IF NOT EXISTS idx_cards_owner_id ON cards(owner_id);
+
+-- Bearer tokens of logged-in users, stored hashed like dashboard sessions
+CREATE TABLE IF NOT EXISTS user_sessions (
+    token_hash TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+    expires_at DATETIME NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_sessions_user_id ON user_sessions(user_id);
@@ -0,0 +1,16 @@
+-- Organizations sharing one instance, e.g. shops at a hosting provider. Each
+-- pays its cards' withdrawals and receives their top-ups on its own Lightning
+-- backend; cards without an organization use the server's backend.
+
+CREATE TABLE IF NOT EXISTS organizations (
+    org_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    -- Backend spec as accepted by --lightning-backend
+    lightning_backend TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE cards ADD COLUMN org_id INTEGER REFERENCES organizations(org_id);
+
+CREATE INDEX IF NOT EXISTS idx_cards_org_id ON cards(org_id);
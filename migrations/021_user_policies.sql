@@ -0,0 +1,8 @@
+-- Per-user quotas and limits, e.g. for tiers. NULL means no cap, or the
+-- server's --default-tx-limit/--default-day-limit for the defaults.
+
+ALTER TABLE users ADD COLUMN max_cards INTEGER;
+ALTER TABLE users ADD COLUMN default_tx_limit_sats INTEGER;
+ALTER TABLE users ADD COLUMN default_day_limit_sats INTEGER;
+ALTER TABLE users ADD COLUMN max_tx_limit_sats INTEGER;
+ALTER TABLE users ADD COLUMN max_day_limit_sats INTEGER;
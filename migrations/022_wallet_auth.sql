@@ -0,0 +1,22 @@
+-- LNURL-auth (LUD-04) logins. A cardholder signs a challenge with their
+-- wallet and may then claim cards, which binds them to the wallet's linking
+-- key: freezing such a card or reading its history takes a wallet login.
+
+CREATE TABLE IF NOT EXISTS wallet_auth_challenges (
+    k1 TEXT PRIMARY KEY,
+    -- Set once the wallet signed the challenge
+    linking_key TEXT,
+    expires_at DATETIME NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS wallet_sessions (
+    token_hash TEXT PRIMARY KEY,
+    linking_key TEXT NOT NULL,
+    expires_at DATETIME NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE cards ADD COLUMN auth_key TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_cards_auth_key ON cards(auth_key);
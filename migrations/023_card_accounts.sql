@@ -0,0 +1,12 @@
+-- Each card's sub-account against the node: what the operator funded it
+-- with (top-ups and allocations) and what it has spent (settled
+-- withdrawals). Existing cards start with their settled spend counted as
+-- funded, plus any prepaid balance.
+
+ALTER TABLE cards ADD COLUMN funded_msats INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE cards ADD COLUMN spent_msats INTEGER NOT NULL DEFAULT 0;
+
+UPDATE cards SET spent_msats = COALESCE(
+    (SELECT SUM(amount_msats) FROM card_spend WHERE card_spend.card_id = cards.card_id), 0
+);
+UPDATE cards SET funded_msats = spent_msats + balance_msats;
@@ -0,0 +1,16 @@
+-- Invite codes for registering cardholder accounts without open signup.
+-- Each account remembers the invite it registered with.
+
+CREATE TABLE IF NOT EXISTS user_invites (
+    invite_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    code TEXT NOT NULL UNIQUE,
+    max_uses INTEGER NOT NULL,
+    uses INTEGER NOT NULL DEFAULT 0,
+    -- NULL for invites that don't expire
+    expires_at DATETIME,
+    note TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    revoked_at DATETIME
+);
+
+ALTER TABLE users ADD COLUMN invite_id INTEGER REFERENCES user_invites(invite_id);
@@ -0,0 +1,6 @@
+-- Organizations reached on a domain of their own: requests on it only see
+-- the organization's cards, and its cards' URLs are built on it.
+
+ALTER TABLE organizations ADD COLUMN domain TEXT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_organizations_domain ON organizations(domain);
@@ -0,0 +1,15 @@
+-- Channels cardholders are notified on about events of their cards.
+
+CREATE TABLE IF NOT EXISTS user_notification_channels (
+    channel_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+    -- email, telegram or nostr
+    kind TEXT NOT NULL,
+    -- Email address, Telegram chat ID or hex Nostr public key
+    target TEXT NOT NULL,
+    -- Comma separated event names, NULL for all events
+    events TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_notification_channels_user ON user_notification_channels(user_id);
@@ -0,0 +1,5 @@
+-- When a user's or card's personal data was erased. The rows stay so
+-- payments and balances still add up.
+
+ALTER TABLE users ADD COLUMN erased_at DATETIME;
+ALTER TABLE cards ADD COLUMN erased_at DATETIME;
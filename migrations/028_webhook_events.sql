@@ -0,0 +1,18 @@
+-- Operator notifications with the state of their delivery to the webhook.
+-- Events are kept once delivered, so a receiver that lost some can have
+-- them sent again.
+
+CREATE TABLE IF NOT EXISTS webhook_events (
+    event_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    event TEXT NOT NULL,
+    -- The notification as JSON, without its event_id
+    payload TEXT NOT NULL,
+    -- pending, delivered or dead
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    delivered_at DATETIME
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_events_status ON webhook_events(status);
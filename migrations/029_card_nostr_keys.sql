@@ -0,0 +1,4 @@
+-- Nostr public key (hex) of a card's holder, receipts of the card's
+-- settled withdrawals are published for it when enabled.
+
+ALTER TABLE cards ADD COLUMN nostr_pubkey TEXT;
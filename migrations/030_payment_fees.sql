@@ -0,0 +1,4 @@
+-- Routing fee the node paid on top of a settled withdrawal, if the backend
+-- reports it.
+
+ALTER TABLE card_payments ADD COLUMN fee_msats INTEGER;
@@ -0,0 +1,5 @@
+-- Price of one bitcoin when a withdrawal settled, in the operator's fiat
+-- currency, for fiat-valued accounting reports.
+
+ALTER TABLE card_payments ADD COLUMN fiat_currency TEXT;
+ALTER TABLE card_payments ADD COLUMN fiat_rate REAL;
@@ -0,0 +1,4 @@
+-- Provider the recorded bitcoin price of a withdrawal came from, e.g.
+-- kraken, so a price can be traced back to its source.
+
+ALTER TABLE card_payments ADD COLUMN fiat_rate_source TEXT;
@@ -0,0 +1,33 @@
+-- Service fees charged on withdrawals, and their payouts to the operator.
+-- The fee is fixed when the invoice is reserved and booked in service_fees
+-- when the withdrawal settles.
+
+ALTER TABLE card_payments ADD COLUMN service_fee_msats INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS fee_payouts (
+    payout_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- Organization whose node pays, NULL for the server's own
+    org_id INTEGER REFERENCES organizations(org_id),
+    amount_msats INTEGER NOT NULL,
+    -- Lightning address or BOLT12 offer
+    destination TEXT NOT NULL,
+    -- pending, paid or failed
+    status TEXT NOT NULL DEFAULT 'pending',
+    payment_hash TEXT,
+    routing_fee_msats INTEGER,
+    error TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    paid_at DATETIME
+);
+
+CREATE TABLE IF NOT EXISTS service_fees (
+    payment_id INTEGER PRIMARY KEY REFERENCES card_payments(payment_id),
+    card_id INTEGER NOT NULL REFERENCES cards(card_id),
+    org_id INTEGER REFERENCES organizations(org_id),
+    amount_msats INTEGER NOT NULL,
+    -- The payout the fee is paid out with, NULL while it accrues
+    payout_id INTEGER REFERENCES fee_payouts(payout_id),
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_service_fees_payout_id ON service_fees(payout_id);
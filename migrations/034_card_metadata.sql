@@ -0,0 +1,4 @@
+-- Key-value data integrators attach to a card (employee id, table number,
+-- external customer id), as a JSON object.
+
+ALTER TABLE cards ADD COLUMN metadata TEXT NOT NULL DEFAULT '{}';
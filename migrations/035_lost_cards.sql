@@ -0,0 +1,8 @@
+-- When a card was reported lost or stolen. Lost cards are disabled and stay
+-- marked until the operator enables them again, so they can be told apart
+-- from cards that were only switched off.
+
+ALTER TABLE cards ADD COLUMN reported_lost_at DATETIME;
+
+UPDATE cards SET reported_lost_at = (SELECT MAX(created_at) FROM lost_reports WHERE lost_reports.card_id = cards.card_id)
+WHERE enabled = 0 AND card_id IN (SELECT card_id FROM lost_reports);
@@ -0,0 +1,7 @@
+-- Temporary freezes, separate from `enabled`: a frozen card refuses taps until
+-- `frozen_until`, or until it is unfrozen if that isn't set. Expired freezes
+-- are cleared by the server.
+
+ALTER TABLE cards ADD COLUMN frozen_at DATETIME;
+ALTER TABLE cards ADD COLUMN frozen_until DATETIME;
+ALTER TABLE cards ADD COLUMN freeze_reason TEXT;
@@ -0,0 +1,6 @@
+-- Per-card templates for the description offered to wallets, NULL for the
+-- server's --withdraw-description, and the description each withdrawal
+-- session was offered with, which the callback checks invoices against.
+
+ALTER TABLE cards ADD COLUMN withdraw_description TEXT;
+ALTER TABLE card_payments ADD COLUMN description TEXT;
@@ -0,0 +1,4 @@
+-- Note on a withdrawal for expense annotation. Taken from the invoice's
+-- description when the wallet sends one, editable by the operator.
+
+ALTER TABLE card_payments ADD COLUMN memo TEXT;
@@ -0,0 +1,9 @@
+-- Ed25519 keys receipts were signed with. The secret is only kept for the key
+-- the server generated itself, a configured --receipt-signing-key is just
+-- recorded so receipts signed with it can still be verified after it changes.
+
+CREATE TABLE IF NOT EXISTS receipt_keys (
+    public_key TEXT PRIMARY KEY,
+    secret_key TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
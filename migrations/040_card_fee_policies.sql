@@ -0,0 +1,5 @@
+-- Service fee of a card, overriding --service-fee-msats and
+-- --service-fee-percent. NULL for cards charged the server's fee.
+
+ALTER TABLE cards ADD COLUMN fee_flat_msats INTEGER;
+ALTER TABLE cards ADD COLUMN fee_percent REAL;
@@ -0,0 +1,9 @@
+-- On-chain payouts of withdrawals too large or failing too often for Lightning.
+-- A session paid on-chain has no invoice or payment hash, but the address it
+-- was paid to and, once known, the transaction.
+
+ALTER TABLE card_payments ADD COLUMN onchain_address TEXT;
+ALTER TABLE card_payments ADD COLUMN onchain_txid TEXT;
+
+-- Address a card's large withdrawals are paid to when the wallet only sends an invoice
+ALTER TABLE cards ADD COLUMN onchain_fallback_address TEXT;
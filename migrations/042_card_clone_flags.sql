@@ -0,0 +1,5 @@
+-- Cards suspected of being cloned: set on every enabled card carrying the UID
+-- of a tap that validated against more than one card record. Flagged cards
+-- refuse taps and withdrawals until the operator clears the flag.
+
+ALTER TABLE cards ADD COLUMN clone_flagged_at DATETIME;
@@ -0,0 +1,7 @@
+-- Soft deletion of cards: a deleted card keeps its history but is left out
+-- of validation and listings until it is restored, or purged for good once
+-- the retention period is over.
+
+ALTER TABLE cards ADD COLUMN deleted_at DATETIME;
+
+CREATE INDEX IF NOT EXISTS idx_cards_deleted_at ON cards(deleted_at);
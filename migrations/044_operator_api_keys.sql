@@ -0,0 +1,12 @@
+-- API keys for the operator API, issued with the CLI. Viewers may only read,
+-- admins may do everything.
+
+CREATE TABLE IF NOT EXISTS operator_api_keys (
+    key_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    role TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    last_used_at DATETIME,
+    revoked_at DATETIME
+);
@@ -0,0 +1,19 @@
+-- Daily limits count the calendar day (UTC) instead of a rolling 24 hours, so
+-- settled spend is kept as one row per card and day instead of per hour.
+
+CREATE TABLE card_spend_daily (
+    card_id INTEGER NOT NULL,
+    day TEXT NOT NULL,
+    amount_msats INTEGER NOT NULL DEFAULT 0,
+    payments INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (card_id, day),
+    FOREIGN KEY (card_id) REFERENCES cards(card_id)
+);
+
+INSERT INTO card_spend_daily (card_id, day, amount_msats, payments)
+SELECT card_id, substr(hour, 1, 10), SUM(amount_msats), SUM(payments)
+FROM card_spend
+GROUP BY card_id, substr(hour, 1, 10);
+
+DROP TABLE card_spend;
+ALTER TABLE card_spend_daily RENAME TO card_spend;
@@ -0,0 +1,18 @@
+-- Counts changes to card keys, so servers drop the keys they cached once a
+-- card was rotated, erased or purged, also when the CLI did it.
+
+CREATE TABLE IF NOT EXISTS card_key_generation (
+    generation INTEGER NOT NULL
+);
+
+INSERT INTO card_key_generation (generation) VALUES (0);
+
+CREATE TRIGGER IF NOT EXISTS card_keys_changed AFTER UPDATE OF k1_decrypt_key, k2_cmac_key ON cards
+BEGIN
+    UPDATE card_key_generation SET generation = generation + 1;
+END;
+
+CREATE TRIGGER IF NOT EXISTS card_keys_deleted AFTER DELETE ON cards
+BEGIN
+    UPDATE card_key_generation SET generation = generation + 1;
+END;
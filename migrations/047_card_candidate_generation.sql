@@ -0,0 +1,17 @@
+-- Taps without card ID are matched against an index of the enabled cards that
+-- servers keep in memory, so adding a card and changing which cards are
+-- enabled, their UIDs or organizations count as key changes as well.
+
+CREATE TRIGGER IF NOT EXISTS card_added AFTER INSERT ON cards
+BEGIN
+    UPDATE card_key_generation SET generation = generation + 1;
+END;
+
+CREATE TRIGGER IF NOT EXISTS card_candidate_changed AFTER UPDATE OF enabled, deleted_at, uid, org_id ON cards
+WHEN OLD.enabled IS NOT NEW.enabled
+    OR OLD.deleted_at IS NOT NEW.deleted_at
+    OR OLD.uid IS NOT NEW.uid
+    OR OLD.org_id IS NOT NEW.org_id
+BEGIN
+    UPDATE card_key_generation SET generation = generation + 1;
+END;
@@ -0,0 +1,5 @@
+-- Set while a card is disabled by its holder, through the user API or a bound
+-- wallet. Holders can only re-enable such cards, not ones an operator
+-- disabled, that were replaced or reported lost.
+
+ALTER TABLE cards ADD COLUMN holder_disabled BOOLEAN NOT NULL DEFAULT 0;
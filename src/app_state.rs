@@ -1,10 +1,75 @@
 use sqlx::{Pool, Sqlite};
 use std::sync::Arc;
-use crate::{config::Config, lightning::LightningBackend};
+use crate::{
+    clock::Clock,
+    backends::LightningBackends,
+    config::Config,
+    graphql::ApiSchema,
+    jobs::JobQueue,
+    key_cache::KeyCache,
+    notifications::{Notifier, channels::Channels},
+    random::Random,
+    receipts::ReceiptSigner,
+    rates::Rates,
+    validation::{CardValidator, DefaultCryptoService},
+};
 
 #[derive(Clone)]
 pub struct AppState {
     pub pool: Pool<Sqlite>,
     pub config: Arc<Config>,
-    pub lightning: Arc<dyn LightningBackend>,
-}
\ No newline at end of file
+    pub lightning: Arc<LightningBackends>,
+    pub http: reqwest::Client,
+    pub notifier: Arc<dyn Notifier>,
+    pub channels: Arc<Channels>,
+    pub rates: Arc<Rates>,
+    /// Time of expiry checks, daily limits, session TTLs and schedules
+    pub clock: Arc<dyn Clock>,
+    /// Source of card keys, one-time codes and k1s
+    pub random: Random,
+    pub key_cache: Arc<KeyCache>,
+    pub receipts: Arc<ReceiptSigner>,
+    pub jobs: Arc<JobQueue>,
+    pub validator: Arc<CardValidator<DefaultCryptoService>>,
+    /// Schema of the GraphQL API, `None` unless it's enabled
+    pub graphql: Option<ApiSchema>,
+}
+/// State of a server on an in-memory database paying with a mock node, for tests
+///
+/// `args` are command line options on top of the domain.
+#[cfg(test)]
+pub async fn test_state(args: &[&str]) -> (AppState, Arc<lnurlw_core::lightning::MockLightning>) {
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Args {
+        #[command(flatten)]
+        config: Config,
+    }
+
+    let config = Args::parse_from(["lnurlw-server", "--domain", "cards.example.com"].iter().chain(args)).config;
+    let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+    crate::db::run_migrations(&pool).await.unwrap();
+    let random = Random::from_config(&config).unwrap();
+    let http = reqwest::Client::new();
+    let mock = Arc::new(lnurlw_core::lightning::MockLightning::default());
+
+    let state = AppState {
+        pool: pool.clone(),
+        lightning: Arc::new(LightningBackends::new(mock.clone())),
+        notifier: Arc::new(crate::notifications::LogNotifier),
+        channels: Arc::new(Channels::from_config(&config, http.clone()).unwrap()),
+        rates: Arc::new(Rates::from_config(&config, http.clone()).unwrap()),
+        clock: Arc::new(crate::clock::SystemClock),
+        key_cache: Arc::default(),
+        receipts: Arc::new(ReceiptSigner::load(&pool, &config, &random).await.unwrap()),
+        jobs: Arc::new(JobQueue::new(pool)),
+        validator: Arc::new(CardValidator::new_default()),
+        graphql: None,
+        random,
+        http,
+        config: Arc::new(config),
+    };
+
+    (state, mock)
+}
@@ -0,0 +1,501 @@
+pub mod oidc;
+
+use argon2::{password_hash::SaltString, Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use axum::{
+    extract::{FromRequestParts, MatchedPath, RawPathParams, Request, State},
+    http::{header, request::Parts, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+use sha2::{Digest, Sha256};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::{
+    app_state::AppState,
+    db::{
+        operator_keys, sessions,
+        users::{self, ApiKeyScope},
+        wallet,
+    },
+    random::Random,
+};
+
+/// Permission level on the dashboard and the operator API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Role {
+    /// Full access, including actions that modify cards
+    Admin,
+    /// Read-only access
+    Viewer,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Viewer => "viewer",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "admin" => Some(Role::Admin),
+            "viewer" => Some(Role::Viewer),
+            _ => None,
+        }
+    }
+}
+
+/// Name of the dashboard session cookie
+pub const SESSION_COOKIE: &str = "lnurlw_session";
+
+/// Name of the cookie tying a pending OIDC login to the browser that started it
+pub const OIDC_STATE_COOKIE: &str = "lnurlw_oidc_state";
+
+/// Hash a password into an Argon2 PHC string with a random salt
+pub fn hash_password(random: &Random, password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::encode_b64(&random.bytes::<16>()?).map_err(|e| anyhow::anyhow!(e))?;
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow::anyhow!(e))?;
+    Ok(hash.to_string())
+}
+
+/// Verify a password against an Argon2 PHC string
+pub fn verify_password(phc_hash: &str, password: &str) -> bool {
+    match PasswordHash::new(phc_hash) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Whether a base32 secret can be used for TOTP codes
+pub fn is_valid_totp_secret(base32_secret: &str) -> bool {
+    Secret::Encoded(base32_secret.to_string())
+        .to_bytes()
+        .is_ok_and(|secret| TOTP::new(Algorithm::SHA1, 6, 1, 30, secret).is_ok())
+}
+
+/// Verify a 6-digit TOTP code (30s step, one step of clock skew) against a base32 secret
+pub fn verify_totp(base32_secret: &str, code: &str) -> bool {
+    let secret = match Secret::Encoded(base32_secret.to_string()).to_bytes() {
+        Ok(secret) => secret,
+        Err(_) => return false,
+    };
+    match TOTP::new(Algorithm::SHA1, 6, 1, 30, secret) {
+        Ok(totp) => totp.check_current(code.trim()).unwrap_or(false),
+        Err(_) => false,
+    }
+}
+
+/// Generate a fresh random session token for the cookie
+pub fn new_session_token(random: &Random) -> anyhow::Result<String> {
+    random.hex::<32>()
+}
+
+/// Sessions are stored hashed so a leaked database doesn't leak live cookies
+pub fn hash_session_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Build the Set-Cookie value for a new session
+pub fn session_cookie(base_path: &str, token: &str, ttl_hours: i64) -> String {
+    format!(
+        "{}={}; Path={}/dashboard; HttpOnly; Secure; SameSite=Lax; Max-Age={}",
+        SESSION_COOKIE,
+        token,
+        base_path,
+        ttl_hours * 3600
+    )
+}
+
+/// Set-Cookie value that removes the session cookie
+pub fn clear_session_cookie(base_path: &str) -> String {
+    format!(
+        "{}=; Path={}/dashboard; HttpOnly; Secure; SameSite=Lax; Max-Age=0",
+        SESSION_COOKIE, base_path
+    )
+}
+
+/// Build the Set-Cookie value for a pending OIDC login, valid as long as its stored state
+pub fn oidc_state_cookie(base_path: &str, state: &str) -> String {
+    format!(
+        "{}={}; Path={}/dashboard/oidc; HttpOnly; Secure; SameSite=Lax; Max-Age=600",
+        OIDC_STATE_COOKIE, state, base_path
+    )
+}
+
+/// Set-Cookie value that removes the OIDC state cookie
+pub fn clear_oidc_state_cookie(base_path: &str) -> String {
+    format!(
+        "{}=; Path={}/dashboard/oidc; HttpOnly; Secure; SameSite=Lax; Max-Age=0",
+        OIDC_STATE_COOKIE, base_path
+    )
+}
+
+/// Extract a cookie's value from the request's Cookie headers
+pub fn cookie_value(headers: &HeaderMap, cookie_name: &str) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == cookie_name)
+        .map(|(_, value)| value.to_string())
+}
+
+/// Extract the session token from the request's Cookie header
+pub fn session_token_from_parts(parts: &Parts) -> Option<String> {
+    cookie_value(&parts.headers, SESSION_COOKIE)
+}
+
+/// A logged-in dashboard user, extracted from the session cookie
+#[derive(Debug, Clone)]
+pub struct DashboardUser {
+    pub username: String,
+    pub role: Role,
+    pub session_hash: String,
+}
+
+impl DashboardUser {
+    /// Reject users without write access
+    pub fn require_admin(&self) -> Result<(), axum::http::StatusCode> {
+        if self.role == Role::Admin {
+            Ok(())
+        } else {
+            Err(axum::http::StatusCode::FORBIDDEN)
+        }
+    }
+}
+
+impl FromRequestParts<AppState> for DashboardUser {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        if !state.config.dashboard_enabled() {
+            return Err(axum::http::StatusCode::NOT_FOUND.into_response());
+        }
+
+        let login = || Redirect::to(&state.config.path("/dashboard/login")).into_response();
+
+        let token = session_token_from_parts(parts).ok_or_else(login)?;
+        let session_hash = hash_session_token(&token);
+
+        let session = sessions::get_active_session(&state.pool, &session_hash, state.clock.now())
+            .await
+            .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response())?
+            .ok_or_else(login)?;
+
+        // Unknown roles fall back to the least privileged one
+        let role = Role::parse(&session.role).unwrap_or(Role::Viewer);
+
+        Ok(DashboardUser {
+            username: session.username,
+            role,
+            session_hash,
+        })
+    }
+}
+
+/// Prefix telling API keys apart from session tokens, and easy to spot for secret scanners
+pub const API_KEY_PREFIX: &str = "lnurlw_";
+
+/// Generate a fresh user API key
+pub fn new_api_key(random: &Random) -> anyhow::Result<String> {
+    Ok(format!("{}{}", API_KEY_PREFIX, random.hex::<32>()?))
+}
+
+/// A cardholder authenticated with a session token or one of their API keys
+#[derive(Debug, Clone)]
+pub struct ApiUser {
+    pub user_id: i64,
+    pub username: String,
+    pub token_hash: String,
+    /// Scopes of the API key used, `None` for a login session which may do everything
+    pub scopes: Option<Vec<ApiKeyScope>>,
+}
+
+impl ApiUser {
+    /// Actor recorded in the audit log for changes made by this user
+    pub fn actor(&self) -> String {
+        format!("user:{}", self.username)
+    }
+
+    /// Reject API keys without `scope`
+    pub fn require_scope(&self, scope: ApiKeyScope) -> Result<(), axum::http::StatusCode> {
+        match &self.scopes {
+            Some(scopes) if !scopes.contains(&scope) => Err(axum::http::StatusCode::FORBIDDEN),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reject API keys, for actions only the user may take themselves
+    pub fn require_session(&self) -> Result<(), axum::http::StatusCode> {
+        match self.scopes {
+            Some(_) => Err(axum::http::StatusCode::FORBIDDEN),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FromRequestParts<AppState> for ApiUser {
+    type Rejection = axum::http::StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let token = bearer_token(parts).ok_or(axum::http::StatusCode::UNAUTHORIZED)?;
+        let token_hash = hash_session_token(token);
+
+        let found = if token.starts_with(API_KEY_PREFIX) {
+            users::use_api_key(&state.pool, &token_hash)
+                .await
+                .map(|found| found.map(|(user, scopes)| (user, Some(scopes))))
+        } else {
+            users::get_session_user(&state.pool, &token_hash, state.clock.now())
+                .await
+                .map(|found| found.map(|user| (user, None)))
+        };
+        let (user, scopes) = found
+            .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(axum::http::StatusCode::UNAUTHORIZED)?;
+
+        Ok(ApiUser {
+            user_id: user.user_id,
+            username: user.username,
+            token_hash,
+            scopes,
+        })
+    }
+}
+
+/// Prefix of operator API keys, telling them apart from users' keys
+pub const OPERATOR_KEY_PREFIX: &str = "lnurlwop_";
+
+/// Generate a fresh operator API key
+pub fn new_operator_key(random: &Random) -> anyhow::Result<String> {
+    Ok(format!("{}{}", OPERATOR_KEY_PREFIX, random.hex::<32>()?))
+}
+
+/// Guard the operator API
+///
+/// Operator keys act by their role: viewers may read, which includes the
+/// read-only GraphQL API, admins may do everything. A user's credentials only
+/// reach their own cards, reading them with the `read` scope and topping them
+/// up with `top_up`.
+pub async fn require_operator(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let (mut parts, body) = request.into_parts();
+    if let Err(status) = operator_access(&state, &mut parts).await {
+        return status.into_response();
+    }
+    next.run(Request::from_parts(parts, body)).await
+}
+
+async fn operator_access(state: &AppState, parts: &mut Parts) -> Result<(), StatusCode> {
+    let token = bearer_token(parts).ok_or(StatusCode::UNAUTHORIZED)?;
+    let route = parts.extensions.get::<MatchedPath>().map(|path| path.as_str().to_string()).unwrap_or_default();
+    let reads = parts.method == Method::GET || parts.method == Method::HEAD;
+
+    if token.starts_with(OPERATOR_KEY_PREFIX) {
+        let role = operator_keys::use_key(&state.pool, &hash_session_token(token))
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::UNAUTHORIZED)?;
+        return match Role::parse(&role).unwrap_or(Role::Viewer) {
+            Role::Admin => Ok(()),
+            Role::Viewer if reads || route.ends_with("/graphql") => Ok(()),
+            Role::Viewer => Err(StatusCode::FORBIDDEN),
+        };
+    }
+
+    let user = ApiUser::from_request_parts(parts, state).await?;
+    let scope = if reads {
+        ApiKeyScope::Read
+    } else if parts.method == Method::POST && route.ends_with("/cards/{card_id}/topups") {
+        ApiKeyScope::TopUp
+    } else {
+        return Err(StatusCode::FORBIDDEN);
+    };
+    user.require_scope(scope)?;
+
+    let params = RawPathParams::from_request_parts(parts, state).await.map_err(|_| StatusCode::FORBIDDEN)?;
+    let card_id = params
+        .iter()
+        .find(|(name, _)| *name == "card_id")
+        .and_then(|(_, value)| value.parse().ok())
+        .ok_or(StatusCode::FORBIDDEN)?;
+    users::get_owned_card(&state.pool, user.user_id, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::FORBIDDEN)?;
+
+    Ok(())
+}
+
+fn bearer_token(parts: &Parts) -> Option<&str> {
+    parts
+        .headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+/// Check an LNURL-auth (LUD-04) signature: `sig` is the DER encoded ECDSA
+/// signature of the 32 byte challenge `k1` by the compressed public `key`
+pub fn verify_lnurl_auth(k1: &str, sig: &str, key: &str) -> bool {
+    use secp256k1::{Message, PublicKey, Secp256k1, ecdsa::Signature};
+
+    let (Ok(k1), Ok(sig), Ok(key)) = (hex::decode(k1), hex::decode(sig), hex::decode(key)) else {
+        return false;
+    };
+    let (Ok(k1), Ok(mut sig), Ok(key)) = (<[u8; 32]>::try_from(k1), Signature::from_der(&sig), PublicKey::from_slice(&key)) else {
+        return false;
+    };
+    // libsecp256k1 only accepts low-S signatures, not every wallet's signer makes them
+    sig.normalize_s();
+
+    Secp256k1::verification_only()
+        .verify_ecdsa(&Message::from_digest(k1), &sig, &key)
+        .is_ok()
+}
+
+/// A cardholder logged in with their wallet over LNURL-auth
+#[derive(Debug, Clone)]
+pub struct WalletUser {
+    /// The wallet's linking key for this server, hex encoded
+    pub linking_key: String,
+    pub token_hash: String,
+}
+
+impl WalletUser {
+    /// Actor recorded in the audit log for changes made with this wallet
+    pub fn actor(&self) -> String {
+        format!("wallet:{}", self.linking_key)
+    }
+}
+
+impl FromRequestParts<AppState> for WalletUser {
+    type Rejection = axum::http::StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let token = bearer_token(parts).ok_or(axum::http::StatusCode::UNAUTHORIZED)?;
+        let token_hash = hash_session_token(token);
+
+        let linking_key = wallet::get_session_key(&state.pool, &token_hash, state.clock.now())
+            .await
+            .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(axum::http::StatusCode::UNAUTHORIZED)?;
+
+        Ok(WalletUser { linking_key, token_hash })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_password() {
+        let hash = hash_password(&Random::default(), "correct horse").unwrap();
+
+        assert!(verify_password(&hash, "correct horse"));
+        assert!(!verify_password(&hash, "wrong"));
+        assert!(!verify_password("not a phc string", "correct horse"));
+    }
+
+    #[test]
+    fn test_verify_totp() {
+        let secret = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+        let totp = TOTP::new(
+            Algorithm::SHA1,
+            6,
+            1,
+            30,
+            Secret::Encoded(secret.to_string()).to_bytes().unwrap(),
+        )
+        .unwrap();
+        let code = totp.generate_current().unwrap();
+
+        assert!(verify_totp(secret, &code));
+        assert!(!verify_totp(secret, "abcdef"));
+        assert!(!verify_totp("not base32!", &code));
+    }
+
+    #[test]
+    fn test_verify_lnurl_auth() {
+        use secp256k1::{Message, Secp256k1, SecretKey};
+
+        let secp = Secp256k1::new();
+        let secret = SecretKey::from_slice(&[7; 32]).unwrap();
+        let key = hex::encode(secret.public_key(&secp).serialize());
+        let k1 = [42; 32];
+        let sig = hex::encode(secp.sign_ecdsa(&Message::from_digest(k1), &secret).serialize_der());
+
+        assert!(verify_lnurl_auth(&hex::encode(k1), &sig, &key));
+        assert!(!verify_lnurl_auth(&hex::encode([43; 32]), &sig, &key));
+        assert!(!verify_lnurl_auth(&hex::encode(k1), "30", &key));
+        assert!(!verify_lnurl_auth("abcd", &sig, &key));
+    }
+
+    #[tokio::test]
+    async fn test_require_operator() {
+        use axum::{Router, body::Body, http::Request, routing::{get, post}};
+        use tower::ServiceExt;
+        use crate::db::queries;
+
+        let (state, _) = crate::app_state::test_state(&[]).await;
+        let pool = &state.pool;
+        let app = Router::new()
+            .route("/cards/{card_id}", get(|| async {}).delete(|| async {}))
+            .route("/cards/{card_id}/topups", post(|| async {}))
+            .route("/graphql", post(|| async {}))
+            .route_layer(axum::middleware::from_fn_with_state(state.clone(), require_operator))
+            .with_state(state.clone());
+
+        let mut keys = Vec::new();
+        for role in ["admin", "viewer", "admin"] {
+            let key = new_operator_key(&state.random).unwrap();
+            let key_id = operator_keys::create_key(pool, role, &hash_session_token(&key), role).await.unwrap();
+            keys.push((key_id, key));
+        }
+        operator_keys::revoke_key(pool, keys[2].0).await.unwrap();
+        let [(_, admin), (_, viewer), (_, revoked)] = &keys[..] else { unreachable!() };
+        let alice = users::create_user(pool, "alice", "hash").await.unwrap().unwrap();
+        let reader = new_api_key(&state.random).unwrap();
+        users::create_api_key(pool, alice, "app", &hash_session_token(&reader), &[ApiKeyScope::Read]).await.unwrap();
+        let key = "00000000000000000000000000000000";
+        let owned = queries::insert_card(pool, "", key, key, key, key, key, "Own", 1000, 10000, true, true, "a", Some(alice), None, chrono::Utc::now())
+            .await
+            .unwrap();
+        let other = queries::insert_card(pool, "", key, key, key, key, key, "Other", 1000, 10000, true, true, "b", None, None, chrono::Utc::now())
+            .await
+            .unwrap();
+
+        let status = async |method: &str, uri: String, token: Option<&str>| {
+            let mut request = Request::builder().method(method).uri(uri);
+            if let Some(token) = token {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            app.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap().status()
+        };
+        let cases = [
+            ("GET", format!("/cards/{}", other), None, StatusCode::UNAUTHORIZED),
+            ("GET", format!("/cards/{}", other), Some(revoked), StatusCode::UNAUTHORIZED),
+            ("DELETE", format!("/cards/{}", other), Some(admin), StatusCode::OK),
+            ("GET", format!("/cards/{}", other), Some(viewer), StatusCode::OK),
+            ("POST", "/graphql".to_string(), Some(viewer), StatusCode::OK),
+            ("DELETE", format!("/cards/{}", other), Some(viewer), StatusCode::FORBIDDEN),
+            // A user's key only reads their own cards, within its scopes
+            ("GET", format!("/cards/{}", owned), Some(&reader), StatusCode::OK),
+            ("GET", format!("/cards/{}", other), Some(&reader), StatusCode::FORBIDDEN),
+            ("POST", format!("/cards/{}/topups", owned), Some(&reader), StatusCode::FORBIDDEN),
+            ("DELETE", format!("/cards/{}", owned), Some(&reader), StatusCode::FORBIDDEN),
+            ("POST", "/graphql".to_string(), Some(&reader), StatusCode::FORBIDDEN),
+        ];
+        for (method, uri, token, expected) in cases {
+            assert_eq!(status(method, uri.clone(), token.map(String::as_str)).await, expected, "{} {}", method, uri);
+        }
+    }
+}
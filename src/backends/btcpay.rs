@@ -0,0 +1,432 @@
+//! Withdrawals paid as payouts of a BTCPay Server pull payment
+//!
+//! For operators whose treasury lives in BTCPay: instead of paying from a
+//! node, each withdrawal claims the invoice against a pull payment over the
+//! Greenfield API, and the store's Lightning payout processor pays it once the
+//! payout is approved. The spec looks like
+//! `btcpay:https://btcpay.example.com?pull_payment=<id>&store=<id>&api_key=<key>`;
+//! `store` and `api_key` are only needed if the pull payment doesn't approve
+//! claims by itself. On-chain fallbacks are claimed as on-chain payouts of the
+//! same pull payment, which the store's on-chain payout processor batches.
+
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use reqwest::{RequestBuilder, Response, Url};
+use serde::{Deserialize, de::DeserializeOwned};
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::lightning::{Invoice, LightningBackend, NodeInfo, OnchainPayment, OutgoingPayment, PaymentResult};
+
+/// How long a withdrawal waits for BTCPay to pay its payout
+const PAYOUT_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+pub struct BtcPayPullPayment {
+    http: reqwest::Client,
+    base_url: Url,
+    pull_payment_id: String,
+    /// Store and API key payouts are approved and cancelled with
+    store: Option<(String, String)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum PayoutState {
+    AwaitingApproval,
+    AwaitingPayment,
+    InProgress,
+    Completed,
+    Cancelled,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Payout {
+    id: String,
+    #[serde(default)]
+    revision: i64,
+    destination: String,
+    amount: Option<String>,
+    state: PayoutState,
+    payment_proof: Option<PaymentProof>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PaymentProof {
+    preimage: Option<String>,
+    /// Set for on-chain payouts once their transaction is broadcast
+    #[serde(rename = "transactionId")]
+    transaction_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PullPayment {
+    name: Option<String>,
+    currency: String,
+    amount: String,
+}
+
+impl Payout {
+    fn payment_hash(&self) -> Option<String> {
+        Invoice::from_str(&self.destination).ok().map(|invoice| invoice.payment_hash())
+    }
+
+    fn preimage(&self) -> Option<String> {
+        self.payment_proof.as_ref().and_then(|proof| proof.preimage.clone())
+    }
+}
+
+impl BtcPayPullPayment {
+    /// Parse the URL part of a `btcpay:` spec
+    pub fn from_url(http: reqwest::Client, url: &str) -> Result<Self> {
+        let mut base_url = Url::parse(url).map_err(|e| anyhow!("Invalid BTCPay URL {:?}: {}", url, e))?;
+        if !matches!(base_url.scheme(), "http" | "https") {
+            bail!("BTCPay URL {:?} must be http(s)", url);
+        }
+
+        let param = |name: &str| {
+            base_url
+                .query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        };
+        let pull_payment_id = param("pull_payment").ok_or_else(|| anyhow!("BTCPay spec needs pull_payment=<id>"))?;
+        let store = match (param("store"), param("api_key")) {
+            (Some(store_id), Some(api_key)) => Some((store_id, api_key)),
+            (None, None) => None,
+            _ => bail!("BTCPay spec needs store and api_key together"),
+        };
+        base_url.set_query(None);
+
+        Ok(Self {
+            http,
+            base_url,
+            pull_payment_id,
+            store,
+        })
+    }
+
+    fn endpoint(&self, segments: &[&str]) -> Url {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .expect("http URLs have a path")
+            .pop_if_empty()
+            .extend(["api", "v1"])
+            .extend(segments);
+        url
+    }
+
+    fn pull_payment_endpoint(&self, segments: &[&str]) -> Url {
+        let mut path = vec!["pull-payments", self.pull_payment_id.as_str()];
+        path.extend(segments);
+        self.endpoint(&path)
+    }
+
+    /// A request on the store's payouts, `None` without store access
+    fn store_request(&self, method: reqwest::Method, payout_id: &str) -> Option<RequestBuilder> {
+        let (store_id, api_key) = self.store.as_ref()?;
+        let url = self.endpoint(&["stores", store_id, "payouts", payout_id]);
+        Some(self.http.request(method, url).header("Authorization", format!("token {}", api_key)))
+    }
+
+    async fn execute(&self, request: RequestBuilder) -> Result<Response> {
+        let response = request
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+            .map_err(|e| anyhow!("BTCPay unreachable: {}", e.without_url()))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body: serde_json::Value = response.json().await.unwrap_or_default();
+            // Greenfield answers errors with an object, validation errors with a list of them
+            let message = body["message"]
+                .as_str()
+                .or_else(|| body[0]["message"].as_str())
+                .unwrap_or("no details");
+            bail!("BTCPay answered {}: {}", status, message);
+        }
+        Ok(response)
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T> {
+        Ok(self.execute(request).await?.json().await?)
+    }
+
+    async fn payouts(&self) -> Result<Vec<Payout>> {
+        let url = self.pull_payment_endpoint(&["payouts"]);
+        self.send(self.http.get(url).query(&[("includeCancelled", "true")])).await
+    }
+
+    async fn payout(&self, payout_id: &str) -> Result<Payout> {
+        self.send(self.http.get(self.pull_payment_endpoint(&["payouts", payout_id]))).await
+    }
+
+    /// The payout claiming the invoice with `payment_hash`, preferring one that wasn't cancelled
+    async fn find_payout(&self, payment_hash: &str) -> Result<Option<Payout>> {
+        let payouts = self.payouts().await?;
+        Ok(payouts
+            .into_iter()
+            .filter(|payout| payout.payment_hash().as_deref() == Some(payment_hash))
+            .min_by_key(|payout| payout.state == PayoutState::Cancelled))
+    }
+
+    /// Claim `invoice`, or pick up the claim of an earlier attempt to pay it
+    async fn claim(&self, invoice: &Invoice) -> Result<Payout> {
+        if let Some(payout) = self.find_payout(&invoice.payment_hash()).await?
+            && payout.state != PayoutState::Cancelled
+        {
+            return Ok(payout);
+        }
+
+        let body = serde_json::json!({
+            "destination": invoice.bolt11(),
+            "payoutMethodId": "BTC-LN",
+            // Name of the field before BTCPay 2.0
+            "paymentMethod": "BTC-LightningNetwork",
+        });
+        self.send(self.http.post(self.pull_payment_endpoint(&["payouts"])).json(&body)).await
+    }
+
+    /// Approve a payout that waits for it, if the store's API key is known
+    async fn approve(&self, payout: Payout) -> Result<Payout> {
+        if payout.state == PayoutState::AwaitingApproval
+            && let Some(request) = self.store_request(reqwest::Method::POST, &payout.id)
+        {
+            let body = serde_json::json!({ "revision": payout.revision });
+            return self.send(request.json(&body)).await;
+        }
+        Ok(payout)
+    }
+
+    /// Wait for the payout to complete or be cancelled, approving it if needed
+    async fn settle(&self, mut payout: Payout) -> Result<Payout> {
+        let deadline = Instant::now() + PAYOUT_TIMEOUT;
+        let mut approved = false;
+        loop {
+            match payout.state {
+                PayoutState::Completed | PayoutState::Cancelled => return Ok(payout),
+                PayoutState::AwaitingApproval if !approved && self.store.is_some() => {
+                    payout = self.approve(payout).await?;
+                    approved = true;
+                    continue;
+                }
+                _ => {}
+            }
+
+            if Instant::now() >= deadline {
+                return self.give_up(payout).await;
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+            payout = self.payout(&payout.id).await?;
+        }
+    }
+
+    /// Cancel a payout BTCPay hasn't started paying, so the withdrawal can fail safely
+    async fn give_up(&self, payout: Payout) -> Result<Payout> {
+        let waiting = matches!(payout.state, PayoutState::AwaitingApproval | PayoutState::AwaitingPayment);
+        if waiting && let Some(request) = self.store_request(reqwest::Method::DELETE, &payout.id) {
+            self.execute(request).await?;
+            return self.payout(&payout.id).await;
+        }
+        bail!(
+            "Payout {} is still {:?} after {}s, reconciliation will pick up its outcome",
+            payout.id,
+            payout.state,
+            PAYOUT_TIMEOUT.as_secs()
+        )
+    }
+}
+
+/// Whole sats as an amount of the pull payment's currency, `None` for fiat currencies
+fn from_sats(sats: u64, currency: &str) -> Option<String> {
+    match currency.to_ascii_uppercase().as_str() {
+        "BTC" => Some(format!("{}.{:08}", sats / 100_000_000, sats % 100_000_000)),
+        "SATS" => Some(sats.to_string()),
+        _ => None,
+    }
+}
+
+/// An amount of the pull payment's currency in msats, `None` for fiat currencies
+fn to_msats(amount: &str, currency: &str) -> Option<u64> {
+    let decimals = match currency.to_ascii_uppercase().as_str() {
+        "BTC" => 11,
+        "SATS" => 3,
+        _ => return None,
+    };
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    let fraction = fraction.trim_end_matches('0');
+    if whole.is_empty() || fraction.len() > decimals || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let whole: u64 = whole.parse().ok()?;
+    let fraction: u64 = format!("{:0<width$}", fraction, width = decimals).parse().ok()?;
+    whole.checked_mul(10u64.pow(decimals as u32))?.checked_add(fraction)
+}
+
+#[async_trait]
+impl LightningBackend for BtcPayPullPayment {
+    async fn pay_invoice(&self, invoice: &Invoice, expected_amount_msats: u64) -> Result<PaymentResult> {
+        let amount_msats = invoice.amount_msats()?;
+        if amount_msats != expected_amount_msats {
+            return Ok(PaymentResult {
+                success: false,
+                preimage: None,
+                error: Some(format!(
+                    "Invoice amount {} msats doesn't match expected {} msats",
+                    amount_msats, expected_amount_msats
+                )),
+                fee_msats: None,
+            });
+        }
+
+        let payout = self.settle(self.claim(invoice).await?).await?;
+        Ok(match payout.state {
+            PayoutState::Completed => PaymentResult {
+                success: true,
+                preimage: payout.preimage(),
+                error: None,
+                // BTCPay doesn't report the routing fee of payouts
+                fee_msats: None,
+            },
+            _ => PaymentResult {
+                success: false,
+                preimage: None,
+                error: Some(format!("BTCPay payout {} was cancelled", payout.id)),
+                fee_msats: None,
+            },
+        })
+    }
+
+    /// Claim an on-chain payout and approve it
+    ///
+    /// On-chain payouts are paid in batches, so this doesn't wait for the
+    /// transaction: the payout counts as sent once it's approved.
+    async fn send_onchain(&self, address: &str, amount_sats: u64) -> Result<OnchainPayment> {
+        let pull_payment: PullPayment = self.send(self.http.get(self.pull_payment_endpoint(&[]))).await?;
+        let amount = from_sats(amount_sats, &pull_payment.currency)
+            .ok_or_else(|| anyhow!("Pull payment is in {}, on-chain payouts need BTC or SATS", pull_payment.currency))?;
+
+        let body = serde_json::json!({
+            "destination": address,
+            "amount": amount,
+            "payoutMethodId": "BTC-CHAIN",
+            // Name of the field before BTCPay 2.0
+            "paymentMethod": "BTC",
+        });
+        let payout: Payout = self.send(self.http.post(self.pull_payment_endpoint(&["payouts"])).json(&body)).await?;
+        let payout = self.approve(payout).await?;
+
+        Ok(match payout.state {
+            PayoutState::AwaitingPayment | PayoutState::InProgress | PayoutState::Completed => OnchainPayment {
+                success: true,
+                txid: payout.payment_proof.and_then(|proof| proof.transaction_id),
+                error: None,
+            },
+            state => OnchainPayment {
+                success: false,
+                txid: None,
+                error: Some(format!("BTCPay payout {} is {:?}", payout.id, state)),
+            },
+        })
+    }
+
+    /// The pull payment's name and what's left of it
+    async fn get_info(&self) -> Result<NodeInfo> {
+        let pull_payment: PullPayment = self.send(self.http.get(self.pull_payment_endpoint(&[]))).await?;
+        let currency = &pull_payment.currency;
+        let limit = to_msats(&pull_payment.amount, currency)
+            .ok_or_else(|| anyhow!("Pull payment amount {} {} can't be counted in msats", pull_payment.amount, currency))?;
+
+        let mut claimed = 0u64;
+        for payout in self.payouts().await? {
+            if payout.state == PayoutState::Cancelled {
+                continue;
+            }
+            let amount = payout.amount.as_deref().unwrap_or("0");
+            claimed += to_msats(amount, currency)
+                .ok_or_else(|| anyhow!("Payout {} has an invalid amount {:?}", payout.id, amount))?;
+        }
+
+        Ok(NodeInfo {
+            alias: format!(
+                "BTCPay pull payment {}",
+                pull_payment.name.as_deref().unwrap_or(&self.pull_payment_id)
+            ),
+            balance_msats: limit.saturating_sub(claimed),
+        })
+    }
+
+    async fn create_invoice(&self, _amount_msats: u64, _description: &str) -> Result<Invoice> {
+        bail!("BTCPay pull payments can't receive top-ups, fund the pull payment in BTCPay instead")
+    }
+
+    async fn is_invoice_paid(&self, _payment_hash: &str) -> Result<bool> {
+        bail!("BTCPay pull payments can't receive top-ups")
+    }
+
+    async fn outgoing_payment(&self, payment_hash: &str) -> Result<OutgoingPayment> {
+        Ok(match self.find_payout(payment_hash).await? {
+            Some(payout) => match payout.state {
+                PayoutState::Completed => OutgoingPayment::Succeeded { preimage: payout.preimage() },
+                PayoutState::Cancelled => OutgoingPayment::Failed,
+                _ => OutgoingPayment::Pending,
+            },
+            None => OutgoingPayment::Unknown,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_url() {
+        let http = reqwest::Client::new();
+        let backend = BtcPayPullPayment::from_url(
+            http.clone(),
+            "https://pay.example.com/btcpay?pull_payment=pp1&store=st1&api_key=key",
+        )
+        .unwrap();
+        assert_eq!(backend.store, Some(("st1".to_string(), "key".to_string())));
+        assert_eq!(
+            backend.pull_payment_endpoint(&["payouts"]).as_str(),
+            "https://pay.example.com/btcpay/api/v1/pull-payments/pp1/payouts"
+        );
+
+        let backend = BtcPayPullPayment::from_url(http.clone(), "https://pay.example.com/?pull_payment=pp1").unwrap();
+        assert!(backend.store.is_none());
+        assert_eq!(
+            backend.endpoint(&["stores", "st1", "payouts", "po1"]).as_str(),
+            "https://pay.example.com/api/v1/stores/st1/payouts/po1"
+        );
+
+        assert!(BtcPayPullPayment::from_url(http.clone(), "https://pay.example.com").is_err());
+        assert!(BtcPayPullPayment::from_url(http.clone(), "https://pay.example.com?pull_payment=pp1&store=st1").is_err());
+        assert!(BtcPayPullPayment::from_url(http, "ftp://pay.example.com?pull_payment=pp1").is_err());
+    }
+
+    #[test]
+    fn test_from_sats() {
+        assert_eq!(from_sats(150_000, "BTC").as_deref(), Some("0.00150000"));
+        assert_eq!(from_sats(210_000_000, "btc").as_deref(), Some("2.10000000"));
+        assert_eq!(from_sats(150_000, "SATS").as_deref(), Some("150000"));
+        assert_eq!(from_sats(150_000, "EUR"), None);
+    }
+
+    #[test]
+    fn test_to_msats() {
+        assert_eq!(to_msats("0.001", "BTC"), Some(100_000_000));
+        assert_eq!(to_msats("1", "BTC"), Some(100_000_000_000));
+        assert_eq!(to_msats("0.00000000001", "BTC"), Some(1));
+        assert_eq!(to_msats("2100", "SATS"), Some(2_100_000));
+        assert_eq!(to_msats("1.5000", "sats"), Some(1_500));
+        assert_eq!(to_msats("10.00", "USD"), None);
+        assert_eq!(to_msats("0.000000000001", "BTC"), None);
+        assert_eq!(to_msats("-1", "BTC"), None);
+    }
+}
@@ -0,0 +1,403 @@
+//! Encrypted database snapshots
+//!
+//! A backup file is `MAGIC | salt | nonce | ciphertext`. The key is derived
+//! from a passphrase with Argon2id and the payload is encrypted and
+//! authenticated with AES-256-GCM, the header as associated data. The
+//! plaintext is a length-prefixed JSON [`Manifest`] followed by the SQLite
+//! database file. Backups of earlier versions, encrypted with AES-256-CTR and
+//! authenticated with HMAC-SHA256, can still be opened.
+//!
+//! Backups can also go to an S3-compatible bucket, see [`s3`], as objects
+//! named after the time they were taken so the newest sorts last.
+
+pub mod s3;
+
+use aes::Aes256;
+use aes_gcm::{Aes256Gcm, Nonce, aead::{Aead, KeyInit, Payload}};
+use anyhow::{Result, anyhow, bail};
+use argon2::Argon2;
+use cipher::{KeyIvInit, StreamCipher};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Sqlite};
+use std::{io::Write, path::Path};
+
+use crate::{
+    config::S3Config,
+    db,
+    random::Random,
+};
+use s3::Bucket;
+
+const MAGIC: &[u8; 8] = b"LNURLWB2";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Backups encrypted with AES-256-CTR and authenticated with HMAC-SHA256
+const LEGACY_MAGIC: &[u8; 8] = b"LNURLWB1";
+const LEGACY_NONCE_LEN: usize = 16;
+const LEGACY_TAG_LEN: usize = 32;
+
+/// Describes what a backup contains, checked before restoring it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub created_at: String,
+    pub server_version: String,
+    /// Highest migration applied to the snapshot
+    pub schema_version: i64,
+    pub config_fingerprint: String,
+}
+
+/// Identifies the configuration a database belongs to
+///
+/// Cards are programmed with URLs on the configured domain, so a database
+/// restored under a different domain would leave every card unusable.
+pub fn config_fingerprint(domain: &str) -> String {
+    hex::encode(Sha256::digest(format!("domain={}", domain)))[..16].to_string()
+}
+
+/// Take a consistent copy of the database and encrypt it with `passphrase`
+///
+/// The unencrypted copy is only readable by the owner and lives in `snapshot_dir`
+/// until it's read back, however taking the backup ends.
+pub async fn create(pool: &Pool<Sqlite>, random: &Random, domain: &str, passphrase: &str, snapshot_dir: &Path) -> Result<Vec<u8>> {
+    // VACUUM INTO writes a transactionally consistent copy even while the server
+    // is running, into a file that is empty or doesn't exist
+    let snapshot = tempfile::Builder::new()
+        .prefix(".lnurlw-backup-")
+        .suffix(".db")
+        .tempfile_in(snapshot_dir)?;
+    sqlx::query("VACUUM INTO ?")
+        .bind(snapshot.path().to_string_lossy().as_ref())
+        .execute(pool)
+        .await?;
+    let database = std::fs::read(snapshot.path())?;
+    snapshot.close()?;
+
+    let schema_version: i64 =
+        sqlx::query_scalar("SELECT COALESCE(MAX(version), 0) FROM _sqlx_migrations WHERE success = 1")
+            .fetch_one(pool)
+            .await?;
+
+    let manifest = Manifest {
+        created_at: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        schema_version,
+        config_fingerprint: config_fingerprint(domain),
+    };
+
+    seal(random, passphrase, &manifest, &database)
+}
+
+/// Decrypt a backup and write its database to `path`, which must not exist yet
+///
+/// Refuses snapshots with a newer schema than this server knows and, unless
+/// `allow_config_change` is set, snapshots taken under a different configuration.
+pub fn restore(
+    backup: &[u8],
+    passphrase: &str,
+    domain: &str,
+    path: &Path,
+    allow_config_change: bool,
+) -> Result<Manifest> {
+    if path.exists() {
+        bail!("{} already exists, restore only into a fresh instance", path.display());
+    }
+
+    let (manifest, database) = open(passphrase, backup)?;
+
+    if manifest.schema_version > db::latest_schema_version() {
+        bail!(
+            "Backup has schema version {}, this server only knows up to {}",
+            manifest.schema_version,
+            db::latest_schema_version()
+        );
+    }
+
+    if !allow_config_change && manifest.config_fingerprint != config_fingerprint(domain) {
+        bail!("Backup was taken with a different domain, pass --allow-config-change to restore anyway");
+    }
+
+    // Written next to the target and renamed, so a failed restore leaves nothing behind
+    write_private(path, &database, false)?;
+
+    Ok(manifest)
+}
+
+/// Write `data` to a file at `path` only its owner can read
+///
+/// The data goes to a temporary file in the same directory first, which is
+/// renamed to `path` once complete and removed otherwise. An existing file at
+/// `path` is only replaced with `overwrite`.
+pub fn write_private(path: &Path, data: &[u8], overwrite: bool) -> Result<()> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut file = tempfile::Builder::new().prefix(".lnurlw-").tempfile_in(dir)?;
+    file.write_all(data)?;
+    file.as_file().sync_all()?;
+    if overwrite {
+        file.persist(path)?;
+    } else {
+        file.persist_noclobber(path)?;
+    }
+
+    Ok(())
+}
+
+/// Object name of a backup taken at `time`
+pub fn object_name(prefix: &str, time: chrono::DateTime<chrono::Utc>) -> String {
+    format!("{}lnurlw-{}.bin", prefix, time.format("%Y%m%dT%H%M%SZ"))
+}
+
+/// Backups among `keys`, oldest first; other objects under the prefix are left alone
+pub fn backups(keys: &[String], prefix: &str) -> Vec<String> {
+    let mut backups: Vec<String> = keys
+        .iter()
+        .filter(|key| {
+            key.strip_prefix(prefix)
+                .and_then(|name| name.strip_prefix("lnurlw-"))
+                .is_some_and(|name| name.ends_with(".bin") && !name.contains('/'))
+        })
+        .cloned()
+        .collect();
+    backups.sort();
+    backups
+}
+
+/// Backups among `keys` beyond the newest `keep`
+pub fn expired(keys: &[String], prefix: &str, keep: usize) -> Vec<String> {
+    let mut backups = backups(keys, prefix);
+    backups.truncate(backups.len().saturating_sub(keep));
+    backups
+}
+
+/// Upload a backup and delete the ones beyond `--s3-keep`, returning the new object's name and the deleted ones
+pub async fn upload(bucket: &Bucket, config: &S3Config, backup: Vec<u8>) -> Result<(String, Vec<String>)> {
+    let key = object_name(&config.s3_prefix, chrono::Utc::now());
+    bucket.put(&key, backup).await?;
+
+    let mut deleted = Vec::new();
+    if let Some(keep) = config.s3_keep {
+        let keys = bucket.list(&config.s3_prefix).await?;
+        for old in expired(&keys, &config.s3_prefix, keep as usize) {
+            bucket.delete(&old).await?;
+            deleted.push(old);
+        }
+    }
+
+    Ok((key, deleted))
+}
+
+/// Fetch a backup by its object name, or the newest one for `latest`
+pub async fn download(bucket: &Bucket, config: &S3Config, key: &str) -> Result<(String, Vec<u8>)> {
+    let key = if key == "latest" {
+        let keys = bucket.list(&config.s3_prefix).await?;
+        backups(&keys, &config.s3_prefix)
+            .pop()
+            .ok_or_else(|| anyhow!("No backups under {:?} in the bucket", config.s3_prefix))?
+    } else {
+        key.to_string()
+    };
+
+    let backup = bucket.get(&key).await?;
+    Ok((key, backup))
+}
+
+/// Encrypt and authenticate a manifest and database
+pub fn seal(random: &Random, passphrase: &str, manifest: &Manifest, database: &[u8]) -> Result<Vec<u8>> {
+    let manifest_json = serde_json::to_vec(manifest)?;
+    let mut plaintext = Vec::with_capacity(4 + manifest_json.len() + database.len());
+    plaintext.extend_from_slice(&(manifest_json.len() as u32).to_be_bytes());
+    plaintext.extend_from_slice(&manifest_json);
+    plaintext.extend_from_slice(database);
+
+    let salt: [u8; SALT_LEN] = random.bytes()?;
+    let nonce: [u8; NONCE_LEN] = random.bytes()?;
+    let key: [u8; 32] = derive_key(passphrase, &salt)?;
+
+    let mut out = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + plaintext.len() + 16);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&nonce);
+    let ciphertext = Aes256Gcm::new(&key.into())
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: &plaintext, aad: &out })
+        .map_err(|_| anyhow!("Encryption failed"))?;
+    out.extend_from_slice(&ciphertext);
+
+    Ok(out)
+}
+
+/// Verify and decrypt a backup, returning its manifest and database
+pub fn open(passphrase: &str, backup: &[u8]) -> Result<(Manifest, Vec<u8>)> {
+    let mut plaintext = match backup.get(..MAGIC.len()) {
+        Some(magic) if magic == MAGIC => decrypt(passphrase, backup)?,
+        Some(magic) if magic == LEGACY_MAGIC => decrypt_legacy(passphrase, backup)?,
+        _ => bail!("Not a backup file"),
+    };
+
+    if plaintext.len() < 4 {
+        bail!("Backup has no manifest");
+    }
+    let manifest_len = u32::from_be_bytes(plaintext[..4].try_into()?) as usize;
+    if plaintext.len() < 4 + manifest_len {
+        bail!("Backup has no manifest");
+    }
+    let manifest = serde_json::from_slice(&plaintext[4..4 + manifest_len])?;
+
+    Ok((manifest, plaintext.split_off(4 + manifest_len)))
+}
+
+fn decrypt(passphrase: &str, backup: &[u8]) -> Result<Vec<u8>> {
+    let header_len = MAGIC.len() + SALT_LEN + NONCE_LEN;
+    if backup.len() < header_len {
+        bail!("Not a backup file");
+    }
+    let (header, ciphertext) = backup.split_at(header_len);
+    let salt = &header[MAGIC.len()..MAGIC.len() + SALT_LEN];
+    let nonce = &header[MAGIC.len() + SALT_LEN..];
+    let key: [u8; 32] = derive_key(passphrase, salt)?;
+
+    Aes256Gcm::new(&key.into())
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+        .map_err(|_| anyhow!("Wrong passphrase or corrupted backup"))
+}
+
+/// Decrypt a backup written before AES-GCM, `LEGACY_MAGIC | salt | nonce | ciphertext | tag`
+fn decrypt_legacy(passphrase: &str, backup: &[u8]) -> Result<Vec<u8>> {
+    let header_len = LEGACY_MAGIC.len() + SALT_LEN + LEGACY_NONCE_LEN;
+    if backup.len() < header_len + LEGACY_TAG_LEN {
+        bail!("Not a backup file");
+    }
+
+    let salt = &backup[LEGACY_MAGIC.len()..LEGACY_MAGIC.len() + SALT_LEN];
+    let nonce = &backup[LEGACY_MAGIC.len() + SALT_LEN..header_len];
+    let (body, tag) = backup.split_at(backup.len() - LEGACY_TAG_LEN);
+    let keys: [u8; 64] = derive_key(passphrase, salt)?;
+    let (enc_key, mac_key) = keys.split_at(32);
+
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(mac_key).map_err(|e| anyhow!("Invalid key length: {:?}", e))?;
+    mac.update(body);
+    mac.verify_slice(tag)
+        .map_err(|_| anyhow!("Wrong passphrase or corrupted backup"))?;
+
+    let mut plaintext = body[header_len..].to_vec();
+    ctr::Ctr128BE::<Aes256>::new_from_slices(enc_key, nonce)
+        .map_err(|e| anyhow!("Invalid key length: {:?}", e))?
+        .apply_keystream(&mut plaintext);
+
+    Ok(plaintext)
+}
+
+fn derive_key<const N: usize>(passphrase: &str, salt: &[u8]) -> Result<[u8; N]> {
+    let mut key = [0u8; N];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open_roundtrip() {
+        let manifest = Manifest {
+            created_at: "2024-01-01 00:00:00".to_string(),
+            server_version: "0.1.0".to_string(),
+            schema_version: 9,
+            config_fingerprint: "abcd".to_string(),
+        };
+        let database = b"SQLite format 3\0 and some pages".repeat(10);
+
+        let backup = seal(&Random::seeded(1), "correct horse", &manifest, &database).unwrap();
+        assert!(!backup.windows(15).any(|w| w == b"SQLite format 3"));
+
+        let (opened, restored) = open("correct horse", &backup).unwrap();
+        assert_eq!(opened, manifest);
+        assert_eq!(restored, database);
+
+        assert!(open("wrong horse", &backup).is_err());
+
+        let mut tampered = backup.clone();
+        tampered[MAGIC.len() + SALT_LEN + NONCE_LEN] ^= 1;
+        assert!(open("correct horse", &tampered).is_err());
+        let mut tampered = backup.clone();
+        tampered[MAGIC.len()] ^= 1;
+        assert!(open("correct horse", &tampered).is_err());
+    }
+
+    #[test]
+    fn test_open_legacy() {
+        let manifest = serde_json::to_vec(&serde_json::json!({
+            "created_at": "2024-01-01 00:00:00",
+            "server_version": "0.1.0",
+            "schema_version": 9,
+            "config_fingerprint": "abcd",
+        }))
+        .unwrap();
+        let mut plaintext = [&(manifest.len() as u32).to_be_bytes()[..], &manifest, b"SQLite format 3\0"].concat();
+
+        // Encrypted the way backups were before AES-GCM
+        let salt = [1u8; SALT_LEN];
+        let nonce = [2u8; LEGACY_NONCE_LEN];
+        let keys: [u8; 64] = derive_key("correct horse", &salt).unwrap();
+        ctr::Ctr128BE::<Aes256>::new_from_slices(&keys[..32], &nonce).unwrap().apply_keystream(&mut plaintext);
+        let mut backup = [LEGACY_MAGIC.as_slice(), &salt, &nonce, &plaintext].concat();
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&keys[32..]).unwrap();
+        mac.update(&backup);
+        backup.extend_from_slice(&mac.finalize().into_bytes());
+
+        let (manifest, database) = open("correct horse", &backup).unwrap();
+        assert_eq!(manifest.schema_version, 9);
+        assert_eq!(database, b"SQLite format 3\0");
+        assert!(open("wrong horse", &backup).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_create_leaves_no_snapshot() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (state, _) = crate::app_state::test_state(&[]).await;
+        // An in-memory database would be copied into memory as well
+        let data = tempfile::tempdir().unwrap();
+        let pool = sqlx::SqlitePool::connect(&format!("sqlite://{}?mode=rwc", data.path().join("lnurlw.db").display()))
+            .await
+            .unwrap();
+        db::run_migrations(&pool).await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+
+        let backup = create(&pool, &state.random, &state.config.domain, "correct horse", dir.path()).await.unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        let path = dir.path().join("lnurlw-backup.bin");
+        write_private(&path, &backup, false).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert!(write_private(&path, b"other", false).is_err());
+        write_private(&path, &backup, true).unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let restored = dir.path().join("restored.db");
+        restore(&std::fs::read(&path).unwrap(), "correct horse", &state.config.domain, &restored, false).unwrap();
+        assert!(std::fs::read(&restored).unwrap().starts_with(b"SQLite format 3\0"));
+        assert_eq!(std::fs::metadata(&restored).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_expired() {
+        let time = |day| chrono::NaiveDate::from_ymd_opt(2026, 3, day).unwrap().and_hms_opt(4, 0, 0).unwrap().and_utc();
+        assert_eq!(object_name("b/", time(1)), "b/lnurlw-20260301T040000Z.bin");
+
+        let keys: Vec<String> = [
+            object_name("b/", time(3)),
+            object_name("b/", time(1)),
+            object_name("b/", time(2)),
+            "b/notes.txt".to_string(),
+            "b/old/lnurlw-20250101T000000Z.bin".to_string(),
+        ]
+        .into();
+        assert_eq!(expired(&keys, "b/", 2), [object_name("b/", time(1))]);
+        assert!(expired(&keys, "b/", 3).is_empty());
+        assert_eq!(backups(&keys, "b/").last(), Some(&object_name("b/", time(3))));
+    }
+}
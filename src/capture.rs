@@ -0,0 +1,227 @@
+//! Capture and replay of LNURLw requests, for reproducing failures
+//!
+//! With `--record-requests` every `/ln` and `/ln/callback` request is appended
+//! to a JSON lines file together with the answer it got. `replay` sends them
+//! again to a test instance, e.g. one restored from a backup taken before the
+//! failure, and reports where the answers differ.
+//!
+//! The log holds the `p` and `c` of taps and the invoices of wallets, it's
+//! meant for debugging and shouldn't be left on in production.
+
+use anyhow::{Context as _, Result, anyhow};
+use axum::{
+    body::{Body, to_bytes},
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use reqwest::Url;
+use std::{collections::HashMap, path::Path, sync::Arc};
+use tokio::{fs::File, io::AsyncWriteExt, sync::Mutex};
+
+/// LNURL answers are small, anything bigger isn't recorded
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// A request and the answer it got
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub at: DateTime<Utc>,
+    pub path: String,
+    pub query: String,
+    pub status: u16,
+    /// The answer's JSON, or its text if it isn't JSON
+    pub body: Value,
+}
+
+impl Record {
+    /// Short summary of the answer: the LNURL tag, `OK`, or the error's code
+    pub fn outcome(&self) -> String {
+        outcome(self.status, &self.body)
+    }
+}
+
+/// Short summary of an LNURL answer with `status` and `body`
+pub fn outcome(status: u16, body: &Value) -> String {
+    if body["status"] == "ERROR" {
+        let code = body["code"].as_str().or(body["reason"].as_str()).unwrap_or("?");
+        return format!("ERROR {}", code);
+    }
+    match (body["tag"].as_str(), body["status"].as_str()) {
+        (Some(tag), _) => tag.to_string(),
+        (None, Some(status)) => status.to_string(),
+        (None, None) => format!("HTTP {}", status),
+    }
+}
+
+/// Appends records to the log file
+pub struct Recorder {
+    file: Mutex<File>,
+}
+
+impl Recorder {
+    pub async fn open(path: &Path) -> Result<Self> {
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .with_context(|| format!("Can't open {} to record requests", path.display()))?;
+        tracing::warn!("Recording LNURLw requests to {}, including card taps and invoices", path.display());
+        Ok(Self { file: Mutex::new(file) })
+    }
+
+    async fn append(&self, record: &Record) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        self.file.lock().await.write_all(&line).await?;
+        Ok(())
+    }
+}
+
+/// Record the request and the answer the handler gave
+pub async fn record(State(recorder): State<Arc<Recorder>>, req: Request, next: Next) -> Response {
+    let at = Utc::now();
+    let path = req.uri().path().to_string();
+    let query = req.uri().query().unwrap_or_default().to_string();
+
+    let (parts, body) = next.run(req).await.into_parts();
+    let Ok(bytes) = to_bytes(body, MAX_BODY_BYTES).await else {
+        tracing::error!("Answer to {} too large to record", path);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    let record = Record { at, path, query, status: parts.status.as_u16(), body: parse_body(&bytes) };
+    if let Err(e) = recorder.append(&record).await {
+        tracing::error!("Failed to record request to {}: {}", record.path, e);
+    }
+
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+/// The answer's JSON, or its text if it isn't JSON
+pub fn parse_body(bytes: &[u8]) -> Value {
+    serde_json::from_slice(bytes).unwrap_or_else(|_| Value::String(String::from_utf8_lossy(bytes).into_owned()))
+}
+
+/// Read a log written with `--record-requests`
+pub fn read_log(contents: &str) -> Result<Vec<Record>> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| serde_json::from_str(line).map_err(|e| anyhow!("Line {}: {}", i + 1, e)))
+        .collect()
+}
+
+/// A recorded request and the answer it gets now
+#[derive(Debug, Clone)]
+pub struct Replayed {
+    pub recorded: Record,
+    pub replayed: Record,
+}
+
+impl Replayed {
+    pub fn differs(&self) -> bool {
+        self.recorded.outcome() != self.replayed.outcome()
+    }
+}
+
+/// Send the recorded requests to `server` in order
+///
+/// Withdrawal sessions get new k1s on the test instance, so the k1 of each
+/// callback is replaced by the one the replayed tap got. With `realtime` the
+/// recorded pauses between requests are kept, for failures that depend on
+/// timing like expired sessions.
+pub async fn replay(http: &reqwest::Client, server: &str, records: &[Record], realtime: bool) -> Result<Vec<Replayed>> {
+    let mut k1s = HashMap::new();
+    let mut replayed = Vec::with_capacity(records.len());
+
+    for (i, recorded) in records.iter().enumerate() {
+        if realtime && let Some(previous) = i.checked_sub(1).map(|i| &records[i]) {
+            let pause = (recorded.at - previous.at).to_std().unwrap_or_default();
+            tokio::time::sleep(pause).await;
+        }
+
+        let query = rewrite_k1(&recorded.query, &k1s);
+        let url = format!("{}{}?{}", server.trim_end_matches('/'), recorded.path, query);
+        let at = Utc::now();
+        let response = http.get(&url).send().await.with_context(|| format!("Request to {} failed", url))?;
+        let status = response.status().as_u16();
+        let body = parse_body(&response.bytes().await?);
+
+        if let (Some(old), Some(new)) = (recorded.body["k1"].as_str(), body["k1"].as_str()) {
+            k1s.insert(old.to_string(), new.to_string());
+        }
+        replayed.push(Replayed {
+            recorded: recorded.clone(),
+            replayed: Record { at, path: recorded.path.clone(), query, status, body },
+        });
+    }
+
+    Ok(replayed)
+}
+
+/// Replace the query's k1 by the one its session got on replay, if any
+fn rewrite_k1(query: &str, k1s: &HashMap<String, String>) -> String {
+    let Ok(mut url) = Url::parse(&format!("http://replay/?{}", query)) else {
+        return query.to_string();
+    };
+    let mut rewritten = false;
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(name, value)| match k1s.get(value.as_ref()) {
+            Some(new) if name == "k1" => {
+                rewritten = true;
+                (name.into_owned(), new.clone())
+            }
+            _ => (name.into_owned(), value.into_owned()),
+        })
+        .collect();
+    if !rewritten {
+        return query.to_string();
+    }
+    url.query_pairs_mut().clear().extend_pairs(pairs);
+    url.query().unwrap_or_default().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn record(status: u16, body: Value) -> Record {
+        Record { at: Utc::now(), path: "/ln".to_string(), query: String::new(), status, body }
+    }
+
+    #[test]
+    fn test_outcome() {
+        assert_eq!(record(200, json!({"tag": "withdrawRequest", "k1": "ab"})).outcome(), "withdrawRequest");
+        assert_eq!(record(200, json!({"status": "OK"})).outcome(), "OK");
+        assert_eq!(
+            record(400, json!({"status": "ERROR", "code": "COUNTER_REUSED", "reason": "Replay"})).outcome(),
+            "ERROR COUNTER_REUSED"
+        );
+        assert_eq!(record(404, json!("Not Found")).outcome(), "HTTP 404");
+    }
+
+    #[test]
+    fn test_rewrite_k1() {
+        let k1s = HashMap::from([("old".to_string(), "new".to_string())]);
+        assert_eq!(rewrite_k1("k1=old&pr=lnbc1", &k1s), "k1=new&pr=lnbc1");
+        // Queries without a replayed session are sent as recorded
+        assert_eq!(rewrite_k1("k1=other&pr=lnbc%31", &k1s), "k1=other&pr=lnbc%31");
+        assert_eq!(rewrite_k1("card_id=1&p=00&c=00", &k1s), "card_id=1&p=00&c=00");
+    }
+
+    #[test]
+    fn test_read_log() {
+        let line = serde_json::to_string(&record(200, json!({"status": "OK"}))).unwrap();
+        let records = read_log(&format!("{}\n\n{}\n", line, line)).unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(read_log("{}").unwrap_err().to_string().starts_with("Line 1"));
+    }
+}
@@ -0,0 +1,206 @@
+//! Fault injection, for trying out retries, the payment state machine and
+//! reconciliation by hand
+//!
+//! With `--chaos` the operator can set faults per route at `/v1/chaos`:
+//! random extra latency, a locked database so the request's writes time out,
+//! and a Lightning node that is down for the calls the request makes. Work
+//! the request leaves to background jobs isn't affected. Never meant for
+//! production.
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite, pool::PoolConnection};
+use std::{collections::BTreeMap, sync::{Arc, RwLock}, time::Duration};
+
+use crate::lightning::{Invoice, LightningBackend, NodeInfo, OnchainPayment, OutgoingPayment, PaymentResult};
+
+/// Key of the faults for the routes without their own
+pub const ANY_ROUTE: &str = "*";
+
+tokio::task_local! {
+    /// Set while a request with a failing node is handled
+    static BACKEND_DOWN: bool;
+}
+
+/// Faults injected into the requests of a route
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Faults {
+    /// Delay each request by a random time up to this
+    pub latency_ms: u64,
+    /// Share of requests, from 0 to 1, during which the database stays locked
+    pub db_timeout: f64,
+    /// Share of requests, from 0 to 1, for which the Lightning node is down
+    pub backend_failure: f64,
+}
+
+impl Faults {
+    pub fn is_valid(&self) -> bool {
+        [self.db_timeout, self.backend_failure].iter().all(|share| (0.0..=1.0).contains(share))
+    }
+}
+
+/// Faults by route, as in the router, e.g. `/ln/callback` or `/v1/cards/{card_id}/tags`
+pub type Routes = BTreeMap<String, Faults>;
+
+pub struct Chaos {
+    pool: Pool<Sqlite>,
+    routes: RwLock<Routes>,
+}
+
+impl Chaos {
+    pub fn new(pool: Pool<Sqlite>) -> Self {
+        tracing::warn!("Fault injection is enabled, configure it at /v1/chaos");
+        Self { pool, routes: RwLock::default() }
+    }
+
+    pub fn routes(&self) -> Routes {
+        self.routes.read().unwrap().clone()
+    }
+
+    /// Replace all faults, `Err` with the route whose faults are invalid
+    pub fn set_routes(&self, routes: Routes) -> Result<(), String> {
+        if let Some((route, _)) = routes.iter().find(|(route, faults)| {
+            !faults.is_valid() || !(route.starts_with('/') || route.as_str() == ANY_ROUTE)
+        }) {
+            return Err(route.clone());
+        }
+        tracing::warn!("Injecting faults into {} route(s)", routes.len());
+        *self.routes.write().unwrap() = routes;
+        Ok(())
+    }
+
+    fn faults(&self, route: &str) -> Option<Faults> {
+        let routes = self.routes.read().unwrap();
+        routes.get(route).or_else(|| routes.get(ANY_ROUTE)).cloned()
+    }
+
+    /// Hold the database's write lock, so the request's writes wait out the busy timeout
+    async fn lock_database(&self) -> Result<PoolConnection<Sqlite>> {
+        let mut connection = self.pool.acquire().await?;
+        sqlx::query("BEGIN IMMEDIATE").execute(&mut *connection).await?;
+        Ok(connection)
+    }
+}
+
+/// Inject the faults configured for the request's route
+pub async fn inject(State(chaos): State<Arc<Chaos>>, req: Request, next: Next) -> Response {
+    let route = match req.extensions().get::<MatchedPath>() {
+        Some(path) => path.as_str().to_string(),
+        None => req.uri().path().to_string(),
+    };
+    let Some(faults) = chaos.faults(&route) else {
+        return next.run(req).await;
+    };
+
+    if faults.latency_ms > 0 {
+        tokio::time::sleep(Duration::from_millis(rand::random_range(0..=faults.latency_ms))).await;
+    }
+
+    let lock = if rand::random::<f64>() < faults.db_timeout {
+        chaos
+            .lock_database()
+            .await
+            .inspect_err(|e| tracing::warn!("Couldn't lock the database for {}: {}", route, e))
+            .ok()
+    } else {
+        None
+    };
+    let backend_down = rand::random::<f64>() < faults.backend_failure;
+    tracing::debug!("Chaos on {}: database locked {}, node down {}", route, lock.is_some(), backend_down);
+
+    let response = BACKEND_DOWN.scope(backend_down, next.run(req)).await;
+
+    if let Some(mut connection) = lock
+        && let Err(e) = sqlx::query("ROLLBACK").execute(&mut *connection).await
+    {
+        tracing::error!("Couldn't release the database lock for {}: {}", route, e);
+        connection.detach();
+    }
+    response
+}
+
+/// A backend that fails while a request with [`Faults::backend_failure`] is handled
+pub struct ChaosBackend(pub Arc<dyn LightningBackend>);
+
+impl ChaosBackend {
+    fn check(&self) -> Result<()> {
+        if BACKEND_DOWN.try_with(|down| *down).unwrap_or(false) {
+            return Err(anyhow!("Injected fault: Lightning node unreachable"));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LightningBackend for ChaosBackend {
+    async fn pay_invoice(&self, invoice: &Invoice, expected_amount_msats: u64) -> Result<PaymentResult> {
+        self.check()?;
+        self.0.pay_invoice(invoice, expected_amount_msats).await
+    }
+
+    async fn get_info(&self) -> Result<NodeInfo> {
+        self.check()?;
+        self.0.get_info().await
+    }
+
+    async fn create_invoice(&self, amount_msats: u64, description: &str) -> Result<Invoice> {
+        self.check()?;
+        self.0.create_invoice(amount_msats, description).await
+    }
+
+    async fn is_invoice_paid(&self, payment_hash: &str) -> Result<bool> {
+        self.check()?;
+        self.0.is_invoice_paid(payment_hash).await
+    }
+
+    async fn outgoing_payment(&self, payment_hash: &str) -> Result<OutgoingPayment> {
+        self.check()?;
+        self.0.outgoing_payment(payment_hash).await
+    }
+
+    async fn pay_offer(&self, offer: &str, amount_msats: u64) -> Result<PaymentResult> {
+        self.check()?;
+        self.0.pay_offer(offer, amount_msats).await
+    }
+
+    async fn send_onchain(&self, address: &str, amount_sats: u64) -> Result<OnchainPayment> {
+        self.check()?;
+        self.0.send_onchain(address, amount_sats).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lightning::MockLightning;
+
+    #[tokio::test]
+    async fn test_backend_down_only_within_request() {
+        let backend = ChaosBackend(Arc::new(MockLightning::default()));
+        assert!(backend.create_invoice(1000, "up").await.is_ok());
+        assert!(BACKEND_DOWN.scope(true, backend.create_invoice(1000, "down")).await.is_err());
+        assert!(BACKEND_DOWN.scope(false, backend.create_invoice(1000, "up")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_set_routes() {
+        let chaos = Chaos::new(sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap());
+        let faults = Faults { latency_ms: 100, ..Faults::default() };
+        let routes = Routes::from([("/ln".to_string(), faults.clone()), (ANY_ROUTE.to_string(), Faults::default())]);
+        chaos.set_routes(routes).unwrap();
+        assert_eq!(chaos.faults("/ln"), Some(faults));
+        assert_eq!(chaos.faults("/new"), Some(Faults::default()));
+
+        let invalid = Faults { backend_failure: 1.5, ..Faults::default() };
+        assert_eq!(chaos.set_routes(Routes::from([("/ln".to_string(), invalid)])), Err("/ln".to_string()));
+        assert_eq!(chaos.set_routes(Routes::from([("ln".to_string(), Faults::default())])), Err("ln".to_string()));
+        assert_eq!(chaos.routes().len(), 2);
+    }
+}
@@ -0,0 +1,2815 @@
+use anyhow::{Result, anyhow, bail};
+use chrono::NaiveDate;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use sqlx::{Pool, Sqlite, sqlite::SqliteConnectOptions};
+use std::{path::PathBuf, str::FromStr, sync::Arc};
+
+use crate::{
+    auth,
+    backends::{self, LightningBackends},
+    backup::{self, s3::Bucket},
+    capture,
+    import,
+    lightning::{MockLightning, Network},
+    loadtest::{self, LoadCard},
+    ledger::{self, LedgerAccounts, LedgerFormat},
+    config::{self, CardIssueConfig, Config, DatabaseConfig, RandomConfig, S3Config},
+    crypto::{AesKey, CardUid, Counter, aes_decrypt, decode_hex, generate_sun, parse_decrypted_data},
+    db::{self, accounts, bulk::CardSelector, deletion, fees, doctor::{self, Issue}, init_pool, invites, lost, operator_keys, models::{CardAccount, CreateCardRequest, Organization, PaymentStatus, UserPolicy}, organizations, privacy::{self, Erasure}, queries, rotation, seed, stats, users},
+    fees as service_fees,
+    handlers::{self, register::{create_card_record, replace_card_record}},
+    notifications::{self, Redelivery},
+    policy,
+    random::Random,
+    receipts::{self as signed_receipts, SignedReceipt},
+    reports::{self, FiatReportFormat, format_csv},
+    self_test,
+    validation::validate_card_pure,
+};
+
+#[derive(Parser, Debug, Clone)]
+#[command(name = "lnurlw-server")]
+#[command(about = "Bolt Card compatible LNURLw server")]
+#[command(version)]
+pub struct Cli {
+    #[command(flatten)]
+    pub database: DatabaseConfig,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Run the LNURLw server
+    Serve(Config),
+    /// Run the server for development: in-memory database, mock backend and a card ready to tap
+    Dev(DevArgs),
+    /// Check configuration, database, Lightning node and callback URL, then print a pass/fail report
+    CheckConfig(CheckConfigArgs),
+    /// Create a card and print its one-time registration URL
+    CreateCard(CreateCardArgs),
+    /// Export cards or payments as JSON or CSV
+    Export(ExportArgs),
+    /// List all cards with their limits and spend today (UTC)
+    ListCards(ListCardsArgs),
+    /// Decrypt and verify the p/c parameters of a tap with the card's keys
+    Decode(DecodeArgs),
+    /// Check a signed receipt's signature and that its preimage pays its payment hash
+    VerifyReceipt(VerifyReceiptArgs),
+    /// Apply pending database migrations
+    Migrate(MigrateArgs),
+    /// Database maintenance
+    #[command(subcommand)]
+    Db(DbCommand),
+    /// Generate key sets with matching p/c pairs as JSON test vectors
+    GenTestVectors(GenTestVectorsArgs),
+    /// Print an /ln URL for the card's next tap
+    SimulateTap(SimulateTapArgs),
+    /// Load sample cards, payments and a card with the Bolt Card test vectors' keys
+    Seed(SeedArgs),
+    /// Send requests recorded with --record-requests to a server again and compare the answers
+    Replay(ReplayArgs),
+    /// Tap and withdraw with many cards at once against a server on the mock backend and report latencies
+    LoadTest(LoadTestArgs),
+    /// Generate new keys for cards, accepting the old ones during a grace window
+    RotateKeys(RotateKeysArgs),
+    /// Create a new card with the settings and balance of one that has to be replaced
+    ReplaceCard(ReplaceCardArgs),
+    /// Write an encrypted snapshot of the database
+    Backup(BackupArgs),
+    /// Restore an encrypted snapshot into a fresh database
+    Restore(RestoreArgs),
+    /// Print payments, volume and failures per card for a date range
+    Stats(StatsArgs),
+    /// Print settled withdrawals as a beancount or ledger journal for a date range
+    Ledger(LedgerArgs),
+    /// Print monthly summaries of settled withdrawals valued in fiat, as CSV, PDF or JSON
+    FiatReport(FiatReportArgs),
+    /// Import cards from the LNbits Boltcards extension
+    ImportLnbits(ImportLnbitsArgs),
+    /// Inspect the background job queue
+    #[command(subcommand)]
+    Jobs(JobsCommand),
+    /// Inspect operator notifications and send them to the webhook again
+    #[command(subcommand)]
+    Webhooks(WebhooksCommand),
+    /// Review payments the Lightning node disagrees about
+    #[command(subcommand)]
+    Discrepancies(DiscrepanciesCommand),
+    /// Manage cardholder accounts
+    #[command(subcommand)]
+    Users(UsersCommand),
+    /// Manage invite codes for registering cardholder accounts
+    #[command(subcommand)]
+    Invites(InvitesCommand),
+    /// Manage API keys for the operator API
+    #[command(subcommand)]
+    OperatorKeys(OperatorKeysCommand),
+    /// Manage organizations and their Lightning backends
+    #[command(subcommand)]
+    Orgs(OrgsCommand),
+    /// Track the node capital funded to cards
+    #[command(subcommand)]
+    Capital(CapitalCommand),
+    /// Review accrued service fees and pay them out
+    #[command(subcommand)]
+    Fees(FeesCommand),
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Json,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CheckConfigArgs {
+    #[command(flatten)]
+    pub config: Config,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CreateCardArgs {
+    #[command(flatten)]
+    pub config: Config,
+
+    /// Card name
+    pub name: String,
+
+    /// Per-payment limit in sats (default: --default-tx-limit)
+    #[arg(long)]
+    pub tx_limit: Option<i64>,
+
+    /// Daily limit in sats (default: --default-day-limit)
+    #[arg(long)]
+    pub day_limit: Option<i64>,
+
+    /// Spend only from a prepaid balance
+    #[arg(long)]
+    pub balance_mode: bool,
+
+    /// Tag the card, can be repeated
+    #[arg(long = "tag")]
+    pub tags: Vec<String>,
+
+    /// Username of the cardholder account owning the card
+    #[arg(long)]
+    pub owner: Option<String>,
+
+    /// Slug of the organization the card belongs to
+    #[arg(long)]
+    pub org: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ExportKind {
+    Cards,
+    Payments,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ExportArgs {
+    /// What to export
+    #[arg(value_enum)]
+    pub kind: ExportKind,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "json")]
+    pub format: ExportFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ListCardsArgs {
+    /// Only list the cards of the organization with this slug
+    #[arg(long)]
+    pub org: Option<String>,
+
+    /// Also list deleted cards that weren't purged yet
+    #[arg(long)]
+    pub include_deleted: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DecodeArgs {
+    /// K1 decrypt key as hex
+    #[arg(long)]
+    pub k1: String,
+
+    /// K2 CMAC key as hex
+    #[arg(long)]
+    pub k2: String,
+
+    /// Encrypted UID and counter as hex
+    #[arg(long, required_unless_present = "url", conflicts_with = "url")]
+    pub p: Option<String>,
+
+    /// CMAC as hex
+    #[arg(long, required_unless_present = "url", conflicts_with = "url")]
+    pub c: Option<String>,
+
+    /// Full tap URL to take p and c from, e.g. "lnurlw://cards.example.com/ln?card_id=1&p=...&c=..."
+    #[arg(long)]
+    pub url: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct VerifyReceiptArgs {
+    /// Signed receipt as returned by /receipt/<k1>/signed, `-` for stdin
+    pub file: PathBuf,
+
+    /// Public key (hex) the receipt has to be signed with, otherwise any key in the database
+    #[arg(long)]
+    pub public_key: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct MigrateArgs {
+    /// Only list pending migrations
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum DbCommand {
+    /// Check the database for broken invariants and optionally repair them
+    Doctor(DoctorArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DoctorArgs {
+    /// Fix the issues found instead of only reporting them
+    #[arg(long)]
+    pub repair: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: OutputFormat,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum JobsCommand {
+    /// List queued, running and dead jobs
+    List(ListJobsArgs),
+    /// Queue a dead job again with a fresh set of attempts
+    Revive(ReviveJobArgs),
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Dead,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ListJobsArgs {
+    /// Only list jobs with this status
+    #[arg(long, value_enum)]
+    pub status: Option<JobStatus>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ReviveJobArgs {
+    /// ID of the dead job
+    pub job_id: i64,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum WebhooksCommand {
+    /// List operator notifications with the state of their delivery, newest first
+    List(ListWebhookEventsArgs),
+    /// Queue delivered or dead notifications for delivery again
+    Redeliver(RedeliverArgs),
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum WebhookEventStatus {
+    Pending,
+    Delivered,
+    Dead,
+}
+
+impl WebhookEventStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            WebhookEventStatus::Pending => "pending",
+            WebhookEventStatus::Delivered => "delivered",
+            WebhookEventStatus::Dead => "dead",
+        }
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ListWebhookEventsArgs {
+    /// Only list events with this status
+    #[arg(long, value_enum)]
+    pub status: Option<WebhookEventStatus>,
+
+    /// Number of events to list
+    #[arg(long, default_value = "100")]
+    pub limit: i64,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+#[command(group(clap::ArgGroup::new("events").required(true).args(["event_ids", "all_dead"])))]
+pub struct RedeliverArgs {
+    /// IDs of the events
+    pub event_ids: Vec<i64>,
+
+    /// Redeliver every dead event, e.g. once the receiver is back up
+    #[arg(long)]
+    pub all_dead: bool,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum DiscrepanciesCommand {
+    /// List unresolved discrepancies found by the reconciliation
+    List(ListDiscrepanciesArgs),
+    /// Mark a discrepancy as dealt with
+    Resolve(ResolveDiscrepancyArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ListDiscrepanciesArgs {
+    /// Include resolved discrepancies
+    #[arg(long)]
+    pub all: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ResolveDiscrepancyArgs {
+    /// ID of the discrepancy
+    pub discrepancy_id: i64,
+
+    /// Also decide its payment if it's still in flight, after checking the node by hand
+    #[arg(long, value_enum)]
+    pub payment: Option<PaymentOutcome>,
+}
+
+/// How a payment whose outcome the node couldn't tell ended
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum PaymentOutcome {
+    /// It went out, the card is charged
+    Settled,
+    /// It didn't, the reservation is released and a prepaid card refunded
+    Failed,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum UsersCommand {
+    /// Create a user and print a generated password
+    Add(AddUserArgs),
+    /// List users with the number of cards they own
+    List(ListUsersArgs),
+    /// Block a user from logging in and end their sessions
+    Disable(UserArgs),
+    /// Let a disabled user log in again
+    Enable(UserArgs),
+    /// Set a user's quotas and limits, replacing the previous ones
+    Policy(UserPolicyArgs),
+    /// Print everything recorded about a user and their cards as JSON, without card keys
+    Export(UserArgs),
+    /// Erase a user's personal data and that of their cards, keeping amounts for accounting
+    Erase(UserArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct UserArgs {
+    pub username: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct AddUserArgs {
+    pub username: String,
+
+    #[command(flatten)]
+    pub random: RandomConfig,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct UserPolicyArgs {
+    pub username: String,
+
+    /// Most cards the user may own (default: no cap)
+    #[arg(long)]
+    pub max_cards: Option<i64>,
+
+    /// Per-payment limit of new cards that don't set one (default: --default-tx-limit)
+    #[arg(long)]
+    pub default_tx_limit: Option<i64>,
+
+    /// Daily limit of new cards that don't set one (default: --default-day-limit)
+    #[arg(long)]
+    pub default_day_limit: Option<i64>,
+
+    /// Highest per-payment limit the user's cards may have (default: no cap)
+    #[arg(long)]
+    pub max_tx_limit: Option<i64>,
+
+    /// Highest daily limit the user's cards may have (default: no cap)
+    #[arg(long)]
+    pub max_day_limit: Option<i64>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ListUsersArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: OutputFormat,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum OperatorKeysCommand {
+    /// Create a key and print it, it isn't shown again
+    Create(CreateOperatorKeyArgs),
+    /// List keys and when they were last used
+    List(ListOperatorKeysArgs),
+    /// Stop a key from working
+    Revoke(RevokeOperatorKeyArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CreateOperatorKeyArgs {
+    /// What the key is used for, shown by `operator-keys list`
+    pub name: String,
+
+    /// Viewers may only read, admins may also change cards
+    #[arg(long, value_enum, default_value = "admin")]
+    pub role: auth::Role,
+
+    #[command(flatten)]
+    pub random: RandomConfig,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ListOperatorKeysArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RevokeOperatorKeyArgs {
+    /// ID of the key
+    pub key_id: i64,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum InvitesCommand {
+    /// Create an invite and print its code
+    Create(CreateInviteArgs),
+    /// List invites and how often they were used
+    List(ListInvitesArgs),
+    /// Stop an invite from admitting anyone else
+    Revoke(RevokeInviteArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CreateInviteArgs {
+    /// Accounts that may register with the invite
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(i64).range(1..))]
+    pub uses: i64,
+
+    /// Hours the invite stays valid (default: no expiry)
+    #[arg(long, value_parser = clap::value_parser!(i64).range(1..))]
+    pub valid_hours: Option<i64>,
+
+    /// Who the invite is for, shown by `invites list`
+    #[arg(long)]
+    pub note: Option<String>,
+
+    #[command(flatten)]
+    pub random: RandomConfig,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ListInvitesArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RevokeInviteArgs {
+    /// ID of the invite
+    pub invite_id: i64,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum OrgsCommand {
+    /// Create an organization
+    Add(AddOrgArgs),
+    /// List organizations with the number of cards they have
+    List(ListOrgsArgs),
+    /// Serve an organization on its own domain, or on the server's again
+    SetDomain(SetOrgDomainArgs),
+    /// Pay an organization's cards from another Lightning backend
+    SetBackend(SetOrgBackendArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct AddOrgArgs {
+    /// Short unique name, used to refer to the organization on the command line
+    pub slug: String,
+
+    /// Display name (default: the slug)
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// Lightning backend paying the organization's cards, `env:<variable>` or
+    /// `file:<path>` for one with credentials
+    #[arg(long, default_value = "mock")]
+    pub lightning_backend: String,
+
+    /// Domain of the organization's own, pointing at this server (default: the server's)
+    #[arg(long, value_parser = config::parse_domain)]
+    pub domain: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct SetOrgDomainArgs {
+    /// Slug of the organization
+    pub slug: String,
+
+    /// Domain pointing at this server, leave out to use the server's domain again
+    #[arg(value_parser = config::parse_domain)]
+    pub domain: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct SetOrgBackendArgs {
+    /// Slug of the organization
+    pub slug: String,
+
+    /// Lightning backend, `env:<variable>` or `file:<path>` for one with credentials
+    pub lightning_backend: String,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum CapitalCommand {
+    /// Allocate node capital to a card, crediting prepaid cards' balance as well
+    Fund(FundCardArgs),
+    /// Compare what the cards of each node were funded with to the node's balance
+    Report(Box<CapitalReportArgs>),
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum FeesCommand {
+    /// Show the service fees accrued on each node and recent payouts
+    List(ListFeesArgs),
+    /// Pay out the accrued fees now instead of waiting for the next scheduled payout
+    Payout(Box<Config>),
+    /// Give up on a payout that didn't go through, so its fees are paid out with the next one
+    Release(ReleasePayoutArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ListFeesArgs {
+    /// Number of payouts to show
+    #[arg(long, default_value = "20")]
+    pub limit: i64,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ReleasePayoutArgs {
+    /// ID of the payout
+    pub payout_id: i64,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct FundCardArgs {
+    /// ID of the card
+    pub card_id: i64,
+
+    /// Amount in sats
+    #[arg(value_parser = clap::value_parser!(i64).range(1..))]
+    pub amount_sats: i64,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CapitalReportArgs {
+    #[command(flatten)]
+    pub config: Config,
+
+    /// List every card's sub-account as well
+    #[arg(long)]
+    pub cards: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ListOrgsArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct GenTestVectorsArgs {
+    /// Card UID as 7 byte hex, one key set per UID (default: one random UID)
+    #[arg(long = "uid")]
+    pub uids: Vec<String>,
+
+    /// Counter values to generate taps for
+    #[arg(long = "counter", default_value = "1", value_parser = clap::value_parser!(u32).range(0..=0xFF_FFFF))]
+    pub counters: Vec<u32>,
+
+    /// Use this K1 instead of a random one
+    #[arg(long)]
+    pub k1: Option<String>,
+
+    /// Use this K2 instead of a random one
+    #[arg(long)]
+    pub k2: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct SimulateTapArgs {
+    /// Card ID
+    pub card_id: i64,
+
+    /// Base URL of the running server
+    #[arg(long, default_value = "http://localhost:8080")]
+    pub server: String,
+
+    /// Counter value (default: one past the card's last counter, or 1 with --k1/--k2)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(0..=0xFF_FFFF))]
+    pub counter: Option<u32>,
+
+    /// Use this K1 instead of reading the card's keys from the database
+    #[arg(long, requires = "k2")]
+    pub k1: Option<String>,
+
+    /// Use this K2 instead of reading the card's keys from the database
+    #[arg(long, requires = "k1")]
+    pub k2: Option<String>,
+
+    /// Card UID as 7 byte hex (default: the card's UID, random if it has none yet)
+    #[arg(long)]
+    pub uid: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DevArgs {
+    /// Port to listen on, on localhost
+    #[arg(long, default_value = "8080")]
+    pub port: u16,
+
+    /// More arguments for `serve`, after `--`, e.g. `-- --lightning-backend mock:script=fail`
+    #[arg(last = true)]
+    pub serve_args: Vec<String>,
+}
+
+impl DevArgs {
+    /// The settings of `serve`: localhost, the mock backend and sample data, unless overridden
+    pub fn config(&self) -> Result<Config> {
+        let port = self.port.to_string();
+        let domain = format!("localhost:{}", self.port);
+        let defaults = [
+            ("--host", Some("127.0.0.1")),
+            ("--port", Some(port.as_str())),
+            ("--domain", Some(domain.as_str())),
+            ("--public-scheme", Some("http")),
+            ("--lightning-backend", Some("mock")),
+            ("--skip-domain-resolution", None),
+        ];
+
+        let mut args = vec!["lnurlw-server", "serve"];
+        for (flag, value) in defaults {
+            let overridden = self.serve_args.iter().any(|arg| arg == flag || arg.starts_with(&format!("{}=", flag)));
+            if !overridden {
+                args.push(flag);
+                args.extend(value);
+            }
+        }
+        args.extend(self.serve_args.iter().map(String::as_str));
+
+        match Cli::try_parse_from(args)?.command {
+            Command::Serve(config) => Ok(config),
+            _ => unreachable!("parsed as serve"),
+        }
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct SeedArgs {
+    #[command(flatten)]
+    pub config: Config,
+
+    /// Base URL of the running server, for the printed tap URLs
+    #[arg(long, default_value = "http://localhost:8080")]
+    pub server: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ReplayArgs {
+    /// Log written with --record-requests
+    pub file: PathBuf,
+
+    /// Base URL of the test instance
+    #[arg(long, default_value = "http://localhost:8080")]
+    pub server: String,
+
+    /// Wait between requests as long as when they were recorded
+    #[arg(long)]
+    pub realtime: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct LoadTestArgs {
+    /// Base URL of the server, which has to run with the mock backend on this database
+    #[arg(long, default_value = "http://localhost:8080")]
+    pub server: String,
+
+    /// Cards tapping at the same time, each is created for the test and disabled after it
+    #[arg(long, default_value = "10", value_parser = clap::value_parser!(u32).range(1..=1000))]
+    pub cards: u32,
+
+    /// Withdrawals in total
+    #[arg(long, default_value = "1000")]
+    pub withdrawals: usize,
+
+    /// Network of the invoices, as the server's --network
+    #[arg(long, default_value = "regtest")]
+    pub network: Network,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RotateKeysArgs {
+    #[command(flatten)]
+    pub config: CardIssueConfig,
+
+    /// Rotate this card, can be repeated
+    #[arg(long = "card-id", conflicts_with = "tag")]
+    pub card_ids: Vec<i64>,
+
+    /// Rotate all cards with this tag
+    #[arg(long)]
+    pub tag: Option<String>,
+
+    /// How long the old keys keep working, in hours
+    #[arg(long, default_value = "168")]
+    pub grace_hours: i64,
+
+    /// Output format, JSON includes the current keys the programming app needs
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ReplaceCardArgs {
+    #[command(flatten)]
+    pub config: CardIssueConfig,
+
+    /// ID of the card to replace
+    pub card_id: i64,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct BackupArgs {
+    /// Public domain of the server, recorded so the backup is only restored under the same one
+    #[arg(long, env = "DOMAIN", value_parser = config::parse_domain)]
+    pub domain: String,
+
+    /// File to write the backup to, may be left out when uploading it to a bucket
+    pub output: Option<PathBuf>,
+
+    /// File holding the passphrase the backup is encrypted with, instead of BACKUP_PASSPHRASE or a prompt
+    #[arg(long)]
+    pub passphrase_file: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub s3: S3Config,
+
+    #[command(flatten)]
+    pub random: RandomConfig,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RestoreArgs {
+    /// Public domain of the server, which the backup must have been taken under
+    #[arg(long, env = "DOMAIN", value_parser = config::parse_domain)]
+    pub domain: String,
+
+    /// Backup file to restore
+    #[arg(required_unless_present = "from_s3", conflicts_with = "from_s3")]
+    pub input: Option<PathBuf>,
+
+    /// Object name of the backup to fetch from the bucket instead, or "latest" for the newest one
+    #[arg(long)]
+    pub from_s3: Option<String>,
+
+    /// File holding the passphrase the backup was encrypted with, instead of BACKUP_PASSPHRASE or a prompt
+    #[arg(long)]
+    pub passphrase_file: Option<PathBuf>,
+
+    /// Restore even if the backup was taken with a different domain
+    #[arg(long)]
+    pub allow_config_change: bool,
+
+    #[command(flatten)]
+    pub s3: S3Config,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ReportFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct StatsArgs {
+    /// First day of the report, YYYY-MM-DD (default: 29 days before --to)
+    #[arg(long)]
+    pub from: Option<NaiveDate>,
+
+    /// Last day of the report, YYYY-MM-DD (default: today, UTC)
+    #[arg(long)]
+    pub to: Option<NaiveDate>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: ReportFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct LedgerArgs {
+    /// First day of the journal, YYYY-MM-DD (default: 29 days before --to)
+    #[arg(long)]
+    pub from: Option<NaiveDate>,
+
+    /// Last day of the journal, YYYY-MM-DD (default: today, UTC)
+    #[arg(long)]
+    pub to: Option<NaiveDate>,
+
+    /// Journal syntax
+    #[arg(long, value_enum, default_value = "beancount")]
+    pub format: LedgerFormat,
+
+    /// Asset account of the server's node, organizations' nodes get a sub-account named after them
+    #[arg(long, default_value = "Assets:Lightning:Node")]
+    pub node_account: String,
+
+    /// Parent of the expense accounts of the cards, one per card as `<account>:Card<id>`
+    #[arg(long, default_value = "Expenses:Cards")]
+    pub card_account: String,
+
+    /// Expense account of routing fees
+    #[arg(long, default_value = "Expenses:Lightning:Fees")]
+    pub fee_account: String,
+
+    /// Income account of the service fees charged to cards
+    #[arg(long, default_value = "Income:ServiceFees")]
+    pub service_fee_account: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct FiatReportArgs {
+    /// Year of the report (default: the current one, UTC)
+    #[arg(long)]
+    pub year: Option<i32>,
+
+    /// Only this month of the year, 1 to 12
+    #[arg(long)]
+    pub month: Option<u32>,
+
+    /// Output format, PDF is written to stdout as well
+    #[arg(long, value_enum, default_value = "csv")]
+    pub format: FiatReportFormat,
+
+    /// One line per withdrawal instead of monthly summaries (CSV and JSON)
+    #[arg(long)]
+    pub payments: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+#[group(required = true, multiple = false, id = "source")]
+pub struct ImportSource {
+    /// JSON list of cards as returned by the extension's API
+    #[arg(long)]
+    pub json: Option<PathBuf>,
+
+    /// The extension's SQLite database, usually ext_boltcards.sqlite3
+    #[arg(long)]
+    pub sqlite: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ImportLnbitsArgs {
+    #[command(flatten)]
+    pub source: ImportSource,
+
+    /// Only report what would be imported and any conflicts
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Import the other cards even if some conflict
+    #[arg(long)]
+    pub skip_conflicts: bool,
+}
+
+/// Run any subcommand except `serve`
+pub async fn run(command: &Command, database: &DatabaseConfig) -> Result<()> {
+    match command {
+        Command::Serve(_) | Command::Dev(_) => bail!("serve and dev are handled by main"),
+        Command::CheckConfig(args) => check_config(database, args).await,
+        Command::CreateCard(args) => create_card(database, args).await,
+        Command::Export(args) => export(database, args).await,
+        Command::ListCards(args) => list_cards(database, args).await,
+        Command::Decode(args) => decode(args),
+        Command::VerifyReceipt(args) => verify_receipt(database, args).await,
+        Command::Migrate(args) => migrate(database, args).await,
+        Command::Db(DbCommand::Doctor(args)) => doctor(database, args).await,
+        Command::GenTestVectors(args) => gen_test_vectors(args),
+        Command::SimulateTap(args) => simulate_tap(database, args).await,
+        Command::Seed(args) => seed_database(database, args).await,
+        Command::Replay(args) => replay(args).await,
+        Command::LoadTest(args) => load_test(database, args).await,
+        Command::RotateKeys(args) => rotate_keys(database, args).await,
+        Command::ReplaceCard(args) => replace_card(database, args).await,
+        Command::Backup(args) => create_backup(database, args).await,
+        Command::Restore(args) => restore_backup(database, args).await,
+        Command::Stats(args) => usage_stats(database, args).await,
+        Command::Ledger(args) => ledger_export(database, args).await,
+        Command::FiatReport(args) => fiat_report(database, args).await,
+        Command::ImportLnbits(args) => import_lnbits(database, args).await,
+        Command::Jobs(JobsCommand::List(args)) => list_jobs(database, args).await,
+        Command::Jobs(JobsCommand::Revive(args)) => revive_job(database, args).await,
+        Command::Webhooks(WebhooksCommand::List(args)) => list_webhook_events(database, args).await,
+        Command::Webhooks(WebhooksCommand::Redeliver(args)) => redeliver_webhook_events(database, args).await,
+        Command::Discrepancies(DiscrepanciesCommand::List(args)) => list_discrepancies(database, args).await,
+        Command::Discrepancies(DiscrepanciesCommand::Resolve(args)) => resolve_discrepancy(database, args).await,
+        Command::Users(UsersCommand::Add(args)) => add_user(database, args).await,
+        Command::Users(UsersCommand::List(args)) => list_users(database, args).await,
+        Command::Users(UsersCommand::Disable(args)) => set_user_enabled(database, args, false).await,
+        Command::Users(UsersCommand::Enable(args)) => set_user_enabled(database, args, true).await,
+        Command::Users(UsersCommand::Policy(args)) => set_user_policy(database, args).await,
+        Command::Users(UsersCommand::Export(args)) => export_user(database, args).await,
+        Command::Users(UsersCommand::Erase(args)) => erase_user(database, args).await,
+        Command::Invites(InvitesCommand::Create(args)) => create_invite(database, args).await,
+        Command::Invites(InvitesCommand::List(args)) => list_invites(database, args).await,
+        Command::Invites(InvitesCommand::Revoke(args)) => revoke_invite(database, args).await,
+        Command::OperatorKeys(OperatorKeysCommand::Create(args)) => create_operator_key(database, args).await,
+        Command::OperatorKeys(OperatorKeysCommand::List(args)) => list_operator_keys(database, args).await,
+        Command::OperatorKeys(OperatorKeysCommand::Revoke(args)) => revoke_operator_key(database, args).await,
+        Command::Orgs(OrgsCommand::Add(args)) => add_org(database, args).await,
+        Command::Orgs(OrgsCommand::List(args)) => list_orgs(database, args).await,
+        Command::Orgs(OrgsCommand::SetDomain(args)) => set_org_domain(database, args).await,
+        Command::Orgs(OrgsCommand::SetBackend(args)) => set_org_backend(database, args).await,
+        Command::Capital(CapitalCommand::Fund(args)) => fund_card(database, args).await,
+        Command::Capital(CapitalCommand::Report(args)) => capital_report(database, args).await,
+        Command::Fees(FeesCommand::List(args)) => list_fees(database, args).await,
+        Command::Fees(FeesCommand::Payout(config)) => pay_out_fees(database, config).await,
+        Command::Fees(FeesCommand::Release(args)) => release_fee_payout(database, args).await,
+    }
+}
+
+async fn check_config(database: &DatabaseConfig, args: &CheckConfigArgs) -> Result<()> {
+    let lightning = LightningBackends::new(args.config.lightning_backend()?);
+    let checks = self_test::run(database, &args.config, &lightning, &reqwest::Client::new()).await;
+
+    match args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&checks)?),
+        OutputFormat::Table => {
+            let rows: Vec<[String; 3]> = checks
+                .iter()
+                .map(|check| {
+                    [
+                        check.outcome.label().to_string(),
+                        check.name.to_string(),
+                        check.outcome.details().to_string(),
+                    ]
+                })
+                .collect();
+            print!("{}", format_table(&["STATUS", "CHECK", "DETAILS"], &rows));
+        }
+    }
+
+    let failed = checks.iter().filter(|check| check.failed()).count();
+    if failed > 0 {
+        bail!("{} check(s) failed", failed);
+    }
+    eprintln!("All checks passed");
+
+    Ok(())
+}
+
+async fn create_card(database: &DatabaseConfig, args: &CreateCardArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    let owner_id = match &args.owner {
+        Some(username) => match users::get_user_by_username(&pool, username).await? {
+            Some(user) => Some(user.user_id),
+            None => bail!("No user named {}", username),
+        },
+        None => None,
+    };
+    let org_id = match &args.org {
+        Some(slug) => Some(org_by_slug(&pool, slug).await?.org_id),
+        None => None,
+    };
+
+    let mut req = CreateCardRequest {
+        card_name: args.name.clone(),
+        tx_limit_sats: args.tx_limit,
+        day_limit_sats: args.day_limit,
+        enabled: Some(true),
+        balance_mode: Some(args.balance_mode),
+        tags: Some(args.tags.clone()),
+        owner_id,
+        org_id,
+    };
+    policy::apply_to_new_card(&pool, &args.config, &mut req).await?;
+
+    let created = create_card_record(&pool, &args.config, &Random::from_config(&args.config)?, &req, chrono::Utc::now()).await?;
+    let domain = organizations::get_org_domain(&pool, org_id).await?;
+
+    println!("Card ID:          {}", created.card_id);
+    println!(
+        "Registration URL: {}?a={}",
+        args.config.urls_on(domain.as_deref()).registration_base(),
+        created.one_time_code
+    );
+
+    Ok(())
+}
+
+/// Issue a new card with the old card's name, limits, owner, tags, metadata and balance and disable the old one
+async fn replace_card(database: &DatabaseConfig, args: &ReplaceCardArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    let Some(card) = queries::get_card_by_id(&pool, args.card_id).await? else {
+        bail!("No card with ID {}", args.card_id);
+    };
+    let random = Random::from_device(args.config.random_device.as_deref())?;
+    let replacement = replace_card_record(&pool, &random, &card, chrono::Utc::now(), "cli").await?;
+    let domain = organizations::get_org_domain(&pool, replacement.org_id).await?;
+
+    println!("Card {} disabled, replaced by card {}", card.card_id, replacement.card.card_id);
+    if card.balance_mode {
+        println!("Balance moved:    {} sats", replacement.balance_msats / 1000);
+    }
+    println!(
+        "Registration URL: {}?a={}",
+        args.config.urls_on(domain.as_deref()).registration_base(),
+        replacement.card.one_time_code
+    );
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct CardExport {
+    card_id: i64,
+    card_name: String,
+    uid: String,
+    enabled: bool,
+    tx_limit_sats: i64,
+    day_limit_sats: i64,
+    last_counter: i64,
+    balance_mode: bool,
+    balance_msats: i64,
+    created_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct PaymentExport {
+    payment_id: i64,
+    card_id: i64,
+    amount_msats: Option<i64>,
+    status: PaymentStatus,
+    payment_time: Option<String>,
+    payment_hash: Option<String>,
+    preimage: Option<String>,
+    created_at: Option<String>,
+    memo: Option<String>,
+    onchain_address: Option<String>,
+    onchain_txid: Option<String>,
+}
+
+/// Export without card keys or withdrawal session secrets
+async fn export(database: &DatabaseConfig, args: &ExportArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    let (header, rows, json) = match args.kind {
+        ExportKind::Cards => {
+            let cards: Vec<CardExport> = queries::list_cards(&pool)
+                .await?
+                .into_iter()
+                .map(|card| CardExport {
+                    card_id: card.card_id,
+                    card_name: card.card_name,
+                    uid: card.uid,
+                    enabled: card.enabled,
+                    tx_limit_sats: card.tx_limit_sats,
+                    day_limit_sats: card.day_limit_sats,
+                    last_counter: card.last_counter,
+                    balance_mode: card.balance_mode,
+                    balance_msats: card.balance_msats,
+                    created_at: card.created_at,
+                })
+                .collect();
+
+            let header = vec![
+                "card_id", "card_name", "uid", "enabled", "tx_limit_sats", "day_limit_sats",
+                "last_counter", "balance_mode", "balance_msats", "created_at",
+            ];
+            let rows: Vec<Vec<String>> = cards
+                .iter()
+                .map(|c| {
+                    vec![
+                        c.card_id.to_string(),
+                        c.card_name.clone(),
+                        c.uid.clone(),
+                        c.enabled.to_string(),
+                        c.tx_limit_sats.to_string(),
+                        c.day_limit_sats.to_string(),
+                        c.last_counter.to_string(),
+                        c.balance_mode.to_string(),
+                        c.balance_msats.to_string(),
+                        c.created_at.clone().unwrap_or_default(),
+                    ]
+                })
+                .collect();
+            (header, rows, serde_json::to_string_pretty(&cards)?)
+        }
+        ExportKind::Payments => {
+            let payments: Vec<PaymentExport> = queries::list_payments(&pool)
+                .await?
+                .into_iter()
+                .map(|payment| PaymentExport {
+                    payment_id: payment.payment_id,
+                    card_id: payment.card_id,
+                    amount_msats: payment.amount_msats,
+                    status: payment.status,
+                    payment_time: payment.payment_time,
+                    payment_hash: payment.payment_hash,
+                    preimage: payment.preimage,
+                    created_at: payment.created_at,
+                    memo: payment.memo,
+                    onchain_address: payment.onchain_address,
+                    onchain_txid: payment.onchain_txid,
+                })
+                .collect();
+
+            let header = vec![
+                "payment_id", "card_id", "amount_msats", "status", "payment_time",
+                "payment_hash", "preimage", "created_at", "memo", "onchain_address", "onchain_txid",
+            ];
+            let rows: Vec<Vec<String>> = payments
+                .iter()
+                .map(|p| {
+                    vec![
+                        p.payment_id.to_string(),
+                        p.card_id.to_string(),
+                        p.amount_msats.map(|a| a.to_string()).unwrap_or_default(),
+                        p.status.to_string(),
+                        p.payment_time.clone().unwrap_or_default(),
+                        p.payment_hash.clone().unwrap_or_default(),
+                        p.preimage.clone().unwrap_or_default(),
+                        p.created_at.clone().unwrap_or_default(),
+                        p.memo.clone().unwrap_or_default(),
+                        p.onchain_address.clone().unwrap_or_default(),
+                        p.onchain_txid.clone().unwrap_or_default(),
+                    ]
+                })
+                .collect();
+            (header, rows, serde_json::to_string_pretty(&payments)?)
+        }
+    };
+
+    match args.format {
+        ExportFormat::Json => println!("{}", json),
+        ExportFormat::Csv => print!("{}", format_csv(&header, &rows)),
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct CardSummary {
+    card_id: i64,
+    card_name: String,
+    uid: String,
+    counter: i64,
+    enabled: bool,
+    /// Disabled because it was reported lost
+    lost: bool,
+    /// Deleted, listed with `--include-deleted` only
+    deleted: bool,
+    tx_limit_sats: i64,
+    day_limit_sats: i64,
+    day_spent_sats: i64,
+}
+
+async fn list_cards(database: &DatabaseConfig, args: &ListCardsArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    let org_cards = match &args.org {
+        Some(slug) => Some(organizations::list_org_card_ids(&pool, org_by_slug(&pool, slug).await?.org_id).await?),
+        None => None,
+    };
+
+    let lost_cards = lost::list_lost_card_ids(&pool).await?;
+    let deleted_cards = deletion::list_deleted_card_ids(&pool).await?;
+    let mut cards = Vec::new();
+    for card in queries::list_cards(&pool).await? {
+        if let Some(org_cards) = &org_cards
+            && !org_cards.contains(&card.card_id)
+        {
+            continue;
+        }
+        let deleted = deleted_cards.contains(&card.card_id);
+        if deleted && !args.include_deleted {
+            continue;
+        }
+        let day_spent_msats = queries::get_daily_total_msats(&pool, card.card_id, chrono::Utc::now()).await?;
+        cards.push(CardSummary {
+            card_id: card.card_id,
+            card_name: card.card_name,
+            uid: card.uid,
+            counter: card.last_counter,
+            enabled: card.enabled,
+            lost: lost_cards.contains(&card.card_id),
+            deleted,
+            tx_limit_sats: card.tx_limit_sats,
+            day_limit_sats: card.day_limit_sats,
+            day_spent_sats: day_spent_msats / 1000,
+        });
+    }
+
+    match args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&cards)?),
+        OutputFormat::Table => {
+            let header = ["ID", "NAME", "UID", "COUNTER", "ENABLED", "TX LIMIT", "DAY LIMIT", "SPENT 24H"];
+            let rows: Vec<[String; 8]> = cards
+                .iter()
+                .map(|card| {
+                    [
+                        card.card_id.to_string(),
+                        card.card_name.clone(),
+                        if card.uid.is_empty() { "-".to_string() } else { card.uid.clone() },
+                        card.counter.to_string(),
+                        match (card.deleted, card.enabled, card.lost) {
+                            (true, _, _) => "deleted",
+                            (false, true, _) => "yes",
+                            (false, false, true) => "lost",
+                            (false, false, false) => "no",
+                        }
+                        .to_string(),
+                        card.tx_limit_sats.to_string(),
+                        card.day_limit_sats.to_string(),
+                        card.day_spent_sats.to_string(),
+                    ]
+                })
+                .collect();
+            print!("{}", format_table(&header, &rows));
+        }
+    }
+
+    Ok(())
+}
+
+async fn list_jobs(database: &DatabaseConfig, args: &ListJobsArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    let status = args.status.map(|status| match status {
+        JobStatus::Queued => "queued",
+        JobStatus::Running => "running",
+        JobStatus::Dead => "dead",
+    });
+    let jobs = db::jobs::list_jobs(&pool, status).await?;
+
+    match args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&jobs)?),
+        OutputFormat::Table => {
+            let header = ["ID", "KIND", "STATUS", "ATTEMPTS", "RUN AFTER", "LAST ERROR"];
+            let rows: Vec<[String; 6]> = jobs
+                .iter()
+                .map(|job| {
+                    [
+                        job.job_id.to_string(),
+                        job.kind.clone(),
+                        job.status.clone(),
+                        job.attempts.to_string(),
+                        job.run_after.clone(),
+                        job.last_error.clone().unwrap_or_else(|| "-".to_string()),
+                    ]
+                })
+                .collect();
+            print!("{}", format_table(&header, &rows));
+        }
+    }
+
+    Ok(())
+}
+
+async fn revive_job(database: &DatabaseConfig, args: &ReviveJobArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    if !db::jobs::revive(&pool, args.job_id).await? {
+        bail!("No dead job with ID {}", args.job_id);
+    }
+    println!("Job {} queued again, a running server picks it up within seconds", args.job_id);
+
+    Ok(())
+}
+
+async fn list_webhook_events(database: &DatabaseConfig, args: &ListWebhookEventsArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    let status = args.status.map(WebhookEventStatus::as_str);
+    let events = db::webhooks::list_events(&pool, status, args.limit).await?;
+
+    match args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&events)?),
+        OutputFormat::Table => {
+            let header = ["ID", "EVENT", "STATUS", "ATTEMPTS", "CREATED", "LAST ERROR"];
+            let rows: Vec<[String; 6]> = events
+                .iter()
+                .map(|event| {
+                    [
+                        event.event_id.to_string(),
+                        event.event.clone(),
+                        event.status.clone(),
+                        event.attempts.to_string(),
+                        event.created_at.clone().unwrap_or_default(),
+                        event.last_error.clone().unwrap_or_else(|| "-".to_string()),
+                    ]
+                })
+                .collect();
+            print!("{}", format_table(&header, &rows));
+        }
+    }
+
+    Ok(())
+}
+
+async fn redeliver_webhook_events(database: &DatabaseConfig, args: &RedeliverArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    let event_ids = if args.all_dead {
+        db::webhooks::list_events(&pool, Some("dead"), i64::MAX)
+            .await?
+            .iter()
+            .rev()
+            .map(|event| event.event_id)
+            .collect()
+    } else {
+        args.event_ids.clone()
+    };
+
+    let mut queued = 0;
+    for event_id in event_ids {
+        match notifications::redeliver(&pool, event_id).await? {
+            Redelivery::Queued => queued += 1,
+            Redelivery::NotFound => eprintln!("No event with ID {}", event_id),
+            Redelivery::Pending => eprintln!("Event {} is still being delivered", event_id),
+        }
+    }
+    println!("Queued {} event(s) for delivery, a running server sends them within seconds", queued);
+
+    Ok(())
+}
+
+async fn list_discrepancies(database: &DatabaseConfig, args: &ListDiscrepanciesArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    let discrepancies = db::reconcile::list(&pool, args.all).await?;
+
+    match args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&discrepancies)?),
+        OutputFormat::Table => {
+            let header = ["ID", "PAYMENT", "CARD", "KIND", "NODE", "FOUND", "RESOLVED"];
+            let rows: Vec<[String; 7]> = discrepancies
+                .iter()
+                .map(|d| {
+                    [
+                        d.discrepancy_id.to_string(),
+                        d.payment_id.to_string(),
+                        d.card_id.to_string(),
+                        d.kind.clone(),
+                        d.node_status.clone(),
+                        d.created_at.clone().unwrap_or_default(),
+                        d.resolved_at.clone().unwrap_or_else(|| "-".to_string()),
+                    ]
+                })
+                .collect();
+            print!("{}", format_table(&header, &rows));
+        }
+    }
+
+    Ok(())
+}
+
+async fn resolve_discrepancy(database: &DatabaseConfig, args: &ResolveDiscrepancyArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    if let Some(outcome) = args.payment {
+        let Some(payment_id) = db::reconcile::open_discrepancy_payment(&pool, args.discrepancy_id).await? else {
+            bail!("No open discrepancy with ID {}", args.discrepancy_id);
+        };
+        let decided = match outcome {
+            PaymentOutcome::Settled => queries::mark_payment_settled(&pool, payment_id, None, None, chrono::Utc::now()).await?,
+            PaymentOutcome::Failed => db::reconcile::fail_in_flight(&pool, payment_id, "Failed by the operator").await?,
+        };
+        if !decided {
+            bail!("Payment {} isn't in flight", payment_id);
+        }
+        let status = match outcome {
+            PaymentOutcome::Settled => PaymentStatus::Settled,
+            PaymentOutcome::Failed => PaymentStatus::Failed,
+        };
+        println!("Payment {} marked {}", payment_id, status);
+    }
+    if !db::reconcile::resolve(&pool, args.discrepancy_id).await? {
+        bail!("No open discrepancy with ID {}", args.discrepancy_id);
+    }
+    println!("Discrepancy {} resolved", args.discrepancy_id);
+
+    Ok(())
+}
+
+async fn list_fees(database: &DatabaseConfig, args: &ListFeesArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    let accrued = fees::accrued(&pool).await?;
+    let payouts = fees::list_payouts(&pool, args.limit).await?;
+
+    match args.format {
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({ "accrued": accrued, "payouts": payouts }))?)
+        }
+        OutputFormat::Table => {
+            let header = ["NODE", "PAYMENTS", "ACCRUED (SATS)"];
+            let rows: Vec<[String; 3]> = accrued
+                .iter()
+                .map(|a| {
+                    [
+                        a.org_slug.clone().unwrap_or_else(|| "-".to_string()),
+                        a.payments.to_string(),
+                        format!("{:.3}", a.amount_msats as f64 / 1000.0),
+                    ]
+                })
+                .collect();
+            print!("{}", format_table(&header, &rows));
+            println!();
+
+            let header = ["PAYOUT", "NODE", "AMOUNT (SATS)", "STATUS", "DESTINATION", "CREATED", "ERROR"];
+            let slugs: std::collections::HashMap<i64, String> = organizations::list_organizations(&pool)
+                .await?
+                .into_iter()
+                .map(|(org, _)| (org.org_id, org.slug))
+                .collect();
+            let rows: Vec<[String; 7]> = payouts
+                .iter()
+                .map(|p| {
+                    [
+                        p.payout_id.to_string(),
+                        p.org_id.and_then(|id| slugs.get(&id).cloned()).unwrap_or_else(|| "-".to_string()),
+                        format!("{:.3}", p.amount_msats as f64 / 1000.0),
+                        p.status.clone(),
+                        p.destination.clone(),
+                        p.created_at.clone().unwrap_or_default(),
+                        p.error.clone().unwrap_or_default(),
+                    ]
+                })
+                .collect();
+            print!("{}", format_table(&header, &rows));
+        }
+    }
+
+    Ok(())
+}
+
+async fn pay_out_fees(database: &DatabaseConfig, config: &Config) -> Result<()> {
+    let pool = init_pool(database).await?;
+    let lightning = LightningBackends::new(config.lightning_backend()?);
+
+    let payouts = service_fees::run(&pool, &lightning, &reqwest::Client::new(), config).await?;
+    if payouts.is_empty() {
+        println!("No node has {} sats or more of fees to pay out", config.fee_payout_min_sats);
+    }
+    for payout in payouts {
+        let Some(payout) = fees::get_payout(&pool, payout.payout_id).await? else {
+            continue;
+        };
+        match payout.error {
+            Some(error) => println!("Payout {} of {} msats {}: {}", payout.payout_id, payout.amount_msats, payout.status, error),
+            None => println!("Payout {} of {} msats {}", payout.payout_id, payout.amount_msats, payout.status),
+        }
+    }
+
+    Ok(())
+}
+
+async fn release_fee_payout(database: &DatabaseConfig, args: &ReleasePayoutArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    if !fees::release_payout(&pool, args.payout_id, "Released by the operator").await? {
+        bail!("No unpaid payout with ID {}", args.payout_id);
+    }
+    println!("Payout {} released, its fees will be paid out with the next one", args.payout_id);
+
+    Ok(())
+}
+
+async fn add_user(database: &DatabaseConfig, args: &AddUserArgs) -> Result<()> {
+    handlers::users::validate_username(&args.username).map_err(|reason| anyhow!(reason))?;
+
+    let pool = init_pool(database).await?;
+    let random = Random::from_device(args.random.random_device.as_deref())?;
+
+    let password = random.hex::<12>()?;
+    let Some(user_id) = users::create_user(&pool, &args.username, &auth::hash_password(&random, &password)?).await? else {
+        bail!("Username {} is taken", args.username);
+    };
+
+    println!("User ID:  {}", user_id);
+    println!("Password: {}", password);
+
+    Ok(())
+}
+
+async fn list_users(database: &DatabaseConfig, args: &ListUsersArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    let users = users::list_users(&pool).await?;
+
+    match args.format {
+        OutputFormat::Json => {
+            let users: Vec<_> = users
+                .iter()
+                .map(|(user, cards)| {
+                    serde_json::json!({
+                        "user_id": user.user_id,
+                        "username": user.username,
+                        "enabled": user.enabled,
+                        "cards": cards,
+                        "policy": user.policy,
+                        "invite_id": user.invite_id,
+                        "created_at": user.created_at,
+                        "erased_at": user.erased_at,
+                    })
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&users)?);
+        }
+        OutputFormat::Table => {
+            let header = ["ID", "USERNAME", "ENABLED", "CARDS", "INVITE", "CREATED"];
+            let rows: Vec<[String; 6]> = users
+                .iter()
+                .map(|(user, cards)| {
+                    [
+                        user.user_id.to_string(),
+                        user.username.clone(),
+                        user.enabled.to_string(),
+                        match user.policy.max_cards {
+                            Some(max) => format!("{}/{}", cards, max),
+                            None => cards.to_string(),
+                        },
+                        user.invite_id.map_or_else(|| "-".to_string(), |id| id.to_string()),
+                        user.created_at.clone().unwrap_or_default(),
+                    ]
+                })
+                .collect();
+            print!("{}", format_table(&header, &rows));
+        }
+    }
+
+    Ok(())
+}
+
+async fn create_invite(database: &DatabaseConfig, args: &CreateInviteArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+    let random = Random::from_device(args.random.random_device.as_deref())?;
+
+    let code = random.hex::<8>()?;
+    let invite_id = invites::create_invite(&pool, &code, args.uses, args.valid_hours, args.note.as_deref(), chrono::Utc::now()).await?;
+
+    println!("Invite ID:   {}", invite_id);
+    println!("Invite code: {}", code);
+
+    Ok(())
+}
+
+async fn list_invites(database: &DatabaseConfig, args: &ListInvitesArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    let invites = invites::list_invites(&pool).await?;
+
+    match args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&invites)?),
+        OutputFormat::Table => {
+            let header = ["ID", "CODE", "USES", "EXPIRES", "REVOKED", "NOTE"];
+            let rows: Vec<[String; 6]> = invites
+                .iter()
+                .map(|invite| {
+                    [
+                        invite.invite_id.to_string(),
+                        invite.code.clone(),
+                        format!("{}/{}", invite.uses, invite.max_uses),
+                        invite.expires_at.clone().unwrap_or_else(|| "-".to_string()),
+                        invite.revoked_at.clone().unwrap_or_else(|| "-".to_string()),
+                        invite.note.clone().unwrap_or_default(),
+                    ]
+                })
+                .collect();
+            print!("{}", format_table(&header, &rows));
+        }
+    }
+
+    Ok(())
+}
+
+async fn revoke_invite(database: &DatabaseConfig, args: &RevokeInviteArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    if !invites::revoke_invite(&pool, args.invite_id).await? {
+        bail!("No active invite with ID {}", args.invite_id);
+    }
+    println!("Invite {} revoked", args.invite_id);
+
+    Ok(())
+}
+
+async fn create_operator_key(database: &DatabaseConfig, args: &CreateOperatorKeyArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+    let random = Random::from_device(args.random.random_device.as_deref())?;
+
+    let key = auth::new_operator_key(&random)?;
+    let key_id = operator_keys::create_key(&pool, &args.name, &auth::hash_session_token(&key), args.role.as_str()).await?;
+
+    println!("Key ID: {}", key_id);
+    println!("Key:    {}", key);
+
+    Ok(())
+}
+
+async fn list_operator_keys(database: &DatabaseConfig, args: &ListOperatorKeysArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    let keys = operator_keys::list_keys(&pool).await?;
+
+    match args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&keys)?),
+        OutputFormat::Table => {
+            let header = ["ID", "NAME", "ROLE", "CREATED", "LAST USED", "REVOKED"];
+            let rows: Vec<[String; 6]> = keys
+                .iter()
+                .map(|key| {
+                    [
+                        key.key_id.to_string(),
+                        key.name.clone(),
+                        key.role.clone(),
+                        key.created_at.clone().unwrap_or_default(),
+                        key.last_used_at.clone().unwrap_or_else(|| "-".to_string()),
+                        key.revoked_at.clone().unwrap_or_else(|| "-".to_string()),
+                    ]
+                })
+                .collect();
+            print!("{}", format_table(&header, &rows));
+        }
+    }
+
+    Ok(())
+}
+
+async fn revoke_operator_key(database: &DatabaseConfig, args: &RevokeOperatorKeyArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    if !operator_keys::revoke_key(&pool, args.key_id).await? {
+        bail!("No active operator key with ID {}", args.key_id);
+    }
+    println!("Operator key {} revoked", args.key_id);
+
+    Ok(())
+}
+
+async fn org_by_slug(pool: &Pool<Sqlite>, slug: &str) -> Result<Organization> {
+    organizations::get_organization_by_slug(pool, slug)
+        .await?
+        .ok_or_else(|| anyhow!("No organization named {}", slug))
+}
+
+async fn add_org(database: &DatabaseConfig, args: &AddOrgArgs) -> Result<()> {
+    if args.slug.is_empty() || !args.slug.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+        bail!("Slug may only contain lowercase letters, digits and '-'");
+    }
+    check_org_backend(&args.lightning_backend)?;
+
+    let pool = init_pool(database).await?;
+
+    let name = args.name.as_deref().unwrap_or(&args.slug);
+    let domain = args.domain.as_ref().map(|domain| domain.to_ascii_lowercase());
+    let Some(org_id) =
+        organizations::create_organization(&pool, &args.slug, name, &args.lightning_backend, domain.as_deref()).await?
+    else {
+        bail!("An organization named {} or with the same domain exists already", args.slug);
+    };
+
+    println!("Organization ID: {}", org_id);
+
+    Ok(())
+}
+
+async fn set_org_domain(database: &DatabaseConfig, args: &SetOrgDomainArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    let org = org_by_slug(&pool, &args.slug).await?;
+    let domain = args.domain.as_ref().map(|domain| domain.to_ascii_lowercase());
+    if !organizations::set_organization_domain(&pool, org.org_id, domain.as_deref()).await? {
+        bail!("Another organization has the domain {}", domain.unwrap_or_default());
+    }
+
+    match domain {
+        Some(domain) => println!("Organization {} is served on {}", args.slug, domain),
+        None => println!("Organization {} is served on the server's domain", args.slug),
+    }
+
+    Ok(())
+}
+
+/// Refuse a backend that can't be built, or would store its credentials in the database
+fn check_org_backend(spec: &str) -> Result<()> {
+    if backends::has_inline_credentials(spec) {
+        bail!("Backends are stored as given, put one with credentials into an environment variable or file and pass env:<variable> or file:<path>");
+    }
+    backends::build(spec)?;
+    Ok(())
+}
+
+async fn set_org_backend(database: &DatabaseConfig, args: &SetOrgBackendArgs) -> Result<()> {
+    check_org_backend(&args.lightning_backend)?;
+
+    let pool = init_pool(database).await?;
+
+    let org = org_by_slug(&pool, &args.slug).await?;
+    organizations::set_organization_backend(&pool, org.org_id, &args.lightning_backend).await?;
+    println!("Organization {} is paid from {}", args.slug, args.lightning_backend);
+
+    Ok(())
+}
+
+async fn list_orgs(database: &DatabaseConfig, args: &ListOrgsArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    let orgs = organizations::list_organizations(&pool).await?;
+
+    match args.format {
+        OutputFormat::Json => {
+            let orgs: Vec<_> = orgs
+                .iter()
+                .map(|(org, cards)| {
+                    serde_json::json!({
+                        "org_id": org.org_id,
+                        "slug": org.slug,
+                        "name": org.name,
+                        "lightning_backend": org.lightning_backend,
+                        "domain": org.domain,
+                        "cards": cards,
+                        "created_at": org.created_at,
+                    })
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&orgs)?);
+        }
+        OutputFormat::Table => {
+            let header = ["ID", "SLUG", "NAME", "BACKEND", "DOMAIN", "CARDS", "CREATED"];
+            let rows: Vec<[String; 7]> = orgs
+                .iter()
+                .map(|(org, cards)| {
+                    [
+                        org.org_id.to_string(),
+                        org.slug.clone(),
+                        org.name.clone(),
+                        org.lightning_backend.clone(),
+                        org.domain.clone().unwrap_or_else(|| "-".to_string()),
+                        cards.to_string(),
+                        org.created_at.clone().unwrap_or_default(),
+                    ]
+                })
+                .collect();
+            print!("{}", format_table(&header, &rows));
+        }
+    }
+
+    Ok(())
+}
+
+async fn fund_card(database: &DatabaseConfig, args: &FundCardArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    if !accounts::fund_card(&pool, args.card_id, args.amount_sats * 1000, "cli").await? {
+        bail!("No card with ID {}", args.card_id);
+    }
+    println!("Card {} funded with {} sats", args.card_id, args.amount_sats);
+
+    Ok(())
+}
+
+/// Capital of the cards on one node, against what the node holds
+#[derive(Debug, Serialize)]
+struct NodeCapital {
+    /// Organization slug, `None` for the server's own node
+    org: Option<String>,
+    cards: usize,
+    funded_msats: i64,
+    spent_msats: i64,
+    reserved_msats: i64,
+    available_msats: i64,
+    node_balance_msats: Option<u64>,
+    /// Node balance minus the unspent funding, negative if the node can't cover the cards
+    surplus_msats: Option<i64>,
+    error: Option<String>,
+}
+
+async fn capital_report(database: &DatabaseConfig, args: &CapitalReportArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+    let lightning = LightningBackends::new(args.config.lightning_backend()?);
+
+    let card_accounts = accounts::list_card_accounts(&pool, None).await?;
+    let orgs = organizations::list_organizations(&pool).await?;
+
+    let nodes = std::iter::once((None, None)).chain(orgs.iter().map(|(org, _)| (Some(org.org_id), Some(org.slug.clone()))));
+    let mut report = Vec::new();
+    for (org_id, org) in nodes {
+        let cards: Vec<&CardAccount> = card_accounts.iter().filter(|a| a.org_id == org_id).collect();
+        let funded_msats: i64 = cards.iter().map(|a| a.funded_msats).sum();
+        let spent_msats: i64 = cards.iter().map(|a| a.spent_msats).sum();
+        let reserved_msats: i64 = cards.iter().map(|a| a.reserved_msats).sum();
+
+        let node_info = match lightning.for_org(&pool, org_id).await {
+            Ok(backend) => backend.get_info().await,
+            Err(e) => Err(e),
+        };
+        let (node_balance_msats, error) = match node_info {
+            Ok(info) => (Some(info.balance_msats), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+
+        report.push(NodeCapital {
+            org,
+            cards: cards.len(),
+            funded_msats,
+            spent_msats,
+            reserved_msats,
+            available_msats: funded_msats - spent_msats - reserved_msats,
+            node_balance_msats,
+            surplus_msats: node_balance_msats.map(|balance| balance as i64 - (funded_msats - spent_msats)),
+            error,
+        });
+    }
+
+    match args.format {
+        OutputFormat::Json => {
+            let mut json = serde_json::json!({ "nodes": report });
+            if args.cards {
+                json["cards"] = serde_json::to_value(&card_accounts)?;
+            }
+            println!("{}", serde_json::to_string_pretty(&json)?);
+        }
+        OutputFormat::Table => {
+            let header = ["NODE", "CARDS", "FUNDED", "SPENT", "RESERVED", "AVAILABLE", "NODE BALANCE", "SURPLUS"];
+            let rows: Vec<[String; 8]> = report
+                .iter()
+                .map(|node| {
+                    [
+                        node.org.clone().unwrap_or_else(|| "server".to_string()),
+                        node.cards.to_string(),
+                        (node.funded_msats / 1000).to_string(),
+                        (node.spent_msats / 1000).to_string(),
+                        (node.reserved_msats / 1000).to_string(),
+                        (node.available_msats / 1000).to_string(),
+                        node.node_balance_msats.map_or_else(|| "error".to_string(), |b| (b / 1000).to_string()),
+                        node.surplus_msats.map_or_else(|| "-".to_string(), |s| (s / 1000).to_string()),
+                    ]
+                })
+                .collect();
+            print!("{}", format_table(&header, &rows));
+
+            for node in &report {
+                if let Some(error) = &node.error {
+                    eprintln!("{}: {}", node.org.as_deref().unwrap_or("server"), error);
+                }
+            }
+
+            if args.cards {
+                println!();
+                let header = ["ID", "NAME", "FUNDED", "SPENT", "RESERVED", "AVAILABLE"];
+                let rows: Vec<[String; 6]> = card_accounts
+                    .iter()
+                    .map(|a| {
+                        [
+                            a.card_id.to_string(),
+                            a.card_name.clone(),
+                            (a.funded_msats / 1000).to_string(),
+                            (a.spent_msats / 1000).to_string(),
+                            (a.reserved_msats / 1000).to_string(),
+                            (a.available_msats() / 1000).to_string(),
+                        ]
+                    })
+                    .collect();
+                print!("{}", format_table(&header, &rows));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn set_user_policy(database: &DatabaseConfig, args: &UserPolicyArgs) -> Result<()> {
+    let user_policy = UserPolicy {
+        max_cards: args.max_cards,
+        default_tx_limit_sats: args.default_tx_limit,
+        default_day_limit_sats: args.default_day_limit,
+        max_tx_limit_sats: args.max_tx_limit,
+        max_day_limit_sats: args.max_day_limit,
+    };
+    let problems = policy::policy_problems(&user_policy);
+    if !problems.is_empty() {
+        bail!("{}", problems.join("; "));
+    }
+
+    let pool = init_pool(database).await?;
+
+    if !users::set_user_policy(&pool, &args.username, &user_policy).await? {
+        bail!("No user named {}", args.username);
+    }
+    println!("Policy of user {} updated", args.username);
+
+    Ok(())
+}
+
+async fn set_user_enabled(database: &DatabaseConfig, args: &UserArgs, enabled: bool) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    if !users::set_user_enabled(&pool, &args.username, enabled).await? {
+        bail!("No user named {}", args.username);
+    }
+    println!("User {} {}", args.username, if enabled { "enabled" } else { "disabled" });
+
+    Ok(())
+}
+
+async fn export_user(database: &DatabaseConfig, args: &UserArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    let Some(user) = users::get_user_by_username(&pool, &args.username).await? else {
+        bail!("No user named {}", args.username);
+    };
+    let export = privacy::export_user(&pool, user.user_id)
+        .await?
+        .ok_or_else(|| anyhow!("No user named {}", args.username))?;
+    println!("{}", serde_json::to_string_pretty(&export)?);
+
+    Ok(())
+}
+
+async fn erase_user(database: &DatabaseConfig, args: &UserArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    let Some(user) = users::get_user_by_username(&pool, &args.username).await? else {
+        bail!("No user named {}", args.username);
+    };
+    match privacy::erase_user(&pool, user.user_id, "cli").await? {
+        Erasure::Erased(card_ids) => {
+            println!("User {} erased, now erased-{}", args.username, user.user_id);
+            println!("Cards erased: {}", card_ids.len());
+        }
+        Erasure::NotFound => bail!("No user named {}", args.username),
+        Erasure::PaymentsInFlight => bail!("A payment of {}'s cards is in flight, try again once it's done", args.username),
+    }
+
+    Ok(())
+}
+
+async fn migrate(database: &DatabaseConfig, args: &MigrateArgs) -> Result<()> {
+    let pool = db::connect(database).await?;
+
+    let pending = db::pending_migrations(&pool).await?;
+    if pending.is_empty() {
+        println!("Database is up to date");
+        return Ok(());
+    }
+
+    for migration in &pending {
+        println!("{:03} {}", migration.version, migration.description);
+    }
+
+    if args.dry_run {
+        println!("{} pending migration(s), nothing applied (dry run)", pending.len());
+        return Ok(());
+    }
+
+    db::run_migrations(&pool).await?;
+    println!("Applied {} migration(s)", pending.len());
+
+    Ok(())
+}
+
+async fn doctor(database: &DatabaseConfig, args: &DoctorArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    let issues = doctor::check(&pool).await?;
+
+    match args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&issues)?),
+        OutputFormat::Table => {
+            let header = ["ISSUE", "DETAILS", "REPAIR"];
+            let rows: Vec<[String; 3]> = issues
+                .iter()
+                .map(|issue| {
+                    let (name, details) = match issue {
+                        Issue::OrphanedPayment { payment_id, card_id } => (
+                            "orphaned payment",
+                            format!("payment {} references missing card {}", payment_id, card_id),
+                        ),
+                        Issue::InvalidKey { card_id, key } => (
+                            "invalid key",
+                            format!("card {} has an invalid {}", card_id, key),
+                        ),
+                        Issue::CounterBehindTaps { card_id, last_counter, max_tap_counter } => (
+                            "counter behind taps",
+                            format!(
+                                "card {} counter {} but accepted a tap with {}",
+                                card_id, last_counter, max_tap_counter
+                            ),
+                        ),
+                        Issue::PaidWithoutInvoice { payment_id, card_id } => (
+                            "paid without invoice",
+                            format!("payment {} of card {} is marked settled", payment_id, card_id),
+                        ),
+                        Issue::SpendMismatch { card_id, recorded_msats, settled_msats } => (
+                            "spend mismatch",
+                            format!(
+                                "card {} spend totals {} msat but settled payments {} msat",
+                                card_id, recorded_msats, settled_msats
+                            ),
+                        ),
+                        Issue::SharedUid { uid, card_ids } => {
+                            let card_ids: Vec<String> = card_ids.iter().map(i64::to_string).collect();
+                            ("shared uid", format!("cards {} all have UID {}", card_ids.join(", "), uid))
+                        }
+                    };
+                    [name.to_string(), details, issue.repair_action().to_string()]
+                })
+                .collect();
+            if !rows.is_empty() {
+                print!("{}", format_table(&header, &rows));
+            }
+        }
+    }
+
+    if issues.is_empty() {
+        eprintln!("No issues found");
+        return Ok(());
+    }
+
+    if !args.repair {
+        bail!("{} issue(s) found, run with --repair to fix them", issues.len());
+    }
+
+    doctor::repair(&pool, &issues, "cli").await?;
+    eprintln!("Repaired {} issue(s)", issues.len());
+
+    Ok(())
+}
+
+fn decode(args: &DecodeArgs) -> Result<()> {
+    let (p, c) = match &args.url {
+        Some(url) => {
+            let url = reqwest::Url::parse(url)?;
+            let param = |name: &str| {
+                url.query_pairs()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.into_owned())
+                    .ok_or_else(|| anyhow!("URL has no {} parameter", name))
+            };
+            (param("p")?, param("c")?)
+        }
+        None => (
+            args.p.clone().unwrap_or_default(),
+            args.c.clone().unwrap_or_default(),
+        ),
+    };
+
+    match validate_card_pure(&args.k1, &args.k2, &p, &c) {
+        Ok(result) => {
+            println!("UID:     {}", result.uid);
+            println!("Counter: {}", result.counter.value());
+            println!("CMAC:    valid");
+            Ok(())
+        }
+        Err(reason) => {
+            // Still show what K1 decrypts to, a plausible UID with a bad CMAC points at K2
+            if let Some((uid, counter)) = decrypt_only(&args.k1, &p) {
+                println!("UID:     {}", uid);
+                println!("Counter: {}", counter);
+            }
+            println!("CMAC:    invalid");
+            bail!(reason)
+        }
+    }
+}
+
+async fn verify_receipt(database: &DatabaseConfig, args: &VerifyReceiptArgs) -> Result<()> {
+    let contents = match args.file.to_str() {
+        Some("-") => std::io::read_to_string(std::io::stdin())?,
+        _ => std::fs::read_to_string(&args.file)?,
+    };
+    let signed: SignedReceipt = serde_json::from_str(&contents)?;
+    let public_keys = match &args.public_key {
+        Some(key) => vec![key.clone()],
+        None => {
+            let pool = init_pool(database).await?;
+            db::receipts::list_keys(&pool).await?.into_iter().map(|key| key.public_key).collect()
+        }
+    };
+
+    let receipt = &signed.receipt;
+    println!("Issuer:       {}", receipt.issuer);
+    println!("Amount:       {} sats", receipt.amount_msats / 1000);
+    println!("Settled at:   {}", receipt.settled_at);
+    println!("Payment hash: {}", receipt.payment_hash);
+    println!("Public key:   {}", signed.public_key);
+    match signed_receipts::verify(&signed, &public_keys) {
+        Ok(()) => {
+            println!("Receipt:      valid");
+            Ok(())
+        }
+        Err(reason) => {
+            println!("Receipt:      invalid");
+            bail!(reason)
+        }
+    }
+}
+
+fn decrypt_only(k1_hex: &str, p_hex: &str) -> Option<(String, u32)> {
+    let k1 = AesKey::from_hex(k1_hex).ok()?;
+    let decrypted = aes_decrypt(&k1, &decode_hex(p_hex).ok()?);
+    let (uid, counter) = parse_decrypted_data(&decrypted).ok()?;
+    Some((uid.to_string(), counter.value()))
+}
+
+#[derive(Debug, Serialize)]
+struct TestVector {
+    k0: AesKey,
+    k1: AesKey,
+    k2: AesKey,
+    k3: AesKey,
+    k4: AesKey,
+    uid: CardUid,
+    taps: Vec<TestTap>,
+}
+
+#[derive(Debug, Serialize)]
+struct TestTap {
+    counter: u32,
+    p: String,
+    c: String,
+}
+
+fn gen_test_vectors(args: &GenTestVectorsArgs) -> Result<()> {
+    let key_or_random = |key: &Option<String>| match key {
+        Some(hex) => AesKey::from_hex(hex),
+        None => Ok(AesKey::generate()),
+    };
+
+    let uids = if args.uids.is_empty() {
+        vec![CardUid::from_bytes(&rand::random::<[u8; 7]>())?]
+    } else {
+        args.uids
+            .iter()
+            .map(|uid| CardUid::from_hex(uid))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    let mut vectors = Vec::new();
+    for uid in uids {
+        let k1 = key_or_random(&args.k1)?;
+        let k2 = key_or_random(&args.k2)?;
+
+        let taps = args
+            .counters
+            .iter()
+            .map(|&counter| {
+                let (p, c) = generate_sun(&k1, &k2, &uid, &Counter::new(counter));
+                TestTap {
+                    counter,
+                    p: hex::encode_upper(p),
+                    c: hex::encode_upper(c),
+                }
+            })
+            .collect();
+
+        vectors.push(TestVector {
+            k0: AesKey::generate(),
+            k1,
+            k2,
+            k3: AesKey::generate(),
+            k4: AesKey::generate(),
+            uid,
+            taps,
+        });
+    }
+
+    println!("{}", serde_json::to_string_pretty(&vectors)?);
+
+    Ok(())
+}
+
+/// Print a URL for the card's next tap, only the URL goes to stdout so it can be passed to curl
+async fn simulate_tap(database: &DatabaseConfig, args: &SimulateTapArgs) -> Result<()> {
+    let (k1, k2, card_uid, last_counter) = match (&args.k1, &args.k2) {
+        (Some(k1), Some(k2)) => (k1.clone(), k2.clone(), String::new(), 0),
+        _ => {
+            let pool = init_pool(database).await?;
+            let card = queries::get_card_by_id(&pool, args.card_id)
+                .await?
+                .ok_or_else(|| anyhow!("card {} not found", args.card_id))?;
+            (card.k1_decrypt_key, card.k2_cmac_key, card.uid, card.last_counter)
+        }
+    };
+
+    let uid = match args.uid.as_deref().or((!card_uid.is_empty()).then_some(card_uid.as_str())) {
+        Some(uid) => CardUid::from_hex(uid)?,
+        None => {
+            // An unused card takes the UID of its first tap
+            let uid = CardUid::from_bytes(&rand::random::<[u8; 7]>())?;
+            eprintln!("Card has no UID yet, using {}", uid);
+            uid
+        }
+    };
+
+    let counter = match args.counter {
+        Some(counter) => counter,
+        None => u32::try_from(last_counter + 1)?,
+    };
+    if counter > Counter::MAX {
+        bail!("The card's counter is exhausted, it can't tap again");
+    }
+
+    let (p, c) = generate_sun(
+        &AesKey::from_hex(&k1)?,
+        &AesKey::from_hex(&k2)?,
+        &uid,
+        &Counter::new(counter),
+    );
+
+    println!(
+        "{}/ln?card_id={}&p={}&c={}",
+        args.server.trim_end_matches('/'),
+        args.card_id,
+        hex::encode_upper(p),
+        hex::encode_upper(c)
+    );
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct RotationPayload {
+    card_id: i64,
+    card_name: String,
+    registration_url: String,
+    valid_until: String,
+    previous_keys: PreviousKeys,
+}
+
+#[derive(Debug, Serialize)]
+struct PreviousKeys {
+    k0: String,
+    k1: String,
+    k2: String,
+    k3: String,
+    k4: String,
+}
+
+async fn seed_database(database: &DatabaseConfig, args: &SeedArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+    let Some(seeded) = seed::seed(&pool, &Random::from_config(&args.config)?).await? else {
+        bail!("The database already has the sample data");
+    };
+
+    let urls = args.config.urls();
+    let rows: Vec<[String; 3]> = seeded
+        .cards
+        .iter()
+        .map(|card| {
+            let registration = format!("{}?a={}", urls.registration_base(), card.one_time_code);
+            [card.card_id.to_string(), card.card_name.clone(), registration]
+        })
+        .collect();
+    print!("{}", format_table(&["ID", "NAME", "REGISTRATION URL"], &rows));
+    println!("\n{} settled payments\n\nTaps of the test vector card, in this order:", seeded.payments);
+    for url in seed::tap_urls(&args.server, seeded.test_vector_card().card_id) {
+        println!("{}", url);
+    }
+
+    Ok(())
+}
+
+async fn replay(args: &ReplayArgs) -> Result<()> {
+    let contents = std::fs::read_to_string(&args.file)?;
+    let records = capture::read_log(&contents)?;
+    let replayed = capture::replay(&reqwest::Client::new(), &args.server, &records, args.realtime).await?;
+
+    let rows: Vec<[String; 4]> = replayed
+        .iter()
+        .enumerate()
+        .map(|(i, request)| {
+            let mark = if request.differs() { "DIFF" } else { "same" };
+            [
+                (i + 1).to_string(),
+                request.recorded.path.clone(),
+                request.recorded.outcome(),
+                format!("{} {}", mark, request.replayed.outcome()),
+            ]
+        })
+        .collect();
+    print!("{}", format_table(&["#", "PATH", "RECORDED", "REPLAYED"], &rows));
+
+    let differing = replayed.iter().filter(|request| request.differs()).count();
+    if differing > 0 {
+        bail!("{} of {} requests got a different answer", differing, replayed.len());
+    }
+    eprintln!("All {} requests got the same answer", replayed.len());
+
+    Ok(())
+}
+
+async fn load_test(database: &DatabaseConfig, args: &LoadTestArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+    let random = Random::default();
+
+    // Limits high enough that only the server's speed decides
+    let mut cards = Vec::new();
+    for i in 1..=args.cards {
+        let (k1, k2) = (random.aes_key()?, random.aes_key()?);
+        let uid = CardUid::from_bytes(&random.bytes::<7>()?)?;
+        let zero = AesKey::from_bytes([0; 16]).to_string();
+        let card_id = queries::insert_card(
+            &pool, &uid.to_string(), &zero, &k1.to_string(), &k2.to_string(), &zero, &zero,
+            &format!("Load test {}", i), 1_000_000, 1_000_000_000, true, false, &random.hex::<16>()?, None, None,
+            chrono::Utc::now(),
+        )
+        .await?;
+        queries::mark_one_time_code_used(&pool, card_id).await?;
+        cards.push(LoadCard { card_id, k1, k2, uid });
+    }
+    let card_ids: Vec<i64> = cards.iter().map(|card| card.card_id).collect();
+    eprintln!("Created cards {} to {}", card_ids[0], card_ids[card_ids.len() - 1]);
+
+    let invoices = Arc::new(MockLightning::default().with_network(args.network));
+    let report = loadtest::run(&reqwest::Client::new(), &args.server, cards, args.withdrawals, invoices).await;
+    for card_id in card_ids {
+        queries::set_card_enabled(&pool, card_id, false).await?;
+    }
+    let report = report?;
+
+    let millis = |latency: Option<std::time::Duration>| {
+        latency.map(|latency| format!("{:.1}", latency.as_secs_f64() * 1000.0)).unwrap_or_else(|| "-".to_string())
+    };
+    let rows: Vec<[String; 6]> = [("/ln", &report.taps), ("/ln/callback", &report.callbacks)]
+        .into_iter()
+        .map(|(path, latencies)| {
+            [
+                path.to_string(),
+                latencies.len().to_string(),
+                millis(latencies.percentile(50.0)),
+                millis(latencies.percentile(90.0)),
+                millis(latencies.percentile(99.0)),
+                millis(latencies.percentile(100.0)),
+            ]
+        })
+        .collect();
+    print!("{}", format_table(&["PATH", "REQUESTS", "P50 MS", "P90 MS", "P99 MS", "MAX MS"], &rows));
+
+    let rows: Vec<[String; 2]> =
+        report.outcomes.iter().map(|(outcome, count)| [outcome.clone(), count.to_string()]).collect();
+    println!();
+    print!("{}", format_table(&["OUTCOME", "COUNT"], &rows));
+    println!(
+        "\n{} withdrawals in {:.1}s, {:.1} per second",
+        report.completed(),
+        report.elapsed.as_secs_f64(),
+        report.completed() as f64 / report.elapsed.as_secs_f64()
+    );
+
+    if report.completed() < args.withdrawals {
+        bail!("{} of {} withdrawals failed", args.withdrawals - report.completed(), args.withdrawals);
+    }
+
+    Ok(())
+}
+
+async fn rotate_keys(database: &DatabaseConfig, args: &RotateKeysArgs) -> Result<()> {
+    let selector = CardSelector {
+        card_ids: (!args.card_ids.is_empty()).then(|| args.card_ids.clone()),
+        tag: args.tag.clone(),
+    };
+    if !selector.is_valid() {
+        bail!("select cards with --card-id or --tag");
+    }
+    if args.grace_hours <= 0 {
+        bail!("--grace-hours must be positive");
+    }
+
+    let pool = init_pool(database).await?;
+
+    let random = Random::from_device(args.config.random_device.as_deref())?;
+    let rotated = rotation::rotate(&pool, &selector, chrono::Duration::hours(args.grace_hours), "cli", &random).await?;
+    if rotated.is_empty() {
+        bail!("no cards matched");
+    }
+
+    let mut payloads = Vec::with_capacity(rotated.len());
+    for card in rotated {
+        let domain = organizations::get_card_domain(&pool, card.card_id).await?;
+        payloads.push(RotationPayload {
+            card_id: card.card_id,
+            card_name: card.card_name,
+            registration_url: format!(
+                "{}?a={}",
+                args.config.urls_on(domain.as_deref()).registration_base(),
+                card.one_time_code
+            ),
+            valid_until: card.valid_until,
+            previous_keys: PreviousKeys {
+                k0: card.previous_keys.k0_auth_key,
+                k1: card.previous_keys.k1_decrypt_key,
+                k2: card.previous_keys.k2_cmac_key,
+                k3: card.previous_keys.k3,
+                k4: card.previous_keys.k4,
+            },
+        });
+    }
+
+    match args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&payloads)?),
+        OutputFormat::Table => {
+            let header = ["ID", "NAME", "OLD KEYS VALID UNTIL", "REGISTRATION URL"];
+            let rows: Vec<[String; 4]> = payloads
+                .iter()
+                .map(|payload| {
+                    [
+                        payload.card_id.to_string(),
+                        payload.card_name.clone(),
+                        payload.valid_until.clone(),
+                        payload.registration_url.clone(),
+                    ]
+                })
+                .collect();
+            print!("{}", format_table(&header, &rows));
+        }
+    }
+
+    Ok(())
+}
+
+async fn create_backup(database: &DatabaseConfig, args: &BackupArgs) -> Result<()> {
+    let pool = init_pool(database).await?;
+
+    let bucket = Bucket::from_config(&args.s3, reqwest::Client::new())?;
+    if args.output.is_none() && bucket.is_none() {
+        bail!("Give a file to write the backup to, or --s3-bucket to upload it");
+    }
+
+    let passphrase = backup_passphrase(args.passphrase_file.as_deref(), true)?;
+    // The unencrypted snapshot stays next to the backup, not in a shared temporary directory
+    let snapshot_dir = match args.output.as_deref().and_then(std::path::Path::parent) {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        Some(_) => PathBuf::from("."),
+        None => std::env::temp_dir(),
+    };
+    let random = Random::from_device(args.random.random_device.as_deref())?;
+    let data = backup::create(&pool, &random, &args.domain, &passphrase, &snapshot_dir).await?;
+    if let Some(output) = &args.output {
+        backup::write_private(output, &data, true)?;
+        eprintln!("Wrote {} bytes to {}", data.len(), output.display());
+    }
+    if let Some(bucket) = &bucket {
+        let size = data.len();
+        let (key, deleted) = backup::upload(bucket, &args.s3, data).await?;
+        eprintln!("Uploaded {} bytes as {}", size, key);
+        for key in deleted {
+            eprintln!("Deleted old backup {}", key);
+        }
+    }
+
+    Ok(())
+}
+
+/// The backup passphrase from `--passphrase-file`, `BACKUP_PASSPHRASE` or a prompt
+///
+/// It's never taken from the command line, where other users could read it
+/// from the process list. A new passphrase is asked for twice.
+fn backup_passphrase(file: Option<&std::path::Path>, confirm: bool) -> Result<String> {
+    let passphrase = match (file, std::env::var("BACKUP_PASSPHRASE")) {
+        (Some(file), _) => std::fs::read_to_string(file)?.trim_end_matches(['\r', '\n']).to_string(),
+        (None, Ok(passphrase)) => passphrase,
+        (None, Err(_)) => {
+            let passphrase = rpassword::prompt_password("Backup passphrase: ")?;
+            if confirm && rpassword::prompt_password("Repeat the passphrase: ")? != passphrase {
+                bail!("The passphrases don't match");
+            }
+            passphrase
+        }
+    };
+    if passphrase.is_empty() {
+        bail!("The backup passphrase is empty");
+    }
+
+    Ok(passphrase)
+}
+
+async fn restore_backup(database: &DatabaseConfig, args: &RestoreArgs) -> Result<()> {
+    let options = SqliteConnectOptions::from_str(&database.database_url)?;
+    let path = options.get_filename();
+
+    let data = match (&args.input, &args.from_s3) {
+        (Some(input), _) => std::fs::read(input)?,
+        (None, Some(key)) => {
+            let bucket = Bucket::from_config(&args.s3, reqwest::Client::new())?
+                .ok_or_else(|| anyhow!("--from-s3 needs --s3-bucket"))?;
+            let (key, data) = backup::download(&bucket, &args.s3, key).await?;
+            eprintln!("Fetched {} ({} bytes)", key, data.len());
+            data
+        }
+        (None, None) => bail!("Give a backup file or --from-s3"),
+    };
+    let passphrase = backup_passphrase(args.passphrase_file.as_deref(), false)?;
+    let manifest = backup::restore(&data, &passphrase, &args.domain, path, args.allow_config_change)?;
+
+    // Bring an older snapshot up to date and make sure it's an intact database
+    let check = async {
+        let pool = init_pool(database).await?;
+        let result: String = sqlx::query_scalar("PRAGMA integrity_check").fetch_one(&pool).await?;
+        pool.close().await;
+        if result != "ok" {
+            bail!("Integrity check failed: {}", result);
+        }
+        Ok(())
+    };
+    if let Err(e) = check.await {
+        std::fs::remove_file(path)?;
+        return Err(e);
+    }
+
+    eprintln!(
+        "Restored backup from {} (server {}, schema {}) to {}",
+        manifest.created_at,
+        manifest.server_version,
+        manifest.schema_version,
+        path.display()
+    );
+
+    Ok(())
+}
+
+async fn ledger_export(database: &DatabaseConfig, args: &LedgerArgs) -> Result<()> {
+    let to = args.to.unwrap_or_else(|| chrono::Utc::now().date_naive());
+    let from = args.from.unwrap_or(to - chrono::Duration::days(29));
+    if from > to {
+        bail!("--from must not be after --to");
+    }
+    let accounts = LedgerAccounts {
+        node: args.node_account.clone(),
+        cards: args.card_account.clone(),
+        fees: args.fee_account.clone(),
+        service_fees: args.service_fee_account.clone(),
+    };
+    if let Some(account) = accounts.invalid() {
+        bail!("Invalid account name {:?}, expected capitalized components like Assets:Lightning", account);
+    }
+
+    let pool = init_pool(database).await?;
+    let payments = db::ledger::settled_payments(&pool, from, to).await?;
+    print!("{}", ledger::render(args.format, &accounts, from, to, &payments));
+
+    Ok(())
+}
+
+async fn fiat_report(database: &DatabaseConfig, args: &FiatReportArgs) -> Result<()> {
+    use std::io::Write;
+
+    let year = args.year.unwrap_or_else(|| chrono::Datelike::year(&chrono::Utc::now()));
+    let (from, to) = reports::period(year, args.month).ok_or_else(|| anyhow!("--month must be 1 to 12"))?;
+    if args.payments && matches!(args.format, FiatReportFormat::Pdf) {
+        bail!("PDF reports are monthly summaries, leave out --payments");
+    }
+
+    let pool = init_pool(database).await?;
+    let payments = db::fiat::settled_payments(&pool, from, to).await?;
+
+    match (args.format, args.payments) {
+        (FiatReportFormat::Json, true) => println!("{}", serde_json::to_string_pretty(&payments)?),
+        (FiatReportFormat::Json, false) => println!("{}", serde_json::to_string_pretty(&reports::monthly(&payments))?),
+        (FiatReportFormat::Csv, true) => print!("{}", reports::payments_csv(&payments)),
+        (FiatReportFormat::Csv, false) => print!("{}", reports::summary_csv(&reports::monthly(&payments))),
+        (FiatReportFormat::Pdf, _) => {
+            let doc = reports::summary_pdf(&format!("Withdrawals {} to {}", from, to), &reports::monthly(&payments));
+            std::io::stdout().write_all(&doc.to_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn usage_stats(database: &DatabaseConfig, args: &StatsArgs) -> Result<()> {
+    let to = args.to.unwrap_or_else(|| chrono::Utc::now().date_naive());
+    let from = args.from.unwrap_or(to - chrono::Duration::days(29));
+    if from > to {
+        bail!("--from must not be after --to");
+    }
+
+    let pool = init_pool(database).await?;
+    let report = stats::usage_report(&pool, from, to).await?;
+
+    match args.format {
+        ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        ReportFormat::Csv => {
+            let header = ["card_id", "card_name", "payments", "failures", "volume_msats"];
+            let rows: Vec<Vec<String>> = report
+                .cards
+                .iter()
+                .map(|card| {
+                    vec![
+                        card.card_id.to_string(),
+                        card.card_name.clone(),
+                        card.payments.to_string(),
+                        card.failures.to_string(),
+                        card.volume_msats.to_string(),
+                    ]
+                })
+                .collect();
+            print!("{}", format_csv(&header, &rows));
+        }
+        ReportFormat::Table => {
+            println!("Period:       {} to {}", report.from, report.to);
+            println!("Payments:     {}", report.total_payments);
+            println!("Failures:     {} ({:.1}%)", report.total_failures, report.failure_rate * 100.0);
+            println!("Volume:       {} sats", report.total_volume_msats / 1000);
+
+            if !report.cards.is_empty() {
+                println!();
+                let header = ["ID", "NAME", "PAYMENTS", "FAILURES", "VOLUME SATS"];
+                let rows: Vec<[String; 5]> = report
+                    .cards
+                    .iter()
+                    .map(|card| {
+                        [
+                            card.card_id.to_string(),
+                            card.card_name.clone(),
+                            card.payments.to_string(),
+                            card.failures.to_string(),
+                            (card.volume_msats / 1000).to_string(),
+                        ]
+                    })
+                    .collect();
+                print!("{}", format_table(&header, &rows));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn import_lnbits(database: &DatabaseConfig, args: &ImportLnbitsArgs) -> Result<()> {
+    let cards = match (&args.source.json, &args.source.sqlite) {
+        (Some(path), _) => import::read_json(path)?,
+        (None, Some(path)) => import::read_sqlite(path).await?,
+        (None, None) => bail!("pass --json or --sqlite"),
+    };
+
+    let pool = init_pool(database).await?;
+    let plan = import::plan(&pool, cards).await?;
+
+    if !plan.conflicts.is_empty() {
+        let header = ["NAME", "UID", "CONFLICT"];
+        let rows: Vec<[String; 3]> = plan
+            .conflicts
+            .iter()
+            .map(|c| [c.card_name.clone(), c.uid.clone(), c.reason.clone()])
+            .collect();
+        print!("{}", format_table(&header, &rows));
+    }
+
+    if args.dry_run {
+        println!(
+            "{} card(s) would be imported, {} conflict(s) (dry run)",
+            plan.cards.len(),
+            plan.conflicts.len()
+        );
+        return Ok(());
+    }
+
+    if !plan.conflicts.is_empty() && !args.skip_conflicts {
+        bail!(
+            "{} conflict(s), nothing imported; pass --skip-conflicts to import the other cards",
+            plan.conflicts.len()
+        );
+    }
+
+    let card_ids = import::apply(&pool, &plan.cards, "cli").await?;
+    println!("Imported {} card(s)", card_ids.len());
+
+    Ok(())
+}
+
+/// Render rows as left-aligned columns padded to the widest cell
+fn format_table<const N: usize>(header: &[&str; N], rows: &[[String; N]]) -> String {
+    let mut widths = header.map(|h| h.chars().count());
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_row = |cells: Vec<&str>| -> String {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        format!("{}\n", line.join("  ").trim_end())
+    };
+
+    let mut out = format_row(header.to_vec());
+    for row in rows {
+        out.push_str(&format_row(row.iter().map(String::as_str).collect()));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_table() {
+        let rows = [
+            ["1".to_string(), "Alice".to_string()],
+            ["10".to_string(), "Bo".to_string()],
+        ];
+        assert_eq!(format_table(&["ID", "NAME"], &rows), "ID  NAME\n1   Alice\n10  Bo\n");
+    }
+
+    #[test]
+    fn test_format_csv() {
+        let rows = vec![vec!["1".to_string(), "Bar, \"The\" Card".to_string()]];
+        assert_eq!(format_csv(&["id", "name"], &rows), "id,name\n1,\"Bar, \"\"The\"\" Card\"\n");
+    }
+
+    #[test]
+    fn test_dev_config() {
+        let args = DevArgs { port: 9090, serve_args: vec![] };
+        let config = args.config().unwrap();
+        assert_eq!(config.domain, "localhost:9090");
+        assert_eq!(config.host, "127.0.0.1");
+        assert!(config.skip_domain_resolution);
+        assert_eq!(config.urls().callback_url(), "http://localhost:9090/ln/callback");
+
+        let serve_args = ["--lightning-backend=mock:script=fail", "--async-payments"].map(String::from).to_vec();
+        let config = DevArgs { port: 9090, serve_args }.config().unwrap();
+        assert_eq!(config.lightning_backend, "mock:script=fail");
+        assert!(config.async_payments);
+    }
+}
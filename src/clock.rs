@@ -0,0 +1,62 @@
+//! The time expiry checks, daily limits, session TTLs and schedules go by
+//!
+//! Handlers and workers ask the [`Clock`] in `AppState` and bind the time into
+//! their queries instead of using SQLite's `datetime('now')`, so tests can
+//! move time forward instead of waiting for it.
+
+use chrono::{DateTime, Utc};
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The system's time
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// `at` in the format of SQLite's `CURRENT_TIMESTAMP`, to compare with stored times
+pub fn sql_timestamp(at: DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// A clock that only moves when told to, for tests
+#[cfg(test)]
+pub struct ManualClock(std::sync::Mutex<DateTime<Utc>>);
+
+#[cfg(test)]
+impl ManualClock {
+    pub fn new(at: DateTime<Utc>) -> Self {
+        Self(std::sync::Mutex::new(at))
+    }
+
+    pub fn advance(&self, by: chrono::Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 3, 1, 23, 59, 30).unwrap());
+        assert_eq!(sql_timestamp(clock.now()), "2025-03-01 23:59:30");
+
+        clock.advance(chrono::Duration::seconds(45));
+        assert_eq!(sql_timestamp(clock.now()), "2025-03-02 00:00:15");
+    }
+}
@@ -0,0 +1,155 @@
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+
+use crate::db::{audit, models::CardAccount};
+
+/// Allocate node capital to a card, crediting the balance of prepaid cards too
+///
+/// Returns false if the card doesn't exist.
+pub async fn fund_card(pool: &Pool<Sqlite>, card_id: i64, amount_msats: i64, actor: &str) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query(
+        "UPDATE cards SET funded_msats = funded_msats + ?,
+                          balance_msats = balance_msats + CASE WHEN balance_mode THEN ? ELSE 0 END
+         WHERE card_id = ?"
+    )
+    .bind(amount_msats)
+    .bind(amount_msats)
+    .bind(card_id)
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    audit::record(
+        &mut tx,
+        actor,
+        "fund_card",
+        &serde_json::json!({ "card_id": card_id, "amount_msats": amount_msats }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// Sub-accounts of all cards, or those of one organization's node
+///
+/// `Some(None)` selects the cards on the server's own node.
+pub async fn list_card_accounts(pool: &Pool<Sqlite>, org_id: Option<Option<i64>>) -> Result<Vec<CardAccount>> {
+    let accounts = sqlx::query_as::<_, CardAccount>(
+        "SELECT cards.card_id, cards.card_name, cards.org_id, cards.funded_msats, cards.spent_msats,
+                COALESCE(SUM(card_payments.amount_msats), 0) AS reserved_msats
+         FROM cards
+         LEFT JOIN card_payments ON card_payments.card_id = cards.card_id
+             AND card_payments.status IN ('invoice_attached', 'in_flight')
+         WHERE ? OR cards.org_id IS ?
+         GROUP BY cards.card_id
+         ORDER BY cards.card_id"
+    )
+    .bind(org_id.is_none())
+    .bind(org_id.flatten())
+    .fetch_all(pool)
+    .await?;
+
+    Ok(accounts)
+}
+
+/// Capital on an organization's node, or the server's, that no card has spent or reserved
+///
+/// Negative once withdrawals exceed what was funded.
+pub async fn available_capital(pool: &Pool<Sqlite>, org_id: Option<i64>) -> Result<i64> {
+    let available = sqlx::query_scalar(
+        "SELECT COALESCE(SUM(funded_msats - spent_msats), 0) - COALESCE(
+             (SELECT SUM(card_payments.amount_msats) FROM card_payments
+              JOIN cards ON cards.card_id = card_payments.card_id
+              WHERE cards.org_id IS ? AND card_payments.status IN ('invoice_attached', 'in_flight')), 0
+         )
+         FROM cards WHERE org_id IS ?"
+    )
+    .bind(org_id)
+    .bind(org_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(available)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{organizations, queries, run_migrations};
+
+    async fn insert_card(pool: &Pool<Sqlite>, code: &str, balance_mode: bool, org_id: Option<i64>) -> i64 {
+        let key = "00000000000000000000000000000000";
+        queries::insert_card(pool, "", key, key, key, key, key, code, 1000, 10000, true, balance_mode, code, None, org_id, chrono::Utc::now())
+            .await
+            .unwrap()
+    }
+
+    async fn payment(pool: &Pool<Sqlite>, card_id: i64, status: &str, amount_msats: i64) {
+        sqlx::query("INSERT INTO card_payments (card_id, k1, status, amount_msats) VALUES (?, ?, ?, ?)")
+            .bind(card_id)
+            .bind(format!("{}-{}-{}", card_id, status, amount_msats))
+            .bind(status)
+            .bind(amount_msats)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fund_card() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let prepaid = insert_card(&pool, "prepaid", true, None).await;
+        let limits_only = insert_card(&pool, "limits", false, None).await;
+
+        assert!(fund_card(&pool, prepaid, 5_000_000, "test").await.unwrap());
+        assert!(fund_card(&pool, limits_only, 3_000_000, "test").await.unwrap());
+        assert!(!fund_card(&pool, limits_only + 1, 1_000_000, "test").await.unwrap());
+
+        // Only prepaid cards get the funding as balance
+        let pool = &pool;
+        let balance = |card_id| async move { queries::get_card_by_id(pool, card_id).await.unwrap().unwrap().balance_msats };
+        assert_eq!(balance(prepaid).await, 5_000_000);
+        assert_eq!(balance(limits_only).await, 0);
+        let funded: Vec<i64> = list_card_accounts(pool, None).await.unwrap().iter().map(|account| account.funded_msats).collect();
+        assert_eq!(funded, vec![5_000_000, 3_000_000]);
+    }
+
+    #[tokio::test]
+    async fn test_available_capital() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let org_id = organizations::create_organization(&pool, "shop", "Shop", "mock", None).await.unwrap();
+        let own = insert_card(&pool, "own", false, None).await;
+        let shop = insert_card(&pool, "shop", false, org_id).await;
+        fund_card(&pool, own, 10_000_000, "test").await.unwrap();
+        fund_card(&pool, shop, 1_000_000, "test").await.unwrap();
+        sqlx::query("UPDATE cards SET spent_msats = 2000000 WHERE card_id = ?").bind(own).execute(&pool).await.unwrap();
+
+        // Withdrawals being paid count against the capital, finished ones only through the spend
+        payment(&pool, own, "invoice_attached", 1_000_000).await;
+        payment(&pool, own, "in_flight", 500_000).await;
+        payment(&pool, own, "failed", 4_000_000).await;
+        payment(&pool, own, "settled", 2_000_000).await;
+        payment(&pool, shop, "in_flight", 1_500_000).await;
+
+        assert_eq!(available_capital(&pool, None).await.unwrap(), 6_500_000);
+        // Overspent, another node's capital doesn't make up for it
+        assert_eq!(available_capital(&pool, org_id).await.unwrap(), -500_000);
+
+        let accounts = list_card_accounts(&pool, Some(None)).await.unwrap();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!((accounts[0].card_id, accounts[0].reserved_msats), (own, 1_500_000));
+        assert_eq!(accounts[0].available_msats(), 6_500_000);
+        let accounts = list_card_accounts(&pool, Some(org_id)).await.unwrap();
+        assert_eq!((accounts[0].card_id, accounts[0].available_msats()), (shop, -500_000));
+        assert_eq!(list_card_accounts(&pool, None).await.unwrap().len(), 2);
+    }
+}
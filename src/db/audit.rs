@@ -0,0 +1,22 @@
+use sqlx::SqliteConnection;
+use anyhow::Result;
+
+/// Append an entry to the audit log
+///
+/// Takes a connection so the entry can be written in the same transaction
+/// as the change it describes.
+pub async fn record(
+    conn: &mut SqliteConnection,
+    actor: &str,
+    action: &str,
+    details: &serde_json::Value,
+) -> Result<()> {
+    sqlx::query("INSERT INTO audit_log (actor, action, details) VALUES (?, ?, ?)")
+        .bind(actor)
+        .bind(action)
+        .bind(details.to_string())
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,284 @@
+use sqlx::{Pool, QueryBuilder, Sqlite, SqliteConnection};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::db::audit;
+
+/// Which cards a bulk action applies to; exactly one of the fields must be set
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CardSelector {
+    pub card_ids: Option<Vec<i64>>,
+    pub tag: Option<String>,
+}
+
+impl CardSelector {
+    pub fn is_valid(&self) -> bool {
+        match (&self.card_ids, &self.tag) {
+            (Some(ids), None) => !ids.is_empty(),
+            (None, Some(tag)) => !tag.trim().is_empty(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum BulkAction {
+    Enable,
+    Disable,
+    /// Update the limits that are set, leaving the others unchanged
+    SetLimits {
+        tx_limit_sats: Option<i64>,
+        day_limit_sats: Option<i64>,
+    },
+}
+
+impl BulkAction {
+    pub fn is_valid(&self) -> bool {
+        match self {
+            BulkAction::SetLimits { tx_limit_sats, day_limit_sats } => {
+                (tx_limit_sats.is_some() || day_limit_sats.is_some())
+                    && tx_limit_sats.is_none_or(|limit| limit > 0)
+                    && day_limit_sats.is_none_or(|limit| limit > 0)
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Resolve `selector` to the ids of existing cards
+pub async fn select_card_ids(conn: &mut SqliteConnection, selector: &CardSelector) -> Result<Vec<i64>> {
+    let card_ids: Vec<i64> = match (&selector.card_ids, &selector.tag) {
+        (Some(ids), _) => {
+            let mut query = QueryBuilder::<Sqlite>::new("SELECT card_id FROM cards WHERE card_id IN (");
+            let mut separated = query.separated(", ");
+            for id in ids {
+                separated.push_bind(*id);
+            }
+            separated.push_unseparated(") ORDER BY card_id");
+
+            query.build_query_scalar().fetch_all(&mut *conn).await?
+        }
+        (None, Some(tag)) => {
+            sqlx::query_scalar("SELECT card_id FROM card_tags WHERE tag = ? ORDER BY card_id")
+                .bind(tag.trim())
+                .fetch_all(&mut *conn)
+                .await?
+        }
+        (None, None) => Vec::new(),
+    };
+
+    Ok(card_ids)
+}
+
+/// Apply `action` to all selected cards in one transaction and audit it
+///
+/// Returns the ids of the affected cards.
+pub async fn apply(
+    pool: &Pool<Sqlite>,
+    selector: &CardSelector,
+    action: &BulkAction,
+    actor: &str,
+) -> Result<Vec<i64>> {
+    let mut tx = pool.begin().await?;
+
+    let card_ids = select_card_ids(&mut tx, selector).await?;
+
+    for card_id in &card_ids {
+        match action {
+            // Enabling a lost card means it was found
+            BulkAction::Enable => {
+                sqlx::query("UPDATE cards SET enabled = 1, holder_disabled = 0, reported_lost_at = NULL WHERE card_id = ?")
+                    .bind(card_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            BulkAction::Disable => {
+                sqlx::query("UPDATE cards SET enabled = 0, holder_disabled = 0 WHERE card_id = ?")
+                    .bind(card_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            BulkAction::SetLimits { tx_limit_sats, day_limit_sats } => {
+                sqlx::query(
+                    "UPDATE cards SET tx_limit_sats = COALESCE(?, tx_limit_sats),
+                     day_limit_sats = COALESCE(?, day_limit_sats) WHERE card_id = ?"
+                )
+                .bind(tx_limit_sats)
+                .bind(day_limit_sats)
+                .bind(card_id)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+    }
+
+    audit::record(
+        &mut tx,
+        actor,
+        "bulk_update",
+        &serde_json::json!({
+            "selector": selector,
+            "action": action,
+            "card_ids": card_ids,
+        }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(card_ids)
+}
+
+/// Enable or disable one card for its holder and audit it like a bulk update
+///
+/// Holders can only re-enable a card they disabled themselves, not one an
+/// operator disabled, that was replaced or reported lost. Returns whether the
+/// card ends up in the requested state.
+pub async fn apply_as_holder(pool: &Pool<Sqlite>, card_id: i64, enabled: bool, actor: &str) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+
+    let applied = if enabled {
+        sqlx::query(
+            "UPDATE cards SET enabled = 1, holder_disabled = 0
+             WHERE card_id = ? AND (enabled = 1 OR (holder_disabled = 1 AND reported_lost_at IS NULL))"
+        )
+        .bind(card_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected()
+            > 0
+    } else {
+        // A card that is already disabled stays disabled by whoever did it
+        sqlx::query("UPDATE cards SET enabled = 0, holder_disabled = 1 WHERE card_id = ? AND enabled = 1")
+            .bind(card_id)
+            .execute(&mut *tx)
+            .await?;
+        true
+    };
+    if !applied {
+        return Ok(false);
+    }
+
+    let action = if enabled { BulkAction::Enable } else { BulkAction::Disable };
+    audit::record(
+        &mut tx,
+        actor,
+        "bulk_update",
+        &serde_json::json!({
+            "selector": CardSelector { card_ids: Some(vec![card_id]), tag: None },
+            "action": action,
+            "card_ids": [card_id],
+        }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{queries, run_migrations, tags};
+
+    async fn insert_card(pool: &Pool<Sqlite>, code: &str) -> i64 {
+        let key = "00000000000000000000000000000000";
+        queries::insert_card(pool, "", key, key, key, key, key, code, 1000, 10000, true, false, code, None, None, chrono::Utc::now())
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn test_is_valid() {
+        assert!(CardSelector { card_ids: Some(vec![1]), tag: None }.is_valid());
+        assert!(CardSelector { card_ids: None, tag: Some("staff".to_string()) }.is_valid());
+        assert!(!CardSelector { card_ids: Some(vec![]), tag: None }.is_valid());
+        assert!(!CardSelector { card_ids: None, tag: Some(" ".to_string()) }.is_valid());
+        assert!(!CardSelector { card_ids: Some(vec![1]), tag: Some("staff".to_string()) }.is_valid());
+        assert!(!CardSelector::default().is_valid());
+
+        assert!(BulkAction::Enable.is_valid());
+        assert!(BulkAction::SetLimits { tx_limit_sats: Some(500), day_limit_sats: None }.is_valid());
+        assert!(!BulkAction::SetLimits { tx_limit_sats: None, day_limit_sats: None }.is_valid());
+        assert!(!BulkAction::SetLimits { tx_limit_sats: Some(500), day_limit_sats: Some(0) }.is_valid());
+    }
+
+    #[tokio::test]
+    async fn test_apply() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let first = insert_card(&pool, "first").await;
+        let second = insert_card(&pool, "second").await;
+        let third = insert_card(&pool, "third").await;
+        tags::set_card_tags(&pool, second, &["staff".to_string()]).await.unwrap();
+        tags::set_card_tags(&pool, third, &["staff".to_string()]).await.unwrap();
+        let card = |card_id| {
+            let pool = pool.clone();
+            async move { queries::get_card_by_id(&pool, card_id).await.unwrap().unwrap() }
+        };
+
+        // Cards that don't exist are left out
+        let by_ids = CardSelector { card_ids: Some(vec![third, first, third + 1]), tag: None };
+        assert_eq!(apply(&pool, &by_ids, &BulkAction::Disable, "api").await.unwrap(), vec![first, third]);
+        assert!(!card(first).await.enabled);
+        assert!(card(second).await.enabled);
+
+        let by_tag = CardSelector { card_ids: None, tag: Some(" staff ".to_string()) };
+        assert_eq!(apply(&pool, &by_tag, &BulkAction::Enable, "api").await.unwrap(), vec![second, third]);
+        assert!(card(third).await.enabled);
+        assert!(!card(first).await.enabled);
+
+        // Limits that aren't given stay as they were
+        let limits = BulkAction::SetLimits { tx_limit_sats: Some(500), day_limit_sats: None };
+        apply(&pool, &by_tag, &limits, "api").await.unwrap();
+        let third = card(third).await;
+        assert_eq!((third.tx_limit_sats, third.day_limit_sats), (500, 10000));
+        assert_eq!(card(first).await.tx_limit_sats, 1000);
+
+        let audited: Vec<(String, String)> = sqlx::query_as("SELECT actor, details FROM audit_log WHERE action = 'bulk_update' ORDER BY audit_id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(audited.len(), 3);
+        let details: serde_json::Value = serde_json::from_str(&audited[2].1).unwrap();
+        assert_eq!(details["action"]["action"], "set_limits");
+        assert_eq!(details["card_ids"], serde_json::json!([second, third.card_id]));
+    }
+
+    #[tokio::test]
+    async fn test_apply_as_holder() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let card_id = insert_card(&pool, "first").await;
+        let enabled = || {
+            let pool = pool.clone();
+            async move { queries::get_card_by_id(&pool, card_id).await.unwrap().unwrap().enabled }
+        };
+        let by_id = CardSelector { card_ids: Some(vec![card_id]), tag: None };
+
+        assert!(apply_as_holder(&pool, card_id, false, "user:alice").await.unwrap());
+        assert!(!enabled().await);
+        assert!(apply_as_holder(&pool, card_id, true, "user:alice").await.unwrap());
+        assert!(enabled().await);
+
+        // An operator's disable can't be undone by the holder, also after the holder disabled it too
+        apply(&pool, &by_id, &BulkAction::Disable, "api").await.unwrap();
+        assert!(apply_as_holder(&pool, card_id, false, "user:alice").await.unwrap());
+        assert!(!apply_as_holder(&pool, card_id, true, "user:alice").await.unwrap());
+        assert!(!enabled().await);
+
+        apply(&pool, &by_id, &BulkAction::Enable, "api").await.unwrap();
+        assert!(apply_as_holder(&pool, card_id, false, "user:alice").await.unwrap());
+        queries::set_card_enabled(&pool, card_id, false).await.unwrap();
+        assert!(!apply_as_holder(&pool, card_id, true, "user:alice").await.unwrap());
+
+        // A replaced card is disabled for good
+        let other = insert_card(&pool, "second").await;
+        assert!(apply_as_holder(&pool, other, false, "user:alice").await.unwrap());
+        let key = "00000000000000000000000000000000";
+        queries::insert_replacement(&pool, other, key, key, key, key, key, "replacement", chrono::Utc::now(), "api").await.unwrap();
+        assert!(!apply_as_holder(&pool, other, true, "user:alice").await.unwrap());
+    }
+}
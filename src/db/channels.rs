@@ -0,0 +1,71 @@
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+
+use crate::db::{models::UserNotificationChannel, query_channel};
+
+pub async fn create_channel(
+    pool: &Pool<Sqlite>,
+    user_id: i64,
+    kind: &str,
+    target: &str,
+    events: Option<&str>,
+) -> Result<i64> {
+    let channel_id = sqlx::query_scalar(
+        "INSERT INTO user_notification_channels (user_id, kind, target, events) VALUES (?, ?, ?, ?)
+         RETURNING channel_id"
+    )
+    .bind(user_id)
+    .bind(kind)
+    .bind(target)
+    .bind(events)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(channel_id)
+}
+
+pub async fn list_channels(pool: &Pool<Sqlite>, user_id: i64) -> Result<Vec<UserNotificationChannel>> {
+    let channels = query_channel!("WHERE user_id = ? ORDER BY channel_id", user_id)
+        .fetch_all(pool)
+    .await?;
+
+    Ok(channels)
+}
+
+pub async fn get_channel(pool: &Pool<Sqlite>, channel_id: i64) -> Result<Option<UserNotificationChannel>> {
+    let channel = query_channel!("WHERE channel_id = ?", channel_id)
+        .fetch_optional(pool)
+    .await?;
+
+    Ok(channel)
+}
+
+/// Remove one of the user's channels, `false` if it doesn't exist
+pub async fn delete_channel(pool: &Pool<Sqlite>, user_id: i64, channel_id: i64) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM user_notification_channels WHERE channel_id = ? AND user_id = ?")
+        .bind(channel_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Channels of the card's owner subscribed to `event`, none for cards without an owner
+pub async fn list_subscribed_channels(
+    pool: &Pool<Sqlite>,
+    card_id: i64,
+    event: &str,
+) -> Result<Vec<UserNotificationChannel>> {
+    let channels = query_channel!(
+        "WHERE user_id = (SELECT owner_id FROM cards WHERE card_id = ?)
+               AND (events IS NULL OR ',' || events || ',' LIKE '%,' || ? || ',%')
+         ORDER BY channel_id",
+        card_id,
+        event
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(channels)
+}
@@ -0,0 +1,112 @@
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+use serde_json::json;
+
+use crate::{clock::sql_timestamp, db::audit};
+
+/// Enabled cards other than `card_id` that carry `uid`
+///
+/// A tap's UID is unique to the chip, so any such card is a clone of the
+/// tapped one or the tapped one is a clone of it.
+pub async fn find_uid_twins(pool: &Pool<Sqlite>, card_id: i64, uid: &str) -> Result<Vec<i64>> {
+    let card_ids = sqlx::query_scalar(
+        "SELECT card_id FROM cards
+         WHERE uid = ? AND uid != '' AND card_id != ? AND enabled = 1 AND erased_at IS NULL AND deleted_at IS NULL
+         ORDER BY card_id"
+    )
+    .bind(uid)
+    .bind(card_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(card_ids)
+}
+
+/// Flag `card_ids` as suspected clones of each other, audited as `actor`
+///
+/// Cards flagged before keep the time of their first flag. Returns the IDs of
+/// the cards that weren't flagged yet.
+pub async fn flag_clones(
+    pool: &Pool<Sqlite>,
+    card_ids: &[i64],
+    uid: &str,
+    now: DateTime<Utc>,
+    actor: &str,
+) -> Result<Vec<i64>> {
+    let mut tx = pool.begin().await?;
+
+    let mut flagged = Vec::new();
+    for card_id in card_ids {
+        let updated = sqlx::query("UPDATE cards SET clone_flagged_at = ? WHERE card_id = ? AND clone_flagged_at IS NULL")
+            .bind(sql_timestamp(now))
+            .bind(card_id)
+            .execute(&mut *tx)
+            .await?;
+        if updated.rows_affected() > 0 {
+            flagged.push(*card_id);
+        }
+    }
+    if !flagged.is_empty() {
+        audit::record(&mut tx, actor, "flag_clone", &json!({ "card_ids": card_ids, "uid": uid })).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(flagged)
+}
+
+/// When the card was flagged as a suspected clone, `None` if it isn't
+pub async fn get_clone_flagged_at(pool: &Pool<Sqlite>, card_id: i64) -> Result<Option<String>> {
+    let flagged_at = sqlx::query_scalar("SELECT clone_flagged_at FROM cards WHERE card_id = ?")
+        .bind(card_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(flagged_at.flatten())
+}
+
+/// Clear the card's clone flag after review, returns false if it wasn't flagged
+pub async fn clear_clone_flag(pool: &Pool<Sqlite>, card_id: i64, actor: &str) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+
+    let updated = sqlx::query("UPDATE cards SET clone_flagged_at = NULL WHERE card_id = ? AND clone_flagged_at IS NOT NULL")
+        .bind(card_id)
+        .execute(&mut *tx)
+        .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
+    audit::record(&mut tx, actor, "clear_clone_flag", &json!({ "card_id": card_id })).await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{insert_test_card, test_pool_with_card};
+
+    #[tokio::test]
+    async fn test_flag_clones() {
+        let uid = "04996c6a926980";
+        let (pool, card_id) = test_pool_with_card(uid).await;
+        let twin_id = insert_test_card(&pool, uid, "Copy").await;
+        insert_test_card(&pool, "", "Blank").await;
+        let now = Utc::now();
+
+        assert_eq!(find_uid_twins(&pool, card_id, uid).await.unwrap(), vec![twin_id]);
+        assert!(find_uid_twins(&pool, card_id, "").await.unwrap().is_empty());
+
+        assert_eq!(flag_clones(&pool, &[card_id, twin_id], uid, now, "tap").await.unwrap(), vec![card_id, twin_id]);
+        assert!(flag_clones(&pool, &[card_id, twin_id], uid, now, "tap").await.unwrap().is_empty());
+        assert_eq!(get_clone_flagged_at(&pool, twin_id).await.unwrap(), Some(sql_timestamp(now)));
+
+        assert!(clear_clone_flag(&pool, card_id, "api").await.unwrap());
+        assert!(!clear_clone_flag(&pool, card_id, "api").await.unwrap());
+        assert_eq!(get_clone_flagged_at(&pool, card_id).await.unwrap(), None);
+        assert!(get_clone_flagged_at(&pool, twin_id).await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Sqlite, SqliteConnection};
+use anyhow::Result;
+use serde_json::json;
+
+use crate::{clock::sql_timestamp, db::audit};
+
+/// Outcome of a deletion request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Deletion {
+    /// When the card was deleted, the first time for a card deleted before
+    Deleted(String),
+    NotFound,
+    /// A payment is being made, try again once it's done
+    PaymentsInFlight,
+}
+
+/// Soft-delete a card, audited as `actor`
+///
+/// The card keeps its history and can be restored until it is purged. Its
+/// open withdrawal sessions are expired, so they can't be paid while deleted.
+pub async fn delete_card(pool: &Pool<Sqlite>, card_id: i64, now: DateTime<Utc>, actor: &str) -> Result<Deletion> {
+    let mut tx = pool.begin().await?;
+
+    let deleted_at: Option<Option<String>> = sqlx::query_scalar("SELECT deleted_at FROM cards WHERE card_id = ?")
+        .bind(card_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    match deleted_at {
+        None => return Ok(Deletion::NotFound),
+        Some(Some(deleted_at)) => return Ok(Deletion::Deleted(deleted_at)),
+        Some(None) => {}
+    }
+    let in_flight: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM card_payments WHERE card_id = ? AND status IN ('invoice_attached', 'in_flight'))"
+    )
+    .bind(card_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    if in_flight {
+        return Ok(Deletion::PaymentsInFlight);
+    }
+
+    let deleted_at = sql_timestamp(now);
+    sqlx::query("UPDATE cards SET deleted_at = ? WHERE card_id = ?")
+        .bind(&deleted_at)
+        .bind(card_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE card_payments SET status = 'expired' WHERE card_id = ? AND status IN ('created', 'failed')")
+        .bind(card_id)
+        .execute(&mut *tx)
+        .await?;
+    audit::record(&mut tx, actor, "delete_card", &json!({ "card_id": card_id })).await?;
+
+    tx.commit().await?;
+
+    Ok(Deletion::Deleted(deleted_at))
+}
+
+/// Bring back a deleted card as it was, returns false if it isn't deleted
+pub async fn restore_card(pool: &Pool<Sqlite>, card_id: i64, actor: &str) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+
+    let updated = sqlx::query("UPDATE cards SET deleted_at = NULL WHERE card_id = ? AND deleted_at IS NOT NULL")
+        .bind(card_id)
+        .execute(&mut *tx)
+        .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
+    audit::record(&mut tx, actor, "restore_card", &json!({ "card_id": card_id })).await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// When the card was deleted, `None` if it isn't
+pub async fn get_deleted_at(pool: &Pool<Sqlite>, card_id: i64) -> Result<Option<String>> {
+    let deleted_at = sqlx::query_scalar("SELECT deleted_at FROM cards WHERE card_id = ?")
+        .bind(card_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(deleted_at.flatten())
+}
+
+pub async fn list_deleted_card_ids(pool: &Pool<Sqlite>) -> Result<HashSet<i64>> {
+    let card_ids: Vec<i64> = sqlx::query_scalar("SELECT card_id FROM cards WHERE deleted_at IS NOT NULL")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(card_ids.into_iter().collect())
+}
+
+/// Remove the cards deleted before `deleted_before` for good, with everything recorded about them
+///
+/// Cards whose service fees weren't paid out yet are kept until they are, so
+/// the payout still adds up. Returns the IDs of the purged cards.
+pub async fn purge_deleted_cards(pool: &Pool<Sqlite>, deleted_before: DateTime<Utc>, actor: &str) -> Result<Vec<i64>> {
+    let mut tx = pool.begin().await?;
+
+    let card_ids: Vec<i64> = sqlx::query_scalar(
+        "SELECT card_id FROM cards
+         WHERE deleted_at IS NOT NULL AND deleted_at <= ?
+               AND card_id NOT IN (SELECT card_id FROM service_fees WHERE payout_id IS NULL)
+         ORDER BY card_id"
+    )
+    .bind(sql_timestamp(deleted_before))
+    .fetch_all(&mut *tx)
+    .await?;
+    for card_id in &card_ids {
+        purge_card(&mut tx, *card_id).await?;
+        audit::record(&mut tx, actor, "purge_card", &json!({ "card_id": card_id })).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(card_ids)
+}
+
+async fn purge_card(conn: &mut SqliteConnection, card_id: i64) -> Result<()> {
+    let auth_key: Option<String> = sqlx::query_scalar("SELECT auth_key FROM cards WHERE card_id = ?")
+        .bind(card_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    if let Some(auth_key) = auth_key {
+        sqlx::query("DELETE FROM wallet_sessions WHERE linking_key = ?")
+            .bind(&auth_key)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    // Rows referring to the card's payments go before the payments
+    for statement in [
+        "DELETE FROM payment_discrepancies WHERE payment_id IN (SELECT payment_id FROM card_payments WHERE card_id = ?)",
+        "DELETE FROM service_fees WHERE card_id = ?",
+        "DELETE FROM card_payments WHERE card_id = ?",
+        "DELETE FROM card_spend WHERE card_id = ?",
+        "DELETE FROM card_taps WHERE card_id = ?",
+        "DELETE FROM card_topups WHERE card_id = ?",
+        "DELETE FROM card_tags WHERE card_id = ?",
+        "DELETE FROM card_tokens WHERE card_id = ?",
+        "DELETE FROM card_previous_keys WHERE card_id = ?",
+        "DELETE FROM lost_reports WHERE card_id = ?",
+        "DELETE FROM cards WHERE card_id = ?",
+    ] {
+        sqlx::query(statement).bind(card_id).execute(&mut *conn).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{queries, taps, test_pool_with_card};
+
+    #[tokio::test]
+    async fn test_delete_restore_purge() {
+        let (pool, card_id) = test_pool_with_card("04996c6a926980").await;
+        let mut conn = pool.acquire().await.unwrap();
+        taps::record_tap(&mut conn, card_id, Some("04996c6a926980"), Some(1), true, None).await.unwrap();
+        drop(conn);
+        let now = Utc::now();
+
+        let deleted_at = sql_timestamp(now);
+        assert_eq!(delete_card(&pool, card_id, now, "api").await.unwrap(), Deletion::Deleted(deleted_at.clone()));
+        let later = now + chrono::Duration::days(1);
+        assert_eq!(delete_card(&pool, card_id, later, "api").await.unwrap(), Deletion::Deleted(deleted_at.clone()));
+        assert_eq!(get_deleted_at(&pool, card_id).await.unwrap(), Some(deleted_at));
+        assert!(queries::get_enabled_card_by_id(&pool, card_id).await.unwrap().is_none());
+        assert!(queries::list_enabled_cards(&pool).await.unwrap().is_empty());
+
+        assert!(restore_card(&pool, card_id, "api").await.unwrap());
+        assert!(!restore_card(&pool, card_id, "api").await.unwrap());
+        assert!(queries::get_enabled_card_by_id(&pool, card_id).await.unwrap().is_some());
+
+        delete_card(&pool, card_id, now, "api").await.unwrap();
+        assert!(purge_deleted_cards(&pool, now - chrono::Duration::seconds(1), "scheduler").await.unwrap().is_empty());
+        assert_eq!(purge_deleted_cards(&pool, now, "scheduler").await.unwrap(), vec![card_id]);
+        assert!(queries::get_card_by_id(&pool, card_id).await.unwrap().is_none());
+        assert_eq!(delete_card(&pool, card_id, now, "api").await.unwrap(), Deletion::NotFound);
+    }
+}
@@ -0,0 +1,48 @@
+use serde_json::json;
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+
+use crate::db::audit;
+
+/// The card's own description template, `None` inside if it uses the server's
+///
+/// Returns `None` if there is no such card.
+pub async fn get_card_template(pool: &Pool<Sqlite>, card_id: i64) -> Result<Option<Option<String>>> {
+    let template = sqlx::query_scalar("SELECT withdraw_description FROM cards WHERE card_id = ? AND erased_at IS NULL")
+        .bind(card_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(template)
+}
+
+/// Set the card's description template, `None` to go back to the server's
+///
+/// Returns false if there is no such card.
+pub async fn set_card_template(pool: &Pool<Sqlite>, card_id: i64, template: Option<&str>, actor: &str) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+
+    let updated = sqlx::query("UPDATE cards SET withdraw_description = ? WHERE card_id = ? AND erased_at IS NULL")
+        .bind(template)
+        .bind(card_id)
+        .execute(&mut *tx)
+        .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
+    audit::record(&mut tx, actor, "set_withdraw_description", &json!({ "card_id": card_id, "template": template })).await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// The description a withdrawal session was offered with, `None` for sessions from before descriptions were stored
+pub async fn get_payment_description(pool: &Pool<Sqlite>, payment_id: i64) -> Result<Option<String>> {
+    let description = sqlx::query_scalar("SELECT description FROM card_payments WHERE payment_id = ?")
+        .bind(payment_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(description.flatten())
+}
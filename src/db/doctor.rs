@@ -0,0 +1,318 @@
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{crypto::AesKey, db::{audit, queries}};
+
+/// A broken invariant found by [`check`]
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "issue", rename_all = "snake_case")]
+pub enum Issue {
+    /// Payment session whose card no longer exists
+    OrphanedPayment { payment_id: i64, card_id: i64 },
+    /// Enabled card with a key that isn't 16 bytes of hex
+    InvalidKey { card_id: i64, key: &'static str },
+    /// Card whose stored counter is below a counter it already accepted
+    CounterBehindTaps { card_id: i64, last_counter: i64, max_tap_counter: i64 },
+    /// Session marked settled without ever getting an invoice or on-chain address
+    PaidWithoutInvoice { payment_id: i64, card_id: i64 },
+    /// Card whose maintained spend totals disagree with its settled payments
+    SpendMismatch { card_id: i64, recorded_msats: i64, settled_msats: i64 },
+    /// Enabled cards with the same UID that aren't all flagged as suspected clones
+    SharedUid { uid: String, card_ids: Vec<i64> },
+}
+
+impl Issue {
+    /// What `repair` does about this issue
+    pub fn repair_action(&self) -> &'static str {
+        match self {
+            Issue::OrphanedPayment { .. } => "delete payment",
+            // Keys can't be recovered, the card has to be replaced
+            Issue::InvalidKey { .. } => "disable card",
+            Issue::CounterBehindTaps { .. } => "raise counter",
+            Issue::PaidWithoutInvoice { .. } => "mark failed",
+            Issue::SpendMismatch { .. } => "rebuild spend totals",
+            Issue::SharedUid { .. } => "flag for review",
+        }
+    }
+}
+
+/// Look for data that violates the invariants the server relies on
+pub async fn check(pool: &Pool<Sqlite>) -> Result<Vec<Issue>> {
+    let mut issues = Vec::new();
+
+    let orphaned: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT payment_id, card_id FROM card_payments
+         WHERE card_id NOT IN (SELECT card_id FROM cards) ORDER BY payment_id"
+    )
+    .fetch_all(pool)
+    .await?;
+    issues.extend(
+        orphaned
+            .into_iter()
+            .map(|(payment_id, card_id)| Issue::OrphanedPayment { payment_id, card_id }),
+    );
+
+    let cards = queries::list_enabled_cards(pool).await?;
+    for card in &cards {
+        let keys = [
+            ("k0", &card.k0_auth_key),
+            ("k1", &card.k1_decrypt_key),
+            ("k2", &card.k2_cmac_key),
+            ("k3", &card.k3),
+            ("k4", &card.k4),
+        ];
+        for (key, value) in keys {
+            if AesKey::from_hex(value).is_err() {
+                issues.push(Issue::InvalidKey { card_id: card.card_id, key });
+            }
+        }
+    }
+
+    // Only successful taps advanced the counter, failed ones may carry replayed values
+    let behind: Vec<(i64, i64, i64)> = sqlx::query_as(
+        "SELECT c.card_id, c.last_counter, MAX(t.counter) FROM cards c
+         JOIN card_taps t ON t.card_id = c.card_id AND t.success = 1
+         GROUP BY c.card_id
+         HAVING MAX(t.counter) > c.last_counter
+         ORDER BY c.card_id"
+    )
+    .fetch_all(pool)
+    .await?;
+    issues.extend(behind.into_iter().map(|(card_id, last_counter, max_tap_counter)| {
+        Issue::CounterBehindTaps { card_id, last_counter, max_tap_counter }
+    }));
+
+    let paid_without_invoice: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT payment_id, card_id FROM card_payments
+         WHERE status = 'settled' AND ((invoice IS NULL AND onchain_address IS NULL) OR amount_msats IS NULL) ORDER BY payment_id"
+    )
+    .fetch_all(pool)
+    .await?;
+    issues.extend(
+        paid_without_invoice
+            .into_iter()
+            .map(|(payment_id, card_id)| Issue::PaidWithoutInvoice { payment_id, card_id }),
+    );
+
+    // Reconcile the aggregates limit checks rely on with a full scan of payments
+    let mismatched: Vec<(i64, i64, i64)> = sqlx::query_as(
+        "SELECT card_id, recorded, settled FROM (
+            SELECT c.card_id,
+                   (SELECT COALESCE(SUM(amount_msats), 0) FROM card_spend s WHERE s.card_id = c.card_id) AS recorded,
+                   (SELECT COALESCE(SUM(amount_msats), 0) FROM card_payments p
+                    WHERE p.card_id = c.card_id AND p.status = 'settled' AND p.payment_time IS NOT NULL) AS settled
+            FROM cards c
+         )
+         WHERE recorded != settled
+         ORDER BY card_id"
+    )
+    .fetch_all(pool)
+    .await?;
+    issues.extend(mismatched.into_iter().map(|(card_id, recorded_msats, settled_msats)| {
+        Issue::SpendMismatch { card_id, recorded_msats, settled_msats }
+    }));
+
+    // Taps flag such cards as they come, this catches the ones not tapped since
+    let shared: Vec<(String, String)> = sqlx::query_as(
+        "SELECT uid, GROUP_CONCAT(card_id) FROM (
+            SELECT uid, card_id, clone_flagged_at FROM cards
+            WHERE uid != '' AND enabled = 1 AND erased_at IS NULL AND deleted_at IS NULL ORDER BY card_id
+         )
+         GROUP BY uid
+         HAVING COUNT(*) > 1 AND COUNT(clone_flagged_at) < COUNT(*)
+         ORDER BY MIN(card_id)"
+    )
+    .fetch_all(pool)
+    .await?;
+    for (uid, card_ids) in shared {
+        let card_ids = card_ids.split(',').map(str::parse).collect::<Result<_, _>>()?;
+        issues.push(Issue::SharedUid { uid, card_ids });
+    }
+
+    Ok(issues)
+}
+
+/// Fix `issues` in a single transaction and record the repair in the audit log
+pub async fn repair(pool: &Pool<Sqlite>, issues: &[Issue], actor: &str) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    for issue in issues {
+        match issue {
+            Issue::OrphanedPayment { payment_id, .. } => {
+                sqlx::query("DELETE FROM card_payments WHERE payment_id = ?")
+                    .bind(payment_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            Issue::InvalidKey { card_id, .. } => {
+                sqlx::query("UPDATE cards SET enabled = 0, holder_disabled = 0 WHERE card_id = ?")
+                    .bind(card_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            Issue::CounterBehindTaps { card_id, max_tap_counter, .. } => {
+                sqlx::query("UPDATE cards SET last_counter = ? WHERE card_id = ? AND last_counter < ?")
+                    .bind(max_tap_counter)
+                    .bind(card_id)
+                    .bind(max_tap_counter)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            Issue::PaidWithoutInvoice { payment_id, .. } => {
+                sqlx::query("UPDATE card_payments SET status = 'failed', payment_time = NULL WHERE payment_id = ?")
+                    .bind(payment_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            Issue::SpendMismatch { card_id, .. } => {
+                sqlx::query("DELETE FROM card_spend WHERE card_id = ?")
+                    .bind(card_id)
+                    .execute(&mut *tx)
+                    .await?;
+                sqlx::query(
+                    "INSERT INTO card_spend (card_id, day, amount_msats, payments)
+                     SELECT card_id, date(payment_time), SUM(COALESCE(amount_msats, 0)), COUNT(*)
+                     FROM card_payments
+                     WHERE card_id = ? AND status = 'settled' AND payment_time IS NOT NULL
+                     GROUP BY date(payment_time)"
+                )
+                .bind(card_id)
+                .execute(&mut *tx)
+                .await?;
+            }
+            Issue::SharedUid { card_ids, .. } => {
+                for card_id in card_ids {
+                    sqlx::query(
+                        "UPDATE cards SET clone_flagged_at = COALESCE(clone_flagged_at, CURRENT_TIMESTAMP) WHERE card_id = ?"
+                    )
+                    .bind(card_id)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+            }
+        }
+    }
+
+    audit::record(&mut tx, actor, "db_repair", &serde_json::json!({ "issues": issues })).await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{models::PaymentStatus, run_migrations};
+
+    async fn setup() -> (Pool<Sqlite>, i64) {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let key = "00000000000000000000000000000000";
+        let card_id = queries::insert_card(&pool, "", key, key, key, key, key, "Card", 1000, 10000, true, false, "code", None, None, chrono::Utc::now())
+            .await
+            .unwrap();
+        (pool, card_id)
+    }
+
+    #[tokio::test]
+    async fn test_onchain_payout_is_paid_with_an_address() {
+        let (pool, card_id) = setup().await;
+        let mut payment_ids = Vec::new();
+        for (k1, invoice, address) in [("a", None, Some("bc1qaddress")), ("b", None, None), ("c", Some("lnbc1"), None)] {
+            let payment_id: i64 = sqlx::query_scalar(
+                "INSERT INTO card_payments (card_id, k1, invoice, onchain_address, amount_msats, status)
+                 VALUES (?, ?, ?, ?, 5000, 'settled') RETURNING payment_id"
+            )
+            .bind(card_id)
+            .bind(k1)
+            .bind(invoice)
+            .bind(address)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+            payment_ids.push(payment_id);
+        }
+
+        let issues = check(&pool).await.unwrap();
+        let paid_without_invoice: Vec<_> = issues
+            .iter()
+            .filter_map(|issue| match issue {
+                Issue::PaidWithoutInvoice { payment_id, .. } => Some(*payment_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(paid_without_invoice, vec![payment_ids[1]]);
+
+        repair(&pool, &issues, "test").await.unwrap();
+        let status = async |payment_id| queries::get_payment_by_id(&pool, payment_id).await.unwrap().unwrap().status;
+        assert_eq!(status(payment_ids[0]).await, PaymentStatus::Settled);
+        assert_eq!(status(payment_ids[1]).await, PaymentStatus::Failed);
+    }
+
+    #[tokio::test]
+    async fn test_check_and_repair() {
+        let (pool, card_id) = setup().await;
+        let key = "00000000000000000000000000000000";
+        assert!(check(&pool).await.unwrap().is_empty());
+
+        // A card whose counter was reset below a tap it accepted
+        sqlx::query("INSERT INTO card_taps (card_id, counter, success) VALUES (?, 7, 1), (?, 99, 0)")
+            .bind(card_id)
+            .bind(card_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        // A settled withdrawal that never made it into the spend totals
+        sqlx::query(
+            "INSERT INTO card_payments (card_id, k1, invoice, amount_msats, status, payment_time)
+             VALUES (?, 'settled', 'lnbc1', 5000, 'settled', '2025-03-01 12:00:00')"
+        )
+        .bind(card_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+        let broken = queries::insert_card(&pool, "", "not hex", key, key, key, key, "Broken", 1000, 10000, true, false, "broken", None, None, chrono::Utc::now())
+            .await
+            .unwrap();
+        let mut clones = Vec::new();
+        for code in ["clone-a", "clone-b"] {
+            let clone = queries::insert_card(&pool, "04996c6a926980", key, key, key, key, key, code, 1000, 10000, true, false, code, None, None, chrono::Utc::now())
+                .await
+                .unwrap();
+            clones.push(clone);
+        }
+        sqlx::query("PRAGMA foreign_keys = OFF").execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO card_payments (card_id, k1, status) VALUES (999, 'orphaned', 'created')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let issues = check(&pool).await.unwrap();
+        let found: Vec<String> = issues.iter().map(|issue| serde_json::to_string(issue).unwrap()).collect();
+        assert_eq!(
+            found,
+            vec![
+                r#"{"issue":"orphaned_payment","payment_id":2,"card_id":999}"#.to_string(),
+                format!(r#"{{"issue":"invalid_key","card_id":{},"key":"k0"}}"#, broken),
+                format!(r#"{{"issue":"counter_behind_taps","card_id":{},"last_counter":0,"max_tap_counter":7}}"#, card_id),
+                format!(r#"{{"issue":"spend_mismatch","card_id":{},"recorded_msats":0,"settled_msats":5000}}"#, card_id),
+                format!(r#"{{"issue":"shared_uid","uid":"04996c6a926980","card_ids":[{},{}]}}"#, clones[0], clones[1]),
+            ]
+        );
+
+        repair(&pool, &issues, "test").await.unwrap();
+        assert!(check(&pool).await.unwrap().is_empty());
+        assert!(!queries::get_card_by_id(&pool, broken).await.unwrap().unwrap().enabled);
+        assert_eq!(queries::get_card_by_id(&pool, card_id).await.unwrap().unwrap().last_counter, 7);
+        let spend: (String, i64, i64) = sqlx::query_as("SELECT day, amount_msats, payments FROM card_spend WHERE card_id = ?")
+            .bind(card_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(spend, ("2025-03-01".to_string(), 5000, 1));
+        let repairs: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM audit_log WHERE action = 'db_repair'").fetch_one(&pool).await.unwrap();
+        assert_eq!(repairs, 1);
+    }
+}
@@ -0,0 +1,78 @@
+use anyhow::Result;
+use sqlx::{Pool, Sqlite};
+
+use crate::db::{models::{AuditEntry, WebhookEvent}, query_event};
+
+/// A settled or failed withdrawal
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct FeedPayment {
+    pub payment_id: i64,
+    pub card_id: i64,
+    pub card_name: String,
+    pub amount_msats: Option<i64>,
+    pub status: String,
+    pub failure_reason: Option<String>,
+    /// Settlement time, or creation time of failed withdrawals
+    pub time: String,
+}
+
+/// Settled and failed withdrawals of one card or all of them, newest first
+pub async fn payments(pool: &Pool<Sqlite>, card_id: Option<i64>, limit: i64) -> Result<Vec<FeedPayment>> {
+    let payments = sqlx::query_as::<_, FeedPayment>(
+        "SELECT p.payment_id, p.card_id, c.card_name, p.amount_msats, p.status, p.failure_reason,
+                COALESCE(p.payment_time, p.created_at) AS time
+         FROM card_payments p
+         JOIN cards c ON c.card_id = p.card_id
+         WHERE p.status IN ('settled', 'failed') AND (? IS NULL OR p.card_id = ?)
+         ORDER BY time DESC, p.payment_id DESC
+         LIMIT ?"
+    )
+    .bind(card_id)
+    .bind(card_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(payments)
+}
+
+/// Operator notifications about cards, newest first
+///
+/// Notifications about payments are left out, the payments themselves are in the feed.
+pub async fn notifications(pool: &Pool<Sqlite>, card_id: Option<i64>, limit: i64) -> Result<Vec<WebhookEvent>> {
+    let events = query_event!(
+        "WHERE event NOT IN ('payment_settled', 'payment_failed')
+           AND (? IS NULL OR json_extract(payload, '$.card_id') = ?)
+         ORDER BY event_id DESC
+         LIMIT ?",
+        card_id,
+        card_id,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(events)
+}
+
+/// Audit log entries naming a card, or one card, newest first
+pub async fn audit_entries(pool: &Pool<Sqlite>, card_id: Option<i64>, limit: i64) -> Result<Vec<AuditEntry>> {
+    let entries = sqlx::query_as!(
+        AuditEntry,
+        r#"SELECT audit_id AS "audit_id!", actor, action, details, created_at AS "created_at: String"
+         FROM audit_log
+         WHERE EXISTS (SELECT 1 FROM (SELECT json_extract(audit_log.details, '$.card_id') AS id
+                                      UNION ALL SELECT json_extract(audit_log.details, '$.replacement_id')
+                                      UNION ALL SELECT value FROM json_each(audit_log.details, '$.card_ids')) AS ids
+                       WHERE ids.id IS NOT NULL AND (? IS NULL OR ids.id = ?))
+         ORDER BY audit_id DESC
+         LIMIT ?"#,
+        card_id,
+        card_id,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(entries)
+}
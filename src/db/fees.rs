@@ -0,0 +1,256 @@
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{Pool, Sqlite, SqliteConnection};
+
+use crate::{db::{audit, query_payout}, fees::ServiceFee};
+
+/// Service fees collected and not paid out yet, of one node
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct AccruedFees {
+    /// `None` for the server's own node
+    pub org_id: Option<i64>,
+    pub org_slug: Option<String>,
+    pub payments: i64,
+    pub amount_msats: i64,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct FeePayout {
+    pub payout_id: i64,
+    pub org_id: Option<i64>,
+    pub amount_msats: i64,
+    pub destination: String,
+    /// `pending`, `paid` or `failed`
+    pub status: String,
+    pub payment_hash: Option<String>,
+    pub routing_fee_msats: Option<i64>,
+    pub error: Option<String>,
+    pub created_at: Option<String>,
+    pub paid_at: Option<String>,
+}
+
+/// Fix the service fee of a withdrawal when its invoice is reserved
+pub async fn set_payment_fee(pool: &Pool<Sqlite>, payment_id: i64, fee_msats: i64) -> Result<()> {
+    sqlx::query("UPDATE card_payments SET service_fee_msats = ? WHERE payment_id = ?")
+        .bind(fee_msats)
+        .bind(payment_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn payment_fee(pool: &Pool<Sqlite>, payment_id: i64) -> Result<i64> {
+    let fee = sqlx::query_scalar("SELECT service_fee_msats FROM card_payments WHERE payment_id = ?")
+        .bind(payment_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(fee.unwrap_or(0))
+}
+
+/// The card's own service fee, `None` inside if it's charged the server's
+///
+/// Returns `None` if there is no such card.
+pub async fn get_card_fee(pool: &Pool<Sqlite>, card_id: i64) -> Result<Option<Option<ServiceFee>>> {
+    let policy: Option<(Option<i64>, Option<f64>)> =
+        sqlx::query_as("SELECT fee_flat_msats, fee_percent FROM cards WHERE card_id = ? AND erased_at IS NULL")
+            .bind(card_id)
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(policy.map(|policy| match policy {
+        (Some(flat_msats), Some(percent)) => Some(ServiceFee { flat_msats, percent }),
+        _ => None,
+    }))
+}
+
+/// Set the card's service fee, `None` to charge it the server's
+///
+/// Returns false if there is no such card.
+pub async fn set_card_fee(pool: &Pool<Sqlite>, card_id: i64, fee: Option<ServiceFee>, actor: &str) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+
+    let updated = sqlx::query("UPDATE cards SET fee_flat_msats = ?, fee_percent = ? WHERE card_id = ? AND erased_at IS NULL")
+        .bind(fee.map(|fee| fee.flat_msats))
+        .bind(fee.map(|fee| fee.percent))
+        .bind(card_id)
+        .execute(&mut *tx)
+        .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
+    let details = json!({
+        "card_id": card_id,
+        "flat_msats": fee.map(|fee| fee.flat_msats),
+        "percent": fee.map(|fee| fee.percent),
+    });
+    audit::record(&mut tx, actor, "set_card_fee", &details).await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// Book the service fee of a withdrawal that just settled, in the settlement's transaction
+pub async fn book(conn: &mut SqliteConnection, payment_id: i64) -> Result<()> {
+    sqlx::query(
+        "INSERT OR IGNORE INTO service_fees (payment_id, card_id, org_id, amount_msats)
+         SELECT p.payment_id, p.card_id, c.org_id, p.service_fee_msats
+         FROM card_payments p
+         JOIN cards c ON c.card_id = p.card_id
+         WHERE p.payment_id = ? AND p.service_fee_msats > 0"
+    )
+    .bind(payment_id)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Fees not paid out yet, by node
+pub async fn accrued(pool: &Pool<Sqlite>) -> Result<Vec<AccruedFees>> {
+    let fees = sqlx::query_as::<_, AccruedFees>(
+        "SELECT f.org_id, o.slug AS org_slug, COUNT(*) AS payments, SUM(f.amount_msats) AS amount_msats
+         FROM service_fees f
+         LEFT JOIN organizations o ON o.org_id = f.org_id
+         WHERE f.payout_id IS NULL
+         GROUP BY f.org_id
+         ORDER BY f.org_id"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(fees)
+}
+
+/// Start a payout of the fees accrued on a node, if they add up to `min_msats`
+///
+/// The fees are attached to the payout so they can't be paid out twice.
+/// Nothing is started while an earlier payout of the node is still pending.
+pub async fn claim_payout(
+    pool: &Pool<Sqlite>,
+    org_id: Option<i64>,
+    destination: &str,
+    min_msats: i64,
+) -> Result<Option<FeePayout>> {
+    let mut tx = pool.begin().await?;
+
+    let pending: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM fee_payouts WHERE org_id IS ? AND status = 'pending')")
+        .bind(org_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    let amount_msats: i64 =
+        sqlx::query_scalar("SELECT COALESCE(SUM(amount_msats), 0) FROM service_fees WHERE org_id IS ? AND payout_id IS NULL")
+            .bind(org_id)
+            .fetch_one(&mut *tx)
+            .await?;
+    if pending || amount_msats == 0 || amount_msats < min_msats {
+        return Ok(None);
+    }
+
+    let payout = sqlx::query_as!(
+        FeePayout,
+        r#"INSERT INTO fee_payouts (org_id, amount_msats, destination) VALUES (?, ?, ?)
+           RETURNING payout_id AS "payout_id!", org_id, amount_msats, destination, status, payment_hash,
+                     routing_fee_msats, error, created_at AS "created_at: String", paid_at AS "paid_at: String""#,
+        org_id,
+        amount_msats,
+        destination
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    sqlx::query("UPDATE service_fees SET payout_id = ? WHERE org_id IS ? AND payout_id IS NULL")
+        .bind(payout.payout_id)
+        .bind(org_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(payout))
+}
+
+/// Remember the invoice's payment hash before paying it, so an interrupted payout can be looked up
+pub async fn set_payout_hash(pool: &Pool<Sqlite>, payout_id: i64, payment_hash: &str) -> Result<()> {
+    sqlx::query("UPDATE fee_payouts SET payment_hash = ? WHERE payout_id = ?")
+        .bind(payment_hash)
+        .bind(payout_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn mark_payout_paid(pool: &Pool<Sqlite>, payout_id: i64, routing_fee_msats: Option<i64>) -> Result<()> {
+    sqlx::query(
+        "UPDATE fee_payouts SET status = 'paid', routing_fee_msats = ?, error = NULL, paid_at = CURRENT_TIMESTAMP
+         WHERE payout_id = ? AND status = 'pending'"
+    )
+    .bind(routing_fee_msats)
+    .bind(payout_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Fail a payout that wasn't paid, its fees accrue again for the next one
+///
+/// Returns `false` if the payout was already paid.
+pub async fn release_payout(pool: &Pool<Sqlite>, payout_id: i64, error: &str) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+
+    let status: Option<String> = sqlx::query_scalar("SELECT status FROM fee_payouts WHERE payout_id = ?")
+        .bind(payout_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    if !matches!(status.as_deref(), Some("pending" | "failed")) {
+        return Ok(false);
+    }
+
+    sqlx::query("UPDATE fee_payouts SET status = 'failed', error = ? WHERE payout_id = ?")
+        .bind(error)
+        .bind(payout_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE service_fees SET payout_id = NULL WHERE payout_id = ?")
+        .bind(payout_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// Payouts still pending since before `older_than_secs` ago, e.g. interrupted by a restart
+pub async fn stale_payouts(pool: &Pool<Sqlite>, older_than_secs: i64) -> Result<Vec<FeePayout>> {
+    let payouts = query_payout!(
+        "WHERE status = 'pending' AND created_at < datetime('now', '-' || ? || ' seconds')
+         ORDER BY payout_id",
+        older_than_secs
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(payouts)
+}
+
+pub async fn get_payout(pool: &Pool<Sqlite>, payout_id: i64) -> Result<Option<FeePayout>> {
+    let payout = query_payout!("WHERE payout_id = ?", payout_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(payout)
+}
+
+/// Payouts, newest first
+pub async fn list_payouts(pool: &Pool<Sqlite>, limit: i64) -> Result<Vec<FeePayout>> {
+    let payouts = query_payout!("ORDER BY payout_id DESC LIMIT ?", limit)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(payouts)
+}
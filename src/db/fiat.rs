@@ -0,0 +1,57 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use serde::Serialize;
+use sqlx::{Pool, Sqlite};
+
+/// A settled withdrawal with the bitcoin price recorded when it settled
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct FiatPayment {
+    pub payment_id: i64,
+    pub card_id: i64,
+    pub card_name: String,
+    pub payment_time: String,
+    pub amount_msats: i64,
+    pub fee_msats: Option<i64>,
+    pub fiat_currency: Option<String>,
+    /// Price of one bitcoin in `fiat_currency`
+    pub fiat_rate: Option<f64>,
+    /// Provider the price came from, `None` for prices recorded before it was kept
+    pub fiat_rate_source: Option<String>,
+}
+
+impl FiatPayment {
+    /// Fiat value of `msats` at the recorded price
+    pub fn value(&self, msats: i64) -> Option<f64> {
+        self.fiat_rate.map(|rate| msats as f64 / 100_000_000_000.0 * rate)
+    }
+}
+
+pub async fn set_payment_rate(pool: &Pool<Sqlite>, payment_id: i64, currency: &str, rate: f64, source: &str) -> Result<()> {
+    sqlx::query("UPDATE card_payments SET fiat_currency = ?, fiat_rate = ?, fiat_rate_source = ? WHERE payment_id = ?")
+        .bind(currency)
+        .bind(rate)
+        .bind(source)
+        .bind(payment_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Withdrawals settled between `from` and `to` (UTC days, inclusive), oldest first
+pub async fn settled_payments(pool: &Pool<Sqlite>, from: NaiveDate, to: NaiveDate) -> Result<Vec<FiatPayment>> {
+    let payments = sqlx::query_as::<_, FiatPayment>(
+        "SELECT p.payment_id, p.card_id, c.card_name, p.payment_time, COALESCE(p.amount_msats, 0) AS amount_msats,
+                p.fee_msats, p.fiat_currency, p.fiat_rate, p.fiat_rate_source
+         FROM card_payments p
+         JOIN cards c ON c.card_id = p.card_id
+         WHERE p.status = 'settled' AND date(p.payment_time) BETWEEN ? AND ?
+         ORDER BY p.payment_time, p.payment_id"
+    )
+    .bind(from.format("%Y-%m-%d").to_string())
+    .bind(to.format("%Y-%m-%d").to_string())
+    .fetch_all(pool)
+    .await?;
+
+    Ok(payments)
+}
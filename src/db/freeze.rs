@@ -0,0 +1,142 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+
+use crate::{clock::sql_timestamp, db::audit};
+
+/// A card's freeze, which refuses taps while the card stays enabled
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct Freeze {
+    pub frozen_at: String,
+    /// `None` for a freeze that lasts until the card is unfrozen
+    pub frozen_until: Option<String>,
+    pub reason: Option<String>,
+}
+
+/// Freeze a card until `until`, or until it is unfrozen, audited as `actor`
+///
+/// Freezing a frozen card replaces its end and reason but keeps when it was
+/// first frozen. Returns `None` if there is no such card.
+pub async fn freeze(
+    pool: &Pool<Sqlite>,
+    card_id: i64,
+    until: Option<DateTime<Utc>>,
+    reason: Option<&str>,
+    now: DateTime<Utc>,
+    actor: &str,
+) -> Result<Option<Freeze>> {
+    let mut tx = pool.begin().await?;
+
+    let freeze: Option<Freeze> = sqlx::query_as(
+        "UPDATE cards SET frozen_at = COALESCE(frozen_at, ?), frozen_until = ?, freeze_reason = ?
+         WHERE card_id = ? AND erased_at IS NULL
+         RETURNING frozen_at, frozen_until, freeze_reason AS reason"
+    )
+    .bind(sql_timestamp(now))
+    .bind(until.map(sql_timestamp))
+    .bind(reason)
+    .bind(card_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(freeze) = freeze else { return Ok(None) };
+    audit::record(
+        &mut tx,
+        actor,
+        "freeze_card",
+        &json!({ "card_id": card_id, "frozen_until": freeze.frozen_until, "reason": reason }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(freeze))
+}
+
+/// Lift a card's freeze, returns false if it wasn't frozen
+pub async fn unfreeze(pool: &Pool<Sqlite>, card_id: i64, actor: &str) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+
+    let updated = sqlx::query(
+        "UPDATE cards SET frozen_at = NULL, frozen_until = NULL, freeze_reason = NULL
+         WHERE card_id = ? AND frozen_at IS NOT NULL"
+    )
+    .bind(card_id)
+    .execute(&mut *tx)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
+    audit::record(&mut tx, actor, "unfreeze_card", &json!({ "card_id": card_id })).await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// The freeze a card is under at `now`, ignoring one that ran out but wasn't cleared yet
+pub async fn get_freeze(pool: &Pool<Sqlite>, card_id: i64, now: DateTime<Utc>) -> Result<Option<Freeze>> {
+    let freeze = sqlx::query_as(
+        "SELECT frozen_at, frozen_until, freeze_reason AS reason FROM cards
+         WHERE card_id = ? AND frozen_at IS NOT NULL AND (frozen_until IS NULL OR frozen_until > ?)"
+    )
+    .bind(card_id)
+    .bind(sql_timestamp(now))
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(freeze)
+}
+
+/// Lift the freezes that ran out by `now`, returns the IDs of the cards unfrozen
+pub async fn expire_freezes(pool: &Pool<Sqlite>, now: DateTime<Utc>) -> Result<Vec<i64>> {
+    let mut tx = pool.begin().await?;
+
+    let card_ids: Vec<i64> = sqlx::query_scalar(
+        "UPDATE cards SET frozen_at = NULL, frozen_until = NULL, freeze_reason = NULL
+         WHERE frozen_until IS NOT NULL AND frozen_until <= ?
+         RETURNING card_id"
+    )
+    .bind(sql_timestamp(now))
+    .fetch_all(&mut *tx)
+    .await?;
+    for card_id in &card_ids {
+        audit::record(&mut tx, "scheduler", "unfreeze_card", &json!({ "card_id": card_id, "expired": true })).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(card_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{queries, test_pool_with_card};
+
+    #[tokio::test]
+    async fn test_freeze_until() {
+        let (pool, card_id) = test_pool_with_card("04996c6a926980").await;
+        let now = Utc::now();
+        let until = now + chrono::Duration::days(7);
+
+        let frozen = freeze(&pool, card_id, Some(until), Some("travelling"), now, "api").await.unwrap().unwrap();
+        assert_eq!(frozen.frozen_until, Some(sql_timestamp(until)));
+        assert_eq!(get_freeze(&pool, card_id, now).await.unwrap(), Some(frozen));
+        assert!(queries::get_card_by_id(&pool, card_id).await.unwrap().unwrap().enabled);
+
+        let later = until + chrono::Duration::seconds(1);
+        assert_eq!(get_freeze(&pool, card_id, later).await.unwrap(), None);
+        assert!(expire_freezes(&pool, now).await.unwrap().is_empty());
+        assert_eq!(expire_freezes(&pool, later).await.unwrap(), vec![card_id]);
+
+        freeze(&pool, card_id, None, None, now, "api").await.unwrap().unwrap();
+        assert!(expire_freezes(&pool, later).await.unwrap().is_empty());
+        assert!(get_freeze(&pool, card_id, later).await.unwrap().is_some());
+        assert!(unfreeze(&pool, card_id, "api").await.unwrap());
+        assert!(!unfreeze(&pool, card_id, "api").await.unwrap());
+
+        assert!(freeze(&pool, card_id + 1, None, None, now, "api").await.unwrap().is_none());
+    }
+}
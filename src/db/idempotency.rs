@@ -0,0 +1,139 @@
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+
+/// How long a key is remembered
+const KEY_TTL: &str = "-1 day";
+
+/// How long a claim without response holds its key, so a request lost in a
+/// crash doesn't block its key until the key expires
+const CLAIM_LEASE: &str = "-5 minutes";
+
+/// Outcome of claiming an idempotency key
+#[derive(Debug, PartialEq)]
+pub enum Claim {
+    /// First use of the key, the request should run
+    New,
+    /// The request already ran, with this response
+    Replay(String),
+    /// A request with this key is still running
+    InProgress,
+    /// The key was used for a different request
+    Mismatch,
+}
+
+/// Claim `key` for a request to `endpoint` whose body hashes to `request_hash`
+pub async fn claim(pool: &Pool<Sqlite>, endpoint: &str, key: &str, request_hash: &str) -> Result<Claim> {
+    sqlx::query(
+        "DELETE FROM idempotency_keys
+         WHERE created_at < datetime('now', ?) OR (response IS NULL AND created_at < datetime('now', ?))"
+    )
+    .bind(KEY_TTL)
+    .bind(CLAIM_LEASE)
+    .execute(pool)
+    .await?;
+
+    let inserted = sqlx::query(
+        "INSERT OR IGNORE INTO idempotency_keys (endpoint, idempotency_key, request_hash) VALUES (?, ?, ?)"
+    )
+    .bind(endpoint)
+    .bind(key)
+    .bind(request_hash)
+    .execute(pool)
+    .await?;
+    if inserted.rows_affected() > 0 {
+        return Ok(Claim::New);
+    }
+
+    let (stored_hash, response): (String, Option<String>) = sqlx::query_as(
+        "SELECT request_hash, response FROM idempotency_keys WHERE endpoint = ? AND idempotency_key = ?"
+    )
+    .bind(endpoint)
+    .bind(key)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(match response {
+        _ if stored_hash != request_hash => Claim::Mismatch,
+        Some(response) => Claim::Replay(response),
+        None => Claim::InProgress,
+    })
+}
+
+/// Store the response of a claimed request for replays
+pub async fn complete(pool: &Pool<Sqlite>, endpoint: &str, key: &str, response: &str) -> Result<()> {
+    sqlx::query("UPDATE idempotency_keys SET response = ? WHERE endpoint = ? AND idempotency_key = ?")
+        .bind(response)
+        .bind(endpoint)
+        .bind(key)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Forget a claim whose request failed, so it can be retried
+pub async fn release(pool: &Pool<Sqlite>, endpoint: &str, key: &str) -> Result<()> {
+    sqlx::query("DELETE FROM idempotency_keys WHERE endpoint = ? AND idempotency_key = ? AND response IS NULL")
+        .bind(endpoint)
+        .bind(key)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::run_migrations;
+
+    async fn pool() -> Pool<Sqlite> {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        pool
+    }
+
+    async fn age(pool: &Pool<Sqlite>, modifier: &str) {
+        sqlx::query("UPDATE idempotency_keys SET created_at = datetime('now', ?)")
+            .bind(modifier)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_claim() {
+        let pool = pool().await;
+
+        assert_eq!(claim(&pool, "cards", "key", "hash").await.unwrap(), Claim::New);
+        assert_eq!(claim(&pool, "cards", "key", "hash").await.unwrap(), Claim::InProgress);
+        assert_eq!(claim(&pool, "cards", "key", "other").await.unwrap(), Claim::Mismatch);
+        // Keys are per endpoint
+        assert_eq!(claim(&pool, "other", "key", "other").await.unwrap(), Claim::New);
+
+        complete(&pool, "cards", "key", "response").await.unwrap();
+        assert_eq!(claim(&pool, "cards", "key", "hash").await.unwrap(), Claim::Replay("response".to_string()));
+        // A completed request keeps its response
+        release(&pool, "cards", "key").await.unwrap();
+        assert_eq!(claim(&pool, "cards", "key", "hash").await.unwrap(), Claim::Replay("response".to_string()));
+
+        release(&pool, "other", "key").await.unwrap();
+        assert_eq!(claim(&pool, "other", "key", "hash").await.unwrap(), Claim::New);
+    }
+
+    #[tokio::test]
+    async fn test_claims_expire() {
+        let pool = pool().await;
+        claim(&pool, "cards", "running", "hash").await.unwrap();
+        claim(&pool, "cards", "done", "hash").await.unwrap();
+        complete(&pool, "cards", "done", "response").await.unwrap();
+
+        // A request that never completed gives up its key after the lease
+        age(&pool, "-6 minutes").await;
+        assert_eq!(claim(&pool, "cards", "running", "hash").await.unwrap(), Claim::New);
+        assert_eq!(claim(&pool, "cards", "done", "hash").await.unwrap(), Claim::Replay("response".to_string()));
+
+        age(&pool, "-25 hours").await;
+        assert_eq!(claim(&pool, "cards", "done", "hash").await.unwrap(), Claim::New);
+    }
+}
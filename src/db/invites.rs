@@ -0,0 +1,108 @@
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+use crate::{clock::sql_timestamp, db::models::UserInvite};
+
+/// Outcome of registering with an invite code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvitedRegistration {
+    Created(i64),
+    /// Unknown, expired, revoked or used up
+    InvalidInvite,
+    /// The invite isn't used up by a failed registration
+    UsernameTaken,
+}
+
+pub async fn create_invite(
+    pool: &Pool<Sqlite>,
+    code: &str,
+    max_uses: i64,
+    valid_hours: Option<i64>,
+    note: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<i64> {
+    let expiry_str = valid_hours.map(|hours| sql_timestamp(now + chrono::Duration::hours(hours)));
+
+    let invite_id = sqlx::query_scalar(
+        "INSERT INTO user_invites (code, max_uses, expires_at, note) VALUES (?, ?, ?, ?) RETURNING invite_id"
+    )
+    .bind(code)
+    .bind(max_uses)
+    .bind(expiry_str)
+    .bind(note)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(invite_id)
+}
+
+pub async fn list_invites(pool: &Pool<Sqlite>) -> Result<Vec<UserInvite>> {
+    let invites = sqlx::query_as!(
+        UserInvite,
+        r#"SELECT invite_id AS "invite_id!", code, max_uses, uses, expires_at AS "expires_at: String", note,
+                  created_at AS "created_at: String", revoked_at AS "revoked_at: String"
+           FROM user_invites ORDER BY invite_id"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(invites)
+}
+
+/// Stop an invite from admitting anyone else, `false` if it doesn't exist or was revoked before
+pub async fn revoke_invite(pool: &Pool<Sqlite>, invite_id: i64) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE user_invites SET revoked_at = CURRENT_TIMESTAMP WHERE invite_id = ? AND revoked_at IS NULL"
+    )
+    .bind(invite_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Create a user on a use of the invite, both or neither
+///
+/// Taking the use first locks the database, so concurrent registrations
+/// can't exceed the invite's uses.
+pub async fn register_with_invite(
+    pool: &Pool<Sqlite>,
+    code: &str,
+    username: &str,
+    password_hash: &str,
+    now: DateTime<Utc>,
+) -> Result<InvitedRegistration> {
+    let mut tx = pool.begin().await?;
+
+    let invite_id: Option<i64> = sqlx::query_scalar(
+        "UPDATE user_invites SET uses = uses + 1
+         WHERE code = ? AND uses < max_uses AND revoked_at IS NULL
+               AND (expires_at IS NULL OR expires_at > ?)
+         RETURNING invite_id"
+    )
+    .bind(code)
+    .bind(sql_timestamp(now))
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(invite_id) = invite_id else {
+        return Ok(InvitedRegistration::InvalidInvite);
+    };
+
+    let user_id: Option<i64> = sqlx::query_scalar(
+        "INSERT INTO users (username, password_hash, invite_id) VALUES (?, ?, ?)
+         ON CONFLICT (username) DO NOTHING RETURNING user_id"
+    )
+    .bind(username)
+    .bind(password_hash)
+    .bind(invite_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(user_id) = user_id else {
+        return Ok(InvitedRegistration::UsernameTaken);
+    };
+
+    tx.commit().await?;
+
+    Ok(InvitedRegistration::Created(user_id))
+}
@@ -0,0 +1,116 @@
+use sqlx::{Pool, Sqlite, SqliteConnection};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use crate::{clock::sql_timestamp, db::models::JobRecord};
+
+pub async fn enqueue(conn: &mut SqliteConnection, kind: &str, payload: &str) -> Result<i64> {
+    let result = sqlx::query("INSERT INTO jobs (kind, payload) VALUES (?, ?)")
+        .bind(kind)
+        .bind(payload)
+        .execute(conn)
+        .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+/// Take the oldest due job and mark it running
+///
+/// The select and update are one statement, so concurrent workers never
+/// claim the same job.
+pub async fn claim(pool: &Pool<Sqlite>, now: DateTime<Utc>) -> Result<Option<JobRecord>> {
+    let now = sql_timestamp(now);
+    let job = sqlx::query_as!(
+        JobRecord,
+        r#"UPDATE jobs SET status = 'running', attempts = attempts + 1, updated_at = CURRENT_TIMESTAMP
+         WHERE job_id = (
+            SELECT job_id FROM jobs WHERE status = 'queued' AND run_after <= ?
+            ORDER BY run_after, job_id LIMIT 1
+         )
+         RETURNING job_id AS "job_id!", kind, payload, status, attempts, run_after AS "run_after: String", last_error,
+                   created_at AS "created_at: String", updated_at AS "updated_at: String""#,
+        now
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(job)
+}
+
+/// Remove a job that ran successfully
+pub async fn complete(pool: &Pool<Sqlite>, job_id: i64) -> Result<()> {
+    sqlx::query("DELETE FROM jobs WHERE job_id = ?")
+        .bind(job_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Put a failed job back into the queue to run again at `run_after`
+pub async fn retry_later(pool: &Pool<Sqlite>, job_id: i64, error: &str, run_after: DateTime<Utc>) -> Result<()> {
+    sqlx::query(
+        "UPDATE jobs SET status = 'queued', last_error = ?, run_after = ?,
+         updated_at = CURRENT_TIMESTAMP WHERE job_id = ?"
+    )
+    .bind(error)
+    .bind(sql_timestamp(run_after))
+    .bind(job_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Give up on a job, keeping it as dead for inspection
+pub async fn bury(pool: &Pool<Sqlite>, job_id: i64, error: &str) -> Result<()> {
+    sqlx::query(
+        "UPDATE jobs SET status = 'dead', last_error = ?, updated_at = CURRENT_TIMESTAMP WHERE job_id = ?"
+    )
+    .bind(error)
+    .bind(job_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Requeue jobs that were running when the server stopped
+pub async fn requeue_interrupted(pool: &Pool<Sqlite>) -> Result<u64> {
+    let result = sqlx::query(
+        "UPDATE jobs SET status = 'queued', updated_at = CURRENT_TIMESTAMP WHERE status = 'running'"
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn list_jobs(pool: &Pool<Sqlite>, status: Option<&str>) -> Result<Vec<JobRecord>> {
+    let jobs = sqlx::query_as!(
+        JobRecord,
+        r#"SELECT job_id AS "job_id!", kind, payload, status, attempts, run_after AS "run_after: String", last_error,
+                  created_at AS "created_at: String", updated_at AS "updated_at: String"
+           FROM jobs WHERE ? IS NULL OR status = ? ORDER BY job_id"#,
+        status,
+        status
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(jobs)
+}
+
+/// Queue a dead job again with a fresh retry budget
+///
+/// Returns false if there is no dead job with that id.
+pub async fn revive(pool: &Pool<Sqlite>, job_id: i64) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE jobs SET status = 'queued', attempts = 0, run_after = datetime('now'),
+         updated_at = CURRENT_TIMESTAMP WHERE job_id = ? AND status = 'dead'"
+    )
+    .bind(job_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
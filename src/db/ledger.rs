@@ -0,0 +1,41 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use sqlx::{Pool, Sqlite};
+
+/// A settled withdrawal as booked in the ledger
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct LedgerPayment {
+    pub payment_id: i64,
+    pub card_id: i64,
+    pub card_name: String,
+    /// Slug of the card's organization, whose node paid it
+    pub org_slug: Option<String>,
+    pub amount_msats: i64,
+    pub fee_msats: Option<i64>,
+    /// Service fee charged to the card
+    pub service_fee_msats: i64,
+    pub payment_time: String,
+    pub payment_hash: Option<String>,
+    pub memo: Option<String>,
+    /// Transaction of a withdrawal paid on-chain
+    pub onchain_txid: Option<String>,
+}
+
+/// Withdrawals settled between `from` and `to` (UTC days, inclusive), oldest first
+pub async fn settled_payments(pool: &Pool<Sqlite>, from: NaiveDate, to: NaiveDate) -> Result<Vec<LedgerPayment>> {
+    let payments = sqlx::query_as::<_, LedgerPayment>(
+        "SELECT p.payment_id, p.card_id, c.card_name, o.slug AS org_slug, COALESCE(p.amount_msats, 0) AS amount_msats,
+                p.fee_msats, p.service_fee_msats, p.payment_time, p.payment_hash, p.memo, p.onchain_txid
+         FROM card_payments p
+         JOIN cards c ON c.card_id = p.card_id
+         LEFT JOIN organizations o ON o.org_id = c.org_id
+         WHERE p.status = 'settled' AND date(p.payment_time) BETWEEN ? AND ?
+         ORDER BY p.payment_time, p.payment_id"
+    )
+    .bind(from.format("%Y-%m-%d").to_string())
+    .bind(to.format("%Y-%m-%d").to_string())
+    .fetch_all(pool)
+    .await?;
+
+    Ok(payments)
+}
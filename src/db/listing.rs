@@ -0,0 +1,196 @@
+//! Filtered, paginated listings of cards, payments and taps for admin frontends
+
+use serde::Serialize;
+use sqlx::{Pool, QueryBuilder, Sqlite};
+use anyhow::Result;
+
+use crate::db::models::{CardPayment, CardTap, PaymentStatus};
+
+/// A card as listed, without its keys
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct CardSummary {
+    pub card_id: i64,
+    pub card_name: String,
+    pub uid: String,
+    pub enabled: bool,
+    pub last_counter: i64,
+    pub tx_limit_sats: i64,
+    pub day_limit_sats: i64,
+    pub balance_mode: bool,
+    pub balance_msats: i64,
+    pub funded_msats: i64,
+    pub spent_msats: i64,
+    pub org_id: Option<i64>,
+    pub owner_id: Option<i64>,
+    pub created_at: Option<String>,
+    pub erased_at: Option<String>,
+    pub reported_lost_at: Option<String>,
+    pub frozen_at: Option<String>,
+    /// End of the freeze, `None` for one that lasts until the card is unfrozen
+    pub frozen_until: Option<String>,
+    /// Set while the card is blocked as a suspected clone
+    pub clone_flagged_at: Option<String>,
+    pub deleted_at: Option<String>,
+}
+
+/// Cards matching all of the set fields
+#[derive(Debug, Clone, Default)]
+pub struct CardFilter {
+    pub card_ids: Option<Vec<i64>>,
+    pub enabled: Option<bool>,
+    /// Whether the card is marked lost
+    pub lost: Option<bool>,
+    /// Whether the card is frozen
+    pub frozen: Option<bool>,
+    /// Whether the card is flagged as a suspected clone
+    pub clone_flagged: Option<bool>,
+    pub org_id: Option<i64>,
+    pub owner_id: Option<i64>,
+    pub tag: Option<String>,
+    /// Part of the card name, case-insensitive
+    pub name: Option<String>,
+    pub include_erased: bool,
+    pub include_deleted: bool,
+}
+
+/// Payments matching all of the set fields, `since` and `until` bounding when the session was created
+#[derive(Debug, Clone, Default)]
+pub struct PaymentFilter {
+    pub card_id: Option<i64>,
+    pub statuses: Option<Vec<PaymentStatus>>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TapFilter {
+    pub card_id: Option<i64>,
+    pub success: Option<bool>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+}
+
+/// One page of a listing and the number of items on all pages
+#[derive(Debug, Clone, Serialize)]
+pub struct Page<T> {
+    pub total: i64,
+    pub items: Vec<T>,
+}
+
+/// Cards in order of their IDs, `limit` of them after skipping `offset`
+pub async fn cards(pool: &Pool<Sqlite>, filter: &CardFilter, limit: i64, offset: i64) -> Result<Page<CardSummary>> {
+    let filtered = |select: &str| {
+        let mut query = QueryBuilder::<Sqlite>::new(select);
+        query.push(" FROM cards WHERE 1 = 1");
+        if let Some(ids) = &filter.card_ids {
+            query.push(" AND card_id IN (SELECT value FROM json_each(");
+            query.push_bind(serde_json::to_string(ids).unwrap_or_default());
+            query.push("))");
+        }
+        if let Some(enabled) = filter.enabled {
+            query.push(" AND enabled = ").push_bind(enabled);
+        }
+        if let Some(lost) = filter.lost {
+            query.push(if lost { " AND reported_lost_at IS NOT NULL" } else { " AND reported_lost_at IS NULL" });
+        }
+        if let Some(frozen) = filter.frozen {
+            query.push(if frozen { " AND frozen_at IS NOT NULL" } else { " AND frozen_at IS NULL" });
+        }
+        if let Some(clone_flagged) = filter.clone_flagged {
+            query.push(if clone_flagged { " AND clone_flagged_at IS NOT NULL" } else { " AND clone_flagged_at IS NULL" });
+        }
+        if let Some(org_id) = filter.org_id {
+            query.push(" AND org_id = ").push_bind(org_id);
+        }
+        if let Some(owner_id) = filter.owner_id {
+            query.push(" AND owner_id = ").push_bind(owner_id);
+        }
+        if let Some(tag) = &filter.tag {
+            query.push(" AND card_id IN (SELECT card_id FROM card_tags WHERE tag = ");
+            query.push_bind(tag.trim().to_string()).push(")");
+        }
+        if let Some(name) = &filter.name {
+            query.push(" AND instr(lower(card_name), lower(").push_bind(name.clone()).push(")) > 0");
+        }
+        if !filter.include_erased {
+            query.push(" AND erased_at IS NULL");
+        }
+        if !filter.include_deleted {
+            query.push(" AND deleted_at IS NULL");
+        }
+        query
+    };
+
+    let total = filtered("SELECT COUNT(*)").build_query_scalar().fetch_one(pool).await?;
+    let mut query = filtered(
+        "SELECT card_id, card_name, uid, enabled, last_counter, tx_limit_sats, day_limit_sats, balance_mode,
+                balance_msats, funded_msats, spent_msats, org_id, owner_id, created_at, erased_at, reported_lost_at,
+                frozen_at, frozen_until, clone_flagged_at, deleted_at"
+    );
+    query.push(" ORDER BY card_id LIMIT ").push_bind(limit).push(" OFFSET ").push_bind(offset);
+    let items = query.build_query_as().fetch_all(pool).await?;
+
+    Ok(Page { total, items })
+}
+
+/// Payments, newest first
+pub async fn payments(pool: &Pool<Sqlite>, filter: &PaymentFilter, limit: i64, offset: i64) -> Result<Page<CardPayment>> {
+    let filtered = |select: &str| {
+        let mut query = QueryBuilder::<Sqlite>::new(select);
+        query.push(" FROM card_payments WHERE 1 = 1");
+        if let Some(card_id) = filter.card_id {
+            query.push(" AND card_id = ").push_bind(card_id);
+        }
+        if let Some(statuses) = &filter.statuses {
+            query.push(" AND status IN (SELECT value FROM json_each(");
+            query.push_bind(serde_json::to_string(statuses).unwrap_or_default());
+            query.push("))");
+        }
+        if let Some(since) = &filter.since {
+            query.push(" AND created_at >= datetime(").push_bind(since.clone()).push(")");
+        }
+        if let Some(until) = &filter.until {
+            query.push(" AND created_at < datetime(").push_bind(until.clone()).push(")");
+        }
+        query
+    };
+
+    let total = filtered("SELECT COUNT(*)").build_query_scalar().fetch_one(pool).await?;
+    let mut query = filtered(
+        "SELECT payment_id, card_id, k1, invoice, amount_msats, payment_time, created_at, payment_hash, preimage,
+                status, failure_reason, min_withdrawable_msats, max_withdrawable_msats, attempts, memo,
+                onchain_address, onchain_txid"
+    );
+    query.push(" ORDER BY payment_id DESC LIMIT ").push_bind(limit).push(" OFFSET ").push_bind(offset);
+    let items = query.build_query_as().fetch_all(pool).await?;
+
+    Ok(Page { total, items })
+}
+
+/// Taps, newest first
+pub async fn taps(pool: &Pool<Sqlite>, filter: &TapFilter, limit: i64, offset: i64) -> Result<Page<CardTap>> {
+    let filtered = |select: &str| {
+        let mut query = QueryBuilder::<Sqlite>::new(select);
+        query.push(" FROM card_taps WHERE 1 = 1");
+        if let Some(card_id) = filter.card_id {
+            query.push(" AND card_id = ").push_bind(card_id);
+        }
+        if let Some(success) = filter.success {
+            query.push(" AND success = ").push_bind(success);
+        }
+        if let Some(since) = &filter.since {
+            query.push(" AND created_at >= datetime(").push_bind(since.clone()).push(")");
+        }
+        if let Some(until) = &filter.until {
+            query.push(" AND created_at < datetime(").push_bind(until.clone()).push(")");
+        }
+        query
+    };
+
+    let total = filtered("SELECT COUNT(*)").build_query_scalar().fetch_one(pool).await?;
+    let mut query = filtered("SELECT tap_id, card_id, uid, counter, success, reason, created_at");
+    query.push(" ORDER BY tap_id DESC LIMIT ").push_bind(limit).push(" OFFSET ").push_bind(offset);
+    let items = query.build_query_as().fetch_all(pool).await?;
+
+    Ok(Page { total, items })
+}
@@ -0,0 +1,115 @@
+use std::collections::HashSet;
+
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+use serde_json::json;
+
+use crate::db::{audit, models::LostReport};
+
+/// Disable a card, mark it lost, expire its open sessions and record the report, all audited as `actor`
+///
+/// Returns `None` if there is no such card. A card reported again keeps the
+/// time of its first report.
+pub async fn report_lost(
+    pool: &Pool<Sqlite>,
+    card_id: i64,
+    source: &str,
+    note: Option<&str>,
+    actor: &str,
+) -> Result<Option<LostReport>> {
+    let mut tx = pool.begin().await?;
+
+    let updated = sqlx::query(
+        "UPDATE cards SET enabled = 0, holder_disabled = 0, reported_lost_at = COALESCE(reported_lost_at, CURRENT_TIMESTAMP)
+         WHERE card_id = ? AND erased_at IS NULL"
+    )
+    .bind(card_id)
+    .execute(&mut *tx)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(None);
+    }
+    // Sessions opened before the report can't be paid anymore
+    sqlx::query("UPDATE card_payments SET status = 'expired' WHERE card_id = ? AND status IN ('created', 'failed')")
+        .bind(card_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let report = sqlx::query_as!(
+        LostReport,
+        r#"INSERT INTO lost_reports (card_id, source, note) VALUES (?, ?, ?)
+           RETURNING report_id AS "report_id!", card_id, source, note, created_at AS "created_at: String""#,
+        card_id,
+        source,
+        note
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    audit::record(
+        &mut tx,
+        actor,
+        "report_lost",
+        &json!({ "card_id": card_id, "report_id": report.report_id, "source": source, "note": note }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(report))
+}
+
+/// When the card was reported lost, `None` if it isn't
+pub async fn get_reported_lost_at(pool: &Pool<Sqlite>, card_id: i64) -> Result<Option<String>> {
+    let reported_lost_at = sqlx::query_scalar("SELECT reported_lost_at FROM cards WHERE card_id = ?")
+        .bind(card_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(reported_lost_at.flatten())
+}
+
+/// IDs of the cards currently marked lost
+pub async fn list_lost_card_ids(pool: &Pool<Sqlite>) -> Result<HashSet<i64>> {
+    let card_ids: Vec<i64> = sqlx::query_scalar("SELECT card_id FROM cards WHERE reported_lost_at IS NOT NULL")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(card_ids.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{bulk::{self, BulkAction, CardSelector}, queries, run_migrations};
+
+    #[tokio::test]
+    async fn test_lost_until_enabled() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let key = "00000000000000000000000000000000";
+        let card_id = queries::insert_card(
+            &pool, "04996c6a926980", key, key, key, key, key, "Lost", 1000, 10000, true, false, "code", None, None,
+            chrono::Utc::now(),
+        )
+        .await
+        .unwrap();
+
+        let first = report_lost(&pool, card_id, "operator", Some("stolen"), "api").await.unwrap().unwrap();
+        let reported_lost_at = get_reported_lost_at(&pool, card_id).await.unwrap();
+        assert!(reported_lost_at.is_some());
+        assert!(!queries::get_card_by_id(&pool, card_id).await.unwrap().unwrap().enabled);
+
+        let second = report_lost(&pool, card_id, "cardholder", None, "cardholder").await.unwrap().unwrap();
+        assert_ne!(first.report_id, second.report_id);
+        assert_eq!(get_reported_lost_at(&pool, card_id).await.unwrap(), reported_lost_at);
+        assert_eq!(list_lost_card_ids(&pool).await.unwrap(), HashSet::from([card_id]));
+
+        let selector = CardSelector { card_ids: Some(vec![card_id]), tag: None };
+        bulk::apply(&pool, &selector, &BulkAction::Disable, "api").await.unwrap();
+        assert!(get_reported_lost_at(&pool, card_id).await.unwrap().is_some());
+        bulk::apply(&pool, &selector, &BulkAction::Enable, "api").await.unwrap();
+        assert_eq!(get_reported_lost_at(&pool, card_id).await.unwrap(), None);
+
+        assert!(report_lost(&pool, card_id + 1, "operator", None, "api").await.unwrap().is_none());
+    }
+}
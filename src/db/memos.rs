@@ -0,0 +1,43 @@
+use serde_json::json;
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+
+use crate::db::audit;
+
+/// Longest memo accepted from the operator; invoice descriptions are cut to it
+pub const MAX_MEMO_LENGTH: usize = 500;
+
+/// Set or clear the memo of a payment, returns false if there is no such payment
+pub async fn set_memo(pool: &Pool<Sqlite>, payment_id: i64, memo: Option<&str>, actor: &str) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+
+    let updated = sqlx::query("UPDATE card_payments SET memo = ? WHERE payment_id = ?")
+        .bind(memo)
+        .bind(payment_id)
+        .execute(&mut *tx)
+        .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
+    audit::record(&mut tx, actor, "set_payment_memo", &json!({ "payment_id": payment_id, "memo": memo })).await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// Take the description of the wallet's invoice as memo, unless the payment has one
+pub async fn capture_invoice_memo(pool: &Pool<Sqlite>, payment_id: i64, description: &str) -> Result<()> {
+    let description: String = description.trim().chars().take(MAX_MEMO_LENGTH).collect();
+    if description.is_empty() {
+        return Ok(());
+    }
+
+    sqlx::query("UPDATE card_payments SET memo = ? WHERE payment_id = ? AND memo IS NULL")
+        .bind(description)
+        .bind(payment_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+
+use crate::db::audit;
+
+/// Key-value data of a card; values are strings, numbers or booleans
+pub type Metadata = Map<String, Value>;
+
+const MAX_KEY_LENGTH: usize = 64;
+/// Upper bound on the stored JSON of a card's metadata
+const MAX_SIZE: usize = 4096;
+
+/// A card with its metadata
+#[derive(Debug, Clone, Serialize)]
+pub struct CardMetadata {
+    pub card_id: i64,
+    pub card_name: String,
+    pub metadata: Metadata,
+}
+
+/// Letters, digits, `_`, `-` and `.`
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.len() <= MAX_KEY_LENGTH
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+pub fn is_valid(metadata: &Metadata) -> bool {
+    metadata.iter().all(|(key, value)| {
+        is_valid_key(key) && matches!(value, Value::String(_) | Value::Number(_) | Value::Bool(_))
+    }) && Value::Object(metadata.clone()).to_string().len() <= MAX_SIZE
+}
+
+/// Apply `patch` to `metadata`, where a `null` value removes the key
+pub fn merge(metadata: &mut Metadata, patch: Metadata) {
+    for (key, value) in patch {
+        match value {
+            Value::Null => metadata.remove(&key),
+            value => metadata.insert(key, value),
+        };
+    }
+}
+
+/// Whether `value` is `query`, numbers and booleans compared as written in JSON
+pub fn value_matches(value: &Value, query: &str) -> bool {
+    match value {
+        Value::String(value) => value == query,
+        value => serde_json::to_string(value).is_ok_and(|json| json == query),
+    }
+}
+
+fn parse(metadata: &str) -> Metadata {
+    serde_json::from_str(metadata).unwrap_or_default()
+}
+
+/// Metadata of a card, `None` if there is no such card
+pub async fn get_card_metadata(pool: &Pool<Sqlite>, card_id: i64) -> Result<Option<Metadata>> {
+    let metadata: Option<String> = sqlx::query_scalar("SELECT metadata FROM cards WHERE card_id = ? AND erased_at IS NULL")
+        .bind(card_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(metadata.as_deref().map(parse))
+}
+
+/// Replace the metadata of a card
+///
+/// Returns false if there is no such card.
+pub async fn set_card_metadata(pool: &Pool<Sqlite>, card_id: i64, metadata: &Metadata, actor: &str) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+
+    let updated = sqlx::query("UPDATE cards SET metadata = ? WHERE card_id = ? AND erased_at IS NULL")
+        .bind(Value::Object(metadata.clone()).to_string())
+        .bind(card_id)
+        .execute(&mut *tx)
+        .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
+    audit::record(&mut tx, actor, "set_metadata", &json!({ "card_id": card_id, "metadata": metadata })).await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// Cards that have `key`, with the value `value` if given
+pub async fn find_cards(pool: &Pool<Sqlite>, key: &str, value: Option<&str>) -> Result<Vec<CardMetadata>> {
+    let path = format!("$.\"{}\"", key);
+    let rows: Vec<(i64, String, String)> = sqlx::query_as(
+        "SELECT card_id, card_name, metadata FROM cards
+         WHERE erased_at IS NULL AND json_type(metadata, ?) IS NOT NULL
+         ORDER BY card_id"
+    )
+    .bind(path)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(card_id, card_name, metadata)| CardMetadata { card_id, card_name, metadata: parse(&metadata) })
+        .filter(|card| value.is_none_or(|value| card.metadata.get(key).is_some_and(|v| value_matches(v, value))))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(value: Value) -> Metadata {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_is_valid() {
+        assert!(is_valid(&metadata(json!({"employee_id": "E-1001", "table": 12, "vip": true}))));
+        assert!(!is_valid(&metadata(json!({"employee id": "E-1001"}))));
+        assert!(!is_valid(&metadata(json!({"": "E-1001"}))));
+        assert!(!is_valid(&metadata(json!({"address": {"city": "Berlin"}}))));
+        assert!(!is_valid(&metadata(json!({"note": "x".repeat(MAX_SIZE)}))));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut current = metadata(json!({"employee_id": "E-1001", "table": 12}));
+        merge(&mut current, metadata(json!({"table": null, "customer_id": "cus_42"})));
+        assert_eq!(Value::Object(current), json!({"employee_id": "E-1001", "customer_id": "cus_42"}));
+    }
+
+    #[test]
+    fn test_value_matches() {
+        assert!(value_matches(&json!("E-1001"), "E-1001"));
+        assert!(value_matches(&json!(12), "12"));
+        assert!(value_matches(&json!(true), "true"));
+        assert!(!value_matches(&json!("12"), "\"12\""));
+    }
+}
@@ -1,16 +1,268 @@
+pub mod accounts;
+pub mod audit;
+pub mod bulk;
+pub mod channels;
+pub mod clones;
+pub mod deletion;
+pub mod descriptions;
+pub mod doctor;
+pub mod feed;
+pub mod fees;
+pub mod fiat;
+pub mod freeze;
+pub mod idempotency;
+pub mod invites;
+pub mod jobs;
+pub mod ledger;
+pub mod metadata;
+pub mod listing;
+pub mod lost;
+pub mod memos;
 pub mod models;
+pub mod nostr;
+pub mod onchain;
+pub mod operator_keys;
+pub mod organizations;
+pub mod privacy;
 pub mod queries;
+pub mod receipts;
+pub mod reconcile;
+pub mod rotation;
+pub mod seed;
+pub mod sessions;
+pub mod stats;
+pub mod tags;
+pub mod taps;
+pub mod tokens;
+pub mod topups;
+pub mod users;
+pub mod wallet;
+pub mod webhooks;
+
+use sqlx::{
+    migrate::{Migration, Migrator},
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    Pool, Sqlite,
+};
+use anyhow::{Result, bail};
+use std::{str::FromStr, time::Duration};
+
+use crate::config::DatabaseConfig;
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// `sqlx::query_as!` for [`models::Card`], followed by the rest of the query
+///
+/// The column list carries the overrides SQLite's type inference needs: a
+/// type for DATETIME columns and non-null for the primary key. `$rest`
+/// continues after `FROM cards`.
+macro_rules! query_card {
+    ($rest:tt $(, $arg:expr)* $(,)?) => {
+        sqlx::query_as!(
+            $crate::db::models::Card,
+            r#"SELECT card_id AS "card_id!", uid, k0_auth_key, k1_decrypt_key, k2_cmac_key, k3, k4, last_counter,
+                      enabled, tx_limit_sats, day_limit_sats, card_name, one_time_code,
+                      one_time_code_expiry AS "one_time_code_expiry: String", one_time_code_used,
+                      created_at AS "created_at: String", balance_mode, balance_msats
+               FROM cards "# + $rest
+            $(, $arg)*
+        )
+    };
+}
+pub(crate) use query_card;
+
+/// `sqlx::query_as!` for [`models::CardPayment`], `$rest` continues after `FROM card_payments`
+macro_rules! query_payment {
+    ($rest:tt $(, $arg:expr)* $(,)?) => {
+        sqlx::query_as!(
+            $crate::db::models::CardPayment,
+            r#"SELECT payment_id AS "payment_id!", card_id, k1, invoice, amount_msats,
+                      payment_time AS "payment_time: String", created_at AS "created_at: String",
+                      payment_hash, preimage, status AS "status: _", failure_reason, min_withdrawable_msats, max_withdrawable_msats,
+                      attempts, memo, onchain_address, onchain_txid
+               FROM card_payments "# + $rest
+            $(, $arg)*
+        )
+    };
+}
+pub(crate) use query_payment;
+
+/// `sqlx::query!` mapped to [`models::User`], `$rest` continues after `FROM users`
+///
+/// A plain `query_as!` can't fill the flattened [`models::UserPolicy`].
+macro_rules! query_user {
+    ($rest:tt $(, $arg:expr)* $(,)?) => {
+        sqlx::query!(
+            r#"SELECT user_id AS "user_id!", username, password_hash, enabled, created_at AS "created_at: String",
+                      max_cards, default_tx_limit_sats, default_day_limit_sats, max_tx_limit_sats, max_day_limit_sats,
+                      invite_id, erased_at AS "erased_at: String"
+               FROM users "# + $rest
+            $(, $arg)*
+        )
+        .map(|row| $crate::db::models::User {
+            user_id: row.user_id,
+            username: row.username,
+            password_hash: row.password_hash,
+            enabled: row.enabled,
+            created_at: row.created_at,
+            policy: $crate::db::models::UserPolicy {
+                max_cards: row.max_cards,
+                default_tx_limit_sats: row.default_tx_limit_sats,
+                default_day_limit_sats: row.default_day_limit_sats,
+                max_tx_limit_sats: row.max_tx_limit_sats,
+                max_day_limit_sats: row.max_day_limit_sats,
+            },
+            invite_id: row.invite_id,
+            erased_at: row.erased_at,
+        })
+    };
+}
+pub(crate) use query_user;
+
+/// `sqlx::query_as!` for [`models::Organization`], `$rest` continues after `FROM organizations`
+macro_rules! query_organization {
+    ($rest:tt $(, $arg:expr)* $(,)?) => {
+        sqlx::query_as!(
+            $crate::db::models::Organization,
+            r#"SELECT org_id AS "org_id!", slug, name, lightning_backend, created_at AS "created_at: String", domain
+               FROM organizations "# + $rest
+            $(, $arg)*
+        )
+    };
+}
+pub(crate) use query_organization;
+
+/// `sqlx::query_as!` for [`models::WebhookEvent`], `$rest` continues after `FROM webhook_events`
+macro_rules! query_event {
+    ($rest:tt $(, $arg:expr)* $(,)?) => {
+        sqlx::query_as!(
+            $crate::db::models::WebhookEvent,
+            r#"SELECT event_id AS "event_id!", event, payload, status, attempts, last_error,
+                      created_at AS "created_at: String", delivered_at AS "delivered_at: String"
+               FROM webhook_events "# + $rest
+            $(, $arg)*
+        )
+    };
+}
+pub(crate) use query_event;
+
+/// `sqlx::query_as!` for [`models::UserNotificationChannel`], `$rest` continues after `FROM user_notification_channels`
+macro_rules! query_channel {
+    ($rest:tt $(, $arg:expr)* $(,)?) => {
+        sqlx::query_as!(
+            $crate::db::models::UserNotificationChannel,
+            r#"SELECT channel_id AS "channel_id!", user_id, kind, target, events, created_at AS "created_at: String"
+               FROM user_notification_channels "# + $rest
+            $(, $arg)*
+        )
+    };
+}
+pub(crate) use query_channel;
+
+/// `sqlx::query_as!` for [`fees::FeePayout`], `$rest` continues after `FROM fee_payouts`
+macro_rules! query_payout {
+    ($rest:tt $(, $arg:expr)* $(,)?) => {
+        sqlx::query_as!(
+            $crate::db::fees::FeePayout,
+            r#"SELECT payout_id AS "payout_id!", org_id, amount_msats, destination, status, payment_hash,
+                      routing_fee_msats, error, created_at AS "created_at: String", paid_at AS "paid_at: String"
+               FROM fee_payouts "# + $rest
+            $(, $arg)*
+        )
+    };
+}
+pub(crate) use query_payout;
+
+/// Connect to the database, applying pending migrations if `auto_migrate` is set
+///
+/// Without auto-migration the schema must already be current, so the server
+/// never runs against a database it doesn't understand.
+pub async fn init_pool(database: &DatabaseConfig) -> Result<Pool<Sqlite>> {
+    let pool = connect(database).await?;
+
+    if !database.no_auto_migrate {
+        run_migrations(&pool).await?;
+    } else {
+        let pending = pending_migrations(&pool).await?;
+        if !pending.is_empty() {
+            bail!(
+                "Database has {} pending migration(s), run `lnurlw-server migrate` first",
+                pending.len()
+            );
+        }
+    }
 
-use sqlx::{Pool, Sqlite, SqlitePool};
-use anyhow::Result;
-
-pub async fn init_pool(database_url: &str) -> Result<Pool<Sqlite>> {
-    let pool = SqlitePool::connect(database_url).await?;
-    
-    // Run migrations
-    sqlx::migrate!("./migrations")
-        .run(&pool)
-        .await?;
-    
     Ok(pool)
-}
\ No newline at end of file
+}
+
+/// Connect with the configured pool settings without touching the schema
+pub async fn connect(database: &DatabaseConfig) -> Result<Pool<Sqlite>> {
+    if database.db_min_connections > database.db_max_connections {
+        bail!(
+            "--db-min-connections ({}) exceeds --db-max-connections ({})",
+            database.db_min_connections,
+            database.db_max_connections
+        );
+    }
+
+    let options = SqliteConnectOptions::from_str(&database.database_url)?
+        .statement_cache_capacity(database.db_statement_cache_size);
+
+    Ok(SqlitePoolOptions::new()
+        .max_connections(database.db_max_connections)
+        .min_connections(database.db_min_connections)
+        .acquire_timeout(Duration::from_secs(database.db_acquire_timeout_secs))
+        .connect_with(options)
+        .await?)
+}
+
+pub async fn run_migrations(pool: &Pool<Sqlite>) -> Result<()> {
+    MIGRATOR.run(pool).await?;
+    Ok(())
+}
+
+/// Migrated in-memory database holding one enabled limits-mode card with the given UID
+#[cfg(test)]
+pub async fn test_pool_with_card(uid: &str) -> (Pool<Sqlite>, i64) {
+    let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+    run_migrations(&pool).await.unwrap();
+    let card_id = insert_test_card(&pool, uid, "Test").await;
+    (pool, card_id)
+}
+
+/// Insert an enabled limits-mode card with all-zero keys, using `name` as its one-time code too
+#[cfg(test)]
+pub async fn insert_test_card(pool: &Pool<Sqlite>, uid: &str, name: &str) -> i64 {
+    let key = "00000000000000000000000000000000";
+    queries::insert_card(pool, uid, key, key, key, key, key, name, 1000, 10000, true, false, name, None, None, chrono::Utc::now())
+        .await
+        .unwrap()
+}
+
+/// Version of the newest migration this build knows about
+pub fn latest_schema_version() -> i64 {
+    MIGRATOR.iter().map(|m| m.version).max().unwrap_or(0)
+}
+
+/// Migrations that haven't been applied to the database yet
+pub async fn pending_migrations(pool: &Pool<Sqlite>) -> Result<Vec<&'static Migration>> {
+    // A fresh database doesn't have the bookkeeping table, don't create it just to look
+    let (has_table,): (bool,) = sqlx::query_as(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'"
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let applied: Vec<i64> = if has_table {
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success = 1")
+            .fetch_all(pool)
+            .await?
+    } else {
+        Vec::new()
+    };
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|m| m.migration_type.is_up_migration() && !applied.contains(&m.version))
+        .collect())
+}
@@ -0,0 +1,35 @@
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+use serde_json::json;
+
+use crate::db::audit;
+
+/// Link a card to its holder's Nostr key (hex), or unlink it with `None`
+///
+/// Returns false if there is no such card.
+pub async fn set_card_nostr_pubkey(pool: &Pool<Sqlite>, card_id: i64, pubkey: Option<&str>, actor: &str) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+
+    let updated = sqlx::query("UPDATE cards SET nostr_pubkey = ? WHERE card_id = ? AND erased_at IS NULL")
+        .bind(pubkey)
+        .bind(card_id)
+        .execute(&mut *tx)
+        .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
+    audit::record(&mut tx, actor, "set_nostr_pubkey", &json!({ "card_id": card_id, "nostr_pubkey": pubkey })).await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+pub async fn get_card_nostr_pubkey(pool: &Pool<Sqlite>, card_id: i64) -> Result<Option<String>> {
+    let pubkey = sqlx::query_scalar("SELECT nostr_pubkey FROM cards WHERE card_id = ?")
+        .bind(card_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(pubkey.flatten())
+}
@@ -0,0 +1,75 @@
+use serde_json::json;
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+
+use crate::db::audit;
+
+/// The card's on-chain fallback address, `None` inside if it has none
+///
+/// Returns `None` if there is no such card.
+pub async fn get_card_address(pool: &Pool<Sqlite>, card_id: i64) -> Result<Option<Option<String>>> {
+    let address = sqlx::query_scalar("SELECT onchain_fallback_address FROM cards WHERE card_id = ? AND erased_at IS NULL")
+        .bind(card_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(address)
+}
+
+/// Set the address the card's withdrawals fall back to, `None` to remove it
+///
+/// Returns false if there is no such card.
+pub async fn set_card_address(pool: &Pool<Sqlite>, card_id: i64, address: Option<&str>, actor: &str) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+
+    let updated = sqlx::query("UPDATE cards SET onchain_fallback_address = ? WHERE card_id = ? AND erased_at IS NULL")
+        .bind(address)
+        .bind(card_id)
+        .execute(&mut *tx)
+        .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
+    audit::record(&mut tx, actor, "set_onchain_fallback", &json!({ "card_id": card_id, "address": address })).await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// Reserve a withdrawal session for an on-chain payout, like [`super::queries::reserve_payment`] does for an invoice
+pub async fn reserve_payment(
+    pool: &Pool<Sqlite>,
+    payment_id: i64,
+    address: &str,
+    amount_msats: i64,
+    max_attempts: i64,
+    opened_after: chrono::DateTime<chrono::Utc>,
+) -> Result<bool> {
+    let opened_after = opened_after.format("%Y-%m-%d %H:%M:%S").to_string();
+    let result = sqlx::query(
+        "UPDATE card_payments SET invoice = NULL, amount_msats = ?, payment_hash = NULL, onchain_address = ?,
+                status = 'invoice_attached', failure_reason = NULL, attempts = attempts + 1
+         WHERE payment_id = ? AND status IN ('created', 'failed') AND attempts < ? AND (attempts = 0 OR created_at >= ?)",
+    )
+    .bind(amount_msats)
+    .bind(address)
+    .bind(payment_id)
+    .bind(max_attempts)
+    .bind(opened_after)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Record the transaction an on-chain payout was sent in
+pub async fn set_txid(pool: &Pool<Sqlite>, payment_id: i64, txid: &str) -> Result<()> {
+    sqlx::query("UPDATE card_payments SET onchain_txid = ? WHERE payment_id = ?")
+        .bind(txid)
+        .bind(payment_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+
+use crate::db::models::OperatorApiKey;
+
+pub async fn create_key(pool: &Pool<Sqlite>, name: &str, key_hash: &str, role: &str) -> Result<i64> {
+    let key_id = sqlx::query_scalar(
+        "INSERT INTO operator_api_keys (name, key_hash, role) VALUES (?, ?, ?) RETURNING key_id"
+    )
+    .bind(name)
+    .bind(key_hash)
+    .bind(role)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(key_id)
+}
+
+pub async fn list_keys(pool: &Pool<Sqlite>) -> Result<Vec<OperatorApiKey>> {
+    let keys = sqlx::query_as::<_, OperatorApiKey>(
+        "SELECT key_id, name, role, created_at, last_used_at, revoked_at FROM operator_api_keys ORDER BY key_id"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(keys)
+}
+
+/// Stop a key from working, `false` if it doesn't exist or was revoked before
+pub async fn revoke_key(pool: &Pool<Sqlite>, key_id: i64) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE operator_api_keys SET revoked_at = CURRENT_TIMESTAMP WHERE key_id = ? AND revoked_at IS NULL"
+    )
+    .bind(key_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Role of an active key, recording its use
+pub async fn use_key(pool: &Pool<Sqlite>, key_hash: &str) -> Result<Option<String>> {
+    let role = sqlx::query_scalar(
+        "UPDATE operator_api_keys SET last_used_at = CURRENT_TIMESTAMP
+         WHERE key_hash = ? AND revoked_at IS NULL RETURNING role"
+    )
+    .bind(key_hash)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(role)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::run_migrations;
+
+    #[tokio::test]
+    async fn test_revoked_key_stops_working() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+
+        let key_id = create_key(&pool, "Backoffice", "hash", "viewer").await.unwrap();
+        assert_eq!(use_key(&pool, "hash").await.unwrap().as_deref(), Some("viewer"));
+        assert_eq!(use_key(&pool, "other").await.unwrap(), None);
+        assert!(list_keys(&pool).await.unwrap()[0].last_used_at.is_some());
+
+        assert!(revoke_key(&pool, key_id).await.unwrap());
+        assert!(!revoke_key(&pool, key_id).await.unwrap());
+        assert_eq!(use_key(&pool, "hash").await.unwrap(), None);
+    }
+}
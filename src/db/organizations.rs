@@ -0,0 +1,156 @@
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+
+use crate::db::{models::Organization, query_organization};
+
+/// Create an organization, or return `None` if the slug or domain is taken
+pub async fn create_organization(
+    pool: &Pool<Sqlite>,
+    slug: &str,
+    name: &str,
+    lightning_backend: &str,
+    domain: Option<&str>,
+) -> Result<Option<i64>> {
+    let org_id = sqlx::query_scalar(
+        "INSERT INTO organizations (slug, name, lightning_backend, domain) VALUES (?, ?, ?, ?)
+         ON CONFLICT DO NOTHING RETURNING org_id"
+    )
+    .bind(slug)
+    .bind(name)
+    .bind(lightning_backend)
+    .bind(domain)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(org_id)
+}
+
+pub async fn get_organization(pool: &Pool<Sqlite>, org_id: i64) -> Result<Option<Organization>> {
+    let org = query_organization!("WHERE org_id = ?", org_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(org)
+}
+
+pub async fn get_organization_by_slug(pool: &Pool<Sqlite>, slug: &str) -> Result<Option<Organization>> {
+    let org = query_organization!("WHERE slug = ?", slug)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(org)
+}
+
+pub async fn list_organizations(pool: &Pool<Sqlite>) -> Result<Vec<(Organization, i64)>> {
+    let orgs = query_organization!("ORDER BY org_id")
+        .fetch_all(pool)
+        .await?;
+
+    let card_counts: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT org_id, COUNT(*) FROM cards WHERE org_id IS NOT NULL GROUP BY org_id"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(orgs
+        .into_iter()
+        .map(|org| {
+            let cards = card_counts
+                .iter()
+                .find(|(org_id, _)| *org_id == org.org_id)
+                .map_or(0, |(_, count)| *count);
+            (org, cards)
+        })
+        .collect())
+}
+
+pub async fn get_organization_by_domain(pool: &Pool<Sqlite>, domain: &str) -> Result<Option<Organization>> {
+    let org = query_organization!("WHERE domain = ?", domain)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(org)
+}
+
+/// Serve an organization on its own domain, or on the server's with `None`
+///
+/// Returns false if another organization has the domain already.
+pub async fn set_organization_domain(pool: &Pool<Sqlite>, org_id: i64, domain: Option<&str>) -> Result<bool> {
+    let result = sqlx::query("UPDATE OR IGNORE organizations SET domain = ? WHERE org_id = ?")
+        .bind(domain)
+        .bind(org_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Pay the organization's cards from another backend, `false` if it doesn't exist
+///
+/// Running servers build the new backend on the next payment of one of its cards.
+pub async fn set_organization_backend(pool: &Pool<Sqlite>, org_id: i64, lightning_backend: &str) -> Result<bool> {
+    let result = sqlx::query("UPDATE organizations SET lightning_backend = ? WHERE org_id = ?")
+        .bind(lightning_backend)
+        .bind(org_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// The own domain of an organization, `None` for the server's cards or an organization without one
+pub async fn get_org_domain(pool: &Pool<Sqlite>, org_id: Option<i64>) -> Result<Option<String>> {
+    let Some(org_id) = org_id else {
+        return Ok(None);
+    };
+
+    let domain = sqlx::query_scalar("SELECT domain FROM organizations WHERE org_id = ?")
+        .bind(org_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(domain.flatten())
+}
+
+/// The domain a card's URLs are built on, if its organization has its own
+pub async fn get_card_domain(pool: &Pool<Sqlite>, card_id: i64) -> Result<Option<String>> {
+    let domain = sqlx::query_scalar(
+        "SELECT organizations.domain FROM cards JOIN organizations ON organizations.org_id = cards.org_id
+         WHERE cards.card_id = ?"
+    )
+    .bind(card_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(domain.flatten())
+}
+
+/// The organization a card belongs to, `None` for the server's own cards
+pub async fn get_card_org(pool: &Pool<Sqlite>, card_id: i64) -> Result<Option<i64>> {
+    let org_id = sqlx::query_scalar("SELECT org_id FROM cards WHERE card_id = ?")
+        .bind(card_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(org_id.flatten())
+}
+
+/// Whether an enabled card of the organization has the name already
+pub async fn card_name_taken(pool: &Pool<Sqlite>, org_id: i64, card_name: &str) -> Result<bool> {
+    let taken = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM cards WHERE org_id = ? AND card_name = ? AND enabled = 1)")
+        .bind(org_id)
+        .bind(card_name)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(taken)
+}
+
+pub async fn list_org_card_ids(pool: &Pool<Sqlite>, org_id: i64) -> Result<Vec<i64>> {
+    let card_ids = sqlx::query_scalar("SELECT card_id FROM cards WHERE org_id = ? ORDER BY card_id")
+        .bind(org_id)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(card_ids)
+}
@@ -0,0 +1,425 @@
+//! Export and erasure of a user's or card's personal data
+//!
+//! Erasure keeps the rows that accounting adds up (cards with their funded,
+//! spent and balance amounts, payment amounts and hashes, spend per hour) and
+//! removes or overwrites everything that identifies the person: names, UIDs,
+//! invoices, notes, metadata, sessions, keys and notification targets, also
+//! where the audit log recorded them. Erased cards get fresh random keys nobody
+//! knows, so they can't be tapped again, and neither can the card be wiped
+//! with its old keys afterwards.
+
+use serde::Serialize;
+use sqlx::{Pool, Sqlite, SqliteConnection};
+use anyhow::Result;
+
+use crate::db::{
+    audit,
+    metadata::Metadata,
+    models::{AuditEntry, CardPayment, CardTap, CardTopup, LostReport, User, UserApiKey, UserNotificationChannel},
+    query_channel, query_payment, query_user,
+};
+
+/// Audit log details that identify the holder, removed from the entries about an erased card
+const PERSONAL_AUDIT_DETAILS: [&str; 7] = ["uid", "note", "reason", "template", "metadata", "nostr_pubkey", "address"];
+
+/// A card with everything recorded about it, without its keys
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct CardRecord {
+    pub card_id: i64,
+    pub card_name: String,
+    pub uid: String,
+    pub enabled: bool,
+    pub tx_limit_sats: i64,
+    pub day_limit_sats: i64,
+    pub balance_mode: bool,
+    pub balance_msats: i64,
+    pub nostr_pubkey: Option<String>,
+    pub created_at: Option<String>,
+    pub erased_at: Option<String>,
+    pub reported_lost_at: Option<String>,
+    pub frozen_at: Option<String>,
+    pub frozen_until: Option<String>,
+    pub freeze_reason: Option<String>,
+    #[sqlx(skip)]
+    pub tags: Vec<String>,
+    #[sqlx(skip)]
+    pub metadata: Metadata,
+    #[sqlx(skip)]
+    pub payments: Vec<CardPayment>,
+    #[sqlx(skip)]
+    pub topups: Vec<CardTopup>,
+    #[sqlx(skip)]
+    pub taps: Vec<CardTap>,
+    #[sqlx(skip)]
+    pub lost_reports: Vec<LostReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CardExport {
+    pub exported_at: String,
+    pub card: CardRecord,
+    /// Changes made to the card
+    pub audit_log: Vec<AuditEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UserExport {
+    pub exported_at: String,
+    pub user: User,
+    pub cards: Vec<CardRecord>,
+    pub api_keys: Vec<UserApiKey>,
+    pub notification_channels: Vec<UserNotificationChannel>,
+    /// Changes made by the user or to their cards
+    pub audit_log: Vec<AuditEntry>,
+}
+
+/// Outcome of an erasure request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Erasure {
+    /// The IDs of the erased cards
+    Erased(Vec<i64>),
+    NotFound,
+    /// A payment is being made and needs its invoice, try again once it's done
+    PaymentsInFlight,
+}
+
+pub async fn export_card(pool: &Pool<Sqlite>, card_id: i64) -> Result<Option<CardExport>> {
+    let mut conn = pool.acquire().await?;
+    let Some(card) = card_record(&mut conn, card_id).await? else {
+        return Ok(None);
+    };
+    let audit_log = audit_entries(&mut conn, &[card_id], None).await?;
+
+    Ok(Some(CardExport { exported_at: now(), card, audit_log }))
+}
+
+pub async fn export_user(pool: &Pool<Sqlite>, user_id: i64) -> Result<Option<UserExport>> {
+    let mut conn = pool.acquire().await?;
+    let Some(user) = query_user!("WHERE user_id = ?", user_id)
+        .fetch_optional(&mut *conn)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let card_ids = owned_card_ids(&mut conn, user_id).await?;
+    let mut cards = Vec::new();
+    for card_id in &card_ids {
+        cards.extend(card_record(&mut conn, *card_id).await?);
+    }
+
+    let api_keys = sqlx::query_as::<_, UserApiKey>(
+        "SELECT key_id, user_id, name, scopes, created_at, last_used_at, revoked_at
+         FROM user_api_keys WHERE user_id = ? ORDER BY key_id"
+    )
+    .bind(user_id)
+    .fetch_all(&mut *conn)
+    .await?;
+    let notification_channels = query_channel!("WHERE user_id = ? ORDER BY channel_id", user_id)
+        .fetch_all(&mut *conn)
+        .await?;
+    let audit_log = audit_entries(&mut conn, &card_ids, Some(&format!("user:{}", user.username))).await?;
+
+    Ok(Some(UserExport {
+        exported_at: now(),
+        user,
+        cards,
+        api_keys,
+        notification_channels,
+        audit_log,
+    }))
+}
+
+/// Erase the personal data of a card and disable it for good
+///
+/// The card's keys are replaced, so a card that is to be reused has to be
+/// wiped before it's erased.
+pub async fn erase_card(pool: &Pool<Sqlite>, card_id: i64, actor: &str) -> Result<Erasure> {
+    let mut tx = pool.begin().await?;
+
+    let exists: Option<i64> = sqlx::query_scalar("SELECT card_id FROM cards WHERE card_id = ?")
+        .bind(card_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    if exists.is_none() {
+        return Ok(Erasure::NotFound);
+    }
+    if has_payments_in_flight(&mut tx, &[card_id]).await? {
+        return Ok(Erasure::PaymentsInFlight);
+    }
+
+    erase_card_data(&mut tx, card_id).await?;
+    audit::record(&mut tx, actor, "erase_card", &serde_json::json!({ "card_id": card_id })).await?;
+
+    tx.commit().await?;
+
+    Ok(Erasure::Erased(vec![card_id]))
+}
+
+/// Erase a user's account and cards, leaving a disabled placeholder account
+///
+/// The username becomes `erased-<user_id>` and is free for new registrations.
+pub async fn erase_user(pool: &Pool<Sqlite>, user_id: i64, actor: &str) -> Result<Erasure> {
+    let mut tx = pool.begin().await?;
+
+    let username: Option<String> = sqlx::query_scalar("SELECT username FROM users WHERE user_id = ? AND erased_at IS NULL")
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let Some(username) = username else {
+        return Ok(Erasure::NotFound);
+    };
+
+    let card_ids = owned_card_ids(&mut tx, user_id).await?;
+    if has_payments_in_flight(&mut tx, &card_ids).await? {
+        return Ok(Erasure::PaymentsInFlight);
+    }
+    for card_id in &card_ids {
+        erase_card_data(&mut tx, *card_id).await?;
+    }
+
+    let placeholder = format!("erased-{}", user_id);
+    sqlx::query(
+        "UPDATE users SET username = ?, password_hash = '', enabled = 0, erased_at = CURRENT_TIMESTAMP
+         WHERE user_id = ?"
+    )
+    .bind(&placeholder)
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+    for table in ["user_sessions", "user_api_keys", "user_notification_channels"] {
+        sqlx::query(&format!("DELETE FROM {} WHERE user_id = ?", table))
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    // Recorded first, so an erasure the user asked for doesn't name them either
+    audit::record(
+        &mut tx,
+        actor,
+        "erase_user",
+        &serde_json::json!({ "user_id": user_id, "card_ids": card_ids }),
+    )
+    .await?;
+    sqlx::query("UPDATE audit_log SET actor = ? WHERE actor = ?")
+        .bind(format!("user:{}", placeholder))
+        .bind(format!("user:{}", username))
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(Erasure::Erased(card_ids))
+}
+
+async fn erase_card_data(conn: &mut SqliteConnection, card_id: i64) -> Result<()> {
+    // Wallet logins linked to the card identify its holder too
+    let auth_key: Option<String> = sqlx::query_scalar("SELECT auth_key FROM cards WHERE card_id = ?")
+        .bind(card_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    if let Some(auth_key) = auth_key {
+        sqlx::query("DELETE FROM wallet_sessions WHERE linking_key = ?")
+            .bind(&auth_key)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query("UPDATE audit_log SET actor = 'wallet:erased' WHERE actor = ?")
+            .bind(format!("wallet:{}", auth_key))
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    let key = || hex::encode(rand::random::<[u8; 16]>());
+    sqlx::query(
+        "UPDATE cards SET card_name = ?, uid = '', enabled = 0, holder_disabled = 0, auth_key = NULL, nostr_pubkey = NULL, metadata = '{}',
+                          one_time_code = NULL, one_time_code_expiry = NULL, freeze_reason = NULL,
+                          withdraw_description = NULL, onchain_fallback_address = NULL,
+                          k0_auth_key = ?, k1_decrypt_key = ?, k2_cmac_key = ?, k3 = ?, k4 = ?,
+                          erased_at = COALESCE(erased_at, CURRENT_TIMESTAMP)
+         WHERE card_id = ?"
+    )
+    .bind(format!("Erased card {}", card_id))
+    .bind(key())
+    .bind(key())
+    .bind(key())
+    .bind(key())
+    .bind(key())
+    .bind(card_id)
+    .execute(&mut *conn)
+    .await?;
+
+    for statement in [
+        "DELETE FROM card_tags WHERE card_id = ?",
+        "DELETE FROM card_tokens WHERE card_id = ?",
+        "DELETE FROM card_previous_keys WHERE card_id = ?",
+        "UPDATE card_taps SET uid = NULL WHERE card_id = ?",
+        "UPDATE lost_reports SET note = NULL WHERE card_id = ?",
+        "UPDATE card_payments SET invoice = NULL, description = NULL, memo = NULL, onchain_address = NULL WHERE card_id = ?",
+        "UPDATE card_topups SET invoice = '' WHERE card_id = ?",
+    ] {
+        sqlx::query(statement).bind(card_id).execute(&mut *conn).await?;
+    }
+
+    let paths: Vec<String> = PERSONAL_AUDIT_DETAILS.iter().map(|key| format!("'$.{}'", key)).collect();
+    sqlx::query(&format!(
+        "UPDATE audit_log SET details = json_remove(details, {})
+         WHERE ?1 IN (json_extract(details, '$.card_id'), json_extract(details, '$.replacement_id'))
+            OR ?1 IN (SELECT value FROM json_each(details, '$.card_ids'))",
+        paths.join(", ")
+    ))
+    .bind(card_id)
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query(
+        "UPDATE audit_log SET details = json_remove(details, '$.memo')
+         WHERE json_extract(details, '$.payment_id') IN (SELECT payment_id FROM card_payments WHERE card_id = ?)"
+    )
+    .bind(card_id)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+async fn card_record(conn: &mut SqliteConnection, card_id: i64) -> Result<Option<CardRecord>> {
+    let Some(mut card) = sqlx::query_as::<_, CardRecord>(
+        "SELECT card_id, card_name, uid, enabled, tx_limit_sats, day_limit_sats, balance_mode, balance_msats,
+                nostr_pubkey, created_at, erased_at, reported_lost_at, frozen_at, frozen_until, freeze_reason
+         FROM cards WHERE card_id = ?"
+    )
+    .bind(card_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    card.tags = sqlx::query_scalar("SELECT tag FROM card_tags WHERE card_id = ? ORDER BY tag")
+        .bind(card_id)
+        .fetch_all(&mut *conn)
+        .await?;
+    let metadata: String = sqlx::query_scalar("SELECT metadata FROM cards WHERE card_id = ?")
+        .bind(card_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    card.metadata = serde_json::from_str(&metadata)?;
+    card.payments = query_payment!("WHERE card_id = ? ORDER BY payment_id", card_id)
+        .fetch_all(&mut *conn)
+        .await?;
+    card.topups = sqlx::query_as!(
+        CardTopup,
+        r#"SELECT topup_id AS "topup_id!", card_id, payment_hash, invoice, amount_msats, paid,
+                  paid_at AS "paid_at: String", created_at AS "created_at: String"
+           FROM card_topups WHERE card_id = ? ORDER BY topup_id"#,
+        card_id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    card.taps = sqlx::query_as!(
+        CardTap,
+        r#"SELECT tap_id AS "tap_id!", card_id, uid, counter, success, reason, created_at AS "created_at: String"
+           FROM card_taps WHERE card_id = ? ORDER BY tap_id"#,
+        card_id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    card.lost_reports = sqlx::query_as!(
+        LostReport,
+        r#"SELECT report_id AS "report_id!", card_id, source, note, created_at AS "created_at: String"
+           FROM lost_reports WHERE card_id = ? ORDER BY report_id"#,
+        card_id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(Some(card))
+}
+
+/// Entries by `actor` or naming one of the cards in their details
+async fn audit_entries(conn: &mut SqliteConnection, card_ids: &[i64], actor: Option<&str>) -> Result<Vec<AuditEntry>> {
+    let card_ids = serde_json::to_string(card_ids)?;
+    let entries = sqlx::query_as!(
+        AuditEntry,
+        r#"SELECT audit_id AS "audit_id!", actor, action, details, created_at AS "created_at: String"
+         FROM audit_log
+         WHERE actor IS ?
+            OR EXISTS (SELECT 1 FROM json_each(?) AS ids
+                       WHERE ids.value IN (json_extract(audit_log.details, '$.card_id'),
+                                           json_extract(audit_log.details, '$.replacement_id'))
+                          OR ids.value IN (SELECT value FROM json_each(audit_log.details, '$.card_ids')))
+         ORDER BY audit_id"#,
+        actor,
+        card_ids
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(entries)
+}
+
+async fn owned_card_ids(conn: &mut SqliteConnection, user_id: i64) -> Result<Vec<i64>> {
+    let card_ids = sqlx::query_scalar("SELECT card_id FROM cards WHERE owner_id = ? ORDER BY card_id")
+        .bind(user_id)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    Ok(card_ids)
+}
+
+async fn has_payments_in_flight(conn: &mut SqliteConnection, card_ids: &[i64]) -> Result<bool> {
+    let in_flight = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM card_payments
+                        WHERE card_id IN (SELECT value FROM json_each(?))
+                              AND status IN ('invoice_attached', 'in_flight'))"
+    )
+    .bind(serde_json::to_string(card_ids)?)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(in_flight)
+}
+
+fn now() -> String {
+    chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{clones, lost, memos, queries, run_migrations};
+
+    #[tokio::test]
+    async fn test_erase_card_scrubs_audit_log() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let key = "00000000000000000000000000000000";
+        let card_id = queries::insert_card(&pool, "04996c6a926980", key, key, key, key, key, "Alice", 1000, 10000, true, false, "a", None, None, chrono::Utc::now())
+            .await
+            .unwrap();
+        let other_id = queries::insert_card(&pool, "04a39493cc8680", key, key, key, key, key, "Bob", 1000, 10000, true, false, "b", None, None, chrono::Utc::now())
+            .await
+            .unwrap();
+        let payment_id: i64 = sqlx::query_scalar(
+            "INSERT INTO card_payments (card_id, k1, amount_msats, status) VALUES (?, 'k1', 1000, 'settled') RETURNING payment_id"
+        )
+        .bind(card_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        lost::report_lost(&pool, card_id, "holder", Some("Left it at Alice's office"), "test").await.unwrap();
+        lost::report_lost(&pool, other_id, "holder", Some("Bob's note"), "test").await.unwrap();
+        clones::flag_clones(&pool, &[card_id], "04996c6a926980", chrono::Utc::now(), "test").await.unwrap();
+        memos::set_memo(&pool, payment_id, Some("Lunch with Alice"), "test").await.unwrap();
+
+        assert_eq!(erase_card(&pool, card_id, "test").await.unwrap(), Erasure::Erased(vec![card_id]));
+
+        let details: Vec<String> = sqlx::query_scalar("SELECT details FROM audit_log ORDER BY audit_id").fetch_all(&pool).await.unwrap();
+        let log = details.join("\n");
+        assert!(!log.contains("Alice"), "{}", log);
+        assert!(!log.contains("04996c6a926980"), "{}", log);
+        // Entries about other cards stay as they are
+        assert!(log.contains("Bob's note"), "{}", log);
+        assert!(log.contains(&format!("\"payment_id\":{}", payment_id)), "{}", log);
+    }
+}
@@ -1,25 +1,31 @@
 use sqlx::{Pool, Sqlite};
 use anyhow::Result;
-use chrono;
-use crate::db::models::{Card, CardPayment};
+use chrono::{DateTime, Utc};
+use crate::clock::sql_timestamp;
+use crate::db::{audit, fees, models::{Card, CardPayment}, query_card, query_payment, taps};
 
-pub async fn get_card_by_uid(pool: &Pool<Sqlite>, uid: &str) -> Result<Option<Card>> {
-    let card = sqlx::query_as::<_, Card>(
-        "SELECT * FROM cards WHERE uid = ? AND enabled = 1"
+pub async fn get_card_by_one_time_code(pool: &Pool<Sqlite>, code: &str, now: DateTime<Utc>) -> Result<Option<Card>> {
+    let now = sql_timestamp(now);
+    let card = query_card!(
+        "WHERE one_time_code = ? AND one_time_code_used = 0 
+         AND one_time_code_expiry > ? AND deleted_at IS NULL",
+        code,
+        now
     )
-    .bind(uid)
     .fetch_optional(pool)
     .await?;
     
     Ok(card)
 }
 
-pub async fn get_card_by_one_time_code(pool: &Pool<Sqlite>, code: &str) -> Result<Option<Card>> {
-    let card = sqlx::query_as::<_, Card>(
-        "SELECT * FROM cards WHERE one_time_code = ? AND one_time_code_used = 0 
-         AND one_time_code_expiry > datetime('now')"
+pub async fn get_unregistered_card(pool: &Pool<Sqlite>, card_id: i64, now: DateTime<Utc>) -> Result<Option<Card>> {
+    let now = sql_timestamp(now);
+    let card = query_card!(
+        "WHERE card_id = ? AND one_time_code_used = 0 
+         AND one_time_code_expiry > ? AND deleted_at IS NULL",
+        card_id,
+        now
     )
-    .bind(code)
     .fetch_optional(pool)
     .await?;
     
@@ -27,29 +33,93 @@ pub async fn get_card_by_one_time_code(pool: &Pool<Sqlite>, code: &str) -> Resul
 }
 
 pub async fn mark_one_time_code_used(pool: &Pool<Sqlite>, card_id: i64) -> Result<()> {
-    sqlx::query(
-        "UPDATE cards SET one_time_code_used = 1 WHERE card_id = ?"
+    sqlx::query!(
+        "UPDATE cards SET one_time_code_used = 1 WHERE card_id = ?",
+        card_id
     )
-    .bind(card_id)
     .execute(pool)
     .await?;
     
     Ok(())
 }
 
-pub async fn update_card_counter(pool: &Pool<Sqlite>, card_id: i64, counter: i64) -> Result<bool> {
-    let result = sqlx::query(
-        "UPDATE cards SET last_counter = ? WHERE card_id = ? AND last_counter < ?"
+/// Apply a verified tap in one transaction: claim the UID of an unused card,
+/// advance the counter, record the tap and open the withdrawal session for `k1`
+/// with the withdrawable range and description advertised to the wallet
+///
+/// Of the card's open sessions (`created` or `failed`) only the newest
+/// `max_open_sessions` are kept, older ones expire.
+///
+/// Returns the payment ID, or `None` without writing anything if a concurrent
+/// tap got there first and the counter or UID no longer allow this one.
+#[allow(clippy::too_many_arguments)]
+pub async fn accept_tap(
+    pool: &Pool<Sqlite>,
+    card_id: i64,
+    uid: &str,
+    counter: i64,
+    k1: &str,
+    min_withdrawable_msats: i64,
+    max_withdrawable_msats: i64,
+    description: &str,
+    max_open_sessions: i64,
+) -> Result<Option<i64>> {
+    let mut tx = pool.begin().await?;
+
+    let claimed = sqlx::query!(
+        "UPDATE cards SET uid = ? WHERE card_id = ? AND (uid = '' OR uid = ?)",
+        uid,
+        card_id,
+        uid
     )
-    .bind(counter)
-    .bind(card_id)
-    .bind(counter)
-    .execute(pool)
+    .execute(&mut *tx)
     .await?;
-    
-    Ok(result.rows_affected() > 0)
+
+    let advanced = sqlx::query!(
+        "UPDATE cards SET last_counter = ? WHERE card_id = ? AND last_counter < ?",
+        counter,
+        card_id,
+        counter
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if claimed.rows_affected() == 0 || advanced.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    taps::record_tap(&mut tx, card_id, Some(uid), Some(counter), true, None).await?;
+
+    let payment = sqlx::query!(
+        "INSERT INTO card_payments (card_id, k1, min_withdrawable_msats, max_withdrawable_msats, description, status)
+         VALUES (?, ?, ?, ?, ?, 'created')",
+        card_id,
+        k1,
+        min_withdrawable_msats,
+        max_withdrawable_msats,
+        description
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE card_payments SET status = 'expired'
+         WHERE card_id = ?1 AND status IN ('created', 'failed') AND payment_id NOT IN (
+            SELECT payment_id FROM card_payments WHERE card_id = ?1 AND status IN ('created', 'failed')
+            ORDER BY payment_id DESC LIMIT ?2
+         )",
+        card_id,
+        max_open_sessions
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(payment.last_insert_rowid()))
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn insert_card(
     pool: &Pool<Sqlite>,
     uid: &str,
@@ -62,100 +132,562 @@ pub async fn insert_card(
     tx_limit: i64,
     day_limit: i64,
     enabled: bool,
+    balance_mode: bool,
     one_time_code: &str,
+    owner_id: Option<i64>,
+    org_id: Option<i64>,
+    now: DateTime<Utc>,
 ) -> Result<i64> {
-    // SQLite datetime in UTC format
-    let expiry = chrono::Utc::now() + chrono::Duration::days(1);
-    let expiry_str = expiry.format("%Y-%m-%d %H:%M:%S").to_string();
+    let expiry_str = sql_timestamp(now + chrono::Duration::days(1));
     
-    let result = sqlx::query(
+    let result = sqlx::query!(
         "INSERT INTO cards (uid, k0_auth_key, k1_decrypt_key, k2_cmac_key, k3, k4, 
-         card_name, tx_limit_sats, day_limit_sats, enabled, one_time_code, 
-         one_time_code_expiry, one_time_code_used)
-         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 0)"
-    )
-    .bind(uid)
-    .bind(k0)
-    .bind(k1)
-    .bind(k2)
-    .bind(k3)
-    .bind(k4)
-    .bind(card_name)
-    .bind(tx_limit)
-    .bind(day_limit)
-    .bind(enabled)
-    .bind(one_time_code)
-    .bind(expiry_str)
+         card_name, tx_limit_sats, day_limit_sats, enabled, balance_mode, one_time_code, 
+         one_time_code_expiry, one_time_code_used, owner_id, org_id)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 0, ?, ?)",
+        uid,
+        k0,
+        k1,
+        k2,
+        k3,
+        k4,
+        card_name,
+        tx_limit,
+        day_limit,
+        enabled,
+        balance_mode,
+        one_time_code,
+        expiry_str,
+        owner_id,
+        org_id
+    )
     .execute(pool)
     .await?;
     
     Ok(result.last_insert_rowid())
 }
 
-pub async fn create_payment(
-    pool: &Pool<Sqlite>,
-    card_id: i64,
-    k1: &str,
-) -> Result<i64> {
-    let result = sqlx::query(
-        "INSERT INTO card_payments (card_id, k1) VALUES (?, ?)"
+pub async fn get_payment_by_id(pool: &Pool<Sqlite>, payment_id: i64) -> Result<Option<CardPayment>> {
+    let payment = query_payment!(
+        "WHERE payment_id = ?",
+        payment_id
     )
-    .bind(card_id)
-    .bind(k1)
-    .execute(pool)
+    .fetch_optional(pool)
     .await?;
-    
-    Ok(result.last_insert_rowid())
+
+    Ok(payment)
 }
 
 pub async fn get_payment_by_k1(pool: &Pool<Sqlite>, k1: &str) -> Result<Option<CardPayment>> {
-    let payment = sqlx::query_as::<_, CardPayment>(
-        "SELECT * FROM card_payments WHERE k1 = ?"
+    let payment = query_payment!(
+        "WHERE k1 = ?",
+        k1
     )
-    .bind(k1)
     .fetch_optional(pool)
     .await?;
     
     Ok(payment)
 }
 
-pub async fn update_payment_with_invoice(
+/// Attach the wallet's invoice and reserve its amount until the payment settles or fails
+///
+/// Moves a `created` or `failed` session to `invoice_attached`. Returns `false`
+/// if the session is already being paid or was paid, has had
+/// `max_attempts` invoices, or is a retry of a session opened before
+/// `opened_after`. Failed sessions can be retried with a new invoice.
+#[allow(clippy::too_many_arguments)]
+pub async fn reserve_payment(
     pool: &Pool<Sqlite>,
     payment_id: i64,
     invoice: &str,
     amount_msats: i64,
-) -> Result<()> {
-    sqlx::query(
-        "UPDATE card_payments SET invoice = ?, amount_msats = ? WHERE payment_id = ?"
+    payment_hash: &str,
+    max_attempts: i64,
+    opened_after: chrono::DateTime<chrono::Utc>,
+) -> Result<bool> {
+    let opened_after = opened_after.format("%Y-%m-%d %H:%M:%S").to_string();
+    let result = sqlx::query!(
+        "UPDATE card_payments SET invoice = ?, amount_msats = ?, payment_hash = ?, onchain_address = NULL, status = 'invoice_attached',
+                failure_reason = NULL, attempts = attempts + 1
+         WHERE payment_id = ? AND status IN ('created', 'failed') AND attempts < ? AND (attempts = 0 OR created_at >= ?)",
+        invoice,
+        amount_msats,
+        payment_hash,
+        payment_id,
+        max_attempts,
+        opened_after
     )
-    .bind(invoice)
-    .bind(amount_msats)
-    .bind(payment_id)
     .execute(pool)
     .await?;
     
+    Ok(result.rows_affected() > 0)
+}
+
+/// Record that a reserved invoice is being handed to the Lightning node
+///
+/// Returns `false` unless the payment was reserved and not yet handed over, so
+/// a payment is never handed to the node twice. One already in flight, e.g.
+/// interrupted by a restart, is decided by what the node knows about it.
+pub async fn mark_payment_in_flight(pool: &Pool<Sqlite>, payment_id: i64, now: DateTime<Utc>) -> Result<bool> {
+    let now = sql_timestamp(now);
+    let result = sqlx::query!(
+        "UPDATE card_payments SET status = 'in_flight', in_flight_since = ?
+         WHERE payment_id = ? AND status = 'invoice_attached'",
+        now,
+        payment_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Release the reservation of a payment that couldn't be paid
+///
+/// Returns `false` if the payment wasn't reserved (anymore).
+pub async fn mark_payment_failed(pool: &Pool<Sqlite>, payment_id: i64, reason: &str) -> Result<bool> {
+    let result = sqlx::query!(
+        "UPDATE card_payments SET status = 'failed', failure_reason = ?
+         WHERE payment_id = ? AND status IN ('invoice_attached', 'in_flight')",
+        reason,
+        payment_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Release the reservation of a payment refused before it was handed to the node, without counting the attempt
+///
+/// For refusals that don't depend on the wallet's invoice, so a retry has as many attempts left as before.
+/// Returns `false` if the payment wasn't reserved (anymore).
+pub async fn mark_payment_refused(pool: &Pool<Sqlite>, payment_id: i64, reason: &str) -> Result<bool> {
+    let result = sqlx::query!(
+        "UPDATE card_payments SET status = 'failed', failure_reason = ?, attempts = attempts - 1
+         WHERE payment_id = ? AND status = 'invoice_attached'",
+        reason,
+        payment_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Mark an in-flight payment settled and add it to the card's spend in the same transaction
+///
+/// Returns `false` if the payment wasn't in flight, e.g. because it was settled before.
+pub async fn mark_payment_settled(
+    pool: &Pool<Sqlite>,
+    payment_id: i64,
+    preimage: Option<&str>,
+    fee_msats: Option<u64>,
+    now: DateTime<Utc>,
+) -> Result<bool> {
+    let now = sql_timestamp(now);
+    let mut tx = pool.begin().await?;
+
+    // Only count a payment once, even if it gets marked settled twice
+    let updated = sqlx::query!(
+        "UPDATE card_payments SET status = 'settled', payment_time = ?, preimage = ?
+         WHERE payment_id = ? AND status = 'in_flight'",
+        now,
+        preimage,
+        payment_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if updated.rows_affected() > 0 {
+        sqlx::query("UPDATE card_payments SET fee_msats = ? WHERE payment_id = ?")
+            .bind(fee_msats.map(|fee| fee as i64))
+            .bind(payment_id)
+            .execute(&mut *tx)
+            .await?;
+        fees::book(&mut tx, payment_id).await?;
+
+        sqlx::query!(
+            "INSERT INTO card_spend (card_id, day, amount_msats, payments)
+             SELECT card_id, date(payment_time), COALESCE(amount_msats, 0), 1
+             FROM card_payments WHERE payment_id = ?
+             ON CONFLICT (card_id, day) DO UPDATE SET
+                amount_msats = amount_msats + excluded.amount_msats,
+                payments = payments + 1",
+            payment_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "UPDATE cards SET spent_msats = spent_msats + (
+                SELECT COALESCE(amount_msats, 0) FROM card_payments WHERE payment_id = ?
+             )
+             WHERE card_id = (SELECT card_id FROM card_payments WHERE payment_id = ?)",
+            payment_id,
+            payment_id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(updated.rows_affected() > 0)
+}
+
+/// Close a session that ran out of time before it could be paid
+pub async fn expire_payment(pool: &Pool<Sqlite>, payment_id: i64) -> Result<()> {
+    sqlx::query!(
+        "UPDATE card_payments SET status = 'expired' WHERE payment_id = ? AND status IN ('created', 'failed')",
+        payment_id
+    )
+    .execute(pool)
+    .await?;
+
     Ok(())
 }
 
-pub async fn mark_payment_paid(pool: &Pool<Sqlite>, payment_id: i64) -> Result<()> {
-    sqlx::query(
-        "UPDATE card_payments SET paid = 1, payment_time = datetime('now') WHERE payment_id = ?"
+pub async fn list_cards(pool: &Pool<Sqlite>) -> Result<Vec<Card>> {
+    let cards = query_card!("ORDER BY card_id")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(cards)
+}
+
+pub async fn list_enabled_cards(pool: &Pool<Sqlite>) -> Result<Vec<Card>> {
+    let cards = query_card!("WHERE enabled = 1 AND deleted_at IS NULL")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(cards)
+}
+
+/// An enabled card, as far as matching a tap without card ID needs it
+#[derive(Debug, Clone)]
+pub struct TapCandidate {
+    pub card_id: i64,
+    pub org_id: Option<i64>,
+    /// Empty until the card's first tap
+    pub uid: String,
+    pub k1_decrypt_key: String,
+    pub k2_cmac_key: String,
+}
+
+pub async fn list_tap_candidates(pool: &Pool<Sqlite>) -> Result<Vec<TapCandidate>> {
+    let candidates = sqlx::query_as!(
+        TapCandidate,
+        r#"SELECT card_id AS "card_id!", org_id, uid, k1_decrypt_key, k2_cmac_key FROM cards WHERE enabled = 1 AND deleted_at IS NULL"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(candidates)
+}
+
+pub async fn list_payments(pool: &Pool<Sqlite>) -> Result<Vec<CardPayment>> {
+    let payments = query_payment!("ORDER BY payment_id")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(payments)
+}
+
+pub async fn get_card_by_id(pool: &Pool<Sqlite>, card_id: i64) -> Result<Option<Card>> {
+    let card = query_card!(
+        "WHERE card_id = ?",
+        card_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(card)
+}
+
+pub async fn get_enabled_card_by_id(pool: &Pool<Sqlite>, card_id: i64) -> Result<Option<Card>> {
+    let card = query_card!(
+        "WHERE card_id = ? AND enabled = 1 AND deleted_at IS NULL",
+        card_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(card)
+}
+
+pub async fn set_card_enabled(pool: &Pool<Sqlite>, card_id: i64, enabled: bool) -> Result<()> {
+    sqlx::query!("UPDATE cards SET enabled = ?, holder_disabled = 0 WHERE card_id = ?", enabled, card_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Take `amount_msats` from a balance-mode card, failing if the balance is too low
+pub async fn debit_balance(pool: &Pool<Sqlite>, card_id: i64, amount_msats: i64) -> Result<bool> {
+    let result = sqlx::query!(
+        "UPDATE cards SET balance_msats = balance_msats - ?
+         WHERE card_id = ? AND balance_msats >= ?",
+        amount_msats,
+        card_id,
+        amount_msats
     )
-    .bind(payment_id)
     .execute(pool)
     .await?;
-    
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn credit_balance(pool: &Pool<Sqlite>, card_id: i64, amount_msats: i64) -> Result<()> {
+    sqlx::query!(
+        "UPDATE cards SET balance_msats = balance_msats + ? WHERE card_id = ?",
+        amount_msats,
+        card_id
+    )
+    .execute(pool)
+    .await?;
+
     Ok(())
 }
 
-pub async fn get_daily_total_msats(pool: &Pool<Sqlite>, card_id: i64) -> Result<i64> {
-    let row: (Option<i64>,) = sqlx::query_as(
-        "SELECT SUM(amount_msats) FROM card_payments 
-         WHERE card_id = ? AND paid = 1 AND payment_time >= datetime('now', '-1 day')"
+/// Insert a replacement for a card with the given keys and disable the old card, in one transaction
+///
+/// The replacement takes over the card's name, limits, owner, organization, tags,
+/// metadata, description, fee, on-chain address and balance. Returns the ID of
+/// the replacement and the balance moved, in msats.
+#[allow(clippy::too_many_arguments)]
+pub async fn insert_replacement(
+    pool: &Pool<Sqlite>,
+    card_id: i64,
+    k0: &str,
+    k1: &str,
+    k2: &str,
+    k3: &str,
+    k4: &str,
+    one_time_code: &str,
+    now: DateTime<Utc>,
+    actor: &str,
+) -> Result<(i64, i64)> {
+    let expiry_str = sql_timestamp(now + chrono::Duration::days(1));
+
+    let mut tx = pool.begin().await?;
+
+    let inserted = sqlx::query!(
+        "INSERT INTO cards (uid, k0_auth_key, k1_decrypt_key, k2_cmac_key, k3, k4,
+         card_name, tx_limit_sats, day_limit_sats, enabled, balance_mode, one_time_code,
+         one_time_code_expiry, one_time_code_used, owner_id, org_id, metadata, withdraw_description,
+         fee_flat_msats, fee_percent, onchain_fallback_address)
+         SELECT '', ?, ?, ?, ?, ?, card_name, tx_limit_sats, day_limit_sats, 1, balance_mode, ?, ?, 0,
+                owner_id, org_id, metadata, withdraw_description, fee_flat_msats, fee_percent, onchain_fallback_address
+         FROM cards WHERE card_id = ?",
+        k0,
+        k1,
+        k2,
+        k3,
+        k4,
+        one_time_code,
+        expiry_str,
+        card_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    if inserted.rows_affected() == 0 {
+        anyhow::bail!("card {} not found", card_id);
+    }
+    let replacement_id = inserted.last_insert_rowid();
+
+    sqlx::query!(
+        "INSERT INTO card_tags (card_id, tag) SELECT ?, tag FROM card_tags WHERE card_id = ?",
+        replacement_id,
+        card_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Writing first takes the database lock, so no payment can debit the old card in between
+    sqlx::query!(
+        "UPDATE cards SET balance_msats = balance_msats + (SELECT balance_msats FROM cards WHERE card_id = ?)
+         WHERE card_id = ?",
+        card_id,
+        replacement_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let balance_msats = sqlx::query_scalar!("SELECT balance_msats FROM cards WHERE card_id = ?", card_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    sqlx::query!("UPDATE cards SET enabled = 0, holder_disabled = 0, balance_msats = 0 WHERE card_id = ?", card_id)
+        .execute(&mut *tx)
+        .await?;
+
+    // The unspent part of its funding goes along, withdrawals still being paid stay on the old card
+    let funding_msats = sqlx::query_scalar!(
+        r#"SELECT MAX(funded_msats - spent_msats - COALESCE(
+               (SELECT SUM(amount_msats) FROM card_payments
+                WHERE card_id = ? AND status IN ('invoice_attached', 'in_flight')), 0
+           ), 0) AS "funding_msats!: i64"
+           FROM cards WHERE card_id = ?"#,
+        card_id,
+        card_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!("UPDATE cards SET funded_msats = funded_msats - ? WHERE card_id = ?", funding_msats, card_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("UPDATE cards SET funded_msats = funded_msats + ? WHERE card_id = ?", funding_msats, replacement_id)
+        .execute(&mut *tx)
+        .await?;
+
+    audit::record(
+        &mut tx,
+        actor,
+        "replace_card",
+        &serde_json::json!({
+            "card_id": card_id,
+            "replacement_id": replacement_id,
+            "balance_msats": balance_msats,
+            "funding_msats": funding_msats,
+        }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok((replacement_id, balance_msats))
+}
+
+/// Spend of the card on the calendar day (UTC) of `now`, including payments still in flight
+///
+/// Settled spend comes from the card's `card_spend` row of the day. Reserved
+/// payments count whenever they were reserved, as they'll settle today if at all.
+pub async fn get_daily_total_msats(pool: &Pool<Sqlite>, card_id: i64, now: DateTime<Utc>) -> Result<i64> {
+    let now = sql_timestamp(now);
+    let total = sqlx::query_scalar!(
+        r#"SELECT
+            (SELECT COALESCE(SUM(amount_msats), 0) FROM card_spend WHERE card_id = ?1 AND day = date(?2))
+          + (SELECT COALESCE(SUM(amount_msats), 0) FROM card_payments
+             WHERE card_id = ?1 AND status IN ('invoice_attached', 'in_flight'))
+          AS "total!: i64""#,
+        card_id,
+        now
     )
-    .bind(card_id)
     .fetch_one(pool)
     .await?;
     
-    Ok(row.0.unwrap_or(0))
-}
\ No newline at end of file
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::run_migrations;
+    use chrono::TimeZone;
+
+    async fn settle(pool: &Pool<Sqlite>, card_id: i64, amount_msats: i64, at: DateTime<Utc>) {
+        let payment_id: i64 = sqlx::query_scalar(
+            "INSERT INTO card_payments (card_id, k1, amount_msats, status) VALUES (?, hex(randomblob(16)), ?, 'in_flight') RETURNING payment_id"
+        )
+        .bind(card_id)
+        .bind(amount_msats)
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        assert!(mark_payment_settled(pool, payment_id, None, None, at).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_daily_total_resets_at_midnight() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let key = "00000000000000000000000000000000";
+        let card_id = insert_card(&pool, "04996c6a926980", key, key, key, key, key, "Alice", 1000, 10000, true, false, "a", None, None, chrono::Utc::now())
+            .await
+            .unwrap();
+
+        settle(&pool, card_id, 1_000_000, Utc.with_ymd_and_hms(2026, 3, 1, 23, 0, 0).unwrap()).await;
+        settle(&pool, card_id, 2_000_000, Utc.with_ymd_and_hms(2026, 3, 1, 23, 30, 0).unwrap()).await;
+        settle(&pool, card_id, 4_000_000, Utc.with_ymd_and_hms(2026, 3, 2, 0, 30, 0).unwrap()).await;
+
+        let total = |at| get_daily_total_msats(&pool, card_id, at);
+        assert_eq!(total(Utc.with_ymd_and_hms(2026, 3, 1, 23, 59, 0).unwrap()).await.unwrap(), 3_000_000);
+        // Half an hour after the last payment of the day, it no longer counts
+        assert_eq!(total(Utc.with_ymd_and_hms(2026, 3, 2, 0, 30, 0).unwrap()).await.unwrap(), 4_000_000);
+        assert_eq!(total(Utc.with_ymd_and_hms(2026, 3, 3, 0, 0, 0).unwrap()).await.unwrap(), 0);
+
+        // One row per card and day, however many payments
+        let rows: Vec<(String, i64, i64)> = sqlx::query_as("SELECT day, amount_msats, payments FROM card_spend WHERE card_id = ? ORDER BY day")
+            .bind(card_id)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(rows, vec![("2026-03-01".to_string(), 3_000_000, 2), ("2026-03-02".to_string(), 4_000_000, 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_daily_total_counts_open_reservations() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let key = "00000000000000000000000000000000";
+        let card_id = insert_card(&pool, "04996c6a926980", key, key, key, key, key, "Alice", 1000, 10000, true, false, "a", None, None, chrono::Utc::now())
+            .await
+            .unwrap();
+
+        // Reserved before midnight, still in flight after it
+        sqlx::query("INSERT INTO card_payments (card_id, k1, amount_msats, status, created_at) VALUES (?, 'k1', 5000000, 'in_flight', '2026-03-01 23:50:00')")
+            .bind(card_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let total = get_daily_total_msats(&pool, card_id, Utc.with_ymd_and_hms(2026, 3, 2, 0, 10, 0).unwrap()).await.unwrap();
+        assert_eq!(total, 5_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_accept_tap_caps_open_sessions() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let key = "00000000000000000000000000000000";
+        let card_id = insert_card(&pool, "", key, key, key, key, key, "Card", 1000, 10000, true, false, "code", None, None, chrono::Utc::now())
+            .await
+            .unwrap();
+        let other_id = insert_card(&pool, "", key, key, key, key, key, "Other", 1000, 10000, true, false, "other", None, None, chrono::Utc::now())
+            .await
+            .unwrap();
+        let tap = |card_id, uid: &'static str, counter, k1: &'static str| {
+            let pool = pool.clone();
+            async move { accept_tap(&pool, card_id, uid, counter, k1, 1000, 1_000_000, "Withdrawal", 2).await.unwrap() }
+        };
+        let statuses = || async {
+            let statuses: Vec<(String, String)> = sqlx::query_as("SELECT k1, status FROM card_payments ORDER BY payment_id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+            statuses
+        };
+
+        let first = tap(card_id, "04996c6a926980", 1, "first").await.unwrap();
+        tap(other_id, "04a39493cc8680", 1, "other").await.unwrap();
+        let second = tap(card_id, "04996c6a926980", 2, "second").await.unwrap();
+        // A session being paid isn't open anymore and stays as it is
+        sqlx::query("UPDATE card_payments SET status = 'in_flight' WHERE payment_id = ?").bind(first).execute(&pool).await.unwrap();
+        // A failed one can still be retried, so it counts
+        sqlx::query("UPDATE card_payments SET status = 'failed' WHERE payment_id = ?").bind(second).execute(&pool).await.unwrap();
+        tap(card_id, "04996c6a926980", 3, "third").await.unwrap();
+        tap(card_id, "04996c6a926980", 4, "fourth").await.unwrap();
+
+        let expected = [("first", "in_flight"), ("other", "created"), ("second", "expired"), ("third", "created"), ("fourth", "created")];
+        let expected: Vec<(String, String)> = expected.iter().map(|(k1, status)| (k1.to_string(), status.to_string())).collect();
+        assert_eq!(statuses().await, expected);
+
+        // A replayed counter or another card's UID opens nothing and expires nothing
+        assert_eq!(tap(card_id, "04996c6a926980", 4, "replayed").await, None);
+        assert_eq!(tap(card_id, "04a39493cc8680", 5, "foreign").await, None);
+        assert_eq!(statuses().await, expected);
+    }
+}
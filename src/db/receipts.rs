@@ -0,0 +1,41 @@
+use serde::Serialize;
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+
+/// A public key receipts were signed with
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ReceiptKey {
+    pub public_key: String,
+    pub created_at: String,
+}
+
+/// Remember a signing key, with its secret only if the server generated it
+pub async fn record_key(pool: &Pool<Sqlite>, public_key: &str, secret_key: Option<&str>) -> Result<()> {
+    sqlx::query("INSERT OR IGNORE INTO receipt_keys (public_key, secret_key) VALUES (?, ?)")
+        .bind(public_key)
+        .bind(secret_key)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// The secret of the key the server generated last, `None` before it generated one
+pub async fn generated_secret_key(pool: &Pool<Sqlite>) -> Result<Option<String>> {
+    let secret_key = sqlx::query_scalar(
+        "SELECT secret_key FROM receipt_keys WHERE secret_key IS NOT NULL ORDER BY created_at DESC, rowid DESC LIMIT 1"
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(secret_key)
+}
+
+/// All keys receipts were signed with, oldest first
+pub async fn list_keys(pool: &Pool<Sqlite>) -> Result<Vec<ReceiptKey>> {
+    let keys = sqlx::query_as("SELECT public_key, created_at FROM receipt_keys ORDER BY created_at, rowid")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(keys)
+}
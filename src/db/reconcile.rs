@@ -0,0 +1,195 @@
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use crate::clock::sql_timestamp;
+use crate::db::{models::{CardPayment, PaymentDiscrepancy}, query_payment};
+
+/// Payments handed to the node more than `older_than_secs` ago without an outcome
+pub async fn stale_in_flight(pool: &Pool<Sqlite>, older_than_secs: i64, now: DateTime<Utc>) -> Result<Vec<CardPayment>> {
+    let cutoff = sql_timestamp(now - chrono::Duration::seconds(older_than_secs));
+    let payments = query_payment!(
+        "WHERE status = 'in_flight' AND in_flight_since < ? ORDER BY payment_id",
+        cutoff
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(payments)
+}
+
+/// Settled and failed payments handed to the node within the last `within_secs`
+pub async fn recently_decided(pool: &Pool<Sqlite>, within_secs: i64, now: DateTime<Utc>) -> Result<Vec<CardPayment>> {
+    let cutoff = sql_timestamp(now - chrono::Duration::seconds(within_secs));
+    let payments = query_payment!(
+        "WHERE status IN ('settled', 'failed') AND payment_hash IS NOT NULL
+           AND in_flight_since >= ?
+         ORDER BY payment_id",
+        cutoff
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(payments)
+}
+
+/// Record a discrepancy for admin review
+///
+/// Returns `false` if the same discrepancy was already recorded for the payment.
+pub async fn flag(pool: &Pool<Sqlite>, payment_id: i64, kind: &str, node_status: &str) -> Result<bool> {
+    let result = sqlx::query(
+        "INSERT OR IGNORE INTO payment_discrepancies (payment_id, kind, node_status) VALUES (?, ?, ?)"
+    )
+    .bind(payment_id)
+    .bind(kind)
+    .bind(node_status)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Fail a payment still in flight, giving a prepaid card back the amount and service fee reserved for it
+///
+/// Returns `false` if the payment isn't in flight (anymore).
+pub async fn fail_in_flight(pool: &Pool<Sqlite>, payment_id: i64, reason: &str) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+
+    let reserved: Option<(i64, i64)> = sqlx::query_as(
+        "UPDATE card_payments SET status = 'failed', failure_reason = ?
+         WHERE payment_id = ? AND status = 'in_flight'
+         RETURNING card_id, COALESCE(amount_msats, 0) + COALESCE(service_fee_msats, 0)"
+    )
+    .bind(reason)
+    .bind(payment_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some((card_id, refund_msats)) = reserved else {
+        return Ok(false);
+    };
+    sqlx::query("UPDATE cards SET balance_msats = balance_msats + ? WHERE card_id = ? AND balance_mode")
+        .bind(refund_msats)
+        .bind(card_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// Payment of an open discrepancy, `None` if there is no open one with this ID
+pub async fn open_discrepancy_payment(pool: &Pool<Sqlite>, discrepancy_id: i64) -> Result<Option<i64>> {
+    let payment_id = sqlx::query_scalar(
+        "SELECT payment_id FROM payment_discrepancies WHERE discrepancy_id = ? AND resolved_at IS NULL"
+    )
+    .bind(discrepancy_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(payment_id)
+}
+
+/// Discrepancies, newest first, optionally including resolved ones
+pub async fn list(pool: &Pool<Sqlite>, include_resolved: bool) -> Result<Vec<PaymentDiscrepancy>> {
+    let discrepancies = sqlx::query_as::<_, PaymentDiscrepancy>(
+        "SELECT d.discrepancy_id, d.payment_id, p.card_id, d.kind, d.node_status, d.created_at, d.resolved_at
+         FROM payment_discrepancies d JOIN card_payments p ON p.payment_id = d.payment_id
+         WHERE ? OR d.resolved_at IS NULL
+         ORDER BY d.discrepancy_id DESC"
+    )
+    .bind(include_resolved)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(discrepancies)
+}
+
+/// Mark a discrepancy as dealt with, returns `false` if there is no open one with this ID
+pub async fn resolve(pool: &Pool<Sqlite>, discrepancy_id: i64) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE payment_discrepancies SET resolved_at = CURRENT_TIMESTAMP
+         WHERE discrepancy_id = ? AND resolved_at IS NULL"
+    )
+    .bind(discrepancy_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{queries, run_migrations};
+
+    async fn setup() -> (Pool<Sqlite>, i64) {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let key = "00000000000000000000000000000000";
+        let card_id = queries::insert_card(&pool, "", key, key, key, key, key, "Card", 1000, 10000, true, true, "code", None, None, chrono::Utc::now())
+            .await
+            .unwrap();
+        (pool, card_id)
+    }
+
+    async fn insert_payment(pool: &Pool<Sqlite>, card_id: i64, k1: &str, status: &str, in_flight_since: &str) -> i64 {
+        sqlx::query_scalar(
+            "INSERT INTO card_payments (card_id, k1, amount_msats, service_fee_msats, payment_hash, status, in_flight_since)
+             VALUES (?, ?, 5000, 100, ?, ?, ?) RETURNING payment_id"
+        )
+        .bind(card_id)
+        .bind(k1)
+        .bind(format!("hash-{}", k1))
+        .bind(status)
+        .bind(in_flight_since)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_stale_and_recent_payments() {
+        let (pool, card_id) = setup().await;
+        let now = DateTime::parse_from_rfc3339("2026-05-01T12:00:00Z").unwrap().with_timezone(&Utc);
+        let stale = insert_payment(&pool, card_id, "a", "in_flight", "2026-05-01 11:00:00").await;
+        insert_payment(&pool, card_id, "b", "in_flight", "2026-05-01 11:59:00").await;
+        let recent = insert_payment(&pool, card_id, "c", "settled", "2026-05-01 11:30:00").await;
+        insert_payment(&pool, card_id, "d", "failed", "2026-04-30 11:30:00").await;
+
+        let ids = |payments: Vec<CardPayment>| payments.iter().map(|payment| payment.payment_id).collect::<Vec<_>>();
+        assert_eq!(ids(stale_in_flight(&pool, 600, now).await.unwrap()), vec![stale]);
+        assert_eq!(ids(recently_decided(&pool, 3600, now).await.unwrap()), vec![recent]);
+    }
+
+    #[tokio::test]
+    async fn test_fail_in_flight_refunds_once() {
+        let (pool, card_id) = setup().await;
+        let payment_id = insert_payment(&pool, card_id, "a", "in_flight", "2026-05-01 11:00:00").await;
+
+        assert!(fail_in_flight(&pool, payment_id, "gone").await.unwrap());
+        assert!(!fail_in_flight(&pool, payment_id, "gone").await.unwrap());
+        let card = queries::get_card_by_id(&pool, card_id).await.unwrap().unwrap();
+        assert_eq!(card.balance_msats, 5100);
+        let payment = queries::get_payment_by_id(&pool, payment_id).await.unwrap().unwrap();
+        assert_eq!(payment.failure_reason.as_deref(), Some("gone"));
+    }
+
+    #[tokio::test]
+    async fn test_flag_and_resolve() {
+        let (pool, card_id) = setup().await;
+        let payment_id = insert_payment(&pool, card_id, "a", "in_flight", "2026-05-01 11:00:00").await;
+
+        assert!(flag(&pool, payment_id, "in_flight_unknown", "unknown").await.unwrap());
+        assert!(!flag(&pool, payment_id, "in_flight_unknown", "unknown").await.unwrap());
+        let discrepancies = list(&pool, false).await.unwrap();
+        assert_eq!(discrepancies.len(), 1);
+        let discrepancy_id = discrepancies[0].discrepancy_id;
+        assert_eq!(open_discrepancy_payment(&pool, discrepancy_id).await.unwrap(), Some(payment_id));
+
+        assert!(resolve(&pool, discrepancy_id).await.unwrap());
+        assert!(!resolve(&pool, discrepancy_id).await.unwrap());
+        assert!(list(&pool, false).await.unwrap().is_empty());
+        assert_eq!(list(&pool, true).await.unwrap().len(), 1);
+        assert_eq!(open_discrepancy_payment(&pool, discrepancy_id).await.unwrap(), None);
+    }
+}
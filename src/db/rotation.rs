@@ -0,0 +1,229 @@
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{
+    clock::sql_timestamp,
+    db::{audit, bulk::{self, CardSelector}, models::CardPreviousKeys, query_card},
+    random::Random,
+};
+
+/// A card with freshly generated keys, waiting to be reprogrammed
+#[derive(Debug, Clone, Serialize)]
+pub struct RotatedCard {
+    pub card_id: i64,
+    pub card_name: String,
+    pub one_time_code: String,
+    pub valid_until: String,
+    /// Keys currently on the card, needed by the programming app to change them
+    pub previous_keys: CardPreviousKeys,
+}
+
+/// Give the selected cards new keys, accepting the old ones for another `grace`
+///
+/// Each card gets a new one-time code valid for the grace window, so `/new`
+/// hands out the new keys for reprogramming.
+pub async fn rotate(
+    pool: &Pool<Sqlite>,
+    selector: &CardSelector,
+    grace: chrono::Duration,
+    actor: &str,
+    random: &Random,
+) -> Result<Vec<RotatedCard>> {
+    let valid_until = (chrono::Utc::now() + grace).format("%Y-%m-%d %H:%M:%S").to_string();
+
+    let mut tx = pool.begin().await?;
+
+    let card_ids = bulk::select_card_ids(&mut tx, selector).await?;
+
+    let mut rotated = Vec::new();
+    for card_id in &card_ids {
+        let card = query_card!("WHERE card_id = ?", card_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        // If the last rotation's keys were never fetched, the card still carries the
+        // keys from before it, so those are the ones to keep accepting
+        let pending = sqlx::query_as!(
+            CardPreviousKeys,
+            r#"SELECT card_id AS "card_id!", k0_auth_key, k1_decrypt_key, k2_cmac_key, k3, k4,
+                      valid_until AS "valid_until: String", created_at AS "created_at: String"
+               FROM card_previous_keys WHERE card_id = ? AND valid_until > datetime('now')"#,
+            card_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .filter(|_| card.one_time_code_used == Some(false));
+
+        let previous_keys = match pending {
+            Some(keys) => keys,
+            None => CardPreviousKeys {
+                card_id: card.card_id,
+                k0_auth_key: card.k0_auth_key,
+                k1_decrypt_key: card.k1_decrypt_key,
+                k2_cmac_key: card.k2_cmac_key,
+                k3: card.k3,
+                k4: card.k4,
+                valid_until: valid_until.clone(),
+                created_at: None,
+            },
+        };
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO card_previous_keys
+             (card_id, k0_auth_key, k1_decrypt_key, k2_cmac_key, k3, k4, valid_until)
+             VALUES (?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(card_id)
+        .bind(&previous_keys.k0_auth_key)
+        .bind(&previous_keys.k1_decrypt_key)
+        .bind(&previous_keys.k2_cmac_key)
+        .bind(&previous_keys.k3)
+        .bind(&previous_keys.k4)
+        .bind(&valid_until)
+        .execute(&mut *tx)
+        .await?;
+
+        let one_time_code = random.hex::<16>()?;
+        sqlx::query(
+            "UPDATE cards SET k0_auth_key = ?, k1_decrypt_key = ?, k2_cmac_key = ?, k3 = ?, k4 = ?,
+             one_time_code = ?, one_time_code_expiry = ?, one_time_code_used = 0
+             WHERE card_id = ?"
+        )
+        .bind(random.aes_key()?.to_string())
+        .bind(random.aes_key()?.to_string())
+        .bind(random.aes_key()?.to_string())
+        .bind(random.aes_key()?.to_string())
+        .bind(random.aes_key()?.to_string())
+        .bind(&one_time_code)
+        .bind(&valid_until)
+        .bind(card_id)
+        .execute(&mut *tx)
+        .await?;
+
+        rotated.push(RotatedCard {
+            card_id: card.card_id,
+            card_name: card.card_name,
+            one_time_code,
+            valid_until: valid_until.clone(),
+            previous_keys: CardPreviousKeys { valid_until: valid_until.clone(), ..previous_keys },
+        });
+    }
+
+    audit::record(
+        &mut tx,
+        actor,
+        "rotate_keys",
+        &serde_json::json!({
+            "selector": selector,
+            "card_ids": card_ids,
+            "valid_until": valid_until,
+        }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(rotated)
+}
+
+/// Keys the card had before its last rotation, if they are still accepted
+pub async fn get_previous_keys(pool: &Pool<Sqlite>, card_id: i64, now: DateTime<Utc>) -> Result<Option<CardPreviousKeys>> {
+    let now = sql_timestamp(now);
+    let keys = sqlx::query_as!(
+        CardPreviousKeys,
+        r#"SELECT card_id AS "card_id!", k0_auth_key, k1_decrypt_key, k2_cmac_key, k3, k4,
+                  valid_until AS "valid_until: String", created_at AS "created_at: String"
+           FROM card_previous_keys WHERE card_id = ? AND valid_until > ?"#,
+        card_id,
+        now
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(keys)
+}
+
+/// Counter of changes to card keys, see [`crate::key_cache::KeyCache::refresh`]
+pub async fn key_generation(pool: &Pool<Sqlite>) -> Result<i64> {
+    let generation = sqlx::query_scalar("SELECT generation FROM card_key_generation")
+        .fetch_one(pool)
+        .await?;
+
+    Ok(generation)
+}
+
+/// Stop accepting the old keys, once the card has proven it carries the new ones
+///
+/// Called on every tap, so it only writes if the card was rotated and still
+/// has old keys, and most taps get by with a read.
+pub async fn clear_previous_keys(pool: &Pool<Sqlite>, card_id: i64) -> Result<()> {
+    let rotated: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM card_previous_keys WHERE card_id = ?)")
+        .bind(card_id)
+        .fetch_one(pool)
+        .await?;
+    if !rotated {
+        return Ok(());
+    }
+
+    sqlx::query("DELETE FROM card_previous_keys WHERE card_id = ?")
+        .bind(card_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{queries, run_migrations};
+
+    #[tokio::test]
+    async fn test_rotate_keeps_the_keys_on_the_card() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let key = "00000000000000000000000000000000";
+        let card_id = queries::insert_card(&pool, "", key, key, key, key, key, "Card", 1000, 10000, true, false, "code", None, None, chrono::Utc::now())
+            .await
+            .unwrap();
+        let selector = CardSelector { card_ids: Some(vec![card_id]), ..Default::default() };
+        let random = Random::seeded(1);
+        let now = Utc::now();
+
+        let rotated = rotate(&pool, &selector, chrono::Duration::hours(1), "test", &random).await.unwrap();
+        assert_eq!(rotated.len(), 1);
+        assert_eq!(rotated[0].previous_keys.k1_decrypt_key, key);
+        let card = queries::get_card_by_id(&pool, card_id).await.unwrap().unwrap();
+        assert_ne!(card.k1_decrypt_key, key);
+        assert_eq!(card.one_time_code.as_deref(), Some(rotated[0].one_time_code.as_str()));
+        assert_eq!(get_previous_keys(&pool, card_id, now).await.unwrap().unwrap().k1_decrypt_key, key);
+        // The old keys stop working after the grace window
+        assert!(get_previous_keys(&pool, card_id, now + chrono::Duration::hours(2)).await.unwrap().is_none());
+
+        // The new keys were never fetched, so the card still carries the original ones
+        let rotated = rotate(&pool, &selector, chrono::Duration::hours(1), "test", &random).await.unwrap();
+        assert_eq!(rotated[0].previous_keys.k1_decrypt_key, key);
+
+        // Once fetched, the new keys are the ones to keep accepting
+        sqlx::query("UPDATE cards SET one_time_code_used = 1 WHERE card_id = ?").bind(card_id).execute(&pool).await.unwrap();
+        let fetched = queries::get_card_by_id(&pool, card_id).await.unwrap().unwrap();
+        let rotated = rotate(&pool, &selector, chrono::Duration::hours(1), "test", &random).await.unwrap();
+        assert_eq!(rotated[0].previous_keys.k1_decrypt_key, fetched.k1_decrypt_key);
+
+        clear_previous_keys(&pool, card_id).await.unwrap();
+        assert!(get_previous_keys(&pool, card_id, now).await.unwrap().is_none());
+        clear_previous_keys(&pool, card_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rotate_without_cards() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let selector = CardSelector { tag: Some("fleet".to_string()), ..Default::default() };
+
+        let rotated = rotate(&pool, &selector, chrono::Duration::hours(1), "test", &Random::seeded(1)).await.unwrap();
+        assert!(rotated.is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+//! Sample data for development
+//!
+//! Gives contributors a working dataset right away: a card with the keys of
+//! the published Bolt Card test vectors, so its `p`/`c` pairs can be tapped
+//! as they are, and a few cards with payments to look at in the dashboard.
+
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::{Pool, Sqlite};
+
+use crate::{
+    db::queries,
+    lightning::{LightningBackend, MockLightning},
+    random::Random,
+};
+
+/// UID and keys of the Bolt Card test vectors
+pub const TEST_VECTOR_UID: &str = "04996c6a926980";
+pub const TEST_VECTOR_K1: &str = "0c3b25d92b38ae443229dd59ad34b85d";
+pub const TEST_VECTOR_K2: &str = "b45775776cb224c75bcde7ca3704e933";
+/// The vectors' taps as `(counter, p, c)`, in the order they have to be used
+pub const TEST_VECTOR_TAPS: [(u32, &str, &str); 3] = [
+    (3, "4E2E289D945A66BB13377A728884E867", "E19CCB1FED8892CE"),
+    (5, "00F48C4F8E386DED06BCDC78FA92E2FE", "66B4826EA4C155B4"),
+    (7, "0DBF3C59B59B0638D60B5842A997D4D1", "CC61660C020B4D96"),
+];
+/// K0, K3 and K4 of a blank card
+const ZERO_KEY: &str = "00000000000000000000000000000000";
+
+/// Sample cards: name, tx limit, day limit, balance in sats if in balance mode, enabled
+const SAMPLE_CARDS: [(&str, i64, i64, Option<i64>, bool); 3] = [
+    ("Alice", 100_000, 1_000_000, None, true),
+    ("Bob (prepaid)", 50_000, 200_000, Some(150_000), true),
+    ("Lost card", 100_000, 1_000_000, None, false),
+];
+
+/// Settled payments of the sample cards: card index, sats, days ago
+const SAMPLE_PAYMENTS: [(usize, i64, i64); 5] = [(0, 21_000, 1), (0, 5_000, 2), (0, 120_000, 6), (1, 10_000, 3), (2, 2_100, 9)];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SeededCard {
+    pub card_id: i64,
+    pub card_name: String,
+    pub one_time_code: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Seeded {
+    /// The card with the test vectors' keys comes first
+    pub cards: Vec<SeededCard>,
+    pub payments: usize,
+}
+
+impl Seeded {
+    pub fn test_vector_card(&self) -> &SeededCard {
+        &self.cards[0]
+    }
+}
+
+/// `/ln` URLs of the test vectors' taps on the server at `server`
+pub fn tap_urls(server: &str, card_id: i64) -> Vec<String> {
+    TEST_VECTOR_TAPS
+        .iter()
+        .map(|(_, p, c)| format!("{}/ln?card_id={}&p={}&c={}", server.trim_end_matches('/'), card_id, p, c))
+        .collect()
+}
+
+/// Load the sample data, `None` if the database already has it
+pub async fn seed(pool: &Pool<Sqlite>, random: &Random) -> Result<Option<Seeded>> {
+    let seeded: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM cards WHERE uid = ?)")
+        .bind(TEST_VECTOR_UID)
+        .fetch_one(pool)
+        .await?;
+    if seeded {
+        return Ok(None);
+    }
+
+    let mut cards = Vec::new();
+    let one_time_code = random.hex::<16>()?;
+    let card_id = queries::insert_card(
+        pool, "", ZERO_KEY, TEST_VECTOR_K1, TEST_VECTOR_K2, ZERO_KEY, ZERO_KEY,
+        "Test vector card", 100_000, 1_000_000, true, false, &one_time_code, None, None,
+        chrono::Utc::now(),
+    )
+    .await?;
+    sqlx::query("UPDATE cards SET uid = ? WHERE card_id = ?")
+        .bind(TEST_VECTOR_UID)
+        .bind(card_id)
+        .execute(pool)
+        .await?;
+    cards.push(SeededCard { card_id, card_name: "Test vector card".to_string(), one_time_code });
+
+    for (name, tx_limit, day_limit, balance_sats, enabled) in SAMPLE_CARDS {
+        let one_time_code = random.hex::<16>()?;
+        let keys = [random.aes_key()?, random.aes_key()?, random.aes_key()?, random.aes_key()?, random.aes_key()?]
+            .map(|key| key.to_string());
+        let card_id = queries::insert_card(
+            pool, "", &keys[0], &keys[1], &keys[2], &keys[3], &keys[4],
+            name, tx_limit, day_limit, enabled, balance_sats.is_some(), &one_time_code, None, None,
+            chrono::Utc::now(),
+        )
+        .await?;
+        if let Some(balance_sats) = balance_sats {
+            queries::credit_balance(pool, card_id, balance_sats * 1000).await?;
+        }
+        cards.push(SeededCard { card_id, card_name: name.to_string(), one_time_code });
+    }
+
+    // Invoices of a mock node, so receipts and exports have something real to show
+    let node = MockLightning::default();
+    for (card, sats, days_ago) in SAMPLE_PAYMENTS {
+        let card_id = cards[card + 1].card_id;
+        let invoice = node.create_invoice(sats as u64 * 1000, "Sample payment").await?;
+        sqlx::query(
+            "INSERT INTO card_payments
+             (card_id, k1, invoice, payment_hash, amount_msats, status, attempts, fee_msats, created_at, payment_time)
+             VALUES (?, ?, ?, ?, ?, 'settled', 1, 0, datetime('now', ?), datetime('now', ?))"
+        )
+        .bind(card_id)
+        .bind(random.hex::<16>()?)
+        .bind(invoice.bolt11())
+        .bind(invoice.payment_hash())
+        .bind(sats * 1000)
+        .bind(format!("-{} days", days_ago))
+        .bind(format!("-{} days", days_ago))
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(Some(Seeded { cards, payments: SAMPLE_PAYMENTS.len() }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::validate_card_pure;
+
+    #[test]
+    fn test_vector_taps_validate() {
+        for (counter, p, c) in TEST_VECTOR_TAPS {
+            let result = validate_card_pure(TEST_VECTOR_K1, TEST_VECTOR_K2, p, c).unwrap();
+            assert_eq!(result.uid.to_string(), TEST_VECTOR_UID);
+            assert_eq!(result.counter.value(), counter);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use crate::{clock::sql_timestamp, db::models::DashboardSession};
+
+pub async fn create_session(
+    pool: &Pool<Sqlite>,
+    session_hash: &str,
+    username: &str,
+    role: &str,
+    ttl_hours: i64,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let expiry_str = sql_timestamp(now + chrono::Duration::hours(ttl_hours));
+
+    sqlx::query(
+        "INSERT INTO dashboard_sessions (session_hash, username, role, expires_at) VALUES (?, ?, ?, ?)"
+    )
+    .bind(session_hash)
+    .bind(username)
+    .bind(role)
+    .bind(expiry_str)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_active_session(pool: &Pool<Sqlite>, session_hash: &str, now: DateTime<Utc>) -> Result<Option<DashboardSession>> {
+    let now = sql_timestamp(now);
+    let session = sqlx::query_as!(
+        DashboardSession,
+        r#"SELECT session_hash AS "session_hash!", username, expires_at AS "expires_at: String",
+                  created_at AS "created_at: String", role
+           FROM dashboard_sessions WHERE session_hash = ? AND expires_at > ?"#,
+        session_hash,
+        now
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(session)
+}
+
+pub async fn delete_session(pool: &Pool<Sqlite>, session_hash: &str) -> Result<()> {
+    sqlx::query("DELETE FROM dashboard_sessions WHERE session_hash = ?")
+        .bind(session_hash)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn delete_expired_sessions(pool: &Pool<Sqlite>, now: DateTime<Utc>) -> Result<u64> {
+    let result = sqlx::query("DELETE FROM dashboard_sessions WHERE expires_at <= ?")
+        .bind(sql_timestamp(now))
+        .execute(pool)
+        .await?;
+
+    sqlx::query("DELETE FROM oidc_login_states WHERE expires_at <= ?")
+        .bind(sql_timestamp(now))
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn create_oidc_state(pool: &Pool<Sqlite>, state: &str, code_verifier: &str, now: DateTime<Utc>) -> Result<()> {
+    let expiry_str = sql_timestamp(now + chrono::Duration::minutes(10));
+
+    sqlx::query(
+        "INSERT INTO oidc_login_states (state, code_verifier, expires_at) VALUES (?, ?, ?)"
+    )
+    .bind(state)
+    .bind(code_verifier)
+    .bind(expiry_str)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Consume a pending OIDC login, returning its PKCE verifier if still valid
+pub async fn take_oidc_state(pool: &Pool<Sqlite>, state: &str, now: DateTime<Utc>) -> Result<Option<String>> {
+    let row: Option<(String,)> = sqlx::query_as(
+        "DELETE FROM oidc_login_states WHERE state = ? AND expires_at > ?
+         RETURNING code_verifier"
+    )
+    .bind(state)
+    .bind(sql_timestamp(now))
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| r.0))
+}
@@ -0,0 +1,224 @@
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+use chrono::{Duration, NaiveDate, Utc};
+use serde::Serialize;
+
+/// Activity for a single day
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct DailyStats {
+    pub day: String,
+    pub volume_msats: i64,
+    pub payments: i64,
+    pub failures: i64,
+}
+
+/// Settled volume for a single card
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct CardVolume {
+    pub card_id: i64,
+    pub card_name: String,
+    pub volume_msats: i64,
+    pub payments: i64,
+}
+
+/// Usage of a single card over a date range
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct CardUsage {
+    pub card_id: i64,
+    pub card_name: String,
+    pub volume_msats: i64,
+    pub payments: i64,
+    pub failures: i64,
+}
+
+/// Usage between two dates, both inclusive
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageReport {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub total_volume_msats: i64,
+    pub total_payments: i64,
+    pub total_failures: i64,
+    pub failure_rate: f64,
+    pub cards: Vec<CardUsage>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsReport {
+    pub days: i64,
+    pub card_id: Option<i64>,
+    pub total_volume_msats: i64,
+    pub total_payments: i64,
+    pub total_failures: i64,
+    /// Share of attempted payments (invoice submitted) that did not settle
+    pub failure_rate: f64,
+    pub daily: Vec<DailyStats>,
+    pub top_cards: Vec<CardVolume>,
+}
+
+/// Per-day volume, settled payments and failures over the last `days` days
+///
+/// A failure is a session whose last payment attempt failed. Days without activity are included with zero values.
+pub async fn daily_stats(pool: &Pool<Sqlite>, days: i64, card_id: Option<i64>) -> Result<Vec<DailyStats>> {
+    let rows = sqlx::query_as::<_, DailyStats>(
+        "SELECT date(created_at) AS day,
+                COALESCE(SUM(CASE WHEN status = 'settled' THEN amount_msats END), 0) AS volume_msats,
+                COALESCE(SUM(CASE WHEN status = 'settled' THEN 1 ELSE 0 END), 0) AS payments,
+                COALESCE(SUM(CASE WHEN status = 'failed' THEN 1 ELSE 0 END), 0) AS failures
+         FROM card_payments
+         WHERE created_at >= date('now', ?) AND (? IS NULL OR card_id = ?)
+         GROUP BY day
+         ORDER BY day"
+    )
+    .bind(format!("-{} days", days - 1))
+    .bind(card_id)
+    .bind(card_id)
+    .fetch_all(pool)
+    .await?;
+
+    let today = Utc::now().date_naive();
+    Ok(fill_missing_days(rows, today, days))
+}
+
+/// Cards with the highest settled volume over the last `days` days
+///
+/// Payments count on the day their session started, like in [`daily_stats`].
+pub async fn top_cards(pool: &Pool<Sqlite>, days: i64, limit: i64) -> Result<Vec<CardVolume>> {
+    let rows = sqlx::query_as::<_, CardVolume>(
+        "SELECT c.card_id, c.card_name,
+                SUM(p.amount_msats) AS volume_msats,
+                COUNT(*) AS payments
+         FROM card_payments p JOIN cards c ON c.card_id = p.card_id
+         WHERE p.status = 'settled' AND p.created_at >= date('now', ?)
+         GROUP BY c.card_id
+         ORDER BY volume_msats DESC
+         LIMIT ?"
+    )
+    .bind(format!("-{} days", days - 1))
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
+
+/// Build the full report served by the stats API and the dashboard charts
+pub async fn report(pool: &Pool<Sqlite>, days: i64, card_id: Option<i64>) -> Result<StatsReport> {
+    let daily = daily_stats(pool, days, card_id).await?;
+    let top_cards = if card_id.is_none() {
+        top_cards(pool, days, 10).await?
+    } else {
+        Vec::new()
+    };
+
+    let total_volume_msats = daily.iter().map(|d| d.volume_msats).sum();
+    let total_payments: i64 = daily.iter().map(|d| d.payments).sum();
+    let total_failures: i64 = daily.iter().map(|d| d.failures).sum();
+
+    Ok(StatsReport {
+        days,
+        card_id,
+        total_volume_msats,
+        total_payments,
+        total_failures,
+        failure_rate: failure_rate(total_payments, total_failures),
+        daily,
+        top_cards,
+    })
+}
+
+/// Per-card volume, payments and failures for sessions started between `from` and `to`
+pub async fn usage_report(pool: &Pool<Sqlite>, from: NaiveDate, to: NaiveDate) -> Result<UsageReport> {
+    let cards = sqlx::query_as::<_, CardUsage>(
+        "SELECT c.card_id, c.card_name,
+                COALESCE(SUM(CASE WHEN p.status = 'settled' THEN p.amount_msats END), 0) AS volume_msats,
+                COALESCE(SUM(CASE WHEN p.status = 'settled' THEN 1 ELSE 0 END), 0) AS payments,
+                COALESCE(SUM(CASE WHEN p.status = 'failed' THEN 1 ELSE 0 END), 0) AS failures
+         FROM card_payments p JOIN cards c ON c.card_id = p.card_id
+         WHERE date(p.created_at) BETWEEN ? AND ?
+         GROUP BY c.card_id
+         HAVING payments > 0 OR failures > 0
+         ORDER BY volume_msats DESC, c.card_id"
+    )
+    .bind(from.format("%Y-%m-%d").to_string())
+    .bind(to.format("%Y-%m-%d").to_string())
+    .fetch_all(pool)
+    .await?;
+
+    let total_volume_msats = cards.iter().map(|c| c.volume_msats).sum();
+    let total_payments: i64 = cards.iter().map(|c| c.payments).sum();
+    let total_failures: i64 = cards.iter().map(|c| c.failures).sum();
+
+    Ok(UsageReport {
+        from,
+        to,
+        total_volume_msats,
+        total_payments,
+        total_failures,
+        failure_rate: failure_rate(total_payments, total_failures),
+        cards,
+    })
+}
+
+/// Share of attempted payments that did not settle
+fn failure_rate(payments: i64, failures: i64) -> f64 {
+    let attempts = payments + failures;
+    if attempts > 0 {
+        failures as f64 / attempts as f64
+    } else {
+        0.0
+    }
+}
+
+fn fill_missing_days(rows: Vec<DailyStats>, today: NaiveDate, days: i64) -> Vec<DailyStats> {
+    (0..days)
+        .rev()
+        .map(|offset| {
+            let day = (today - Duration::days(offset)).format("%Y-%m-%d").to_string();
+            rows.iter()
+                .find(|row| row.day == day)
+                .cloned()
+                .unwrap_or(DailyStats {
+                    day,
+                    volume_msats: 0,
+                    payments: 0,
+                    failures: 0,
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{queries, run_migrations};
+
+    #[tokio::test]
+    async fn test_top_cards_match_daily_series() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let key = "00000000000000000000000000000000";
+        let card_id = queries::insert_card(&pool, "", key, key, key, key, key, "Card", 1000, 10000, true, false, "code", None, None, chrono::Utc::now())
+            .await
+            .unwrap();
+        // Sessions started yesterday and a week ago, both settled today
+        for (k1, started) in [("recent", "-1 days"), ("old", "-7 days")] {
+            sqlx::query(
+                "INSERT INTO card_payments (card_id, k1, status, amount_msats, created_at, payment_time)
+                 VALUES (?, ?, 'settled', 1000, datetime('now', ?), datetime('now'))"
+            )
+            .bind(card_id)
+            .bind(k1)
+            .bind(started)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let report = report(&pool, 3, None).await.unwrap();
+        assert_eq!(report.total_volume_msats, 1000);
+        assert_eq!(report.top_cards.len(), 1);
+        assert_eq!(report.top_cards[0].volume_msats, report.total_volume_msats);
+        assert_eq!(report.top_cards[0].payments, report.total_payments);
+    }
+}
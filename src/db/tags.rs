@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+
+/// Trim tags and drop empty and duplicate ones
+pub fn normalize_tags<S: AsRef<str>>(tags: &[S]) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.as_ref().trim();
+        if !tag.is_empty() && !out.iter().any(|t| t == tag) {
+            out.push(tag.to_string());
+        }
+    }
+    out
+}
+
+/// Replace all tags of a card
+pub async fn set_card_tags(pool: &Pool<Sqlite>, card_id: i64, tags: &[String]) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("DELETE FROM card_tags WHERE card_id = ?")
+        .bind(card_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for tag in normalize_tags(tags) {
+        sqlx::query("INSERT INTO card_tags (card_id, tag) VALUES (?, ?)")
+            .bind(card_id)
+            .bind(tag)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Tags of one card
+pub async fn get_card_tags(pool: &Pool<Sqlite>, card_id: i64) -> Result<Vec<String>> {
+    let tags = sqlx::query_scalar("SELECT tag FROM card_tags WHERE card_id = ? ORDER BY tag")
+        .bind(card_id)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(tags)
+}
+
+/// Tags of all cards, keyed by card id
+pub async fn list_card_tags(pool: &Pool<Sqlite>) -> Result<HashMap<i64, Vec<String>>> {
+    let rows: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT card_id, tag FROM card_tags ORDER BY card_id, tag"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+    for (card_id, tag) in rows {
+        tags.entry(card_id).or_default().push(tag);
+    }
+
+    Ok(tags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_tags() {
+        assert_eq!(
+            normalize_tags(&[" event-2024", "", "staff", "event-2024 "]),
+            vec!["event-2024".to_string(), "staff".to_string()]
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use sqlx::{Pool, Sqlite, SqliteConnection};
+use anyhow::Result;
+use crate::db::models::CardTap;
+
+pub async fn record_tap(
+    conn: &mut SqliteConnection,
+    card_id: i64,
+    uid: Option<&str>,
+    counter: Option<i64>,
+    success: bool,
+    reason: Option<&str>,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO card_taps (card_id, uid, counter, success, reason) VALUES (?, ?, ?, ?, ?)"
+    )
+    .bind(card_id)
+    .bind(uid)
+    .bind(counter)
+    .bind(success)
+    .bind(reason)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_recent_taps(pool: &Pool<Sqlite>, card_id: i64, limit: i64) -> Result<Vec<CardTap>> {
+    let taps = sqlx::query_as!(
+        CardTap,
+        r#"SELECT tap_id AS "tap_id!", card_id, uid, counter, success, reason, created_at AS "created_at: String"
+           FROM card_taps WHERE card_id = ? ORDER BY tap_id DESC LIMIT ?"#,
+        card_id,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(taps)
+}
+
+pub async fn get_first_successful_tap(pool: &Pool<Sqlite>, card_id: i64) -> Result<Option<CardTap>> {
+    let tap = sqlx::query_as!(
+        CardTap,
+        r#"SELECT tap_id AS "tap_id!", card_id, uid, counter, success, reason, created_at AS "created_at: String"
+           FROM card_taps WHERE card_id = ? AND success = 1 ORDER BY tap_id LIMIT 1"#,
+        card_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(tap)
+}
@@ -0,0 +1,102 @@
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use crate::{
+    db::{models::Card, query_card, wallet},
+    random::Random,
+};
+
+/// What a card token grants access to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenScope {
+    /// Cardholder self-service lost report
+    ReportLost,
+    /// Read-only status widget for customer-facing displays
+    Widget,
+    /// Top-up page for balance-mode cards
+    TopUp,
+}
+
+impl TokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::ReportLost => "report_lost",
+            TokenScope::Widget => "widget",
+            TokenScope::TopUp => "top_up",
+        }
+    }
+}
+
+/// Tokens are stored hashed, the plain value only exists in the issued URL
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Issue a new token for a card and return its plain value
+pub async fn create_card_token(pool: &Pool<Sqlite>, random: &Random, card_id: i64, scope: TokenScope) -> Result<String> {
+    let token = random.hex::<16>()?;
+
+    sqlx::query(
+        "INSERT INTO card_tokens (token_hash, card_id, scope) VALUES (?, ?, ?)"
+    )
+    .bind(hash_token(&token))
+    .bind(card_id)
+    .bind(scope.as_str())
+    .execute(pool)
+    .await?;
+
+    Ok(token)
+}
+
+/// Resolve a token to its card, only if it was issued for `scope`
+pub async fn get_card_by_token(pool: &Pool<Sqlite>, token: &str, scope: TokenScope) -> Result<Option<Card>> {
+    let token_hash = hash_token(token);
+    let scope = scope.as_str();
+    let card = query_card!(
+        "WHERE card_id = (SELECT card_id FROM card_tokens WHERE token_hash = ? AND scope = ?)",
+        token_hash,
+        scope
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(card)
+}
+
+/// Like [`get_card_by_token`], but `None` for a card bound to a wallet, which
+/// only the wallet may report lost or look at
+pub async fn get_unbound_card_by_token(pool: &Pool<Sqlite>, token: &str, scope: TokenScope) -> Result<Option<Card>> {
+    let Some(card) = get_card_by_token(pool, token, scope).await? else {
+        return Ok(None);
+    };
+    if wallet::get_card_auth_key(pool, card.card_id).await?.is_some() {
+        return Ok(None);
+    }
+
+    Ok(Some(card))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{queries, run_migrations};
+
+    #[tokio::test]
+    async fn test_bound_card_tokens() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let key = "00000000000000000000000000000000";
+        let card_id = queries::insert_card(&pool, "", key, key, key, key, key, "Card", 1000, 10000, true, false, "code", None, None, chrono::Utc::now())
+            .await
+            .unwrap();
+        let token = create_card_token(&pool, &Random::seeded(1), card_id, TokenScope::Widget).await.unwrap();
+
+        assert!(get_unbound_card_by_token(&pool, &token, TokenScope::Widget).await.unwrap().is_some());
+        assert!(get_unbound_card_by_token(&pool, &token, TokenScope::ReportLost).await.unwrap().is_none());
+
+        wallet::claim_card_by_code(&pool, "code", "wallet", chrono::Utc::now()).await.unwrap().unwrap();
+        assert!(get_unbound_card_by_token(&pool, &token, TokenScope::Widget).await.unwrap().is_none());
+        assert!(get_card_by_token(&pool, &token, TokenScope::Widget).await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,68 @@
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+use crate::db::models::CardTopup;
+
+pub async fn create_topup(
+    pool: &Pool<Sqlite>,
+    card_id: i64,
+    payment_hash: &str,
+    invoice: &str,
+    amount_msats: i64,
+) -> Result<i64> {
+    let result = sqlx::query(
+        "INSERT INTO card_topups (card_id, payment_hash, invoice, amount_msats) VALUES (?, ?, ?, ?)"
+    )
+    .bind(card_id)
+    .bind(payment_hash)
+    .bind(invoice)
+    .bind(amount_msats)
+    .execute(pool)
+    .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn get_topup_by_hash(pool: &Pool<Sqlite>, payment_hash: &str) -> Result<Option<CardTopup>> {
+    let topup = sqlx::query_as!(
+        CardTopup,
+        r#"SELECT topup_id AS "topup_id!", card_id, payment_hash, invoice, amount_msats, paid,
+                  paid_at AS "paid_at: String", created_at AS "created_at: String"
+           FROM card_topups WHERE payment_hash = ?"#,
+        payment_hash
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(topup)
+}
+
+/// Mark a top-up as paid and credit the card's balance and funding
+///
+/// Returns false if the top-up was already settled, so concurrent status
+/// checks can't credit the same invoice twice.
+pub async fn settle_topup(pool: &Pool<Sqlite>, topup: &CardTopup) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query(
+        "UPDATE card_topups SET paid = 1, paid_at = CURRENT_TIMESTAMP
+         WHERE topup_id = ? AND paid = 0"
+    )
+    .bind(topup.topup_id)
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query("UPDATE cards SET balance_msats = balance_msats + ?, funded_msats = funded_msats + ? WHERE card_id = ?")
+        .bind(topup.amount_msats)
+        .bind(topup.amount_msats)
+        .bind(topup.card_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
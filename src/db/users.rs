@@ -0,0 +1,389 @@
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+use crate::{
+    clock::sql_timestamp,
+    db::{models::{Card, CardPayment, User, UserApiKey, UserPolicy}, query_card, query_payment, query_user},
+};
+
+/// What an API key may do with its user's cards
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeyScope {
+    /// List cards and read their details and payments
+    Read,
+    /// Create cards owned by the user
+    Create,
+    /// Create top-up invoices for balance-mode cards and follow them
+    TopUp,
+}
+
+impl ApiKeyScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiKeyScope::Read => "read",
+            ApiKeyScope::Create => "create",
+            ApiKeyScope::TopUp => "top_up",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "read" => Some(ApiKeyScope::Read),
+            "create" => Some(ApiKeyScope::Create),
+            "top_up" => Some(ApiKeyScope::TopUp),
+            _ => None,
+        }
+    }
+
+    /// Parse a stored scope list, skipping unknown scopes
+    pub fn parse_list(s: &str) -> Vec<Self> {
+        s.split(',').filter_map(Self::parse).collect()
+    }
+}
+
+/// Create a user, or return `None` if the username is taken
+pub async fn create_user(pool: &Pool<Sqlite>, username: &str, password_hash: &str) -> Result<Option<i64>> {
+    let user_id = sqlx::query_scalar(
+        "INSERT INTO users (username, password_hash) VALUES (?, ?)
+         ON CONFLICT (username) DO NOTHING RETURNING user_id"
+    )
+    .bind(username)
+    .bind(password_hash)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user_id)
+}
+
+pub async fn get_user_by_username(pool: &Pool<Sqlite>, username: &str) -> Result<Option<User>> {
+    let user = query_user!("WHERE username = ?", username)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(user)
+}
+
+pub async fn list_users(pool: &Pool<Sqlite>) -> Result<Vec<(User, i64)>> {
+    let users = query_user!("ORDER BY user_id")
+        .fetch_all(pool)
+        .await?;
+
+    let card_counts: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT owner_id, COUNT(*) FROM cards WHERE owner_id IS NOT NULL GROUP BY owner_id"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(users
+        .into_iter()
+        .map(|user| {
+            let cards = card_counts
+                .iter()
+                .find(|(owner_id, _)| *owner_id == user.user_id)
+                .map_or(0, |(_, count)| *count);
+            (user, cards)
+        })
+        .collect())
+}
+
+/// Enable or disable a user; a disabled user's sessions are ended
+pub async fn set_user_enabled(pool: &Pool<Sqlite>, username: &str, enabled: bool) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+
+    let user_id: Option<i64> = sqlx::query_scalar("UPDATE users SET enabled = ? WHERE username = ? AND erased_at IS NULL RETURNING user_id")
+        .bind(enabled)
+        .bind(username)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    if let Some(user_id) = user_id
+        && !enabled
+    {
+        sqlx::query("DELETE FROM user_sessions WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(user_id.is_some())
+}
+
+/// Replace a user's policy, `false` if there's no such user
+pub async fn set_user_policy(pool: &Pool<Sqlite>, username: &str, policy: &UserPolicy) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE users SET max_cards = ?, default_tx_limit_sats = ?, default_day_limit_sats = ?,
+         max_tx_limit_sats = ?, max_day_limit_sats = ? WHERE username = ?"
+    )
+    .bind(policy.max_cards)
+    .bind(policy.default_tx_limit_sats)
+    .bind(policy.default_day_limit_sats)
+    .bind(policy.max_tx_limit_sats)
+    .bind(policy.max_day_limit_sats)
+    .bind(username)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_user_policy(pool: &Pool<Sqlite>, user_id: i64) -> Result<Option<UserPolicy>> {
+    let policy = sqlx::query_as!(
+        UserPolicy,
+        "SELECT max_cards, default_tx_limit_sats, default_day_limit_sats, max_tx_limit_sats, max_day_limit_sats
+         FROM users WHERE user_id = ?",
+        user_id
+    )
+    .fetch_optional(pool)
+        .await?;
+
+    Ok(policy)
+}
+
+pub async fn create_session(
+    pool: &Pool<Sqlite>,
+    token_hash: &str,
+    user_id: i64,
+    ttl_hours: i64,
+    now: DateTime<Utc>,
+) -> Result<String> {
+    let expiry_str = sql_timestamp(now + chrono::Duration::hours(ttl_hours));
+
+    sqlx::query("DELETE FROM user_sessions WHERE expires_at <= ?")
+        .bind(sql_timestamp(now))
+        .execute(pool)
+        .await?;
+
+    sqlx::query("INSERT INTO user_sessions (token_hash, user_id, expires_at) VALUES (?, ?, ?)")
+        .bind(token_hash)
+        .bind(user_id)
+        .bind(&expiry_str)
+        .execute(pool)
+        .await?;
+
+    Ok(expiry_str)
+}
+
+/// The enabled user an unexpired session token belongs to
+pub async fn get_session_user(pool: &Pool<Sqlite>, token_hash: &str, now: DateTime<Utc>) -> Result<Option<User>> {
+    let now = sql_timestamp(now);
+    let user = query_user!(
+        "WHERE user_id = (SELECT user_id FROM user_sessions WHERE token_hash = ? AND expires_at > ?)
+         AND enabled",
+        token_hash,
+        now
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user)
+}
+
+pub async fn delete_session(pool: &Pool<Sqlite>, token_hash: &str) -> Result<()> {
+    sqlx::query("DELETE FROM user_sessions WHERE token_hash = ?")
+        .bind(token_hash)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn count_owned_cards(pool: &Pool<Sqlite>, user_id: i64) -> Result<i64> {
+    let count = sqlx::query_scalar("SELECT COUNT(*) FROM cards WHERE owner_id = ?")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(count)
+}
+
+pub async fn list_owned_cards(pool: &Pool<Sqlite>, user_id: i64) -> Result<Vec<Card>> {
+    let cards = query_card!("WHERE owner_id = ? AND deleted_at IS NULL ORDER BY card_id", user_id)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(cards)
+}
+
+/// The card if `user_id` owns it, so one user can't reach another's cards by ID
+pub async fn get_owned_card(pool: &Pool<Sqlite>, user_id: i64, card_id: i64) -> Result<Option<Card>> {
+    let card = query_card!(
+        "WHERE card_id = ? AND owner_id = ? AND deleted_at IS NULL AND erased_at IS NULL",
+        card_id,
+        user_id
+    )
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(card)
+}
+
+/// Withdrawals of an owned card, newest first
+pub async fn list_owned_card_payments(pool: &Pool<Sqlite>, user_id: i64, card_id: i64) -> Result<Vec<CardPayment>> {
+    let payments = query_payment!(
+        "WHERE card_id = ? AND card_id IN (SELECT card_id FROM cards WHERE owner_id = ?)
+         ORDER BY payment_id DESC",
+        card_id,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(payments)
+}
+
+/// Store a new API key, returning its ID
+pub async fn create_api_key(
+    pool: &Pool<Sqlite>,
+    user_id: i64,
+    name: &str,
+    key_hash: &str,
+    scopes: &[ApiKeyScope],
+) -> Result<i64> {
+    let scopes = scopes.iter().map(ApiKeyScope::as_str).collect::<Vec<_>>().join(",");
+
+    let key_id = sqlx::query_scalar(
+        "INSERT INTO user_api_keys (user_id, name, key_hash, scopes) VALUES (?, ?, ?, ?) RETURNING key_id"
+    )
+    .bind(user_id)
+    .bind(name)
+    .bind(key_hash)
+    .bind(scopes)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(key_id)
+}
+
+pub async fn list_api_keys(pool: &Pool<Sqlite>, user_id: i64) -> Result<Vec<UserApiKey>> {
+    let keys = sqlx::query_as::<_, UserApiKey>(
+        "SELECT key_id, user_id, name, scopes, created_at, last_used_at, revoked_at
+         FROM user_api_keys WHERE user_id = ? ORDER BY key_id"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(keys)
+}
+
+/// Revoke one of the user's keys, `false` if it doesn't exist or was revoked before
+pub async fn revoke_api_key(pool: &Pool<Sqlite>, user_id: i64, key_id: i64) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE user_api_keys SET revoked_at = CURRENT_TIMESTAMP
+         WHERE key_id = ? AND user_id = ? AND revoked_at IS NULL"
+    )
+    .bind(key_id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// The enabled user and scopes of an unrevoked key, noting that it was used
+pub async fn use_api_key(pool: &Pool<Sqlite>, key_hash: &str) -> Result<Option<(User, Vec<ApiKeyScope>)>> {
+    let scopes: Option<(i64, String)> = sqlx::query_as(
+        "UPDATE user_api_keys SET last_used_at = CURRENT_TIMESTAMP
+         WHERE key_hash = ? AND revoked_at IS NULL RETURNING user_id, scopes"
+    )
+    .bind(key_hash)
+    .fetch_optional(pool)
+    .await?;
+    let Some((user_id, scopes)) = scopes else {
+        return Ok(None);
+    };
+
+    let user = query_user!("WHERE user_id = ? AND enabled", user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(user.map(|user| (user, ApiKeyScope::parse_list(&scopes))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{db::{queries, run_migrations}, policy::{self, PolicyViolation}};
+
+    async fn setup() -> (Pool<Sqlite>, i64, i64) {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let alice = create_user(&pool, "alice", "hash").await.unwrap().unwrap();
+        let bob = create_user(&pool, "bob", "hash").await.unwrap().unwrap();
+        (pool, alice, bob)
+    }
+
+    async fn insert_card(pool: &Pool<Sqlite>, code: &str, owner_id: Option<i64>) -> i64 {
+        let key = "00000000000000000000000000000000";
+        queries::insert_card(pool, "", key, key, key, key, key, code, 1000, 10000, true, true, code, owner_id, None, chrono::Utc::now())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_owned_cards_are_scoped_to_their_owner() {
+        let (pool, alice, bob) = setup().await;
+        let alices = insert_card(&pool, "a", Some(alice)).await;
+        let bobs = insert_card(&pool, "b", Some(bob)).await;
+        insert_card(&pool, "operator", None).await;
+        sqlx::query("INSERT INTO card_payments (card_id, k1, status) VALUES (?, 'k1', 'settled')")
+            .bind(alices)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let cards: Vec<i64> = list_owned_cards(&pool, alice).await.unwrap().iter().map(|card| card.card_id).collect();
+        assert_eq!(cards, vec![alices]);
+        assert!(get_owned_card(&pool, alice, alices).await.unwrap().is_some());
+        assert!(get_owned_card(&pool, alice, bobs).await.unwrap().is_none());
+        assert_eq!(list_owned_card_payments(&pool, alice, alices).await.unwrap().len(), 1);
+        assert!(list_owned_card_payments(&pool, bob, alices).await.unwrap().is_empty());
+        assert!(get_owned_card(&pool, bob, bobs).await.unwrap().is_some());
+
+        // Deleted and erased cards are gone for their owner too
+        sqlx::query("UPDATE cards SET deleted_at = CURRENT_TIMESTAMP WHERE card_id = ?")
+            .bind(bobs)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(get_owned_card(&pool, bob, bobs).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_session_expires_by_the_given_time() {
+        let (pool, alice, _) = setup().await;
+        let now = chrono::Utc::now();
+        let expires_at = create_session(&pool, "hash", alice, 2, now).await.unwrap();
+        assert_eq!(expires_at, sql_timestamp(now + chrono::Duration::hours(2)));
+
+        let user_at = |at| get_session_user(&pool, "hash", at);
+        assert_eq!(user_at(now).await.unwrap().map(|user| user.user_id), Some(alice));
+        assert!(user_at(now + chrono::Duration::hours(3)).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_card_quota() {
+        let (pool, alice, _) = setup().await;
+        let defaults = (100_000, 1_000_000);
+
+        // Without a policy of their own, the server's quota applies
+        let policy = policy::effective(get_user_policy(&pool, alice).await.unwrap(), 1);
+        assert!(policy::new_card_limits(&policy, count_owned_cards(&pool, alice).await.unwrap(), (None, None), defaults).is_ok());
+        insert_card(&pool, "a", Some(alice)).await;
+        assert_eq!(
+            policy::new_card_limits(&policy, count_owned_cards(&pool, alice).await.unwrap(), (None, None), defaults),
+            Err(PolicyViolation::CardQuotaReached(1))
+        );
+
+        let tier = UserPolicy { max_cards: Some(2), ..UserPolicy::default() };
+        assert!(set_user_policy(&pool, "alice", &tier).await.unwrap());
+        assert!(!set_user_policy(&pool, "carol", &tier).await.unwrap());
+        let policy = policy::effective(get_user_policy(&pool, alice).await.unwrap(), 1);
+        assert_eq!(policy, tier);
+        assert!(policy::new_card_limits(&policy, count_owned_cards(&pool, alice).await.unwrap(), (None, None), defaults).is_ok());
+    }
+}
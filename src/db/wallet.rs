@@ -0,0 +1,260 @@
+use sqlx::{Pool, Sqlite};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+use crate::{clock::sql_timestamp, db::{models::{Card, CardPayment}, query_card, query_payment}};
+
+/// Where a login challenge stands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChallengeState {
+    /// Unknown or expired
+    Missing,
+    /// Waiting for the wallet's signature
+    Pending,
+    /// Signed by the wallet with this linking key, the challenge is used up
+    Signed(String),
+}
+
+pub async fn create_challenge(pool: &Pool<Sqlite>, k1: &str, ttl_secs: i64, now: DateTime<Utc>) -> Result<()> {
+    let expiry_str = sql_timestamp(now + chrono::Duration::seconds(ttl_secs));
+
+    sqlx::query("DELETE FROM wallet_auth_challenges WHERE expires_at <= ?")
+        .bind(sql_timestamp(now))
+        .execute(pool)
+        .await?;
+
+    sqlx::query("INSERT INTO wallet_auth_challenges (k1, expires_at) VALUES (?, ?)")
+        .bind(k1)
+        .bind(expiry_str)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Record the wallet's signature of an open challenge, `false` if it's unknown, expired or signed already
+pub async fn sign_challenge(pool: &Pool<Sqlite>, k1: &str, linking_key: &str, now: DateTime<Utc>) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE wallet_auth_challenges SET linking_key = ?
+         WHERE k1 = ? AND linking_key IS NULL AND expires_at > ?"
+    )
+    .bind(linking_key)
+    .bind(k1)
+    .bind(sql_timestamp(now))
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// The state of a challenge, removing it once signed so it yields one session only
+pub async fn take_challenge(pool: &Pool<Sqlite>, k1: &str, now: DateTime<Utc>) -> Result<ChallengeState> {
+    let signed: Option<String> = sqlx::query_scalar(
+        "DELETE FROM wallet_auth_challenges
+         WHERE k1 = ? AND linking_key IS NOT NULL AND expires_at > ? RETURNING linking_key"
+    )
+    .bind(k1)
+    .bind(sql_timestamp(now))
+    .fetch_optional(pool)
+    .await?;
+    if let Some(linking_key) = signed {
+        return Ok(ChallengeState::Signed(linking_key));
+    }
+
+    let pending: Option<String> =
+        sqlx::query_scalar("SELECT k1 FROM wallet_auth_challenges WHERE k1 = ? AND expires_at > ?")
+            .bind(k1)
+            .bind(sql_timestamp(now))
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(match pending {
+        Some(_) => ChallengeState::Pending,
+        None => ChallengeState::Missing,
+    })
+}
+
+pub async fn create_session(
+    pool: &Pool<Sqlite>,
+    token_hash: &str,
+    linking_key: &str,
+    ttl_hours: i64,
+    now: DateTime<Utc>,
+) -> Result<String> {
+    let expiry_str = sql_timestamp(now + chrono::Duration::hours(ttl_hours));
+
+    sqlx::query("DELETE FROM wallet_sessions WHERE expires_at <= ?")
+        .bind(sql_timestamp(now))
+        .execute(pool)
+        .await?;
+
+    sqlx::query("INSERT INTO wallet_sessions (token_hash, linking_key, expires_at) VALUES (?, ?, ?)")
+        .bind(token_hash)
+        .bind(linking_key)
+        .bind(&expiry_str)
+        .execute(pool)
+        .await?;
+
+    Ok(expiry_str)
+}
+
+/// The linking key an unexpired session token belongs to
+pub async fn get_session_key(pool: &Pool<Sqlite>, token_hash: &str, now: DateTime<Utc>) -> Result<Option<String>> {
+    let linking_key = sqlx::query_scalar(
+        "SELECT linking_key FROM wallet_sessions WHERE token_hash = ? AND expires_at > ?"
+    )
+    .bind(token_hash)
+    .bind(sql_timestamp(now))
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(linking_key)
+}
+
+pub async fn delete_session(pool: &Pool<Sqlite>, token_hash: &str) -> Result<()> {
+    sqlx::query("DELETE FROM wallet_sessions WHERE token_hash = ?")
+        .bind(token_hash)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Bind the unclaimed card with this one-time code to `linking_key`, returning its ID
+///
+/// Like registration, only an unused and unexpired code works, and claiming uses it up.
+pub async fn claim_card_by_code(pool: &Pool<Sqlite>, code: &str, linking_key: &str, now: DateTime<Utc>) -> Result<Option<i64>> {
+    let card_id = sqlx::query_scalar(
+        "UPDATE cards SET auth_key = ?, one_time_code_used = 1
+         WHERE one_time_code = ? AND one_time_code_used = 0 AND one_time_code_expiry > ?
+           AND auth_key IS NULL AND deleted_at IS NULL
+         RETURNING card_id"
+    )
+    .bind(linking_key)
+    .bind(code)
+    .bind(sql_timestamp(now))
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(card_id)
+}
+
+/// Bind an unclaimed card to `linking_key` with a verified tap
+///
+/// The tap's counter is used up like a payment tap's, so it can't be replayed
+/// at the LNURLw endpoint; an unused card takes its UID. Returns `false`
+/// without writing anything if the card was claimed meanwhile or a newer tap
+/// got there first.
+pub async fn claim_card_by_tap(
+    pool: &Pool<Sqlite>,
+    card_id: i64,
+    uid: &str,
+    counter: i64,
+    linking_key: &str,
+) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE cards SET auth_key = ?, last_counter = ?, uid = ?
+         WHERE card_id = ? AND auth_key IS NULL AND last_counter < ? AND (uid = '' OR uid = ?)"
+    )
+    .bind(linking_key)
+    .bind(counter)
+    .bind(uid)
+    .bind(card_id)
+    .bind(counter)
+    .bind(uid)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// The linking key a card is bound to, if it was claimed
+pub async fn get_card_auth_key(pool: &Pool<Sqlite>, card_id: i64) -> Result<Option<String>> {
+    let auth_key = sqlx::query_scalar("SELECT auth_key FROM cards WHERE card_id = ?")
+        .bind(card_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(auth_key.flatten())
+}
+
+pub async fn list_bound_cards(pool: &Pool<Sqlite>, linking_key: &str) -> Result<Vec<Card>> {
+    let cards = query_card!("WHERE auth_key = ? AND deleted_at IS NULL ORDER BY card_id", linking_key)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(cards)
+}
+
+/// The card if it's bound to `linking_key`
+pub async fn get_bound_card(pool: &Pool<Sqlite>, linking_key: &str, card_id: i64) -> Result<Option<Card>> {
+    let card = query_card!("WHERE card_id = ? AND auth_key = ? AND deleted_at IS NULL", card_id, linking_key)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(card)
+}
+
+/// Withdrawals of a bound card, newest first
+pub async fn list_bound_card_payments(pool: &Pool<Sqlite>, linking_key: &str, card_id: i64) -> Result<Vec<CardPayment>> {
+    let payments = query_payment!(
+        "WHERE card_id = ? AND card_id IN (SELECT card_id FROM cards WHERE auth_key = ?)
+         ORDER BY payment_id DESC",
+        card_id,
+        linking_key
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(payments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{queries, run_migrations};
+
+    #[tokio::test]
+    async fn test_claim_card_by_code() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let key = "00000000000000000000000000000000";
+        let mut card_ids = Vec::new();
+        for code in ["fresh", "used", "expired"] {
+            let card_id = queries::insert_card(&pool, "", key, key, key, key, key, code, 1000, 10000, true, false, code, None, None, chrono::Utc::now())
+                .await
+                .unwrap();
+            card_ids.push(card_id);
+        }
+        queries::mark_one_time_code_used(&pool, card_ids[1]).await.unwrap();
+        let now = Utc::now();
+
+        assert_eq!(claim_card_by_code(&pool, "used", "wallet", now).await.unwrap(), None);
+        assert_eq!(claim_card_by_code(&pool, "expired", "wallet", now + chrono::Duration::days(2)).await.unwrap(), None);
+        assert_eq!(claim_card_by_code(&pool, "fresh", "wallet", now).await.unwrap(), Some(card_ids[0]));
+        // The code is used up, also for registration
+        assert_eq!(claim_card_by_code(&pool, "fresh", "other", now).await.unwrap(), None);
+        assert!(queries::get_card_by_one_time_code(&pool, "fresh", now).await.unwrap().is_none());
+        assert_eq!(get_card_auth_key(&pool, card_ids[0]).await.unwrap().as_deref(), Some("wallet"));
+    }
+
+    #[tokio::test]
+    async fn test_deleted_card_isnt_bound() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let key = "00000000000000000000000000000000";
+        let card_id = queries::insert_card(&pool, "", key, key, key, key, key, "card", 1000, 10000, true, false, "code", None, None, chrono::Utc::now())
+            .await
+            .unwrap();
+        claim_card_by_code(&pool, "code", "wallet", Utc::now()).await.unwrap();
+        assert!(get_bound_card(&pool, "wallet", card_id).await.unwrap().is_some());
+        assert!(get_bound_card(&pool, "other", card_id).await.unwrap().is_none());
+
+        sqlx::query("UPDATE cards SET deleted_at = CURRENT_TIMESTAMP WHERE card_id = ?")
+            .bind(card_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(get_bound_card(&pool, "wallet", card_id).await.unwrap().is_none());
+    }
+}
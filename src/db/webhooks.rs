@@ -0,0 +1,74 @@
+use sqlx::{Pool, Sqlite, SqliteConnection};
+use anyhow::Result;
+use crate::db::{models::WebhookEvent, query_event};
+
+/// Store an operator notification before its delivery is queued
+pub async fn record_event(pool: &Pool<Sqlite>, event: &str, payload: &str) -> Result<i64> {
+    let result = sqlx::query("INSERT INTO webhook_events (event, payload) VALUES (?, ?)")
+        .bind(event)
+        .bind(payload)
+        .execute(pool)
+        .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn get_event(conn: &mut SqliteConnection, event_id: i64) -> Result<Option<WebhookEvent>> {
+    let event = query_event!("WHERE event_id = ?", event_id)
+        .fetch_optional(conn)
+        .await?;
+
+    Ok(event)
+}
+
+/// Events, newest first
+pub async fn list_events(pool: &Pool<Sqlite>, status: Option<&str>, limit: i64) -> Result<Vec<WebhookEvent>> {
+    let events = query_event!(
+        "WHERE ? IS NULL OR status = ? ORDER BY event_id DESC LIMIT ?",
+        status,
+        status,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(events)
+}
+
+/// Record a delivery attempt, `error` being `None` if the webhook accepted the event
+///
+/// A failed event stays pending while it's retried, `dead` marks it as given up on.
+pub async fn record_attempt(pool: &Pool<Sqlite>, event_id: i64, error: Option<&str>, dead: bool) -> Result<()> {
+    sqlx::query(
+        "UPDATE webhook_events
+         SET attempts = attempts + 1,
+             status = CASE WHEN ? IS NULL THEN 'delivered' WHEN ? THEN 'dead' ELSE 'pending' END,
+             last_error = ?,
+             delivered_at = CASE WHEN ? IS NULL THEN CURRENT_TIMESTAMP ELSE delivered_at END
+         WHERE event_id = ?"
+    )
+    .bind(error)
+    .bind(dead)
+    .bind(error)
+    .bind(error)
+    .bind(event_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Mark a delivered or dead event pending again with a fresh count of attempts
+///
+/// Returns false if the event is already pending.
+pub async fn reset(conn: &mut SqliteConnection, event_id: i64) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE webhook_events SET status = 'pending', attempts = 0, last_error = NULL
+         WHERE event_id = ? AND status != 'pending'"
+    )
+    .bind(event_id)
+    .execute(conn)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
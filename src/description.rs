@@ -0,0 +1,123 @@
+//! Templates for the `defaultDescription` offered to wallets
+//!
+//! A template is text with `{variable}` placeholders, filled in when a card is
+//! tapped. Cards can have a template of their own, the others use
+//! `--withdraw-description`. The rendered description is stored with the
+//! withdrawal session, so the callback checks invoices against what the
+//! wallet was actually offered.
+
+use chrono::NaiveDate;
+
+/// Placeholders a template may use
+pub const VARIABLES: &[&str] = &["card_name", "card_id", "remaining_sats", "merchant", "date"];
+
+const MAX_LENGTH: usize = 200;
+
+/// What the placeholders are filled in with
+#[derive(Debug, Clone)]
+pub struct Context<'a> {
+    pub card_id: i64,
+    pub card_name: &'a str,
+    /// The most the wallet is offered, i.e. what's left of the limits and balance
+    pub remaining_sats: i64,
+    /// The card's `merchant` metadata, or the organization whose domain was tapped on
+    pub merchant: &'a str,
+    pub date: NaiveDate,
+}
+
+impl Context<'_> {
+    fn value(&self, variable: &str) -> Option<String> {
+        match variable {
+            "card_name" => Some(self.card_name.to_string()),
+            "card_id" => Some(self.card_id.to_string()),
+            "remaining_sats" => Some(self.remaining_sats.to_string()),
+            "merchant" => Some(self.merchant.to_string()),
+            "date" => Some(self.date.format("%Y-%m-%d").to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Check that a template fits into an invoice and only uses known placeholders
+pub fn validate(template: &str) -> Result<(), String> {
+    if template.trim().is_empty() {
+        return Err("template is empty".to_string());
+    }
+    if template.chars().count() > MAX_LENGTH {
+        return Err(format!("template is longer than {} characters", MAX_LENGTH));
+    }
+
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let after = &rest[start + 1..];
+        let end = after.find('}').ok_or("unclosed {")?;
+        let variable = &after[..end];
+        if !VARIABLES.contains(&variable) {
+            return Err(format!("unknown variable {{{}}}, expected one of {}", variable, VARIABLES.join(", ")));
+        }
+        rest = &after[end + 1..];
+    }
+
+    Ok(())
+}
+
+/// Parser for `--withdraw-description`
+pub fn parse_template(template: &str) -> Result<String, String> {
+    validate(template).map(|()| template.to_string())
+}
+
+/// Fill in the placeholders; values aren't searched for placeholders again
+pub fn render(template: &str, context: &Context) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('}').and_then(|end| Some((end, context.value(&after[..end])?))) {
+            Some((end, value)) => {
+                out.push_str(&value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> Context<'static> {
+        Context {
+            card_id: 7,
+            card_name: "Alice {date}",
+            remaining_sats: 2100,
+            merchant: "Bar Central",
+            date: NaiveDate::from_ymd_opt(2025, 3, 1).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(render("Withdrawal from {card_name}", &context()), "Withdrawal from Alice {date}");
+        assert_eq!(
+            render("{merchant} {date}: up to {remaining_sats} sats (#{card_id})", &context()),
+            "Bar Central 2025-03-01: up to 2100 sats (#7)"
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate("Withdrawal from {card_name} on {date}").is_ok());
+        assert!(validate("{balance}").unwrap_err().contains("unknown variable {balance}"));
+        assert!(validate("Withdrawal {card_name").is_err());
+        assert!(validate(" ").is_err());
+        assert!(validate(&"x".repeat(MAX_LENGTH + 1)).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+//! Checks for triaging a card that stopped working
+//!
+//! Looks at what the server knows about a card: its recent taps, how its
+//! counter moved between them and whether its stored keys can work at all.
+//! Nothing here talks to the card, so a card that never reaches the server
+//! only shows up as taps that stopped.
+
+use serde::Serialize;
+
+use crate::{
+    crypto::{AesKey, CardUid, Counter},
+    db::models::{Card, CardTap},
+};
+
+/// How far the counter moved between consecutive successful taps
+///
+/// Gaps above 1 are reads the server never saw, e.g. phones reading the card
+/// outside a withdrawal, or taps on another server sharing its keys.
+#[derive(Debug, Default, Serialize)]
+pub struct CounterGaps {
+    /// Successful taps the gaps are taken from
+    pub taps: usize,
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+    pub mean: Option<f64>,
+    /// Counter values skipped in total
+    pub skipped: i64,
+}
+
+/// Whether a stored key or the UID has a usable format
+#[derive(Debug, Serialize)]
+pub struct KeyCheck {
+    pub key: &'static str,
+    pub ok: bool,
+    pub problem: Option<String>,
+}
+
+/// Gaps between the successful ones of `taps`, newest first as stored
+pub fn counter_gaps(taps: &[CardTap]) -> CounterGaps {
+    let counters: Vec<i64> = taps.iter().rev().filter(|tap| tap.success).filter_map(|tap| tap.counter).collect();
+    let gaps: Vec<i64> = counters.windows(2).map(|pair| pair[1] - pair[0]).collect();
+
+    CounterGaps {
+        taps: counters.len(),
+        min: gaps.iter().copied().min(),
+        max: gaps.iter().copied().max(),
+        mean: (!gaps.is_empty()).then(|| gaps.iter().sum::<i64>() as f64 / gaps.len() as f64),
+        skipped: gaps.iter().map(|gap| (gap - 1).max(0)).sum(),
+    }
+}
+
+/// Check the UID and the five keys for a format the card and server can use
+pub fn check_keys(card: &Card) -> Vec<KeyCheck> {
+    let uid = match card.uid.as_str() {
+        // Learned from the first tap
+        "" => None,
+        uid => CardUid::from_hex(uid).err().map(|_| "not 7 bytes of hex".to_string()),
+    };
+    let mut checks = vec![KeyCheck { key: "uid", ok: uid.is_none(), problem: uid }];
+
+    let keys = [
+        ("k0", &card.k0_auth_key),
+        ("k1", &card.k1_decrypt_key),
+        ("k2", &card.k2_cmac_key),
+        ("k3", &card.k3),
+        ("k4", &card.k4),
+    ];
+    for (i, (name, key)) in keys.iter().enumerate() {
+        let problem = match AesKey::from_hex(key) {
+            Err(_) => Some("not 16 bytes of hex".to_string()),
+            Ok(parsed) if parsed.as_bytes() == &[0; 16] => Some("factory default key".to_string()),
+            Ok(_) => keys[..i]
+                .iter()
+                .find(|(_, other)| other.eq_ignore_ascii_case(key))
+                .map(|(other, _)| format!("same as {}", other)),
+        };
+        checks.push(KeyCheck { key: name, ok: problem.is_none(), problem });
+    }
+
+    checks
+}
+
+/// Taps the card has left before its counter runs out
+pub fn counter_remaining(card: &Card) -> i64 {
+    (Counter::MAX as i64 - card.last_counter).max(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tap(counter: i64, success: bool) -> CardTap {
+        CardTap {
+            tap_id: counter,
+            card_id: 1,
+            uid: None,
+            counter: Some(counter),
+            success,
+            reason: None,
+            created_at: None,
+        }
+    }
+
+    #[test]
+    fn test_counter_gaps() {
+        let taps = [tap(9, true), tap(8, false), tap(5, true), tap(4, true)];
+        let gaps = counter_gaps(&taps);
+        assert_eq!((gaps.taps, gaps.min, gaps.max, gaps.skipped), (3, Some(1), Some(4), 3));
+        assert_eq!(gaps.mean, Some(2.5));
+
+        assert_eq!(counter_gaps(&[tap(1, true)]).mean, None);
+    }
+
+    #[test]
+    fn test_check_keys() {
+        let card = Card {
+            card_id: 1,
+            uid: "04a1b2c3d4e5".to_string(),
+            k0_auth_key: "00".repeat(16),
+            k1_decrypt_key: "0123456789abcdef0123456789abcdef".to_string(),
+            k2_cmac_key: "0123456789ABCDEF0123456789ABCDEF".to_string(),
+            k3: "fedcba9876543210fedcba9876543210".to_string(),
+            k4: "xyz".to_string(),
+            last_counter: 0,
+            enabled: true,
+            tx_limit_sats: 0,
+            day_limit_sats: 0,
+            card_name: "Alice".to_string(),
+            one_time_code: None,
+            one_time_code_expiry: None,
+            one_time_code_used: None,
+            created_at: None,
+            balance_mode: false,
+            balance_msats: 0,
+        };
+        let problems: Vec<_> = check_keys(&card).into_iter().map(|check| (check.key, check.problem)).collect();
+        assert_eq!(
+            problems,
+            [
+                ("uid", Some("not 7 bytes of hex".to_string())),
+                ("k0", Some("factory default key".to_string())),
+                ("k1", None),
+                ("k2", Some("same as k1".to_string())),
+                ("k3", None),
+                ("k4", Some("not 16 bytes of hex".to_string())),
+            ]
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Card and payment activity as Atom or RSS, for feed readers and automation
+//!
+//! A feed merges settled and failed withdrawals, operator notifications about
+//! cards and audit log entries naming cards, newest first.
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Deserialize;
+use std::fmt::Write;
+
+use crate::{
+    db::{
+        feed::FeedPayment,
+        models::{AuditEntry, WebhookEvent},
+    },
+    handlers::html::escape,
+    notifications::Notification,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedFormat {
+    Atom,
+    Rss,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedEntry {
+    /// Unique within the server, e.g. `payment-12`
+    pub key: String,
+    pub title: String,
+    pub summary: String,
+    pub time: DateTime<Utc>,
+}
+
+/// SQLite's CURRENT_TIMESTAMP format, always UTC
+fn parse_time(time: Option<&str>) -> DateTime<Utc> {
+    time.and_then(|time| NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").ok())
+        .map(|time| time.and_utc())
+        .unwrap_or_default()
+}
+
+fn payment_entry(payment: &FeedPayment) -> FeedEntry {
+    let sats = payment.amount_msats.unwrap_or(0) / 1000;
+    let (title, summary) = if payment.status == "settled" {
+        (
+            format!("Card {} (#{}) withdrew {} sats", payment.card_name, payment.card_id, sats),
+            format!("Withdrawal {} settled", payment.payment_id),
+        )
+    } else {
+        (
+            format!("Withdrawal of {} sats with card {} (#{}) failed", sats, payment.card_name, payment.card_id),
+            payment.failure_reason.clone().unwrap_or_else(|| format!("Withdrawal {} failed", payment.payment_id)),
+        )
+    };
+    FeedEntry {
+        key: format!("payment-{}", payment.payment_id),
+        title,
+        summary,
+        time: parse_time(Some(&payment.time)),
+    }
+}
+
+fn notification_entry(event: &WebhookEvent) -> FeedEntry {
+    let title = match serde_json::from_str::<Notification>(&event.payload) {
+        Ok(notification) => notification.event.describe(),
+        Err(_) => event.event.clone(),
+    };
+    FeedEntry {
+        key: format!("event-{}", event.event_id),
+        title,
+        summary: event.payload.clone(),
+        time: parse_time(event.created_at.as_deref()),
+    }
+}
+
+fn audit_entry(entry: &AuditEntry) -> FeedEntry {
+    FeedEntry {
+        key: format!("audit-{}", entry.audit_id),
+        title: format!("{} by {}", entry.action, entry.actor),
+        summary: entry.details.clone(),
+        time: parse_time(entry.created_at.as_deref()),
+    }
+}
+
+/// The newest `limit` entries of all sources
+pub fn entries(
+    payments: &[FeedPayment],
+    notifications: &[WebhookEvent],
+    audit_log: &[AuditEntry],
+    limit: usize,
+) -> Vec<FeedEntry> {
+    let mut entries: Vec<FeedEntry> = payments
+        .iter()
+        .map(payment_entry)
+        .chain(notifications.iter().map(notification_entry))
+        .chain(audit_log.iter().map(audit_entry))
+        .collect();
+    entries.sort_by(|a, b| b.time.cmp(&a.time).then_with(|| a.key.cmp(&b.key)));
+    entries.truncate(limit);
+    entries
+}
+
+/// An Atom feed served at `url`, whose entry IDs are fragments of it
+pub fn atom(title: &str, url: &str, entries: &[FeedEntry]) -> String {
+    let updated = entries.first().map(|entry| entry.time).unwrap_or_default();
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    let _ = writeln!(out, "<title>{}</title>", escape(title));
+    let _ = writeln!(out, "<id>{}</id>", escape(url));
+    let _ = writeln!(out, "<link rel=\"self\" href=\"{}\"/>", escape(url));
+    let _ = writeln!(out, "<updated>{}</updated>", updated.to_rfc3339());
+    let _ = writeln!(out, "<author><name>lnurlw-server</name></author>");
+    for entry in entries {
+        let _ = writeln!(
+            out,
+            "<entry><id>{}#{}</id><title>{}</title><updated>{}</updated><summary>{}</summary></entry>",
+            escape(url),
+            entry.key,
+            escape(&entry.title),
+            entry.time.to_rfc3339(),
+            escape(&entry.summary),
+        );
+    }
+    out.push_str("</feed>\n");
+    out
+}
+
+/// The same as an RSS 2.0 channel
+pub fn rss(title: &str, url: &str, entries: &[FeedEntry]) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<rss version=\"2.0\">\n<channel>\n");
+    let _ = writeln!(out, "<title>{}</title>", escape(title));
+    let _ = writeln!(out, "<link>{}</link>", escape(url));
+    let _ = writeln!(out, "<description>{}</description>", escape(title));
+    for entry in entries {
+        let _ = writeln!(
+            out,
+            "<item><guid isPermaLink=\"false\">{}#{}</guid><title>{}</title><pubDate>{}</pubDate><description>{}</description></item>",
+            escape(url),
+            entry.key,
+            escape(&entry.title),
+            entry.time.to_rfc2822(),
+            escape(&entry.summary),
+        );
+    }
+    out.push_str("</channel>\n</rss>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payment(payment_id: i64, status: &str, time: &str) -> FeedPayment {
+        FeedPayment {
+            payment_id,
+            card_id: 3,
+            card_name: "Bar <1>".to_string(),
+            amount_msats: Some(21_000),
+            status: status.to_string(),
+            failure_reason: (status == "failed").then(|| "no route".to_string()),
+            time: time.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_entries() {
+        let audit = AuditEntry {
+            audit_id: 5,
+            actor: "dashboard:admin".to_string(),
+            action: "bulk_update".to_string(),
+            details: r#"{"card_ids":[3],"action":"disable"}"#.to_string(),
+            created_at: Some("2026-03-04 12:30:00".to_string()),
+        };
+        let entries = entries(
+            &[payment(1, "settled", "2026-03-04 12:00:00"), payment(2, "failed", "2026-03-04 13:00:00")],
+            &[],
+            &[audit],
+            2,
+        );
+
+        let keys: Vec<&str> = entries.iter().map(|entry| entry.key.as_str()).collect();
+        assert_eq!(keys, ["payment-2", "audit-5"]);
+        assert_eq!(entries[0].title, "Withdrawal of 21 sats with card Bar <1> (#3) failed");
+        assert_eq!(entries[0].summary, "no route");
+        assert_eq!(entries[1].title, "bulk_update by dashboard:admin");
+    }
+
+    #[test]
+    fn test_atom() {
+        let entries = entries(&[payment(1, "settled", "2026-03-04 12:00:00")], &[], &[], 10);
+        let feed = atom("Card activity", "https://cards.example.com/api/feed", &entries);
+
+        assert!(feed.contains("<updated>2026-03-04T12:00:00+00:00</updated>"));
+        assert!(feed.contains("<id>https://cards.example.com/api/feed#payment-1</id>"));
+        assert!(feed.contains("<title>Card Bar &lt;1&gt; (#3) withdrew 21 sats</title>"));
+
+        let feed = rss("Card activity", "https://cards.example.com/api/feed", &entries);
+        assert!(feed.contains("<pubDate>Wed, 4 Mar 2026 12:00:00 +0000</pubDate>"));
+    }
+}
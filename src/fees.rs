@@ -0,0 +1,436 @@
+//! Service fees on withdrawals and their payout to the operator
+//!
+//! With `--service-fee-msats` and/or `--service-fee-percent` every withdrawal
+//! is charged a fee on top of its amount. Prepaid cards pay it from their
+//! balance, for other cards it's only booked. Fees are booked per node when
+//! the withdrawal settles and, with `--fee-payout-destination`, paid out by
+//! that node to a Lightning address or BOLT12 offer once enough accrued.
+
+use anyhow::{Result, anyhow, bail};
+use serde::Deserialize;
+use sqlx::{Pool, Sqlite};
+use std::{str::FromStr, time::Duration};
+
+use crate::{
+    app_state::AppState,
+    backends::LightningBackends,
+    config::Config,
+    db::fees::{self, FeePayout},
+    lightning::{Invoice, OutgoingPayment},
+};
+
+/// Payouts pending longer than this were interrupted
+const STALE_AFTER_SECS: i64 = 600;
+const LNURL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Fee charged on each withdrawal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ServiceFee {
+    pub flat_msats: i64,
+    pub percent: f64,
+}
+
+impl ServiceFee {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            flat_msats: config.service_fee_msats as i64,
+            percent: config.service_fee_percent,
+        }
+    }
+
+    /// The fee of a withdrawal of `amount_msats`, rounded down to the msat
+    pub fn for_amount(&self, amount_msats: i64) -> i64 {
+        self.flat_msats + (amount_msats as f64 * self.percent / 100.0).floor() as i64
+    }
+
+    /// The largest withdrawal whose amount and fee fit in `balance_msats`
+    pub fn affordable(&self, balance_msats: i64) -> i64 {
+        let available = balance_msats - self.flat_msats;
+        if available <= 0 {
+            return 0;
+        }
+        let mut amount = (available as f64 / (1.0 + self.percent / 100.0)).floor() as i64;
+        // The percentage is rounded down, so the estimate can be off by a msat or so either way
+        while amount > 0 && amount + self.for_amount(amount) > balance_msats {
+            amount -= 1;
+        }
+        while (amount + 1) + self.for_amount(amount + 1) <= balance_msats {
+            amount += 1;
+        }
+        amount
+    }
+}
+
+/// Where accrued fees are paid to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Destination {
+    LightningAddress { user: String, domain: String },
+    Offer(String),
+}
+
+impl FromStr for Destination {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.to_ascii_lowercase().starts_with("lno1") {
+            return Ok(Destination::Offer(s.to_ascii_lowercase()));
+        }
+        match s.split_once('@') {
+            Some((user, domain))
+                if !user.is_empty()
+                    && user.chars().all(|c| c.is_ascii_alphanumeric() || "-_.+".contains(c))
+                    && !domain.is_empty()
+                    && crate::config::parse_domain(domain).is_ok() =>
+            {
+                Ok(Destination::LightningAddress { user: user.to_ascii_lowercase(), domain: domain.to_ascii_lowercase() })
+            }
+            _ => Err("expected a Lightning address (name@domain) or a BOLT12 offer (lno1...)".to_string()),
+        }
+    }
+}
+
+/// Validates `--fee-payout-destination`
+pub fn parse_destination(destination: &str) -> Result<String, String> {
+    Destination::from_str(destination).map(|_| destination.trim().to_string())
+}
+
+/// Validates `--service-fee-percent`, the same range a card's own fee may have
+pub fn parse_percent(percent: &str) -> Result<f64, String> {
+    match percent.trim().parse::<f64>() {
+        Ok(percent) if (0.0..100.0).contains(&percent) => Ok(percent),
+        _ => Err("expected a percentage from 0 up to (not including) 100".to_string()),
+    }
+}
+
+/// Start the payout loop, if a destination is configured and the interval isn't zero
+pub fn start(state: AppState) {
+    if state.config.fee_payout_destination.is_none() || state.config.fee_payout_interval_secs == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(state.config.fee_payout_interval_secs));
+        loop {
+            interval.tick().await;
+            if let Err(e) = run(&state.pool, &state.lightning, &state.http, &state.config).await {
+                tracing::error!("Fee payout failed: {}", e);
+            }
+        }
+    });
+}
+
+/// Pay out the fees accrued on each node, returning the payouts started
+pub async fn run(
+    pool: &Pool<Sqlite>,
+    lightning: &LightningBackends,
+    http: &reqwest::Client,
+    config: &Config,
+) -> Result<Vec<FeePayout>> {
+    let destination = config
+        .fee_payout_destination
+        .as_deref()
+        .ok_or_else(|| anyhow!("No --fee-payout-destination configured"))?;
+
+    for payout in fees::stale_payouts(pool, STALE_AFTER_SECS).await? {
+        if let Err(e) = recover(pool, lightning, &payout).await {
+            tracing::error!("Failed to check interrupted fee payout {}: {}", payout.payout_id, e);
+        }
+    }
+
+    let min_msats = config.fee_payout_min_sats as i64 * 1000;
+    let mut payouts = Vec::new();
+    for accrued in fees::accrued(pool).await? {
+        let Some(payout) = fees::claim_payout(pool, accrued.org_id, destination, min_msats).await? else {
+            continue;
+        };
+        let outcome = pay(pool, lightning, http, &payout).await;
+        if let Err(e) = &outcome {
+            tracing::error!("Fee payout {} of {} msats failed: {}", payout.payout_id, payout.amount_msats, e);
+        }
+        payouts.push(payout);
+    }
+
+    Ok(payouts)
+}
+
+/// Pay a claimed payout; its fees accrue again unless it was paid or may have been
+async fn pay(pool: &Pool<Sqlite>, lightning: &LightningBackends, http: &reqwest::Client, payout: &FeePayout) -> Result<()> {
+    let lightning = lightning.for_org(pool, payout.org_id).await?;
+    let amount_msats = payout.amount_msats as u64;
+
+    let destination = Destination::from_str(&payout.destination).map_err(|e| anyhow!(e));
+    let result = match destination {
+        Ok(Destination::LightningAddress { user, domain }) => {
+            let invoice = match lnurl_pay_invoice(http, &user, &domain, amount_msats).await {
+                Ok(invoice) => invoice,
+                Err(e) => {
+                    fees::release_payout(pool, payout.payout_id, &e.to_string()).await?;
+                    return Err(e);
+                }
+            };
+            fees::set_payout_hash(pool, payout.payout_id, &invoice.payment_hash()).await?;
+            lightning.pay_invoice(&invoice, amount_msats).await
+        }
+        Ok(Destination::Offer(offer)) => lightning.pay_offer(&offer, amount_msats).await,
+        Err(e) => {
+            fees::release_payout(pool, payout.payout_id, &e.to_string()).await?;
+            return Err(e);
+        }
+    };
+
+    match result {
+        Ok(result) if result.success => {
+            fees::mark_payout_paid(pool, payout.payout_id, result.fee_msats.map(|fee| fee as i64)).await?;
+            tracing::info!("Paid out {} msats of service fees to {}", payout.amount_msats, payout.destination);
+            Ok(())
+        }
+        Ok(result) => {
+            let error = result.error.unwrap_or_else(|| "Payment failed".to_string());
+            fees::release_payout(pool, payout.payout_id, &error).await?;
+            bail!(error)
+        }
+        // The payment may or may not have gone out, so the payout stays pending until it's looked up
+        Err(e) => Err(e),
+    }
+}
+
+/// Decide a payout left pending, e.g. by a restart, by asking the node about its invoice
+///
+/// Offers have no invoice to look up, those have to be checked on the node
+/// and released with `fees release` if they weren't paid.
+async fn recover(pool: &Pool<Sqlite>, lightning: &LightningBackends, payout: &FeePayout) -> Result<()> {
+    let Some(payment_hash) = payout.payment_hash.as_deref() else {
+        tracing::warn!(
+            "Fee payout {} to {} is pending without an invoice to check, release it with `fees release` if it wasn't paid",
+            payout.payout_id,
+            payout.destination
+        );
+        return Ok(());
+    };
+
+    let lightning = lightning.for_org(pool, payout.org_id).await?;
+    match lightning.outgoing_payment(payment_hash).await? {
+        OutgoingPayment::Succeeded { .. } => fees::mark_payout_paid(pool, payout.payout_id, None).await,
+        OutgoingPayment::Failed | OutgoingPayment::Unknown => {
+            fees::release_payout(pool, payout.payout_id, "Payment didn't complete on the node").await?;
+            Ok(())
+        }
+        OutgoingPayment::Pending => Ok(()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PayRequest {
+    tag: String,
+    callback: String,
+    min_sendable: u64,
+    max_sendable: u64,
+    metadata: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PayResponse {
+    pr: Option<String>,
+    reason: Option<String>,
+}
+
+/// An invoice for `amount_msats` from a Lightning address, following LUD-06 and LUD-16
+async fn lnurl_pay_invoice(http: &reqwest::Client, user: &str, domain: &str, amount_msats: u64) -> Result<Invoice> {
+    let url = format!("https://{}/.well-known/lnurlp/{}", domain, user);
+    let request: PayRequest = http
+        .get(&url)
+        .timeout(LNURL_TIMEOUT)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| anyhow!("{}@{} unreachable: {}", user, domain, e.without_url()))?
+        .json()
+        .await
+        .map_err(|e| anyhow!("{}@{} isn't a Lightning address: {}", user, domain, e))?;
+    check_pay_request(&request, amount_msats)?;
+
+    let mut callback = reqwest::Url::parse(&request.callback)?;
+    if callback.scheme() != "https" {
+        bail!("Callback of {}@{} isn't https", user, domain);
+    }
+    callback.query_pairs_mut().append_pair("amount", &amount_msats.to_string());
+    let response: PayResponse = http
+        .get(callback)
+        .timeout(LNURL_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| anyhow!("Callback of {}@{} failed: {}", user, domain, e.without_url()))?
+        .json()
+        .await?;
+
+    check_invoice(response, &request.metadata, amount_msats)
+}
+
+fn check_pay_request(request: &PayRequest, amount_msats: u64) -> Result<()> {
+    if request.tag != "payRequest" {
+        bail!("Expected a payRequest, got {:?}", request.tag);
+    }
+    if !(request.min_sendable..=request.max_sendable).contains(&amount_msats) {
+        bail!(
+            "{} msats is outside the {} to {} msats the address accepts",
+            amount_msats,
+            request.min_sendable,
+            request.max_sendable
+        );
+    }
+    Ok(())
+}
+
+/// The invoice must be for the amount asked for and commit to the address's metadata
+fn check_invoice(response: PayResponse, metadata: &str, amount_msats: u64) -> Result<Invoice> {
+    let pr = match response {
+        PayResponse { pr: Some(pr), .. } => pr,
+        PayResponse { reason, .. } => bail!("No invoice: {}", reason.unwrap_or_default()),
+    };
+    let invoice = Invoice::from_str(&pr)?;
+    if invoice.amount_msats()? != amount_msats {
+        bail!("Invoice is for {} msats instead of {}", invoice.amount_msats()?, amount_msats);
+    }
+    if !invoice.description_matches(metadata) {
+        bail!("Invoice doesn't commit to the address's metadata");
+    }
+    Ok(invoice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app_state::test_state, db::queries, lightning::Fault};
+
+    #[test]
+    fn test_service_fee() {
+        let fee = ServiceFee { flat_msats: 1_000, percent: 0.5 };
+        assert_eq!(fee.for_amount(100_000), 1_500);
+        assert_eq!(fee.for_amount(999), 1_004);
+
+        for balance in [0, 1_000, 1_001, 100_000, 123_456_789] {
+            let amount = fee.affordable(balance);
+            assert!(amount + fee.for_amount(amount) <= balance || amount == 0, "{}", balance);
+            assert!((amount + 1) + fee.for_amount(amount + 1) > balance, "{}", balance);
+        }
+        assert_eq!(ServiceFee { flat_msats: 0, percent: 0.0 }.affordable(5_000), 5_000);
+    }
+
+    #[test]
+    fn test_destination() {
+        assert_eq!(
+            "Fees@Example.com".parse(),
+            Ok(Destination::LightningAddress { user: "fees".to_string(), domain: "example.com".to_string() })
+        );
+        assert!(matches!("lno1qcp4256ypq".parse(), Ok(Destination::Offer(_))));
+        assert!("fees@".parse::<Destination>().is_err());
+        assert!("https://example.com".parse::<Destination>().is_err());
+        assert!("fe es@example.com".parse::<Destination>().is_err());
+    }
+
+    #[test]
+    fn test_pay_request() {
+        let request: PayRequest = serde_json::from_str(
+            r#"{"tag":"payRequest","callback":"https://example.com/cb","minSendable":1000,"maxSendable":100000000,"metadata":"[[\"text/plain\",\"fees\"]]"}"#,
+        )
+        .unwrap();
+        assert!(check_pay_request(&request, 50_000).is_ok());
+        assert!(check_pay_request(&request, 500).is_err());
+
+        let error = check_invoice(PayResponse { pr: None, reason: Some("disabled".to_string()) }, &request.metadata, 50_000);
+        assert!(error.unwrap_err().to_string().contains("disabled"));
+    }
+
+    #[test]
+    fn test_parse_percent() {
+        assert_eq!(parse_percent("0.5"), Ok(0.5));
+        assert_eq!(parse_percent("0"), Ok(0.0));
+        assert!(parse_percent("100").is_err());
+        assert!(parse_percent("-1").is_err());
+        assert!(parse_percent("NaN").is_err());
+        assert!(parse_percent("inf").is_err());
+    }
+
+    /// Book `fee_msats` of service fees on the server's node, as a settled withdrawal would
+    async fn accrue(state: &AppState, fee_msats: i64) {
+        let key = "00000000000000000000000000000000";
+        let code = hex::encode(rand::random::<[u8; 8]>());
+        let card_id = queries::insert_card(&state.pool, "", key, key, key, key, key, "Card", 1000, 10000, true, false, &code, None, None, state.clock.now())
+            .await
+            .unwrap();
+        let payment_id: i64 = sqlx::query_scalar(
+            "INSERT INTO card_payments (card_id, k1, amount_msats, status, service_fee_msats)
+             VALUES (?, ?, 1000000, 'settled', ?) RETURNING payment_id"
+        )
+        .bind(card_id)
+        .bind(&code)
+        .bind(fee_msats)
+        .fetch_one(&state.pool)
+        .await
+        .unwrap();
+        fees::book(&mut state.pool.acquire().await.unwrap(), payment_id).await.unwrap();
+    }
+
+    async fn run_payouts(state: &AppState) -> Vec<FeePayout> {
+        let payouts = run(&state.pool, &state.lightning, &state.http, &state.config).await.unwrap();
+        let mut current = Vec::new();
+        for payout in payouts {
+            current.push(fees::get_payout(&state.pool, payout.payout_id).await.unwrap().unwrap());
+        }
+        current
+    }
+
+    const PAYOUT_ARGS: [&str; 4] = ["--fee-payout-destination", "lno1qcp4256ypq", "--fee-payout-min-sats", "5"];
+
+    #[tokio::test]
+    async fn test_payout_waits_for_minimum() {
+        let (state, mock) = test_state(&PAYOUT_ARGS).await;
+
+        accrue(&state, 3_000).await;
+        assert!(run_payouts(&state).await.is_empty());
+
+        accrue(&state, 3_000).await;
+        let payouts = run_payouts(&state).await;
+        assert_eq!(payouts.len(), 1);
+        assert_eq!((payouts[0].amount_msats, payouts[0].status.as_str()), (6_000, "paid"));
+        let calls = mock.calls();
+        let call = calls.iter().find(|call| call.method == "pay_offer").unwrap();
+        assert_eq!(call.amount_msats, Some(6_000));
+
+        // Paid fees aren't paid again
+        assert!(run_payouts(&state).await.is_empty());
+        assert!(fees::accrued(&state.pool).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_payout_accrues_again() {
+        let (state, mock) = test_state(&PAYOUT_ARGS).await;
+        mock.script([Fault::Fail("no route".to_string())]);
+        accrue(&state, 6_000).await;
+
+        let payouts = run_payouts(&state).await;
+        assert_eq!(payouts[0].status, "failed");
+        assert_eq!(payouts[0].error.as_deref(), Some("no route"));
+        assert_eq!(fees::accrued(&state.pool).await.unwrap()[0].amount_msats, 6_000);
+
+        let payouts = run_payouts(&state).await;
+        assert_eq!((payouts[0].amount_msats, payouts[0].status.as_str()), (6_000, "paid"));
+    }
+
+    #[tokio::test]
+    async fn test_errored_payout_stays_pending() {
+        let (state, mock) = test_state(&PAYOUT_ARGS).await;
+        mock.script([Fault::Error("connection reset".to_string())]);
+        accrue(&state, 6_000).await;
+
+        let payouts = run_payouts(&state).await;
+        assert_eq!(payouts[0].status, "pending");
+
+        // It may have gone out, so nothing is paid again until it's decided
+        accrue(&state, 6_000).await;
+        assert!(run_payouts(&state).await.is_empty());
+        assert_eq!(mock.calls().iter().filter(|call| call.method == "pay_offer").count(), 1);
+    }
+}
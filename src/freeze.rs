@@ -0,0 +1,31 @@
+//! Lifting card freezes once their time is up
+//!
+//! Taps check the end of a freeze themselves, so a freeze that ran out stops
+//! refusing taps right away. The loop clears it from the card afterwards, so
+//! listings and the audit log show the card as unfrozen.
+
+use std::time::Duration;
+
+use crate::{app_state::AppState, db::freeze};
+
+/// Start the unfreeze loop, unless disabled by a zero interval
+pub fn start(state: AppState) {
+    if state.config.unfreeze_interval_secs == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(state.config.unfreeze_interval_secs));
+        loop {
+            interval.tick().await;
+            match freeze::expire_freezes(&state.pool, state.clock.now()).await {
+                Ok(card_ids) => {
+                    for card_id in card_ids {
+                        tracing::info!("Freeze of card {} ran out, card unfrozen", card_id);
+                    }
+                }
+                Err(e) => tracing::error!("Failed to unfreeze cards: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,550 @@
+//! Read-only GraphQL API for admin frontends
+//!
+//! Cards, payments, taps and stats with the filters and pagination a
+//! dashboard needs, so frontends can ask for what they show in one request
+//! instead of combining REST endpoints.
+
+use async_graphql::{
+    ComplexObject, Context, EmptyMutation, EmptySubscription, Enum, InputObject, Json, Object, Schema, SimpleObject,
+};
+use sqlx::{Pool, Sqlite};
+
+use crate::db::{
+    listing::{self, CardSummary},
+    metadata::{self, Metadata},
+    models::{CardPayment, CardTap, PaymentStatus},
+    stats::{self, CardVolume, DailyStats, StatsReport},
+    tags,
+};
+
+pub type ApiSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+const MAX_PAGE_SIZE: i64 = 500;
+const MAX_DAYS: i64 = 366;
+
+pub fn schema(pool: Pool<Sqlite>) -> ApiSchema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .data(pool)
+        // Every nested list is another query, keep clients from asking for too many
+        .limit_depth(8)
+        .limit_complexity(2000)
+        .finish()
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    async fn card(&self, ctx: &Context<'_>, card_id: i64) -> async_graphql::Result<Option<Card>> {
+        find_card(ctx, card_id).await
+    }
+
+    /// Cards in order of their IDs; erased cards only if the filter asks for them
+    async fn cards(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<CardFilter>,
+        #[graphql(default = 50)] first: i64,
+        #[graphql(default)] offset: i64,
+    ) -> async_graphql::Result<CardPage> {
+        let filter = filter.map(Into::into).unwrap_or_default();
+        let (limit, offset) = page_bounds(first, offset);
+        let page = listing::cards(pool(ctx), &filter, limit, offset).await.map_err(internal)?;
+
+        Ok(CardPage {
+            total_count: page.total,
+            nodes: page.items.into_iter().map(Card::from).collect(),
+        })
+    }
+
+    /// Withdrawal sessions, newest first
+    async fn payments(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<PaymentFilter>,
+        #[graphql(default = 50)] first: i64,
+        #[graphql(default)] offset: i64,
+    ) -> async_graphql::Result<PaymentPage> {
+        payment_page(ctx, filter.map(Into::into).unwrap_or_default(), first, offset).await
+    }
+
+    /// Taps, newest first
+    async fn taps(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<TapFilter>,
+        #[graphql(default = 50)] first: i64,
+        #[graphql(default)] offset: i64,
+    ) -> async_graphql::Result<TapPage> {
+        tap_page(ctx, filter.map(Into::into).unwrap_or_default(), first, offset).await
+    }
+
+    /// Daily volume, failures and top cards over the last `days` days, like GET /v1/stats
+    async fn stats(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 30)] days: i64,
+        card_id: Option<i64>,
+    ) -> async_graphql::Result<Stats> {
+        let report = stats::report(pool(ctx), days.clamp(1, MAX_DAYS), card_id).await.map_err(internal)?;
+
+        Ok(report.into())
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Card {
+    card_id: i64,
+    card_name: String,
+    uid: String,
+    enabled: bool,
+    last_counter: i64,
+    tx_limit_sats: i64,
+    day_limit_sats: i64,
+    balance_mode: bool,
+    balance_msats: i64,
+    funded_msats: i64,
+    spent_msats: i64,
+    org_id: Option<i64>,
+    owner_id: Option<i64>,
+    created_at: Option<String>,
+    erased_at: Option<String>,
+    /// Set while the card is disabled because it was reported lost
+    reported_lost_at: Option<String>,
+    frozen_at: Option<String>,
+    /// End of the freeze, null for one that lasts until the card is unfrozen
+    frozen_until: Option<String>,
+    /// Set while the card is blocked as a suspected clone
+    clone_flagged_at: Option<String>,
+    /// Set while the card is deleted and can still be restored
+    deleted_at: Option<String>,
+}
+
+#[ComplexObject]
+impl Card {
+    async fn tags(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<String>> {
+        tags::get_card_tags(pool(ctx), self.card_id).await.map_err(internal)
+    }
+
+    /// Key-value data set through /v1/cards/{card_id}/metadata
+    async fn metadata(&self, ctx: &Context<'_>) -> async_graphql::Result<Json<Metadata>> {
+        let metadata = metadata::get_card_metadata(pool(ctx), self.card_id).await.map_err(internal)?;
+        Ok(Json(metadata.unwrap_or_default()))
+    }
+
+    async fn payments(
+        &self,
+        ctx: &Context<'_>,
+        statuses: Option<Vec<PaymentState>>,
+        #[graphql(default = 50)] first: i64,
+        #[graphql(default)] offset: i64,
+    ) -> async_graphql::Result<PaymentPage> {
+        let filter = listing::PaymentFilter {
+            card_id: Some(self.card_id),
+            statuses: statuses.map(|statuses| statuses.into_iter().map(Into::into).collect()),
+            ..Default::default()
+        };
+        payment_page(ctx, filter, first, offset).await
+    }
+
+    async fn taps(
+        &self,
+        ctx: &Context<'_>,
+        success: Option<bool>,
+        #[graphql(default = 50)] first: i64,
+        #[graphql(default)] offset: i64,
+    ) -> async_graphql::Result<TapPage> {
+        let filter = listing::TapFilter { card_id: Some(self.card_id), success, ..Default::default() };
+        tap_page(ctx, filter, first, offset).await
+    }
+}
+
+impl From<CardSummary> for Card {
+    fn from(card: CardSummary) -> Self {
+        Self {
+            card_id: card.card_id,
+            card_name: card.card_name,
+            uid: card.uid,
+            enabled: card.enabled,
+            last_counter: card.last_counter,
+            tx_limit_sats: card.tx_limit_sats,
+            day_limit_sats: card.day_limit_sats,
+            balance_mode: card.balance_mode,
+            balance_msats: card.balance_msats,
+            funded_msats: card.funded_msats,
+            spent_msats: card.spent_msats,
+            org_id: card.org_id,
+            owner_id: card.owner_id,
+            created_at: card.created_at,
+            erased_at: card.erased_at,
+            reported_lost_at: card.reported_lost_at,
+            frozen_at: card.frozen_at,
+            frozen_until: card.frozen_until,
+            clone_flagged_at: card.clone_flagged_at,
+            deleted_at: card.deleted_at,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Payment {
+    payment_id: i64,
+    card_id: i64,
+    k1: String,
+    status: PaymentState,
+    invoice: Option<String>,
+    amount_msats: Option<i64>,
+    payment_hash: Option<String>,
+    failure_reason: Option<String>,
+    attempts: i64,
+    created_at: Option<String>,
+    payment_time: Option<String>,
+    /// Note for expense annotation, the invoice's description unless the operator set one
+    memo: Option<String>,
+    /// Address the withdrawal was paid to on-chain instead of an invoice
+    onchain_address: Option<String>,
+    onchain_txid: Option<String>,
+}
+
+#[ComplexObject]
+impl Payment {
+    async fn card(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Card>> {
+        find_card(ctx, self.card_id).await
+    }
+}
+
+impl From<CardPayment> for Payment {
+    fn from(payment: CardPayment) -> Self {
+        Self {
+            payment_id: payment.payment_id,
+            card_id: payment.card_id,
+            k1: payment.k1,
+            status: payment.status.into(),
+            invoice: payment.invoice,
+            amount_msats: payment.amount_msats,
+            payment_hash: payment.payment_hash,
+            failure_reason: payment.failure_reason,
+            attempts: payment.attempts,
+            created_at: payment.created_at,
+            payment_time: payment.payment_time,
+            memo: payment.memo,
+            onchain_address: payment.onchain_address,
+            onchain_txid: payment.onchain_txid,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Tap {
+    tap_id: i64,
+    card_id: i64,
+    uid: Option<String>,
+    counter: Option<i64>,
+    success: bool,
+    reason: Option<String>,
+    created_at: Option<String>,
+}
+
+#[ComplexObject]
+impl Tap {
+    async fn card(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Card>> {
+        find_card(ctx, self.card_id).await
+    }
+}
+
+impl From<CardTap> for Tap {
+    fn from(tap: CardTap) -> Self {
+        Self {
+            tap_id: tap.tap_id,
+            card_id: tap.card_id,
+            uid: tap.uid,
+            counter: tap.counter,
+            success: tap.success,
+            reason: tap.reason,
+            created_at: tap.created_at,
+        }
+    }
+}
+
+/// [`PaymentStatus`] as a GraphQL enum
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+#[graphql(name = "PaymentStatus")]
+pub enum PaymentState {
+    Created,
+    InvoiceAttached,
+    InFlight,
+    Settled,
+    Failed,
+    Expired,
+}
+
+impl From<PaymentStatus> for PaymentState {
+    fn from(status: PaymentStatus) -> Self {
+        match status {
+            PaymentStatus::Created => PaymentState::Created,
+            PaymentStatus::InvoiceAttached => PaymentState::InvoiceAttached,
+            PaymentStatus::InFlight => PaymentState::InFlight,
+            PaymentStatus::Settled => PaymentState::Settled,
+            PaymentStatus::Failed => PaymentState::Failed,
+            PaymentStatus::Expired => PaymentState::Expired,
+        }
+    }
+}
+
+impl From<PaymentState> for PaymentStatus {
+    fn from(state: PaymentState) -> Self {
+        match state {
+            PaymentState::Created => PaymentStatus::Created,
+            PaymentState::InvoiceAttached => PaymentStatus::InvoiceAttached,
+            PaymentState::InFlight => PaymentStatus::InFlight,
+            PaymentState::Settled => PaymentStatus::Settled,
+            PaymentState::Failed => PaymentStatus::Failed,
+            PaymentState::Expired => PaymentStatus::Expired,
+        }
+    }
+}
+
+/// Cards matching all of the given fields
+#[derive(InputObject, Default)]
+pub struct CardFilter {
+    card_ids: Option<Vec<i64>>,
+    enabled: Option<bool>,
+    /// Whether the card is marked lost
+    lost: Option<bool>,
+    /// Whether the card is frozen
+    frozen: Option<bool>,
+    /// Whether the card is flagged as a suspected clone
+    clone_flagged: Option<bool>,
+    org_id: Option<i64>,
+    owner_id: Option<i64>,
+    tag: Option<String>,
+    /// Part of the card name, case-insensitive
+    name: Option<String>,
+    #[graphql(default)]
+    include_erased: bool,
+    #[graphql(default)]
+    include_deleted: bool,
+}
+
+impl From<CardFilter> for listing::CardFilter {
+    fn from(filter: CardFilter) -> Self {
+        Self {
+            card_ids: filter.card_ids,
+            enabled: filter.enabled,
+            lost: filter.lost,
+            frozen: filter.frozen,
+            clone_flagged: filter.clone_flagged,
+            org_id: filter.org_id,
+            owner_id: filter.owner_id,
+            tag: filter.tag,
+            name: filter.name,
+            include_erased: filter.include_erased,
+            include_deleted: filter.include_deleted,
+        }
+    }
+}
+
+/// Payments matching all of the given fields; `since` and `until` are UTC timestamps like `2025-06-01T00:00:00`
+#[derive(InputObject, Default)]
+pub struct PaymentFilter {
+    card_id: Option<i64>,
+    statuses: Option<Vec<PaymentState>>,
+    since: Option<String>,
+    until: Option<String>,
+}
+
+impl From<PaymentFilter> for listing::PaymentFilter {
+    fn from(filter: PaymentFilter) -> Self {
+        Self {
+            card_id: filter.card_id,
+            statuses: filter.statuses.map(|statuses| statuses.into_iter().map(Into::into).collect()),
+            since: filter.since,
+            until: filter.until,
+        }
+    }
+}
+
+/// Taps matching all of the given fields; `since` and `until` are UTC timestamps like `2025-06-01T00:00:00`
+#[derive(InputObject, Default)]
+pub struct TapFilter {
+    card_id: Option<i64>,
+    success: Option<bool>,
+    since: Option<String>,
+    until: Option<String>,
+}
+
+impl From<TapFilter> for listing::TapFilter {
+    fn from(filter: TapFilter) -> Self {
+        Self {
+            card_id: filter.card_id,
+            success: filter.success,
+            since: filter.since,
+            until: filter.until,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct CardPage {
+    /// Cards matching the filter on all pages
+    total_count: i64,
+    nodes: Vec<Card>,
+}
+
+#[derive(SimpleObject)]
+pub struct PaymentPage {
+    /// Payments matching the filter on all pages
+    total_count: i64,
+    nodes: Vec<Payment>,
+}
+
+#[derive(SimpleObject)]
+pub struct TapPage {
+    /// Taps matching the filter on all pages
+    total_count: i64,
+    nodes: Vec<Tap>,
+}
+
+#[derive(SimpleObject)]
+pub struct Stats {
+    days: i64,
+    card_id: Option<i64>,
+    total_volume_msats: i64,
+    total_payments: i64,
+    total_failures: i64,
+    /// Share of attempted payments (invoice submitted) that did not settle
+    failure_rate: f64,
+    daily: Vec<DayStats>,
+    top_cards: Vec<CardStats>,
+}
+
+impl From<StatsReport> for Stats {
+    fn from(report: StatsReport) -> Self {
+        Self {
+            days: report.days,
+            card_id: report.card_id,
+            total_volume_msats: report.total_volume_msats,
+            total_payments: report.total_payments,
+            total_failures: report.total_failures,
+            failure_rate: report.failure_rate,
+            daily: report.daily.into_iter().map(DayStats::from).collect(),
+            top_cards: report.top_cards.into_iter().map(CardStats::from).collect(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct DayStats {
+    day: String,
+    volume_msats: i64,
+    payments: i64,
+    failures: i64,
+}
+
+impl From<DailyStats> for DayStats {
+    fn from(day: DailyStats) -> Self {
+        Self {
+            day: day.day,
+            volume_msats: day.volume_msats,
+            payments: day.payments,
+            failures: day.failures,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct CardStats {
+    card_id: i64,
+    card_name: String,
+    volume_msats: i64,
+    payments: i64,
+}
+
+impl From<CardVolume> for CardStats {
+    fn from(card: CardVolume) -> Self {
+        Self {
+            card_id: card.card_id,
+            card_name: card.card_name,
+            volume_msats: card.volume_msats,
+            payments: card.payments,
+        }
+    }
+}
+
+async fn find_card(ctx: &Context<'_>, card_id: i64) -> async_graphql::Result<Option<Card>> {
+    let filter = listing::CardFilter {
+        card_ids: Some(vec![card_id]),
+        include_erased: true,
+        include_deleted: true,
+        ..Default::default()
+    };
+    let page = listing::cards(pool(ctx), &filter, 1, 0).await.map_err(internal)?;
+
+    Ok(page.items.into_iter().next().map(Card::from))
+}
+
+async fn payment_page(
+    ctx: &Context<'_>,
+    filter: listing::PaymentFilter,
+    first: i64,
+    offset: i64,
+) -> async_graphql::Result<PaymentPage> {
+    let (limit, offset) = page_bounds(first, offset);
+    let page = listing::payments(pool(ctx), &filter, limit, offset).await.map_err(internal)?;
+
+    Ok(PaymentPage {
+        total_count: page.total,
+        nodes: page.items.into_iter().map(Payment::from).collect(),
+    })
+}
+
+async fn tap_page(ctx: &Context<'_>, filter: listing::TapFilter, first: i64, offset: i64) -> async_graphql::Result<TapPage> {
+    let (limit, offset) = page_bounds(first, offset);
+    let page = listing::taps(pool(ctx), &filter, limit, offset).await.map_err(internal)?;
+
+    Ok(TapPage {
+        total_count: page.total,
+        nodes: page.items.into_iter().map(Tap::from).collect(),
+    })
+}
+
+/// `first` and `offset` of a page request as a limit and offset within range
+fn page_bounds(first: i64, offset: i64) -> (i64, i64) {
+    (first.clamp(0, MAX_PAGE_SIZE), offset.max(0))
+}
+
+fn pool<'a>(ctx: &Context<'a>) -> &'a Pool<Sqlite> {
+    ctx.data_unchecked::<Pool<Sqlite>>()
+}
+
+/// Log a database error and hide its details from the client
+fn internal(e: anyhow::Error) -> async_graphql::Error {
+    tracing::error!("GraphQL query failed: {:#}", e);
+    async_graphql::Error::new("Internal error")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_bounds() {
+        assert_eq!(page_bounds(50, 0), (50, 0));
+        assert_eq!(page_bounds(10_000, -5), (MAX_PAGE_SIZE, 0));
+        assert_eq!(page_bounds(-1, 20), (0, 20));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_deep_queries() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().connect_lazy("sqlite::memory:").unwrap();
+        let query = "{ payments { nodes { card { payments { nodes { card { payments { nodes { card { cardId } } } } } } } } } }";
+
+        let response = schema(pool).execute(query).await;
+
+        assert_eq!(response.errors.len(), 1);
+        assert!(response.errors[0].message.contains("nested too deep"), "{}", response.errors[0].message);
+    }
+}
@@ -0,0 +1,110 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    db::{
+        bulk::{self, BulkAction, CardSelector},
+        nostr, queries, tags,
+    },
+    notifications::nostr::parse_public_key,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct BulkRequest {
+    #[serde(flatten)]
+    pub selector: CardSelector,
+    #[serde(flatten)]
+    pub action: BulkAction,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkResponse {
+    pub status: String,
+    pub card_ids: Vec<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetTagsRequest {
+    pub tags: Vec<String>,
+}
+
+/// POST /v1/cards/bulk
+/// Enable, disable or change limits of cards selected by id list or tag
+pub async fn bulk_update(
+    State(state): State<AppState>,
+    Json(req): Json<BulkRequest>,
+) -> Result<Json<BulkResponse>, StatusCode> {
+    if !req.selector.is_valid() || !req.action.is_valid() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let card_ids = bulk::apply(&state.pool, &req.selector, &req.action, "api")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    tracing::info!("Bulk update {:?} applied to cards {:?}", req.action, card_ids);
+
+    Ok(Json(BulkResponse {
+        status: "OK".to_string(),
+        card_ids,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetNostrKeyRequest {
+    /// Hex or `npub` public key, `null` to unlink the card
+    pub pubkey: Option<String>,
+}
+
+impl SetNostrKeyRequest {
+    /// The key as hex, `Err` if it isn't a valid one
+    pub fn normalized(&self) -> Result<Option<String>, StatusCode> {
+        match &self.pubkey {
+            Some(pubkey) => parse_public_key(pubkey.trim()).map(Some).ok_or(StatusCode::BAD_REQUEST),
+            None => Ok(None),
+        }
+    }
+}
+
+/// PUT /v1/cards/{card_id}/tags
+/// Replace the tags of a card
+pub async fn set_tags(
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+    Json(req): Json<SetTagsRequest>,
+) -> Result<StatusCode, StatusCode> {
+    queries::get_card_by_id(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    tags::set_card_tags(&state.pool, card_id, &req.tags)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// PUT /v1/cards/{card_id}/nostr
+/// Link a card to its holder's Nostr key for receipts of its withdrawals
+pub async fn set_nostr_pubkey(
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+    Json(req): Json<SetNostrKeyRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let pubkey = req.normalized()?;
+
+    let updated = nostr::set_card_nostr_pubkey(&state.pool, card_id, pubkey.as_deref(), "api")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    match updated {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err(StatusCode::NOT_FOUND),
+    }
+}
@@ -0,0 +1,30 @@
+use axum::{extract::State, http::StatusCode, Json};
+use std::sync::Arc;
+
+use crate::chaos::{Chaos, Routes};
+
+/// GET /v1/chaos
+/// Faults injected by route
+pub async fn get_faults(State(chaos): State<Arc<Chaos>>) -> Json<Routes> {
+    Json(chaos.routes())
+}
+
+/// PUT /v1/chaos
+/// Replace the injected faults, e.g. `{"/ln/callback": {"backend_failure": 0.5, "latency_ms": 200}}`;
+/// `*` stands for the routes without their own faults
+pub async fn set_faults(
+    State(chaos): State<Arc<Chaos>>,
+    Json(routes): Json<Routes>,
+) -> Result<Json<Routes>, (StatusCode, String)> {
+    chaos
+        .set_routes(routes)
+        .map_err(|route| (StatusCode::BAD_REQUEST, format!("Invalid route or faults for {}", route)))?;
+    Ok(Json(chaos.routes()))
+}
+
+/// DELETE /v1/chaos
+/// Stop injecting faults
+pub async fn clear_faults(State(chaos): State<Arc<Chaos>>) -> StatusCode {
+    let _ = chaos.set_routes(Routes::new());
+    StatusCode::NO_CONTENT
+}
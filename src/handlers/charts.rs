@@ -0,0 +1,63 @@
+use crate::handlers::html::escape;
+
+const WIDTH: f64 = 640.0;
+const HEIGHT: f64 = 200.0;
+const LABEL_SPACE: f64 = 20.0;
+
+/// Render a simple inline SVG bar chart
+///
+/// Every bar carries a tooltip with its label and formatted value; only the
+/// first and last labels are drawn on the axis to keep long ranges readable.
+pub fn bar_chart(bars: &[(String, f64)], format_value: impl Fn(f64) -> String) -> String {
+    if bars.is_empty() {
+        return "<p>No data</p>".to_string();
+    }
+
+    let max = bars.iter().map(|(_, v)| *v).fold(0.0, f64::max);
+    let slot = WIDTH / bars.len() as f64;
+    let plot_height = HEIGHT - LABEL_SPACE;
+
+    let mut svg = format!(
+        r#"<svg viewBox="0 0 {WIDTH} {HEIGHT}" width="100%" role="img" xmlns="http://www.w3.org/2000/svg">"#
+    );
+    svg.push_str(&format!(
+        r##"<line x1="0" y1="{plot_height}" x2="{WIDTH}" y2="{plot_height}" stroke="#999"/>"##
+    ));
+
+    for (i, (label, value)) in bars.iter().enumerate() {
+        let height = if max > 0.0 { value / max * (plot_height - 15.0) } else { 0.0 };
+        let x = i as f64 * slot + slot * 0.1;
+        svg.push_str(&format!(
+            r##"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="#f7931a"><title>{}: {}</title></rect>"##,
+            x,
+            plot_height - height,
+            slot * 0.8,
+            height,
+            escape(label),
+            escape(&format_value(*value)),
+        ));
+    }
+
+    let first = &bars[0].0;
+    let last = &bars[bars.len() - 1].0;
+    svg.push_str(&format!(
+        r#"<text x="0" y="{HEIGHT}" font-size="11">{}</text><text x="{WIDTH}" y="{HEIGHT}" font-size="11" text-anchor="end">{}</text>"#,
+        escape(first),
+        escape(last),
+    ));
+    svg.push_str(&format!(
+        r#"<text x="0" y="11" font-size="11">max {}</text>"#,
+        escape(&format_value(max)),
+    ));
+    svg.push_str("</svg>");
+
+    svg
+}
+
+pub fn format_sats(msats: f64) -> String {
+    format!("{} sats", (msats / 1000.0).round() as i64)
+}
+
+pub fn format_percent(ratio: f64) -> String {
+    format!("{:.1}%", ratio * 100.0)
+}
@@ -0,0 +1,30 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+};
+
+use crate::{
+    app_state::AppState,
+    db::{clones, queries},
+};
+
+/// DELETE /v1/cards/{card_id}/clone-flag
+/// Let a card flagged as a suspected clone pay again once the operator reviewed it
+///
+/// Clearing the flag doesn't resolve the shared UID; the next tap flags the
+/// card again unless the other cards with its UID were disabled or replaced.
+pub async fn clear_clone_flag(Path(card_id): Path<i64>, State(state): State<AppState>) -> Result<StatusCode, StatusCode> {
+    queries::get_card_by_id(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let cleared = clones::clear_clone_flag(&state.pool, card_id, "api")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if cleared {
+        tracing::info!("Clone flag of card {} cleared", card_id);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,728 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{Html, IntoResponse, Redirect, Response},
+    Form,
+};
+use serde::Deserialize;
+
+use crate::{
+    app_state::AppState,
+    auth::{self, oidc, DashboardUser, Role},
+    db::{
+        bulk::{self, BulkAction, CardSelector},
+        deletion,
+        models::CreateCardRequest,
+        organizations, queries, sessions, stats, tags, taps,
+    },
+    handlers::{
+        charts::{bar_chart, format_percent, format_sats},
+        html::{escape, page, qr_svg},
+        register,
+        stats::StatsQuery,
+    },
+};
+
+#[derive(Debug, Deserialize)]
+pub struct LoginPageQuery {
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewCardForm {
+    card_name: String,
+    tx_limit_sats: Option<String>,
+    day_limit_sats: Option<String>,
+    balance_mode: Option<String>,
+    tags: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkForm {
+    card_ids: Option<String>,
+    tag: Option<String>,
+    action: String,
+    tx_limit_sats: Option<String>,
+    day_limit_sats: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginForm {
+    username: String,
+    password: String,
+    totp: Option<String>,
+}
+
+/// GET /dashboard/login
+pub async fn login_page(
+    Query(params): Query<LoginPageQuery>,
+    State(state): State<AppState>,
+) -> Result<Html<String>, StatusCode> {
+    if !state.config.dashboard_enabled() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let error = if params.error.is_some() {
+        "<p><strong>Invalid credentials</strong></p>\n"
+    } else {
+        ""
+    };
+
+    let totp_field = if state.config.dashboard_totp_secret.is_some() {
+        "<p><label>Authenticator code<br><input name=\"totp\" inputmode=\"numeric\" autocomplete=\"one-time-code\" required></label></p>\n"
+    } else {
+        ""
+    };
+
+    let base = &state.config.public.base_path;
+    let password_form = if state.config.password_login_enabled() {
+        format!(
+            r#"<form method="post" action="{base}/dashboard/login">
+<p><label>Username<br><input name="username" autocomplete="username" required></label></p>
+<p><label>Password<br><input name="password" type="password" autocomplete="current-password" required></label></p>
+{totp_field}<p><button type="submit">Log in</button></p>
+</form>
+"#
+        )
+    } else {
+        String::new()
+    };
+
+    let sso_link = if state.config.oidc_enabled() {
+        format!("<p><a href=\"{base}/dashboard/oidc/login\">Log in with single sign-on</a></p>\n")
+    } else {
+        String::new()
+    };
+
+    let body = format!("<h1>Dashboard login</h1>\n{error}{password_form}{sso_link}");
+
+    Ok(page(&state.config, "Dashboard login", &body))
+}
+
+/// POST /dashboard/login
+pub async fn login(
+    State(state): State<AppState>,
+    Form(form): Form<LoginForm>,
+) -> Result<Response, StatusCode> {
+    let config = &state.config;
+    let (Some(username), Some(password_hash)) = (&config.dashboard_username, &config.dashboard_password_hash) else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    // Always run the password check so timing doesn't reveal valid usernames
+    let password_ok = auth::verify_password(password_hash, &form.password);
+    let totp_ok = match &config.dashboard_totp_secret {
+        Some(secret) => form.totp.as_deref().is_some_and(|code| auth::verify_totp(secret, code)),
+        None => true,
+    };
+
+    if form.username != *username || !password_ok || !totp_ok {
+        tracing::warn!("Failed dashboard login for user {:?}", form.username);
+        return Ok(Redirect::to(&config.path("/dashboard/login?error=1")).into_response());
+    }
+
+    let token = auth::new_session_token(&state.random).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sessions::create_session(
+        &state.pool,
+        &auth::hash_session_token(&token),
+        username,
+        Role::Admin.as_str(),
+        config.dashboard_session_hours,
+        state.clock.now(),
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(start_session(&state, &token).await)
+}
+
+/// GET /dashboard/oidc/login
+/// Redirect to the OpenID Connect provider
+pub async fn oidc_login(State(state): State<AppState>) -> Result<Response, StatusCode> {
+    let issuer = state.config.oidc_issuer_url.as_deref().ok_or(StatusCode::NOT_FOUND)?;
+
+    let metadata = oidc::discover(&state.http, issuer).await.map_err(|e| {
+        tracing::error!("OIDC discovery failed: {}", e);
+        StatusCode::BAD_GATEWAY
+    })?;
+
+    let login_state = state.random.hex::<16>().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let code_verifier = oidc::new_code_verifier(&state.random).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    sessions::create_oidc_state(&state.pool, &login_state, &code_verifier, state.clock.now())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let url = oidc::authorization_url(&metadata, &state.config, &login_state, &code_verifier)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((
+        [(header::SET_COOKIE, auth::oidc_state_cookie(&state.config.public.base_path, &login_state))],
+        Redirect::to(&url),
+    )
+        .into_response())
+}
+
+/// GET /dashboard/oidc/callback
+/// Complete the authorization code flow and map the user's groups to a role
+///
+/// The state must match the cookie set by `oidc_login`, so a callback URL
+/// started in another browser can't log this one in.
+pub async fn oidc_callback(
+    Query(params): Query<OidcCallbackQuery>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let config = &state.config;
+    let issuer = config.oidc_issuer_url.as_deref().ok_or(StatusCode::NOT_FOUND)?;
+
+    if let Some(error) = params.error {
+        tracing::warn!("OIDC provider returned error: {}", error);
+        return Ok(Redirect::to(&config.path("/dashboard/login?error=1")).into_response());
+    }
+
+    let (Some(code), Some(login_state)) = (params.code, params.state) else {
+        return Err(StatusCode::BAD_REQUEST);
+    };
+
+    if auth::cookie_value(&headers, auth::OIDC_STATE_COOKIE).as_deref() != Some(login_state.as_str()) {
+        tracing::warn!("OIDC callback state doesn't match this browser's login");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let code_verifier = sessions::take_oidc_state(&state.pool, &login_state, state.clock.now())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    let claims = async {
+        let metadata = oidc::discover(&state.http, issuer).await?;
+        let access_token = oidc::exchange_code(&state.http, &metadata, config, &code, &code_verifier).await?;
+        oidc::fetch_userinfo(&state.http, &metadata, &access_token).await
+    }
+    .await
+    .map_err(|e| {
+        tracing::error!("OIDC login failed: {}", e);
+        StatusCode::BAD_GATEWAY
+    })?;
+
+    let username = oidc::username_from_claims(&claims).ok_or(StatusCode::BAD_GATEWAY)?;
+    let groups = oidc::groups_from_claims(&claims, &config.oidc_groups_claim);
+
+    let Some(role) = oidc::map_role(&groups, &config.oidc_admin_groups, &config.oidc_viewer_groups) else {
+        tracing::warn!("OIDC user {} has no dashboard role (groups: {:?})", username, groups);
+        return Err(StatusCode::FORBIDDEN);
+    };
+
+    let token = auth::new_session_token(&state.random).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sessions::create_session(
+        &state.pool,
+        &auth::hash_session_token(&token),
+        &username,
+        role.as_str(),
+        config.dashboard_session_hours,
+        state.clock.now(),
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(start_session(&state, &token).await)
+}
+
+/// Set the session cookie and send the user to the dashboard
+async fn start_session(state: &AppState, token: &str) -> Response {
+    // Opportunistically clean up stale sessions and login states
+    let _ = sessions::delete_expired_sessions(&state.pool, state.clock.now()).await;
+
+    (
+        [
+            (header::SET_COOKIE, auth::session_cookie(&state.config.public.base_path, token, state.config.dashboard_session_hours)),
+            (header::SET_COOKIE, auth::clear_oidc_state_cookie(&state.config.public.base_path)),
+        ],
+        Redirect::to(&state.config.path("/dashboard")),
+    )
+        .into_response()
+}
+
+/// POST /dashboard/logout
+pub async fn logout(
+    user: DashboardUser,
+    State(state): State<AppState>,
+) -> Result<Response, StatusCode> {
+    sessions::delete_session(&state.pool, &user.session_hash)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((
+        [(header::SET_COOKIE, auth::clear_session_cookie(&state.config.public.base_path))],
+        Redirect::to(&state.config.path("/dashboard/login")),
+    )
+        .into_response())
+}
+
+/// GET /dashboard
+/// Card overview for logged-in operators
+pub async fn index(
+    user: DashboardUser,
+    State(state): State<AppState>,
+) -> Result<Html<String>, StatusCode> {
+    let cards = queries::list_cards(&state.pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let card_tags = tags::list_card_tags(&state.pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let deleted = deletion::list_deleted_card_ids(&state.pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let base = &state.config.public.base_path;
+    let rows: String = cards
+        .iter()
+        .filter(|card| !deleted.contains(&card.card_id))
+        .map(|card| {
+            format!(
+                "<tr><td><a href=\"{base}/dashboard/stats?card_id={}\">{}</a></td><td>{}</td><td>{}</td><td><code>{}</code></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                card.card_id,
+                card.card_id,
+                escape(&card.card_name),
+                escape(&card_tags.get(&card.card_id).map(|t| t.join(", ")).unwrap_or_default()),
+                escape(&card.uid),
+                if card.enabled { "yes" } else { "no" },
+                card.tx_limit_sats,
+                card.day_limit_sats,
+                card.last_counter,
+            )
+        })
+        .collect();
+
+    let body = format!(
+        r#"<form method="post" action="{base}/dashboard/logout" style="float: right">
+<button type="submit">Log out {} ({})</button>
+</form>
+<h1>Cards</h1>
+<p><a href="{base}/dashboard/cards/new">New card</a> | <a href="{base}/dashboard/stats">Spending trends</a></p>
+{}<table>
+<tr><th>ID</th><th>Name</th><th>Tags</th><th>UID</th><th>Enabled</th><th>Tx limit</th><th>Day limit</th><th>Counter</th></tr>
+{}</table>
+{}"#,
+        escape(&user.username),
+        user.role.as_str(),
+        price_line(&state).await,
+        rows,
+        if user.role == Role::Admin { bulk_form(base) } else { String::new() },
+    );
+
+    Ok(page(&state.config, "Dashboard", &body))
+}
+
+/// The bitcoin price in the fiat currency, if one is configured
+async fn price_line(state: &AppState) -> String {
+    let Some(currency) = &state.config.fiat_currency else {
+        return String::new();
+    };
+    match state.rates.price(currency).await {
+        Ok(quote) => format!(
+            "<p>Bitcoin price: {:.2} {} ({}, {}{})</p>\n",
+            quote.price,
+            escape(currency),
+            quote.source,
+            quote.fetched_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            if quote.stale { ", stale" } else { "" },
+        ),
+        Err(e) => {
+            tracing::warn!("No bitcoin price for the dashboard: {}", e);
+            format!("<p>Bitcoin price: unavailable in {}</p>\n", escape(currency))
+        }
+    }
+}
+
+fn bulk_form(base: &str) -> String {
+    format!(
+        r#"<h2>Bulk update</h2>
+<form method="post" action="{base}/dashboard/cards/bulk">
+<p><label>Card IDs (comma separated)<br><input name="card_ids"></label> or <label>tag<br><input name="tag"></label></p>
+<p><label>Action<br><select name="action">
+<option value="disable">Disable</option>
+<option value="enable">Enable</option>
+<option value="set_limits">Set limits</option>
+</select></label></p>
+<p><label>Per-payment limit (sats)<br><input name="tx_limit_sats" type="number" min="1"></label>
+<label>Daily limit (sats)<br><input name="day_limit_sats" type="number" min="1"></label></p>
+<p><button type="submit">Apply</button></p>
+</form>"#
+    )
+}
+
+/// Parse an optional numeric form field, treating blank input as unset
+fn parse_limit(value: Option<String>) -> Result<Option<i64>, StatusCode> {
+    match value.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(v) => v.parse().map(Some).map_err(|_| StatusCode::BAD_REQUEST),
+    }
+}
+
+/// POST /dashboard/cards/bulk
+pub async fn bulk_update(
+    user: DashboardUser,
+    State(state): State<AppState>,
+    Form(form): Form<BulkForm>,
+) -> Result<Response, StatusCode> {
+    user.require_admin()?;
+
+    let card_ids = match form.card_ids.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(ids) => Some(
+            ids.split(',')
+                .map(|id| id.trim().parse())
+                .collect::<Result<Vec<i64>, _>>()
+                .map_err(|_| StatusCode::BAD_REQUEST)?,
+        ),
+    };
+    let selector = CardSelector {
+        card_ids,
+        tag: form.tag.filter(|tag| !tag.trim().is_empty()),
+    };
+
+    let action = match form.action.as_str() {
+        "enable" => BulkAction::Enable,
+        "disable" => BulkAction::Disable,
+        "set_limits" => BulkAction::SetLimits {
+            tx_limit_sats: parse_limit(form.tx_limit_sats)?,
+            day_limit_sats: parse_limit(form.day_limit_sats)?,
+        },
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+
+    if !selector.is_valid() || !action.is_valid() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let card_ids = bulk::apply(&state.pool, &selector, &action, &user.username)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    tracing::info!("{} applied bulk update {:?} to cards {:?}", user.username, action, card_ids);
+
+    Ok(Redirect::to(&state.config.path("/dashboard")).into_response())
+}
+
+/// GET /dashboard/stats?days={n}&card_id={id}
+/// Spending and failure charts, globally or for one card
+pub async fn stats_page(
+    _user: DashboardUser,
+    Query(params): Query<StatsQuery>,
+    State(state): State<AppState>,
+) -> Result<Html<String>, StatusCode> {
+    let report = stats::report(&state.pool, params.days(), params.card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let title = match report.card_id {
+        Some(card_id) => {
+            let card = queries::get_card_by_id(&state.pool, card_id)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .ok_or(StatusCode::NOT_FOUND)?;
+            format!("Spending: {}", card.card_name)
+        }
+        None => "Spending: all cards".to_string(),
+    };
+
+    let volume: Vec<(String, f64)> = report
+        .daily
+        .iter()
+        .map(|d| (d.day.clone(), d.volume_msats as f64))
+        .collect();
+    let failure_rate: Vec<(String, f64)> = report
+        .daily
+        .iter()
+        .map(|d| {
+            let attempts = d.payments + d.failures;
+            let rate = if attempts > 0 { d.failures as f64 / attempts as f64 } else { 0.0 };
+            (d.day.clone(), rate)
+        })
+        .collect();
+
+    let base = &state.config.public.base_path;
+    let top_cards = if report.top_cards.is_empty() {
+        String::new()
+    } else {
+        let rows: String = report
+            .top_cards
+            .iter()
+            .map(|c| {
+                format!(
+                    "<tr><td><a href=\"{base}/dashboard/stats?card_id={}&amp;days={}\">{}</a></td><td>{}</td><td>{}</td></tr>\n",
+                    c.card_id,
+                    report.days,
+                    escape(&c.card_name),
+                    c.payments,
+                    format_sats(c.volume_msats as f64),
+                )
+            })
+            .collect();
+        format!(
+            "<h2>Top cards</h2>\n<table>\n<tr><th>Card</th><th>Payments</th><th>Volume</th></tr>\n{}</table>\n",
+            rows
+        )
+    };
+
+    let card_param = report
+        .card_id
+        .map(|id| format!("&amp;card_id={}", id))
+        .unwrap_or_default();
+    let ranges: Vec<String> = [7, 30, 90]
+        .iter()
+        .map(|days| format!("<a href=\"{base}/dashboard/stats?days={}{}\">{} days</a>", days, card_param, days))
+        .collect();
+
+    let body = format!(
+        r#"<p><a href="{base}/dashboard">&larr; Cards</a> | {ranges}</p>
+<h1>{title}</h1>
+<p>Last {days} days: {payments} payments, {volume}, {failures} failed ({rate} failure rate)</p>
+<h2>Daily volume</h2>
+{volume_chart}
+<h2>Daily failure rate</h2>
+{failure_chart}
+{top_cards}"#,
+        ranges = ranges.join(" | "),
+        title = escape(&title),
+        days = report.days,
+        payments = report.total_payments,
+        volume = format_sats(report.total_volume_msats as f64),
+        failures = report.total_failures,
+        rate = format_percent(report.failure_rate),
+        volume_chart = bar_chart(&volume, format_sats),
+        failure_chart = bar_chart(&failure_rate, format_percent),
+        top_cards = top_cards,
+    );
+
+    Ok(page(&state.config, &title, &body))
+}
+
+/// GET /dashboard/cards/new
+/// First step of the programming wizard
+pub async fn new_card_page(
+    user: DashboardUser,
+    State(state): State<AppState>,
+) -> Result<Html<String>, StatusCode> {
+    user.require_admin()?;
+
+    let base = &state.config.public.base_path;
+    let body = format!(
+        r#"<p><a href="{base}/dashboard">&larr; Cards</a></p>
+<h1>New card</h1>
+<form method="post" action="{base}/dashboard/cards/new">
+<p><label>Name<br><input name="card_name" required></label></p>
+<p><label>Per-payment limit (sats)<br><input name="tx_limit_sats" type="number" min="1" placeholder="{}"></label></p>
+<p><label>Daily limit (sats)<br><input name="day_limit_sats" type="number" min="1" placeholder="{}"></label></p>
+<p><label>Tags (comma separated)<br><input name="tags"></label></p>
+<p><label><input name="balance_mode" type="checkbox"> Prepaid balance (spend only what was topped up)</label></p>
+<p><button type="submit">Create and program</button></p>
+</form>"#,
+        state.config.default_tx_limit, state.config.default_day_limit,
+    );
+
+    Ok(page(&state.config, "New card", &body))
+}
+
+/// POST /dashboard/cards/new
+pub async fn create_card(
+    user: DashboardUser,
+    State(state): State<AppState>,
+    Form(form): Form<NewCardForm>,
+) -> Result<Response, StatusCode> {
+    user.require_admin()?;
+
+    let req = CreateCardRequest {
+        card_name: form.card_name,
+        tx_limit_sats: parse_limit(form.tx_limit_sats)?,
+        day_limit_sats: parse_limit(form.day_limit_sats)?,
+        enabled: Some(true),
+        balance_mode: Some(form.balance_mode.is_some()),
+        tags: form.tags.map(|tags| tags.split(',').map(str::to_string).collect()),
+        owner_id: None,
+        org_id: None,
+    };
+
+    let created = register::create_card_record(&state.pool, &state.config, &state.random, &req, state.clock.now())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Redirect::to(&state.config.path(&format!("/dashboard/cards/{}/wizard", created.card_id))).into_response())
+}
+
+/// GET /dashboard/cards/{card_id}/wizard
+/// Walks through programming and a verification tap, refreshing until each step completes
+///
+/// Admin only: the first step shows the registration link that hands out the card keys.
+pub async fn card_wizard(
+    user: DashboardUser,
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Html<String>, StatusCode> {
+    user.require_admin()?;
+
+    let card = queries::get_card_by_id(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let first_tap = taps::get_first_successful_tap(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let base = &state.config.public.base_path;
+    let registration_fetched = card.one_time_code_used.unwrap_or(false);
+    let mut refresh = true;
+
+    let step = if let Some(tap) = first_tap {
+        refresh = false;
+        format!(
+            r#"<h2>Step 3 of 3: Verified</h2>
+<p>The card was tapped and validated successfully.</p>
+<table>
+<tr><th>UID</th><td><code>{}</code></td></tr>
+<tr><th>Counter</th><td>{}</td></tr>
+<tr><th>Time (UTC)</th><td>{}</td></tr>
+</table>
+<p><a href="{base}/dashboard">Back to cards</a></p>"#,
+            escape(tap.uid.as_deref().unwrap_or("")),
+            tap.counter.unwrap_or(0),
+            escape(tap.created_at.as_deref().unwrap_or("")),
+        )
+    } else if registration_fetched {
+        let recent = taps::get_recent_taps(&state.pool, card_id, 1)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let last_failure = recent
+            .first()
+            .and_then(|tap| tap.reason.as_deref())
+            .map(|reason| format!("<p><strong>Last tap failed:</strong> {}</p>\n", escape(reason)))
+            .unwrap_or_default();
+
+        format!(
+            r#"<h2>Step 2 of 3: Verification tap</h2>
+<p>The programming app fetched the card keys. Now tap the card on a phone or point-of-sale reader so the server can validate it.</p>
+{last_failure}<p>Waiting for the first tap&hellip;</p>"#
+        )
+    } else {
+        let domain = organizations::get_card_domain(&state.pool, card_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let url = format!(
+            "{}?a={}",
+            state.config.urls_on(domain.as_deref()).registration_base(),
+            card.one_time_code.as_deref().unwrap_or("")
+        );
+        let qr = qr_svg(&url).ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        format!(
+            r#"<h2>Step 1 of 3: Program the card</h2>
+<p>Scan this code with the Bolt Card programming app and write the card.</p>
+{qr}
+<p><code>{}</code></p>
+<p>Waiting for the programming app&hellip;</p>"#,
+            escape(&url)
+        )
+    };
+
+    let body = format!(
+        "{}<p><a href=\"{base}/dashboard\">&larr; Cards</a></p>\n<h1>Program {}</h1>\n{}",
+        if refresh { "<meta http-equiv=\"refresh\" content=\"3\">\n" } else { "" },
+        escape(&card.card_name),
+        step
+    );
+
+    Ok(page(&state.config, "Program card", &body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_card_wizard_requires_admin() {
+        let (state, _) = crate::app_state::test_state(&[]).await;
+        let user = |role| DashboardUser { username: "op".to_string(), role, session_hash: String::new() };
+
+        assert_eq!(
+            card_wizard(user(Role::Viewer), Path(1), State(state.clone())).await.unwrap_err(),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            card_wizard(user(Role::Admin), Path(1), State(state)).await.unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pages_behind_base_path() {
+        let (state, _) = crate::app_state::test_state(&[
+            "--base-path", "/cards",
+            "--dashboard-username", "op",
+            "--dashboard-password-hash", "hash",
+        ])
+        .await;
+
+        let login = login_page(Query(LoginPageQuery { error: None }), State(state.clone())).await.unwrap();
+        assert!(login.0.contains(r#"action="/cards/dashboard/login""#), "{}", login.0);
+
+        let user = DashboardUser { username: "op".to_string(), role: Role::Admin, session_hash: String::new() };
+        let index = index(user.clone(), State(state.clone())).await.unwrap();
+        assert!(index.0.contains(r#"action="/cards/dashboard/cards/bulk""#), "{}", index.0);
+        assert!(!index.0.contains(r#""/dashboard"#), "{}", index.0);
+
+        let response = logout(user, State(state)).await.unwrap();
+        assert_eq!(response.headers()[header::LOCATION], "/cards/dashboard/login");
+        assert!(response.headers()[header::SET_COOKIE].to_str().unwrap().contains("Path=/cards/dashboard;"));
+    }
+
+    #[tokio::test]
+    async fn test_oidc_callback_checks_state_cookie() {
+        let (state, _) = crate::app_state::test_state(&[
+            "--oidc-issuer-url", "http://127.0.0.1:1",
+            "--oidc-client-id", "lnurlw-dashboard",
+        ])
+        .await;
+        sessions::create_oidc_state(&state.pool, "login-state", "verifier", state.clock.now()).await.unwrap();
+
+        let callback = |cookie: Option<&str>| {
+            let mut headers = HeaderMap::new();
+            if let Some(cookie) = cookie {
+                headers.insert(header::COOKIE, cookie.parse().unwrap());
+            }
+            let params = OidcCallbackQuery {
+                code: Some("code".to_string()),
+                state: Some("login-state".to_string()),
+                error: None,
+            };
+            oidc_callback(Query(params), State(state.clone()), headers)
+        };
+
+        // Another browser's callback URL is refused without using up the login
+        assert_eq!(callback(None).await.unwrap_err(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            callback(Some("lnurlw_oidc_state=other-state")).await.unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
+        // The browser that started the login gets as far as the (unreachable) provider
+        assert_eq!(
+            callback(Some("lnurlw_session=x; lnurlw_oidc_state=login-state")).await.unwrap_err(),
+            StatusCode::BAD_GATEWAY
+        );
+        assert_eq!(
+            callback(Some("lnurlw_oidc_state=login-state")).await.unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+}
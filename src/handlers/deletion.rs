@@ -0,0 +1,42 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+};
+
+use crate::{
+    app_state::AppState,
+    db::deletion::{self, Deletion},
+};
+
+/// DELETE /v1/cards/{card_id}
+/// Delete a card, keeping it restorable until the retention period is over
+///
+/// `409` while a payment of the card is being made.
+pub async fn delete_card(Path(card_id): Path<i64>, State(state): State<AppState>) -> Result<StatusCode, StatusCode> {
+    let deletion = deletion::delete_card(&state.pool, card_id, state.clock.now(), "api")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    match deletion {
+        Deletion::Deleted(deleted_at) => {
+            tracing::info!("Card {} deleted at {}", card_id, deleted_at);
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Deletion::NotFound => Err(StatusCode::NOT_FOUND),
+        Deletion::PaymentsInFlight => Err(StatusCode::CONFLICT),
+    }
+}
+
+/// POST /v1/cards/{card_id}/restore
+/// Bring back a deleted card that wasn't purged yet
+pub async fn restore_card(Path(card_id): Path<i64>, State(state): State<AppState>) -> Result<StatusCode, StatusCode> {
+    let restored = deletion::restore_card(&state.pool, card_id, "api")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !restored {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    tracing::info!("Card {} restored", card_id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,51 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{app_state::AppState, db::descriptions, description};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DescriptionTemplate {
+    /// The card's own template, `null` for the server's `--withdraw-description`
+    pub template: Option<String>,
+}
+
+/// GET /v1/cards/{card_id}/description
+pub async fn get_description(
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<DescriptionTemplate>, StatusCode> {
+    let template = descriptions::get_card_template(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(DescriptionTemplate { template }))
+}
+
+/// PUT /v1/cards/{card_id}/description
+/// Set the template of the description wallets are offered for the card's withdrawals
+pub async fn set_description(
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+    Json(req): Json<DescriptionTemplate>,
+) -> Result<Json<DescriptionTemplate>, StatusCode> {
+    if let Some(template) = &req.template
+        && let Err(e) = description::validate(template)
+    {
+        tracing::debug!("Rejected description template for card {}: {}", card_id, e);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let updated = descriptions::set_card_template(&state.pool, card_id, req.template.as_deref(), "api")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    match updated {
+        true => Ok(Json(req)),
+        false => Err(StatusCode::NOT_FOUND),
+    }
+}
@@ -0,0 +1,134 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Serialize;
+
+use crate::{
+    app_state::AppState,
+    db::{clones, deletion, freeze, lost, queries, taps},
+    diagnostics::{self, CounterGaps, KeyCheck},
+};
+
+/// Taps the diagnostics look at, newest first
+const RECENT_TAPS: i64 = 100;
+const RECENT_FAILURES: usize = 10;
+
+#[derive(Debug, Serialize)]
+pub struct TapFailure {
+    pub created_at: Option<String>,
+    pub counter: Option<i64>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CardDiagnostics {
+    pub card_id: i64,
+    pub card_name: String,
+    pub enabled: bool,
+    pub frozen: bool,
+    pub frozen_until: Option<String>,
+    pub reported_lost_at: Option<String>,
+    /// Set while the card is blocked as a suspected clone
+    pub clone_flagged_at: Option<String>,
+    /// Set while the card is deleted and can still be restored
+    pub deleted_at: Option<String>,
+    pub last_counter: i64,
+    /// Taps left before the counter runs out
+    pub counter_remaining: i64,
+    pub last_tap_at: Option<String>,
+    pub last_successful_tap_at: Option<String>,
+    pub counter_gaps: CounterGaps,
+    pub recent_failures: Vec<TapFailure>,
+    pub key_checks: Vec<KeyCheck>,
+    /// Everything above that keeps the card from working, in plain words
+    pub issues: Vec<String>,
+}
+
+/// GET /v1/cards/{card_id}/diagnostics
+/// What support needs to triage a card that stopped working
+pub async fn get_diagnostics(
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<CardDiagnostics>, StatusCode> {
+    let card = queries::get_card_by_id(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let recent = taps::get_recent_taps(&state.pool, card_id, RECENT_TAPS)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let freeze = freeze::get_freeze(&state.pool, card_id, state.clock.now())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let reported_lost_at = lost::get_reported_lost_at(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let clone_flagged_at = clones::get_clone_flagged_at(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let deleted_at = deletion::get_deleted_at(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let key_checks = diagnostics::check_keys(&card);
+    let counter_remaining = diagnostics::counter_remaining(&card);
+    let last_successful_tap_at = recent.iter().find(|tap| tap.success).and_then(|tap| tap.created_at.clone());
+
+    let mut issues = Vec::new();
+    if let Some(deleted_at) = &deleted_at {
+        issues.push(format!("Card was deleted at {}", deleted_at));
+    }
+    if !card.enabled {
+        issues.push("Card is disabled".to_string());
+    }
+    if let Some(freeze) = &freeze {
+        issues.push(format!("Card is frozen until {}", freeze.frozen_until.as_deref().unwrap_or("unfrozen")));
+    }
+    if let Some(reported_lost_at) = &reported_lost_at {
+        issues.push(format!("Card was reported lost at {}", reported_lost_at));
+    }
+    if let Some(clone_flagged_at) = &clone_flagged_at {
+        issues.push(format!("Card shares its UID with another card and is under review since {}", clone_flagged_at));
+    }
+    if counter_remaining == 0 {
+        issues.push("Counter is exhausted, the card needs replacement".to_string());
+    }
+    for check in &key_checks {
+        if let Some(problem) = &check.problem {
+            issues.push(format!("{}: {}", check.key, problem));
+        }
+    }
+    if recent.is_empty() {
+        issues.push("Card was never tapped".to_string());
+    } else if last_successful_tap_at.is_none() {
+        issues.push(format!("None of the last {} taps succeeded", recent.len()));
+    } else if let Some(failure) = recent.first().filter(|tap| !tap.success) {
+        issues.push(format!("Last tap failed: {}", failure.reason.as_deref().unwrap_or("unknown reason")));
+    }
+
+    Ok(Json(CardDiagnostics {
+        card_id,
+        card_name: card.card_name,
+        enabled: card.enabled,
+        frozen: freeze.is_some(),
+        frozen_until: freeze.and_then(|freeze| freeze.frozen_until),
+        reported_lost_at,
+        clone_flagged_at,
+        deleted_at,
+        last_counter: card.last_counter,
+        counter_remaining,
+        last_tap_at: recent.first().and_then(|tap| tap.created_at.clone()),
+        last_successful_tap_at,
+        counter_gaps: diagnostics::counter_gaps(&recent),
+        recent_failures: recent
+            .iter()
+            .filter(|tap| !tap.success)
+            .take(RECENT_FAILURES)
+            .map(|tap| TapFailure { created_at: tap.created_at.clone(), counter: tap.counter, reason: tap.reason.clone() })
+            .collect(),
+        key_checks,
+        issues,
+    }))
+}
@@ -0,0 +1,267 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use std::fmt;
+
+use crate::{
+    i18n::Locale,
+    validation::{AuthError, ParamError},
+};
+
+/// Error answered by the LNURL endpoints
+///
+/// Wallets show the `reason`, which is translated; clients that react to
+/// errors programmatically use the stable `code`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiError {
+    Database,
+    CardNotFound,
+    /// The card is frozen, until the time given or until it is unfrozen
+    CardFrozen { until: Option<String> },
+    /// The card is flagged as a suspected clone until the operator reviewed it
+    CardUnderReview,
+    /// Query string that doesn't parse, e.g. a repeated parameter
+    MalformedQuery,
+    /// `card_id` that isn't a number
+    InvalidCardId,
+    /// Malformed `p` or `c` parameter
+    InvalidParameter(ParamError),
+    /// Tap that doesn't authenticate with the card's keys
+    AuthenticationFailed(AuthError),
+    UidMismatch,
+    /// The card used up its 24-bit counter
+    CounterExhausted,
+    ReplayDetected,
+    /// The counter moved on between validation and update, i.e. a concurrent tap
+    CounterUpdateFailed,
+    InvalidK1,
+    SessionExpired,
+    /// A failed session ran out of retries
+    AttemptsExhausted,
+    PaymentAlreadyProcessed,
+    InvalidInvoice,
+    InvoiceWithoutAmount,
+    /// The invoice is for another network than `--network`
+    WrongNetwork,
+    AmountOutOfRange,
+    /// Asked for an on-chain payout of a withdrawal not eligible for one
+    OnchainNotAllowed,
+    /// On-chain address that doesn't parse or is for another network
+    InvalidAddress,
+    DescriptionMismatch,
+    TxLimitExceeded,
+    DailyLimitExceeded,
+    InsufficientBalance,
+    /// The cards of the node would spend more than was funded to them
+    CapitalExhausted,
+    /// Refused by the policy plugin, with the reason it gave
+    PolicyDenied(Option<String>),
+    /// The Lightning payment failed, with the reason given by the backend
+    PaymentFailed(String),
+    /// The node couldn't tell how the payment ended, the reconciliation will
+    PaymentPending,
+    /// LNURL-auth challenge unknown, expired or signed before
+    LoginExpired,
+    /// LNURL-auth signature that doesn't verify against the key
+    InvalidSignature,
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Database => "DATABASE_ERROR",
+            ApiError::CardNotFound => "CARD_NOT_FOUND",
+            ApiError::CardFrozen { .. } => "CARD_FROZEN",
+            ApiError::CardUnderReview => "CARD_UNDER_REVIEW",
+            ApiError::MalformedQuery | ApiError::InvalidCardId => "INVALID_PARAMETERS",
+            ApiError::InvalidParameter(ParamError::Missing(_)) => "MISSING_PARAMETER",
+            ApiError::InvalidParameter(ParamError::OddLength(_)) => "ODD_LENGTH_PARAMETER",
+            ApiError::InvalidParameter(ParamError::TooShort(_)) => "PARAMETER_TOO_SHORT",
+            ApiError::InvalidParameter(ParamError::TooLong(_)) => "PARAMETER_TOO_LONG",
+            ApiError::InvalidParameter(ParamError::NotHex(_)) => "INVALID_HEX",
+            ApiError::AuthenticationFailed(AuthError::InvalidK1Key | AuthError::InvalidK2Key) => "INVALID_CARD_KEY",
+            ApiError::AuthenticationFailed(_) => "AUTHENTICATION_FAILED",
+            ApiError::UidMismatch => "UID_MISMATCH",
+            ApiError::CounterExhausted => "COUNTER_EXHAUSTED",
+            ApiError::ReplayDetected | ApiError::CounterUpdateFailed => "REPLAY_DETECTED",
+            ApiError::InvalidK1 => "INVALID_K1",
+            ApiError::SessionExpired => "SESSION_EXPIRED",
+            ApiError::AttemptsExhausted => "ATTEMPTS_EXHAUSTED",
+            ApiError::PaymentAlreadyProcessed => "PAYMENT_ALREADY_PROCESSED",
+            ApiError::InvalidInvoice => "INVALID_INVOICE",
+            ApiError::InvoiceWithoutAmount => "INVOICE_WITHOUT_AMOUNT",
+            ApiError::WrongNetwork => "WRONG_NETWORK",
+            ApiError::AmountOutOfRange => "AMOUNT_OUT_OF_RANGE",
+            ApiError::OnchainNotAllowed => "ONCHAIN_NOT_ALLOWED",
+            ApiError::InvalidAddress => "INVALID_ADDRESS",
+            ApiError::DescriptionMismatch => "DESCRIPTION_MISMATCH",
+            ApiError::TxLimitExceeded => "TX_LIMIT_EXCEEDED",
+            ApiError::DailyLimitExceeded => "DAILY_LIMIT_EXCEEDED",
+            ApiError::InsufficientBalance => "INSUFFICIENT_BALANCE",
+            ApiError::CapitalExhausted => "CAPITAL_EXHAUSTED",
+            ApiError::PolicyDenied(_) => "POLICY_DENIED",
+            ApiError::PaymentFailed(_) => "PAYMENT_FAILED",
+            ApiError::PaymentPending => "PAYMENT_PENDING",
+            ApiError::LoginExpired => "LOGIN_EXPIRED",
+            ApiError::InvalidSignature => "INVALID_SIGNATURE",
+        }
+    }
+
+    /// English reason, also the key of its translations
+    pub fn reason(&self) -> &str {
+        match self {
+            ApiError::Database => "Database error",
+            ApiError::CardNotFound => "Card not found or disabled",
+            ApiError::CardFrozen { until: None } => "Card is frozen",
+            ApiError::CardFrozen { until: Some(_) } => "Card is frozen until {until}",
+            ApiError::CardUnderReview => "Card is under review, contact the issuer",
+            ApiError::MalformedQuery => "Malformed query string",
+            ApiError::InvalidCardId => "Invalid card_id parameter",
+            ApiError::InvalidParameter(error) => error.as_str(),
+            ApiError::AuthenticationFailed(error) => error.as_str(),
+            ApiError::UidMismatch => "UID mismatch",
+            ApiError::CounterExhausted => "Card counter exhausted - card needs replacement",
+            ApiError::ReplayDetected => "Invalid counter - possible replay attack",
+            ApiError::CounterUpdateFailed => "Counter update failed",
+            ApiError::InvalidK1 => "Invalid k1",
+            ApiError::SessionExpired => "Withdrawal session expired, tap the card again",
+            ApiError::AttemptsExhausted => "Too many failed attempts, tap the card again",
+            ApiError::PaymentAlreadyProcessed => "Payment already processed",
+            ApiError::InvalidInvoice => "Invalid invoice",
+            ApiError::InvoiceWithoutAmount => "Invoice must have amount",
+            ApiError::WrongNetwork => "Invoice is for another network",
+            ApiError::AmountOutOfRange => "Amount outside the withdrawable range",
+            ApiError::OnchainNotAllowed => "On-chain withdrawal not available for this amount",
+            ApiError::InvalidAddress => "Invalid on-chain address",
+            ApiError::DescriptionMismatch => "Invoice description doesn't match the withdrawal",
+            ApiError::TxLimitExceeded => "Amount exceeds transaction limit",
+            ApiError::DailyLimitExceeded => "Amount exceeds daily limit",
+            ApiError::InsufficientBalance => "Insufficient card balance",
+            ApiError::CapitalExhausted => "Withdrawals are paused, try again later",
+            ApiError::PolicyDenied(reason) => reason.as_deref().unwrap_or("Withdrawal declined"),
+            ApiError::PaymentFailed(reason) => reason,
+            ApiError::PaymentPending => "Payment is pending, check back later",
+            ApiError::LoginExpired => "Login expired, scan the code again",
+            ApiError::InvalidSignature => "Invalid signature",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::Database => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// The reason translated into `locale`, with the time a frozen card is frozen until
+    pub fn localized_reason(&self, locale: Locale) -> String {
+        match self {
+            ApiError::CardFrozen { until: Some(until) } => locale.trf(self.reason(), &[("until", until)]),
+            _ => locale.tr(self.reason()).to_string(),
+        }
+    }
+
+    /// Answer with the reason translated into `locale`
+    pub fn localize(self, locale: Locale) -> LocalizedApiError {
+        LocalizedApiError { error: self, locale, status_ok: false }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.localized_reason(Locale::En))
+    }
+}
+
+/// Lets handlers use `?` on database queries
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        tracing::error!("Database error: {}", e);
+        ApiError::Database
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        self.localize(Locale::En).into_response()
+    }
+}
+
+/// An [`ApiError`] together with the language to answer in
+#[derive(Debug)]
+pub struct LocalizedApiError {
+    error: ApiError,
+    locale: Locale,
+    /// Answer with HTTP 200, for wallets that ignore the body of error statuses
+    status_ok: bool,
+}
+
+impl LocalizedApiError {
+    pub fn status_ok(mut self, status_ok: bool) -> Self {
+        self.status_ok = status_ok;
+        self
+    }
+}
+
+/// Body of LNURL error responses
+#[derive(Debug, Serialize)]
+pub struct LnurlwError {
+    pub status: String,
+    pub code: &'static str,
+    pub reason: String,
+}
+
+impl IntoResponse for LocalizedApiError {
+    fn into_response(self) -> Response {
+        let body = LnurlwError {
+            status: "ERROR".to_string(),
+            code: self.error.code(),
+            reason: self.error.localized_reason(self.locale),
+        };
+        let status = if self.status_ok { StatusCode::OK } else { self.error.status() };
+        (status, Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validation_errors_get_codes() {
+        use crate::validation::{TapParam, decode_params};
+
+        let param_error = |p: &str, c: &str| ApiError::InvalidParameter(decode_params(p, c).unwrap_err());
+        assert_eq!(param_error(&"x".repeat(32), "E19CCB1FED8892CE").code(), "INVALID_HEX");
+        assert_eq!(param_error("4E2", "E19CCB1FED8892CE").code(), "ODD_LENGTH_PARAMETER");
+        assert_eq!(param_error(&"00".repeat(17), "E19CCB1FED8892CE").code(), "PARAMETER_TOO_LONG");
+        assert_eq!(param_error(&"00".repeat(16), "").code(), "MISSING_PARAMETER");
+        assert_eq!(ApiError::InvalidParameter(ParamError::TooShort(TapParam::C)).reason(), "c parameter too short");
+
+        assert_eq!(ApiError::AuthenticationFailed(AuthError::InvalidK2Key).code(), "INVALID_CARD_KEY");
+        assert_eq!(ApiError::AuthenticationFailed(AuthError::InvalidCmac).code(), "AUTHENTICATION_FAILED");
+        assert_eq!(
+            ApiError::AuthenticationFailed(AuthError::InvalidCmac).localized_reason(Locale::De),
+            "Ungültiger CMAC - Kartenauthentifizierung fehlgeschlagen"
+        );
+    }
+
+    #[test]
+    fn test_status_ok() {
+        let response = ApiError::InvalidK1.localize(Locale::En).into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = ApiError::InvalidK1.localize(Locale::En).status_ok(true).into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_frozen_until() {
+        let error = ApiError::CardFrozen { until: Some("2026-11-01 08:00:00 UTC".to_string()) };
+        assert_eq!(error.to_string(), "Card is frozen until 2026-11-01 08:00:00 UTC");
+        assert_eq!(error.localized_reason(Locale::De), "Karte ist gesperrt bis 2026-11-01 08:00:00 UTC");
+    }
+}
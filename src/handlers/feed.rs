@@ -0,0 +1,105 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    app_state::AppState,
+    db::{feed, queries},
+    feed::{self as activity, FeedFormat},
+};
+
+/// Most entries a feed can be asked for
+const MAX_ENTRIES: i64 = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct FeedQuery {
+    /// The feed token, for readers that can't send an Authorization header
+    pub token: Option<String>,
+    pub format: Option<FeedFormat>,
+    pub limit: Option<i64>,
+}
+
+/// `404` unless `--feed-token` is set, `401` unless the request carries it as query parameter or bearer token
+fn authorize(state: &AppState, params: &FeedQuery, headers: &HeaderMap) -> Result<(), StatusCode> {
+    let expected = state.config.feed_token.as_deref().ok_or(StatusCode::NOT_FOUND)?;
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim);
+    let given = params.token.as_deref().or(bearer).ok_or(StatusCode::UNAUTHORIZED)?;
+
+    // Digests are compared so the time taken doesn't depend on how much of the token is right
+    if Sha256::digest(given) == Sha256::digest(expected) {
+        Ok(())
+    } else {
+        Err(StatusCode::UNAUTHORIZED)
+    }
+}
+
+async fn render(state: &AppState, card_id: Option<i64>, title: &str, path: &str, params: &FeedQuery) -> Response {
+    let limit = params.limit.unwrap_or(50).clamp(1, MAX_ENTRIES);
+    let loaded = async {
+        anyhow::Ok((
+            feed::payments(&state.pool, card_id, limit).await?,
+            feed::notifications(&state.pool, card_id, limit).await?,
+            feed::audit_entries(&state.pool, card_id, limit).await?,
+        ))
+    }
+    .await;
+    let (payments, notifications, audit_log) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            tracing::error!("Failed to load the activity feed: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let entries = activity::entries(&payments, &notifications, &audit_log, limit as usize);
+    let url = state.config.urls().url(path);
+    let (content_type, body) = match params.format.unwrap_or(FeedFormat::Atom) {
+        FeedFormat::Atom => ("application/atom+xml; charset=utf-8", activity::atom(title, &url, &entries)),
+        FeedFormat::Rss => ("application/rss+xml; charset=utf-8", activity::rss(title, &url, &entries)),
+    };
+
+    ([(header::CONTENT_TYPE, content_type)], body).into_response()
+}
+
+/// GET /v1/feed?token={token}&format={atom|rss}&limit={n}
+/// Activity of all cards
+pub async fn all_cards(Query(params): Query<FeedQuery>, headers: HeaderMap, State(state): State<AppState>) -> Response {
+    if let Err(status) = authorize(&state, &params, &headers) {
+        return status.into_response();
+    }
+
+    render(&state, None, "Card activity", "/v1/feed", &params).await
+}
+
+/// GET /v1/cards/{card_id}/feed?token={token}&format={atom|rss}&limit={n}
+/// Activity of one card
+pub async fn card(
+    Path(card_id): Path<i64>,
+    Query(params): Query<FeedQuery>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Response {
+    if let Err(status) = authorize(&state, &params, &headers) {
+        return status.into_response();
+    }
+
+    let card = match queries::get_card_by_id(&state.pool, card_id).await {
+        Ok(Some(card)) => card,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            tracing::error!("Failed to load card {} for its feed: {}", card_id, e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let title = format!("Activity of card {} (#{})", card.card_name, card_id);
+    render(&state, Some(card_id), &title, &format!("/v1/cards/{}/feed", card_id), &params).await
+}
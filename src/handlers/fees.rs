@@ -0,0 +1,111 @@
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    db::fees::{self, AccruedFees, FeePayout},
+    fees::ServiceFee,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct FeesQuery {
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CardFeeRequest {
+    #[serde(default)]
+    pub flat_msats: i64,
+    #[serde(default)]
+    pub percent: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CardFee {
+    pub card_id: i64,
+    /// Whether the card has a fee of its own, otherwise it's charged the server's
+    pub own: bool,
+    pub flat_msats: i64,
+    pub percent: f64,
+}
+
+impl CardFee {
+    fn new(card_id: i64, card_fee: Option<ServiceFee>, server_fee: ServiceFee) -> Self {
+        let fee = card_fee.unwrap_or(server_fee);
+        Self { card_id, own: card_fee.is_some(), flat_msats: fee.flat_msats, percent: fee.percent }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeesResponse {
+    pub accrued: Vec<AccruedFees>,
+    pub payouts: Vec<FeePayout>,
+}
+
+/// GET /v1/fees?limit={n}
+/// Service fees accrued on each node and the most recent payouts
+pub async fn list_fees(Query(params): Query<FeesQuery>, State(state): State<AppState>) -> Response {
+    let loaded = async {
+        anyhow::Ok(FeesResponse {
+            accrued: fees::accrued(&state.pool).await?,
+            payouts: fees::list_payouts(&state.pool, params.limit.unwrap_or(20).clamp(1, 500)).await?,
+        })
+    }
+    .await;
+
+    match loaded {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to load service fees: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// GET /v1/cards/{card_id}/fee
+/// The service fee charged on the card's withdrawals
+pub async fn get_card_fee(Path(card_id): Path<i64>, State(state): State<AppState>) -> Result<Json<CardFee>, StatusCode> {
+    let card_fee = fees::get_card_fee(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(CardFee::new(card_id, card_fee, ServiceFee::from_config(&state.config))))
+}
+
+/// PUT /v1/cards/{card_id}/fee
+/// Charge the card a fee of its own, which its advertised maxWithdrawable leaves room for
+pub async fn set_card_fee(
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+    Json(req): Json<CardFeeRequest>,
+) -> Result<Json<CardFee>, StatusCode> {
+    if req.flat_msats < 0 || !(0.0..100.0).contains(&req.percent) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let fee = ServiceFee { flat_msats: req.flat_msats, percent: req.percent };
+
+    let updated = fees::set_card_fee(&state.pool, card_id, Some(fee), "api")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    match updated {
+        true => Ok(Json(CardFee::new(card_id, Some(fee), ServiceFee::from_config(&state.config)))),
+        false => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+/// DELETE /v1/cards/{card_id}/fee
+/// Charge the card the server's fee again
+pub async fn clear_card_fee(Path(card_id): Path<i64>, State(state): State<AppState>) -> StatusCode {
+    match fees::set_card_fee(&state.pool, card_id, None, "api").await {
+        Ok(true) => StatusCode::NO_CONTENT,
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
@@ -0,0 +1,81 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    db::{freeze, queries},
+};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct FreezeRequest {
+    /// RFC 3339 time the card thaws at, frozen until unfrozen if missing
+    until: Option<DateTime<Utc>>,
+    reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FreezeResponse {
+    pub status: String,
+    pub card_id: i64,
+    pub frozen_at: String,
+    pub frozen_until: Option<String>,
+    pub reason: Option<String>,
+}
+
+/// POST /v1/cards/{card_id}/freeze
+/// Refuse the card's taps until the given time or until it is unfrozen, e.g. while its holder travels
+///
+/// Unlike a disable the card thaws by itself and taps tell the holder until when it is frozen.
+pub async fn freeze_card(
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+    req: Option<Json<FreezeRequest>>,
+) -> Result<Json<FreezeResponse>, StatusCode> {
+    let req = req.map(|Json(req)| req).unwrap_or_default();
+    let now = state.clock.now();
+    if req.until.is_some_and(|until| until <= now) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let reason = req.reason.map(|r| r.trim().to_string()).filter(|r| !r.is_empty());
+
+    let frozen = freeze::freeze(&state.pool, card_id, req.until, reason.as_deref(), now, "api")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    tracing::info!(
+        "Card {} frozen until {}",
+        card_id,
+        frozen.frozen_until.as_deref().unwrap_or("unfrozen")
+    );
+
+    Ok(Json(FreezeResponse {
+        status: "OK".to_string(),
+        card_id,
+        frozen_at: frozen.frozen_at,
+        frozen_until: frozen.frozen_until,
+        reason: frozen.reason,
+    }))
+}
+
+/// DELETE /v1/cards/{card_id}/freeze
+/// Lift the card's freeze before its time
+pub async fn unfreeze_card(Path(card_id): Path<i64>, State(state): State<AppState>) -> Result<StatusCode, StatusCode> {
+    queries::get_card_by_id(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let unfrozen = freeze::unfreeze(&state.pool, card_id, "api")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if unfrozen {
+        tracing::info!("Card {} unfrozen", card_id);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,14 @@
+use axum::{extract::State, http::StatusCode, Json};
+
+use crate::app_state::AppState;
+
+/// POST /v1/graphql
+/// Run a GraphQL query against cards, payments, taps and stats, `404` unless `--graphql` is set
+pub async fn execute(
+    State(state): State<AppState>,
+    Json(request): Json<async_graphql::Request>,
+) -> Result<Json<async_graphql::Response>, StatusCode> {
+    let schema = state.graphql.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(schema.execute(request).await))
+}
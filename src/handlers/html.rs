@@ -0,0 +1,119 @@
+use axum::response::Html;
+use qrcode::{render::svg, QrCode};
+
+use crate::{config::Config, i18n::Locale};
+
+/// Escape text for safe inclusion in HTML element content and attributes
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+/// Render `data` as an inline SVG QR code
+pub fn qr_svg(data: &str) -> Option<String> {
+    let code = QrCode::new(data.as_bytes()).ok()?;
+    let svg = code
+        .render::<svg::Color>()
+        .min_dimensions(240, 240)
+        .build();
+
+    // Drop the XML prolog, it isn't valid inside an HTML document
+    let start = svg.find("<svg")?;
+    Some(svg[start..].to_string())
+}
+
+/// Operator branding: colors, an optional theme.css override and the brand name in titles
+pub fn brand_head(config: &Config, title: &str) -> String {
+    let title = match &config.brand_name {
+        Some(brand) => format!("{} · {}", escape(title), escape(brand)),
+        None => escape(title),
+    };
+
+    let mut head = format!(
+        "<title>{}</title>\n<style>:root {{ --brand: {}; --brand-background: {}; }}</style>",
+        title,
+        escape(&config.brand_color),
+        escape(&config.brand_background),
+    );
+
+    if config.static_dir.is_some() {
+        head.push_str(&format!("\n<link rel=\"stylesheet\" href=\"{}/static/theme.css\">", config.public.base_path));
+    }
+
+    head
+}
+
+/// Header with the operator's logo and brand name, empty if neither is configured
+fn brand_header(config: &Config) -> String {
+    let logo = config
+        .brand_logo_url
+        .as_ref()
+        .map(|url| {
+            format!(
+                "<img src=\"{}\" alt=\"{}\">",
+                escape(url),
+                escape(config.brand_name.as_deref().unwrap_or(""))
+            )
+        })
+        .unwrap_or_default();
+    let name = config
+        .brand_name
+        .as_ref()
+        .map(|name| format!("<span>{}</span>", escape(name)))
+        .unwrap_or_default();
+
+    if logo.is_empty() && name.is_empty() {
+        return String::new();
+    }
+
+    format!("<header class=\"brand\">{}{}</header>\n", logo, name)
+}
+
+/// Wrap a page body in the shared HTML layout
+pub fn page(config: &Config, title: &str, body: &str) -> Html<String> {
+    localized_page(config, Locale::En, title, body)
+}
+
+/// Wrap a page body in the shared HTML layout, translating the title
+pub fn localized_page(config: &Config, locale: Locale, title: &str, body: &str) -> Html<String> {
+    // The theme.css link comes after the built-in styles so it can override them
+    let head = brand_head(config, locale.tr(title));
+
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="{lang}">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<style>
+body {{ font-family: sans-serif; max-width: 40rem; margin: 2rem auto; padding: 0 1rem; color: #222; background: var(--brand-background); }}
+h1, h2, a {{ color: var(--brand); }}
+button {{ background: var(--brand); color: var(--brand-background); border: none; padding: 0.4rem 0.8rem; border-radius: 0.2rem; }}
+header.brand {{ display: flex; align-items: center; gap: 0.6rem; font-weight: bold; margin-bottom: 1.5rem; }}
+header.brand img {{ max-height: 2.5rem; }}
+table {{ border-collapse: collapse; width: 100%; }}
+th, td {{ text-align: left; padding: 0.4rem; border-bottom: 1px solid #ddd; }}
+code {{ word-break: break-all; font-size: 0.85rem; }}
+</style>
+{head}
+</head>
+<body>
+{header}{body}
+</body>
+</html>"#,
+        lang = locale.code(),
+        head = head,
+        header = brand_header(config),
+        body = body,
+    ))
+}
@@ -0,0 +1,72 @@
+use axum::{
+    extract::{Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::{
+    app_state::AppState,
+    db,
+    ledger::{self, LedgerAccounts, LedgerFormat},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct LedgerQuery {
+    pub format: Option<LedgerFormat>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub node_account: Option<String>,
+    pub card_account: Option<String>,
+    pub fee_account: Option<String>,
+    pub service_fee_account: Option<String>,
+}
+
+/// GET /v1/ledger?format={beancount|ledger}&from={YYYY-MM-DD}&to={YYYY-MM-DD}
+/// Settled withdrawals as a plaintext accounting journal, the last 30 days by default
+pub async fn export(Query(params): Query<LedgerQuery>, State(state): State<AppState>) -> Response {
+    let to = params.to.unwrap_or_else(|| chrono::Utc::now().date_naive());
+    let from = params.from.unwrap_or(to - chrono::Duration::days(29));
+    if from > to {
+        return (StatusCode::BAD_REQUEST, "from must not be after to").into_response();
+    }
+
+    let defaults = LedgerAccounts::default();
+    let accounts = LedgerAccounts {
+        node: params.node_account.unwrap_or(defaults.node),
+        cards: params.card_account.unwrap_or(defaults.cards),
+        fees: params.fee_account.unwrap_or(defaults.fees),
+        service_fees: params.service_fee_account.unwrap_or(defaults.service_fees),
+    };
+    if let Some(account) = accounts.invalid() {
+        return (StatusCode::BAD_REQUEST, format!("Invalid account name {:?}", account)).into_response();
+    }
+
+    let payments = match db::ledger::settled_payments(&state.pool, from, to).await {
+        Ok(payments) => payments,
+        Err(e) => {
+            tracing::error!("Failed to load payments for the ledger: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let format = params.format.unwrap_or(LedgerFormat::Beancount);
+    let journal = ledger::render(format, &accounts, from, to, &payments);
+    let extension = match format {
+        LedgerFormat::Beancount => "beancount",
+        LedgerFormat::Ledger => "ledger",
+    };
+
+    (
+        [
+            (header::CONTENT_TYPE, "text/plain; charset=utf-8".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("inline; filename=\"withdrawals-{}-{}.{}\"", from, to, extension),
+            ),
+        ],
+        journal,
+    )
+        .into_response()
+}
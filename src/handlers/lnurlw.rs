@@ -1,24 +1,57 @@
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
+    extract::{rejection::QueryRejection, Query, State},
     Json,
 };
 use serde::{Deserialize, Serialize};
 use anyhow::Result;
+use std::{collections::HashSet, sync::Arc};
 
 use crate::{
     app_state::AppState,
-    db::queries,
-    validation::validate_card_pure,
+    crypto::Counter,
+    description,
+    db::{accounts, clones, descriptions, fees, freeze, memos, metadata, onchain, organizations, queries, rotation, tags, taps},
+    fees::ServiceFee,
+    i18n::Locale,
+    db::models::{Card, CardPayment, PaymentStatus},
+    handlers::error::{ApiError, LocalizedApiError},
+    lightning::{self, Invoice, OutgoingPayment, PaymentResult},
+    jobs::Job,
+    key_cache::Candidate,
+    notifications::{self, Event},
+    plugin::{self, Stage},
+    rates,
+    reconcile,
+    tenant::Tenant,
+    validation::{AuthError, CardCheckError, decode_params, pure::ValidationResult, validate_card_pure},
 };
 
+/// Cards tried per blocking task when a tap without card ID needs a full scan
+const SCAN_CHUNK_CARDS: usize = 256;
+
+/// Query of a tap; parameters some wallets append are ignored
+///
+/// Everything is taken as a string, so a malformed value is answered with a
+/// precise LNURL error instead of a plain deserialization rejection.
 #[derive(Debug, Deserialize)]
 pub struct LnurlwParams {
-    card_id: i64,  // card ID for direct lookup
+    card_id: Option<String>,  // card ID for direct lookup, otherwise the card is searched
+    #[serde(default)]
     p: String,  // encrypted UID + counter
+    #[serde(default)]
     c: String,  // CMAC
 }
 
+impl LnurlwParams {
+    /// The card ID, where an empty value counts as missing
+    fn card_id(&self) -> Result<Option<i64>, ApiError> {
+        match self.card_id.as_deref() {
+            None | Some("") => Ok(None),
+            Some(id) => id.parse().map(Some).map_err(|_| ApiError::InvalidCardId),
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LnurlwResponse {
@@ -31,98 +64,186 @@ pub struct LnurlwResponse {
     pub tag: String,
 }
 
-#[derive(Debug, Serialize)]
-pub struct LnurlwError {
-    pub status: String,
-    pub reason: String,
-}
-
 /// GET /ln?card_id={id}&p={encrypted}&c={cmac}
-/// LNURLw endpoint that validates card and returns withdrawal info
+/// LNURLw endpoint that validates card and returns withdrawal info;
+/// without `card_id` the card is found by trying the keys of all cards
+/// served on the request's domain
 pub async fn lnurlw_request(
-    Query(params): Query<LnurlwParams>,
+    locale: Locale,
+    tenant: Tenant,
+    params: Result<Query<LnurlwParams>, QueryRejection>,
     State(state): State<AppState>,
-) -> Result<Json<LnurlwResponse>, (StatusCode, Json<LnurlwError>)> {
-    // Look up the specific card by ID
-    let card = sqlx::query_as::<_, crate::db::models::Card>(
-        "SELECT * FROM cards WHERE card_id = ? AND enabled = 1"
-    )
-    .bind(params.card_id)
-    .fetch_optional(&state.pool)
-    .await
-    .map_err(|_| error_response("Database error"))?
-    .ok_or_else(|| error_response("Card not found or disabled"))?;
-
-    // Validate the card using pure validation function
-    let validation_result = validate_card_pure(
-        &card.k1_decrypt_key,
-        &card.k2_cmac_key,
-        &params.p,
-        &params.c,
-    );
-
-    let (uid, counter) = match validation_result {
-        Ok(result) => (result.uid, result.counter),
-        Err(msg) => return Err(error_response(&msg)),
-    };
-
-    // Update UID if not set
-    if card.uid.is_empty() {
-        sqlx::query("UPDATE cards SET uid = ? WHERE card_id = ?")
-            .bind(uid.to_string())
-            .bind(card.card_id)
-            .execute(&state.pool)
-            .await
-            .map_err(|_| error_response("Database error"))?;
-    } else if card.uid != uid.to_string() {
-        return Err(error_response("UID mismatch"));
-    }
+) -> Result<Json<LnurlwResponse>, LocalizedApiError> {
+    let result = match params {
+        Ok(Query(params)) => handle_tap(&state, &tenant, &params).await,
+        // e.g. a repeated parameter
+        Err(_) => Err(ApiError::MalformedQuery),
+    };
+    result
+        .map(Json)
+        .map_err(|e| e.localize(locale).status_ok(state.config.lnurl_errors_with_200))
+}
 
-    // Check and update counter (replay protection)
-    if counter.value() as i64 <= card.last_counter {
-        return Err(error_response("Invalid counter - possible replay attack"));
-    }
+async fn handle_tap(state: &AppState, tenant: &Tenant, params: &LnurlwParams) -> Result<LnurlwResponse, ApiError> {
+    let tap = decode_params(&params.p, &params.c).map_err(ApiError::InvalidParameter);
+    state.key_cache.refresh(&state.pool).await?;
+    let (card, validation_result) = match params.card_id()? {
+        Some(card_id) => {
+            // Look up the specific card by ID
+            let card = queries::get_enabled_card_by_id(&state.pool, card_id)
+                .await?
+                .ok_or(ApiError::CardNotFound)?;
+            if !tenant.serves_card(state, card_id).await? {
+                return Err(ApiError::CardNotFound);
+            }
 
-    let updated = queries::update_card_counter(&state.pool, card.card_id, counter.value() as i64)
-        .await
-        .map_err(|_| error_response("Database error"))?;
+            let result = tap.and_then(|(p, c)| validate_tap(state, &card, &p, &c).map_err(ApiError::AuthenticationFailed));
+            (card, result)
+        }
+        None => {
+            // Without valid parameters no card can match
+            let (p, c) = tap?;
+            let (card, result) = find_card(state, tenant, &p, &c).await?.ok_or(ApiError::CardNotFound)?;
+            (card, Ok(result))
+        }
+    };
 
-    if !updated {
-        return Err(error_response("Counter update failed"));
-    }
+    let tap = match validation_result {
+        Ok(result) => {
+            // The card carries its new keys, so the ones from before a rotation are done
+            rotation::clear_previous_keys(&state.pool, card.card_id).await?;
+            result
+        }
+        Err(error) => {
+            // Within a rotation's grace window the card may not be reprogrammed yet
+            let previous_keys = rotation::get_previous_keys(&state.pool, card.card_id, state.clock.now()).await?;
+            let previous_result = previous_keys.and_then(|keys| {
+                validate_card_pure(&keys.k1_decrypt_key, &keys.k2_cmac_key, &params.p, &params.c).ok()
+            });
 
-    // Generate k1 for this withdrawal session
-    let withdrawal_k1 = hex::encode(rand::random::<[u8; 16]>());
+            match previous_result {
+                Some(result) => result,
+                None => {
+                    record_failed_tap(state, card.card_id, None, None, error.reason()).await;
+                    return Err(error);
+                }
+            }
+        }
+    };
 
-    // Create payment record
-    queries::create_payment(&state.pool, card.card_id, &withdrawal_k1)
-        .await
-        .map_err(|_| error_response("Database error"))?;
+    // An unused card takes the UID of its first tap, after that UID and counter must fit
+    let uid = tap.uid.to_string();
+    let counter = tap.counter.value() as i64;
+    if let Err(error) = state.validator.check_card(&card, &tap) {
+        let error = match error {
+            CardCheckError::UidMismatch => ApiError::UidMismatch,
+            CardCheckError::CounterExhausted => ApiError::CounterExhausted,
+            CardCheckError::Replay => ApiError::ReplayDetected,
+        };
+        record_failed_tap(state, card.card_id, Some(&uid), Some(counter), error.reason()).await;
+        return Err(error);
+    }
+    if let Err(error) = check_uid_unique(state, &card, &uid).await {
+        record_failed_tap(state, card.card_id, Some(&uid), Some(counter), error.reason()).await;
+        return Err(error);
+    }
+    if let Err(error) = check_not_under_review(state, card.card_id).await {
+        record_failed_tap(state, card.card_id, Some(&uid), Some(counter), error.reason()).await;
+        return Err(error);
+    }
+    if let Err(error) = check_not_frozen(state, card.card_id).await {
+        record_failed_tap(state, card.card_id, Some(&uid), Some(counter), &error.to_string()).await;
+        return Err(error);
+    }
 
     // Calculate actual withdrawable amount (respecting limits)
-    let daily_spent_msats = queries::get_daily_total_msats(&state.pool, card.card_id)
+    let daily_spent_msats = queries::get_daily_total_msats(&state.pool, card.card_id, state.clock.now())
         .await
         .unwrap_or(0);
     let daily_remaining_sats = (card.day_limit_sats * 1000 - daily_spent_msats) / 1000;
-    let max_withdrawable_sats = std::cmp::min(card.tx_limit_sats, daily_remaining_sats);
+    let mut max_withdrawable_sats = std::cmp::min(card.tx_limit_sats, daily_remaining_sats);
+    let card_fee = fees::get_card_fee(&state.pool, card.card_id).await?.flatten();
+    let service_fee = card_fee.unwrap_or_else(|| ServiceFee::from_config(&state.config));
+    // A card's own fee is a markup the limits have to leave room for, the server's only comes out of balances
+    if card_fee.is_some() {
+        max_withdrawable_sats = service_fee.affordable(max_withdrawable_sats * 1000) / 1000;
+    }
+    if card.balance_mode {
+        let affordable_msats = service_fee.affordable(card.balance_msats);
+        max_withdrawable_sats = std::cmp::min(max_withdrawable_sats, affordable_msats / 1000);
+    }
+    let min_withdrawable_msats = 1000;  // 1 sat in millisats
+    let mut max_withdrawable_msats = max_withdrawable_sats * 1000;
+
+    // Custom risk checks may refuse the tap or lower what's offered
+    if state.config.policy_plugin.is_some() {
+        let mut context = plugin_context(state, Stage::Tap, &card, daily_spent_msats).await?;
+        context.uid = uid.clone();
+        context.counter = Some(counter);
+        context.max_withdrawable_msats = max_withdrawable_msats;
+        match plugin::check(&state.config, &context).await {
+            Ok(cap) => max_withdrawable_msats = cap.map_or(max_withdrawable_msats, |cap| cap.min(max_withdrawable_msats)),
+            Err(error) => {
+                record_failed_tap(state, card.card_id, Some(&uid), Some(counter), error.reason()).await;
+                return Err(error);
+            }
+        }
+    }
+
+    // A lowered daily limit can leave less than nothing, which no wallet could withdraw
+    if max_withdrawable_msats < min_withdrawable_msats {
+        let error = if daily_remaining_sats < 1 {
+            ApiError::DailyLimitExceeded
+        } else if card.balance_mode && max_withdrawable_sats < 1 {
+            ApiError::InsufficientBalance
+        } else {
+            ApiError::AmountOutOfRange
+        };
+        record_failed_tap(state, card.card_id, Some(&uid), Some(counter), error.reason()).await;
+        return Err(error);
+    }
+
+    // Generate k1 for this withdrawal session
+    let withdrawal_k1 = state.random.hex::<16>()?;
+    let default_description = withdraw_description(state, tenant, &card, max_withdrawable_msats / 1000).await?;
+
+    // UID, counter, tap history and payment record are written together or not at all
+    queries::accept_tap(
+        &state.pool,
+        card.card_id,
+        &uid,
+        counter,
+        &withdrawal_k1,
+        min_withdrawable_msats,
+        max_withdrawable_msats,
+        &default_description,
+        state.config.max_open_sessions,
+    )
+    .await?
+    .ok_or(ApiError::CounterUpdateFailed)?;
+    state.key_cache.touch(card.card_id);
+    warn_about_counter(state, &card, &tap).await;
 
     let response = LnurlwResponse {
         status: "OK".to_string(),
-        callback: format!("https://{}/ln/callback", state.config.domain),
+        // The wallet calls back on the domain it tapped on
+        callback: state.config.urls_on(tenant.domain()).callback_url(),
         k1: withdrawal_k1,
-        default_description: format!("Withdrawal from {}", card.card_name),
-        min_withdrawable: 1000,  // 1 sat in millisats
-        max_withdrawable: (max_withdrawable_sats * 1000) as u64,  // Convert to millisats
+        default_description,
+        min_withdrawable: min_withdrawable_msats as u64,
+        max_withdrawable: max_withdrawable_msats as u64,
         tag: "withdrawRequest".to_string(),
     };
 
-    Ok(Json(response))
+    Ok(response)
 }
 
 #[derive(Debug, Deserialize)]
 pub struct CallbackParams {
     k1: String,
-    pr: String,  // Lightning invoice
+    pr: Option<String>,  // Lightning invoice
+    address: Option<String>,  // on-chain address, for withdrawals eligible for on-chain payouts
+    amount: Option<u64>,  // msats to pay to `address`
 }
 
 #[derive(Debug, Serialize)]
@@ -131,83 +252,1106 @@ pub struct CallbackResponse {
 }
 
 /// GET /ln/callback?k1={k1}&pr={invoice}
-/// Process withdrawal with Lightning invoice
+/// Process withdrawal with Lightning invoice, or `address={address}&amount={msats}` to be paid on-chain
 pub async fn lnurlw_callback(
+    locale: Locale,
+    tenant: Tenant,
     Query(params): Query<CallbackParams>,
     State(state): State<AppState>,
-) -> Result<Json<CallbackResponse>, (StatusCode, Json<LnurlwError>)> {
+) -> Result<Json<CallbackResponse>, LocalizedApiError> {
+    handle_callback(&state, &tenant, locale, &params)
+        .await
+        .map(Json)
+        .map_err(|e| e.localize(locale).status_ok(state.config.lnurl_errors_with_200))
+}
+
+async fn handle_callback(
+    state: &AppState,
+    tenant: &Tenant,
+    locale: Locale,
+    params: &CallbackParams,
+) -> Result<CallbackResponse, ApiError> {
     use std::str::FromStr;
 
     // Get payment record by k1
     let payment = queries::get_payment_by_k1(&state.pool, &params.k1)
-        .await
-        .map_err(|_| error_response("Database error"))?
-        .ok_or_else(|| error_response("Invalid k1"))?;
+        .await?
+        .ok_or(ApiError::InvalidK1)?;
+    if !tenant.serves_card(state, payment.card_id).await? {
+        return Err(ApiError::InvalidK1);
+    }
+
+    match payment.status {
+        PaymentStatus::Settled => return Err(ApiError::PaymentAlreadyProcessed),
+        PaymentStatus::Expired => return Err(ApiError::SessionExpired),
+        _ => {}
+    }
+
+    // A failed payment may be retried with a new invoice, but not forever
+    let opened_after = state.clock.now() - chrono::Duration::seconds(state.config.withdraw_session_ttl_secs);
+    let opened_at = payment
+        .created_at
+        .as_deref()
+        .and_then(|t| chrono::NaiveDateTime::parse_from_str(t, "%Y-%m-%d %H:%M:%S").ok());
+    if payment.attempts > 0 && opened_at.is_some_and(|t| t.and_utc() < opened_after) {
+        queries::expire_payment(&state.pool, payment.payment_id).await?;
+        return Err(ApiError::SessionExpired);
+    }
+    if payment.attempts >= state.config.max_payment_attempts {
+        return Err(ApiError::AttemptsExhausted);
+    }
 
-    if payment.paid.unwrap_or(false) {
-        return Err(error_response("Payment already processed"));
+    // Parse and validate invoice, an on-chain withdrawal names its amount instead
+    let invoice = match (&params.pr, &params.address) {
+        (Some(pr), _) => Some(Invoice::from_str(pr).map_err(|_| ApiError::InvalidInvoice)?),
+        (None, Some(_)) => None,
+        // Paid to the card's own fallback address, see `onchain_destination`
+        (None, None) if params.amount.is_some() => None,
+        (None, None) => return Err(ApiError::InvalidInvoice),
+    };
+    if let Some(invoice) = &invoice
+        && let Some(network) = state.config.network
+        && invoice.network() != Some(network)
+    {
+        return Err(ApiError::WrongNetwork);
     }
 
-    // Parse and validate invoice
-    let invoice = crate::lightning::Invoice::from_str(&params.pr)
-        .map_err(|_| error_response("Invalid invoice"))?;
+    let amount_msats = match &invoice {
+        Some(invoice) => invoice.amount_msats().map_err(|_| ApiError::InvoiceWithoutAmount)?,
+        // On-chain payouts are whole sats
+        None => params.amount.filter(|amount| amount % 1000 == 0).ok_or(ApiError::AmountOutOfRange)?,
+    };
 
-    let amount_msats = invoice.amount_msats()
-        .map_err(|_| error_response("Invoice must have amount"))?;
+    // The wallet may only ask for what it was offered with this k1
+    if let (Some(min), Some(max)) = (payment.min_withdrawable_msats, payment.max_withdrawable_msats)
+        && !(min..=max).contains(&(amount_msats as i64))
+    {
+        return Err(ApiError::AmountOutOfRange);
+    }
 
     // Get card to check limits
-    let card = sqlx::query_as::<_, crate::db::models::Card>(
-        "SELECT * FROM cards WHERE card_id = ?"
+    let card = queries::get_card_by_id(&state.pool, payment.card_id)
+        .await?
+        .ok_or(ApiError::Database)?;
+    // Disabled or reported lost since the tap
+    if !card.enabled {
+        return Err(ApiError::CardNotFound);
+    }
+    // The card may have been frozen or flagged as a clone since the tap
+    check_not_under_review(state, card.card_id).await?;
+    check_not_frozen(state, card.card_id).await?;
+
+    let onchain_address = onchain_destination(
+        state,
+        &payment,
+        &card,
+        amount_msats as i64,
+        params.address.as_deref(),
+        invoice.is_some(),
     )
-    .bind(payment.card_id)
-    .fetch_one(&state.pool)
-    .await
-    .map_err(|_| error_response("Database error"))?;
+    .await?;
+    let payout = match (onchain_address.as_deref(), &invoice) {
+        (Some(address), _) => Payout::Onchain(address),
+        (None, Some(invoice)) => Payout::Invoice(invoice),
+        (None, None) => return Err(ApiError::OnchainNotAllowed),
+    };
+
+    // Keeps a leaked callback URL from being used to pay unrelated invoices
+    if state.config.require_invoice_description
+        && let Some(invoice) = &invoice
+    {
+        let offered = match descriptions::get_payment_description(&state.pool, payment.payment_id).await? {
+            Some(offered) => offered,
+            None => {
+                let remaining_sats = payment.max_withdrawable_msats.unwrap_or_default() / 1000;
+                withdraw_description(state, tenant, &card, remaining_sats).await?
+            }
+        };
+        if !invoice.description_matches(&offered) {
+            return Err(ApiError::DescriptionMismatch);
+        }
+    }
 
     // Check transaction limit
     if amount_msats > (card.tx_limit_sats * 1000) as u64 {
-        return Err(error_response("Amount exceeds transaction limit"));
+        return Err(ApiError::TxLimitExceeded);
     }
 
     // Check daily limit
-    let daily_spent_msats = queries::get_daily_total_msats(&state.pool, card.card_id)
+    let daily_spent_msats = queries::get_daily_total_msats(&state.pool, card.card_id, state.clock.now())
         .await
         .unwrap_or(0);
 
     if (daily_spent_msats + amount_msats as i64) > (card.day_limit_sats * 1000) {
-        return Err(error_response("Amount exceeds daily limit"));
+        return Err(ApiError::DailyLimitExceeded);
     }
 
-    // Update payment with invoice details
-    queries::update_payment_with_invoice(&state.pool, payment.payment_id, &params.pr, amount_msats as i64)
-        .await
-        .map_err(|_| error_response("Database error"))?;
+    if state.config.policy_plugin.is_some() {
+        let mut context = plugin_context(state, Stage::Withdraw, &card, daily_spent_msats).await?;
+        context.max_withdrawable_msats = payment.max_withdrawable_msats.unwrap_or(card.tx_limit_sats * 1000);
+        context.amount_msats = Some(amount_msats as i64);
+        context.payment_hash = invoice.as_ref().map(|invoice| invoice.payment_hash());
+        if let Some(cap) = plugin::check(&state.config, &context).await?
+            && amount_msats as i64 > cap
+        {
+            return Err(ApiError::AmountOutOfRange);
+        }
+    }
 
-    // Pay the invoice
-    let payment_result = state.lightning.pay_invoice(&invoice, amount_msats)
-        .await
-        .map_err(|e| error_response(&format!("Payment failed: {}", e)))?;
+    // Reserve the amount against the limits until the payment settles or fails
+    let reserved = match payout {
+        Payout::Invoice(invoice) => {
+            queries::reserve_payment(
+                &state.pool,
+                payment.payment_id,
+                params.pr.as_deref().unwrap_or_default(),
+                amount_msats as i64,
+                &invoice.payment_hash(),
+                state.config.max_payment_attempts,
+                opened_after,
+            )
+            .await?
+        }
+        Payout::Onchain(address) => {
+            onchain::reserve_payment(
+                &state.pool,
+                payment.payment_id,
+                address,
+                amount_msats as i64,
+                state.config.max_payment_attempts,
+                opened_after,
+            )
+            .await?
+        }
+    };
 
-    if !payment_result.success {
-        return Err(error_response(&payment_result.error.unwrap_or_else(|| "Payment failed".to_string())));
+    if !reserved {
+        return Err(ApiError::PaymentAlreadyProcessed);
+    }
+    if let Some(description) = invoice.as_ref().and_then(|invoice| invoice.description())
+        && let Err(e) = memos::capture_invoice_memo(&state.pool, payment.payment_id, &description).await
+    {
+        tracing::warn!("Failed to record memo of payment {}: {}", payment.payment_id, e);
+    }
+    let service_fee = fees::get_card_fee(&state.pool, card.card_id)
+        .await?
+        .flatten()
+        .unwrap_or_else(|| ServiceFee::from_config(&state.config));
+    let service_fee_msats = service_fee.for_amount(amount_msats as i64);
+    if service_fee_msats > 0 {
+        fees::set_payment_fee(&state.pool, payment.payment_id, service_fee_msats).await?;
     }
 
-    // Mark payment as paid
-    queries::mark_payment_paid(&state.pool, payment.payment_id)
-        .await
-        .map_err(|_| error_response("Database error"))?;
+    // Checked after reserving, so concurrent withdrawals see each other's
+    // reservations and can't overspend the node's funded capital together
+    if state.config.enforce_funded_capital {
+        let org_id = organizations::get_card_org(&state.pool, card.card_id).await?;
+        if accounts::available_capital(&state.pool, org_id).await? < 0 {
+            let error = ApiError::CapitalExhausted;
+            tracing::warn!("Withdrawal from card {} refused, funded capital exhausted", card.card_id);
+            // Not the wallet's fault, so it doesn't use up one of the session's attempts
+            if let Err(e) = queries::mark_payment_refused(&state.pool, payment.payment_id, error.reason()).await {
+                tracing::error!("Failed to release payment {}: {}", payment.payment_id, e);
+            }
+            return Err(error);
+        }
+    }
+
+    // Reserve the amount on prepaid cards before paying, so concurrent
+    // withdrawals can't overdraw the balance
+    if card.balance_mode {
+        let debited = queries::debit_balance(&state.pool, card.card_id, amount_msats as i64 + service_fee_msats).await?;
+
+        if !debited {
+            let error = ApiError::InsufficientBalance;
+            release_payment(state, payment.payment_id, error.reason()).await;
+            return Err(error);
+        }
+    }
+
+    if state.config.async_payments {
+        let job = Job::ExecutePayment { payment_id: payment.payment_id, locale };
+        if let Err(e) = state.jobs.enqueue(&job).await {
+            tracing::error!("Failed to queue payment {}: {}", payment.payment_id, e);
+            if card.balance_mode
+                && let Err(e) = queries::credit_balance(&state.pool, card.card_id, amount_msats as i64 + service_fee_msats).await
+            {
+                tracing::error!("Failed to refund balance of card {}: {}", card.card_id, e);
+            }
+            let error = ApiError::Database;
+            release_payment(state, payment.payment_id, error.reason()).await;
+            return Err(error);
+        }
+
+        return Ok(CallbackResponse {
+            status: "OK".to_string(),
+        });
+    }
+
+    execute_payment(state, locale, &payment, &card, payout, amount_msats, service_fee_msats).await?;
 
-    Ok(Json(CallbackResponse {
+    Ok(CallbackResponse {
         status: "OK".to_string(),
-    }))
+    })
 }
 
-fn error_response(reason: &str) -> (StatusCode, Json<LnurlwError>) {
-    (
-        StatusCode::BAD_REQUEST,
-        Json(LnurlwError {
-            status: "ERROR".to_string(),
-            reason: reason.to_string(),
-        })
+/// What a reserved withdrawal is paid to
+#[derive(Debug, Clone, Copy)]
+enum Payout<'a> {
+    Invoice(&'a Invoice),
+    /// On-chain address, see `--onchain-fallback`
+    Onchain(&'a str),
+}
+
+/// The address a withdrawal is paid to on-chain instead of over Lightning, if any
+///
+/// Withdrawals of at least `--onchain-min-sats`, or whose session already had
+/// `--onchain-after-failures` invoices fail, go to the wallet's address, or
+/// without one to the card's fallback address. An invoice the wallet sent is
+/// only passed over for the card's address once Lightning failed that often.
+/// Other withdrawals can't name an address.
+async fn onchain_destination(
+    state: &AppState,
+    payment: &CardPayment,
+    card: &Card,
+    amount_msats: i64,
+    address: Option<&str>,
+    has_invoice: bool,
+) -> Result<Option<String>, ApiError> {
+    let lightning_failed = payment.attempts >= state.config.onchain_after_failures;
+    let eligible =
+        state.config.onchain_fallback && (amount_msats >= state.config.onchain_min_sats * 1000 || lightning_failed);
+
+    match address {
+        Some(_) if !eligible => Err(ApiError::OnchainNotAllowed),
+        Some(address) => lightning::parse_address(address, state.config.network)
+            .map(Some)
+            .map_err(|_| ApiError::InvalidAddress),
+        None if eligible && (!has_invoice || lightning_failed) => {
+            Ok(onchain::get_card_address(&state.pool, card.card_id).await?.flatten())
+        }
+        None => Ok(None),
+    }
+}
+
+/// Pay a reserved withdrawal and record the outcome
+///
+/// On failure the card's balance, including the service fee, is refunded and
+/// the reservation released. A call that errors may have paid all the same,
+/// so the node is asked how the payment ended; one it can't tell about stays
+/// in flight for the reconciliation, with neither settlement nor refund.
+async fn execute_payment(
+    state: &AppState,
+    locale: Locale,
+    payment: &CardPayment,
+    card: &Card,
+    payout: Payout<'_>,
+    amount_msats: u64,
+    service_fee_msats: i64,
+) -> Result<(), ApiError> {
+    if !queries::mark_payment_in_flight(&state.pool, payment.payment_id, state.clock.now()).await? {
+        return Err(ApiError::PaymentAlreadyProcessed);
+    }
+    let refund_msats = amount_msats as i64 + service_fee_msats;
+
+    // A card whose organization's backend can't be built fails like a failed payment
+    let lightning = match state.lightning.for_card(&state.pool, card.card_id).await {
+        Ok(lightning) => lightning,
+        Err(e) => {
+            let reason = locale.trf("Payment failed: {error}", &[("error", &e.to_string())]);
+            fail_payment(state, payment.payment_id, card, refund_msats, &reason).await;
+            return Err(ApiError::PaymentFailed(reason));
+        }
+    };
+    let mut txid = None;
+    let outcome = match payout {
+        Payout::Invoice(invoice) => lightning.pay_invoice(invoice, amount_msats).await,
+        Payout::Onchain(address) => lightning.send_onchain(address, amount_msats / 1000).await.map(|sent| {
+            txid = sent.txid;
+            PaymentResult { success: sent.success, preimage: None, error: sent.error, fee_msats: None }
+        }),
+    };
+    let payment_result = match outcome {
+        Ok(result) if result.success => result,
+        Ok(result) => {
+            let reason = result.error.unwrap_or_else(|| "Payment failed".to_string());
+            fail_payment(state, payment.payment_id, card, refund_msats, &reason).await;
+            return Err(ApiError::PaymentFailed(reason));
+        }
+        Err(e) => {
+            let node = match payout {
+                Payout::Invoice(invoice) => lightning.outgoing_payment(&invoice.payment_hash()).await.ok(),
+                // On-chain payouts can't be looked up
+                Payout::Onchain(_) => None,
+            };
+            match node {
+                Some(OutgoingPayment::Succeeded { preimage }) => PaymentResult { success: true, preimage, error: None, fee_msats: None },
+                Some(OutgoingPayment::Failed) => {
+                    let reason = locale.trf("Payment failed: {error}", &[("error", &e.to_string())]);
+                    fail_payment(state, payment.payment_id, card, refund_msats, &reason).await;
+                    return Err(ApiError::PaymentFailed(reason));
+                }
+                _ => {
+                    tracing::warn!("Payment {} errored ({}) with an unknown outcome, leaving it in flight", payment.payment_id, e);
+                    return Err(ApiError::PaymentPending);
+                }
+            }
+        }
+    };
+
+    if let Some(txid) = &txid {
+        onchain::set_txid(&state.pool, payment.payment_id, txid).await?;
+    }
+    // Mark payment as paid, unless the reconciliation got there first
+    let settled = queries::mark_payment_settled(
+        &state.pool,
+        payment.payment_id,
+        payment_result.preimage.as_deref(),
+        payment_result.fee_msats,
+        state.clock.now(),
     )
-}
\ No newline at end of file
+    .await?;
+    if !settled {
+        return Ok(());
+    }
+    notifications::queue_receipt(state, card.card_id, payment.payment_id).await;
+    rates::queue_rate(state, payment.payment_id).await;
+    warn_about_spend(state, card, amount_msats as i64).await;
+
+    tracing::info!("Payment {} settled, receipt: {}", payment.payment_id, state.config.urls().receipt_url(&payment.k1));
+
+    Ok(())
+}
+
+/// Pay a withdrawal queued in async mode and notify the operator of the outcome
+///
+/// Does nothing if the payment was already decided, e.g. when the job is
+/// rerun after a restart. A payment interrupted mid-flight isn't paid again
+/// but decided by what the node knows about it, as the reconciliation does.
+pub async fn execute_queued_payment(state: &AppState, payment_id: i64, locale: Locale) -> Result<()> {
+    use std::str::FromStr;
+
+    let payment = queries::get_payment_by_id(&state.pool, payment_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("payment {} not found", payment_id))?;
+    match payment.status {
+        PaymentStatus::InvoiceAttached => {}
+        PaymentStatus::InFlight => {
+            tracing::warn!("Payment {} was interrupted in flight, asking the node about it", payment_id);
+            return reconcile::decide_in_flight(state, &payment).await;
+        }
+        _ => return Ok(()),
+    }
+
+    let card = queries::get_card_by_id(&state.pool, payment.card_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("card {} not found", payment.card_id))?;
+    let invoice;
+    let payout = match payment.onchain_address.as_deref() {
+        Some(address) => Payout::Onchain(address),
+        None => {
+            invoice = Invoice::from_str(payment.invoice.as_deref().unwrap_or_default())?;
+            Payout::Invoice(&invoice)
+        }
+    };
+    let amount_msats = payment.amount_msats.unwrap_or_default();
+    let service_fee_msats = fees::payment_fee(&state.pool, payment_id).await?;
+
+    let event = match execute_payment(state, locale, &payment, &card, payout, amount_msats as u64, service_fee_msats).await {
+        Ok(()) => Event::PaymentSettled {
+            payment_id,
+            card_id: card.card_id,
+            k1: payment.k1,
+            amount_msats,
+        },
+        // Decided by the reconciliation once the node knows
+        Err(ApiError::PaymentPending) => return Ok(()),
+        Err(e) => Event::PaymentFailed {
+            payment_id,
+            card_id: card.card_id,
+            k1: payment.k1,
+            amount_msats,
+            reason: e.to_string(),
+        },
+    };
+    notifications::send(state, event).await;
+
+    Ok(())
+}
+
+/// Tell the operator when a card's counter runs out, so it can be replaced in time
+///
+/// Each notification is sent by the tap that crosses its threshold only.
+async fn warn_about_counter(state: &AppState, card: &Card, tap: &ValidationResult) {
+    let warn_at = (Counter::MAX - state.config.counter_warning_remaining) as i64;
+    let counter = tap.counter.value() as i64;
+
+    let event = if tap.counter.remaining() == 0 {
+        Event::CounterExhausted {
+            card_id: card.card_id,
+            card_name: card.card_name.clone(),
+        }
+    } else if card.last_counter < warn_at && counter >= warn_at {
+        Event::CounterNearLimit {
+            card_id: card.card_id,
+            card_name: card.card_name.clone(),
+            counter: tap.counter.value(),
+            remaining: tap.counter.remaining(),
+        }
+    } else {
+        return;
+    };
+    notifications::send(state, event).await;
+}
+
+/// Tell the holder and the operator when a card gets close to its daily limit, so a refusal at the till doesn't come as a surprise
+///
+/// Only the payment that takes the spend over `--spend-warning-percent` of the limit sends the notification.
+async fn warn_about_spend(state: &AppState, card: &Card, amount_msats: i64) {
+    let percent = state.config.spend_warning_percent;
+    let day_limit_msats = card.day_limit_sats * 1000;
+    if percent == 0 || day_limit_msats <= 0 {
+        return;
+    }
+
+    let spent_msats = match queries::get_daily_total_msats(&state.pool, card.card_id, state.clock.now()).await {
+        Ok(spent_msats) => spent_msats,
+        Err(e) => {
+            tracing::warn!("Failed to check spend of card {}: {}", card.card_id, e);
+            return;
+        }
+    };
+    let warn_at = day_limit_msats * percent as i64 / 100;
+    if spent_msats < warn_at || spent_msats - amount_msats >= warn_at {
+        return;
+    }
+
+    let event = Event::SpendNearLimit {
+        card_id: card.card_id,
+        card_name: card.card_name.clone(),
+        spent_msats,
+        day_limit_msats,
+        percent,
+    };
+    notifications::send(state, event).await;
+}
+
+/// The card's description template, or the server's, filled in for a tap
+async fn withdraw_description(state: &AppState, tenant: &Tenant, card: &Card, remaining_sats: i64) -> Result<String> {
+    let template = descriptions::get_card_template(&state.pool, card.card_id).await?.flatten();
+    let merchant = metadata::get_card_metadata(&state.pool, card.card_id)
+        .await?
+        .and_then(|metadata| metadata.get("merchant")?.as_str().map(str::to_string))
+        .or_else(|| tenant.0.as_ref().map(|org| org.name.clone()))
+        .unwrap_or_default();
+    let context = description::Context {
+        card_id: card.card_id,
+        card_name: &card.card_name,
+        remaining_sats,
+        merchant: &merchant,
+        date: state.clock.now().date_naive(),
+    };
+
+    Ok(description::render(template.as_deref().unwrap_or(&state.config.withdraw_description), &context))
+}
+
+/// Refuse a frozen card, telling until when if the freeze ends by itself
+async fn check_not_frozen(state: &AppState, card_id: i64) -> Result<(), ApiError> {
+    match freeze::get_freeze(&state.pool, card_id, state.clock.now()).await? {
+        Some(freeze) => Err(ApiError::CardFrozen { until: freeze.frozen_until.map(|until| format!("{} UTC", until)) }),
+        None => Ok(()),
+    }
+}
+
+/// Refuse a tap whose UID another enabled card carries as well, flagging all of them
+///
+/// The UID check of a single card passes for a clone programmed with the keys
+/// of a second card record, or for two blank cards that took the same UID, so
+/// every card with the UID is blocked until the operator reviewed them.
+async fn check_uid_unique(state: &AppState, card: &Card, uid: &str) -> Result<(), ApiError> {
+    let twins = clones::find_uid_twins(&state.pool, card.card_id, uid).await?;
+    if twins.is_empty() {
+        return Ok(());
+    }
+
+    let card_ids: Vec<i64> = std::iter::once(card.card_id).chain(twins.iter().copied()).collect();
+    let flagged = clones::flag_clones(&state.pool, &card_ids, uid, state.clock.now(), "tap").await?;
+    // Repeated taps of a card already under review don't notify again
+    if !flagged.is_empty() {
+        tracing::warn!("Card {} shares UID {} with cards {:?}, flagged for review", card.card_id, uid, twins);
+        let event = Event::CardCloneSuspected {
+            card_id: card.card_id,
+            card_name: card.card_name.clone(),
+            other_card_ids: twins,
+        };
+        notifications::send(state, event).await;
+    }
+
+    Err(ApiError::CardUnderReview)
+}
+
+/// Refuse a card flagged as a suspected clone until the operator clears the flag
+async fn check_not_under_review(state: &AppState, card_id: i64) -> Result<(), ApiError> {
+    match clones::get_clone_flagged_at(&state.pool, card_id).await? {
+        Some(_) => Err(ApiError::CardUnderReview),
+        None => Ok(()),
+    }
+}
+
+/// Release a payment that was handed to the node but failed, refunding a prepaid card
+async fn fail_payment(state: &AppState, payment_id: i64, card: &Card, refund_msats: i64, reason: &str) {
+    if card.balance_mode
+        && let Err(e) = queries::credit_balance(&state.pool, card.card_id, refund_msats).await
+    {
+        tracing::error!("Failed to refund balance of card {}: {}", card.card_id, e);
+    }
+    release_payment(state, payment_id, reason).await;
+}
+
+/// Mark a reserved payment failed, logging instead of propagating errors
+async fn release_payment(state: &AppState, payment_id: i64, reason: &str) {
+    if let Err(e) = queries::mark_payment_failed(&state.pool, payment_id, reason).await {
+        tracing::error!("Failed to release payment {}: {}", payment_id, e);
+    }
+}
+
+/// Validate a tap against the card's cached, already parsed keys
+///
+/// The key cache must have been refreshed for the tap.
+pub fn validate_tap(state: &AppState, card: &Card, p: &[u8; 16], c: &[u8; 8]) -> Result<ValidationResult, AuthError> {
+    let keys = state.key_cache.keys(card)?;
+    state.validator.authenticate(&keys.k1, &keys.k2, p, c)
+}
+
+/// Find the card a tap without card ID belongs to by trying each card's keys
+///
+/// The enabled cards and their parsed keys come from an index kept in memory,
+/// so only the matching card is loaded. Recently tapped cards are tried first,
+/// so a busy instance usually finds the card after a few AES operations
+/// instead of one per card. If none of them matches, the remaining cards are
+/// tried in chunks on the blocking thread pool, so a scan of a large fleet
+/// neither stalls the executor nor runs on a single core.
+async fn find_card(state: &AppState, tenant: &Tenant, p: &[u8; 16], c: &[u8; 8]) -> Result<Option<(Card, ValidationResult)>> {
+    let candidates = state.key_cache.candidates(&state.pool).await?;
+    let recent = state.key_cache.recent();
+
+    let mut found = recent
+        .iter()
+        .filter_map(|&card_id| candidates.get(card_id))
+        .filter(|card| tenant.serves(card.org_id))
+        .find_map(|card| match_card(state, card, p, c));
+
+    if found.is_none() {
+        let recent: HashSet<i64> = recent.into_iter().collect();
+        let recent = Arc::new(recent);
+        let mut scans = Vec::new();
+        for start in (0..candidates.cards.len()).step_by(SCAN_CHUNK_CARDS) {
+            let (state, tenant, candidates, recent, p, c) = (state.clone(), tenant.clone(), candidates.clone(), recent.clone(), *p, *c);
+            scans.push(tokio::task::spawn_blocking(move || {
+                candidates.cards[start..(start + SCAN_CHUNK_CARDS).min(candidates.cards.len())]
+                    .iter()
+                    .filter(|card| !recent.contains(&card.card_id) && tenant.serves(card.org_id))
+                    .find_map(|card| match_card(&state, card, &p, &c))
+            }));
+        }
+
+        for scan in scans {
+            if let Some(result) = scan.await? {
+                found = Some(result);
+                break;
+            }
+        }
+    }
+
+    let Some((card_id, result)) = found else {
+        return Ok(None);
+    };
+    // Disabled since the index was loaded, the tap doesn't belong to any card then
+    let card = queries::get_enabled_card_by_id(&state.pool, card_id).await?;
+    Ok(card.map(|card| (card, result)))
+}
+
+/// The tap's result if the card's keys authenticate it and it carries the card's UID
+fn match_card(state: &AppState, card: &Candidate, p: &[u8; 16], c: &[u8; 8]) -> Option<(i64, ValidationResult)> {
+    match state.validator.authenticate(&card.keys.k1, &card.keys.k2, p, c) {
+        Ok(tap) if card.uid.is_empty() || card.uid == tap.uid.to_string() => Some((card.card_id, tap)),
+        _ => None,
+    }
+}
+
+/// What the policy plugin is told about a card
+async fn plugin_context(state: &AppState, stage: Stage, card: &Card, daily_spent_msats: i64) -> Result<plugin::Context> {
+    let org_id = organizations::get_card_org(&state.pool, card.card_id).await?;
+    let tags = tags::get_card_tags(&state.pool, card.card_id).await?;
+    Ok(plugin::Context::new(stage, card, org_id, tags, daily_spent_msats))
+}
+
+/// Record a rejected tap in the card's history
+///
+/// Tap history is diagnostic only, so failures to write it don't fail the request.
+async fn record_failed_tap(
+    state: &AppState,
+    card_id: i64,
+    uid: Option<&str>,
+    counter: Option<i64>,
+    reason: &str,
+) {
+    let result = match state.pool.acquire().await {
+        Ok(mut conn) => taps::record_tap(&mut conn, card_id, uid, counter, false, Some(reason)).await,
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = result {
+        tracing::warn!("Failed to record tap for card {}: {}", card_id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app_state::test_state, lightning::{Fault, LightningBackend, MockLightning}};
+
+    /// A prepaid card with a withdrawal of 1000 sats reserved for an invoice of the mock
+    async fn reserved_payment(state: &AppState, mock: &MockLightning) -> (i64, i64, Invoice) {
+        let key = "00000000000000000000000000000000";
+        let card_id = queries::insert_card(
+            &state.pool, "04996c6a926980", key, key, key, key, key, "Card", 10_000, 100_000, true, true, "code", None, None,
+            chrono::Utc::now(),
+        )
+        .await
+        .unwrap();
+        let invoice = mock.create_invoice(1_000_000, "withdrawal").await.unwrap();
+        let payment_id = sqlx::query_scalar(
+            "INSERT INTO card_payments (card_id, k1, invoice, amount_msats, payment_hash, status, attempts)
+             VALUES (?, 'k1', ?, 1000000, ?, 'invoice_attached', 1) RETURNING payment_id"
+        )
+        .bind(card_id)
+        .bind(invoice.bolt11())
+        .bind(invoice.payment_hash())
+        .fetch_one(&state.pool)
+        .await
+        .unwrap();
+
+        (card_id, payment_id, invoice)
+    }
+
+    async fn status(state: &AppState, payment_id: i64) -> PaymentStatus {
+        queries::get_payment_by_id(&state.pool, payment_id).await.unwrap().unwrap().status
+    }
+
+    async fn balance(state: &AppState, card_id: i64) -> i64 {
+        queries::get_card_by_id(&state.pool, card_id).await.unwrap().unwrap().balance_msats
+    }
+
+    fn pay_calls(mock: &MockLightning) -> usize {
+        mock.calls().iter().filter(|call| call.method == "pay_invoice").count()
+    }
+
+    #[tokio::test]
+    async fn test_queued_payment_is_paid_once() {
+        let (state, mock) = test_state(&[]).await;
+        let (card_id, payment_id, _) = reserved_payment(&state, &mock).await;
+
+        execute_queued_payment(&state, payment_id, Locale::En).await.unwrap();
+        execute_queued_payment(&state, payment_id, Locale::En).await.unwrap();
+
+        assert_eq!(status(&state, payment_id).await, PaymentStatus::Settled);
+        assert_eq!(pay_calls(&mock), 1);
+        assert_eq!(balance(&state, card_id).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_payment_interrupted_in_flight_is_not_paid_again() {
+        let (state, mock) = test_state(&[]).await;
+        let (card_id, payment_id, invoice) = reserved_payment(&state, &mock).await;
+        // The node paid, then the server went down before recording it
+        assert!(queries::mark_payment_in_flight(&state.pool, payment_id, state.clock.now()).await.unwrap());
+        assert!(mock.pay_invoice(&invoice, 1_000_000).await.unwrap().success);
+
+        execute_queued_payment(&state, payment_id, Locale::En).await.unwrap();
+
+        assert_eq!(status(&state, payment_id).await, PaymentStatus::Settled);
+        assert_eq!(pay_calls(&mock), 1);
+        assert_eq!(balance(&state, card_id).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_errored_payment_goes_by_the_node() {
+        let cases = [
+            // The connection dropped but the payment went out: settled, not refunded
+            (Fault::Lost, PaymentStatus::Settled, 0),
+            // The node has no record of it: left in flight, neither settled nor refunded
+            (Fault::Error("timeout".to_string()), PaymentStatus::InFlight, 0),
+            // The node reports it failed: released and refunded
+            (Fault::Fail("no route".to_string()), PaymentStatus::Failed, 1_000_000),
+        ];
+        for (fault, expected_status, expected_balance) in cases {
+            let (state, mock) = test_state(&[]).await;
+            mock.script([fault]);
+            let (card_id, payment_id, _) = reserved_payment(&state, &mock).await;
+
+            execute_queued_payment(&state, payment_id, Locale::En).await.unwrap();
+
+            assert_eq!(status(&state, payment_id).await, expected_status);
+            assert_eq!(balance(&state, card_id).await, expected_balance);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tap_over_lowered_daily_limit_is_refused() {
+        let (state, _) = test_state(&[]).await;
+        let (k1, k2) = ("0c3b25d92b38ae443229dd59ad34b85d", "b45775776cb224c75bcde7ca3704e933");
+        let card_id = queries::insert_card(&state.pool, "", k2, k1, k2, k2, k2, "Card", 10_000, 1_000, true, false, "code", None, None, state.clock.now())
+            .await
+            .unwrap();
+        // Spent 2000 sats today, then the limit went down to 1000
+        sqlx::query("INSERT INTO card_spend (card_id, day, amount_msats, payments) VALUES (?, date('now'), 2000000, 1)")
+            .bind(card_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        let params = LnurlwParams {
+            card_id: Some(card_id.to_string()),
+            p: "4E2E289D945A66BB13377A728884E867".to_string(),
+            c: "E19CCB1FED8892CE".to_string(),
+        };
+
+        let error = handle_tap(&state, &Tenant(None), &params).await.unwrap_err();
+        assert!(matches!(error, ApiError::DailyLimitExceeded), "{:?}", error);
+
+        // The refused tap didn't use up the counter
+        sqlx::query("DELETE FROM card_spend").execute(&state.pool).await.unwrap();
+        let response = handle_tap(&state, &Tenant(None), &params).await.unwrap();
+        assert_eq!(response.max_withdrawable, 1_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_find_card_without_card_id() {
+        let (state, _) = test_state(&[]).await;
+        let shop = organizations::create_organization(&state.pool, "shop-a", "Shop A", "mock", None).await.unwrap();
+        let zero = "00000000000000000000000000000000";
+        queries::insert_card(&state.pool, "", zero, zero, zero, zero, zero, "Other", 10_000, 100_000, true, false, "other", None, None, state.clock.now())
+            .await
+            .unwrap();
+        let (p, c) = decode_params("4E2E289D945A66BB13377A728884E867", "E19CCB1FED8892CE").unwrap();
+        let find = |tenant: Tenant| {
+            let state = state.clone();
+            async move {
+                state.key_cache.refresh(&state.pool).await.unwrap();
+                find_card(&state, &tenant, &p, &c).await.unwrap().map(|(card, _)| card.card_id)
+            }
+        };
+        assert_eq!(find(Tenant(None)).await, None);
+
+        // A card added after the index was loaded is found on the next tap
+        let (k1, k2) = ("0c3b25d92b38ae443229dd59ad34b85d", "b45775776cb224c75bcde7ca3704e933");
+        let card_id = queries::insert_card(&state.pool, "", k2, k1, k2, k2, k2, "Card", 10_000, 100_000, true, false, "code", None, shop, state.clock.now())
+            .await
+            .unwrap();
+        assert_eq!(find(Tenant(None)).await, Some(card_id));
+        state.key_cache.touch(card_id);
+        assert_eq!(find(Tenant(None)).await, Some(card_id));
+
+        // Only the card's organization serves it on its domain
+        let other = organizations::create_organization(&state.pool, "shop-b", "Shop B", "mock", None).await.unwrap();
+        let other = organizations::get_organization(&state.pool, other.unwrap()).await.unwrap();
+        assert_eq!(find(Tenant(other)).await, None);
+        let shop = organizations::get_organization(&state.pool, shop.unwrap()).await.unwrap();
+        assert_eq!(find(Tenant(shop)).await, Some(card_id));
+
+        queries::set_card_enabled(&state.pool, card_id, false).await.unwrap();
+        assert_eq!(find(Tenant(None)).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_tap_with_keys_from_before_rotation() {
+        use crate::crypto::{AesKey, CardUid, generate_sun};
+
+        let (state, _) = test_state(&[]).await;
+        let (k1, k2) = ("0c3b25d92b38ae443229dd59ad34b85d", "b45775776cb224c75bcde7ca3704e933");
+        let card_id = queries::insert_card(&state.pool, "", k2, k1, k2, k2, k2, "Card", 10_000, 100_000, true, false, "code", None, None, state.clock.now())
+            .await
+            .unwrap();
+        let selector = crate::db::bulk::CardSelector { card_ids: Some(vec![card_id]), ..Default::default() };
+        rotation::rotate(&state.pool, &selector, chrono::Duration::hours(1), "test", &state.random).await.unwrap();
+        let previous_keys = || rotation::get_previous_keys(&state.pool, card_id, state.clock.now());
+
+        // Not reprogrammed yet, the card still taps with its old keys
+        let params = LnurlwParams {
+            card_id: Some(card_id.to_string()),
+            p: "4E2E289D945A66BB13377A728884E867".to_string(),
+            c: "E19CCB1FED8892CE".to_string(),
+        };
+        handle_tap(&state, &Tenant(None), &params).await.unwrap();
+        assert!(previous_keys().await.unwrap().is_some());
+
+        // Its first tap with the new keys ends the grace window
+        let card = queries::get_card_by_id(&state.pool, card_id).await.unwrap().unwrap();
+        let (p, c) = generate_sun(
+            &AesKey::from_hex(&card.k1_decrypt_key).unwrap(),
+            &AesKey::from_hex(&card.k2_cmac_key).unwrap(),
+            &CardUid::from_hex(&card.uid).unwrap(),
+            &Counter::new(card.last_counter as u32 + 1),
+        );
+        let params = LnurlwParams { card_id: Some(card_id.to_string()), p: hex::encode_upper(p), c: hex::encode_upper(c) };
+        handle_tap(&state, &Tenant(None), &params).await.unwrap();
+        assert!(previous_keys().await.unwrap().is_none());
+        assert!(handle_tap(&state, &Tenant(None), &LnurlwParams {
+            card_id: Some(card_id.to_string()),
+            p: "4E2E289D945A66BB13377A728884E867".to_string(),
+            c: "E19CCB1FED8892CE".to_string(),
+        })
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_card_address_doesnt_preempt_an_invoice() {
+        let (state, mock) = test_state(&["--onchain-fallback", "--onchain-min-sats", "500"]).await;
+        let (card_id, payment_id, _) = reserved_payment(&state, &mock).await;
+        onchain::set_card_address(&state.pool, card_id, Some("bcrt1qcardaddress"), "test").await.unwrap();
+        let card = queries::get_card_by_id(&state.pool, card_id).await.unwrap().unwrap();
+        let mut payment = queries::get_payment_by_id(&state.pool, payment_id).await.unwrap().unwrap();
+        let card_address = Some("bcrt1qcardaddress".to_string());
+
+        // Large enough to go on-chain, but the wallet's invoice may well be paid
+        assert_eq!(onchain_destination(&state, &payment, &card, 1_000_000, None, true).await.unwrap(), None);
+        // Without an invoice there's only the card's address to pay to
+        assert_eq!(onchain_destination(&state, &payment, &card, 1_000_000, None, false).await.unwrap(), card_address);
+        // Once Lightning failed often enough the invoice is passed over
+        payment.attempts = 2;
+        assert_eq!(onchain_destination(&state, &payment, &card, 1_000_000, None, true).await.unwrap(), card_address);
+        // Small withdrawals stay on Lightning until then
+        payment.attempts = 1;
+        assert_eq!(onchain_destination(&state, &payment, &card, 100_000, None, false).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_lost_card_session_cant_be_paid() {
+        let (state, mock) = test_state(&[]).await;
+        let (k1, k2) = ("0c3b25d92b38ae443229dd59ad34b85d", "b45775776cb224c75bcde7ca3704e933");
+        let card_id = queries::insert_card(&state.pool, "", k2, k1, k2, k2, k2, "Card", 10_000, 100_000, true, false, "code", None, None, state.clock.now())
+            .await
+            .unwrap();
+        let tap = || async {
+            let counter = queries::get_card_by_id(&state.pool, card_id).await.unwrap().unwrap().last_counter;
+            let (p, c) = crate::crypto::generate_sun(
+                &crate::crypto::AesKey::from_hex(k1).unwrap(),
+                &crate::crypto::AesKey::from_hex(k2).unwrap(),
+                &crate::crypto::CardUid::from_hex("04996c6a926980").unwrap(),
+                &Counter::new(counter as u32 + 1),
+            );
+            let params = LnurlwParams { card_id: Some(card_id.to_string()), p: hex::encode_upper(p), c: hex::encode_upper(c) };
+            handle_tap(&state, &Tenant(None), &params).await.unwrap().k1
+        };
+        let callback = |k1: String| async {
+            let invoice = mock.create_invoice(1_000_000, "withdrawal").await.unwrap();
+            let params = CallbackParams { k1, pr: Some(invoice.bolt11()), address: None, amount: None };
+            handle_callback(&state, &Tenant(None), Locale::En, &params).await
+        };
+
+        // A session taken before the report is expired with it
+        let k1_before_report = tap().await;
+        crate::db::lost::report_lost(&state.pool, card_id, "holder", None, "test").await.unwrap().unwrap();
+        let error = callback(k1_before_report.clone()).await.unwrap_err();
+        assert!(matches!(error, ApiError::SessionExpired), "{:?}", error);
+        let payment = queries::get_payment_by_k1(&state.pool, &k1_before_report).await.unwrap().unwrap();
+        assert_eq!(payment.status, PaymentStatus::Expired);
+        assert_eq!(pay_calls(&mock), 0);
+
+        // Any other open session of a disabled card is refused too
+        sqlx::query("INSERT INTO card_payments (card_id, k1, status) VALUES (?, 'open', 'created')")
+            .bind(card_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        let error = callback("open".to_string()).await.unwrap_err();
+        assert!(matches!(error, ApiError::CardNotFound), "{:?}", error);
+        assert_eq!(pay_calls(&mock), 0);
+    }
+
+    /// A card with the test vector's keys, and the parameters of its tap with `counter`
+    async fn test_vector_card(state: &AppState) -> (i64, impl Fn(u32) -> LnurlwParams) {
+        let (k1, k2) = ("0c3b25d92b38ae443229dd59ad34b85d", "b45775776cb224c75bcde7ca3704e933");
+        let card_id = queries::insert_card(&state.pool, "", k2, k1, k2, k2, k2, "Card", 10_000, 100_000, true, false, "code", None, None, state.clock.now())
+            .await
+            .unwrap();
+        let params = move |counter| {
+            let (p, c) = crate::crypto::generate_sun(
+                &crate::crypto::AesKey::from_hex(k1).unwrap(),
+                &crate::crypto::AesKey::from_hex(k2).unwrap(),
+                &crate::crypto::CardUid::from_hex("04996c6a926980").unwrap(),
+                &Counter::new(counter),
+            );
+            LnurlwParams { card_id: Some(card_id.to_string()), p: hex::encode_upper(p), c: hex::encode_upper(c) }
+        };
+        (card_id, params)
+    }
+
+    /// Settings running `script` with `sh -c` as the policy plugin
+    fn plugin_args(script: &str) -> [&str; 6] {
+        ["--policy-plugin", "sh", "--policy-plugin-arg", "-c", "--policy-plugin-arg", script]
+    }
+
+    #[tokio::test]
+    async fn test_policy_plugin_on_tap() {
+        let deny = r#"cat > /dev/null; echo '{"decision": "deny", "reason": "Card paused overnight"}'"#;
+        let (state, _) = test_state(&plugin_args(deny)).await;
+        let (_, params) = test_vector_card(&state).await;
+        let error = handle_tap(&state, &Tenant(None), &params(1)).await.unwrap_err();
+        assert_eq!(error, ApiError::PolicyDenied(Some("Card paused overnight".to_string())));
+
+        let cap = r#"cat > /dev/null; echo '{"decision": "approve", "max_withdrawable_msats": 500000}'"#;
+        let (state, _) = test_state(&plugin_args(cap)).await;
+        let (_, params) = test_vector_card(&state).await;
+        let response = handle_tap(&state, &Tenant(None), &params(1)).await.unwrap();
+        assert_eq!(response.max_withdrawable, 500_000);
+    }
+
+    #[tokio::test]
+    async fn test_policy_plugin_caps_withdrawal() {
+        // Only the withdrawal is capped, below the invoice's 1000 sats
+        let script = r#"case "$(cat)" in
+            *'"stage":"withdraw"'*) echo '{"decision": "approve", "max_withdrawable_msats": 500000}' ;;
+            *) echo '{"decision": "approve"}' ;;
+        esac"#;
+        let (state, mock) = test_state(&plugin_args(script)).await;
+        let (_, params) = test_vector_card(&state).await;
+        let tapped = handle_tap(&state, &Tenant(None), &params(1)).await.unwrap();
+        assert_eq!(tapped.max_withdrawable, 10_000_000);
+
+        let invoice = mock.create_invoice(1_000_000, "withdrawal").await.unwrap();
+        let params = CallbackParams { k1: tapped.k1, pr: Some(invoice.bolt11()), address: None, amount: None };
+        let error = handle_callback(&state, &Tenant(None), Locale::En, &params).await.unwrap_err();
+        assert_eq!(error, ApiError::AmountOutOfRange);
+        assert_eq!(pay_calls(&mock), 0);
+    }
+
+    #[tokio::test]
+    async fn test_policy_plugin_timeout() {
+        let slow = plugin_args("sleep 5");
+        let (state, _) = test_state(&[&slow[..], &["--policy-plugin-timeout-ms", "100"]].concat()).await;
+        let (_, params) = test_vector_card(&state).await;
+        let error = handle_tap(&state, &Tenant(None), &params(1)).await.unwrap_err();
+        assert_eq!(error, ApiError::PolicyDenied(None));
+
+        let (state, _) = test_state(&[&slow[..], &["--policy-plugin-timeout-ms", "100", "--policy-plugin-fail-open"]].concat()).await;
+        let (_, params) = test_vector_card(&state).await;
+        let response = handle_tap(&state, &Tenant(None), &params(1)).await.unwrap();
+        assert_eq!(response.max_withdrawable, 10_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_counter_warnings() {
+        let (state, _) = test_state(&["--counter-warning-remaining", "10"]).await;
+        let key = "00000000000000000000000000000000";
+        let card_id = queries::insert_card(&state.pool, "04996c6a926980", key, key, key, key, key, "Card", 1000, 10000, true, false, "code", None, None, state.clock.now())
+            .await
+            .unwrap();
+        let mut card = queries::get_card_by_id(&state.pool, card_id).await.unwrap().unwrap();
+        let tap = |counter| ValidationResult {
+            uid: crate::crypto::CardUid::from_hex("04996c6a926980").unwrap(),
+            counter: Counter::new(counter),
+        };
+        let events = || async {
+            crate::db::webhooks::list_events(&state.pool, None, 10)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|event| event.event)
+                .collect::<Vec<_>>()
+        };
+
+        card.last_counter = (Counter::MAX - 12) as i64;
+        warn_about_counter(&state, &card, &tap(Counter::MAX - 11)).await;
+        assert!(events().await.is_empty());
+
+        // Only the tap reaching the threshold warns, even if it skips counter values
+        card.last_counter = (Counter::MAX - 11) as i64;
+        warn_about_counter(&state, &card, &tap(Counter::MAX - 5)).await;
+        card.last_counter = (Counter::MAX - 5) as i64;
+        warn_about_counter(&state, &card, &tap(Counter::MAX - 4)).await;
+        assert_eq!(events().await, vec!["counter_near_limit"]);
+
+        card.last_counter = (Counter::MAX - 4) as i64;
+        warn_about_counter(&state, &card, &tap(Counter::MAX)).await;
+        assert_eq!(events().await, vec!["counter_exhausted", "counter_near_limit"]);
+    }
+
+    #[tokio::test]
+    async fn test_session_ttl_limits_retries() {
+        let (state, mock) = test_state(&[]).await;
+        let key = "00000000000000000000000000000000";
+        let card_id = queries::insert_card(&state.pool, "04996c6a926980", key, key, key, key, key, "Card", 10_000, 100_000, true, false, "code", None, None, state.clock.now())
+            .await
+            .unwrap();
+        let session = |k1: &'static str, status: &'static str, attempts: i64, age: &'static str| {
+            let pool = state.pool.clone();
+            async move {
+                sqlx::query(
+                    "INSERT INTO card_payments (card_id, k1, status, attempts, created_at) VALUES (?, ?, ?, ?, datetime('now', ?))"
+                )
+                .bind(card_id)
+                .bind(k1)
+                .bind(status)
+                .bind(attempts)
+                .bind(age)
+                .execute(&pool)
+                .await
+                .unwrap();
+            }
+        };
+        let callback = |k1: &'static str| {
+            let (state, mock) = (state.clone(), mock.clone());
+            async move {
+                let invoice = mock.create_invoice(1_000_000, "withdrawal").await.unwrap();
+                let params = CallbackParams { k1: k1.to_string(), pr: Some(invoice.bolt11()), address: None, amount: None };
+                handle_callback(&state, &Tenant(None), Locale::En, &params).await
+            }
+        };
+
+        // The wallet's first invoice is taken however long after the tap it comes
+        session("first", "created", 0, "-1 hour").await;
+        callback("first").await.unwrap();
+
+        session("retry", "failed", 1, "-1 hour").await;
+        let error = callback("retry").await.unwrap_err();
+        assert!(matches!(error, ApiError::SessionExpired), "{:?}", error);
+
+        session("recent", "failed", 1, "-1 minute").await;
+        callback("recent").await.unwrap();
+
+        session("exhausted", "failed", 3, "-1 minute").await;
+        let error = callback("exhausted").await.unwrap_err();
+        assert!(matches!(error, ApiError::AttemptsExhausted), "{:?}", error);
+    }
+
+    #[tokio::test]
+    async fn test_capital_refusal_keeps_the_attempt() {
+        let (state, mock) = test_state(&["--enforce-funded-capital"]).await;
+        let key = "00000000000000000000000000000000";
+        let card_id = queries::insert_card(&state.pool, "04996c6a926980", key, key, key, key, key, "Card", 10_000, 100_000, true, false, "code", None, None, state.clock.now())
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO card_payments (card_id, k1, status) VALUES (?, 'k1', 'created')")
+            .bind(card_id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        let callback = || async {
+            let invoice = mock.create_invoice(1_000_000, "withdrawal").await.unwrap();
+            let params = CallbackParams { k1: "k1".to_string(), pr: Some(invoice.bolt11()), address: None, amount: None };
+            handle_callback(&state, &Tenant(None), Locale::En, &params).await
+        };
+
+        let error = callback().await.unwrap_err();
+        assert!(matches!(error, ApiError::CapitalExhausted), "{:?}", error);
+        let payment = queries::get_payment_by_k1(&state.pool, "k1").await.unwrap().unwrap();
+        assert_eq!((payment.status, payment.attempts), (PaymentStatus::Failed, 0));
+        assert_eq!(pay_calls(&mock), 0);
+
+        crate::db::accounts::fund_card(&state.pool, card_id, 1_000_000, "test").await.unwrap();
+        callback().await.unwrap();
+        let payment = queries::get_payment_by_k1(&state.pool, "k1").await.unwrap().unwrap();
+        assert_eq!((payment.status, payment.attempts), (PaymentStatus::Settled, 1));
+    }
+}
@@ -0,0 +1,270 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Html,
+    Form, Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    db::{lost, queries, tokens::{self, TokenScope}},
+    handlers::{html::{escape, localized_page}, register::replace_card_record},
+    i18n::Locale,
+    notifications::{self, Event},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct LostReportForm {
+    note: Option<String>,
+    /// Issue a replacement card that takes over the balance
+    #[serde(default)]
+    replace: bool,
+}
+
+/// GET /lost/{token}
+/// Cardholder page for reporting a card lost
+pub async fn report_page(
+    locale: Locale,
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Html<String>, StatusCode> {
+    let card = tokens::get_unbound_card_by_token(&state.pool, &token, TokenScope::ReportLost)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let name = format!("<strong>{}</strong>", escape(&card.card_name));
+
+    let body = if card.enabled {
+        format!(
+            r#"<h1>{}</h1>
+<p>{}</p>
+<form method="post">
+<p><label>{}<br><textarea name="note" rows="3" cols="40"></textarea></label></p>
+<p><label><input type="checkbox" name="replace" value="true"> {}</label></p>
+<p><button type="submit">{}</button></p>
+</form>"#,
+            locale.tr("Report card lost"),
+            locale.trf(
+                "Reporting {card} lost disables it immediately. Nobody will be able to pay with it until the operator re-enables or replaces it.",
+                &[("card", &name)],
+            ),
+            locale.tr("Anything the operator should know (optional)"),
+            locale.tr("Issue a replacement card that takes over the balance"),
+            locale.tr("Disable my card"),
+        )
+    } else {
+        format!(
+            "<h1>{}</h1>\n<p>{}</p>",
+            locale.tr("Card disabled"),
+            locale.trf("{card} is disabled and cannot be used for payments.", &[("card", &name)]),
+        )
+    };
+
+    Ok(localized_page(&state.config, locale, "Report card lost", &body))
+}
+
+/// POST /lost/{token}
+/// Disable the card, record the report and notify the operator
+///
+/// With `replace` a replacement is issued right away, like `POST /v1/cards/{card_id}/replace`
+/// does, unless the card was disabled already, e.g. by an earlier report.
+pub async fn report_lost(
+    locale: Locale,
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+    Form(form): Form<LostReportForm>,
+) -> Result<Html<String>, StatusCode> {
+    let card = tokens::get_unbound_card_by_token(&state.pool, &token, TokenScope::ReportLost)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let note = form
+        .note
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty());
+
+    // Replacing disables the card first, so the report only adds the lost mark
+    let replacement_id = if form.replace && card.enabled {
+        let replacement = replace_card_record(&state.pool, &state.random, &card, state.clock.now(), "cardholder")
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        tracing::info!("Card {} replaced by card {} at the holder's request", card.card_id, replacement.card.card_id);
+        Some(replacement.card.card_id)
+    } else {
+        None
+    };
+
+    lost::report_lost(&state.pool, card.card_id, "cardholder", note.as_deref(), "cardholder")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    notifications::send(
+        &state,
+        Event::CardReportedLost {
+            card_id: card.card_id,
+            card_name: card.card_name.clone(),
+            note,
+            replacement_id,
+        },
+    )
+    .await;
+
+    let name = format!("<strong>{}</strong>", escape(&card.card_name));
+    let message = match replacement_id {
+        Some(_) => locale.trf(
+            "{card} has been disabled and a replacement card with its balance was issued. The operator was notified and will hand it over to you.",
+            &[("card", &name)],
+        ),
+        None => locale.trf(
+            "{card} has been disabled and the operator was notified. Contact them to get a replacement card.",
+            &[("card", &name)],
+        ),
+    };
+    let body = format!("<h1>{}</h1>\n<p>{}</p>", locale.tr("Card disabled"), message);
+
+    Ok(localized_page(&state.config, locale, "Card disabled", &body))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ReportLostRequest {
+    note: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportLostResponse {
+    pub status: String,
+    pub report_id: i64,
+    /// When the card was first reported lost, earlier reports included
+    pub reported_lost_at: Option<String>,
+}
+
+/// POST /v1/cards/{card_id}/report-lost
+/// Freeze a lost or stolen card, record the report and notify the operator and the card's owner
+///
+/// Unlike a plain disable the card stays marked lost until it is enabled again.
+pub async fn report_card_lost(
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+    req: Option<Json<ReportLostRequest>>,
+) -> Result<Json<ReportLostResponse>, StatusCode> {
+    let card = queries::get_card_by_id(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let note = req
+        .and_then(|Json(req)| req.note)
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty());
+
+    let report = lost::report_lost(&state.pool, card_id, "operator", note.as_deref(), "api")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let reported_lost_at = lost::get_reported_lost_at(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tracing::info!("Card {} reported lost by the operator", card_id);
+
+    notifications::send(
+        &state,
+        Event::CardReportedLost {
+            card_id,
+            card_name: card.card_name,
+            note,
+            replacement_id: None,
+        },
+    )
+    .await;
+
+    Ok(Json(ReportLostResponse {
+        status: "OK".to_string(),
+        report_id: report.report_id,
+        reported_lost_at,
+    }))
+}
+
+/// `409` if the card is marked lost, which only the operator can enable again
+pub async fn require_not_lost(state: &AppState, card_id: i64) -> Result<(), StatusCode> {
+    let reported_lost_at = lost::get_reported_lost_at(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    match reported_lost_at {
+        Some(_) => Err(StatusCode::CONFLICT),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state::test_state;
+
+    async fn report(state: &AppState, token: &str, replace: bool) -> String {
+        let form = LostReportForm { note: Some(" left it on the bus ".to_string()), replace };
+        let Html(page) = report_lost(Locale::En, Path(token.to_string()), State(state.clone()), Form(form)).await.unwrap();
+        page
+    }
+
+    async fn card_ids(state: &AppState) -> Vec<i64> {
+        sqlx::query_scalar("SELECT card_id FROM cards ORDER BY card_id").fetch_all(&state.pool).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_report_lost() {
+        let (state, _) = test_state(&[]).await;
+        let key = "00000000000000000000000000000000";
+        let card_id = queries::insert_card(&state.pool, "04996c6a926980", key, key, key, key, key, "Lost", 1000, 10000, true, true, "code", None, None, state.clock.now())
+            .await
+            .unwrap();
+        let token = tokens::create_card_token(&state.pool, &state.random, card_id, TokenScope::ReportLost).await.unwrap();
+
+        let page = report(&state, &token, false).await;
+        assert!(page.contains("Contact them to get a replacement card"));
+        assert_eq!(card_ids(&state).await, vec![card_id]);
+        assert!(lost::get_reported_lost_at(&state.pool, card_id).await.unwrap().is_some());
+        let card = queries::get_card_by_id(&state.pool, card_id).await.unwrap().unwrap();
+        assert!(!card.enabled);
+
+        // The card is disabled already, reporting again doesn't replace it
+        report(&state, &token, true).await;
+        assert_eq!(card_ids(&state).await, vec![card_id]);
+    }
+
+    #[tokio::test]
+    async fn test_report_lost_with_replacement() {
+        let (state, _) = test_state(&[]).await;
+        let key = "00000000000000000000000000000000";
+        let card_id = queries::insert_card(&state.pool, "04996c6a926980", key, key, key, key, key, "Lost", 1000, 10000, true, true, "code", None, None, state.clock.now())
+            .await
+            .unwrap();
+        queries::credit_balance(&state.pool, card_id, 5_000_000).await.unwrap();
+        let token = tokens::create_card_token(&state.pool, &state.random, card_id, TokenScope::ReportLost).await.unwrap();
+
+        let page = report(&state, &token, true).await;
+        assert!(page.contains("a replacement card with its balance was issued"));
+
+        let card_ids = card_ids(&state).await;
+        assert_eq!(card_ids.len(), 2);
+        let replacement = queries::get_card_by_id(&state.pool, card_ids[1]).await.unwrap().unwrap();
+        assert!(replacement.enabled);
+        assert_eq!(replacement.balance_msats, 5_000_000);
+        assert_eq!(lost::get_reported_lost_at(&state.pool, replacement.card_id).await.unwrap(), None);
+        let card = queries::get_card_by_id(&state.pool, card_id).await.unwrap().unwrap();
+        assert!(!card.enabled);
+        assert_eq!(card.balance_msats, 0);
+        assert!(lost::get_reported_lost_at(&state.pool, card_id).await.unwrap().is_some());
+
+        let events = crate::db::webhooks::list_events(&state.pool, None, 10).await.unwrap();
+        let Event::CardReportedLost { note, replacement_id, .. } = serde_json::from_str::<notifications::Notification>(&events[0].payload).unwrap().event
+        else {
+            panic!("unexpected event {}", events[0].event);
+        };
+        assert_eq!(note.as_deref(), Some("left it on the bus"));
+        assert_eq!(replacement_id, Some(replacement.card_id));
+    }
+}
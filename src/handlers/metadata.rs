@@ -0,0 +1,83 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+
+use crate::{
+    app_state::AppState,
+    db::metadata::{self, CardMetadata, Metadata},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct FindQuery {
+    pub key: String,
+    /// Matched against strings as they are and against numbers and booleans as written in JSON
+    pub value: Option<String>,
+}
+
+/// GET /v1/cards/{card_id}/metadata
+pub async fn get_metadata(Path(card_id): Path<i64>, State(state): State<AppState>) -> Result<Json<Metadata>, StatusCode> {
+    metadata::get_card_metadata(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// PUT /v1/cards/{card_id}/metadata
+/// Replace the metadata of a card
+pub async fn set_metadata(
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+    Json(req): Json<Metadata>,
+) -> Result<Json<Metadata>, StatusCode> {
+    store(&state, card_id, req).await
+}
+
+/// PATCH /v1/cards/{card_id}/metadata
+/// Set the given keys and remove those set to `null`, keeping the others
+pub async fn update_metadata(
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+    Json(req): Json<Metadata>,
+) -> Result<Json<Metadata>, StatusCode> {
+    let mut current = metadata::get_card_metadata(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    metadata::merge(&mut current, req);
+
+    store(&state, card_id, current).await
+}
+
+async fn store(state: &AppState, card_id: i64, metadata: Metadata) -> Result<Json<Metadata>, StatusCode> {
+    if !metadata::is_valid(&metadata) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let updated = metadata::set_card_metadata(&state.pool, card_id, &metadata, "api")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    match updated {
+        true => Ok(Json(metadata)),
+        false => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+/// GET /v1/cards/metadata?key={key}&value={value}
+/// Cards that have a metadata key, optionally with a given value
+pub async fn find_cards(
+    Query(params): Query<FindQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<CardMetadata>>, StatusCode> {
+    if !metadata::is_valid_key(&params.key) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let cards = metadata::find_cards(&state.pool, &params.key, params.value.as_deref())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(cards))
+}
@@ -1,2 +1,35 @@
+pub mod bulk;
+pub mod chaos;
+pub mod charts;
+pub mod clones;
+pub mod dashboard;
+pub mod deletion;
+pub mod description;
+pub mod diagnostics;
+pub mod error;
+pub mod feed;
+pub mod fees;
+pub mod freeze;
+pub mod graphql;
+pub mod html;
+pub mod ledger;
+pub mod metadata;
+pub mod onchain;
 pub mod register;
-pub mod lnurlw;
\ No newline at end of file
+pub mod reports;
+pub mod lnurlw;
+pub mod lost;
+pub mod payments;
+pub mod print;
+pub mod privacy;
+pub mod receipt;
+pub mod stats;
+pub mod tokens;
+pub mod topup;
+pub mod users;
+pub mod wallet;
+pub mod webhooks;
+pub mod widget;
+
+#[cfg(test)]
+mod response_shapes;
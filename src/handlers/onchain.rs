@@ -0,0 +1,55 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{app_state::AppState, db::onchain, lightning};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OnchainFallback {
+    /// Address the card's eligible withdrawals are paid to when the wallet sends an invoice, `null` for none
+    pub address: Option<String>,
+}
+
+/// GET /v1/cards/{card_id}/onchain-fallback
+pub async fn get_onchain_fallback(
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<OnchainFallback>, StatusCode> {
+    let address = onchain::get_card_address(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(OnchainFallback { address }))
+}
+
+/// PUT /v1/cards/{card_id}/onchain-fallback
+/// Set the address the card's large or repeatedly failing withdrawals are paid to on-chain
+pub async fn set_onchain_fallback(
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+    Json(req): Json<OnchainFallback>,
+) -> Result<Json<OnchainFallback>, StatusCode> {
+    let address = match &req.address {
+        Some(address) => match lightning::parse_address(address, state.config.network) {
+            Ok(address) => Some(address),
+            Err(e) => {
+                tracing::debug!("Rejected on-chain fallback address for card {}: {}", card_id, e);
+                return Err(StatusCode::BAD_REQUEST);
+            }
+        },
+        None => None,
+    };
+
+    let updated = onchain::set_card_address(&state.pool, card_id, address.as_deref(), "api")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    match updated {
+        true => Ok(Json(OnchainFallback { address })),
+        false => Err(StatusCode::NOT_FOUND),
+    }
+}
@@ -0,0 +1,88 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    db::{memos, models, queries},
+};
+
+#[derive(Debug, Serialize)]
+pub struct PaymentStatus {
+    pub k1: String,
+    pub card_id: i64,
+    pub status: models::PaymentStatus,
+    pub amount_msats: Option<i64>,
+    pub payment_hash: Option<String>,
+    pub failure_reason: Option<String>,
+    /// Invoices tried so far
+    pub attempts: i64,
+    pub payment_time: Option<String>,
+    pub memo: Option<String>,
+    /// Address the withdrawal was paid to on-chain instead of an invoice
+    pub onchain_address: Option<String>,
+    pub onchain_txid: Option<String>,
+}
+
+impl From<models::CardPayment> for PaymentStatus {
+    fn from(payment: models::CardPayment) -> Self {
+        Self {
+            k1: payment.k1,
+            card_id: payment.card_id,
+            status: payment.status,
+            amount_msats: payment.amount_msats,
+            payment_hash: payment.payment_hash,
+            failure_reason: payment.failure_reason,
+            attempts: payment.attempts,
+            payment_time: payment.payment_time,
+            memo: payment.memo,
+            onchain_address: payment.onchain_address,
+            onchain_txid: payment.onchain_txid,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetMemoRequest {
+    memo: Option<String>,
+}
+
+/// GET /v1/payments/{k1}
+/// Status of a withdrawal session, used to follow payments made in the background
+pub async fn get_payment(
+    Path(k1): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<PaymentStatus>, StatusCode> {
+    let payment = queries::get_payment_by_k1(&state.pool, &k1)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(payment.into()))
+}
+
+/// PUT /v1/payments/{k1}/memo
+/// Annotate a withdrawal, e.g. for expense reports; an empty or `null` memo removes it
+pub async fn set_memo(
+    Path(k1): Path<String>,
+    State(state): State<AppState>,
+    Json(req): Json<SetMemoRequest>,
+) -> Result<Json<PaymentStatus>, StatusCode> {
+    let memo = req.memo.map(|m| m.trim().to_string()).filter(|m| !m.is_empty());
+    if memo.as_ref().is_some_and(|m| m.chars().count() > memos::MAX_MEMO_LENGTH) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let payment = queries::get_payment_by_k1(&state.pool, &k1)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    memos::set_memo(&state.pool, payment.payment_id, memo.as_deref(), "api")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(PaymentStatus { memo, ..payment.into() }))
+}
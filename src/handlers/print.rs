@@ -0,0 +1,110 @@
+use axum::{
+    extract::{Path, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+
+use crate::{
+    app_state::AppState,
+    db::{models::Card, organizations, queries},
+    pdf::{Page, PdfDocument, PAGE_HEIGHT, PAGE_WIDTH},
+};
+
+const QR_SIZE: f32 = 200.0;
+
+#[derive(Debug, Deserialize)]
+pub struct BatchInsertsRequest {
+    pub card_ids: Vec<i64>,
+}
+
+/// GET /v1/cards/{card_id}/insert.pdf
+/// Print-ready card insert with the registration QR code
+pub async fn card_insert_pdf(
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Response, StatusCode> {
+    let card = queries::get_unregistered_card(&state.pool, card_id, state.clock.now())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let domain = organizations::get_card_domain(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut doc = PdfDocument::new();
+    doc.add_page(render_insert(&state, &card, domain.as_deref())?);
+
+    Ok(pdf_response(doc, &format!("card-{}.pdf", card_id)))
+}
+
+/// POST /v1/cards/inserts.pdf
+/// One insert page per card, for printing a batch of handouts at once
+pub async fn batch_inserts_pdf(
+    State(state): State<AppState>,
+    Json(req): Json<BatchInsertsRequest>,
+) -> Result<Response, StatusCode> {
+    if req.card_ids.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut doc = PdfDocument::new();
+    for card_id in req.card_ids {
+        // Every card in the batch must still be programmable, otherwise the handout is useless
+        let card = queries::get_unregistered_card(&state.pool, card_id, state.clock.now())
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::NOT_FOUND)?;
+        let domain = organizations::get_card_domain(&state.pool, card_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        doc.add_page(render_insert(&state, &card, domain.as_deref())?);
+    }
+
+    Ok(pdf_response(doc, "cards.pdf"))
+}
+
+/// Render a card's insert, with the registration URL on `domain` if its organization has one
+fn render_insert(state: &AppState, card: &Card, domain: Option<&str>) -> Result<Page, StatusCode> {
+    let one_time_code = card.one_time_code.as_deref().ok_or(StatusCode::NOT_FOUND)?;
+    let url = format!("{}?a={}", state.config.urls_on(domain).registration_base(), one_time_code);
+
+    let mut page = Page::default();
+    if let Some(brand) = &state.config.brand_name {
+        page.text_centered(PAGE_HEIGHT - 25.0, 9.0, brand);
+    }
+    page.text_centered(PAGE_HEIGHT - 50.0, 18.0, &card.card_name);
+    page.qr_code(
+        (PAGE_WIDTH - QR_SIZE) / 2.0,
+        PAGE_HEIGHT - 75.0,
+        QR_SIZE,
+        &url,
+    )
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut y = PAGE_HEIGHT - 75.0 - QR_SIZE - 30.0;
+    page.text_centered(y, 10.0, "Scan with the Bolt Card programming app");
+    y -= 25.0;
+    page.text_centered(y, 11.0, &format!("Per payment: {} sats", card.tx_limit_sats));
+    y -= 16.0;
+    page.text_centered(y, 11.0, &format!("Per day: {} sats", card.day_limit_sats));
+    y -= 25.0;
+    page.text_centered(y, 8.0, one_time_code);
+
+    Ok(page)
+}
+
+fn pdf_response(doc: PdfDocument, filename: &str) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, "application/pdf".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("inline; filename=\"{}\"", filename),
+            ),
+        ],
+        doc.to_bytes(),
+    )
+        .into_response()
+}
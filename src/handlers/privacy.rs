@@ -0,0 +1,45 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+
+use crate::{
+    app_state::AppState,
+    db::privacy::{self, CardExport, Erasure},
+};
+
+/// GET /v1/cards/{card_id}/export
+/// Everything recorded about a card except its keys, for data access requests
+pub async fn export_card(
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<CardExport>, StatusCode> {
+    privacy::export_card(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// POST /v1/cards/{card_id}/erase
+/// Erase a card's personal data, keeping its payments' amounts for accounting
+pub async fn erase_card(Path(card_id): Path<i64>, State(state): State<AppState>) -> Result<StatusCode, StatusCode> {
+    let erasure = privacy::erase_card(&state.pool, card_id, "api")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    erasure_status(erasure)
+}
+
+/// `204` once erased, `409` while a payment is being made
+pub fn erasure_status(erasure: Erasure) -> Result<StatusCode, StatusCode> {
+    match erasure {
+        Erasure::Erased(card_ids) => {
+            tracing::info!("Erased personal data of cards {:?}", card_ids);
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Erasure::NotFound => Err(StatusCode::NOT_FOUND),
+        Erasure::PaymentsInFlight => Err(StatusCode::CONFLICT),
+    }
+}
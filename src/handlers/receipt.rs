@@ -0,0 +1,162 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Html,
+    Json,
+};
+use serde::Serialize;
+
+use crate::{
+    app_state::AppState,
+    db::{
+        models::{CardPayment, PaymentStatus},
+        queries,
+        receipts::{self, ReceiptKey},
+    },
+    handlers::html::{escape, localized_page},
+    i18n::Locale,
+    receipts::{Receipt, SignedReceipt},
+};
+
+#[derive(Debug, Serialize)]
+pub struct ReceiptVerification {
+    pub valid: bool,
+    /// Why the receipt isn't valid
+    pub reason: Option<String>,
+}
+
+/// The session's payment, only settled payments have a receipt
+async fn settled_payment(state: &AppState, k1: &str) -> Result<CardPayment, StatusCode> {
+    let payment = queries::get_payment_by_k1(&state.pool, k1)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    match payment.status {
+        PaymentStatus::Settled => Ok(payment),
+        _ => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+/// GET /receipt/{k1}
+/// Shareable proof of settlement for a paid withdrawal
+pub async fn get_receipt(
+    locale: Locale,
+    Path(k1): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Html<String>, StatusCode> {
+    let payment = settled_payment(&state, &k1).await?;
+
+    let amount_msats = payment.amount_msats.unwrap_or(0);
+
+    let mut rows = vec![
+        ("Amount", format!("{} sats", amount_msats / 1000)),
+        ("Time (UTC)", payment.payment_time.unwrap_or_default()),
+    ];
+    if let Some(memo) = payment.memo {
+        rows.push(("Note", memo));
+    }
+
+    if state.config.receipt_show_card_name {
+        let card = queries::get_card_by_id(&state.pool, payment.card_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if let Some(card) = card {
+            rows.push(("Card", card.card_name));
+        }
+    }
+
+    let onchain = payment.onchain_address.is_some();
+    match payment.onchain_address {
+        Some(address) => {
+            rows.push(("Address", address));
+            rows.push(("Transaction", payment.onchain_txid.unwrap_or_default()));
+        }
+        None => {
+            rows.push(("Payment hash", payment.payment_hash.unwrap_or_default()));
+            rows.push(("Preimage", payment.preimage.unwrap_or_default()));
+        }
+    }
+
+    let table: String = rows
+        .iter()
+        .map(|(label, value)| {
+            format!("<tr><th>{}</th><td><code>{}</code></td></tr>\n", locale.tr(label), escape(value))
+        })
+        .collect();
+
+    // Signed receipts prove settlement with the preimage, which on-chain payouts don't have
+    let body = match onchain {
+        false => format!(
+            "<h1>{}</h1>\n<p>{}</p>\n<table>\n{}</table>\n<p><a href=\"{}/receipt/{}/signed\">{}</a></p>",
+            locale.tr("Payment receipt"),
+            locale.tr("This withdrawal was settled over Lightning."),
+            table,
+            state.config.public.base_path,
+            escape(&k1),
+            locale.tr("Signed receipt")
+        ),
+        true => format!(
+            "<h1>{}</h1>\n<p>{}</p>\n<table>\n{}</table>",
+            locale.tr("Payment receipt"),
+            locale.tr("This withdrawal was paid on-chain."),
+            table
+        ),
+    };
+
+    Ok(localized_page(&state.config, locale, "Payment receipt", &body))
+}
+
+/// GET /receipt/{k1}/signed
+/// The receipt signed with the server's key, for third parties to verify
+pub async fn get_signed_receipt(
+    Path(k1): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<SignedReceipt>, StatusCode> {
+    let payment = settled_payment(&state, &k1).await?;
+    if payment.onchain_address.is_some() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let receipt = Receipt {
+        issuer: state.config.domain.clone(),
+        k1: payment.k1,
+        amount_msats: payment.amount_msats.unwrap_or(0),
+        payment_hash: payment.payment_hash.unwrap_or_default(),
+        preimage: payment.preimage.unwrap_or_default(),
+        settled_at: payment.payment_time.unwrap_or_default(),
+    };
+
+    Ok(Json(state.receipts.sign(receipt)))
+}
+
+/// GET /v1/receipts/keys
+/// Public keys receipts were signed with, the current one last
+pub async fn list_keys(State(state): State<AppState>) -> Result<Json<Vec<ReceiptKey>>, StatusCode> {
+    let keys = receipts::list_keys(&state.pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(keys))
+}
+
+/// POST /v1/receipts/verify
+/// Check a signed receipt against the keys of this server
+pub async fn verify_receipt(
+    State(state): State<AppState>,
+    Json(signed): Json<SignedReceipt>,
+) -> Result<Json<ReceiptVerification>, StatusCode> {
+    let keys: Vec<String> = receipts::list_keys(&state.pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .map(|key| key.public_key)
+        .collect();
+
+    let verification = match crate::receipts::verify(&signed, &keys) {
+        Ok(()) => ReceiptVerification { valid: true, reason: None },
+        Err(reason) => ReceiptVerification { valid: false, reason: Some(reason) },
+    };
+
+    Ok(Json(verification))
+}
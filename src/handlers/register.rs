@@ -1,32 +1,70 @@
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
     Json,
 };
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+use sqlx::{Pool, Sqlite};
 
 use crate::{
     app_state::AppState,
-    crypto::AesKey,
-    db::{models::{CreateCardRequest, CardRegistrationResponse}, queries},
+    config::Config,
+    random::Random,
+    db::{
+        idempotency::{self, Claim},
+        models::{Card, CreateCardRequest, CardRegistrationResponse},
+        organizations, queries, tags,
+    },
+    policy::{self, PolicyViolation},
+    tenant::Tenant,
 };
 
+/// Endpoint name idempotency keys of card creation are stored under
+const CREATE_CARD_ENDPOINT: &str = "createboltcard";
+
+/// An enabled card of the organization has the name already
+///
+/// Each organization's card names are its own namespace, its shops tell
+/// their cards apart by name. Cards without an organization may share names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CardNameTaken(pub String);
+
+impl std::fmt::Display for CardNameTaken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The organization has a card named {} already", self.0)
+    }
+}
+
+impl std::error::Error for CardNameTaken {}
+
 #[derive(Debug, Deserialize)]
 pub struct NewCardQuery {
     a: String,  // one-time authentication code
 }
 
-/// GET /new?a={one_time_code}
+/// GET /v1/new?a={one_time_code}
 /// Returns card configuration for NFC programming
+///
+/// The card is programmed with its organization's domain, if it has one.
 pub async fn get_card_registration(
+    tenant: Tenant,
     Query(params): Query<NewCardQuery>,
     State(state): State<AppState>,
 ) -> Result<Json<CardRegistrationResponse>, StatusCode> {
-    let card = queries::get_card_by_one_time_code(&state.pool, &params.a)
+    let card = queries::get_card_by_one_time_code(&state.pool, &params.a, state.clock.now())
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::NOT_FOUND)?;
+    if !tenant.serves_card(&state, card.card_id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)? {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let domain = organizations::get_card_domain(&state.pool, card.card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     // Mark the one-time code as used
     queries::mark_one_time_code_used(&state.pool, card.card_id)
@@ -37,7 +75,7 @@ pub async fn get_card_registration(
         protocol_name: "create_bolt_card_response".to_string(),
         protocol_version: 2,
         card_name: card.card_name,
-        lnurlw_base: state.config.lnurlw_base_with_card_id(card.card_id),
+        lnurlw_base: state.config.urls_on(domain.as_deref()).lnurlw_base_with_card_id(card.card_id),
         k0: card.k0_auth_key,
         k1: card.k1_decrypt_key,
         k2: card.k2_cmac_key,
@@ -48,36 +86,200 @@ pub async fn get_card_registration(
     Ok(Json(response))
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct CreateCardResponse {
     pub status: String,
     pub url: String,
 }
 
-/// POST /api/createboltcard
+/// A freshly created card awaiting programming
+#[derive(Debug)]
+pub struct CreatedCard {
+    pub card_id: i64,
+    pub one_time_code: String,
+}
+
+/// POST /v1/createboltcard
 /// Creates a new card with random keys
+///
+/// Requests with an `Idempotency-Key` header are answered once: retries with
+/// the same key and body get the original response instead of another card.
 pub async fn create_card(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(req): Json<CreateCardRequest>,
 ) -> Result<Json<CreateCardResponse>, StatusCode> {
+    let Some(key) = headers.get("idempotency-key") else {
+        return create_card_response(&state, &req).await.map(Json);
+    };
+    let key = key
+        .to_str()
+        .ok()
+        .filter(|key| !key.is_empty() && key.len() <= 255)
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    let body = serde_json::to_vec(&req).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let request_hash = hex::encode(Sha256::digest(&body));
+    let claim = idempotency::claim(&state.pool, CREATE_CARD_ENDPOINT, key, &request_hash)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match claim {
+        Claim::New => {}
+        Claim::Replay(response) => {
+            return serde_json::from_str(&response)
+                .map(Json)
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        Claim::InProgress => return Err(StatusCode::CONFLICT),
+        Claim::Mismatch => return Err(StatusCode::UNPROCESSABLE_ENTITY),
+    }
+
+    let response = match create_card_response(&state, &req).await {
+        Ok(response) => response,
+        Err(status) => {
+            if let Err(e) = idempotency::release(&state.pool, CREATE_CARD_ENDPOINT, key).await {
+                tracing::error!("Failed to release idempotency key: {}", e);
+            }
+            return Err(status);
+        }
+    };
+
+    // The card exists either way, so a failure to store the response doesn't fail the request
+    let stored = serde_json::to_string(&response).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if let Err(e) = idempotency::complete(&state.pool, CREATE_CARD_ENDPOINT, key, &stored).await {
+        tracing::error!("Failed to store response for idempotency key: {}", e);
+    }
+
+    Ok(Json(response))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReplaceCardResponse {
+    pub status: String,
+    /// ID of the replacement
+    pub card_id: i64,
+    pub replaced_card_id: i64,
+    /// Balance moved to the replacement
+    pub balance_msats: i64,
+    /// Registration URL to program the replacement with
+    pub url: String,
+}
+
+/// POST /v1/cards/{card_id}/replace
+/// Issue a replacement for a broken or lost card and disable the old one
+pub async fn replace_card(
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<ReplaceCardResponse>, StatusCode> {
+    let card = queries::get_card_by_id(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let replacement = replace_card_record(&state.pool, &state.random, &card, state.clock.now(), "api")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tracing::info!("Card {} replaced by card {}", card_id, replacement.card.card_id);
+
+    let domain = organizations::get_org_domain(&state.pool, replacement.org_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let url = format!(
+        "{}?a={}",
+        state.config.urls_on(domain.as_deref()).registration_base(),
+        replacement.card.one_time_code
+    );
+
+    Ok(Json(ReplaceCardResponse {
+        status: "OK".to_string(),
+        card_id: replacement.card.card_id,
+        replaced_card_id: card_id,
+        balance_msats: replacement.balance_msats,
+        url,
+    }))
+}
+
+/// Create a card within its owner's policy, 403 if the owner may not have it
+/// and 409 if its organization has a card of the name
+pub async fn create_card_response(state: &AppState, req: &CreateCardRequest) -> Result<CreateCardResponse, StatusCode> {
+    let mut req = req.clone();
+    policy::apply_to_new_card(&state.pool, &state.config, &mut req).await.map_err(|e| {
+        if e.is::<PolicyViolation>() {
+            StatusCode::FORBIDDEN
+        } else {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    let created = create_card_record(&state.pool, &state.config, &state.random, &req, state.clock.now())
+        .await
+        .map_err(|e| {
+            if e.is::<CardNameTaken>() {
+                StatusCode::CONFLICT
+            } else {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    let domain = organizations::get_org_domain(&state.pool, req.org_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let url = format!("{}?a={}", state.config.urls_on(domain.as_deref()).registration_base(), created.one_time_code);
+
+    Ok(CreateCardResponse {
+        status: "OK".to_string(),
+        url,
+    })
+}
+
+/// Generate keys and a one-time code and store the new card
+///
+/// Fails with [`CardNameTaken`] if the card's organization has an enabled card of the name.
+pub async fn create_card_record(
+    pool: &Pool<Sqlite>,
+    config: &Config,
+    random: &Random,
+    req: &CreateCardRequest,
+    now: DateTime<Utc>,
+) -> Result<CreatedCard> {
+    if let Some(org_id) = req.org_id
+        && organizations::card_name_taken(pool, org_id, &req.card_name).await?
+    {
+        return Err(CardNameTaken(req.card_name.clone()).into());
+    }
+
+    // Use defaults from config if not specified
+    let tx_limit = req.tx_limit_sats.unwrap_or(config.default_tx_limit as i64);
+    let day_limit = req.day_limit_sats.unwrap_or(config.default_day_limit as i64);
+
+    insert_card_record(pool, random, req, tx_limit, day_limit, now).await
+}
+
+/// Store a new card without checking its name, for a replacement taking over its card's
+async fn insert_card_record(
+    pool: &Pool<Sqlite>,
+    random: &Random,
+    req: &CreateCardRequest,
+    tx_limit: i64,
+    day_limit: i64,
+    now: DateTime<Utc>,
+) -> Result<CreatedCard> {
     // Generate all keys
-    let k0 = AesKey::generate();
-    let k1 = AesKey::generate();
-    let k2 = AesKey::generate();
-    let k3 = AesKey::generate();
-    let k4 = AesKey::generate();
+    let k0 = random.aes_key()?;
+    let k1 = random.aes_key()?;
+    let k2 = random.aes_key()?;
+    let k3 = random.aes_key()?;
+    let k4 = random.aes_key()?;
 
     // Generate one-time code
-    let one_time_code = hex::encode(rand::random::<[u8; 16]>());
+    let one_time_code = random.hex::<16>()?;
 
-    // Use defaults from config if not specified
-    let tx_limit = req.tx_limit_sats.unwrap_or(state.config.default_tx_limit as i64);
-    let day_limit = req.day_limit_sats.unwrap_or(state.config.default_day_limit as i64);
     let enabled = req.enabled.unwrap_or(true);
+    let balance_mode = req.balance_mode.unwrap_or(false);
 
     // Insert card into database (UID will be set on first use)
-    queries::insert_card(
-        &state.pool,
+    let card_id = queries::insert_card(
+        pool,
         "",  // UID empty initially
         &k0.to_string(),
         &k1.to_string(),
@@ -88,15 +290,171 @@ pub async fn create_card(
         tx_limit,
         day_limit,
         enabled,
+        balance_mode,
         &one_time_code,
+        req.owner_id,
+        req.org_id,
+        now,
     )
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .await?;
 
-    let url = format!("{}?a={}", state.config.registration_base(), one_time_code);
+    if let Some(card_tags) = &req.tags {
+        tags::set_card_tags(pool, card_id, card_tags).await?;
+    }
 
-    Ok(Json(CreateCardResponse {
-        status: "OK".to_string(),
-        url,
-    }))
-}
\ No newline at end of file
+    Ok(CreatedCard {
+        card_id,
+        one_time_code,
+    })
+}
+
+/// A card issued in place of another
+#[derive(Debug)]
+pub struct Replacement {
+    pub card: CreatedCard,
+    /// Organization of both cards
+    pub org_id: Option<i64>,
+    /// Balance moved over from the old card
+    pub balance_msats: i64,
+}
+
+/// Issue a new card with the old card's name, limits, owner, tags, metadata, description and balance and disable the old one
+///
+/// Both happen in one transaction, so a failure leaves the old card as it was.
+/// The owner's policy doesn't apply, the replacement only takes over what the old card had.
+pub async fn replace_card_record(
+    pool: &Pool<Sqlite>,
+    random: &Random,
+    card: &Card,
+    now: DateTime<Utc>,
+    actor: &str,
+) -> Result<Replacement> {
+    let one_time_code = random.hex::<16>()?;
+    let (card_id, balance_msats) = queries::insert_replacement(
+        pool,
+        card.card_id,
+        &random.aes_key()?.to_string(),
+        &random.aes_key()?.to_string(),
+        &random.aes_key()?.to_string(),
+        &random.aes_key()?.to_string(),
+        &random.aes_key()?.to_string(),
+        &one_time_code,
+        now,
+        actor,
+    )
+    .await?;
+
+    Ok(Replacement {
+        card: CreatedCard {
+            card_id,
+            one_time_code,
+        },
+        // The balance moved to the replacement, so it stays on the same backend
+        org_id: organizations::get_card_org(pool, card_id).await?,
+        balance_msats,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_card_names_are_unique_per_organization() {
+        let (state, _) = crate::app_state::test_state(&[]).await;
+        let shop_a = organizations::create_organization(&state.pool, "shop-a", "Shop A", "mock", None).await.unwrap();
+        let shop_b = organizations::create_organization(&state.pool, "shop-b", "Shop B", "mock", None).await.unwrap();
+        let create = |org_id| {
+            let req = CreateCardRequest {
+                card_name: "Till 1".to_string(),
+                tx_limit_sats: None,
+                day_limit_sats: None,
+                enabled: None,
+                balance_mode: None,
+                tags: None,
+                owner_id: None,
+                org_id,
+            };
+            let state = state.clone();
+            async move { create_card_record(&state.pool, &state.config, &state.random, &req, state.clock.now()).await }
+        };
+
+        let till = create(shop_a).await.unwrap();
+        let error = create(shop_a).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<CardNameTaken>(), Some(&CardNameTaken("Till 1".to_string())));
+        create(shop_b).await.unwrap();
+        create(None).await.unwrap();
+        create(None).await.unwrap();
+
+        // The replacement takes over the name, and frees it once disabled
+        let card = queries::get_card_by_id(&state.pool, till.card_id).await.unwrap().unwrap();
+        let replacement = replace_card_record(&state.pool, &state.random, &card, state.clock.now(), "test").await.unwrap();
+        let replacement = queries::get_card_by_id(&state.pool, replacement.card.card_id).await.unwrap().unwrap();
+        assert_eq!(replacement.card_name, "Till 1");
+        assert!(create(shop_a).await.is_err());
+        queries::set_card_enabled(&state.pool, replacement.card_id, false).await.unwrap();
+        create(shop_a).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replace_card() {
+        let (state, _) = crate::app_state::test_state(&[]).await;
+        let key = "00000000000000000000000000000000";
+        let card_id = queries::insert_card(&state.pool, "04996c6a926980", key, key, key, key, key, "Card", 1000, 10000, true, true, "code", None, None, state.clock.now())
+            .await
+            .unwrap();
+        queries::credit_balance(&state.pool, card_id, 5_000_000).await.unwrap();
+        tags::set_card_tags(&state.pool, card_id, &["staff".to_string()]).await.unwrap();
+        crate::db::onchain::set_card_address(&state.pool, card_id, Some("bcrt1qcardaddress"), "test").await.unwrap();
+        let card = queries::get_card_by_id(&state.pool, card_id).await.unwrap().unwrap();
+
+        let replacement = replace_card_record(&state.pool, &state.random, &card, state.clock.now(), "test").await.unwrap();
+        assert_eq!(replacement.balance_msats, 5_000_000);
+
+        let old = queries::get_card_by_id(&state.pool, card_id).await.unwrap().unwrap();
+        assert!(!old.enabled);
+        assert_eq!(old.balance_msats, 0);
+        let new = queries::get_card_by_id(&state.pool, replacement.card.card_id).await.unwrap().unwrap();
+        assert!(new.enabled);
+        assert_eq!(new.uid, "");
+        assert_eq!((new.card_name.as_str(), new.tx_limit_sats, new.day_limit_sats), ("Card", 1000, 10000));
+        assert_eq!(new.balance_msats, 5_000_000);
+        assert_ne!(new.k1_decrypt_key, key);
+        assert_eq!(new.one_time_code, Some(replacement.card.one_time_code));
+        assert_eq!(tags::get_card_tags(&state.pool, new.card_id).await.unwrap(), vec!["staff"]);
+        assert_eq!(
+            crate::db::onchain::get_card_address(&state.pool, new.card_id).await.unwrap().flatten().as_deref(),
+            Some("bcrt1qcardaddress")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_replacement_leaves_the_card() {
+        let (state, _) = crate::app_state::test_state(&[]).await;
+        let key = "00000000000000000000000000000000";
+        let card_id = queries::insert_card(&state.pool, "04996c6a926980", key, key, key, key, key, "Card", 1000, 10000, true, true, "code", None, None, state.clock.now())
+            .await
+            .unwrap();
+        queries::credit_balance(&state.pool, card_id, 5_000_000).await.unwrap();
+        tags::set_card_tags(&state.pool, card_id, &["staff".to_string()]).await.unwrap();
+        let card = queries::get_card_by_id(&state.pool, card_id).await.unwrap().unwrap();
+
+        // Disabling the old card is the last step, failing it must undo the replacement
+        sqlx::query(
+            "CREATE TRIGGER fail_disable BEFORE UPDATE OF enabled ON cards
+             BEGIN SELECT RAISE(ABORT, 'disabling failed'); END"
+        )
+        .execute(&state.pool)
+        .await
+        .unwrap();
+        assert!(replace_card_record(&state.pool, &state.random, &card, state.clock.now(), "test").await.is_err());
+
+        let cards: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM cards").fetch_one(&state.pool).await.unwrap();
+        assert_eq!(cards, 1);
+        let tagged: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM card_tags").fetch_one(&state.pool).await.unwrap();
+        assert_eq!(tagged, 1);
+        let card = queries::get_card_by_id(&state.pool, card_id).await.unwrap().unwrap();
+        assert!(card.enabled);
+        assert_eq!(card.balance_msats, 5_000_000);
+    }
+}
@@ -0,0 +1,70 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::Datelike;
+use serde::Deserialize;
+
+use crate::{
+    app_state::AppState,
+    db::fiat,
+    reports::{self, FiatReportFormat},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct FiatReportQuery {
+    pub year: Option<i32>,
+    pub month: Option<u32>,
+    pub format: Option<FiatReportFormat>,
+    /// One line per withdrawal instead of monthly summaries (CSV and JSON)
+    #[serde(default)]
+    pub payments: bool,
+}
+
+/// GET /v1/reports/fiat?year={yyyy}&month={m}&format={csv|pdf|json}&payments={bool}
+/// Settled withdrawals valued at the bitcoin price recorded when they settled
+pub async fn fiat_report(Query(params): Query<FiatReportQuery>, State(state): State<AppState>) -> Response {
+    let year = params.year.unwrap_or_else(|| chrono::Utc::now().year());
+    let Some((from, to)) = reports::period(year, params.month) else {
+        return (StatusCode::BAD_REQUEST, "month must be 1 to 12").into_response();
+    };
+
+    let payments = match fiat::settled_payments(&state.pool, from, to).await {
+        Ok(payments) => payments,
+        Err(e) => {
+            tracing::error!("Failed to load payments for the fiat report: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let name = match params.month {
+        Some(month) => format!("withdrawals-{}-{:02}", year, month),
+        None => format!("withdrawals-{}", year),
+    };
+    let attachment = |content_type: &str, extension: &str, body: Vec<u8>| {
+        (
+            [
+                (header::CONTENT_TYPE, content_type.to_string()),
+                (header::CONTENT_DISPOSITION, format!("inline; filename=\"{}.{}\"", name, extension)),
+            ],
+            body,
+        )
+            .into_response()
+    };
+
+    match (params.format.unwrap_or(FiatReportFormat::Json), params.payments) {
+        (FiatReportFormat::Json, true) => Json(payments).into_response(),
+        (FiatReportFormat::Json, false) => Json(reports::monthly(&payments)).into_response(),
+        (FiatReportFormat::Csv, true) => attachment("text/csv", "csv", reports::payments_csv(&payments).into_bytes()),
+        (FiatReportFormat::Csv, false) => {
+            attachment("text/csv", "csv", reports::summary_csv(&reports::monthly(&payments)).into_bytes())
+        }
+        (FiatReportFormat::Pdf, true) => (StatusCode::BAD_REQUEST, "PDF reports are monthly summaries").into_response(),
+        (FiatReportFormat::Pdf, false) => {
+            let doc = reports::summary_pdf(&format!("Withdrawals {} to {}", from, to), &reports::monthly(&payments));
+            attachment("application/pdf", "pdf", doc.to_bytes())
+        }
+    }
+}
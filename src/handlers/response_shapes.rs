@@ -0,0 +1,477 @@
+//! Snapshots of the JSON the endpoints answer with
+//!
+//! Wallets and integrations parse these bodies, so a renamed or dropped field
+//! breaks them without any compiler error. A change to a shape shows up here
+//! as a changed file under `snapshots/` that has to be accepted with
+//! `cargo insta review`. The fields the Go reference server
+//! (github.com/boltcard/boltcard) sends are checked on their own, as wallets
+//! and the card programming apps were written against it.
+
+use axum::{body::to_bytes, response::IntoResponse};
+use insta::assert_json_snapshot;
+use serde_json::{Value, json};
+
+use super::{
+    bulk::BulkResponse,
+    description::DescriptionTemplate,
+    diagnostics::{CardDiagnostics, TapFailure},
+    error::ApiError,
+    fees::{CardFee, FeesResponse},
+    freeze::FreezeResponse,
+    lost::ReportLostResponse,
+    onchain::OnchainFallback,
+    lnurlw::{CallbackResponse, LnurlwResponse},
+    payments::PaymentStatus,
+    receipt::ReceiptVerification,
+    register::{CreateCardResponse, ReplaceCardResponse},
+    tokens::CreateTokenResponse,
+    topup::{TopupResponse, TopupStatus},
+};
+use crate::{
+    db::{
+        fees::{AccruedFees, FeePayout},
+        metadata::CardMetadata,
+        models::{self, CardRegistrationResponse, WebhookEvent},
+        stats::{CardVolume, DailyStats, StatsReport},
+    },
+    diagnostics::{CounterGaps, KeyCheck},
+    i18n::Locale,
+    receipts::{Receipt, ReceiptSigner},
+    validation::{AuthError, ParamError, TapParam},
+};
+
+/// Fields the reference sends with the JSON type of their value; ours may have more
+const REFERENCE_WITHDRAW_REQUEST: [(&str, &str); 6] = [
+    ("tag", "string"),
+    ("callback", "string"),
+    ("k1", "string"),
+    ("defaultDescription", "string"),
+    ("minWithdrawable", "number"),
+    ("maxWithdrawable", "number"),
+];
+const REFERENCE_CALLBACK: [(&str, &str); 1] = [("status", "string")];
+const REFERENCE_ERROR: [(&str, &str); 2] = [("status", "string"), ("reason", "string")];
+const REFERENCE_NEW_CARD: [(&str, &str); 9] = [
+    ("protocol_name", "string"),
+    ("protocol_version", "number"),
+    ("card_name", "string"),
+    ("lnurlw_base", "string"),
+    ("k0", "string"),
+    ("k1", "string"),
+    ("k2", "string"),
+    ("k3", "string"),
+    ("k4", "string"),
+];
+
+const K1: &str = "5b6a0d6a2e4f8c1d9e3b7a6f0c2d4e8f";
+const INVOICE: &str = "lnbcrt210n1pjexample";
+const PAYMENT_HASH: &str = "8e1f0c5d2b7a4e6f9c3d1b0a2e4f6c8d0b1a3c5e7f9d2b4a6c8e0f1d3b5a7c9e";
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn assert_has_reference_fields(body: &impl serde::Serialize, fields: &[(&str, &str)]) {
+    let body = serde_json::to_value(body).unwrap();
+    for (field, kind) in fields {
+        let value = body.get(field).unwrap_or_else(|| panic!("{} is missing in {}", field, body));
+        assert_eq!(json_type(value), *kind, "{} in {}", field, body);
+    }
+}
+
+async fn error_body(error: ApiError, locale: Locale) -> Value {
+    let response = error.localize(locale).into_response();
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+fn withdraw_request() -> LnurlwResponse {
+    LnurlwResponse {
+        status: "OK".to_string(),
+        callback: "https://card.example.com/ln/callback".to_string(),
+        k1: K1.to_string(),
+        default_description: "Bolt Card withdrawal".to_string(),
+        min_withdrawable: 1_000,
+        max_withdrawable: 100_000_000,
+        tag: "withdrawRequest".to_string(),
+    }
+}
+
+#[test]
+fn lnurlw_withdraw_request() {
+    assert_json_snapshot!(withdraw_request());
+}
+
+#[test]
+fn lnurlw_callback() {
+    assert_json_snapshot!(CallbackResponse { status: "OK".to_string() });
+}
+
+#[tokio::test]
+async fn lnurlw_error() {
+    assert_json_snapshot!(error_body(ApiError::ReplayDetected, Locale::En).await);
+    assert_json_snapshot!("lnurlw_error_translated", error_body(ApiError::ReplayDetected, Locale::De).await);
+}
+
+/// Clients branch on the codes, which have to stay as they are
+#[tokio::test]
+async fn lnurlw_error_codes() {
+    let errors = [
+        ApiError::Database,
+        ApiError::CardNotFound,
+        ApiError::CardFrozen { until: Some("2025-03-08 12:00:00 UTC".to_string()) },
+        ApiError::CardUnderReview,
+        ApiError::MalformedQuery,
+        ApiError::InvalidParameter(ParamError::Missing(TapParam::P)),
+        ApiError::InvalidParameter(ParamError::OddLength(TapParam::C)),
+        ApiError::InvalidParameter(ParamError::TooShort(TapParam::P)),
+        ApiError::InvalidParameter(ParamError::TooLong(TapParam::C)),
+        ApiError::InvalidParameter(ParamError::NotHex(TapParam::P)),
+        ApiError::AuthenticationFailed(AuthError::InvalidK1Key),
+        ApiError::AuthenticationFailed(AuthError::InvalidCmac),
+        ApiError::UidMismatch,
+        ApiError::CounterExhausted,
+        ApiError::ReplayDetected,
+        ApiError::CounterUpdateFailed,
+        ApiError::InvalidK1,
+        ApiError::SessionExpired,
+        ApiError::AttemptsExhausted,
+        ApiError::PaymentAlreadyProcessed,
+        ApiError::InvalidInvoice,
+        ApiError::InvoiceWithoutAmount,
+        ApiError::WrongNetwork,
+        ApiError::AmountOutOfRange,
+        ApiError::OnchainNotAllowed,
+        ApiError::InvalidAddress,
+        ApiError::DescriptionMismatch,
+        ApiError::TxLimitExceeded,
+        ApiError::DailyLimitExceeded,
+        ApiError::InsufficientBalance,
+        ApiError::CapitalExhausted,
+        ApiError::PolicyDenied(None),
+        ApiError::PaymentFailed("no route".to_string()),
+        ApiError::PaymentPending,
+        ApiError::LoginExpired,
+        ApiError::InvalidSignature,
+    ];
+    let mut bodies = Vec::new();
+    for error in errors {
+        let status = error.status().as_u16();
+        bodies.push(json!({"status": status, "body": error_body(error, Locale::En).await}));
+    }
+    assert_json_snapshot!(bodies);
+}
+
+fn card_registration_response() -> CardRegistrationResponse {
+    CardRegistrationResponse {
+        protocol_name: "create_bolt_card_response".to_string(),
+        protocol_version: 2,
+        card_name: "Alice".to_string(),
+        lnurlw_base: "lnurlw://card.example.com/ln?card_id=1".to_string(),
+        k0: "00000000000000000000000000000000".to_string(),
+        k1: "0c3b25d92b38ae443229dd59ad34b85d".to_string(),
+        k2: "b45775776cb224c75bcde7ca3704e933".to_string(),
+        k3: "00000000000000000000000000000000".to_string(),
+        k4: "00000000000000000000000000000000".to_string(),
+    }
+}
+
+#[test]
+fn card_registration() {
+    assert_json_snapshot!(card_registration_response());
+}
+
+#[tokio::test]
+async fn boltcard_reference_fields() {
+    assert_has_reference_fields(&withdraw_request(), &REFERENCE_WITHDRAW_REQUEST);
+    assert_has_reference_fields(&CallbackResponse { status: "OK".to_string() }, &REFERENCE_CALLBACK);
+    assert_has_reference_fields(&error_body(ApiError::InvalidK1, Locale::En).await, &REFERENCE_ERROR);
+    assert_has_reference_fields(&card_registration_response(), &REFERENCE_NEW_CARD);
+}
+
+#[test]
+fn create_card() {
+    assert_json_snapshot!(CreateCardResponse {
+        status: "OK".to_string(),
+        url: "https://card.example.com/v1/new?a=9f86d081884c7d65".to_string(),
+    });
+}
+
+#[test]
+fn payment_status() {
+    assert_json_snapshot!(PaymentStatus {
+        k1: K1.to_string(),
+        card_id: 1,
+        status: models::PaymentStatus::Settled,
+        amount_msats: Some(21_000),
+        payment_hash: Some(PAYMENT_HASH.to_string()),
+        failure_reason: None,
+        attempts: 1,
+        payment_time: Some("2025-03-01 12:00:00".to_string()),
+        memo: Some("Team lunch".to_string()),
+        onchain_address: None,
+        onchain_txid: None,
+    });
+}
+
+#[test]
+fn stats() {
+    assert_json_snapshot!(StatsReport {
+        days: 7,
+        card_id: None,
+        total_volume_msats: 26_000_000,
+        total_payments: 2,
+        total_failures: 1,
+        failure_rate: 1.0 / 3.0,
+        daily: vec![DailyStats { day: "2025-03-01".to_string(), volume_msats: 26_000_000, payments: 2, failures: 1 }],
+        top_cards: vec![CardVolume { card_id: 1, card_name: "Alice".to_string(), volume_msats: 26_000_000, payments: 2 }],
+    });
+}
+
+#[test]
+fn fees() {
+    assert_json_snapshot!(FeesResponse {
+        accrued: vec![AccruedFees { org_id: None, org_slug: None, payments: 2, amount_msats: 260 }],
+        payouts: vec![FeePayout {
+            payout_id: 1,
+            org_id: Some(2),
+            amount_msats: 5_000,
+            destination: "fees@example.com".to_string(),
+            status: "paid".to_string(),
+            payment_hash: Some(PAYMENT_HASH.to_string()),
+            routing_fee_msats: Some(3),
+            error: None,
+            created_at: Some("2025-03-01 12:00:00".to_string()),
+            paid_at: Some("2025-03-01 12:00:05".to_string()),
+        }],
+    });
+}
+
+#[test]
+fn bulk_update() {
+    assert_json_snapshot!(BulkResponse { status: "OK".to_string(), card_ids: vec![1, 2] });
+}
+
+#[test]
+fn card_token() {
+    assert_json_snapshot!(CreateTokenResponse {
+        status: "OK".to_string(),
+        token: "c2d4e8f5b6a0d6a2".to_string(),
+        url: "https://card.example.com/lost/c2d4e8f5b6a0d6a2".to_string(),
+    });
+}
+
+#[test]
+fn topup() {
+    assert_json_snapshot!(TopupResponse {
+        status: "OK".to_string(),
+        payment_hash: PAYMENT_HASH.to_string(),
+        invoice: INVOICE.to_string(),
+    });
+    assert_json_snapshot!("topup_status", TopupStatus {
+        payment_hash: PAYMENT_HASH.to_string(),
+        amount_sats: 10_000,
+        paid: true,
+        balance_sats: 160_000,
+    });
+}
+
+#[test]
+fn webhook_events() {
+    assert_json_snapshot!(vec![WebhookEvent {
+        event_id: 1,
+        event: "payment_settled".to_string(),
+        payload: r#"{"card_id":1,"amount_msats":21000}"#.to_string(),
+        status: "delivered".to_string(),
+        attempts: 1,
+        last_error: None,
+        created_at: Some("2025-03-01 12:00:00".to_string()),
+        delivered_at: Some("2025-03-01 12:00:01".to_string()),
+    }]);
+}
+
+/// The types of `lnurlw-client` have to read what the server sends
+#[test]
+fn client_parses_responses() {
+    use lnurlw_client::models as client;
+
+    fn parse<T: serde::de::DeserializeOwned>(body: impl serde::Serialize) -> T {
+        serde_json::from_value(serde_json::to_value(body).unwrap()).unwrap()
+    }
+
+    let request: client::WithdrawRequest = parse(withdraw_request());
+    assert_eq!(request.k1, K1);
+    let _: client::CardRegistrationResponse = parse(card_registration_response());
+    let card: client::CreatedCard = parse(CreateCardResponse {
+        status: "OK".to_string(),
+        url: "https://card.example.com/v1/new?a=9f86d081884c7d65".to_string(),
+    });
+    assert_eq!(card.one_time_code(), Some("9f86d081884c7d65"));
+    let payment: client::Payment = parse(PaymentStatus {
+        k1: K1.to_string(),
+        card_id: 1,
+        status: models::PaymentStatus::Failed,
+        amount_msats: None,
+        payment_hash: None,
+        failure_reason: Some("no route".to_string()),
+        attempts: 1,
+        payment_time: None,
+        memo: None,
+        onchain_address: None,
+        onchain_txid: None,
+    });
+    assert_eq!(payment.status, client::PaymentStatus::Failed);
+    let _: client::Stats = parse(StatsReport {
+        days: 7,
+        card_id: Some(1),
+        total_volume_msats: 0,
+        total_payments: 0,
+        total_failures: 0,
+        failure_rate: 0.0,
+        daily: vec![DailyStats { day: "2025-03-01".to_string(), volume_msats: 0, payments: 0, failures: 0 }],
+        top_cards: vec![CardVolume { card_id: 1, card_name: "Alice".to_string(), volume_msats: 0, payments: 0 }],
+    });
+    let _: client::BulkResponse = parse(BulkResponse { status: "OK".to_string(), card_ids: vec![1] });
+    let _: client::CardToken = parse(CreateTokenResponse {
+        status: "OK".to_string(),
+        token: "c2d4e8f5b6a0d6a2".to_string(),
+        url: "https://card.example.com/widget/c2d4e8f5b6a0d6a2".to_string(),
+    });
+    let _: client::Topup = parse(TopupResponse {
+        status: "OK".to_string(),
+        payment_hash: PAYMENT_HASH.to_string(),
+        invoice: INVOICE.to_string(),
+    });
+    let _: client::TopupStatus =
+        parse(TopupStatus { payment_hash: PAYMENT_HASH.to_string(), amount_sats: 1, paid: false, balance_sats: 0 });
+}
+
+#[test]
+fn replace_card() {
+    let response = ReplaceCardResponse {
+        status: "OK".to_string(),
+        card_id: 2,
+        replaced_card_id: 1,
+        balance_msats: 150_000_000,
+        url: "https://card.example.com/v1/new?a=9f86d081884c7d65".to_string(),
+    };
+    assert_json_snapshot!(response);
+    let _: lnurlw_client::models::ReplacedCard = serde_json::from_value(serde_json::to_value(response).unwrap()).unwrap();
+}
+
+#[test]
+fn report_lost() {
+    let response = ReportLostResponse {
+        status: "OK".to_string(),
+        report_id: 3,
+        reported_lost_at: Some("2025-03-01 12:00:00".to_string()),
+    };
+    assert_json_snapshot!(response);
+    let _: lnurlw_client::models::LostReport = serde_json::from_value(serde_json::to_value(response).unwrap()).unwrap();
+}
+
+#[test]
+fn freeze_card() {
+    let response = FreezeResponse {
+        status: "OK".to_string(),
+        card_id: 1,
+        frozen_at: "2025-03-01 12:00:00".to_string(),
+        frozen_until: Some("2025-03-08 12:00:00".to_string()),
+        reason: Some("travelling".to_string()),
+    };
+    assert_json_snapshot!(response);
+    let _: lnurlw_client::models::FrozenCard = serde_json::from_value(serde_json::to_value(response).unwrap()).unwrap();
+}
+
+#[test]
+fn description_template() {
+    let response = DescriptionTemplate { template: Some("{merchant}: up to {remaining_sats} sats".to_string()) };
+    assert_json_snapshot!(response);
+    let _: lnurlw_client::models::DescriptionTemplate =
+        serde_json::from_value(serde_json::to_value(response).unwrap()).unwrap();
+}
+
+#[test]
+fn onchain_fallback() {
+    let response = OnchainFallback { address: Some("bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080".to_string()) };
+    assert_json_snapshot!(response);
+    let _: lnurlw_client::models::OnchainFallback =
+        serde_json::from_value(serde_json::to_value(response).unwrap()).unwrap();
+}
+
+#[test]
+fn card_diagnostics() {
+    let diagnostics = CardDiagnostics {
+        card_id: 1,
+        card_name: "Alice".to_string(),
+        enabled: true,
+        frozen: false,
+        frozen_until: None,
+        reported_lost_at: None,
+        clone_flagged_at: None,
+        deleted_at: None,
+        last_counter: 42,
+        counter_remaining: 16_777_173,
+        last_tap_at: Some("2025-03-01 12:05:00".to_string()),
+        last_successful_tap_at: Some("2025-03-01 12:00:00".to_string()),
+        counter_gaps: CounterGaps { taps: 3, min: Some(1), max: Some(4), mean: Some(2.5), skipped: 3 },
+        recent_failures: vec![TapFailure {
+            created_at: Some("2025-03-01 12:05:00".to_string()),
+            counter: None,
+            reason: Some("CMAC verification failed".to_string()),
+        }],
+        key_checks: vec![
+            KeyCheck { key: "uid", ok: true, problem: None },
+            KeyCheck { key: "k0", ok: false, problem: Some("factory default key".to_string()) },
+        ],
+        issues: vec!["k0: factory default key".to_string(), "Last tap failed: CMAC verification failed".to_string()],
+    };
+    assert_json_snapshot!(diagnostics);
+    let _: lnurlw_client::models::CardDiagnostics =
+        serde_json::from_value(serde_json::to_value(diagnostics).unwrap()).unwrap();
+}
+
+#[test]
+fn card_metadata() {
+    let metadata = json!({"employee_id": "E-1001", "table": 12}).as_object().unwrap().clone();
+    let cards = vec![CardMetadata { card_id: 1, card_name: "Alice".to_string(), metadata }];
+    assert_json_snapshot!(cards);
+    let _: Vec<lnurlw_client::models::CardMetadata> = serde_json::from_value(serde_json::to_value(cards).unwrap()).unwrap();
+}
+
+#[test]
+fn signed_receipt() {
+    let receipt = Receipt {
+        issuer: "card.example.com".to_string(),
+        k1: K1.to_string(),
+        amount_msats: 21_000,
+        payment_hash: PAYMENT_HASH.to_string(),
+        preimage: "0000000000000000000000000000000000000000000000000000000000000000".to_string(),
+        settled_at: "2025-03-01 12:00:00".to_string(),
+    };
+    let signed = ReceiptSigner::from_seed(&[1u8; 32]).unwrap().sign(receipt);
+    assert_json_snapshot!(signed);
+    let _: lnurlw_client::models::SignedReceipt = serde_json::from_value(serde_json::to_value(signed).unwrap()).unwrap();
+}
+
+#[test]
+fn receipt_verification() {
+    let verification = ReceiptVerification { valid: false, reason: Some("invalid signature".to_string()) };
+    assert_json_snapshot!(verification);
+    let _: lnurlw_client::models::ReceiptVerification =
+        serde_json::from_value(serde_json::to_value(verification).unwrap()).unwrap();
+}
+
+#[test]
+fn card_fee() {
+    let response = CardFee { card_id: 1, own: true, flat_msats: 1_000, percent: 0.5 };
+    assert_json_snapshot!(response);
+    let _: lnurlw_client::models::CardFee = serde_json::from_value(serde_json::to_value(response).unwrap()).unwrap();
+}
@@ -0,0 +1,38 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+
+use crate::{
+    app_state::AppState,
+    db::stats::{self, StatsReport},
+};
+
+const MAX_DAYS: i64 = 366;
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    pub days: Option<i64>,
+    pub card_id: Option<i64>,
+}
+
+impl StatsQuery {
+    pub fn days(&self) -> i64 {
+        self.days.unwrap_or(30).clamp(1, MAX_DAYS)
+    }
+}
+
+/// GET /v1/stats?days={n}&card_id={id}
+/// Daily volume, failures and top cards, globally or for one card
+pub async fn get_stats(
+    Query(params): Query<StatsQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<StatsReport>, StatusCode> {
+    let report = stats::report(&state.pool, params.days(), params.card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(report))
+}
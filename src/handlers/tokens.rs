@@ -0,0 +1,61 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    db::{organizations, queries, tokens::{self, TokenScope}},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTokenRequest {
+    pub scope: TokenScope,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateTokenResponse {
+    pub status: String,
+    pub token: String,
+    pub url: String,
+}
+
+/// POST /v1/cards/{card_id}/tokens
+/// Issue a cardholder link for one self-service page of a card
+pub async fn create_token(
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+    Json(req): Json<CreateTokenRequest>,
+) -> Result<Json<CreateTokenResponse>, StatusCode> {
+    let card = queries::get_card_by_id(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // Only prepaid cards have a balance to top up
+    if req.scope == TokenScope::TopUp && !card.balance_mode {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let token = tokens::create_card_token(&state.pool, &state.random, card_id, req.scope)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let domain = organizations::get_card_domain(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let urls = state.config.urls_on(domain.as_deref());
+    let url = match req.scope {
+        TokenScope::ReportLost => urls.lost_report_url(&token),
+        TokenScope::Widget => urls.widget_url(&token),
+        TokenScope::TopUp => urls.topup_url(&token),
+    };
+
+    Ok(Json(CreateTokenResponse {
+        status: "OK".to_string(),
+        token,
+        url,
+    }))
+}
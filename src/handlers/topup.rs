@@ -0,0 +1,341 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Redirect, Response},
+    Form, Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    db::{
+        models::{Card, CardTopup},
+        queries,
+        tokens::{self, TokenScope},
+        topups,
+    },
+    handlers::html::{escape, localized_page, qr_svg},
+    i18n::Locale,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct TopupRequest {
+    pub amount_sats: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TopupResponse {
+    pub status: String,
+    pub payment_hash: String,
+    pub invoice: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TopupStatus {
+    pub payment_hash: String,
+    pub amount_sats: i64,
+    pub paid: bool,
+    pub balance_sats: i64,
+}
+
+/// Create a Lightning invoice that credits `card` once paid
+pub async fn create_topup_invoice(state: &AppState, card: &Card, amount_sats: i64) -> Result<CardTopup, StatusCode> {
+    if !card.balance_mode {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if amount_sats < 1 || amount_sats > state.config.max_topup_sats {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let amount_msats = amount_sats.checked_mul(1000).ok_or(StatusCode::BAD_REQUEST)?;
+    let lightning = state.lightning.for_card(&state.pool, card.card_id).await.map_err(|e| {
+        tracing::error!("No Lightning backend for card {}: {}", card.card_id, e);
+        StatusCode::BAD_GATEWAY
+    })?;
+    let invoice = lightning
+        .create_invoice(amount_msats as u64, &format!("Top up {}", card.card_name))
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to create top-up invoice for card {}: {}", card.card_id, e);
+            StatusCode::BAD_GATEWAY
+        })?;
+
+    let payment_hash = invoice.payment_hash();
+    topups::create_topup(&state.pool, card.card_id, &payment_hash, &invoice.bolt11(), amount_msats)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    topups::get_topup_by_hash(&state.pool, &payment_hash)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Ask the backend whether an open top-up was paid and credit the card if so
+async fn refresh_topup(state: &AppState, mut topup: CardTopup) -> Result<CardTopup, StatusCode> {
+    if topup.paid {
+        return Ok(topup);
+    }
+
+    let lightning = state.lightning.for_card(&state.pool, topup.card_id).await.map_err(|e| {
+        tracing::error!("No Lightning backend for card {}: {}", topup.card_id, e);
+        StatusCode::BAD_GATEWAY
+    })?;
+    let paid = lightning
+        .is_invoice_paid(&topup.payment_hash)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to look up top-up invoice {}: {}", topup.payment_hash, e);
+            StatusCode::BAD_GATEWAY
+        })?;
+
+    if paid {
+        if topups::settle_topup(&state.pool, &topup)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        {
+            tracing::info!("Card {} topped up by {} msats", topup.card_id, topup.amount_msats);
+        }
+        topup.paid = true;
+    }
+
+    Ok(topup)
+}
+
+pub async fn topup_status(state: &AppState, topup: CardTopup) -> Result<TopupStatus, StatusCode> {
+    let topup = refresh_topup(state, topup).await?;
+
+    let card = queries::get_card_by_id(&state.pool, topup.card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(TopupStatus {
+        payment_hash: topup.payment_hash,
+        amount_sats: topup.amount_msats / 1000,
+        paid: topup.paid,
+        balance_sats: card.balance_msats / 1000,
+    })
+}
+
+/// POST /v1/cards/{card_id}/topups
+/// Create an invoice that tops up a balance-mode card
+pub async fn create_topup(
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+    Json(req): Json<TopupRequest>,
+) -> Result<Json<TopupResponse>, StatusCode> {
+    let card = queries::get_card_by_id(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let topup = create_topup_invoice(&state, &card, req.amount_sats).await?;
+
+    Ok(Json(TopupResponse {
+        status: "OK".to_string(),
+        payment_hash: topup.payment_hash,
+        invoice: topup.invoice,
+    }))
+}
+
+/// GET /v1/topups/{payment_hash}
+/// Top-up status, crediting the card if the invoice was paid since the last check
+pub async fn get_topup(
+    Path(payment_hash): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<TopupStatus>, StatusCode> {
+    let topup = topups::get_topup_by_hash(&state.pool, &payment_hash)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(topup_status(&state, topup).await?))
+}
+
+async fn card_for_token(state: &AppState, token: &str) -> Result<Card, StatusCode> {
+    let card = tokens::get_card_by_token(&state.pool, token, TokenScope::TopUp)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if !card.balance_mode {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(card)
+}
+
+/// GET /topup/{token}
+/// Cardholder page showing the balance and asking for a top-up amount
+pub async fn topup_page(
+    locale: Locale,
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Html<String>, StatusCode> {
+    let card = card_for_token(&state, &token).await?;
+
+    let body = format!(
+        r#"<h1>{}</h1>
+<p>{}</p>
+<form method="post">
+<p><label>{}<br><input name="amount_sats" type="number" min="1" max="{}" required></label></p>
+<p><button type="submit">{}</button></p>
+</form>"#,
+        locale.trf("Top up {card}", &[("card", &escape(&card.card_name))]),
+        locale.trf(
+            "Current balance: {amount}",
+            &[("amount", &format!("<strong>{} sats</strong>", card.balance_msats / 1000))],
+        ),
+        locale.tr("Amount (sats)"),
+        state.config.max_topup_sats,
+        locale.tr("Create invoice"),
+    );
+
+    Ok(localized_page(&state.config, locale, "Top up card", &body))
+}
+
+/// POST /topup/{token}
+pub async fn create_topup_from_page(
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+    Form(req): Form<TopupRequest>,
+) -> Result<Response, StatusCode> {
+    let card = card_for_token(&state, &token).await?;
+
+    let topup = create_topup_invoice(&state, &card, req.amount_sats).await?;
+
+    Ok(Redirect::to(&state.config.path(&format!("/topup/{}/{}", token, topup.payment_hash))).into_response())
+}
+
+/// GET /topup/{token}/{payment_hash}
+/// Shows the invoice QR and refreshes until it settles
+pub async fn topup_invoice_page(
+    locale: Locale,
+    Path((token, payment_hash)): Path<(String, String)>,
+    State(state): State<AppState>,
+) -> Result<Html<String>, StatusCode> {
+    let card = card_for_token(&state, &token).await?;
+
+    let topup = topups::get_topup_by_hash(&state.pool, &payment_hash)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .filter(|topup| topup.card_id == card.card_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let invoice = topup.invoice.clone();
+    let status = topup_status(&state, topup).await?;
+
+    let title = locale.trf("Top up {card}", &[("card", &escape(&card.card_name))]);
+    let amount = format!("<strong>{} sats</strong>", status.amount_sats);
+
+    let body = if status.paid {
+        format!(
+            r#"<h1>{}</h1>
+<p>{}</p>
+<p><a href="{}/topup/{}">{}</a></p>"#,
+            title,
+            locale.trf(
+                "Received {amount}. New balance: {balance}",
+                &[
+                    ("amount", &amount),
+                    ("balance", &format!("<strong>{} sats</strong>", status.balance_sats)),
+                ],
+            ),
+            state.config.public.base_path,
+            escape(&token),
+            locale.tr("Top up again"),
+        )
+    } else {
+        let lightning_uri = format!("lightning:{}", invoice.to_uppercase());
+        format!(
+            r#"<meta http-equiv="refresh" content="3">
+<h1>{}</h1>
+<p>{}</p>
+<p><a href="{}">{}</a></p>
+<p><code>{}</code></p>"#,
+            title,
+            locale.trf(
+                "Pay this invoice for {amount} with any Lightning wallet. This page updates once it is paid.",
+                &[("amount", &amount)],
+            ),
+            escape(&lightning_uri),
+            qr_svg(&lightning_uri).unwrap_or_default(),
+            escape(&invoice)
+        )
+    };
+
+    Ok(localized_page(&state.config, locale, "Top up card", &body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state::test_state;
+
+    #[tokio::test]
+    async fn test_topup_is_credited_once_paid() {
+        let (state, mock) = test_state(&[]).await;
+        mock.hold_invoices(true);
+        let key = "00000000000000000000000000000000";
+        let card_id = queries::insert_card(&state.pool, "04996c6a926980", key, key, key, key, key, "Card", 1000, 10000, true, true, "code", None, None, state.clock.now())
+            .await
+            .unwrap();
+        let card = queries::get_card_by_id(&state.pool, card_id).await.unwrap().unwrap();
+        let topup = create_topup_invoice(&state, &card, 2000).await.unwrap();
+        assert_eq!(topup.amount_msats, 2_000_000);
+        let status = || async {
+            let topup = topups::get_topup_by_hash(&state.pool, &topup.payment_hash).await.unwrap().unwrap();
+            topup_status(&state, topup).await.unwrap()
+        };
+
+        let unpaid = status().await;
+        assert!(!unpaid.paid);
+        assert_eq!(unpaid.balance_sats, 0);
+
+        mock.receive(&topup.payment_hash);
+        let paid = status().await;
+        assert!(paid.paid);
+        assert_eq!(paid.balance_sats, 2000);
+
+        // Asking again doesn't credit the invoice twice
+        assert_eq!(status().await.balance_sats, 2000);
+        let funded_msats: i64 = sqlx::query_scalar("SELECT funded_msats FROM cards WHERE card_id = ?")
+            .bind(card_id)
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(funded_msats, 2_000_000);
+        let asked = mock.calls().iter().filter(|call| call.method == "is_invoice_paid").count();
+        assert_eq!(asked, 2);
+    }
+
+    #[tokio::test]
+    async fn test_topup_refusals() {
+        let (state, mock) = test_state(&[]).await;
+        let key = "00000000000000000000000000000000";
+        let card = |balance_mode, code: &'static str| {
+            let pool = state.pool.clone();
+            async move {
+                let card_id = queries::insert_card(&pool, "", key, key, key, key, key, code, 1000, 10000, true, balance_mode, code, None, None, chrono::Utc::now())
+                    .await
+                    .unwrap();
+                queries::get_card_by_id(&pool, card_id).await.unwrap().unwrap()
+            }
+        };
+
+        // Only balance-mode cards hold a balance to top up
+        let limits_only = card(false, "limits").await;
+        assert_eq!(create_topup_invoice(&state, &limits_only, 1000).await.unwrap_err(), StatusCode::BAD_REQUEST);
+        let prepaid = card(true, "prepaid").await;
+        assert_eq!(create_topup_invoice(&state, &prepaid, 0).await.unwrap_err(), StatusCode::BAD_REQUEST);
+        let too_much = state.config.max_topup_sats + 1;
+        assert_eq!(create_topup_invoice(&state, &prepaid, too_much).await.unwrap_err(), StatusCode::BAD_REQUEST);
+        assert_eq!(create_topup_invoice(&state, &prepaid, i64::MAX).await.unwrap_err(), StatusCode::BAD_REQUEST);
+
+        mock.set_offline(true);
+        assert_eq!(create_topup_invoice(&state, &prepaid, 1000).await.unwrap_err(), StatusCode::BAD_GATEWAY);
+        let error = get_topup(Path("00".repeat(32)), State(state.clone())).await.unwrap_err();
+        assert_eq!(error, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,672 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    auth::{self, ApiUser},
+    db::{
+        bulk::{self, BulkAction, CardSelector},
+        channels,
+        invites::{self, InvitedRegistration},
+        nostr,
+        models::{Card, CardPayment, CreateCardRequest, PaymentStatus, UserApiKey, UserNotificationChannel, UserPolicy},
+        privacy::{self, UserExport},
+        topups,
+        users::{self, ApiKeyScope},
+        wallet,
+    },
+    handlers::{
+        bulk::SetNostrKeyRequest,
+        lost,
+        privacy::erasure_status,
+        register::{self, CreateCardResponse},
+        topup::{self, TopupRequest, TopupResponse, TopupStatus},
+    },
+    notifications::channels::{self as notification_channels, ChannelKind},
+    policy,
+};
+
+/// Shortest password accepted at registration
+const MIN_PASSWORD_LENGTH: usize = 10;
+
+#[derive(Debug, Deserialize)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Registration {
+    pub username: String,
+    pub password: String,
+    /// Required unless registration is open to everyone
+    pub invite_code: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserResponse {
+    pub user_id: i64,
+    pub username: String,
+    /// Quotas and limits set by the operator
+    pub policy: UserPolicy,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LoginResponse {
+    pub token: String,
+    pub expires_at: String,
+}
+
+/// A card as its owner sees it, without keys
+#[derive(Debug, Serialize)]
+pub struct OwnedCard {
+    pub card_id: i64,
+    pub card_name: String,
+    /// Empty until the card was tapped for the first time
+    pub uid: String,
+    pub enabled: bool,
+    pub tx_limit_sats: i64,
+    pub day_limit_sats: i64,
+    pub balance_mode: bool,
+    pub balance_msats: i64,
+    pub created_at: Option<String>,
+}
+
+impl From<Card> for OwnedCard {
+    fn from(card: Card) -> Self {
+        OwnedCard {
+            card_id: card.card_id,
+            card_name: card.card_name,
+            uid: card.uid,
+            enabled: card.enabled,
+            tx_limit_sats: card.tx_limit_sats,
+            day_limit_sats: card.day_limit_sats,
+            balance_mode: card.balance_mode,
+            balance_msats: card.balance_msats,
+            created_at: card.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct OwnedPayment {
+    pub payment_id: i64,
+    pub status: PaymentStatus,
+    pub amount_msats: Option<i64>,
+    pub payment_hash: Option<String>,
+    pub failure_reason: Option<String>,
+    pub payment_time: Option<String>,
+    pub created_at: Option<String>,
+    pub memo: Option<String>,
+}
+
+impl From<CardPayment> for OwnedPayment {
+    fn from(payment: CardPayment) -> Self {
+        OwnedPayment {
+            payment_id: payment.payment_id,
+            status: payment.status,
+            amount_msats: payment.amount_msats,
+            payment_hash: payment.payment_hash,
+            failure_reason: payment.failure_reason,
+            payment_time: payment.payment_time,
+            created_at: payment.created_at,
+            memo: payment.memo,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    pub scopes: Vec<ApiKeyScope>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateApiKeyResponse {
+    pub key_id: i64,
+    /// Only shown here, the server keeps a hash
+    pub key: String,
+    pub scopes: Vec<ApiKeyScope>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiKeyInfo {
+    pub key_id: i64,
+    pub name: String,
+    pub scopes: Vec<ApiKeyScope>,
+    pub created_at: Option<String>,
+    pub last_used_at: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+impl From<UserApiKey> for ApiKeyInfo {
+    fn from(key: UserApiKey) -> Self {
+        ApiKeyInfo {
+            key_id: key.key_id,
+            name: key.name,
+            scopes: ApiKeyScope::parse_list(&key.scopes),
+            created_at: key.created_at,
+            last_used_at: key.last_used_at,
+            revoked_at: key.revoked_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateChannelRequest {
+    pub kind: ChannelKind,
+    /// Email address, Telegram chat ID or Nostr public key
+    pub target: String,
+    /// Events to be notified about, all of them if missing
+    pub events: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChannelInfo {
+    pub channel_id: i64,
+    pub kind: String,
+    pub target: String,
+    /// `None` for all events
+    pub events: Option<Vec<String>>,
+    pub created_at: Option<String>,
+}
+
+impl From<UserNotificationChannel> for ChannelInfo {
+    fn from(channel: UserNotificationChannel) -> Self {
+        ChannelInfo {
+            channel_id: channel.channel_id,
+            events: notification_channels::parse_events(channel.events.as_deref()),
+            kind: channel.kind,
+            target: channel.target,
+            created_at: channel.created_at,
+        }
+    }
+}
+
+/// 3 to 64 letters, digits, `.`, `_` or `-`
+pub fn validate_username(username: &str) -> Result<(), &'static str> {
+    if !(3..=64).contains(&username.len()) {
+        return Err("Username must be 3 to 64 characters long");
+    }
+    if !username.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-')) {
+        return Err("Username may only contain letters, digits, '.', '_' and '-'");
+    }
+    Ok(())
+}
+
+/// POST /v1/users
+/// Register a cardholder account with an invite code, or without one if open registration is enabled
+///
+/// 403 if the invite is unknown, expired, revoked or used up.
+pub async fn register(
+    State(state): State<AppState>,
+    Json(req): Json<Registration>,
+) -> Result<(StatusCode, Json<UserResponse>), StatusCode> {
+    if req.invite_code.is_none() && !state.config.user_registration {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    if validate_username(&req.username).is_err() || req.password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let password_hash = auth::hash_password(&state.random, &req.password).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let user_id = match &req.invite_code {
+        Some(code) => match invites::register_with_invite(&state.pool, code, &req.username, &password_hash, state.clock.now())
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        {
+            InvitedRegistration::Created(user_id) => user_id,
+            InvitedRegistration::InvalidInvite => return Err(StatusCode::FORBIDDEN),
+            InvitedRegistration::UsernameTaken => return Err(StatusCode::CONFLICT),
+        },
+        None => users::create_user(&state.pool, &req.username, &password_hash)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::CONFLICT)?,
+    };
+
+    tracing::info!("User {} registered", req.username);
+
+    Ok((
+        StatusCode::CREATED,
+        Json(UserResponse {
+            user_id,
+            username: req.username,
+            policy: policy::effective(None, state.config.user_max_cards),
+        }),
+    ))
+}
+
+/// POST /v1/users/login
+/// Exchange username and password for a bearer token
+pub async fn login(
+    State(state): State<AppState>,
+    Json(req): Json<Credentials>,
+) -> Result<Json<LoginResponse>, StatusCode> {
+    let user = users::get_user_by_username(&state.pool, &req.username)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Hash anyway for unknown users so timing doesn't reveal valid usernames
+    let password_ok = match &user {
+        Some(user) => auth::verify_password(&user.password_hash, &req.password),
+        None => {
+            let _ = auth::hash_password(&state.random, &req.password);
+            false
+        }
+    };
+    let Some(user) = user.filter(|user| password_ok && user.enabled) else {
+        tracing::warn!("Failed login for user {:?}", req.username);
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+
+    let token = auth::new_session_token(&state.random).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let expires_at = users::create_session(
+        &state.pool,
+        &auth::hash_session_token(&token),
+        user.user_id,
+        state.config.user_session_hours,
+        state.clock.now(),
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(LoginResponse { token, expires_at }))
+}
+
+/// POST /v1/users/logout
+pub async fn logout(user: ApiUser, State(state): State<AppState>) -> Result<StatusCode, StatusCode> {
+    user.require_session()?;
+
+    users::delete_session(&state.pool, &user.token_hash)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /v1/me
+pub async fn me(user: ApiUser, State(state): State<AppState>) -> Result<Json<UserResponse>, StatusCode> {
+    let policy = policy::get_policy(&state.pool, &state.config, user.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(UserResponse {
+        user_id: user.user_id,
+        username: user.username,
+        policy,
+    }))
+}
+
+/// GET /v1/me/cards
+pub async fn list_cards(user: ApiUser, State(state): State<AppState>) -> Result<Json<Vec<OwnedCard>>, StatusCode> {
+    user.require_scope(ApiKeyScope::Read)?;
+
+    let cards = users::list_owned_cards(&state.pool, user.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(cards.into_iter().map(OwnedCard::from).collect()))
+}
+
+/// POST /v1/me/cards
+/// Create a card owned by the user, answered with its registration URL
+///
+/// The card is always in balance mode: it spends what the user tops it up
+/// with, never the operator's funds.
+pub async fn create_card(
+    user: ApiUser,
+    State(state): State<AppState>,
+    Json(req): Json<CreateCardRequest>,
+) -> Result<Json<CreateCardResponse>, StatusCode> {
+    user.require_scope(ApiKeyScope::Create)?;
+
+    let req = CreateCardRequest {
+        owner_id: Some(user.user_id),
+        org_id: None,
+        balance_mode: Some(true),
+        ..req
+    };
+
+    register::create_card_response(&state, &req).await.map(Json)
+}
+
+/// GET /v1/me/cards/{card_id}
+pub async fn get_card(
+    user: ApiUser,
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<OwnedCard>, StatusCode> {
+    user.require_scope(ApiKeyScope::Read)?;
+
+    owned_card(&state, &user, card_id).await.map(|card| Json(card.into()))
+}
+
+/// POST /v1/me/cards/{card_id}/actions
+/// Enable, disable or change the limits of an owned card, same body as a bulk update
+///
+/// Not open to API keys: changing limits is up to the cardholder. Limits
+/// above the user's policy are refused with 403, as is enabling a card the
+/// user didn't disable themselves.
+pub async fn card_action(
+    user: ApiUser,
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+    Json(action): Json<BulkAction>,
+) -> Result<Json<OwnedCard>, StatusCode> {
+    user.require_session()?;
+    if !action.is_valid() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    owned_card(&state, &user, card_id).await?;
+    require_unbound(&state, card_id).await?;
+    if matches!(action, BulkAction::Enable) {
+        lost::require_not_lost(&state, card_id).await?;
+    }
+
+    match action {
+        BulkAction::Enable | BulkAction::Disable => {
+            let enabled = matches!(action, BulkAction::Enable);
+            let applied = bulk::apply_as_holder(&state.pool, card_id, enabled, &user.actor())
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            if !applied {
+                return Err(StatusCode::FORBIDDEN);
+            }
+        }
+        BulkAction::SetLimits { tx_limit_sats, day_limit_sats } => {
+            let policy = policy::get_policy(&state.pool, &state.config, user.user_id)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            policy::check_limits(&policy, tx_limit_sats, day_limit_sats).map_err(|_| StatusCode::FORBIDDEN)?;
+
+            let selector = CardSelector {
+                card_ids: Some(vec![card_id]),
+                tag: None,
+            };
+            bulk::apply(&state.pool, &selector, &action, &user.actor())
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        }
+    }
+
+    owned_card(&state, &user, card_id).await.map(|card| Json(card.into()))
+}
+
+/// PUT /v1/me/cards/{card_id}/nostr
+/// Link an own card to a Nostr key for receipts of its withdrawals
+pub async fn set_nostr_pubkey(
+    user: ApiUser,
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+    Json(req): Json<SetNostrKeyRequest>,
+) -> Result<StatusCode, StatusCode> {
+    user.require_session()?;
+    let pubkey = req.normalized()?;
+    owned_card(&state, &user, card_id).await?;
+    require_unbound(&state, card_id).await?;
+
+    nostr::set_card_nostr_pubkey(&state.pool, card_id, pubkey.as_deref(), &user.actor())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /v1/me/cards/{card_id}/payments
+pub async fn list_payments(
+    user: ApiUser,
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<OwnedPayment>>, StatusCode> {
+    user.require_scope(ApiKeyScope::Read)?;
+    owned_card(&state, &user, card_id).await?;
+    require_unbound(&state, card_id).await?;
+
+    let payments = users::list_owned_card_payments(&state.pool, user.user_id, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(payments.into_iter().map(OwnedPayment::from).collect()))
+}
+
+/// POST /v1/me/cards/{card_id}/topups
+/// Create an invoice that tops up an owned balance-mode card
+pub async fn create_topup(
+    user: ApiUser,
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+    Json(req): Json<TopupRequest>,
+) -> Result<Json<TopupResponse>, StatusCode> {
+    user.require_scope(ApiKeyScope::TopUp)?;
+    let card = owned_card(&state, &user, card_id).await?;
+
+    let topup = topup::create_topup_invoice(&state, &card, req.amount_sats).await?;
+
+    Ok(Json(TopupResponse {
+        status: "OK".to_string(),
+        payment_hash: topup.payment_hash,
+        invoice: topup.invoice,
+    }))
+}
+
+/// GET /v1/me/cards/{card_id}/topups/{payment_hash}
+/// Status of a top-up of an owned card, crediting it if the invoice was paid
+pub async fn get_topup(
+    user: ApiUser,
+    Path((card_id, payment_hash)): Path<(i64, String)>,
+    State(state): State<AppState>,
+) -> Result<Json<TopupStatus>, StatusCode> {
+    user.require_scope(ApiKeyScope::TopUp)?;
+    owned_card(&state, &user, card_id).await?;
+
+    let topup = topups::get_topup_by_hash(&state.pool, &payment_hash)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .filter(|topup| topup.card_id == card_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    topup::topup_status(&state, topup).await.map(Json)
+}
+
+/// POST /v1/me/keys
+/// Issue an API key for a third-party app, limited to `scopes`
+pub async fn create_api_key(
+    user: ApiUser,
+    State(state): State<AppState>,
+    Json(req): Json<CreateApiKeyRequest>,
+) -> Result<(StatusCode, Json<CreateApiKeyResponse>), StatusCode> {
+    user.require_session()?;
+    if req.name.trim().is_empty() || req.scopes.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let key = auth::new_api_key(&state.random).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let key_id = users::create_api_key(
+        &state.pool,
+        user.user_id,
+        req.name.trim(),
+        &auth::hash_session_token(&key),
+        &req.scopes,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((StatusCode::CREATED, Json(CreateApiKeyResponse { key_id, key, scopes: req.scopes })))
+}
+
+/// GET /v1/me/keys
+pub async fn list_api_keys(user: ApiUser, State(state): State<AppState>) -> Result<Json<Vec<ApiKeyInfo>>, StatusCode> {
+    user.require_session()?;
+
+    let keys = users::list_api_keys(&state.pool, user.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(keys.into_iter().map(ApiKeyInfo::from).collect()))
+}
+
+/// DELETE /v1/me/keys/{key_id}
+pub async fn revoke_api_key(
+    user: ApiUser,
+    Path(key_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, StatusCode> {
+    user.require_session()?;
+
+    if !users::revoke_api_key(&state.pool, user.user_id, key_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /v1/me/notifications
+/// Add a channel the user is notified on about events of their cards
+pub async fn create_channel(
+    user: ApiUser,
+    State(state): State<AppState>,
+    Json(req): Json<CreateChannelRequest>,
+) -> Result<(StatusCode, Json<ChannelInfo>), StatusCode> {
+    user.require_session()?;
+    if !state.channels.supports(req.kind) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let target = req.kind.normalize_target(&req.target).ok_or(StatusCode::BAD_REQUEST)?;
+    let events = req.events.map(validate_events).transpose()?;
+
+    let channel_id = channels::create_channel(&state.pool, user.user_id, req.kind.as_str(), &target, events.as_deref())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let channel = channels::get_channel(&state.pool, channel_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((StatusCode::CREATED, Json(channel.into())))
+}
+
+/// GET /v1/me/notifications
+pub async fn list_channels(user: ApiUser, State(state): State<AppState>) -> Result<Json<Vec<ChannelInfo>>, StatusCode> {
+    user.require_session()?;
+
+    let channels = channels::list_channels(&state.pool, user.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(channels.into_iter().map(ChannelInfo::from).collect()))
+}
+
+/// DELETE /v1/me/notifications/{channel_id}
+pub async fn delete_channel(
+    user: ApiUser,
+    Path(channel_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, StatusCode> {
+    user.require_session()?;
+
+    if !channels::delete_channel(&state.pool, user.user_id, channel_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// A non-empty list of events cardholders may subscribe to, stored comma separated
+fn validate_events(events: Vec<String>) -> Result<String, StatusCode> {
+    let mut subscribed: Vec<&str> = Vec::new();
+    for event in &events {
+        let known = notification_channels::USER_EVENTS
+            .iter()
+            .find(|known| **known == event.as_str())
+            .ok_or(StatusCode::BAD_REQUEST)?;
+        if !subscribed.contains(known) {
+            subscribed.push(known);
+        }
+    }
+
+    if subscribed.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(subscribed.join(","))
+}
+
+/// GET /v1/me/export
+/// Everything recorded about the user and their cards, except card keys
+pub async fn export_data(user: ApiUser, State(state): State<AppState>) -> Result<Json<UserExport>, StatusCode> {
+    user.require_session()?;
+
+    privacy::export_user(&state.pool, user.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// DELETE /v1/me
+/// Erase the user's account and cards, which can't be undone
+pub async fn erase_account(user: ApiUser, State(state): State<AppState>) -> Result<StatusCode, StatusCode> {
+    user.require_session()?;
+
+    let erasure = privacy::erase_user(&state.pool, user.user_id, &user.actor())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    erasure_status(erasure)
+}
+
+/// Cards of other users answer 404 like missing ones, so IDs can't be probed
+async fn owned_card(state: &AppState, user: &ApiUser, card_id: i64) -> Result<Card, StatusCode> {
+    users::get_owned_card(&state.pool, user.user_id, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Reject cards bound to a wallet, whose actions and history take a wallet login
+async fn require_unbound(state: &AppState, card_id: i64) -> Result<(), StatusCode> {
+    let auth_key = wallet::get_card_auth_key(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    match auth_key {
+        Some(_) => Err(StatusCode::FORBIDDEN),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_username() {
+        assert!(validate_username("alice").is_ok());
+        assert!(validate_username("bar.tab-2024_x").is_ok());
+        assert!(validate_username("al").is_err());
+        assert!(validate_username("alice smith").is_err());
+        assert!(validate_username("älice").is_err());
+    }
+
+    #[test]
+    fn test_validate_events() {
+        let events = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            validate_events(events(&["payment_settled", "card_reported_lost", "payment_settled"])),
+            Ok("payment_settled,card_reported_lost".to_string())
+        );
+        assert_eq!(validate_events(events(&[])), Err(StatusCode::BAD_REQUEST));
+        assert_eq!(validate_events(events(&["payment_discrepancy"])), Err(StatusCode::BAD_REQUEST));
+    }
+}
@@ -0,0 +1,263 @@
+use axum::{
+    extract::{rejection::QueryRejection, Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    auth::{self, WalletUser},
+    db::{
+        bulk::{self, BulkAction},
+        models::Card,
+        queries,
+        wallet::{self, ChallengeState},
+    },
+    handlers::{
+        error::{ApiError, LocalizedApiError},
+        lost,
+        lnurlw::{self, CallbackResponse},
+        users::{LoginResponse, OwnedCard, OwnedPayment},
+    },
+    i18n::Locale,
+    tenant::Tenant,
+    validation::decode_params,
+};
+
+/// How long a login challenge waits for the wallet's signature
+const CHALLENGE_TTL_SECS: i64 = 300;
+
+#[derive(Debug, Serialize)]
+pub struct LoginChallenge {
+    pub k1: String,
+    /// For the wallet, usually shown as a QR code
+    pub lnurl: String,
+    pub expires_in: i64,
+}
+
+/// Query of the LUD-04 callback
+#[derive(Debug, Deserialize)]
+pub struct AuthParams {
+    k1: String,
+    sig: String,
+    key: String,
+}
+
+/// Proof that the cardholder holds a card: its one-time code, or a tap
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ClaimRequest {
+    Code { code: String },
+    Tap { card_id: i64, p: String, c: String },
+}
+
+/// POST /v1/wallet/login
+/// Start an LNURL-auth login, answered with the challenge for the wallet to sign
+pub async fn start_login(tenant: Tenant, State(state): State<AppState>) -> Result<Json<LoginChallenge>, StatusCode> {
+    let k1 = state.random.hex::<32>().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    wallet::create_challenge(&state.pool, &k1, CHALLENGE_TTL_SECS, state.clock.now())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(LoginChallenge {
+        lnurl: state.config.urls_on(tenant.domain()).wallet_login_url(&k1),
+        k1,
+        expires_in: CHALLENGE_TTL_SECS,
+    }))
+}
+
+/// GET /wallet/auth?tag=login&k1={k1}&sig={sig}&key={key}
+/// LUD-04 callback the wallet sends its signature of the challenge to
+pub async fn auth_callback(
+    locale: Locale,
+    params: Result<Query<AuthParams>, QueryRejection>,
+    State(state): State<AppState>,
+) -> Result<Json<CallbackResponse>, LocalizedApiError> {
+    let result = match params {
+        Ok(Query(params)) => verify_login(&state, &params).await,
+        Err(_) => Err(ApiError::MalformedQuery),
+    };
+    result
+        .map(Json)
+        .map_err(|e| e.localize(locale).status_ok(state.config.lnurl_errors_with_200))
+}
+
+async fn verify_login(state: &AppState, params: &AuthParams) -> Result<CallbackResponse, ApiError> {
+    if !auth::verify_lnurl_auth(&params.k1, &params.sig, &params.key) {
+        return Err(ApiError::InvalidSignature);
+    }
+
+    // Keys are compared as stored, so normalize the hex
+    let linking_key = params.key.to_ascii_lowercase();
+    if !wallet::sign_challenge(&state.pool, &params.k1.to_ascii_lowercase(), &linking_key, state.clock.now()).await? {
+        return Err(ApiError::LoginExpired);
+    }
+
+    Ok(CallbackResponse {
+        status: "OK".to_string(),
+    })
+}
+
+/// POST /v1/wallet/login/{k1}
+/// Exchange a signed challenge for a bearer token, 202 while the wallet hasn't signed yet
+pub async fn finish_login(Path(k1): Path<String>, State(state): State<AppState>) -> Result<Response, StatusCode> {
+    let challenge = wallet::take_challenge(&state.pool, &k1, state.clock.now())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let linking_key = match challenge {
+        ChallengeState::Signed(linking_key) => linking_key,
+        ChallengeState::Pending => return Ok(StatusCode::ACCEPTED.into_response()),
+        ChallengeState::Missing => return Err(StatusCode::NOT_FOUND),
+    };
+
+    let token = auth::new_session_token(&state.random).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let expires_at = wallet::create_session(
+        &state.pool,
+        &auth::hash_session_token(&token),
+        &linking_key,
+        state.config.user_session_hours,
+        state.clock.now(),
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(LoginResponse { token, expires_at }).into_response())
+}
+
+/// POST /v1/wallet/logout
+pub async fn logout(wallet_user: WalletUser, State(state): State<AppState>) -> Result<StatusCode, StatusCode> {
+    wallet::delete_session(&state.pool, &wallet_user.token_hash)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /v1/wallet/cards
+pub async fn list_cards(
+    wallet_user: WalletUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<OwnedCard>>, StatusCode> {
+    let cards = wallet::list_bound_cards(&state.pool, &wallet_user.linking_key)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(cards.into_iter().map(OwnedCard::from).collect()))
+}
+
+/// POST /v1/wallet/cards/claim
+/// Bind an unclaimed card to the wallet
+///
+/// 404 if the code or card is unknown or the card was claimed already, 403 if
+/// the tap doesn't authenticate or isn't newer than the card's last one.
+pub async fn claim_card(
+    wallet_user: WalletUser,
+    State(state): State<AppState>,
+    Json(req): Json<ClaimRequest>,
+) -> Result<Json<OwnedCard>, StatusCode> {
+    let claimed = match &req {
+        ClaimRequest::Code { code } => wallet::claim_card_by_code(&state.pool, code, &wallet_user.linking_key, state.clock.now())
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        ClaimRequest::Tap { card_id, p, c } => claim_by_tap(&state, &wallet_user, *card_id, p, c).await?,
+    };
+    let card_id = claimed.ok_or(StatusCode::NOT_FOUND)?;
+
+    tracing::info!("Card {} claimed by wallet {}", card_id, wallet_user.linking_key);
+
+    bound_card(&state, &wallet_user, card_id).await.map(|card| Json(card.into()))
+}
+
+async fn claim_by_tap(
+    state: &AppState,
+    wallet_user: &WalletUser,
+    card_id: i64,
+    p: &str,
+    c: &str,
+) -> Result<Option<i64>, StatusCode> {
+    let card = queries::get_card_by_id(&state.pool, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let (p, c) = decode_params(p, c).map_err(|_| StatusCode::BAD_REQUEST)?;
+    state.key_cache.refresh(&state.pool).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let tap = lnurlw::validate_tap(state, &card, &p, &c).map_err(|_| StatusCode::FORBIDDEN)?;
+    state.validator.check_card(&card, &tap).map_err(|_| StatusCode::FORBIDDEN)?;
+
+    let claimed = wallet::claim_card_by_tap(
+        &state.pool,
+        card_id,
+        &tap.uid.to_string(),
+        tap.counter.value() as i64,
+        &wallet_user.linking_key,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(claimed.then_some(card_id))
+}
+
+/// GET /v1/wallet/cards/{card_id}
+pub async fn get_card(
+    wallet_user: WalletUser,
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<OwnedCard>, StatusCode> {
+    bound_card(&state, &wallet_user, card_id).await.map(|card| Json(card.into()))
+}
+
+/// GET /v1/wallet/cards/{card_id}/payments
+pub async fn list_payments(
+    wallet_user: WalletUser,
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<OwnedPayment>>, StatusCode> {
+    bound_card(&state, &wallet_user, card_id).await?;
+
+    let payments = wallet::list_bound_card_payments(&state.pool, &wallet_user.linking_key, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(payments.into_iter().map(OwnedPayment::from).collect()))
+}
+
+/// POST /v1/wallet/cards/{card_id}/actions
+/// Freeze or unfreeze a bound card with `disable`/`enable`; limits stay with the operator and owner
+///
+/// Only a card the holder disabled can be enabled again, 403 for others.
+pub async fn card_action(
+    wallet_user: WalletUser,
+    Path(card_id): Path<i64>,
+    State(state): State<AppState>,
+    Json(action): Json<BulkAction>,
+) -> Result<Json<OwnedCard>, StatusCode> {
+    let enabled = match action {
+        BulkAction::Enable => true,
+        BulkAction::Disable => false,
+        BulkAction::SetLimits { .. } => return Err(StatusCode::FORBIDDEN),
+    };
+    bound_card(&state, &wallet_user, card_id).await?;
+    if enabled {
+        lost::require_not_lost(&state, card_id).await?;
+    }
+
+    let applied = bulk::apply_as_holder(&state.pool, card_id, enabled, &wallet_user.actor())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !applied {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    bound_card(&state, &wallet_user, card_id).await.map(|card| Json(card.into()))
+}
+
+/// A card bound to the wallet, 404 for others so card IDs can't be probed
+async fn bound_card(state: &AppState, wallet_user: &WalletUser, card_id: i64) -> Result<Card, StatusCode> {
+    wallet::get_bound_card(&state.pool, &wallet_user.linking_key, card_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)
+}
@@ -0,0 +1,56 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+
+use crate::{
+    app_state::AppState,
+    db::{models::WebhookEvent, webhooks},
+    notifications::{self, Redelivery},
+};
+
+const MAX_EVENTS: i64 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+    /// `pending`, `delivered` or `dead`
+    pub status: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// GET /v1/webhooks/events?status={status}&limit={n}
+/// Operator notifications with the state of their delivery, newest first
+pub async fn list_events(
+    Query(params): Query<EventsQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<WebhookEvent>>, StatusCode> {
+    if params.status.as_deref().is_some_and(|status| !["pending", "delivered", "dead"].contains(&status)) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let limit = params.limit.unwrap_or(100).clamp(1, MAX_EVENTS);
+
+    let events = webhooks::list_events(&state.pool, params.status.as_deref(), limit)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(events))
+}
+
+/// POST /v1/webhooks/events/{event_id}/redeliver
+/// Send a delivered or dead event to the webhook again, `409` while it's still pending
+pub async fn redeliver(Path(event_id): Path<i64>, State(state): State<AppState>) -> StatusCode {
+    match notifications::redeliver(&state.pool, event_id).await {
+        Ok(Redelivery::Queued) => {
+            tracing::info!("Queued event {} for redelivery", event_id);
+            StatusCode::ACCEPTED
+        }
+        Ok(Redelivery::NotFound) => StatusCode::NOT_FOUND,
+        Ok(Redelivery::Pending) => StatusCode::CONFLICT,
+        Err(e) => {
+            tracing::error!("Failed to queue event {} for redelivery: {}", event_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Html,
+    Json,
+};
+use serde::Serialize;
+
+use crate::{
+    app_state::AppState,
+    db::{queries, tokens::{self, TokenScope}},
+    handlers::html::{brand_head, escape},
+    i18n::Locale,
+};
+
+/// Seconds between widget refreshes on customer-facing displays
+const REFRESH_SECS: u32 = 30;
+
+#[derive(Debug, Serialize)]
+pub struct WidgetStatus {
+    pub card_name: String,
+    pub enabled: bool,
+    pub tx_limit_sats: i64,
+    pub day_limit_sats: i64,
+    pub day_spent_sats: i64,
+    pub day_remaining_sats: i64,
+    /// Prepaid balance, only for balance-mode cards
+    pub balance_sats: Option<i64>,
+    /// Largest amount the next tap can withdraw
+    pub available_sats: i64,
+}
+
+async fn load_status(state: &AppState, token: &str) -> Result<WidgetStatus, StatusCode> {
+    let card = tokens::get_unbound_card_by_token(&state.pool, token, TokenScope::Widget)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let day_spent_msats = queries::get_daily_total_msats(&state.pool, card.card_id, state.clock.now())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let day_remaining_sats = (card.day_limit_sats - day_spent_msats / 1000).max(0);
+    let balance_sats = card.balance_mode.then_some(card.balance_msats / 1000);
+    let available_sats = if card.enabled {
+        let limit = card.tx_limit_sats.min(day_remaining_sats);
+        balance_sats.map_or(limit, |balance| limit.min(balance))
+    } else {
+        0
+    };
+
+    Ok(WidgetStatus {
+        card_name: card.card_name,
+        enabled: card.enabled,
+        tx_limit_sats: card.tx_limit_sats,
+        day_limit_sats: card.day_limit_sats,
+        day_spent_sats: day_spent_msats / 1000,
+        day_remaining_sats,
+        balance_sats,
+        available_sats,
+    })
+}
+
+/// GET /widget/{token}/status
+/// Card status as JSON for displays rendering their own UI
+pub async fn widget_status(
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<WidgetStatus>, StatusCode> {
+    Ok(Json(load_status(&state, &token).await?))
+}
+
+/// GET /widget/{token}
+/// Compact self-refreshing card status meant to be embedded in an iframe
+pub async fn widget_page(
+    locale: Locale,
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Html<String>, StatusCode> {
+    let status = load_status(&state, &token).await?;
+
+    let detail = if status.enabled {
+        format!(
+            "<div class=\"amount\">{} sats</div>\n<div class=\"detail\">{}</div>",
+            status.available_sats,
+            locale.trf(
+                "available today &middot; max {amount} sats per payment",
+                &[("amount", &status.tx_limit_sats.to_string())],
+            ),
+        )
+    } else {
+        format!(
+            "<div class=\"amount\">{}</div>\n<div class=\"detail\">{}</div>",
+            locale.tr("Disabled"),
+            locale.tr("this card cannot be used for payments"),
+        )
+    };
+
+    Ok(Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="{lang}">
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="{refresh}">
+<style>
+body {{ font-family: sans-serif; margin: 0; padding: 0.5rem; color: #222; background: transparent; }}
+.name {{ font-size: 0.9rem; color: #666; }}
+.amount {{ font-size: 1.8rem; font-weight: bold; color: var(--brand); }}
+.detail {{ font-size: 0.8rem; color: #666; }}
+</style>
+{head}
+</head>
+<body>
+<div class="name">{name}</div>
+{detail}
+</body>
+</html>"#,
+        lang = locale.code(),
+        refresh = REFRESH_SECS,
+        head = brand_head(&state.config, &status.card_name),
+        name = escape(&status.card_name),
+        detail = detail,
+    )))
+}
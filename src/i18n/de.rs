@@ -0,0 +1,98 @@
+//! German messages
+
+pub const MESSAGES: &[(&str, &str)] = &[
+    // LNURL error reasons
+    ("Database error", "Datenbankfehler"),
+    ("Card not found or disabled", "Karte nicht gefunden oder deaktiviert"),
+    ("Card is frozen", "Karte ist gesperrt"),
+    ("Card is frozen until {until}", "Karte ist gesperrt bis {until}"),
+    ("Card is under review, contact the issuer", "Karte wird geprüft, bitte an den Herausgeber wenden"),
+    ("Malformed query string", "Fehlerhafte Abfrageparameter"),
+    ("Invalid card_id parameter", "Ungültiger Parameter card_id"),
+    ("Missing p parameter", "Parameter p fehlt"),
+    ("Missing c parameter", "Parameter c fehlt"),
+    ("Odd-length p parameter", "Parameter p hat eine ungerade Länge"),
+    ("Odd-length c parameter", "Parameter c hat eine ungerade Länge"),
+    ("p parameter too short", "Parameter p ist zu kurz"),
+    ("c parameter too short", "Parameter c ist zu kurz"),
+    ("p parameter too long", "Parameter p ist zu lang"),
+    ("c parameter too long", "Parameter c ist zu lang"),
+    ("p parameter is not hex", "Parameter p ist nicht hexadezimal"),
+    ("c parameter is not hex", "Parameter c ist nicht hexadezimal"),
+    ("Invalid k1 key", "Ungültiger Schlüssel k1"),
+    ("Invalid k2 key", "Ungültiger Schlüssel k2"),
+    ("Invalid CMAC - card authentication failed", "Ungültiger CMAC - Kartenauthentifizierung fehlgeschlagen"),
+    ("UID mismatch", "UID stimmt nicht überein"),
+    ("Card counter exhausted - card needs replacement", "Kartenzähler erschöpft - die Karte muss ersetzt werden"),
+    ("Invalid counter - possible replay attack", "Ungültiger Zähler - möglicher Replay-Angriff"),
+    ("Counter update failed", "Zähler konnte nicht aktualisiert werden"),
+    ("Invalid k1", "Ungültiges k1"),
+    ("Login expired, scan the code again", "Anmeldung abgelaufen, bitte den Code erneut scannen"),
+    ("Invalid signature", "Ungültige Signatur"),
+    ("Withdrawal session expired, tap the card again", "Abhebung abgelaufen, bitte die Karte erneut auflegen"),
+    ("Too many failed attempts, tap the card again", "Zu viele Fehlversuche, bitte die Karte erneut auflegen"),
+    ("Payment already processed", "Zahlung wurde bereits verarbeitet"),
+    ("Payment is pending, check back later", "Zahlung ist noch offen, später erneut prüfen"),
+    ("Invalid invoice", "Ungültige Rechnung"),
+    ("Invoice must have amount", "Rechnung muss einen Betrag enthalten"),
+    ("Invoice is for another network", "Rechnung ist für ein anderes Netzwerk"),
+    ("Amount outside the withdrawable range", "Betrag außerhalb des abhebbaren Bereichs"),
+    ("On-chain withdrawal not available for this amount", "On-Chain-Abhebung für diesen Betrag nicht verfügbar"),
+    ("Invalid on-chain address", "Ungültige On-Chain-Adresse"),
+    ("Invoice description doesn't match the withdrawal", "Rechnungsbeschreibung passt nicht zur Abhebung"),
+    ("Amount exceeds transaction limit", "Betrag überschreitet das Transaktionslimit"),
+    ("Amount exceeds daily limit", "Betrag überschreitet das Tageslimit"),
+    ("Insufficient card balance", "Kartenguthaben reicht nicht aus"),
+    ("Withdrawals are paused, try again later", "Abhebungen sind pausiert, bitte später erneut versuchen"),
+    ("Withdrawal declined", "Abhebung abgelehnt"),
+    ("Payment failed", "Zahlung fehlgeschlagen"),
+    ("Payment failed: {error}", "Zahlung fehlgeschlagen: {error}"),
+    // Receipts
+    ("Payment receipt", "Zahlungsbeleg"),
+    ("This withdrawal was settled over Lightning.", "Diese Abhebung wurde über Lightning abgewickelt."),
+    ("This withdrawal was paid on-chain.", "Diese Abhebung wurde On-Chain ausgezahlt."),
+    ("Address", "Adresse"),
+    ("Transaction", "Transaktion"),
+    ("Amount", "Betrag"),
+    ("Time (UTC)", "Zeit (UTC)"),
+    ("Card", "Karte"),
+    ("Note", "Notiz"),
+    ("Signed receipt", "Signierter Beleg"),
+    ("Payment hash", "Zahlungs-Hash"),
+    ("Preimage", "Preimage"),
+    // Lost card reports
+    ("Report card lost", "Karte als verloren melden"),
+    (
+        "Reporting {card} lost disables it immediately. Nobody will be able to pay with it until the operator re-enables or replaces it.",
+        "Wenn du {card} als verloren meldest, wird sie sofort deaktiviert. Niemand kann damit bezahlen, bis der Betreiber sie wieder aktiviert oder ersetzt.",
+    ),
+    ("Anything the operator should know (optional)", "Hinweise für den Betreiber (optional)"),
+    ("Issue a replacement card that takes over the balance", "Ersatzkarte ausstellen, die das Guthaben übernimmt"),
+    ("Disable my card", "Karte deaktivieren"),
+    ("Card disabled", "Karte deaktiviert"),
+    ("{card} is disabled and cannot be used for payments.", "{card} ist deaktiviert und kann nicht zum Bezahlen verwendet werden."),
+    (
+        "{card} has been disabled and the operator was notified. Contact them to get a replacement card.",
+        "{card} wurde deaktiviert und der Betreiber wurde benachrichtigt. Wende dich an ihn, um eine Ersatzkarte zu erhalten.",
+    ),
+    (
+        "{card} has been disabled and a replacement card with its balance was issued. The operator was notified and will hand it over to you.",
+        "{card} wurde deaktiviert und eine Ersatzkarte mit ihrem Guthaben wurde ausgestellt. Der Betreiber wurde benachrichtigt und händigt sie dir aus.",
+    ),
+    // Status widget
+    ("available today &middot; max {amount} sats per payment", "heute verfügbar &middot; max. {amount} sats pro Zahlung"),
+    ("Disabled", "Deaktiviert"),
+    ("this card cannot be used for payments", "diese Karte kann nicht zum Bezahlen verwendet werden"),
+    // Top-ups
+    ("Top up card", "Karte aufladen"),
+    ("Top up {card}", "{card} aufladen"),
+    ("Current balance: {amount}", "Aktuelles Guthaben: {amount}"),
+    ("Amount (sats)", "Betrag (sats)"),
+    ("Create invoice", "Rechnung erstellen"),
+    ("Received {amount}. New balance: {balance}", "{amount} erhalten. Neues Guthaben: {balance}"),
+    ("Top up again", "Erneut aufladen"),
+    (
+        "Pay this invoice for {amount} with any Lightning wallet. This page updates once it is paid.",
+        "Bezahle diese Rechnung über {amount} mit einer beliebigen Lightning-Wallet. Diese Seite aktualisiert sich, sobald sie bezahlt ist.",
+    ),
+];
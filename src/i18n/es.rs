@@ -0,0 +1,98 @@
+//! Spanish messages
+
+pub const MESSAGES: &[(&str, &str)] = &[
+    // LNURL error reasons
+    ("Database error", "Error de base de datos"),
+    ("Card not found or disabled", "Tarjeta no encontrada o desactivada"),
+    ("Card is frozen", "La tarjeta está bloqueada"),
+    ("Card is frozen until {until}", "La tarjeta está bloqueada hasta {until}"),
+    ("Card is under review, contact the issuer", "La tarjeta está en revisión, contacte con el emisor"),
+    ("Malformed query string", "Parámetros de consulta mal formados"),
+    ("Invalid card_id parameter", "Parámetro card_id no válido"),
+    ("Missing p parameter", "Falta el parámetro p"),
+    ("Missing c parameter", "Falta el parámetro c"),
+    ("Odd-length p parameter", "El parámetro p tiene una longitud impar"),
+    ("Odd-length c parameter", "El parámetro c tiene una longitud impar"),
+    ("p parameter too short", "El parámetro p es demasiado corto"),
+    ("c parameter too short", "El parámetro c es demasiado corto"),
+    ("p parameter too long", "El parámetro p es demasiado largo"),
+    ("c parameter too long", "El parámetro c es demasiado largo"),
+    ("p parameter is not hex", "El parámetro p no es hexadecimal"),
+    ("c parameter is not hex", "El parámetro c no es hexadecimal"),
+    ("Invalid k1 key", "Clave k1 no válida"),
+    ("Invalid k2 key", "Clave k2 no válida"),
+    ("Invalid CMAC - card authentication failed", "CMAC no válido - falló la autenticación de la tarjeta"),
+    ("UID mismatch", "El UID no coincide"),
+    ("Card counter exhausted - card needs replacement", "Contador de la tarjeta agotado - hay que reemplazar la tarjeta"),
+    ("Invalid counter - possible replay attack", "Contador no válido - posible ataque de repetición"),
+    ("Counter update failed", "No se pudo actualizar el contador"),
+    ("Invalid k1", "k1 no válido"),
+    ("Login expired, scan the code again", "El inicio de sesión ha caducado, escanee el código de nuevo"),
+    ("Invalid signature", "Firma no válida"),
+    ("Withdrawal session expired, tap the card again", "La sesión de retiro ha caducado, acerque la tarjeta de nuevo"),
+    ("Too many failed attempts, tap the card again", "Demasiados intentos fallidos, acerque la tarjeta de nuevo"),
+    ("Payment already processed", "El pago ya fue procesado"),
+    ("Payment is pending, check back later", "El pago está pendiente, vuelve a consultar más tarde"),
+    ("Invalid invoice", "Factura no válida"),
+    ("Invoice must have amount", "La factura debe incluir un importe"),
+    ("Invoice is for another network", "La factura es de otra red"),
+    ("Amount outside the withdrawable range", "Importe fuera del rango retirable"),
+    ("On-chain withdrawal not available for this amount", "Retiro on-chain no disponible para este importe"),
+    ("Invalid on-chain address", "Dirección on-chain inválida"),
+    ("Invoice description doesn't match the withdrawal", "La descripción de la factura no coincide con el retiro"),
+    ("Amount exceeds transaction limit", "El importe supera el límite por transacción"),
+    ("Amount exceeds daily limit", "El importe supera el límite diario"),
+    ("Insufficient card balance", "Saldo de la tarjeta insuficiente"),
+    ("Withdrawals are paused, try again later", "Los retiros están en pausa, inténtalo más tarde"),
+    ("Withdrawal declined", "Retiro rechazado"),
+    ("Payment failed", "El pago falló"),
+    ("Payment failed: {error}", "El pago falló: {error}"),
+    // Receipts
+    ("Payment receipt", "Recibo de pago"),
+    ("This withdrawal was settled over Lightning.", "Este retiro se liquidó a través de Lightning."),
+    ("This withdrawal was paid on-chain.", "Este retiro se pagó on-chain."),
+    ("Address", "Dirección"),
+    ("Transaction", "Transacción"),
+    ("Amount", "Importe"),
+    ("Time (UTC)", "Hora (UTC)"),
+    ("Card", "Tarjeta"),
+    ("Note", "Nota"),
+    ("Signed receipt", "Recibo firmado"),
+    ("Payment hash", "Hash del pago"),
+    ("Preimage", "Preimagen"),
+    // Lost card reports
+    ("Report card lost", "Reportar tarjeta perdida"),
+    (
+        "Reporting {card} lost disables it immediately. Nobody will be able to pay with it until the operator re-enables or replaces it.",
+        "Al reportar {card} como perdida se desactiva de inmediato. Nadie podrá pagar con ella hasta que el operador la reactive o la reemplace.",
+    ),
+    ("Anything the operator should know (optional)", "Algo que el operador deba saber (opcional)"),
+    ("Issue a replacement card that takes over the balance", "Emitir una tarjeta de reemplazo que reciba el saldo"),
+    ("Disable my card", "Desactivar mi tarjeta"),
+    ("Card disabled", "Tarjeta desactivada"),
+    ("{card} is disabled and cannot be used for payments.", "{card} está desactivada y no se puede usar para pagar."),
+    (
+        "{card} has been disabled and the operator was notified. Contact them to get a replacement card.",
+        "{card} fue desactivada y se notificó al operador. Contáctalo para obtener una tarjeta de reemplazo.",
+    ),
+    (
+        "{card} has been disabled and a replacement card with its balance was issued. The operator was notified and will hand it over to you.",
+        "{card} fue desactivada y se emitió una tarjeta de reemplazo con su saldo. Se notificó al operador, que te la entregará.",
+    ),
+    // Status widget
+    ("available today &middot; max {amount} sats per payment", "disponible hoy &middot; máx. {amount} sats por pago"),
+    ("Disabled", "Desactivada"),
+    ("this card cannot be used for payments", "esta tarjeta no se puede usar para pagar"),
+    // Top-ups
+    ("Top up card", "Recargar tarjeta"),
+    ("Top up {card}", "Recargar {card}"),
+    ("Current balance: {amount}", "Saldo actual: {amount}"),
+    ("Amount (sats)", "Importe (sats)"),
+    ("Create invoice", "Crear factura"),
+    ("Received {amount}. New balance: {balance}", "Recibido {amount}. Nuevo saldo: {balance}"),
+    ("Top up again", "Recargar de nuevo"),
+    (
+        "Pay this invoice for {amount} with any Lightning wallet. This page updates once it is paid.",
+        "Paga esta factura de {amount} con cualquier billetera Lightning. Esta página se actualiza cuando se pague.",
+    ),
+];
@@ -0,0 +1,157 @@
+//! Message catalogs for cardholder-facing pages and LNURL error reasons
+//!
+//! Messages are looked up by their English source text, so anything without
+//! a translation (or with dynamic content) falls back to English.
+
+mod de;
+mod es;
+
+use std::convert::Infallible;
+
+use axum::{extract::FromRequestParts, http::request::Parts};
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    En,
+    De,
+    Es,
+}
+
+impl Locale {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::De => "de",
+            Locale::Es => "es",
+        }
+    }
+
+    fn catalog(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Locale::En => &[],
+            Locale::De => de::MESSAGES,
+            Locale::Es => es::MESSAGES,
+        }
+    }
+
+    /// Translate an English source message
+    pub fn tr<'a>(&self, msg: &'a str) -> &'a str {
+        self.catalog()
+            .iter()
+            .find(|(source, _)| *source == msg)
+            .map(|(_, translated)| *translated)
+            .unwrap_or(msg)
+    }
+
+    /// Translate a message and fill in its `{name}` placeholders
+    ///
+    /// The template is read once, so placeholders inside argument values stay as they are.
+    pub fn trf(&self, msg: &str, args: &[(&str, &str)]) -> String {
+        let mut rest = self.tr(msg);
+        let mut out = String::with_capacity(rest.len());
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let value = rest
+                .find('}')
+                .and_then(|end| args.iter().find(|(name, _)| *name == &rest[1..end]).map(|(_, value)| (end, value)));
+            match value {
+                Some((end, value)) => {
+                    out.push_str(value);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    out.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+
+    /// Match a language tag like `de-AT` on its primary subtag
+    fn from_tag(tag: &str) -> Option<Locale> {
+        let primary = tag.split('-').next()?.trim().to_ascii_lowercase();
+        match primary.as_str() {
+            "en" => Some(Locale::En),
+            "de" => Some(Locale::De),
+            "es" => Some(Locale::Es),
+            _ => None,
+        }
+    }
+
+    /// Pick the most preferred supported language from an Accept-Language header
+    pub fn from_accept_language(header: &str) -> Option<Locale> {
+        let mut candidates: Vec<(f32, Locale)> = header
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.split(';');
+                let locale = Locale::from_tag(parts.next()?)?;
+                let quality = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.parse().ok())?;
+                (quality > 0.0).then_some((quality, locale))
+            })
+            .collect();
+
+        // Stable sort keeps header order for equal weights
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+        candidates.first().map(|(_, locale)| *locale)
+    }
+}
+
+/// Resolves the request locale from Accept-Language, falling back to the configured default
+impl FromRequestParts<AppState> for Locale {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .headers
+            .get(axum::http::header::ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Locale::from_accept_language)
+            .unwrap_or(state.config.default_locale))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_accept_language() {
+        assert_eq!(Locale::from_accept_language("de-AT,de;q=0.9,en;q=0.8"), Some(Locale::De));
+        assert_eq!(Locale::from_accept_language("fr-FR,es;q=0.5,en;q=0.7"), Some(Locale::En));
+        assert_eq!(Locale::from_accept_language("en;q=0,es"), Some(Locale::Es));
+        assert_eq!(Locale::from_accept_language("fr,it"), None);
+    }
+
+    #[test]
+    fn test_tr_falls_back_to_source() {
+        assert_eq!(Locale::De.tr("Card not found or disabled"), "Karte nicht gefunden oder deaktiviert");
+        assert_eq!(Locale::De.tr("Something new"), "Something new");
+        assert_eq!(Locale::En.trf("Top up {card}", &[("card", "Alice")]), "Top up Alice");
+    }
+
+    #[test]
+    fn test_trf_substitutes_once() {
+        let args = [("card", "{amount}"), ("amount", "100 sats")];
+        assert_eq!(Locale::En.trf("{amount} from {card}", &args), "100 sats from {amount}");
+        assert_eq!(Locale::En.trf("{unknown} {card", &args), "{unknown} {card");
+        assert_eq!(Locale::En.trf("{{card}}", &args), "{{amount}}");
+    }
+
+    #[test]
+    fn test_catalogs_have_same_messages() {
+        let mut de: Vec<_> = de::MESSAGES.iter().map(|(source, _)| *source).collect();
+        let mut es: Vec<_> = es::MESSAGES.iter().map(|(source, _)| *source).collect();
+        de.sort();
+        es.sort();
+        assert_eq!(de, es);
+    }
+}
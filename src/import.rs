@@ -0,0 +1,162 @@
+//! Import of cards from the LNbits Boltcards extension
+//!
+//! Reads either the JSON returned by the extension's card list API or the
+//! `cards` table of its SQLite database. LNbits only stores K0-K2; its
+//! programming endpoint hands out K1 and K2 as K3 and K4, so the same is
+//! done here.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite, SqlitePool};
+use std::{collections::HashSet, path::Path};
+
+use crate::{
+    crypto::{AesKey, CardUid},
+    db::audit,
+};
+
+/// A card as stored by the LNbits Boltcards extension
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LnbitsCard {
+    pub card_name: String,
+    pub uid: String,
+    pub counter: i64,
+    pub tx_limit: i64,
+    pub daily_limit: i64,
+    pub enable: bool,
+    pub k0: String,
+    pub k1: String,
+    pub k2: String,
+}
+
+/// A card that can't be imported as is
+#[derive(Debug, Clone, Serialize)]
+pub struct Conflict {
+    pub card_name: String,
+    pub uid: String,
+    pub reason: String,
+}
+
+/// Cards ready to import, normalized, and the ones that conflict
+#[derive(Debug, Default)]
+pub struct ImportPlan {
+    pub cards: Vec<LnbitsCard>,
+    pub conflicts: Vec<Conflict>,
+}
+
+pub fn read_json(path: &Path) -> Result<Vec<LnbitsCard>> {
+    Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+}
+
+pub async fn read_sqlite(path: &Path) -> Result<Vec<LnbitsCard>> {
+    let pool = SqlitePool::connect(&format!("sqlite://{}?mode=ro", path.display())).await?;
+    let cards = sqlx::query_as::<_, LnbitsCard>(
+        "SELECT card_name, uid, counter, tx_limit, daily_limit, enable, k0, k1, k2 FROM cards"
+    )
+    .fetch_all(&pool)
+    .await?;
+    pool.close().await;
+
+    Ok(cards)
+}
+
+/// Check `cards` against each other and the existing cards
+pub async fn plan(pool: &Pool<Sqlite>, cards: Vec<LnbitsCard>) -> Result<ImportPlan> {
+    let mut seen: HashSet<String> = sqlx::query_scalar("SELECT uid FROM cards WHERE uid != ''")
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .collect();
+
+    let mut plan = ImportPlan::default();
+    for card in cards {
+        let conflict = |reason: &str| Conflict {
+            card_name: card.card_name.clone(),
+            uid: card.uid.clone(),
+            reason: reason.to_string(),
+        };
+
+        let keys: Result<Vec<String>> = [&card.k0, &card.k1, &card.k2]
+            .into_iter()
+            .map(|key| Ok(AesKey::from_hex(key)?.to_string()))
+            .collect();
+        let Ok(keys) = keys else {
+            plan.conflicts.push(conflict("invalid key"));
+            continue;
+        };
+
+        // Cards that were never tapped have no UID yet and pick it up on their first tap here
+        let uid = if card.uid.is_empty() {
+            String::new()
+        } else {
+            match CardUid::from_hex(&card.uid) {
+                Ok(uid) => uid.to_string(),
+                Err(_) => {
+                    plan.conflicts.push(conflict("invalid UID"));
+                    continue;
+                }
+            }
+        };
+        if !uid.is_empty() && !seen.insert(uid.clone()) {
+            plan.conflicts.push(conflict("UID already exists"));
+            continue;
+        }
+
+        if card.tx_limit <= 0 || card.daily_limit <= 0 || card.counter < 0 {
+            plan.conflicts.push(conflict("invalid limits or counter"));
+            continue;
+        }
+
+        plan.cards.push(LnbitsCard {
+            uid,
+            k0: keys[0].clone(),
+            k1: keys[1].clone(),
+            k2: keys[2].clone(),
+            ..card
+        });
+    }
+
+    Ok(plan)
+}
+
+/// Insert planned cards as already programmed cards in one transaction
+///
+/// Returns the ids of the new cards.
+pub async fn apply(pool: &Pool<Sqlite>, cards: &[LnbitsCard], actor: &str) -> Result<Vec<i64>> {
+    let mut tx = pool.begin().await?;
+
+    let mut card_ids = Vec::new();
+    for card in cards {
+        let result = sqlx::query(
+            "INSERT INTO cards (uid, k0_auth_key, k1_decrypt_key, k2_cmac_key, k3, k4,
+             card_name, tx_limit_sats, day_limit_sats, enabled, last_counter, one_time_code_used)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 1)"
+        )
+        .bind(&card.uid)
+        .bind(&card.k0)
+        .bind(&card.k1)
+        .bind(&card.k2)
+        .bind(&card.k1)
+        .bind(&card.k2)
+        .bind(&card.card_name)
+        .bind(card.tx_limit)
+        .bind(card.daily_limit)
+        .bind(card.enable)
+        .bind(card.counter)
+        .execute(&mut *tx)
+        .await?;
+        card_ids.push(result.last_insert_rowid());
+    }
+
+    audit::record(
+        &mut tx,
+        actor,
+        "import_lnbits",
+        &serde_json::json!({ "card_ids": card_ids }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(card_ids)
+}
@@ -0,0 +1,194 @@
+//! Durable queue for work done outside of requests
+//!
+//! Jobs are stored in the `jobs` table before anything is attempted, so they
+//! survive restarts. Workers claim due jobs, retry failures with exponential
+//! backoff and mark a job dead once it ran out of attempts.
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use std::{fmt, time::Duration};
+use tokio::sync::Notify;
+
+use crate::{
+    app_state::AppState,
+    db::{channels, jobs, models::JobRecord, webhooks},
+    handlers::lnurlw,
+    i18n::Locale,
+    notifications::{self, Notification},
+    rates,
+};
+
+/// Concurrently running workers, so a slow payment doesn't hold up notifications
+const WORKERS: usize = 4;
+/// Attempts before a job is marked dead
+const MAX_ATTEMPTS: i64 = 8;
+/// How often idle workers look for jobs that became due
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Job {
+    /// Pay the reserved invoice of a withdrawal accepted in async mode
+    ExecutePayment { payment_id: i64, locale: Locale },
+    /// Deliver an operator notification
+    Notify { notification: Notification },
+    /// Deliver a notification to one of a cardholder's channels
+    NotifyUser { channel_id: i64, notification: Notification },
+    /// Publish the Nostr receipt of a settled withdrawal
+    PublishReceipt { payment_id: i64 },
+    /// Record the bitcoin price on a settled withdrawal
+    RecordRate { payment_id: i64 },
+}
+
+impl Job {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Job::ExecutePayment { .. } => "execute_payment",
+            Job::Notify { .. } => "notify",
+            Job::NotifyUser { .. } => "notify_user",
+            Job::PublishReceipt { .. } => "publish_receipt",
+            Job::RecordRate { .. } => "record_rate",
+        }
+    }
+}
+
+/// Failure that would repeat on every attempt, the job is marked dead right away
+#[derive(Debug)]
+pub struct Permanent(pub String);
+
+impl fmt::Display for Permanent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Permanent {}
+
+pub struct JobQueue {
+    pool: Pool<Sqlite>,
+    wake: Notify,
+}
+
+impl JobQueue {
+    pub fn new(pool: Pool<Sqlite>) -> Self {
+        Self { pool, wake: Notify::new() }
+    }
+
+    /// Store a job and wake a worker to run it
+    pub async fn enqueue(&self, job: &Job) -> Result<i64> {
+        let mut conn = self.pool.acquire().await?;
+        let job_id = jobs::enqueue(&mut conn, job.kind(), &serde_json::to_string(job)?).await?;
+        self.wake.notify_one();
+        Ok(job_id)
+    }
+}
+
+/// Requeue jobs interrupted by the last shutdown and start the workers
+pub async fn start(state: AppState) -> Result<()> {
+    let requeued = jobs::requeue_interrupted(&state.pool).await?;
+    if requeued > 0 {
+        tracing::warn!("Requeued {} job(s) interrupted by a restart", requeued);
+    }
+
+    for _ in 0..WORKERS {
+        tokio::spawn(work(state.clone()));
+    }
+
+    Ok(())
+}
+
+async fn work(state: AppState) {
+    loop {
+        match jobs::claim(&state.pool, state.clock.now()).await {
+            Ok(Some(job)) => run(&state, job).await,
+            Ok(None) => {
+                let _ = tokio::time::timeout(POLL_INTERVAL, state.jobs.wake.notified()).await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to claim job: {}", e);
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        }
+    }
+}
+
+async fn run(state: &AppState, record: JobRecord) {
+    let job = serde_json::from_str::<Job>(&record.payload);
+    let result = match &job {
+        Ok(job) => execute(state, job.clone()).await,
+        Err(e) => Err(anyhow!("Invalid job payload: {}", e)),
+    };
+    let dead = result.as_ref().is_err_and(|e| record.attempts >= MAX_ATTEMPTS || e.is::<Permanent>());
+
+    // Stored operator notifications track their delivery for redelivery
+    if let Ok(Job::Notify { notification: Notification { event_id: Some(event_id), .. } }) = &job {
+        let error = result.as_ref().err().map(|e| e.to_string());
+        if let Err(e) = webhooks::record_attempt(&state.pool, *event_id, error.as_deref(), dead).await {
+            tracing::error!("Failed to record delivery of event {}: {}", event_id, e);
+        }
+    }
+
+    let outcome = match result {
+        Ok(()) => jobs::complete(&state.pool, record.job_id).await,
+        Err(e) if dead => {
+            tracing::error!("Job {} ({}) failed for good: {}", record.job_id, record.kind, e);
+            jobs::bury(&state.pool, record.job_id, &e.to_string()).await
+        }
+        Err(e) => {
+            tracing::warn!("Job {} ({}) failed, retrying: {}", record.job_id, record.kind, e);
+            let run_after = state.clock.now() + chrono::Duration::seconds(backoff_secs(record.attempts));
+            jobs::retry_later(&state.pool, record.job_id, &e.to_string(), run_after).await
+        }
+    };
+
+    if let Err(e) = outcome {
+        tracing::error!("Failed to update job {}: {}", record.job_id, e);
+    }
+}
+
+async fn execute(state: &AppState, job: Job) -> Result<()> {
+    match job {
+        Job::ExecutePayment { payment_id, locale } => lnurlw::execute_queued_payment(state, payment_id, locale).await,
+        Job::Notify { notification } => state.notifier.notify(&notification).await,
+        Job::NotifyUser { channel_id, notification } => {
+            // Nothing to do once the user removed the channel
+            match channels::get_channel(&state.pool, channel_id).await? {
+                Some(channel) => state.channels.deliver(&channel, &notification).await,
+                None => Ok(()),
+            }
+        }
+        Job::PublishReceipt { payment_id } => notifications::publish_receipt(state, payment_id).await,
+        Job::RecordRate { payment_id } => rates::record_rate(state, payment_id).await,
+    }
+}
+
+/// Delay before the next attempt: 10s, 20s, 40s, ... capped at an hour
+fn backoff_secs(attempts: i64) -> i64 {
+    (10i64 << attempts.clamp(1, 12).saturating_sub(1)).min(3600)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_secs() {
+        assert_eq!(backoff_secs(1), 10);
+        assert_eq!(backoff_secs(2), 20);
+        assert_eq!(backoff_secs(4), 80);
+        assert_eq!(backoff_secs(20), 3600);
+    }
+
+    #[test]
+    fn test_notify_jobs_without_event_id() {
+        // Queued before operator notifications were stored
+        let payload = r#"{"kind":"notify","notification":{"event":"counter_exhausted","card_id":1,"card_name":"A","timestamp":"2025-01-01T00:00:00Z"}}"#;
+
+        let Job::Notify { notification } = serde_json::from_str(payload).unwrap() else {
+            panic!("Not a notify job");
+        };
+        assert_eq!(notification.event_id, None);
+        assert!(!serde_json::to_string(&notification).unwrap().contains("event_id"));
+    }
+}
@@ -0,0 +1,248 @@
+use anyhow::Result;
+use sqlx::{Pool, Sqlite};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicI64, AtomicU64, Ordering},
+    },
+};
+
+use crate::{db::{models::Card, queries, rotation}, validation::{AuthError, CardKeys}};
+
+#[derive(Debug)]
+struct Entry {
+    k1_hex: String,
+    k2_hex: String,
+    keys: Arc<CardKeys>,
+    /// Value of [`KeyCache::uses`] when the keys were last used
+    last_used: AtomicU64,
+}
+
+/// An enabled card with its parsed keys, for taps without card ID
+#[derive(Debug)]
+pub struct Candidate {
+    pub card_id: i64,
+    pub org_id: Option<i64>,
+    /// Empty until the card's first tap
+    pub uid: String,
+    pub keys: CardKeys,
+}
+
+/// The enabled cards, indexed by card ID
+#[derive(Debug, Default)]
+pub struct Candidates {
+    pub cards: Vec<Candidate>,
+    by_id: HashMap<i64, usize>,
+}
+
+impl Candidates {
+    pub fn get(&self, card_id: i64) -> Option<&Candidate> {
+        self.by_id.get(&card_id).map(|&index| &self.cards[index])
+    }
+}
+
+/// How many recently tapped cards are tried first when a tap has no card ID
+const RECENT_CARDS: usize = 128;
+
+/// How many cards' keys are kept, the least recently used are dropped beyond
+const CAPACITY: usize = 10_000;
+
+/// Cache of parsed card keys, so taps don't hex-parse them every time
+///
+/// Keys are dropped once the database's key generation changes, i.e. after a
+/// rotation, erasure or purge, also by another process like the CLI. Entries
+/// are still checked against the hex keys of the loaded card, for a tap that
+/// loaded its card before a change and parses the keys after the refresh.
+#[derive(Debug)]
+pub struct KeyCache {
+    entries: RwLock<HashMap<i64, Entry>>,
+    capacity: usize,
+    /// Key generation the entries were parsed in, -1 before the first refresh
+    generation: AtomicI64,
+    /// Counts uses of the cache, for finding the least recently used entry
+    uses: AtomicU64,
+    /// The enabled cards with the key generation they were loaded in
+    candidates: RwLock<Option<(i64, Arc<Candidates>)>>,
+    /// Most recently tapped cards first
+    recent: Mutex<VecDeque<i64>>,
+}
+
+impl Default for KeyCache {
+    fn default() -> Self {
+        Self::with_capacity(CAPACITY)
+    }
+}
+
+impl KeyCache {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: RwLock::default(),
+            capacity,
+            generation: AtomicI64::new(-1),
+            uses: AtomicU64::new(0),
+            candidates: RwLock::default(),
+            recent: Mutex::default(),
+        }
+    }
+
+    /// Drop all keys if card keys or the enabled cards changed since the last refresh
+    pub async fn refresh(&self, pool: &Pool<Sqlite>) -> Result<()> {
+        let generation = rotation::key_generation(pool).await?;
+        if self.generation.swap(generation, Ordering::AcqRel) != generation {
+            self.entries.write().unwrap().clear();
+            *self.candidates.write().unwrap() = None;
+        }
+        Ok(())
+    }
+
+    /// The enabled cards with their keys, loaded once per key generation
+    ///
+    /// Cards with unparsable keys are left out, as no tap can match them.
+    pub async fn candidates(&self, pool: &Pool<Sqlite>) -> Result<Arc<Candidates>> {
+        // Taken before loading, so cards changed meanwhile are loaded again on the next refresh
+        let generation = self.generation.load(Ordering::Acquire);
+        if let Some((loaded, candidates)) = &*self.candidates.read().unwrap()
+            && *loaded == generation
+        {
+            return Ok(candidates.clone());
+        }
+
+        let mut candidates = Candidates::default();
+        for card in queries::list_tap_candidates(pool).await? {
+            let Ok(keys) = CardKeys::from_hex(&card.k1_decrypt_key, &card.k2_cmac_key) else {
+                continue;
+            };
+            candidates.by_id.insert(card.card_id, candidates.cards.len());
+            candidates.cards.push(Candidate {
+                card_id: card.card_id,
+                org_id: card.org_id,
+                uid: card.uid,
+                keys,
+            });
+        }
+
+        let candidates = Arc::new(candidates);
+        *self.candidates.write().unwrap() = Some((generation, candidates.clone()));
+        Ok(candidates)
+    }
+
+    /// The card's parsed keys
+    pub fn keys(&self, card: &Card) -> Result<Arc<CardKeys>, AuthError> {
+        let use_count = self.uses.fetch_add(1, Ordering::Relaxed);
+        if let Some(entry) = self.entries.read().unwrap().get(&card.card_id)
+            && entry.k1_hex == card.k1_decrypt_key
+            && entry.k2_hex == card.k2_cmac_key
+        {
+            entry.last_used.store(use_count, Ordering::Relaxed);
+            return Ok(entry.keys.clone());
+        }
+
+        let keys = Arc::new(CardKeys::from_card(card)?);
+        let mut entries = self.entries.write().unwrap();
+        if entries.len() >= self.capacity
+            && !entries.contains_key(&card.card_id)
+            && let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used.load(Ordering::Relaxed))
+                .map(|(&card_id, _)| card_id)
+        {
+            entries.remove(&oldest);
+        }
+        entries.insert(
+            card.card_id,
+            Entry {
+                k1_hex: card.k1_decrypt_key.clone(),
+                k2_hex: card.k2_cmac_key.clone(),
+                keys: keys.clone(),
+                last_used: AtomicU64::new(use_count),
+            },
+        );
+
+        Ok(keys)
+    }
+
+    /// Mark a card as recently tapped
+    pub fn touch(&self, card_id: i64) {
+        let mut recent = self.recent.lock().unwrap();
+        recent.retain(|&id| id != card_id);
+        recent.push_front(card_id);
+        recent.truncate(RECENT_CARDS);
+    }
+
+    /// The recently tapped cards, most recent first
+    pub fn recent(&self) -> Vec<i64> {
+        self.recent.lock().unwrap().iter().copied().collect()
+    }
+
+    /// IDs of the cards whose keys are cached
+    #[cfg(test)]
+    fn cached(&self) -> Vec<i64> {
+        let mut card_ids: Vec<i64> = self.entries.read().unwrap().keys().copied().collect();
+        card_ids.sort();
+        card_ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{bulk::CardSelector, queries, run_migrations};
+
+    async fn card(pool: &Pool<Sqlite>, card_id: i64) -> Card {
+        queries::get_card_by_id(pool, card_id).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_evicts_least_recently_used() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let key = "00000000000000000000000000000000";
+        let mut cards = Vec::new();
+        for code in ["a", "b", "c"] {
+            let card_id = queries::insert_card(&pool, "", key, key, key, key, key, code, 1000, 10000, true, false, code, None, None, chrono::Utc::now())
+                .await
+                .unwrap();
+            cards.push(card(&pool, card_id).await);
+        }
+        let cache = KeyCache::with_capacity(2);
+        cache.refresh(&pool).await.unwrap();
+
+        let first = cache.keys(&cards[0]).unwrap();
+        cache.keys(&cards[1]).unwrap();
+        // Using the first card again makes the second the least recently used
+        assert!(Arc::ptr_eq(&first, &cache.keys(&cards[0]).unwrap()));
+        cache.keys(&cards[2]).unwrap();
+
+        assert_eq!(cache.cached(), vec![cards[0].card_id, cards[2].card_id]);
+        assert!(Arc::ptr_eq(&first, &cache.keys(&cards[0]).unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_after_rotation() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let key = "00000000000000000000000000000000";
+        let card_id = queries::insert_card(&pool, "", key, key, key, key, key, "Card", 1000, 10000, true, false, "code", None, None, chrono::Utc::now())
+            .await
+            .unwrap();
+        let cache = KeyCache::default();
+        cache.refresh(&pool).await.unwrap();
+        let before = cache.keys(&card(&pool, card_id).await).unwrap();
+
+        // Nothing changed, the keys stay cached
+        cache.refresh(&pool).await.unwrap();
+        assert!(Arc::ptr_eq(&before, &cache.keys(&card(&pool, card_id).await).unwrap()));
+
+        let selector = CardSelector { card_ids: Some(vec![card_id]), ..Default::default() };
+        rotation::rotate(&pool, &selector, chrono::Duration::hours(1), "test", &crate::random::Random::default())
+            .await
+            .unwrap();
+        cache.refresh(&pool).await.unwrap();
+        assert!(cache.cached().is_empty());
+        let rotated = card(&pool, card_id).await;
+        let after = cache.keys(&rotated).unwrap();
+        assert!(!Arc::ptr_eq(&before, &after));
+        assert_eq!(after.k1.to_string(), rotated.k1_decrypt_key);
+    }
+}
@@ -0,0 +1,289 @@
+//! Settled withdrawals as a double-entry journal for plaintext accounting
+//!
+//! Every withdrawal debits the expense account of its card and, if the
+//! backend reported one, the fee account with the routing fee; the asset
+//! account of the node that paid it is credited with both. A service fee is
+//! booked separately, charged to the card's account and credited to the
+//! service fee income account. Cards of an organization are paid from its
+//! node, booked to a sub-account named after the organization.
+
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::{collections::BTreeSet, fmt::Write};
+
+use crate::db::ledger::LedgerPayment;
+
+const COMMODITY: &str = "BTC";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LedgerFormat {
+    Beancount,
+    /// ledger-cli and hledger
+    Ledger,
+}
+
+/// Account names the journal books to
+#[derive(Debug, Clone)]
+pub struct LedgerAccounts {
+    /// Asset account of the server's node, organizations' nodes get a sub-account
+    pub node: String,
+    /// Parent of the per-card expense accounts
+    pub cards: String,
+    pub fees: String,
+    /// Income account of service fees
+    pub service_fees: String,
+}
+
+impl Default for LedgerAccounts {
+    fn default() -> Self {
+        Self {
+            node: "Assets:Lightning:Node".to_string(),
+            cards: "Expenses:Cards".to_string(),
+            fees: "Expenses:Lightning:Fees".to_string(),
+            service_fees: "Income:ServiceFees".to_string(),
+        }
+    }
+}
+
+impl LedgerAccounts {
+    /// The first account name that isn't usable in both formats
+    pub fn invalid(&self) -> Option<&str> {
+        [&self.node, &self.cards, &self.fees, &self.service_fees]
+            .into_iter()
+            .find(|account| !is_valid_account(account))
+            .map(String::as_str)
+    }
+
+    fn node(&self, org_slug: Option<&str>) -> String {
+        match org_slug {
+            Some(slug) => format!("{}:{}", self.node, account_component(slug)),
+            None => self.node.clone(),
+        }
+    }
+
+    fn card(&self, card_id: i64) -> String {
+        format!("{}:Card{}", self.cards, card_id)
+    }
+}
+
+/// Colon-separated components starting with a capital letter or digit, as beancount requires
+fn is_valid_account(account: &str) -> bool {
+    account.split(':').all(|component| {
+        component.starts_with(|c: char| c.is_ascii_uppercase() || c.is_ascii_digit())
+            && component.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    })
+}
+
+/// An organization slug as an account component, e.g. `shop-a` as `Shop-a`
+fn account_component(slug: &str) -> String {
+    let component: String = slug
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let mut chars = component.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphanumeric() => first.to_ascii_uppercase().to_string() + chars.as_str(),
+        _ => format!("X{}", component),
+    }
+}
+
+/// An amount in BTC, with 8 decimals unless it has msats
+fn format_btc(msats: i64) -> String {
+    let sign = if msats < 0 { "-" } else { "" };
+    let msats = msats.unsigned_abs();
+    let fraction = format!("{:011}", msats % 100_000_000_000);
+    let fraction = match fraction.trim_end_matches('0') {
+        trimmed if trimmed.len() <= 8 => &fraction[..8],
+        trimmed => trimmed,
+    };
+    format!("{}{}.{} {}", sign, msats / 100_000_000_000, fraction, COMMODITY)
+}
+
+/// Text fit for a single-line, double-quoted narration
+fn single_line(text: &str) -> String {
+    let text: String = text
+        .chars()
+        .map(|c| if c.is_control() || c == '"' || c == '\\' { ' ' } else { c })
+        .collect();
+    text.trim().to_string()
+}
+
+/// The card name, followed by the memo if the payment has one
+fn narration(payment: &LedgerPayment) -> String {
+    let narration = format!("Withdrawal {} of card {} ({})", payment.payment_id, payment.card_id, single_line(&payment.card_name));
+    match payment.memo.as_deref().map(single_line).filter(|memo| !memo.is_empty()) {
+        Some(memo) => format!("{}: {}", narration, memo),
+        None => narration,
+    }
+}
+
+/// The journal of `payments`, opening the accounts it uses on `from`
+pub fn render(format: LedgerFormat, accounts: &LedgerAccounts, from: NaiveDate, to: NaiveDate, payments: &[LedgerPayment]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "; Withdrawals settled from {} to {} (UTC)", from, to);
+
+    let mut used = BTreeSet::new();
+    for payment in payments {
+        used.insert(accounts.node(payment.org_slug.as_deref()));
+        used.insert(accounts.card(payment.card_id));
+        if payment.fee_msats.unwrap_or(0) > 0 {
+            used.insert(accounts.fees.clone());
+        }
+        if payment.service_fee_msats > 0 {
+            used.insert(accounts.service_fees.clone());
+        }
+    }
+    if !used.is_empty() {
+        out.push('\n');
+    }
+    for account in &used {
+        let _ = match format {
+            LedgerFormat::Beancount => writeln!(out, "{} open {} {}", from, account, COMMODITY),
+            LedgerFormat::Ledger => writeln!(out, "account {}", account),
+        };
+    }
+
+    for payment in payments {
+        let date = payment.payment_time.get(..10).unwrap_or(&payment.payment_time);
+        let fee_msats = payment.fee_msats.unwrap_or(0).max(0);
+        let mut postings = vec![(accounts.card(payment.card_id), payment.amount_msats)];
+        if fee_msats > 0 {
+            postings.push((accounts.fees.clone(), fee_msats));
+        }
+        postings.push((accounts.node(payment.org_slug.as_deref()), -(payment.amount_msats + fee_msats)));
+        if payment.service_fee_msats > 0 {
+            postings.push((accounts.card(payment.card_id), payment.service_fee_msats));
+            postings.push((accounts.service_fees.clone(), -payment.service_fee_msats));
+        }
+
+        out.push('\n');
+        let _ = match format {
+            LedgerFormat::Beancount => writeln!(out, "{} * \"{}\"", date, narration(payment)),
+            LedgerFormat::Ledger => writeln!(out, "{} * {}", date, narration(payment)),
+        };
+        if let Some(hash) = &payment.payment_hash {
+            let _ = match format {
+                LedgerFormat::Beancount => writeln!(out, "  payment_hash: \"{}\"", hash),
+                LedgerFormat::Ledger => writeln!(out, "    ; payment_hash: {}", hash),
+            };
+        }
+        if let Some(txid) = &payment.onchain_txid {
+            let _ = match format {
+                LedgerFormat::Beancount => writeln!(out, "  txid: \"{}\"", txid),
+                LedgerFormat::Ledger => writeln!(out, "    ; txid: {}", txid),
+            };
+        }
+        let indent = match format {
+            LedgerFormat::Beancount => "  ",
+            LedgerFormat::Ledger => "    ",
+        };
+        for (account, msats) in postings {
+            let _ = writeln!(out, "{}{:<40}  {:>20}", indent, account, format_btc(msats));
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payment(payment_id: i64, org_slug: Option<&str>, fee_msats: Option<i64>) -> LedgerPayment {
+        LedgerPayment {
+            payment_id,
+            card_id: 7,
+            card_name: "Till \"1\"".to_string(),
+            org_slug: org_slug.map(str::to_string),
+            amount_msats: 2_000_500,
+            fee_msats,
+            service_fee_msats: 0,
+            payment_time: "2026-03-04 12:00:00".to_string(),
+            payment_hash: Some("ab".repeat(32)),
+            memo: None,
+            onchain_txid: None,
+        }
+    }
+
+    /// Lines of the journal with runs of whitespace collapsed
+    fn lines(journal: &str) -> Vec<String> {
+        journal.lines().map(|line| line.split_whitespace().collect::<Vec<_>>().join(" ")).collect()
+    }
+
+    #[test]
+    fn test_format_btc() {
+        assert_eq!(format_btc(2_000_000), "0.00002000 BTC");
+        assert_eq!(format_btc(2_000_500), "0.000020005 BTC");
+        assert_eq!(format_btc(-150_000_000_000), "-1.50000000 BTC");
+        assert_eq!(format_btc(0), "0.00000000 BTC");
+    }
+
+    #[test]
+    fn test_accounts() {
+        let accounts = LedgerAccounts::default();
+        assert!(accounts.invalid().is_none());
+        assert_eq!(accounts.node(Some("shop-a")), "Assets:Lightning:Node:Shop-a");
+        assert_eq!(accounts.card(7), "Expenses:Cards:Card7");
+
+        let accounts = LedgerAccounts { fees: "expenses:fees".to_string(), ..Default::default() };
+        assert_eq!(accounts.invalid(), Some("expenses:fees"));
+        assert!(!is_valid_account("Assets::Node"));
+        assert!(!is_valid_account("Assets:My Node"));
+    }
+
+    #[test]
+    fn test_render_beancount() {
+        let from = NaiveDate::from_ymd_opt(2026, 3, 1).unwrap();
+        let to = NaiveDate::from_ymd_opt(2026, 3, 31).unwrap();
+        let journal = render(
+            LedgerFormat::Beancount,
+            &LedgerAccounts::default(),
+            from,
+            to,
+            &[
+                LedgerPayment { service_fee_msats: 20_000, ..payment(1, None, Some(1_000)) },
+                payment(2, Some("shop-a"), None),
+            ],
+        );
+
+        let lines = lines(&journal);
+        for expected in [
+            "2026-03-01 open Expenses:Lightning:Fees BTC".to_string(),
+            "2026-03-01 open Assets:Lightning:Node:Shop-a BTC".to_string(),
+            "2026-03-04 * \"Withdrawal 1 of card 7 (Till 1)\"".to_string(),
+            format!("payment_hash: \"{}\"", "ab".repeat(32)),
+            "Expenses:Cards:Card7 0.000020005 BTC".to_string(),
+            "Expenses:Lightning:Fees 0.00000001 BTC".to_string(),
+            "Assets:Lightning:Node -0.000020015 BTC".to_string(),
+            "Assets:Lightning:Node:Shop-a -0.000020005 BTC".to_string(),
+            "2026-03-01 open Income:ServiceFees BTC".to_string(),
+            "Expenses:Cards:Card7 0.00000020 BTC".to_string(),
+            "Income:ServiceFees -0.00000020 BTC".to_string(),
+        ] {
+            assert!(lines.contains(&expected), "missing {:?} in\n{}", expected, journal);
+        }
+    }
+
+    #[test]
+    fn test_render_ledger() {
+        let day = NaiveDate::from_ymd_opt(2026, 3, 4).unwrap();
+        let annotated = LedgerPayment { memo: Some("Team lunch\n".to_string()), ..payment(2, None, None) };
+        let onchain = LedgerPayment { payment_hash: None, onchain_txid: Some("cd".repeat(32)), ..payment(3, None, None) };
+        let journal = render(
+            LedgerFormat::Ledger,
+            &LedgerAccounts::default(),
+            day,
+            day,
+            &[payment(1, None, Some(0)), annotated, onchain],
+        );
+
+        let lines = lines(&journal);
+        assert!(lines.contains(&"account Expenses:Cards:Card7".to_string()));
+        assert!(lines.contains(&"2026-03-04 * Withdrawal 1 of card 7 (Till 1)".to_string()));
+        assert!(lines.contains(&"2026-03-04 * Withdrawal 2 of card 7 (Till 1): Team lunch".to_string()));
+        assert!(lines.contains(&format!("; payment_hash: {}", "ab".repeat(32))));
+        assert!(lines.contains(&format!("; txid: {}", "cd".repeat(32))));
+        assert!(!journal.contains("Fees"));
+    }
+}
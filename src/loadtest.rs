@@ -0,0 +1,191 @@
+//! Synthetic tap and withdrawal traffic against a running server
+//!
+//! Each worker plays one card: it taps with the card's next counter, as the
+//! card would, and withdraws the offered minimum to a mock invoice, as a
+//! wallet would. The latencies of both requests are reported as percentiles,
+//! to check performance work under concurrent load.
+//!
+//! The invoices are made up, so the server has to run with the mock backend.
+
+use anyhow::Result;
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::task::JoinSet;
+
+use crate::{
+    capture::{outcome, parse_body},
+    crypto::{AesKey, CardUid, Counter, generate_sun},
+    lightning::{LightningBackend, MockLightning},
+};
+
+/// A card the load test taps, with the keys it was created with
+#[derive(Debug, Clone)]
+pub struct LoadCard {
+    pub card_id: i64,
+    pub k1: AesKey,
+    pub k2: AesKey,
+    pub uid: CardUid,
+}
+
+/// Request latencies, sorted once collected
+#[derive(Debug, Clone, Default)]
+pub struct Latencies(Vec<Duration>);
+
+impl Latencies {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The latency `percent` of the requests stayed within, by nearest rank
+    pub fn percentile(&self, percent: f64) -> Option<Duration> {
+        let rank = (percent / 100.0 * self.0.len() as f64).ceil() as usize;
+        self.0.get(rank.clamp(1, self.0.len().max(1)) - 1).copied()
+    }
+
+    fn sort(&mut self) {
+        self.0.sort();
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub taps: Latencies,
+    pub callbacks: Latencies,
+    /// How many requests got each answer, see [`outcome`]
+    pub outcomes: BTreeMap<String, usize>,
+    pub elapsed: Duration,
+}
+
+impl Report {
+    /// Withdrawals the server accepted
+    pub fn completed(&self) -> usize {
+        self.outcomes.get("OK").copied().unwrap_or_default()
+    }
+
+    fn merge(&mut self, other: Report) {
+        self.taps.0.extend(other.taps.0);
+        self.callbacks.0.extend(other.callbacks.0);
+        for (outcome, count) in other.outcomes {
+            *self.outcomes.entry(outcome).or_default() += count;
+        }
+    }
+}
+
+/// Tap and withdraw `withdrawals` times in total, with one worker per card
+pub async fn run(
+    http: &reqwest::Client,
+    server: &str,
+    cards: Vec<LoadCard>,
+    withdrawals: usize,
+    invoices: Arc<MockLightning>,
+) -> Result<Report> {
+    let server = server.trim_end_matches('/').to_string();
+    let started = Arc::new(AtomicUsize::new(0));
+    let begin = Instant::now();
+
+    let mut workers = JoinSet::new();
+    for card in cards {
+        let (http, server, started, invoices) = (http.clone(), server.clone(), started.clone(), invoices.clone());
+        workers.spawn(async move {
+            let mut report = Report::default();
+            let mut counter = 0;
+            while started.fetch_add(1, Ordering::Relaxed) < withdrawals {
+                counter += 1;
+                withdraw(&http, &server, &card, counter, &invoices, &mut report).await?;
+            }
+            anyhow::Ok(report)
+        });
+    }
+
+    let mut report = Report::default();
+    while let Some(worker) = workers.join_next().await {
+        report.merge(worker??);
+    }
+    report.elapsed = begin.elapsed();
+    report.taps.sort();
+    report.callbacks.sort();
+
+    Ok(report)
+}
+
+/// One tap and, if it was accepted, the withdrawal it offers
+async fn withdraw(
+    http: &reqwest::Client,
+    server: &str,
+    card: &LoadCard,
+    counter: u32,
+    invoices: &MockLightning,
+    report: &mut Report,
+) -> Result<()> {
+    let (p, c) = generate_sun(&card.k1, &card.k2, &card.uid, &Counter::new(counter));
+    let url = format!("{}/ln?card_id={}&p={}&c={}", server, card.card_id, hex::encode_upper(p), hex::encode_upper(c));
+    let tap = get(http, &url, &[]).await;
+    report.taps.0.push(tap.latency);
+
+    let (Some(k1), Some(msats)) = (tap.body["k1"].as_str(), tap.body["minWithdrawable"].as_u64()) else {
+        *report.outcomes.entry(format!("tap {}", tap.outcome())).or_default() += 1;
+        return Ok(());
+    };
+    let description = tap.body["defaultDescription"].as_str().unwrap_or_default();
+    let invoice = invoices.create_invoice(msats, description).await?;
+
+    let callback = get(http, &format!("{}/ln/callback", server), &[("k1", k1), ("pr", &invoice.bolt11())]).await;
+    report.callbacks.0.push(callback.latency);
+    *report.outcomes.entry(callback.outcome()).or_default() += 1;
+
+    Ok(())
+}
+
+struct Answer {
+    /// `None` if no answer came
+    status: Option<u16>,
+    body: Value,
+    latency: Duration,
+}
+
+impl Answer {
+    fn outcome(&self) -> String {
+        match self.status {
+            Some(status) => outcome(status, &self.body),
+            None => "no answer".to_string(),
+        }
+    }
+}
+
+async fn get(http: &reqwest::Client, url: &str, query: &[(&str, &str)]) -> Answer {
+    let begin = Instant::now();
+    let (status, body) = match http.get(url).query(query).send().await {
+        Ok(response) => {
+            let status = response.status().as_u16();
+            (Some(status), response.bytes().await.map(|bytes| parse_body(&bytes)).unwrap_or_default())
+        }
+        Err(e) => {
+            tracing::debug!("Request to {} failed: {}", url, e);
+            (None, Value::Null)
+        }
+    };
+    Answer { status, body, latency: begin.elapsed() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let mut latencies = Latencies((1..=200).rev().map(Duration::from_millis).collect());
+        latencies.sort();
+        assert_eq!(latencies.percentile(50.0), Some(Duration::from_millis(100)));
+        assert_eq!(latencies.percentile(99.0), Some(Duration::from_millis(198)));
+        assert_eq!(latencies.percentile(100.0), Some(Duration::from_millis(200)));
+        assert_eq!(latencies.percentile(0.0), Some(Duration::from_millis(1)));
+        assert_eq!(Latencies::default().percentile(50.0), None);
+    }
+}
@@ -1,26 +1,68 @@
 mod app_state;
+mod auth;
+mod backends;
+mod backup;
+mod capture;
+mod chaos;
+mod cli;
+mod clock;
 mod config;
-mod crypto;
 mod db;
+mod description;
+mod diagnostics;
+mod feed;
+mod fees;
+mod freeze;
+mod graphql;
 mod handlers;
-mod lightning;
+mod i18n;
+mod import;
+mod jobs;
+mod key_cache;
+mod ledger;
+mod loadtest;
+mod notifications;
+mod pdf;
+mod plugin;
+mod policy;
+mod purge;
+mod random;
+mod rates;
+mod receipts;
+mod reconcile;
+mod reports;
+mod self_test;
+mod tenant;
 mod validation;
+mod versioning;
 
 use axum::{
-    routing::{get, post},
+    middleware,
+    routing::{delete, get, post, put},
     Router,
 };
 use clap::Parser;
 use std::sync::Arc;
 use tower::ServiceBuilder;
-use tower_http::trace::TraceLayer;
+use tower_http::{services::ServeDir, trace::TraceLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use app_state::AppState;
-use config::Config;
+use backends::LightningBackends;
+use cli::{Cli, Command};
+use config::{Config, DatabaseConfig};
 use db::init_pool;
-use handlers::{lnurlw, register};
-use lightning::MockLightning;
+use handlers::{
+    bulk, dashboard, lnurlw, lost, payments, print, privacy, receipt, register, stats, tokens, topup, users, wallet,
+    webhooks, widget,
+};
+use jobs::JobQueue;
+use lnurlw_core::{crypto, lightning};
+use lightning::LightningBackend;
+use notifications::{LogNotifier, Notifier, WebhookNotifier, channels::Channels};
+
+/// Shared in-memory SQLite database of `dev`
+const DEV_DATABASE_URL: &str = "sqlite:file:lnurlw-dev?mode=memory&cache=shared";
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -33,34 +75,288 @@ async fn main() -> anyhow::Result<()> {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    // Parse configuration
-    let config = Arc::new(Config::parse());
+    let cli = Cli::parse();
+
+    match cli.command {
+        Command::Serve(config) => serve(&cli.database, config).await,
+        Command::Dev(args) => dev(&cli.database, &args).await,
+        command => cli::run(&command, &cli.database).await,
+    }
+}
+
+/// Serve on an in-memory database with sample data, printing how to try a withdrawal
+async fn dev(database: &DatabaseConfig, args: &cli::DevArgs) -> anyhow::Result<()> {
+    let config = args.config()?;
+    let database = DatabaseConfig { database_url: DEV_DATABASE_URL.to_string(), ..database.clone() };
+    // The database lives as long as a connection to it is open, the pool may close all of its own
+    let _keep = <sqlx::SqliteConnection as sqlx::Connection>::connect(DEV_DATABASE_URL).await?;
+
+    let pool = init_pool(&database).await?;
+    let seeded = db::seed::seed(&pool, &random::Random::from_config(&config)?)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("in-memory database already seeded"))?;
+    let invoice = lightning::MockLightning::default().create_invoice(1_000_000, "Dev withdrawal").await?;
+    let server = config.urls().url("");
+
+    println!("Development server on {} with {} sample cards, nothing is kept after it stops\n", server, seeded.cards.len());
+    println!("Tap the test vector card, each URL once and in this order:");
+    for url in db::seed::tap_urls(&server, seeded.test_vector_card().card_id) {
+        println!("  curl '{}'", url);
+    }
+    println!("\nWithdraw 1000 sats with the k1 of a tap:");
+    println!("  curl '{}/ln/callback?k1=<k1>&pr={}'", server, invoice.bolt11());
+    println!("\nMore taps, raising the counter each time:");
+    println!(
+        "  curl \"$(lnurlw-server simulate-tap {} --server {} --k1 {} --k2 {} --uid {} --counter 8)\"",
+        seeded.test_vector_card().card_id,
+        server,
+        db::seed::TEST_VECTOR_K1,
+        db::seed::TEST_VECTOR_K2,
+        db::seed::TEST_VECTOR_UID
+    );
+    println!("\nRegister a card: {}?a={}\n", config.urls().registration_base(), seeded.test_vector_card().one_time_code);
+
+    serve(&database, config).await
+}
+
+async fn serve(database: &DatabaseConfig, config: Config) -> anyhow::Result<()> {
+    config.public.check_scheme(&config.domain)?;
+    config.check_domain_resolves().await?;
+    let config = Arc::new(config);
 
     // Initialize database
-    let pool = init_pool(&config.database_url).await?;
+    let pool = init_pool(database).await?;
+
+    let random = random::Random::from_config(&config)?;
+    if config.dev {
+        seed(&pool, &config, &random).await?;
+    }
+
+    let mut lightning = LightningBackends::new(config.lightning_backend()?);
+    let chaos = config.chaos.then(|| Arc::new(chaos::Chaos::new(pool.clone())));
+    if chaos.is_some() {
+        lightning = lightning.with_chaos();
+    }
+    let lightning = Arc::new(lightning);
+    let http = reqwest::Client::new();
+
+    if config.self_test {
+        let checks = self_test::run(database, &config, &lightning, &http).await;
+        for check in &checks {
+            let (label, details) = (check.outcome.label(), check.outcome.details());
+            if check.failed() {
+                tracing::error!("Self-test {} {}: {}", label, check.name, details);
+            } else {
+                tracing::info!("Self-test {} {}: {}", label, check.name, details);
+            }
+        }
+        let failed = checks.iter().filter(|check| check.failed()).count();
+        if failed > 0 {
+            anyhow::bail!("{} self-test check(s) failed", failed);
+        }
+    }
 
-    // Initialize Lightning backend (using mock for now)
-    let lightning: Arc<dyn lightning::LightningBackend> = Arc::new(MockLightning);
+    // Operator notifications go to the webhook if configured, otherwise only to the log
+    let notifier: Arc<dyn Notifier> = match &config.operator_webhook_url {
+        Some(url) => Arc::new(WebhookNotifier::new(http.clone(), url.clone())),
+        None => Arc::new(LogNotifier),
+    };
+    // Cardholders can set up channels of the kinds configured here
+    let channels = Arc::new(Channels::from_config(&config, http.clone())?);
+    let rates = Arc::new(rates::Rates::from_config(&config, http.clone())?);
+    let receipts = Arc::new(receipts::ReceiptSigner::load(&pool, &config, &random).await?);
 
     // Create shared state
     let state = AppState {
-        pool,
+        pool: pool.clone(),
         config: config.clone(),
         lightning,
+        http,
+        notifier,
+        channels,
+        rates,
+        clock: Arc::new(clock::SystemClock),
+        random,
+        key_cache: Arc::default(),
+        receipts,
+        jobs: Arc::new(JobQueue::new(pool.clone())),
+        validator: Arc::new(validation::CardValidator::new_default()),
+        graphql: config.graphql.then(|| graphql::schema(pool.clone())),
     };
 
-    // Build router
-    let app = Router::new()
-        // LNURLw endpoints
+    // Workers for queued payments and notifications, the check against the node, expiring freezes and purges
+    jobs::start(state.clone()).await?;
+    reconcile::start(state.clone());
+    fees::start(state.clone());
+    freeze::start(state.clone());
+    purge::start(state.clone());
+
+    // Operator API and dashboard, not served on the domains of organizations
+    let mut operator_api = Router::new()
+        .route("/createboltcard", post(register::create_card))
+        .route("/cards/{card_id}/insert.pdf", get(print::card_insert_pdf))
+        .route("/cards/inserts.pdf", post(print::batch_inserts_pdf))
+        .route("/stats", get(stats::get_stats))
+        .route("/ledger", get(handlers::ledger::export))
+        .route("/reports/fiat", get(handlers::reports::fiat_report))
+        .route("/fees", get(handlers::fees::list_fees))
+        .route("/graphql", post(handlers::graphql::execute))
+        .route("/cards/bulk", post(bulk::bulk_update))
+        .route("/cards/{card_id}/tags", put(bulk::set_tags))
+        .route("/cards/{card_id}/nostr", put(bulk::set_nostr_pubkey))
+        .route("/cards/metadata", get(handlers::metadata::find_cards))
+        .route(
+            "/cards/{card_id}/metadata",
+            get(handlers::metadata::get_metadata)
+                .put(handlers::metadata::set_metadata)
+                .patch(handlers::metadata::update_metadata),
+        )
+        .route(
+            "/cards/{card_id}/description",
+            get(handlers::description::get_description).put(handlers::description::set_description),
+        )
+        .route(
+            "/cards/{card_id}/fee",
+            get(handlers::fees::get_card_fee)
+                .put(handlers::fees::set_card_fee)
+                .delete(handlers::fees::clear_card_fee),
+        )
+        .route(
+            "/cards/{card_id}/onchain-fallback",
+            get(handlers::onchain::get_onchain_fallback).put(handlers::onchain::set_onchain_fallback),
+        )
+        .route("/cards/{card_id}", delete(handlers::deletion::delete_card))
+        .route("/cards/{card_id}/restore", post(handlers::deletion::restore_card))
+        .route("/cards/{card_id}/diagnostics", get(handlers::diagnostics::get_diagnostics))
+        .route("/cards/{card_id}/export", get(privacy::export_card))
+        .route("/cards/{card_id}/erase", post(privacy::erase_card))
+        .route("/cards/{card_id}/tokens", post(tokens::create_token))
+        .route("/cards/{card_id}/report-lost", post(lost::report_card_lost))
+        .route("/cards/{card_id}/replace", post(register::replace_card))
+        .route(
+            "/cards/{card_id}/freeze",
+            post(handlers::freeze::freeze_card).delete(handlers::freeze::unfreeze_card),
+        )
+        .route("/cards/{card_id}/clone-flag", delete(handlers::clones::clear_clone_flag))
+        .route("/cards/{card_id}/topups", post(topup::create_topup))
+        .route("/topups/{payment_hash}", get(topup::get_topup))
+        .route("/payments/{k1}/memo", put(payments::set_memo))
+        .route("/webhooks/events", get(webhooks::list_events))
+        .route("/webhooks/events/{event_id}/redeliver", post(webhooks::redeliver));
+    if let Some(chaos) = &chaos {
+        let faults = Router::new()
+            .route(
+                "/chaos",
+                get(handlers::chaos::get_faults).put(handlers::chaos::set_faults).delete(handlers::chaos::clear_faults),
+            )
+            .with_state(chaos.clone());
+        operator_api = operator_api.merge(faults);
+    }
+    let operator_api = operator_api
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::require_operator))
+        .route_layer(middleware::from_fn(tenant::server_domain_only));
+    // Feeds take their own token, as readers can't always send headers
+    let feeds = Router::new()
+        .route("/feed", get(handlers::feed::all_cards))
+        .route("/cards/{card_id}/feed", get(handlers::feed::card))
+        .route_layer(middleware::from_fn(tenant::server_domain_only));
+    let dashboard = Router::new()
+        .route("/dashboard", get(dashboard::index))
+        .route("/dashboard/login", get(dashboard::login_page).post(dashboard::login))
+        .route("/dashboard/logout", post(dashboard::logout))
+        .route("/dashboard/stats", get(dashboard::stats_page))
+        .route("/dashboard/cards/bulk", post(dashboard::bulk_update))
+        .route("/dashboard/cards/new", get(dashboard::new_card_page).post(dashboard::create_card))
+        .route("/dashboard/cards/{card_id}/wizard", get(dashboard::card_wizard))
+        .route("/dashboard/oidc/login", get(dashboard::oidc_login))
+        .route("/dashboard/oidc/callback", get(dashboard::oidc_callback))
+        .route_layer(middleware::from_fn(tenant::server_domain_only));
+
+    // LNURLw endpoints, recorded for replay if asked to
+    let mut lnurl = Router::new()
         .route("/ln", get(lnurlw::lnurlw_request))
-        .route("/ln/callback", get(lnurlw::lnurlw_callback))
-        // Card registration endpoints
-        .route("/new", get(register::get_card_registration))
-        .route("/api/createboltcard", post(register::create_card))
+        .route("/ln/callback", get(lnurlw::lnurlw_callback));
+    if let Some(path) = &config.record_requests {
+        let recorder = Arc::new(capture::Recorder::open(path).await?);
+        lnurl = lnurl.route_layer(middleware::from_fn_with_state(recorder, capture::record));
+    }
+
+    // API for operators, cardholders and integrations, versioned by path
+    let api = Router::new()
+        .route("/receipts/keys", get(receipt::list_keys))
+        .route("/receipts/verify", post(receipt::verify_receipt))
+        .route("/payments/{k1}", get(payments::get_payment))
+        // Cardholder accounts
+        .route("/users", post(users::register))
+        .route("/users/login", post(users::login))
+        .route("/users/logout", post(users::logout))
+        .route("/me", get(users::me).delete(users::erase_account))
+        .route("/me/export", get(users::export_data))
+        .route("/me/cards", get(users::list_cards).post(users::create_card))
+        .route("/me/cards/{card_id}", get(users::get_card))
+        .route("/me/cards/{card_id}/actions", post(users::card_action))
+        .route("/me/cards/{card_id}/payments", get(users::list_payments))
+        .route("/me/cards/{card_id}/nostr", put(users::set_nostr_pubkey))
+        .route("/me/cards/{card_id}/topups", post(users::create_topup))
+        .route("/me/cards/{card_id}/topups/{payment_hash}", get(users::get_topup))
+        .route("/me/keys", get(users::list_api_keys).post(users::create_api_key))
+        .route("/me/keys/{key_id}", delete(users::revoke_api_key))
+        .route("/me/notifications", get(users::list_channels).post(users::create_channel))
+        .route("/me/notifications/{channel_id}", delete(users::delete_channel))
+        // Cards bound to wallets
+        .route("/wallet/login", post(wallet::start_login))
+        .route("/wallet/login/{k1}", post(wallet::finish_login))
+        .route("/wallet/logout", post(wallet::logout))
+        .route("/wallet/cards", get(wallet::list_cards))
+        .route("/wallet/cards/claim", post(wallet::claim_card))
+        .route("/wallet/cards/{card_id}", get(wallet::get_card))
+        .route("/wallet/cards/{card_id}/actions", post(wallet::card_action))
+        .route("/wallet/cards/{card_id}/payments", get(wallet::list_payments))
+        .merge(operator_api)
+        .merge(feeds);
+
+    // Card registration, read by the programming apps
+    let registration = Router::new().route("/new", get(register::get_card_registration));
+
+    // Build router
+    let mut app = Router::new()
+        .merge(lnurl)
+        .route("/versions", get(versioning::list_versions))
+        .nest(
+            "/v1",
+            api.clone().merge(registration.clone()).layer(middleware::from_fn(versioning::current)),
+        )
+        // Paths from before versioning, kept as deprecated aliases of v1
+        .nest("/api", api.layer(middleware::from_fn_with_state(state.clone(), versioning::legacy)))
+        .merge(registration.layer(middleware::from_fn_with_state(state.clone(), versioning::legacy)))
+        .route("/receipt/{k1}", get(receipt::get_receipt))
+        .route("/receipt/{k1}/signed", get(receipt::get_signed_receipt))
+        // Wallet login (LNURL-auth)
+        .route("/wallet/auth", get(wallet::auth_callback))
+        // Cardholder self-service
+        .route("/lost/{token}", get(lost::report_page).post(lost::report_lost))
+        .route("/widget/{token}", get(widget::widget_page))
+        .route("/widget/{token}/status", get(widget::widget_status))
+        .route("/topup/{token}", get(topup::topup_page).post(topup::create_topup_from_page))
+        .route("/topup/{token}/{payment_hash}", get(topup::topup_invoice_page))
+        .merge(dashboard);
+
+    // Operator assets (logo, theme.css) for white-labeling
+    if let Some(static_dir) = &config.static_dir {
+        app = app.nest_service("/static", ServeDir::new(static_dir));
+    }
+
+    // Faults apply within the request's route, so they're layered on every route
+    if let Some(chaos) = chaos {
+        app = app.layer(middleware::from_fn_with_state(chaos, chaos::inject));
+    }
+
+    let app = app
         // Add middleware
         .layer(
             ServiceBuilder::new()
                 .layer(TraceLayer::new_for_http())
+                .layer(middleware::from_fn_with_state(state.clone(), tenant::resolve))
         )
         // Add shared state
         .with_state(state);
@@ -70,9 +366,22 @@ async fn main() -> anyhow::Result<()> {
 
     tracing::info!("Server running on {}", config.socket_addr());
     tracing::info!("Domain: {}", config.domain);
-    tracing::info!("LNURLw base: {}", config.lnurlw_base());
+    tracing::info!("LNURLw base: {}", config.urls().lnurlw_base());
 
     axum::serve(listener, app).await?;
 
     Ok(())
 }
+
+/// Load the sample data for `--dev` and log how to tap the test vector card
+async fn seed(pool: &sqlx::SqlitePool, config: &Config, random: &random::Random) -> anyhow::Result<()> {
+    let Some(seeded) = db::seed::seed(pool, random).await? else {
+        tracing::info!("Database already has the sample data");
+        return Ok(());
+    };
+    tracing::info!("Loaded {} sample cards and {} payments", seeded.cards.len(), seeded.payments);
+    for url in db::seed::tap_urls(&config.urls().url(""), seeded.test_vector_card().card_id) {
+        tracing::info!("Tap the test vector card: {}", url);
+    }
+    Ok(())
+}
@@ -0,0 +1,158 @@
+//! Notifications for cardholders, on channels they set up themselves
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, db::models::UserNotificationChannel};
+
+use super::{
+    Notification,
+    email::{self, EmailSender},
+    nostr::{self, NostrSender, Receipt},
+    telegram::{self, TelegramBot},
+};
+
+/// Events cardholders can subscribe to, discrepancies are left to the operator
+pub const USER_EVENTS: &[&str] = &[
+    "card_reported_lost",
+    "counter_near_limit",
+    "counter_exhausted",
+    "spend_near_limit",
+    "payment_settled",
+    "payment_failed",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelKind {
+    Email,
+    Telegram,
+    Nostr,
+}
+
+impl ChannelKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChannelKind::Email => "email",
+            ChannelKind::Telegram => "telegram",
+            ChannelKind::Nostr => "nostr",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "email" => Some(ChannelKind::Email),
+            "telegram" => Some(ChannelKind::Telegram),
+            "nostr" => Some(ChannelKind::Nostr),
+            _ => None,
+        }
+    }
+
+    /// The target in the form it's stored in, `None` if it isn't one for this kind
+    ///
+    /// Email addresses and Telegram chat IDs are kept as given, Nostr keys as hex.
+    pub fn normalize_target(&self, target: &str) -> Option<String> {
+        let target = target.trim();
+        match self {
+            ChannelKind::Email => email::is_valid_address(target).then(|| target.to_string()),
+            ChannelKind::Telegram => telegram::is_valid_chat_id(target).then(|| target.to_string()),
+            ChannelKind::Nostr => nostr::parse_public_key(target),
+        }
+    }
+}
+
+/// Parse a stored event list, `None` standing for all events
+pub fn parse_events(events: Option<&str>) -> Option<Vec<String>> {
+    events.map(|events| events.split(',').map(str::to_string).collect())
+}
+
+/// Senders for each kind of channel the server is configured for
+pub struct Channels {
+    email: Option<EmailSender>,
+    telegram: Option<TelegramBot>,
+    nostr: Option<NostrSender>,
+    /// Event kind of Nostr receipts, if they are published
+    receipt_kind: Option<u16>,
+}
+
+impl Channels {
+    pub fn from_config(config: &Config, http: reqwest::Client) -> Result<Self> {
+        let email = match (&config.smtp_url, &config.smtp_from) {
+            (Some(url), Some(from)) => {
+                let hostname = reqwest::Url::parse(&format!("https://{}", config.domain))?
+                    .host_str()
+                    .unwrap_or("localhost")
+                    .to_string();
+                Some(EmailSender::new(url, from, &hostname)?)
+            }
+            (Some(_), None) => anyhow::bail!("--smtp-url needs --smtp-from"),
+            _ => None,
+        };
+        let telegram = config
+            .telegram_bot_token
+            .as_ref()
+            .map(|token| TelegramBot::new(http, token.clone()));
+        let nostr = config
+            .nostr_secret_key
+            .as_ref()
+            .map(|key| NostrSender::new(key, &config.nostr_relays))
+            .transpose()?;
+        if config.nostr_receipts && nostr.is_none() {
+            anyhow::bail!("--nostr-receipts needs --nostr-secret-key");
+        }
+        let receipt_kind = config.nostr_receipts.then_some(config.nostr_receipt_kind);
+
+        Ok(Self { email, telegram, nostr, receipt_kind })
+    }
+
+    /// Whether channels of `kind` can be delivered to
+    pub fn supports(&self, kind: ChannelKind) -> bool {
+        match kind {
+            ChannelKind::Email => self.email.is_some(),
+            ChannelKind::Telegram => self.telegram.is_some(),
+            ChannelKind::Nostr => self.nostr.is_some(),
+        }
+    }
+
+    /// Whether receipts of settled withdrawals are published to Nostr
+    pub fn publishes_receipts(&self) -> bool {
+        self.receipt_kind.is_some()
+    }
+
+    pub async fn publish_receipt(&self, receipt: &Receipt) -> Result<()> {
+        match (&self.nostr, self.receipt_kind) {
+            (Some(nostr), Some(kind)) => nostr.send_receipt(kind, receipt).await,
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn deliver(&self, channel: &UserNotificationChannel, notification: &Notification) -> Result<()> {
+        let kind = ChannelKind::parse(&channel.kind);
+        let text = notification.event.describe();
+        let target = channel.target.as_str();
+
+        match kind {
+            Some(ChannelKind::Email) => {
+                if let Some(email) = &self.email {
+                    let body = format!("{}\n\n{}", text, notification.timestamp);
+                    return email.send(target, &text, &body).await;
+                }
+            }
+            Some(ChannelKind::Telegram) => {
+                if let Some(telegram) = &self.telegram {
+                    return telegram.send(target, &text).await;
+                }
+            }
+            Some(ChannelKind::Nostr) => {
+                if let Some(nostr) = &self.nostr {
+                    return nostr.send(target, &text).await;
+                }
+            }
+            None => {}
+        }
+
+        // Retrying won't help once the operator took the transport away
+        tracing::warn!("Dropping notification for channel {}: {} isn't configured", channel.channel_id, channel.kind);
+        Ok(())
+    }
+}
@@ -0,0 +1,352 @@
+pub mod channels;
+mod email;
+pub mod nostr;
+mod telegram;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use std::time::Duration;
+
+use crate::{
+    app_state::AppState,
+    db::{channels as channel_db, jobs as job_db, models::PaymentStatus, nostr as nostr_db, queries, webhooks},
+    jobs::{Job, Permanent},
+};
+use nostr::Receipt;
+
+/// Time the webhook has to answer before the delivery is retried
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Events the operator is notified about
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// A card was reported lost by its holder or the operator and disabled
+    CardReportedLost {
+        card_id: i64,
+        card_name: String,
+        note: Option<String>,
+        /// The card issued in its place at the holder's request, to be programmed and handed over
+        #[serde(default)]
+        replacement_id: Option<i64>,
+    },
+    /// A tap's UID belongs to more than one card, all of which were flagged and refuse payments until reviewed
+    CardCloneSuspected {
+        card_id: i64,
+        card_name: String,
+        /// The other cards with the same UID
+        other_card_ids: Vec<i64>,
+    },
+    /// A card's counter is close to its 24-bit maximum
+    CounterNearLimit {
+        card_id: i64,
+        card_name: String,
+        counter: u32,
+        /// Taps the card has left
+        remaining: u32,
+    },
+    /// A card made its last possible tap and has to be replaced
+    CounterExhausted {
+        card_id: i64,
+        card_name: String,
+    },
+    /// A settled withdrawal took a card's spend over the warning share of its daily limit
+    SpendNearLimit {
+        card_id: i64,
+        card_name: String,
+        /// Spend today (UTC), payments in flight included
+        spent_msats: i64,
+        day_limit_msats: i64,
+        /// The `--spend-warning-percent` crossed
+        percent: u8,
+    },
+    /// A withdrawal paid in the background settled
+    PaymentSettled {
+        payment_id: i64,
+        card_id: i64,
+        k1: String,
+        amount_msats: i64,
+    },
+    /// A withdrawal paid in the background failed and its reservation was released
+    PaymentFailed {
+        payment_id: i64,
+        card_id: i64,
+        k1: String,
+        amount_msats: i64,
+        reason: String,
+    },
+    /// The Lightning node disagrees with the recorded outcome of a payment
+    PaymentDiscrepancy {
+        payment_id: i64,
+        card_id: i64,
+        k1: String,
+        /// `settled_not_on_node` or `paid_on_node_not_recorded`
+        kind: String,
+        node_status: String,
+    },
+}
+
+impl Event {
+    /// Name of the event as it appears in the `event` field
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::CardReportedLost { .. } => "card_reported_lost",
+            Event::CardCloneSuspected { .. } => "card_clone_suspected",
+            Event::CounterNearLimit { .. } => "counter_near_limit",
+            Event::CounterExhausted { .. } => "counter_exhausted",
+            Event::SpendNearLimit { .. } => "spend_near_limit",
+            Event::PaymentSettled { .. } => "payment_settled",
+            Event::PaymentFailed { .. } => "payment_failed",
+            Event::PaymentDiscrepancy { .. } => "payment_discrepancy",
+        }
+    }
+
+    pub fn card_id(&self) -> i64 {
+        match self {
+            Event::CardReportedLost { card_id, .. }
+            | Event::CardCloneSuspected { card_id, .. }
+            | Event::CounterNearLimit { card_id, .. }
+            | Event::CounterExhausted { card_id, .. }
+            | Event::SpendNearLimit { card_id, .. }
+            | Event::PaymentSettled { card_id, .. }
+            | Event::PaymentFailed { card_id, .. }
+            | Event::PaymentDiscrepancy { card_id, .. } => *card_id,
+        }
+    }
+
+    /// One line for people to read, used by the cardholder channels
+    pub fn describe(&self) -> String {
+        match self {
+            Event::CardReportedLost { card_name, replacement_id: None, .. } => {
+                format!("Card {} was reported lost and has been disabled", card_name)
+            }
+            Event::CardReportedLost { card_name, replacement_id: Some(replacement_id), .. } => {
+                format!("Card {} was reported lost and has been disabled, card #{} replaces it", card_name, replacement_id)
+            }
+            Event::CardCloneSuspected { card_name, other_card_ids, .. } => {
+                let others: Vec<String> = other_card_ids.iter().map(|card_id| format!("#{}", card_id)).collect();
+                format!("Card {} shares its UID with card {} and is blocked until reviewed", card_name, others.join(", "))
+            }
+            Event::CounterNearLimit { card_name, remaining, .. } => {
+                format!("Card {} has {} taps left, ask for a replacement", card_name, remaining)
+            }
+            Event::CounterExhausted { card_name, .. } => {
+                format!("Card {} made its last tap and has to be replaced", card_name)
+            }
+            Event::SpendNearLimit { card_name, spent_msats, day_limit_msats, .. } => {
+                format!(
+                    "Card {} spent {} of its {} sats for today, further payments may be declined",
+                    card_name,
+                    spent_msats / 1000,
+                    day_limit_msats / 1000
+                )
+            }
+            Event::PaymentSettled { card_id, amount_msats, .. } => {
+                format!("Card #{} paid {} sats", card_id, amount_msats / 1000)
+            }
+            Event::PaymentFailed { card_id, amount_msats, reason, .. } => {
+                format!("Payment of {} sats with card #{} failed: {}", amount_msats / 1000, card_id, reason)
+            }
+            Event::PaymentDiscrepancy { payment_id, card_id, kind, .. } => {
+                format!("Payment {} of card #{} needs review: {}", payment_id, card_id, kind)
+            }
+        }
+    }
+}
+
+/// Envelope sent to notification targets
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    #[serde(flatten)]
+    pub event: Event,
+    pub timestamp: String,
+    /// ID of the stored event, the same on every delivery so receivers can drop duplicates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_id: Option<i64>,
+}
+
+impl Notification {
+    pub fn new(event: Event) -> Self {
+        Self {
+            event,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            event_id: None,
+        }
+    }
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// Deliver a notification to the operator
+    async fn notify(&self, notification: &Notification) -> Result<()>;
+}
+
+/// Notifier that only writes to the log, used when no target is configured
+pub struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<()> {
+        tracing::warn!("Operator notification: {}", serde_json::to_string(notification)?);
+        Ok(())
+    }
+}
+
+/// Notifier that POSTs the notification as JSON to an operator webhook
+///
+/// Timeouts, connection errors, 5xx, 408 and 429 answers are retried; other
+/// 4xx answers won't change on a retry and mark the delivery dead.
+pub struct WebhookNotifier {
+    http: reqwest::Client,
+    url: String,
+}
+
+impl WebhookNotifier {
+    pub fn new(http: reqwest::Client, url: String) -> Self {
+        Self { http, url }
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<()> {
+        LogNotifier.notify(notification).await?;
+
+        let response = self
+            .http
+            .post(&self.url)
+            .timeout(WEBHOOK_TIMEOUT)
+            .json(notification)
+            .send()
+            .await
+            .map_err(reqwest::Error::without_url)?;
+
+        let status = response.status();
+        if status.is_client_error() && !matches!(status, StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS) {
+            return Err(Permanent(format!("Webhook rejected the notification with {}", status)).into());
+        }
+        response.error_for_status().map_err(reqwest::Error::without_url)?;
+
+        Ok(())
+    }
+}
+
+/// Queue a notification for the operator and the card owner's subscribed channels
+///
+/// Failures are logged instead of propagated. Delivery happens in background
+/// jobs, one per target, each retried until its target accepts it.
+pub async fn send(state: &AppState, event: Event) {
+    let subscribed = if channels::USER_EVENTS.contains(&event.name()) {
+        channel_db::list_subscribed_channels(&state.pool, event.card_id(), event.name()).await
+    } else {
+        Ok(Vec::new())
+    };
+    let notification = Notification::new(event);
+
+    // Stored first, so the event can be delivered again whatever happens to the job
+    let mut operator_notification = notification.clone();
+    match record_event(&state.pool, &notification).await {
+        Ok(event_id) => operator_notification.event_id = Some(event_id),
+        Err(e) => tracing::error!("Failed to store operator notification: {}", e),
+    }
+    let job = Job::Notify { notification: operator_notification };
+    if let Err(e) = state.jobs.enqueue(&job).await {
+        tracing::error!("Failed to queue operator notification: {}", e);
+    }
+
+    let channels = match subscribed {
+        Ok(channels) => channels,
+        Err(e) => {
+            tracing::error!("Failed to look up notification channels: {}", e);
+            return;
+        }
+    };
+    for channel in channels {
+        let job = Job::NotifyUser { channel_id: channel.channel_id, notification: notification.clone() };
+        if let Err(e) = state.jobs.enqueue(&job).await {
+            tracing::error!("Failed to queue notification for channel {}: {}", channel.channel_id, e);
+        }
+    }
+}
+
+/// Queue the Nostr receipt of a settled payment, if receipts are enabled and the card is linked to a key
+pub async fn queue_receipt(state: &AppState, card_id: i64, payment_id: i64) {
+    if !state.channels.publishes_receipts() {
+        return;
+    }
+
+    match nostr_db::get_card_nostr_pubkey(&state.pool, card_id).await {
+        Ok(Some(_)) => {
+            if let Err(e) = state.jobs.enqueue(&Job::PublishReceipt { payment_id }).await {
+                tracing::error!("Failed to queue receipt of payment {}: {}", payment_id, e);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => tracing::error!("Failed to look up Nostr key of card {}: {}", card_id, e),
+    }
+}
+
+/// Publish the Nostr receipt of a settled payment, skipped if its card was unlinked since
+pub async fn publish_receipt(state: &AppState, payment_id: i64) -> Result<()> {
+    let Some(payment) = queries::get_payment_by_id(&state.pool, payment_id).await? else {
+        return Ok(());
+    };
+    if payment.status != PaymentStatus::Settled {
+        return Ok(());
+    }
+    let Some(sender) = nostr_db::get_card_nostr_pubkey(&state.pool, payment.card_id).await? else {
+        return Ok(());
+    };
+
+    let paid_at = payment
+        .payment_time
+        .and_then(|time| chrono::NaiveDateTime::parse_from_str(&time, "%Y-%m-%d %H:%M:%S").ok())
+        .map(|time| time.and_utc().timestamp());
+    let receipt = Receipt {
+        sender,
+        invoice: payment.invoice,
+        preimage: payment.preimage,
+        amount_msats: payment.amount_msats.unwrap_or_default(),
+        paid_at,
+    };
+
+    state.channels.publish_receipt(&receipt).await
+}
+
+async fn record_event(pool: &Pool<Sqlite>, notification: &Notification) -> Result<i64> {
+    webhooks::record_event(pool, notification.event.name(), &serde_json::to_string(notification)?).await
+}
+
+/// Outcome of a redelivery request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redelivery {
+    Queued,
+    NotFound,
+    /// The event is still being delivered
+    Pending,
+}
+
+/// Queue a delivered or dead operator notification for delivery again
+///
+/// The event keeps its ID, a fresh job retries it as often as a new one.
+pub async fn redeliver(pool: &Pool<Sqlite>, event_id: i64) -> Result<Redelivery> {
+    let mut tx = pool.begin().await?;
+
+    let Some(event) = webhooks::get_event(&mut tx, event_id).await? else {
+        return Ok(Redelivery::NotFound);
+    };
+    if !webhooks::reset(&mut tx, event_id).await? {
+        return Ok(Redelivery::Pending);
+    }
+    let mut notification: Notification = serde_json::from_str(&event.payload)?;
+    notification.event_id = Some(event_id);
+    let job = Job::Notify { notification };
+    job_db::enqueue(&mut tx, job.kind(), &serde_json::to_string(&job)?).await?;
+
+    tx.commit().await?;
+
+    Ok(Redelivery::Queued)
+}
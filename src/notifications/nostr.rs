@@ -0,0 +1,300 @@
+//! Encrypted direct messages and payment receipts over Nostr
+//!
+//! Notifications go out as NIP-04 direct messages (kind 4) signed with the
+//! server's key and published to each configured relay over a WebSocket.
+//! Receipts for settled withdrawals are public events in the shape of NIP-57
+//! zap receipts, with the card's key as the sender.
+
+use aes::Aes256;
+use anyhow::{Result, anyhow, bail};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use cipher::{BlockEncryptMut, KeyIvInit, block_padding::Pkcs7};
+use futures_util::{SinkExt, StreamExt};
+use reqwest::Url;
+use secp256k1::{Keypair, Message, Parity, PublicKey, Secp256k1, XOnlyPublicKey, ecdh};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::{Message as WsMessage, protocol::WebSocketConfig};
+
+/// How long a relay gets to accept an event
+const RELAY_TIMEOUT: Duration = Duration::from_secs(15);
+/// Largest message read from a relay
+const MAX_MESSAGE_LEN: usize = 1 << 20;
+
+pub struct NostrSender {
+    keypair: Keypair,
+    relays: Vec<Url>,
+}
+
+impl NostrSender {
+    /// `secret_key` in hex or as `nsec`, `relays` as `wss://` URLs
+    pub fn new(secret_key: &str, relays: &[String]) -> Result<Self> {
+        let secret = parse_key(secret_key, "nsec").ok_or_else(|| anyhow!("Invalid Nostr secret key"))?;
+        let keypair = Keypair::from_seckey_slice(&Secp256k1::new(), &secret)?;
+
+        let relays = relays
+            .iter()
+            .map(|relay| match Url::parse(relay) {
+                Ok(url) if matches!(url.scheme(), "ws" | "wss") && url.host_str().is_some() => Ok(url),
+                _ => Err(anyhow!("Invalid Nostr relay URL {}", relay)),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if relays.is_empty() {
+            bail!("No Nostr relays configured");
+        }
+
+        Ok(Self { keypair, relays })
+    }
+
+    /// Send `text` to `recipient`, succeeding once a relay accepted it
+    pub async fn send(&self, recipient: &str, text: &str) -> Result<()> {
+        let event = direct_message(&self.keypair, recipient, text, chrono::Utc::now().timestamp())?;
+        self.publish(&event).await
+    }
+
+    /// Publish a receipt as an event of `kind`, succeeding once a relay accepted it
+    pub async fn send_receipt(&self, kind: u16, receipt: &Receipt) -> Result<()> {
+        let event = receipt_event(&self.keypair, kind, receipt, chrono::Utc::now().timestamp());
+        self.publish(&event).await
+    }
+
+    async fn publish(&self, event: &Value) -> Result<()> {
+        let id = event["id"].as_str().unwrap_or_default().to_string();
+        let message = json!(["EVENT", event]).to_string();
+
+        let mut errors = Vec::new();
+        for relay in &self.relays {
+            match tokio::time::timeout(RELAY_TIMEOUT, publish(relay, &message, &id)).await {
+                Ok(Ok(())) => return Ok(()),
+                Ok(Err(e)) => errors.push(format!("{}: {}", relay, e)),
+                Err(_) => errors.push(format!("{}: timed out", relay)),
+            }
+        }
+
+        bail!("No relay accepted the event ({})", errors.join("; "))
+    }
+}
+
+/// A settled withdrawal as published in a receipt
+#[derive(Debug, Clone)]
+pub struct Receipt {
+    /// Hex public key linked to the card that paid
+    pub sender: String,
+    pub invoice: Option<String>,
+    pub preimage: Option<String>,
+    pub amount_msats: i64,
+    /// When the payment settled, as a Unix timestamp
+    pub paid_at: Option<i64>,
+}
+
+/// A public key in hex or as `npub`, as lowercase hex
+pub fn parse_public_key(key: &str) -> Option<String> {
+    let key = parse_key(key, "npub")?;
+    XOnlyPublicKey::from_slice(&key).ok()?;
+    Some(hex::encode(key))
+}
+
+/// 32 bytes given in hex or as bech32 with the human-readable part `hrp`
+fn parse_key(key: &str, hrp: &str) -> Option<[u8; 32]> {
+    let bytes = if key.starts_with(hrp) {
+        let (parsed_hrp, bytes) = bech32::decode(key).ok()?;
+        if parsed_hrp.as_str() != hrp {
+            return None;
+        }
+        bytes
+    } else {
+        hex::decode(key).ok()?
+    };
+
+    bytes.try_into().ok()
+}
+
+/// A signed kind 4 event carrying `text` encrypted to `recipient`
+fn direct_message(keypair: &Keypair, recipient: &str, text: &str, created_at: i64) -> Result<Value> {
+    let recipient_key = XOnlyPublicKey::from_slice(&hex::decode(recipient)?)?;
+    let content = encrypt(keypair, &recipient_key, text, rand::random());
+
+    Ok(sign_event(keypair, 4, json!([["p", recipient]]), &content, created_at))
+}
+
+/// A signed receipt of `kind`, tagged like a NIP-57 zap receipt
+///
+/// The card pays, so its key goes into the sender's `P` tag. There is no zap
+/// request to put into a `description` tag.
+fn receipt_event(keypair: &Keypair, kind: u16, receipt: &Receipt, created_at: i64) -> Value {
+    let mut tags = vec![json!(["P", receipt.sender]), json!(["amount", receipt.amount_msats.to_string()])];
+    if let Some(invoice) = &receipt.invoice {
+        tags.push(json!(["bolt11", invoice]));
+    }
+    if let Some(preimage) = &receipt.preimage {
+        tags.push(json!(["preimage", preimage]));
+    }
+
+    sign_event(keypair, kind, Value::Array(tags), "", receipt.paid_at.unwrap_or(created_at))
+}
+
+/// NIP-01: the ID is the hash of the serialized fields, signed with Schnorr
+fn sign_event(keypair: &Keypair, kind: u16, tags: Value, content: &str, created_at: i64) -> Value {
+    let pubkey = hex::encode(keypair.x_only_public_key().0.serialize());
+    let id: [u8; 32] = Sha256::digest(json!([0, pubkey, created_at, kind, tags, content]).to_string()).into();
+    let sig = Secp256k1::new().sign_schnorr_no_aux_rand(&Message::from_digest(id), keypair);
+
+    json!({
+        "id": hex::encode(id),
+        "pubkey": pubkey,
+        "created_at": created_at,
+        "kind": kind,
+        "tags": tags,
+        "content": content,
+        "sig": hex::encode(sig.as_ref()),
+    })
+}
+
+/// NIP-04: AES-256-CBC with the x coordinate of the ECDH point as key
+fn encrypt(keypair: &Keypair, recipient: &XOnlyPublicKey, text: &str, iv: [u8; 16]) -> String {
+    let data = cbc::Encryptor::<Aes256>::new(&shared_key(keypair, recipient).into(), &iv.into())
+        .encrypt_padded_vec_mut::<Pkcs7>(text.as_bytes());
+
+    format!("{}?iv={}", STANDARD.encode(data), STANDARD.encode(iv))
+}
+
+fn shared_key(keypair: &Keypair, other: &XOnlyPublicKey) -> [u8; 32] {
+    let point = ecdh::shared_secret_point(
+        &PublicKey::from_x_only_public_key(*other, Parity::Even),
+        &keypair.secret_key(),
+    );
+    point[..32].try_into().expect("point holds two coordinates")
+}
+
+/// Send an `EVENT` message to a relay and wait for its `OK`
+async fn publish(relay: &Url, message: &str, event_id: &str) -> Result<()> {
+    let config = WebSocketConfig::default()
+        .max_message_size(Some(MAX_MESSAGE_LEN))
+        .max_frame_size(Some(MAX_MESSAGE_LEN));
+    let (mut socket, _) = tokio_tungstenite::connect_async_with_config(relay.as_str(), Some(config), false).await?;
+
+    socket.send(WsMessage::text(message)).await?;
+
+    while let Some(reply) = socket.next().await {
+        let WsMessage::Text(reply) = reply? else {
+            continue;
+        };
+        let reply: Value = serde_json::from_str(&reply)?;
+        if reply[0] == "OK" && reply[1] == event_id {
+            let _ = socket.close(None).await;
+            if reply[2] == true {
+                return Ok(());
+            }
+            bail!("Relay rejected the message: {}", reply[3].as_str().unwrap_or_default());
+        }
+    }
+
+    bail!("Relay closed the connection")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cipher::{BlockDecryptMut, block_padding::Pkcs7};
+    use tokio::net::TcpListener;
+
+    fn decrypt(keypair: &Keypair, sender: &XOnlyPublicKey, content: &str) -> String {
+        let (data, iv) = content.split_once("?iv=").unwrap();
+        let (data, iv) = (STANDARD.decode(data).unwrap(), STANDARD.decode(iv).unwrap());
+        let iv: [u8; 16] = iv.try_into().unwrap();
+        let data = cbc::Decryptor::<Aes256>::new(&shared_key(keypair, sender).into(), &iv.into())
+            .decrypt_padded_vec_mut::<Pkcs7>(&data)
+            .unwrap();
+
+        String::from_utf8(data).unwrap()
+    }
+
+    /// A relay on localhost answering the first event with `accepted`
+    async fn relay(accepted: bool) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            let WsMessage::Text(message) = socket.next().await.unwrap().unwrap() else {
+                panic!("expected a text message");
+            };
+            let message: Value = serde_json::from_str(&message).unwrap();
+            assert_eq!(message[0], "EVENT");
+            socket.send(WsMessage::text(json!(["NOTICE", "hello"]).to_string())).await.unwrap();
+            let reply = json!(["OK", message[1]["id"], accepted, if accepted { "" } else { "blocked: spam" }]);
+            socket.send(WsMessage::text(reply.to_string())).await.unwrap();
+            let _ = socket.next().await;
+        });
+
+        url
+    }
+
+    #[test]
+    fn test_direct_message() {
+        let secp = Secp256k1::new();
+        let server = Keypair::from_seckey_slice(&secp, &[1; 32]).unwrap();
+        let user = Keypair::from_seckey_slice(&secp, &[2; 32]).unwrap();
+        let recipient = hex::encode(user.x_only_public_key().0.serialize());
+
+        let event = direct_message(&server, &recipient, "Card Alice was reported lost", 1_700_000_000).unwrap();
+
+        let id = hex::decode(event["id"].as_str().unwrap()).unwrap();
+        let sig = secp256k1::schnorr::Signature::from_slice(&hex::decode(event["sig"].as_str().unwrap()).unwrap()).unwrap();
+        let message = Message::from_digest(id.try_into().unwrap());
+        assert!(secp.verify_schnorr(&sig, &message, &server.x_only_public_key().0).is_ok());
+        assert_eq!(event["tags"], json!([["p", recipient]]));
+
+        let content = event["content"].as_str().unwrap();
+        assert_eq!(decrypt(&user, &server.x_only_public_key().0, content), "Card Alice was reported lost");
+    }
+
+    #[test]
+    fn test_receipt_event() {
+        let secp = Secp256k1::new();
+        let server = Keypair::from_seckey_slice(&secp, &[1; 32]).unwrap();
+        let receipt = Receipt {
+            sender: "ab".repeat(32),
+            invoice: Some("lnbc10n1".to_string()),
+            preimage: None,
+            amount_msats: 1000,
+            paid_at: Some(1_700_000_000),
+        };
+
+        let event = receipt_event(&server, 9735, &receipt, 1_800_000_000);
+
+        assert_eq!(event["kind"], 9735);
+        assert_eq!(event["created_at"], 1_700_000_000);
+        assert_eq!(event["tags"], json!([["P", "ab".repeat(32)], ["amount", "1000"], ["bolt11", "lnbc10n1"]]));
+        let id: [u8; 32] = Sha256::digest(
+            json!([0, event["pubkey"], event["created_at"], event["kind"], event["tags"], event["content"]]).to_string(),
+        )
+        .into();
+        assert_eq!(event["id"], hex::encode(id));
+    }
+
+    #[test]
+    fn test_parse_public_key() {
+        let hex_key = "3bf0c63fcb93463407af97a5e5ee64fa883d107ef9e558472c4eb9aaaefa459d";
+        let npub = "npub180cvv07tjdrrgpa0j7j7tmnyl2yr6yr7l8j4s3evf6u64th6gkwsyjh6w6";
+
+        assert_eq!(parse_public_key(hex_key).as_deref(), Some(hex_key));
+        assert_eq!(parse_public_key(npub).as_deref(), Some(hex_key));
+        assert_eq!(parse_public_key("nsec1vl029mgpspedva04g90vltkh6fvh240zqtv9k0t9af8935ke9laqsnlfe5"), None);
+        assert_eq!(parse_public_key("abcd"), None);
+    }
+
+    #[tokio::test]
+    async fn test_publish() {
+        let recipient = "3bf0c63fcb93463407af97a5e5ee64fa883d107ef9e558472c4eb9aaaefa459d";
+
+        let sender = NostrSender::new(&"01".repeat(32), &[relay(true).await]).unwrap();
+        sender.send(recipient, "Card Alice was reported lost").await.unwrap();
+
+        let sender = NostrSender::new(&"01".repeat(32), &[relay(false).await]).unwrap();
+        let error = sender.send(recipient, "Card Alice was reported lost").await.unwrap_err();
+        assert!(error.to_string().contains("blocked: spam"), "{}", error);
+    }
+}
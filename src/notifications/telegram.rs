@@ -0,0 +1,38 @@
+use anyhow::Result;
+
+/// Sends messages as a Telegram bot through the Bot API
+pub struct TelegramBot {
+    http: reqwest::Client,
+    token: String,
+}
+
+impl TelegramBot {
+    pub fn new(http: reqwest::Client, token: String) -> Self {
+        Self { http, token }
+    }
+
+    /// Message a chat, which has to have started a conversation with the bot
+    pub async fn send(&self, chat_id: &str, text: &str) -> Result<()> {
+        // Errors name the URL, which holds the token
+        self.http
+            .post(format!("https://api.telegram.org/bot{}/sendMessage", self.token))
+            .json(&serde_json::json!({ "chat_id": chat_id, "text": text }))
+            .send()
+            .await
+            .map_err(reqwest::Error::without_url)?
+            .error_for_status()
+            .map_err(reqwest::Error::without_url)?;
+
+        Ok(())
+    }
+}
+
+/// A numeric chat ID or the `@username` of a public channel
+pub fn is_valid_chat_id(chat_id: &str) -> bool {
+    match chat_id.strip_prefix('@') {
+        Some(username) => {
+            (5..=32).contains(&username.len()) && username.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => chat_id.parse::<i64>().is_ok(),
+    }
+}
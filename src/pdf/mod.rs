@@ -0,0 +1,186 @@
+use anyhow::{Result, anyhow};
+use qrcode::{Color, QrCode};
+
+/// A6 page size in PDF points, a common size for card inserts
+pub const PAGE_WIDTH: f32 = 297.64;
+pub const PAGE_HEIGHT: f32 = 419.53;
+
+/// Minimal PDF writer for print-ready card inserts
+///
+/// Only supports what the inserts need: Helvetica text and QR codes drawn
+/// as filled rectangles, so no font embedding or image handling is required.
+#[derive(Default)]
+pub struct PdfDocument {
+    pages: Vec<String>,
+}
+
+/// A single page being drawn; coordinates are in points from the bottom-left corner
+#[derive(Default)]
+pub struct Page {
+    content: String,
+}
+
+impl Page {
+    /// Draw a line of text at the given baseline position
+    pub fn text(&mut self, x: f32, y: f32, size: f32, text: &str) {
+        self.content.push_str(&format!(
+            "BT /F1 {:.1} Tf {:.2} {:.2} Td ({}) Tj ET\n",
+            size,
+            x,
+            y,
+            escape_text(text)
+        ));
+    }
+
+    /// Draw a line of text horizontally centered on the page
+    pub fn text_centered(&mut self, y: f32, size: f32, text: &str) {
+        // Helvetica averages roughly half an em per glyph
+        let width = text.chars().count() as f32 * size * 0.5;
+        self.text((PAGE_WIDTH - width) / 2.0, y, size, text);
+    }
+
+    /// Draw a QR code encoding `data` with its top-left corner at (x, y)
+    pub fn qr_code(&mut self, x: f32, y: f32, size: f32, data: &str) -> Result<()> {
+        let code = QrCode::new(data.as_bytes()).map_err(|e| anyhow!("QR encoding failed: {}", e))?;
+        let width = code.width();
+        let module = size / width as f32;
+
+        self.content.push_str("0 g\n");
+        for (i, color) in code.to_colors().iter().enumerate() {
+            if *color == Color::Dark {
+                let col = (i % width) as f32;
+                let row = (i / width) as f32;
+                self.content.push_str(&format!(
+                    "{:.2} {:.2} {:.2} {:.2} re f\n",
+                    x + col * module,
+                    y - (row + 1.0) * module,
+                    module,
+                    module
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl PdfDocument {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_page(&mut self, page: Page) {
+        self.pages.push(page.content);
+    }
+
+    /// Serialize the document into PDF bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // Object layout: 1 catalog, 2 page tree, 3 font, then a page and content object per page
+        let mut objects: Vec<String> = Vec::new();
+        let page_ids: Vec<usize> = (0..self.pages.len()).map(|i| 4 + i * 2).collect();
+
+        objects.push("<< /Type /Catalog /Pages 2 0 R >>".to_string());
+        objects.push(format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            page_ids.iter().map(|id| format!("{} 0 R", id)).collect::<Vec<_>>().join(" "),
+            self.pages.len()
+        ));
+        objects.push(
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_string(),
+        );
+
+        for (page_id, content) in page_ids.iter().zip(&self.pages) {
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH,
+                PAGE_HEIGHT,
+                page_id + 1
+            ));
+            objects.push(format!(
+                "<< /Length {} >>\nstream\n{}endstream",
+                content.len(),
+                content
+            ));
+        }
+
+        let mut out = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).as_bytes());
+        }
+
+        let xref_offset = out.len();
+        out.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+        for offset in offsets {
+            out.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        out.extend_from_slice(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+                objects.len() + 1,
+                xref_offset
+            )
+            .as_bytes(),
+        );
+
+        out
+    }
+}
+
+/// Escape a string for a PDF literal, replacing anything outside printable ASCII
+fn escape_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '(' | ')' | '\\' => {
+                out.push('\\');
+                out.push(ch);
+            }
+            ' '..='~' => out.push(ch),
+            _ => out.push('?'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_structure() {
+        let mut doc = PdfDocument::new();
+        let mut page = Page::default();
+        page.text(10.0, 10.0, 12.0, "Hello (world)");
+        page.qr_code(10.0, 200.0, 100.0, "https://example.com/v1/new?a=00").unwrap();
+        doc.add_page(page);
+        doc.add_page(Page::default());
+
+        let bytes = doc.to_bytes();
+        let text = String::from_utf8(bytes).unwrap();
+
+        assert!(text.starts_with("%PDF-1.4\n"));
+        assert!(text.ends_with("%%EOF\n"));
+        assert!(text.contains("/Count 2"));
+        assert!(text.contains("(Hello \\(world\\)) Tj"));
+
+        // The xref offset must point at the xref table
+        let startxref: usize = text
+            .rsplit("startxref\n")
+            .next()
+            .unwrap()
+            .lines()
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(text[startxref..].starts_with("xref\n"));
+    }
+
+    #[test]
+    fn test_escape_text() {
+        assert_eq!(escape_text("a\\b"), "a\\\\b");
+        assert_eq!(escape_text("Café"), "Caf?");
+    }
+}
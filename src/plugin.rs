@@ -0,0 +1,186 @@
+//! Policy plugin for custom risk checks
+//!
+//! With `--policy-plugin` the server runs an external program for every tap
+//! and every withdrawal. It gets the withdrawal's context as one JSON object
+//! on stdin and answers with a [`Decision`] on stdout: approve, deny with a
+//! reason shown in the wallet, or approve with a lower maximum amount. WASM
+//! modules are run the same way through a WASI runtime, e.g.
+//! `--policy-plugin wasmtime --policy-plugin-arg run --policy-plugin-arg /etc/lnurlw/policy.wasm`.
+//!
+//! A plugin can only restrict what the card's limits allow, never raise it.
+
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::{path::Path, process::Stdio, time::Duration};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::{config::Config, db::models::Card, handlers::error::ApiError};
+
+/// Version of the JSON given to plugins, raised on incompatible changes
+const CONTEXT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// The card was tapped, before the wallet is offered a withdrawal
+    Tap,
+    /// The wallet sent its invoice, before it's paid
+    Withdraw,
+}
+
+/// What the plugin is told about a tap or withdrawal
+#[derive(Debug, Clone, Serialize)]
+pub struct Context {
+    pub version: u32,
+    pub stage: Stage,
+    pub card_id: i64,
+    pub card_name: String,
+    pub org_id: Option<i64>,
+    pub tags: Vec<String>,
+    pub uid: String,
+    pub tx_limit_sats: i64,
+    pub day_limit_sats: i64,
+    pub balance_mode: bool,
+    pub balance_msats: i64,
+    pub daily_spent_msats: i64,
+    /// What the card's limits allow on taps, what the session offered on withdrawals
+    pub max_withdrawable_msats: i64,
+    /// The tap's counter, only on taps
+    pub counter: Option<i64>,
+    /// The invoice's amount and hash, only on withdrawals
+    pub amount_msats: Option<i64>,
+    pub payment_hash: Option<String>,
+}
+
+impl Context {
+    pub fn new(stage: Stage, card: &Card, org_id: Option<i64>, tags: Vec<String>, daily_spent_msats: i64) -> Self {
+        Self {
+            version: CONTEXT_VERSION,
+            stage,
+            card_id: card.card_id,
+            card_name: card.card_name.clone(),
+            org_id,
+            tags,
+            uid: card.uid.clone(),
+            tx_limit_sats: card.tx_limit_sats,
+            day_limit_sats: card.day_limit_sats,
+            balance_mode: card.balance_mode,
+            balance_msats: card.balance_msats,
+            daily_spent_msats,
+            max_withdrawable_msats: 0,
+            counter: None,
+            amount_msats: None,
+            payment_hash: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Approve,
+    Deny,
+}
+
+/// The plugin's answer
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Decision {
+    pub decision: Verdict,
+    /// Shown in the wallet when denied
+    pub reason: Option<String>,
+    /// Lowers the most the wallet may withdraw, ignored if above the card's limits
+    pub max_withdrawable_msats: Option<i64>,
+}
+
+/// Ask the configured plugin, if any
+///
+/// Returns the cap on the amount the plugin set, or the error to answer the
+/// wallet with. A plugin that fails or doesn't answer in time denies the
+/// withdrawal unless `--policy-plugin-fail-open` is set.
+pub async fn check(config: &Config, context: &Context) -> Result<Option<i64>, ApiError> {
+    let Some(program) = config.policy_plugin.as_deref() else {
+        return Ok(None);
+    };
+
+    let timeout = Duration::from_millis(config.policy_plugin_timeout_ms);
+    let decision = match ask(program, &config.policy_plugin_args, context, timeout).await {
+        Ok(decision) => decision,
+        Err(e) if config.policy_plugin_fail_open => {
+            tracing::warn!("Policy plugin failed, going ahead with card {}: {}", context.card_id, e);
+            return Ok(None);
+        }
+        Err(e) => {
+            tracing::error!("Policy plugin failed, refusing card {}: {}", context.card_id, e);
+            return Err(ApiError::PolicyDenied(None));
+        }
+    };
+
+    match decision.decision {
+        Verdict::Approve => Ok(decision.max_withdrawable_msats.map(|max| max.max(0))),
+        Verdict::Deny => {
+            tracing::info!(
+                "Policy plugin denied {:?} of card {}: {}",
+                context.stage,
+                context.card_id,
+                decision.reason.as_deref().unwrap_or("no reason given")
+            );
+            Err(ApiError::PolicyDenied(decision.reason.filter(|reason| !reason.trim().is_empty())))
+        }
+    }
+}
+
+/// Run the plugin with the context on stdin and parse its answer
+async fn ask(program: &Path, args: &[String], context: &Context, timeout: Duration) -> Result<Decision> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| anyhow!("Can't run {}: {}", program.display(), e))?;
+
+    let mut input = serde_json::to_vec(context)?;
+    input.push(b'\n');
+    let mut stdin = child.stdin.take().ok_or_else(|| anyhow!("No stdin"))?;
+    let run = async move {
+        // A plugin that decides without reading its input closes the pipe early, which is fine
+        let _ = stdin.write_all(&input).await;
+        drop(stdin);
+        child.wait_with_output().await
+    };
+    let output = tokio::time::timeout(timeout, run)
+        .await
+        .map_err(|_| anyhow!("No answer within {} ms", timeout.as_millis()))??;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        tracing::warn!("Policy plugin: {}", stderr.trim());
+    }
+    if !output.status.success() {
+        bail!("Exited with {}", output.status);
+    }
+    parse_decision(&output.stdout)
+}
+
+fn parse_decision(stdout: &[u8]) -> Result<Decision> {
+    serde_json::from_slice(stdout).map_err(|e| anyhow!("Unreadable answer {:?}: {}", String::from_utf8_lossy(stdout).trim(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_decision() {
+        let decision = parse_decision(br#"{"decision":"approve","max_withdrawable_msats":5000}"#).unwrap();
+        assert_eq!(decision.decision, Verdict::Approve);
+        assert_eq!(decision.max_withdrawable_msats, Some(5000));
+
+        let decision = parse_decision(b"{\"decision\": \"deny\", \"reason\": \"Card used abroad\"}\n").unwrap();
+        assert_eq!(decision.reason.as_deref(), Some("Card used abroad"));
+
+        assert!(parse_decision(b"").is_err());
+        assert!(parse_decision(br#"{"decision":"maybe"}"#).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+//! Per-user quotas and limits
+//!
+//! A user's [`UserPolicy`] caps how many cards they own and the limits those
+//! cards may have, and supplies the limits of new cards that don't ask for
+//! any, so operators can offer tiers. It applies to cards created with an
+//! owner and to limit changes made by the cardholder; the operator's own bulk
+//! updates aren't restricted. Users without a quota of their own get the
+//! server's `--user-max-cards`, so no account can create cards without bound.
+
+use anyhow::Result;
+use sqlx::{Pool, Sqlite};
+use std::fmt;
+
+use crate::{
+    config::Config,
+    db::{models::{CreateCardRequest, UserPolicy}, users},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyViolation {
+    /// The user owns as many cards as allowed
+    CardQuotaReached(i64),
+    TxLimitAboveMax(i64),
+    DayLimitAboveMax(i64),
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyViolation::CardQuotaReached(max) => write!(f, "User may own at most {} cards", max),
+            PolicyViolation::TxLimitAboveMax(max) => write!(f, "Transaction limit may be at most {} sats", max),
+            PolicyViolation::DayLimitAboveMax(max) => write!(f, "Daily limit may be at most {} sats", max),
+        }
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
+/// The user's policy, with the server's card quota `max_cards` if it sets none
+pub fn effective(policy: Option<UserPolicy>, max_cards: i64) -> UserPolicy {
+    let policy = policy.unwrap_or_default();
+    UserPolicy {
+        max_cards: policy.max_cards.or(Some(max_cards)),
+        ..policy
+    }
+}
+
+/// The effective policy of a user
+pub async fn get_policy(pool: &Pool<Sqlite>, config: &Config, user_id: i64) -> Result<UserPolicy> {
+    Ok(effective(users::get_user_policy(pool, user_id).await?, config.user_max_cards))
+}
+
+/// Reject limits above the policy's maximums, unset limits aren't changed
+pub fn check_limits(policy: &UserPolicy, tx_limit_sats: Option<i64>, day_limit_sats: Option<i64>) -> Result<(), PolicyViolation> {
+    if let (Some(limit), Some(max)) = (tx_limit_sats, policy.max_tx_limit_sats)
+        && limit > max
+    {
+        return Err(PolicyViolation::TxLimitAboveMax(max));
+    }
+    if let (Some(limit), Some(max)) = (day_limit_sats, policy.max_day_limit_sats)
+        && limit > max
+    {
+        return Err(PolicyViolation::DayLimitAboveMax(max));
+    }
+    Ok(())
+}
+
+/// Limits of a user's new card
+///
+/// Requested limits must be within the policy. Missing ones come from the
+/// policy's defaults, else from the server's, capped at the policy's maximums.
+pub fn new_card_limits(
+    policy: &UserPolicy,
+    owned_cards: i64,
+    requested: (Option<i64>, Option<i64>),
+    server_defaults: (i64, i64),
+) -> Result<(i64, i64), PolicyViolation> {
+    if let Some(max) = policy.max_cards
+        && owned_cards >= max
+    {
+        return Err(PolicyViolation::CardQuotaReached(max));
+    }
+    check_limits(policy, requested.0, requested.1)?;
+
+    let tx_limit = requested
+        .0
+        .or(policy.default_tx_limit_sats)
+        .unwrap_or(server_defaults.0)
+        .min(policy.max_tx_limit_sats.unwrap_or(i64::MAX));
+    let day_limit = requested
+        .1
+        .or(policy.default_day_limit_sats)
+        .unwrap_or(server_defaults.1)
+        .min(policy.max_day_limit_sats.unwrap_or(i64::MAX));
+
+    Ok((tx_limit, day_limit))
+}
+
+/// Settings that contradict each other, e.g. a default above the maximum
+pub fn policy_problems(policy: &UserPolicy) -> Vec<&'static str> {
+    let mut problems = Vec::new();
+
+    let values = [
+        policy.max_cards,
+        policy.default_tx_limit_sats,
+        policy.default_day_limit_sats,
+        policy.max_tx_limit_sats,
+        policy.max_day_limit_sats,
+    ];
+    if values.iter().flatten().any(|value| *value < 0) {
+        problems.push("Policy values can't be negative");
+    }
+    if let (Some(default), Some(max)) = (policy.default_tx_limit_sats, policy.max_tx_limit_sats)
+        && default > max
+    {
+        problems.push("Default transaction limit is above the maximum");
+    }
+    if let (Some(default), Some(max)) = (policy.default_day_limit_sats, policy.max_day_limit_sats)
+        && default > max
+    {
+        problems.push("Default daily limit is above the maximum");
+    }
+
+    problems
+}
+
+/// Set the limits of a new card according to its owner's policy, if it has an owner
+///
+/// Fails with a [`PolicyViolation`] if the owner may not have the card.
+pub async fn apply_to_new_card(pool: &Pool<Sqlite>, config: &Config, req: &mut CreateCardRequest) -> Result<()> {
+    let Some(owner_id) = req.owner_id else {
+        return Ok(());
+    };
+
+    let policy = get_policy(pool, config, owner_id).await?;
+    let owned_cards = users::count_owned_cards(pool, owner_id).await?;
+    let (tx_limit, day_limit) = new_card_limits(
+        &policy,
+        owned_cards,
+        (req.tx_limit_sats, req.day_limit_sats),
+        (config.default_tx_limit as i64, config.default_day_limit as i64),
+    )?;
+
+    req.tx_limit_sats = Some(tx_limit);
+    req.day_limit_sats = Some(day_limit);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tier() -> UserPolicy {
+        UserPolicy {
+            max_cards: Some(2),
+            default_tx_limit_sats: Some(1_000),
+            default_day_limit_sats: None,
+            max_tx_limit_sats: Some(5_000),
+            max_day_limit_sats: Some(20_000),
+        }
+    }
+
+    #[test]
+    fn test_new_card_limits() {
+        let defaults = (100_000, 1_000_000);
+
+        assert_eq!(new_card_limits(&UserPolicy::default(), 10, (None, None), defaults), Ok(defaults));
+        // Policy default for tx, server default capped at the maximum for day
+        assert_eq!(new_card_limits(&tier(), 0, (None, None), defaults), Ok((1_000, 20_000)));
+        assert_eq!(new_card_limits(&tier(), 1, (Some(5_000), Some(10)), defaults), Ok((5_000, 10)));
+        assert_eq!(
+            new_card_limits(&tier(), 0, (Some(5_001), None), defaults),
+            Err(PolicyViolation::TxLimitAboveMax(5_000))
+        );
+        assert_eq!(
+            new_card_limits(&tier(), 2, (None, None), defaults),
+            Err(PolicyViolation::CardQuotaReached(2))
+        );
+    }
+
+    #[test]
+    fn test_effective() {
+        assert_eq!(effective(None, 3).max_cards, Some(3));
+        assert_eq!(effective(Some(tier()), 3), tier());
+        let unlimited_cards = UserPolicy { max_cards: None, ..tier() };
+        assert_eq!(effective(Some(unlimited_cards), 3).max_cards, Some(3));
+    }
+
+    #[test]
+    fn test_policy_problems() {
+        assert!(policy_problems(&tier()).is_empty());
+
+        let policy = UserPolicy {
+            default_tx_limit_sats: Some(6_000),
+            max_cards: Some(-1),
+            ..tier()
+        };
+        assert_eq!(policy_problems(&policy).len(), 2);
+    }
+}
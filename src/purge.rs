@@ -0,0 +1,32 @@
+//! Purging deleted cards once they can no longer be restored
+//!
+//! Deleted cards stay in the database for `--deleted-card-retention-days`, so
+//! a mistaken deletion can be undone. The loop removes them for good after
+//! that, with their taps, payments and everything else recorded about them.
+
+use std::time::Duration;
+
+use crate::{app_state::AppState, db::deletion};
+
+/// Start the purge loop, unless disabled by a zero interval
+pub fn start(state: AppState) {
+    if state.config.purge_interval_secs == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(state.config.purge_interval_secs));
+        loop {
+            interval.tick().await;
+            let deleted_before = state.clock.now() - chrono::Duration::days(state.config.deleted_card_retention_days.into());
+            match deletion::purge_deleted_cards(&state.pool, deleted_before, "scheduler").await {
+                Ok(card_ids) => {
+                    for card_id in card_ids {
+                        tracing::info!("Card {} purged after its retention period", card_id);
+                    }
+                }
+                Err(e) => tracing::error!("Failed to purge deleted cards: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,138 @@
+//! Randomness for card keys, one-time codes, k1s and other secrets
+//!
+//! Comes from the operating system's generator unless `--random-device`
+//! points at a device to read it from, e.g. a hardware RNG at `/dev/hwrng`.
+//! Tests use [`Random::seeded`] to get the same values on every run.
+
+use anyhow::{Context as _, Result, anyhow};
+use rand::{TryRngCore, rngs::OsRng};
+use std::{
+    fs::File,
+    io::Read,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::{config::Config, crypto::AesKey};
+
+/// Something that fills buffers with random bytes
+pub trait RandomSource: Send + Sync {
+    fn fill(&self, dest: &mut [u8]) -> Result<()>;
+}
+
+/// The operating system's generator
+struct OsRandom;
+
+impl RandomSource for OsRandom {
+    fn fill(&self, dest: &mut [u8]) -> Result<()> {
+        OsRng.try_fill_bytes(dest).map_err(|e| anyhow!("OS random generator failed: {}", e))
+    }
+}
+
+/// Reads from a device like `/dev/hwrng`
+struct DeviceRandom(Mutex<File>);
+
+impl RandomSource for DeviceRandom {
+    fn fill(&self, dest: &mut [u8]) -> Result<()> {
+        let mut device = self.0.lock().map_err(|_| anyhow!("Random device lock poisoned"))?;
+        device.read_exact(dest).context("Reading the random device failed")
+    }
+}
+
+/// Deterministic, only for tests
+#[cfg(test)]
+struct SeededRandom(Mutex<rand::rngs::StdRng>);
+
+#[cfg(test)]
+impl RandomSource for SeededRandom {
+    fn fill(&self, dest: &mut [u8]) -> Result<()> {
+        use rand::RngCore;
+        self.0.lock().map_err(|_| anyhow!("Random generator lock poisoned"))?.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Shared handle on the configured [`RandomSource`]
+#[derive(Clone)]
+pub struct Random(Arc<dyn RandomSource>);
+
+impl Default for Random {
+    fn default() -> Self {
+        Self(Arc::new(OsRandom))
+    }
+}
+
+impl Random {
+    pub fn new(source: impl RandomSource + 'static) -> Self {
+        Self(Arc::new(source))
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Self::from_device(config.random_device.as_deref())
+    }
+
+    /// Read from `device` if given, else from the OS
+    pub fn from_device(device: Option<&Path>) -> Result<Self> {
+        match device {
+            Some(path) => Self::device(path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Read from `path`, which is checked to deliver bytes
+    pub fn device(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Can't open random device {}", path.display()))?;
+        let random = Self::new(DeviceRandom(Mutex::new(file)));
+        random.bytes::<1>().with_context(|| format!("Random device {} gives no bytes", path.display()))?;
+        Ok(random)
+    }
+
+    /// The same sequence for the same seed
+    #[cfg(test)]
+    pub fn seeded(seed: u64) -> Self {
+        use rand::SeedableRng;
+        Self::new(SeededRandom(Mutex::new(rand::rngs::StdRng::seed_from_u64(seed))))
+    }
+
+    pub fn bytes<const N: usize>(&self) -> Result<[u8; N]> {
+        let mut bytes = [0u8; N];
+        self.0.fill(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// `N` random bytes as hex, for k1s, codes and tokens
+    pub fn hex<const N: usize>(&self) -> Result<String> {
+        Ok(hex::encode(self.bytes::<N>()?))
+    }
+
+    pub fn aes_key(&self) -> Result<AesKey> {
+        Ok(AesKey::from_bytes(self.bytes()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded() {
+        let (a, b) = (Random::seeded(7), Random::seeded(7));
+        assert_eq!(a.aes_key().unwrap(), b.aes_key().unwrap());
+        assert_eq!(a.hex::<16>().unwrap(), b.hex::<16>().unwrap());
+        assert_ne!(a.hex::<16>().unwrap(), Random::seeded(8).hex::<16>().unwrap());
+    }
+
+    #[test]
+    fn test_device() {
+        let path = std::env::temp_dir().join(format!("lnurlw-random-{}", std::process::id()));
+        std::fs::write(&path, [0xAB; 20]).unwrap();
+
+        let random = Random::device(&path).unwrap();
+        assert_eq!(random.hex::<16>().unwrap(), "ab".repeat(16));
+        // The device ran dry
+        assert!(random.bytes::<4>().is_err());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(Random::device(&path).is_err());
+    }
+}
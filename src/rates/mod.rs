@@ -0,0 +1,241 @@
+//! Bitcoin price in the operator's fiat currency, recorded when withdrawals settle
+//!
+//! Prices come from public sources (see `--rate-providers`), asked in order
+//! until one answers. A price is reused for `--rate-cache-secs`, and when no
+//! source answers, one no older than `--rate-max-age-secs` is used instead,
+//! marked as stale.
+
+mod providers;
+
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Serialize;
+use std::{collections::HashMap, sync::Mutex};
+
+pub use providers::{Coinbase, Kraken, Mempool};
+
+use crate::{
+    app_state::AppState,
+    config::Config,
+    db::{fiat, models::PaymentStatus, queries},
+    jobs::{Job, Permanent},
+};
+
+/// A price looked up later than this after settlement wouldn't be the one at settlement
+const MAX_RATE_DELAY_SECS: i64 = 3600;
+
+/// A source of the current bitcoin price
+#[async_trait]
+pub trait RateProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Price of one bitcoin in `currency` (ISO 4217, uppercase)
+    async fn btc_price(&self, currency: &str) -> Result<f64>;
+}
+
+/// The names `--rate-providers` accepts
+pub const PROVIDERS: [&str; 3] = ["kraken", "coinbase", "mempool"];
+
+pub fn provider(name: &str, http: reqwest::Client) -> Result<Box<dyn RateProvider>> {
+    Ok(match name.trim() {
+        "kraken" => Box::new(Kraken { http }),
+        "coinbase" => Box::new(Coinbase { http }),
+        "mempool" => Box::new(Mempool { http }),
+        other => bail!("unknown rate provider {:?}, expected one of {}", other, PROVIDERS.join(", ")),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Quote {
+    pub price: f64,
+    /// Name of the provider that supplied the price
+    pub source: &'static str,
+    pub fetched_at: DateTime<Utc>,
+    /// Whether no provider answered and this is an older price
+    pub stale: bool,
+}
+
+/// The configured providers with a cache of their latest prices
+pub struct Rates {
+    providers: Vec<Box<dyn RateProvider>>,
+    cache_for: chrono::Duration,
+    max_age: chrono::Duration,
+    cache: Mutex<HashMap<String, Quote>>,
+}
+
+impl Rates {
+    pub fn new(providers: Vec<Box<dyn RateProvider>>, cache_secs: u64, max_age_secs: u64) -> Self {
+        Self {
+            providers,
+            cache_for: chrono::Duration::seconds(cache_secs as i64),
+            max_age: chrono::Duration::seconds(max_age_secs as i64),
+            cache: Mutex::default(),
+        }
+    }
+
+    pub fn from_config(config: &Config, http: reqwest::Client) -> Result<Self> {
+        let providers = config
+            .rate_providers
+            .iter()
+            .filter(|name| !name.trim().is_empty())
+            .map(|name| provider(name, http.clone()))
+            .collect::<Result<Vec<_>>>()?;
+        if providers.is_empty() {
+            bail!("--rate-providers needs at least one provider");
+        }
+        Ok(Self::new(providers, config.rate_cache_secs, config.rate_max_age_secs))
+    }
+
+    fn cached(&self, currency: &str) -> Option<Quote> {
+        self.cache.lock().unwrap().get(currency).cloned()
+    }
+
+    /// The current price of one bitcoin in `currency`
+    pub async fn price(&self, currency: &str) -> Result<Quote> {
+        let cached = self.cached(currency);
+        if let Some(quote) = &cached
+            && Utc::now() - quote.fetched_at < self.cache_for
+        {
+            return Ok(quote.clone());
+        }
+
+        let mut failures = Vec::new();
+        for provider in &self.providers {
+            match provider.btc_price(currency).await {
+                Ok(price) => {
+                    let quote = Quote { price, source: provider.name(), fetched_at: Utc::now(), stale: false };
+                    self.cache.lock().unwrap().insert(currency.to_string(), quote.clone());
+                    return Ok(quote);
+                }
+                Err(e) => {
+                    tracing::warn!("Rate provider {} failed for {}: {}", provider.name(), currency, e);
+                    failures.push(format!("{}: {}", provider.name(), e));
+                }
+            }
+        }
+
+        match cached {
+            Some(quote) if Utc::now() - quote.fetched_at <= self.max_age => Ok(Quote { stale: true, ..quote }),
+            _ => Err(anyhow!("No bitcoin price in {}: {}", currency, failures.join("; "))),
+        }
+    }
+}
+
+/// Queue looking up the price for a settled payment, if a fiat currency is configured
+pub async fn queue_rate(state: &AppState, payment_id: i64) {
+    if state.config.fiat_currency.is_none() {
+        return;
+    }
+    if let Err(e) = state.jobs.enqueue(&Job::RecordRate { payment_id }).await {
+        tracing::error!("Failed to queue price lookup for payment {}: {}", payment_id, e);
+    }
+}
+
+/// Record the current price on a settled payment, given up once it's too late to stand for the settlement
+pub async fn record_rate(state: &AppState, payment_id: i64) -> Result<()> {
+    let Some(currency) = state.config.fiat_currency.as_deref() else {
+        return Ok(());
+    };
+    let Some(payment) = queries::get_payment_by_id(&state.pool, payment_id).await? else {
+        return Ok(());
+    };
+    if payment.status != PaymentStatus::Settled {
+        return Ok(());
+    }
+
+    let settled_at = payment
+        .payment_time
+        .as_deref()
+        .and_then(|time| NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").ok());
+    let late = settled_at.is_none_or(|at| (chrono::Utc::now().naive_utc() - at).num_seconds() > MAX_RATE_DELAY_SECS);
+    if late {
+        return Err(Permanent(format!("payment {} settled too long ago to record its price", payment_id)).into());
+    }
+
+    // A stale quote is at most --rate-max-age-secs old, close enough to stand for the settlement
+    let quote = state.rates.price(currency).await?;
+    fiat::set_payment_rate(&state.pool, payment_id, currency, quote.price, quote.source).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    };
+
+    #[derive(Clone, Default)]
+    struct Fake {
+        down: Arc<AtomicBool>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl RateProvider for Fake {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        async fn btc_price(&self, _currency: &str) -> Result<f64> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.down.load(Ordering::SeqCst) {
+                bail!("down");
+            }
+            Ok(50_000.0)
+        }
+    }
+
+    struct Broken;
+
+    #[async_trait]
+    impl RateProvider for Broken {
+        fn name(&self) -> &'static str {
+            "broken"
+        }
+
+        async fn btc_price(&self, _currency: &str) -> Result<f64> {
+            bail!("unreachable")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failover_and_cache() {
+        let fake = Fake::default();
+        let rates = Rates::new(vec![Box::new(Broken), Box::new(fake.clone())], 60, 600);
+
+        let quote = rates.price("EUR").await.unwrap();
+        assert_eq!((quote.price, quote.source, quote.stale), (50_000.0, "fake", false));
+        rates.price("EUR").await.unwrap();
+        assert_eq!(fake.calls.load(Ordering::SeqCst), 1);
+
+        fake.down.store(true, Ordering::SeqCst);
+        let error = rates.price("USD").await.unwrap_err().to_string();
+        assert!(error.contains("broken: unreachable") && error.contains("fake: down"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_stale() {
+        let fake = Fake::default();
+        let rates = Rates::new(vec![Box::new(fake.clone())], 0, 600);
+        rates.price("EUR").await.unwrap();
+
+        fake.down.store(true, Ordering::SeqCst);
+        let quote = rates.price("EUR").await.unwrap();
+        assert!(quote.stale);
+        assert_eq!(fake.calls.load(Ordering::SeqCst), 2);
+
+        fake.down.store(false, Ordering::SeqCst);
+        let rates = Rates::new(vec![Box::new(fake.clone())], 0, 0);
+        rates.price("EUR").await.unwrap();
+        fake.down.store(true, Ordering::SeqCst);
+        assert!(rates.price("EUR").await.is_err());
+    }
+
+    #[test]
+    fn test_provider() {
+        assert_eq!(provider("mempool", reqwest::Client::new()).unwrap().name(), "mempool");
+        assert!(provider("bitstamp", reqwest::Client::new()).is_err());
+    }
+}
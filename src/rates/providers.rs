@@ -0,0 +1,146 @@
+//! Public price feeds, none of which needs an API key
+
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use serde::{Deserialize, de::DeserializeOwned};
+use std::{collections::HashMap, time::Duration};
+
+use super::RateProvider;
+
+async fn get_json<T: DeserializeOwned>(http: &reqwest::Client, url: &str) -> Result<T> {
+    let response = http
+        .get(url)
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| anyhow!("request failed: {}", e.without_url()))?;
+    Ok(response.json().await?)
+}
+
+fn positive(price: f64) -> Result<f64> {
+    if price.is_finite() && price > 0.0 {
+        Ok(price)
+    } else {
+        bail!("invalid price {}", price)
+    }
+}
+
+/// Coinbase's spot price
+pub struct Coinbase {
+    pub http: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct CoinbaseSpot {
+    data: CoinbaseAmount,
+}
+
+#[derive(Deserialize)]
+struct CoinbaseAmount {
+    amount: String,
+}
+
+#[async_trait]
+impl RateProvider for Coinbase {
+    fn name(&self) -> &'static str {
+        "coinbase"
+    }
+
+    async fn btc_price(&self, currency: &str) -> Result<f64> {
+        let url = format!("https://api.coinbase.com/v2/prices/BTC-{}/spot", currency);
+        let spot: CoinbaseSpot = get_json(&self.http, &url).await?;
+        positive(spot.data.amount.parse().map_err(|_| anyhow!("invalid price {:?}", spot.data.amount))?)
+    }
+}
+
+/// Last trade price on Kraken
+pub struct Kraken {
+    pub http: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct KrakenTicker {
+    error: Vec<String>,
+    #[serde(default)]
+    result: HashMap<String, KrakenPair>,
+}
+
+#[derive(Deserialize)]
+struct KrakenPair {
+    /// Last trade as price and volume
+    c: Vec<String>,
+}
+
+impl KrakenTicker {
+    fn last_price(&self) -> Result<f64> {
+        if let Some(error) = self.error.first() {
+            bail!("{}", error);
+        }
+        // The pair comes back under Kraken's own name for it, e.g. XXBTZEUR for XBTEUR
+        let pair = self.result.values().next().ok_or_else(|| anyhow!("no ticker in response"))?;
+        let last = pair.c.first().ok_or_else(|| anyhow!("no last trade in response"))?;
+        positive(last.parse().map_err(|_| anyhow!("invalid price {:?}", last))?)
+    }
+}
+
+#[async_trait]
+impl RateProvider for Kraken {
+    fn name(&self) -> &'static str {
+        "kraken"
+    }
+
+    async fn btc_price(&self, currency: &str) -> Result<f64> {
+        let url = format!("https://api.kraken.com/0/public/Ticker?pair=XBT{}", currency);
+        get_json::<KrakenTicker>(&self.http, &url).await?.last_price()
+    }
+}
+
+/// mempool.space's price index, which covers a handful of major currencies
+pub struct Mempool {
+    pub http: reqwest::Client,
+}
+
+#[async_trait]
+impl RateProvider for Mempool {
+    fn name(&self) -> &'static str {
+        "mempool"
+    }
+
+    async fn btc_price(&self, currency: &str) -> Result<f64> {
+        let prices: HashMap<String, serde_json::Value> = get_json(&self.http, "https://mempool.space/api/v1/prices").await?;
+        mempool_price(&prices, currency)
+    }
+}
+
+fn mempool_price(prices: &HashMap<String, serde_json::Value>, currency: &str) -> Result<f64> {
+    let price = prices
+        .get(currency)
+        .and_then(serde_json::Value::as_f64)
+        .ok_or_else(|| anyhow!("no price in {}", currency))?;
+    positive(price)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kraken_ticker() {
+        let ticker: KrakenTicker = serde_json::from_str(
+            r#"{"error":[],"result":{"XXBTZEUR":{"a":["60010.0","1","1.000"],"c":["60000.5","0.01"]}}}"#,
+        )
+        .unwrap();
+        assert_eq!(ticker.last_price().unwrap(), 60000.5);
+
+        let ticker: KrakenTicker = serde_json::from_str(r#"{"error":["EQuery:Unknown asset pair"]}"#).unwrap();
+        assert!(ticker.last_price().unwrap_err().to_string().contains("Unknown asset pair"));
+    }
+
+    #[test]
+    fn test_mempool_price() {
+        let prices = serde_json::from_str(r#"{"time":1760000000,"USD":62000,"EUR":57000.5}"#).unwrap();
+        assert_eq!(mempool_price(&prices, "EUR").unwrap(), 57000.5);
+        assert!(mempool_price(&prices, "CHF").is_err());
+    }
+}
@@ -0,0 +1,159 @@
+//! Signed receipts of settled withdrawals
+//!
+//! A receipt states that a withdrawal of an amount settled with a payment hash
+//! and its preimage, signed with the server's Ed25519 key so anyone can check
+//! it without trusting a screenshot. The key is `--receipt-signing-key`, or one
+//! the server generates on its first start and keeps in the database. All
+//! public keys the server signed with are published, so receipts stay
+//! verifiable after the key changes.
+
+use anyhow::{Result, anyhow};
+use ring::signature::{ED25519, Ed25519KeyPair, KeyPair, UnparsedPublicKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Sqlite};
+
+use crate::{config::Config, db::receipts, random::Random};
+
+/// First line of the signed message, changes with its layout
+const VERSION: &str = "lnurlw-receipt-v1";
+
+/// What a receipt states about a withdrawal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Receipt {
+    /// Domain of the server that signed the receipt
+    pub issuer: String,
+    pub k1: String,
+    pub amount_msats: i64,
+    pub payment_hash: String,
+    pub preimage: String,
+    pub settled_at: String,
+}
+
+impl Receipt {
+    /// The signed message: the version and the fields in this order, one per line
+    pub fn message(&self) -> String {
+        [
+            VERSION,
+            &self.issuer,
+            &self.k1,
+            &self.amount_msats.to_string(),
+            &self.payment_hash,
+            &self.preimage,
+            &self.settled_at,
+        ]
+        .join("\n")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedReceipt {
+    pub receipt: Receipt,
+    /// Ed25519 public key as hex
+    pub public_key: String,
+    /// Ed25519 signature of [`Receipt::message`] as hex
+    pub signature: String,
+}
+
+/// Signs receipts with the server's key
+pub struct ReceiptSigner {
+    key: Ed25519KeyPair,
+}
+
+impl ReceiptSigner {
+    pub fn from_seed(seed: &[u8]) -> Result<Self> {
+        let key = Ed25519KeyPair::from_seed_unchecked(seed).map_err(|e| anyhow!("Invalid receipt signing key: {}", e))?;
+        Ok(Self { key })
+    }
+
+    /// The configured key, otherwise the one generated before or a new one, recorded in the database
+    pub async fn load(pool: &Pool<Sqlite>, config: &Config, random: &Random) -> Result<Self> {
+        if let Some(seed) = &config.receipt_signing_key {
+            let signer = Self::from_seed(&hex::decode(seed)?)?;
+            receipts::record_key(pool, &signer.public_key(), None).await?;
+            return Ok(signer);
+        }
+
+        if let Some(seed) = receipts::generated_secret_key(pool).await? {
+            return Self::from_seed(&hex::decode(seed)?);
+        }
+        let seed = random.bytes::<32>()?;
+        let signer = Self::from_seed(&seed)?;
+        receipts::record_key(pool, &signer.public_key(), Some(&hex::encode(seed))).await?;
+        tracing::info!("Generated receipt signing key {}", signer.public_key());
+
+        Ok(signer)
+    }
+
+    pub fn public_key(&self) -> String {
+        hex::encode(self.key.public_key())
+    }
+
+    pub fn sign(&self, receipt: Receipt) -> SignedReceipt {
+        let signature = self.key.sign(receipt.message().as_bytes());
+        SignedReceipt { public_key: self.public_key(), signature: hex::encode(signature), receipt }
+    }
+}
+
+/// Check that a receipt is signed by one of `public_keys` and its preimage is the payment hash's
+pub fn verify(signed: &SignedReceipt, public_keys: &[String]) -> Result<(), String> {
+    if !public_keys.iter().any(|key| key.eq_ignore_ascii_case(&signed.public_key)) {
+        return Err(format!("signed with unknown key {}", signed.public_key));
+    }
+    let public_key = hex::decode(&signed.public_key).map_err(|_| "public key is not hex")?;
+    let signature = hex::decode(&signed.signature).map_err(|_| "signature is not hex")?;
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(signed.receipt.message().as_bytes(), &signature)
+        .map_err(|_| "invalid signature")?;
+
+    let preimage = hex::decode(&signed.receipt.preimage).map_err(|_| "preimage is not hex")?;
+    if !hex::encode(Sha256::digest(preimage)).eq_ignore_ascii_case(&signed.receipt.payment_hash) {
+        return Err("preimage doesn't match the payment hash".to_string());
+    }
+
+    Ok(())
+}
+
+/// Parser for `--receipt-signing-key`
+pub fn parse_signing_key(seed: &str) -> Result<String, String> {
+    match hex::decode(seed) {
+        Ok(bytes) if bytes.len() == 32 => Ok(seed.to_lowercase()),
+        _ => Err("expected a 32-byte Ed25519 seed as hex".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receipt() -> Receipt {
+        let preimage = [7u8; 32];
+        Receipt {
+            issuer: "card.example.com".to_string(),
+            k1: "5b6a0d6a2e4f8c1d9e3b7a6f0c2d4e8f".to_string(),
+            amount_msats: 21_000,
+            payment_hash: hex::encode(Sha256::digest(preimage)),
+            preimage: hex::encode(preimage),
+            settled_at: "2025-03-01 12:00:00".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let signer = ReceiptSigner::from_seed(&[1u8; 32]).unwrap();
+        let signed = signer.sign(receipt());
+        let keys = [signer.public_key()];
+        assert_eq!(verify(&signed, &keys), Ok(()));
+
+        let other = ReceiptSigner::from_seed(&[2u8; 32]).unwrap();
+        assert!(verify(&signed, &[other.public_key()]).unwrap_err().contains("unknown key"));
+
+        let mut forged = signed.clone();
+        forged.receipt.amount_msats = 2_100_000;
+        assert_eq!(verify(&forged, &keys), Err("invalid signature".to_string()));
+
+        let mut unpaid = receipt();
+        unpaid.preimage = hex::encode([8u8; 32]);
+        assert_eq!(verify(&signer.sign(unpaid), &keys), Err("preimage doesn't match the payment hash".to_string()));
+    }
+}
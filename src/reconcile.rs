@@ -0,0 +1,218 @@
+//! Periodic cross-check of payments against the Lightning node
+//!
+//! Payments stuck in flight, e.g. because the server went down while paying,
+//! are settled or failed according to the node. Those the node has no record
+//! of may have gone out all the same, and recently decided payments the node
+//! disagrees about can't be fixed automatically either: they are flagged for
+//! the operator instead.
+
+use anyhow::Result;
+use std::time::Duration;
+
+use crate::{
+    app_state::AppState,
+    db::{models::{CardPayment, PaymentStatus}, queries, reconcile},
+    lightning::OutgoingPayment,
+    notifications::{self, Event},
+    rates,
+};
+
+/// In-flight payments younger than this may still be waiting for `pay_invoice`
+const STALE_AFTER_SECS: i64 = 600;
+/// How far back settled and failed payments are compared with the node
+const LOOKBACK_SECS: i64 = 3600;
+
+/// Start the reconciliation loop, unless disabled by a zero interval
+pub fn start(state: AppState) {
+    if state.config.reconcile_interval_secs == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(state.config.reconcile_interval_secs));
+        loop {
+            interval.tick().await;
+            if let Err(e) = run(&state).await {
+                tracing::error!("Payment reconciliation failed: {}", e);
+            }
+        }
+    });
+}
+
+/// Reconcile once; a payment the node can't be asked about is retried on the next run
+pub async fn run(state: &AppState) -> Result<()> {
+    for payment in reconcile::stale_in_flight(&state.pool, STALE_AFTER_SECS, state.clock.now()).await? {
+        if let Err(e) = decide_in_flight(state, &payment).await {
+            tracing::error!("Failed to reconcile payment {}: {}", payment.payment_id, e);
+        }
+    }
+
+    for payment in reconcile::recently_decided(&state.pool, LOOKBACK_SECS, state.clock.now()).await? {
+        if let Err(e) = check_decided(state, &payment).await {
+            tracing::error!("Failed to reconcile payment {}: {}", payment.payment_id, e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Settle or fail a payment stuck in flight according to the node
+///
+/// A payment the node doesn't know, or an on-chain one it can't be asked
+/// about, stays in flight and is flagged as `in_flight_unknown`, to be decided
+/// with `discrepancies resolve --payment`.
+pub async fn decide_in_flight(state: &AppState, payment: &CardPayment) -> Result<()> {
+    let Some(payment_hash) = payment.payment_hash.as_deref() else {
+        return report(state, payment, "in_flight_unknown", &OutgoingPayment::Unknown).await;
+    };
+
+    let lightning = state.lightning.for_card(&state.pool, payment.card_id).await?;
+    match lightning.outgoing_payment(payment_hash).await? {
+        OutgoingPayment::Succeeded { preimage } => {
+            let now = state.clock.now();
+            if queries::mark_payment_settled(&state.pool, payment.payment_id, preimage.as_deref(), None, now).await? {
+                notifications::queue_receipt(state, payment.card_id, payment.payment_id).await;
+                rates::queue_rate(state, payment.payment_id).await;
+                tracing::warn!("Payment {} was stuck in flight but settled on the node", payment.payment_id);
+            }
+        }
+        OutgoingPayment::Failed => {
+            if reconcile::fail_in_flight(&state.pool, payment.payment_id, "Payment didn't complete on the node").await? {
+                tracing::warn!("Payment {} was stuck in flight and failed on the node", payment.payment_id);
+            }
+        }
+        node @ OutgoingPayment::Unknown => report(state, payment, "in_flight_unknown", &node).await?,
+        OutgoingPayment::Pending => {}
+    }
+
+    Ok(())
+}
+
+/// Flag a settled or failed payment if the node has a different outcome
+async fn check_decided(state: &AppState, payment: &CardPayment) -> Result<()> {
+    let Some(payment_hash) = payment.payment_hash.as_deref() else { return Ok(()) };
+
+    let lightning = state.lightning.for_card(&state.pool, payment.card_id).await?;
+    let node = lightning.outgoing_payment(payment_hash).await?;
+    let kind = match (payment.status, &node) {
+        (PaymentStatus::Settled, OutgoingPayment::Failed | OutgoingPayment::Unknown) => "settled_not_on_node",
+        (PaymentStatus::Failed, OutgoingPayment::Succeeded { .. }) => "paid_on_node_not_recorded",
+        _ => return Ok(()),
+    };
+
+    report(state, payment, kind, &node).await
+}
+
+/// Flag a payment for review and tell the operator, once per kind of discrepancy
+async fn report(state: &AppState, payment: &CardPayment, kind: &str, node: &OutgoingPayment) -> Result<()> {
+    if reconcile::flag(&state.pool, payment.payment_id, kind, node.as_str()).await? {
+        tracing::error!("Payment {} needs review: {} (node: {})", payment.payment_id, kind, node.as_str());
+        let event = Event::PaymentDiscrepancy {
+            payment_id: payment.payment_id,
+            card_id: payment.card_id,
+            k1: payment.k1.clone(),
+            kind: kind.to_string(),
+            node_status: node.as_str().to_string(),
+        };
+        notifications::send(state, event).await;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app_state::test_state, clock::sql_timestamp};
+    use lnurlw_core::lightning::{Fault, LightningBackend, MockLightning};
+
+    /// A payment of 1000 sats and a 10 sat fee from a prepaid card, paid on the
+    /// mock node as `fault` says (not at all if `None`) and recorded as `status`
+    async fn payment(state: &AppState, mock: &MockLightning, card_id: i64, fault: Option<Fault>, status: &str, age_secs: i64) -> i64 {
+        let invoice = mock.create_invoice(1_000_000, "withdrawal").await.unwrap();
+        if let Some(fault) = fault {
+            mock.script([fault]);
+            let _ = mock.pay_invoice(&invoice, 1_000_000).await;
+        }
+        sqlx::query_scalar(
+            "INSERT INTO card_payments (card_id, k1, invoice, amount_msats, service_fee_msats, payment_hash, status, in_flight_since)
+             VALUES (?, ?, ?, 1000000, 10000, ?, ?, ?) RETURNING payment_id"
+        )
+        .bind(card_id)
+        .bind(invoice.payment_hash())
+        .bind(invoice.bolt11())
+        .bind(invoice.payment_hash())
+        .bind(status)
+        .bind(sql_timestamp(state.clock.now() - chrono::Duration::seconds(age_secs)))
+        .fetch_one(&state.pool)
+        .await
+        .unwrap()
+    }
+
+    async fn status(state: &AppState, payment_id: i64) -> PaymentStatus {
+        queries::get_payment_by_id(&state.pool, payment_id).await.unwrap().unwrap().status
+    }
+
+    async fn flagged(state: &AppState) -> Vec<(i64, String)> {
+        let mut flagged: Vec<_> = reconcile::list(&state.pool, false)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|discrepancy| (discrepancy.payment_id, discrepancy.kind))
+            .collect();
+        flagged.sort();
+        flagged
+    }
+
+    #[tokio::test]
+    async fn test_stuck_payments_go_by_the_node() {
+        let (state, mock) = test_state(&[]).await;
+        let key = "00000000000000000000000000000000";
+        let card_id = queries::insert_card(&state.pool, "", key, key, key, key, key, "Card", 10_000, 100_000, true, true, "code", None, None, state.clock.now())
+            .await
+            .unwrap();
+
+        let paid = payment(&state, &mock, card_id, Some(Fault::Lost), "in_flight", 3600).await;
+        let failed = payment(&state, &mock, card_id, Some(Fault::Fail("No route".to_string())), "in_flight", 3600).await;
+        let pending = payment(&state, &mock, card_id, Some(Fault::Partial { arrived_msats: 0 }), "in_flight", 3600).await;
+        let unknown = payment(&state, &mock, card_id, None, "in_flight", 3600).await;
+        let fresh = payment(&state, &mock, card_id, None, "in_flight", 60).await;
+
+        run(&state).await.unwrap();
+        run(&state).await.unwrap();
+
+        assert_eq!(status(&state, paid).await, PaymentStatus::Settled);
+        assert_eq!(status(&state, failed).await, PaymentStatus::Failed);
+        assert_eq!(status(&state, pending).await, PaymentStatus::InFlight);
+        assert_eq!(status(&state, unknown).await, PaymentStatus::InFlight);
+        assert_eq!(status(&state, fresh).await, PaymentStatus::InFlight);
+        // Only the payment that failed on the node is refunded, once
+        assert_eq!(queries::get_card_by_id(&state.pool, card_id).await.unwrap().unwrap().balance_msats, 1_010_000);
+        assert_eq!(flagged(&state).await, vec![(unknown, "in_flight_unknown".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_decided_payments_disagreeing_with_the_node_are_flagged() {
+        let (state, mock) = test_state(&[]).await;
+        let key = "00000000000000000000000000000000";
+        let card_id = queries::insert_card(&state.pool, "", key, key, key, key, key, "Card", 10_000, 100_000, true, false, "code", None, None, state.clock.now())
+            .await
+            .unwrap();
+
+        let settled_unknown = payment(&state, &mock, card_id, None, "settled", 60).await;
+        let failed_but_paid = payment(&state, &mock, card_id, Some(Fault::Lost), "failed", 60).await;
+        payment(&state, &mock, card_id, Some(Fault::Lost), "settled", 60).await;
+        payment(&state, &mock, card_id, None, "settled", 2 * LOOKBACK_SECS).await;
+
+        run(&state).await.unwrap();
+
+        assert_eq!(
+            flagged(&state).await,
+            vec![
+                (settled_unknown, "settled_not_on_node".to_string()),
+                (failed_but_paid, "paid_on_node_not_recorded".to_string()),
+            ]
+        );
+        assert_eq!(status(&state, failed_but_paid).await, PaymentStatus::Failed);
+    }
+}
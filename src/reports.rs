@@ -0,0 +1,266 @@
+//! Fiat-valued summaries of settled withdrawals for bookkeeping
+//!
+//! Each withdrawal is valued at the bitcoin price recorded when it settled
+//! (see `--fiat-currency`). Withdrawals without a recorded price are counted
+//! as unvalued rather than guessed.
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{
+    db::fiat::FiatPayment,
+    pdf::{PAGE_HEIGHT, Page, PdfDocument},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FiatReportFormat {
+    Csv,
+    Pdf,
+    Json,
+}
+
+/// First and last day of a year, or of one of its months, `None` for an invalid month
+pub fn period(year: i32, month: Option<u32>) -> Option<(NaiveDate, NaiveDate)> {
+    match month {
+        Some(month) => {
+            let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+            let last = first.checked_add_months(chrono::Months::new(1))?.pred_opt()?;
+            Some((first, last))
+        }
+        None => Some((NaiveDate::from_ymd_opt(year, 1, 1)?, NaiveDate::from_ymd_opt(year, 12, 31)?)),
+    }
+}
+
+/// Withdrawals of one month valued in one currency
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MonthlySummary {
+    /// YYYY-MM
+    pub month: String,
+    /// `None` for withdrawals without a recorded price
+    pub currency: Option<String>,
+    pub payments: i64,
+    pub volume_msats: i64,
+    pub fees_msats: i64,
+    pub fiat_volume: Option<f64>,
+    pub fiat_fees: Option<f64>,
+}
+
+/// Summaries by month and currency, in order
+pub fn monthly(payments: &[FiatPayment]) -> Vec<MonthlySummary> {
+    let mut months: BTreeMap<(String, Option<String>), MonthlySummary> = BTreeMap::new();
+    for payment in payments {
+        let month = payment.payment_time.get(..7).unwrap_or(&payment.payment_time).to_string();
+        let currency = payment.fiat_rate.and(payment.fiat_currency.clone());
+        let fee_msats = payment.fee_msats.unwrap_or(0);
+
+        let summary = months
+            .entry((month.clone(), currency.clone()))
+            .or_insert_with(|| MonthlySummary {
+                month,
+                fiat_volume: currency.as_ref().map(|_| 0.0),
+                fiat_fees: currency.as_ref().map(|_| 0.0),
+                currency,
+                payments: 0,
+                volume_msats: 0,
+                fees_msats: 0,
+            });
+        summary.payments += 1;
+        summary.volume_msats += payment.amount_msats;
+        summary.fees_msats += fee_msats;
+        if let (Some(volume), Some(value)) = (summary.fiat_volume.as_mut(), payment.value(payment.amount_msats)) {
+            *volume += value;
+        }
+        if let (Some(fees), Some(value)) = (summary.fiat_fees.as_mut(), payment.value(fee_msats)) {
+            *fees += value;
+        }
+    }
+
+    // Sums of products of floats, rounded back to cents
+    let cents = |value: Option<f64>| value.map(|value| (value * 100.0).round() / 100.0);
+    months
+        .into_values()
+        .map(|summary| MonthlySummary {
+            fiat_volume: cents(summary.fiat_volume),
+            fiat_fees: cents(summary.fiat_fees),
+            ..summary
+        })
+        .collect()
+}
+
+/// Render rows as CSV, quoting fields that need it
+pub fn format_csv(header: &[&str], rows: &[Vec<String>]) -> String {
+    let field = |value: &str| {
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    };
+
+    let mut out = header.join(",") + "\n";
+    for row in rows {
+        let line: Vec<String> = row.iter().map(|value| field(value)).collect();
+        out.push_str(&line.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+fn fiat(value: Option<f64>) -> String {
+    value.map(|value| format!("{:.2}", value)).unwrap_or_default()
+}
+
+pub fn summary_csv(summaries: &[MonthlySummary]) -> String {
+    let header = ["month", "currency", "payments", "volume_msats", "fees_msats", "fiat_volume", "fiat_fees"];
+    let rows: Vec<Vec<String>> = summaries
+        .iter()
+        .map(|summary| {
+            vec![
+                summary.month.clone(),
+                summary.currency.clone().unwrap_or_default(),
+                summary.payments.to_string(),
+                summary.volume_msats.to_string(),
+                summary.fees_msats.to_string(),
+                fiat(summary.fiat_volume),
+                fiat(summary.fiat_fees),
+            ]
+        })
+        .collect();
+    format_csv(&header, &rows)
+}
+
+/// One line per withdrawal with its price and fiat value
+pub fn payments_csv(payments: &[FiatPayment]) -> String {
+    let header = [
+        "payment_id", "card_id", "card_name", "payment_time", "amount_msats", "fee_msats", "currency", "rate",
+        "fiat_amount", "fiat_fee", "rate_source",
+    ];
+    let rows: Vec<Vec<String>> = payments
+        .iter()
+        .map(|payment| {
+            let fee_msats = payment.fee_msats.unwrap_or(0);
+            vec![
+                payment.payment_id.to_string(),
+                payment.card_id.to_string(),
+                payment.card_name.clone(),
+                payment.payment_time.clone(),
+                payment.amount_msats.to_string(),
+                fee_msats.to_string(),
+                payment.fiat_rate.and(payment.fiat_currency.clone()).unwrap_or_default(),
+                fiat(payment.fiat_rate),
+                fiat(payment.value(payment.amount_msats)),
+                fiat(payment.value(fee_msats)),
+                payment.fiat_rate_source.clone().unwrap_or_default(),
+            ]
+        })
+        .collect();
+    format_csv(&header, &rows)
+}
+
+/// The monthly summaries as a printable table, continued on further pages as needed
+pub fn summary_pdf(title: &str, summaries: &[MonthlySummary]) -> PdfDocument {
+    const LINE: f32 = 11.0;
+    const COLUMNS: [f32; 6] = [20.0, 62.0, 96.0, 132.0, 192.0, 246.0];
+
+    let header = ["Month", "Currency", "Count", "Sats", "Fiat", "Fees"];
+    let new_page = |continued: bool| {
+        let mut page = Page::default();
+        let heading = if continued { format!("{} (continued)", title) } else { title.to_string() };
+        page.text(COLUMNS[0], PAGE_HEIGHT - 30.0, 10.0, &heading);
+        for (x, label) in COLUMNS.iter().zip(header) {
+            page.text(*x, PAGE_HEIGHT - 50.0, 7.0, label);
+        }
+        page
+    };
+
+    let mut doc = PdfDocument::new();
+    let mut page = new_page(false);
+    let mut y = PAGE_HEIGHT - 50.0 - LINE;
+    for summary in summaries {
+        if y < 30.0 {
+            doc.add_page(std::mem::replace(&mut page, new_page(true)));
+            y = PAGE_HEIGHT - 50.0 - LINE;
+        }
+        let cells = [
+            summary.month.clone(),
+            summary.currency.clone().unwrap_or_else(|| "-".to_string()),
+            summary.payments.to_string(),
+            (summary.volume_msats / 1000).to_string(),
+            summary.fiat_volume.map(|value| format!("{:.2}", value)).unwrap_or_else(|| "-".to_string()),
+            summary.fiat_fees.map(|value| format!("{:.2}", value)).unwrap_or_else(|| "-".to_string()),
+        ];
+        for (x, cell) in COLUMNS.iter().zip(&cells) {
+            page.text(*x, y, 7.0, cell);
+        }
+        y -= LINE;
+    }
+    if summaries.is_empty() {
+        page.text(COLUMNS[0], y, 7.0, "No settled withdrawals");
+    }
+    doc.add_page(page);
+
+    doc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payment(payment_time: &str, amount_msats: i64, rate: Option<f64>) -> FiatPayment {
+        FiatPayment {
+            payment_id: 1,
+            card_id: 1,
+            card_name: "Till, 1".to_string(),
+            payment_time: payment_time.to_string(),
+            amount_msats,
+            fee_msats: Some(1_000),
+            fiat_currency: rate.map(|_| "EUR".to_string()),
+            fiat_rate: rate,
+            fiat_rate_source: rate.map(|_| "kraken".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_period() {
+        let day = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        assert_eq!(period(2024, Some(2)), Some((day(2024, 2, 1), day(2024, 2, 29))));
+        assert_eq!(period(2026, None), Some((day(2026, 1, 1), day(2026, 12, 31))));
+        assert_eq!(period(2026, Some(13)), None);
+    }
+
+    #[test]
+    fn test_monthly() {
+        let summaries = monthly(&[
+            payment("2026-03-04 12:00:00", 100_000_000, Some(50_000.0)),
+            payment("2026-03-20 12:00:00", 200_000_000, Some(60_000.0)),
+            payment("2026-03-21 12:00:00", 50_000_000, None),
+            payment("2026-04-01 00:00:00", 100_000_000, Some(70_000.0)),
+        ]);
+
+        assert_eq!(summaries.len(), 3);
+        let march = &summaries[1];
+        assert_eq!((march.month.as_str(), march.currency.as_deref()), ("2026-03", Some("EUR")));
+        assert_eq!((march.payments, march.volume_msats, march.fees_msats), (2, 300_000_000, 2_000));
+        assert_eq!(march.fiat_volume, Some(170.0));
+        assert_eq!(summaries[0].currency, None);
+        assert_eq!(summaries[0].fiat_volume, None);
+        assert_eq!(summaries[2].month, "2026-04");
+    }
+
+    #[test]
+    fn test_payments_csv() {
+        let csv = payments_csv(&[payment("2026-03-04 12:00:00", 100_000_000, Some(50_000.0))]);
+        let line = csv.lines().nth(1).unwrap();
+        assert_eq!(line, "1,1,\"Till, 1\",2026-03-04 12:00:00,100000000,1000,EUR,50000.00,50.00,0.00,kraken");
+    }
+
+    #[test]
+    fn test_summary_pdf_pages() {
+        let summary = monthly(&[payment("2026-03-04 12:00:00", 1_000, None)]).remove(0);
+        let doc = summary_pdf("Withdrawals 2026", &vec![summary; 80]);
+        let pdf = String::from_utf8_lossy(&doc.to_bytes()).into_owned();
+        assert!(pdf.contains("/Count 3"));
+    }
+}
@@ -0,0 +1,280 @@
+//! Pass/fail checks of a deployment
+//!
+//! Run by `check-config`, and by `serve --self-test` before it accepts
+//! connections, so a misconfiguration shows up before the first card is tapped.
+
+use serde::Serialize;
+use std::time::Duration;
+
+use sqlx::{Pool, Sqlite};
+
+use crate::{
+    auth,
+    backends::LightningBackends,
+    config::{Config, DatabaseConfig},
+    db::{self, organizations},
+    lightning::LightningBackend,
+    notifications::channels::Channels,
+    rates::Rates,
+};
+
+/// How long the callback URL gets to answer
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", content = "details", rename_all = "snake_case")]
+pub enum Outcome {
+    Pass(String),
+    Fail(String),
+    Skipped(String),
+}
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: String,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+impl Outcome {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Outcome::Pass(_) => "PASS",
+            Outcome::Fail(_) => "FAIL",
+            Outcome::Skipped(_) => "SKIP",
+        }
+    }
+
+    pub fn details(&self) -> &str {
+        match self {
+            Outcome::Pass(details) | Outcome::Fail(details) | Outcome::Skipped(details) => details,
+        }
+    }
+}
+
+impl Check {
+    fn new(name: &str, outcome: Outcome) -> Self {
+        Self { name: name.to_string(), outcome }
+    }
+
+    pub fn failed(&self) -> bool {
+        matches!(self.outcome, Outcome::Fail(_))
+    }
+}
+
+/// Run all checks; none of them changes the database
+pub async fn run(
+    database: &DatabaseConfig,
+    config: &Config,
+    lightning: &LightningBackends,
+    http: &reqwest::Client,
+) -> Vec<Check> {
+    let (database_outcome, pool) = check_database(database).await;
+    let mut checks = vec![
+        Check::new("configuration", check_config(config)),
+        Check::new("database", database_outcome),
+        Check::new("lightning", check_lightning(lightning.default_backend().as_ref()).await),
+    ];
+    if let Some(pool) = pool {
+        checks.extend(check_org_backends(&pool, lightning).await);
+    }
+    checks.push(Check::new("domain", check_domain(config).await));
+    checks.push(Check::new("callback", check_callback(config, http).await));
+    checks.push(Check::new("rates", check_rates(config, http).await));
+
+    checks
+}
+
+fn check_config(config: &Config) -> Outcome {
+    let problems = config_problems(config);
+    if problems.is_empty() {
+        Outcome::Pass(format!("LNURLw base {}", config.urls().lnurlw_base()))
+    } else {
+        Outcome::Fail(problems.join("; "))
+    }
+}
+
+/// Settings that parse on their own but don't work out
+fn config_problems(config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if config.dashboard_username.is_some() != config.dashboard_password_hash.is_some() {
+        problems.push("--dashboard-username and --dashboard-password-hash must be set together".to_string());
+    }
+    if let Some(hash) = &config.dashboard_password_hash
+        && argon2::PasswordHash::new(hash).is_err()
+    {
+        problems.push("--dashboard-password-hash isn't an Argon2 PHC string".to_string());
+    }
+    if let Some(secret) = &config.dashboard_totp_secret
+        && !auth::is_valid_totp_secret(secret)
+    {
+        problems.push("--dashboard-totp-secret isn't a valid base32 secret".to_string());
+    }
+    if config.oidc_issuer_url.is_some()
+        && (config.oidc_client_id.is_none() || config.oidc_client_secret.is_none())
+    {
+        problems.push("--oidc-issuer-url needs --oidc-client-id and --oidc-client-secret".to_string());
+    }
+    if let Some(url) = &config.operator_webhook_url
+        && reqwest::Url::parse(url).is_err()
+    {
+        problems.push(format!("--operator-webhook-url {} isn't a URL", url));
+    }
+    if let Some(dir) = &config.static_dir
+        && !dir.is_dir()
+    {
+        problems.push(format!("--static-dir {} isn't a directory", dir.display()));
+    }
+    if let Err(e) = Channels::from_config(config, reqwest::Client::new()) {
+        problems.push(format!("Notification channels: {}", e));
+    }
+    if let Err(e) = Rates::from_config(config, reqwest::Client::new()) {
+        problems.push(format!("Rate providers: {}", e));
+    }
+
+    problems
+}
+
+/// Connects without migrating, so the check leaves the database as it is
+///
+/// The pool is returned for the checks that read the database, only if the
+/// schema is up to date.
+async fn check_database(database: &DatabaseConfig) -> (Outcome, Option<Pool<Sqlite>>) {
+    let pool = match db::connect(database).await {
+        Ok(pool) => pool,
+        Err(e) => return (Outcome::Fail(format!("can't connect: {}", e)), None),
+    };
+
+    match db::pending_migrations(&pool).await {
+        Ok(pending) if pending.is_empty() => (Outcome::Pass("connected, schema up to date".to_string()), Some(pool)),
+        Ok(pending) if database.no_auto_migrate => (
+            Outcome::Fail(format!(
+                "{} pending migration(s) and --no-auto-migrate is set, run `lnurlw-server migrate`",
+                pending.len()
+            )),
+            None,
+        ),
+        Ok(pending) => (
+            Outcome::Pass(format!("connected, {} pending migration(s) applied on start", pending.len())),
+            None,
+        ),
+        Err(e) => (Outcome::Fail(format!("can't read migrations: {}", e)), None),
+    }
+}
+
+async fn check_lightning(lightning: &dyn LightningBackend) -> Outcome {
+    match lightning.get_info().await {
+        Ok(info) => Outcome::Pass(format!("node {}, balance {} sats", info.alias, info.balance_msats / 1000)),
+        Err(e) => Outcome::Fail(format!("node unreachable: {}", e)),
+    }
+}
+
+/// One check per organization, of the backend its cards are paid from
+async fn check_org_backends(pool: &Pool<Sqlite>, lightning: &LightningBackends) -> Vec<Check> {
+    let orgs = match organizations::list_organizations(pool).await {
+        Ok(orgs) => orgs,
+        Err(e) => return vec![Check::new("organizations", Outcome::Fail(format!("can't list: {}", e)))],
+    };
+
+    let mut checks = Vec::new();
+    for (org, _) in orgs {
+        let outcome = match lightning.for_org(pool, Some(org.org_id)).await {
+            Ok(backend) => check_lightning(backend.as_ref()).await,
+            Err(e) => Outcome::Fail(e.to_string()),
+        };
+        checks.push(Check::new(&format!("lightning ({})", org.slug), outcome));
+    }
+
+    checks
+}
+
+async fn check_domain(config: &Config) -> Outcome {
+    if let Err(e) = config.public.check_scheme(&config.domain) {
+        return Outcome::Fail(e.to_string());
+    }
+    if config.skip_domain_resolution {
+        return Outcome::Skipped("--skip-domain-resolution is set".to_string());
+    }
+
+    match config.check_domain_resolves().await {
+        Ok(()) => Outcome::Pass(format!("{} resolves", config.domain)),
+        Err(e) => Outcome::Fail(e.to_string()),
+    }
+}
+
+/// Any HTTP answer counts: it proves DNS, TLS and the proxy in front of the server work
+async fn check_callback(config: &Config, http: &reqwest::Client) -> Outcome {
+    if config.skip_domain_resolution {
+        return Outcome::Skipped("--skip-domain-resolution is set".to_string());
+    }
+
+    let url = config.urls().callback_url();
+    match http.get(&url).timeout(CALLBACK_TIMEOUT).send().await {
+        Ok(response) => Outcome::Pass(format!("{} answered {}", url, response.status())),
+        Err(e) => Outcome::Fail(format!("{} unreachable: {}", url, error_chain(&e))),
+    }
+}
+
+/// Whether a bitcoin price can be fetched in the fiat currency
+async fn check_rates(config: &Config, http: &reqwest::Client) -> Outcome {
+    let Some(currency) = &config.fiat_currency else {
+        return Outcome::Skipped("no --fiat-currency configured".to_string());
+    };
+    let rates = match Rates::from_config(config, http.clone()) {
+        Ok(rates) => rates,
+        Err(e) => return Outcome::Fail(e.to_string()),
+    };
+    match rates.price(currency).await {
+        Ok(quote) => Outcome::Pass(format!("1 BTC = {:.2} {} from {}", quote.price, currency, quote.source)),
+        Err(e) => Outcome::Fail(e.to_string()),
+    }
+}
+
+/// reqwest keeps the interesting part, e.g. the certificate problem, in the sources
+fn error_chain(e: &dyn std::error::Error) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Cli, Command};
+    use clap::Parser;
+
+    fn parse_config(args: &[&str]) -> Config {
+        let cli = Cli::parse_from(["lnurlw-server", "check-config"].iter().chain(args));
+        match cli.command {
+            Command::CheckConfig(args) => args.config,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_config_problems() {
+        let config = parse_config(&["--domain", "cards.example.com"]);
+        assert!(config_problems(&config).is_empty());
+
+        let config = parse_config(&["--domain", "cards.example.com", "--rate-providers", "kraken,bitstamp"]);
+        assert!(config_problems(&config)[0].contains("unknown rate provider \"bitstamp\""));
+
+        let config = parse_config(&[
+            "--domain",
+            "cards.example.com",
+            "--dashboard-username",
+            "admin",
+            "--oidc-issuer-url",
+            "https://id.example.com",
+            "--operator-webhook-url",
+            "not a url",
+        ]);
+        assert_eq!(config_problems(&config).len(), 3);
+    }
+}
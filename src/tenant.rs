@@ -0,0 +1,111 @@
+//! Organizations served on a domain of their own
+//!
+//! The `Host` of each request selects the organization whose domain it is.
+//! On its domain an organization's cardholders only reach its cards, and the
+//! operator's dashboard and API aren't served at all; the server's own
+//! `--domain`, and any host that isn't an organization's, serves everything.
+
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    http::{header, request::Parts, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::convert::Infallible;
+
+use crate::{
+    app_state::AppState,
+    db::{models::Organization, organizations},
+};
+
+/// The organization whose domain a request came in on, `None` on the server's
+#[derive(Debug, Clone, Default)]
+pub struct Tenant(pub Option<Organization>);
+
+impl Tenant {
+    /// The organization's domain, for URLs handed out in answer to the request
+    pub fn domain(&self) -> Option<&str> {
+        self.0.as_ref().and_then(|org| org.domain.as_deref())
+    }
+
+    /// Whether a card of organization `org_id` is served on this domain
+    pub fn serves(&self, org_id: Option<i64>) -> bool {
+        match &self.0 {
+            Some(org) => org_id == Some(org.org_id),
+            None => true,
+        }
+    }
+
+    /// Whether the card is served on this domain, `false` if it doesn't exist
+    pub async fn serves_card(&self, state: &AppState, card_id: i64) -> anyhow::Result<bool> {
+        if self.0.is_none() {
+            return Ok(true);
+        }
+        let org_id = organizations::get_card_org(&state.pool, card_id).await?;
+        Ok(self.serves(org_id))
+    }
+}
+
+/// Look up the organization of the request's host for the handlers
+pub async fn resolve(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    let host = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .map(|host| host.to_ascii_lowercase());
+
+    let org = match host {
+        Some(host) if host != state.config.domain => {
+            match organizations::get_organization_by_domain(&state.pool, &host).await {
+                Ok(org) => org,
+                Err(e) => {
+                    tracing::error!("Failed to look up organization of {}: {}", host, e);
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+            }
+        }
+        _ => None,
+    };
+
+    req.extensions_mut().insert(Tenant(org));
+    next.run(req).await
+}
+
+/// Answer 404 on an organization's domain, for the operator's routes
+pub async fn server_domain_only(tenant: Tenant, req: Request, next: Next) -> Response {
+    if tenant.0.is_some() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    next.run(req).await
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Tenant {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<Tenant>().cloned().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serves() {
+        let org = Organization {
+            org_id: 1,
+            slug: "shop-a".to_string(),
+            name: "Shop A".to_string(),
+            lightning_backend: "mock".to_string(),
+            created_at: None,
+            domain: Some("cards.shop-a.example".to_string()),
+        };
+
+        assert!(Tenant(None).serves(None));
+        assert!(Tenant(None).serves(Some(1)));
+        assert!(Tenant(Some(org.clone())).serves(Some(1)));
+        assert!(!Tenant(Some(org.clone())).serves(Some(2)));
+        assert!(!Tenant(Some(org)).serves(None));
+    }
+}
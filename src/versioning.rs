@@ -0,0 +1,107 @@
+//! Versions of the HTTP API
+//!
+//! The operator, cardholder and registration API is served under `/v1/`. The
+//! paths it had before, `/api/...` and `/new`, stay as aliases of `v1` whose
+//! answers are marked deprecated and point at their `v1` path, so existing
+//! integrations and programming apps keep working while they move over.
+//! Answers carry the version that served them in `API-Version`. A breaking
+//! change gets a new version served next to the old ones, which clients pick
+//! by path; `GET /versions` lists what the server speaks.
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::app_state::AppState;
+
+/// Version served under `/v1/` and by the legacy aliases
+pub const CURRENT: &str = "v1";
+
+/// When the unversioned paths were deprecated, as sent in `Deprecation` (RFC 9745)
+const LEGACY_DEPRECATED_AT: i64 = 1_792_108_800;
+
+/// Header naming the API version that answered a request
+pub static API_VERSION: HeaderName = HeaderName::from_static("api-version");
+
+static DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+static SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+#[derive(Debug, Serialize)]
+pub struct Versions {
+    pub current: &'static str,
+    pub supported: Vec<&'static str>,
+    /// When the unversioned `/api/...` and `/new` paths go away, if announced
+    pub legacy_sunset: Option<String>,
+}
+
+/// GET /versions
+/// The API versions the server speaks, for clients to pick one before their first request
+pub async fn list_versions(State(state): State<AppState>) -> Json<Versions> {
+    Json(Versions {
+        current: CURRENT,
+        supported: vec![CURRENT],
+        legacy_sunset: state.config.legacy_api_sunset.map(|sunset| sunset.to_rfc3339()),
+    })
+}
+
+/// Tag the answers of the versioned routes with their version
+pub async fn current(req: Request, next: Next) -> Response {
+    let mut response = next.run(req).await;
+    response.headers_mut().insert(API_VERSION.clone(), HeaderValue::from_static(CURRENT));
+    response
+}
+
+/// Mark the answers of a legacy alias deprecated, linking the `v1` path that replaces it
+pub async fn legacy(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let successor = successor_path(req.uri().path());
+    let mut response = next.run(req).await;
+
+    let headers = response.headers_mut();
+    headers.insert(API_VERSION.clone(), HeaderValue::from_static(CURRENT));
+    headers.insert(DEPRECATION.clone(), HeaderValue::from_str(&format!("@{}", LEGACY_DEPRECATED_AT)).unwrap());
+    if let Ok(link) = HeaderValue::from_str(&format!("<{}>; rel=\"successor-version\"", successor)) {
+        headers.append(header::LINK, link);
+    }
+    if let Some(sunset) = state.config.legacy_api_sunset {
+        headers.insert(SUNSET.clone(), HeaderValue::from_str(&http_date(sunset)).unwrap());
+    }
+
+    response
+}
+
+/// The `v1` path of a legacy one, `/api/cards/1` becomes `/v1/cards/1` and `/new` `/v1/new`
+fn successor_path(path: &str) -> String {
+    match path.strip_prefix("/api") {
+        Some(rest) => format!("/{}{}", CURRENT, rest),
+        None => format!("/{}{}", CURRENT, path),
+    }
+}
+
+/// Time in the IMF-fixdate form of HTTP headers
+fn http_date(time: DateTime<Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_successor_path() {
+        assert_eq!(successor_path("/api/cards/1/freeze"), "/v1/cards/1/freeze");
+        assert_eq!(successor_path("/api/createboltcard"), "/v1/createboltcard");
+        assert_eq!(successor_path("/new"), "/v1/new");
+    }
+
+    #[test]
+    fn test_http_date() {
+        let sunset = DateTime::parse_from_rfc3339("2027-04-01T00:00:00Z").unwrap().with_timezone(&Utc);
+        assert_eq!(http_date(sunset), "Thu, 01 Apr 2027 00:00:00 GMT");
+    }
+}
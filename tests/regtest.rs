@@ -0,0 +1,189 @@
+//! End-to-end withdrawals against the server binary
+//!
+//! Each test starts `lnurlw-server serve --network regtest` on a fresh
+//! database, creates a card, taps it and withdraws to an invoice the way a
+//! wallet would. By default the server pays with the mock backend; to catch
+//! drift in a real node's API, point it at a backend on a regtest node and at
+//! commands that create and check invoices on a second node:
+//!
+//! ```sh
+//! REGTEST_BACKEND='btcpay:http://localhost:23002?pull_payment=...' \
+//! REGTEST_INVOICE_CMD='lncli --network regtest addinvoice --amt_msat {msats} | jq -r .payment_request' \
+//! REGTEST_PAID_CMD='lncli --network regtest lookupinvoice {payment_hash} | jq -e ".state == \"SETTLED\""' \
+//! cargo test --test regtest -- --ignored
+//! ```
+//!
+//! The tests start processes and bind ports, so they only run with `--ignored`.
+
+use std::{
+    net::TcpListener,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    str::FromStr,
+    time::Duration,
+};
+
+use lnurlw_client::{Client, Error, models::{Payment, PaymentStatus}};
+use lnurlw_core::lightning::{Invoice, LightningBackend, MockLightning};
+
+const BINARY: &str = env!("CARGO_BIN_EXE_lnurlw-server");
+
+/// A server on a free port with its own database, killed when dropped
+struct Server {
+    child: Child,
+    domain: String,
+    database: PathBuf,
+    client: Client,
+}
+
+impl Server {
+    async fn start(backend: &str) -> Self {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let domain = format!("127.0.0.1:{}", port);
+        let database = std::env::temp_dir().join(format!("lnurlw-regtest-{}-{}.db", std::process::id(), port));
+
+        let child = Command::new(BINARY)
+            .env("DATABASE_URL", database_url(&database))
+            .args(["serve", "--network", "regtest", "--skip-domain-resolution", "--host", "127.0.0.1"])
+            .args(["--port", &port.to_string(), "--domain", &domain, "--lightning-backend", backend])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("server starts");
+        let client = Client::new(&format!("http://{}", domain));
+        let server = Server { child, client, domain, database };
+
+        for _ in 0..100 {
+            if !matches!(server.client.tap(None, "", "").await, Err(Error::Http(_))) {
+                return server;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("server didn't come up on {}", server.client.base());
+    }
+
+    /// Run a CLI command on the server's database and return its output
+    fn cli(&self, args: &[&str]) -> String {
+        let output = Command::new(BINARY)
+            .env("DATABASE_URL", database_url(&self.database))
+            .args(args)
+            .output()
+            .expect("CLI runs");
+        assert!(output.status.success(), "{:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    fn create_card(&self) -> i64 {
+        let output = self.cli(&["create-card", "E2E", "--domain", &self.domain, "--skip-domain-resolution"]);
+        let line = output.lines().find(|line| line.starts_with("Card ID:")).expect("card ID printed");
+        line.trim_start_matches("Card ID:").trim().parse().unwrap()
+    }
+
+    /// Tap the card and return the k1 of the withdrawal it offers
+    async fn tap(&self, card_id: i64, counter: u32) -> String {
+        let args = ["simulate-tap", &card_id.to_string(), "--counter", &counter.to_string(), "--server", self.client.base()];
+        let url = self.cli(&args).trim().to_string();
+        let request = self.client.withdraw_request(&url).await.unwrap();
+        assert_eq!(request.tag, "withdrawRequest", "{:?}", request);
+        request.k1
+    }
+
+    async fn payment(&self, k1: &str) -> Payment {
+        self.client.payment(k1).await.unwrap()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", self.database.display(), suffix));
+        }
+    }
+}
+
+fn database_url(path: &std::path::Path) -> String {
+    format!("sqlite://{}?mode=rwc", path.display())
+}
+
+fn backend() -> String {
+    std::env::var("REGTEST_BACKEND").unwrap_or_else(|_| "mock".to_string())
+}
+
+fn real_node() -> bool {
+    std::env::var("REGTEST_BACKEND").is_ok()
+}
+
+/// Run a command from the environment with `{name}` placeholders filled in
+fn run_template(template: &str, values: &[(&str, &str)]) -> std::process::Output {
+    let command = values
+        .iter()
+        .fold(template.to_string(), |command, (name, value)| command.replace(&format!("{{{}}}", name), value));
+    Command::new("sh").args(["-c", &command]).output().expect("command runs")
+}
+
+/// A regtest invoice from the receiving node, or made up for the mock
+async fn invoice(msats: u64) -> String {
+    match std::env::var("REGTEST_INVOICE_CMD") {
+        Ok(template) => {
+            let output = run_template(&template, &[("msats", &msats.to_string())]);
+            assert!(output.status.success(), "REGTEST_INVOICE_CMD failed: {}", String::from_utf8_lossy(&output.stderr));
+            String::from_utf8(output.stdout).unwrap().trim().to_string()
+        }
+        Err(_) => MockLightning::default().create_invoice(msats, "E2E").await.unwrap().bolt11(),
+    }
+}
+
+#[tokio::test]
+#[ignore = "starts the server, run with --ignored"]
+async fn withdrawal_settles() {
+    let server = Server::start(&backend()).await;
+    let card_id = server.create_card();
+    let k1 = server.tap(card_id, 1).await;
+
+    let invoice = invoice(21_000).await;
+    server.client.withdraw(&k1, &invoice).await.unwrap();
+
+    let payment = server.payment(&k1).await;
+    assert_eq!(payment.status, PaymentStatus::Settled, "{:?}", payment);
+    assert_eq!(payment.amount_msats, Some(21_000));
+
+    if let Ok(template) = std::env::var("REGTEST_PAID_CMD") {
+        let payment_hash = Invoice::from_str(&invoice).unwrap().payment_hash();
+        let output = run_template(&template, &[("payment_hash", &payment_hash)]);
+        assert!(output.status.success(), "receiving node has no payment {}", payment_hash);
+    }
+}
+
+#[tokio::test]
+#[ignore = "starts the server, run with --ignored"]
+async fn invoice_for_other_network_is_refused() {
+    let server = Server::start(&backend()).await;
+    let card_id = server.create_card();
+    let k1 = server.tap(card_id, 1).await;
+
+    let mainnet = MockLightning::from_spec("network=bitcoin").unwrap();
+    let invoice = mainnet.create_invoice(21_000, "E2E").await.unwrap();
+    let response = server.client.withdraw(&k1, &invoice.bolt11()).await;
+    assert!(matches!(&response, Err(Error::Lnurl { code, .. }) if code == "WRONG_NETWORK"), "{:?}", response);
+}
+
+#[tokio::test]
+#[ignore = "starts the server, run with --ignored"]
+async fn failed_payment_can_be_retried() {
+    if real_node() {
+        // A real node can't be told to fail
+        return;
+    }
+    let server = Server::start("mock:script=fail").await;
+    let card_id = server.create_card();
+    let k1 = server.tap(card_id, 1).await;
+
+    let response = server.client.withdraw(&k1, &invoice(5_000).await).await;
+    assert!(matches!(&response, Err(Error::Lnurl { code, .. }) if code == "PAYMENT_FAILED"), "{:?}", response);
+    assert_eq!(server.payment(&k1).await.status, PaymentStatus::Failed);
+
+    server.client.withdraw(&k1, &invoice(5_000).await).await.unwrap();
+    assert_eq!(server.payment(&k1).await.attempts, 2);
+}